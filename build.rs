@@ -0,0 +1,58 @@
+//! Precompresses the compressible static assets with Brotli and gzip, so the server sends
+//! them as they are instead of compressing the same files on every request.
+//! The variants land in `$OUT_DIR/static-compressed/` as `<file>.br` and `<file>.gz`,
+//! where `src/embedded.rs` embeds them next to the originals.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Extensions of the files worth compressing; the other images are compressed already
+const COMPRESSIBLE: &[&str] = &["css", "js", "html", "svg", "json", "txt", "ttf"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=static");
+    let out = Path::new(&std::env::var("OUT_DIR").unwrap()).join("static-compressed");
+    let _ = fs::remove_dir_all(&out);
+    fs::create_dir_all(&out).unwrap();
+    compress_dir(Path::new("static"), &out);
+}
+
+fn compress_dir(dir: &Path, out: &Path) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let target = out.join(path.file_name().unwrap());
+        if path.is_dir() {
+            fs::create_dir_all(&target).unwrap();
+            compress_dir(&path, &target);
+            continue;
+        }
+        let compressible = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COMPRESSIBLE.contains(&ext.to_ascii_lowercase().as_str()));
+        if !compressible {
+            continue;
+        }
+
+        let data = fs::read(&path).unwrap();
+        let mut brotli = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22);
+            writer.write_all(&data).unwrap();
+        }
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(&data).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        // A variant that doesn't save anything isn't worth a Content-Encoding
+        for (variant, extension) in [(brotli, "br"), (gzip, "gz")] {
+            if variant.len() < data.len() {
+                let mut name = target.clone().into_os_string();
+                name.push(".");
+                name.push(extension);
+                fs::write(name, variant).unwrap();
+            }
+        }
+    }
+}
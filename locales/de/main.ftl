@@ -0,0 +1,202 @@
+## Navigation
+
+nav-music = 🎵 Musik
+nav-gallery = 🖼️ Galerie
+nav-folders = 📁 Ordner
+nav-timeline = 📅 Zeitleiste
+nav-map = 🗺️ Karte
+nav-filter = 🔍 Filter
+nav-random = 🎲 Zufall
+nav-faq = FAQ
+nav-api = 📚 API
+nav-library = 💿 Bibliothek
+nav-feed = 📡 Feed
+nav-feed-hint = In einer Podcast-App abonnieren. Der Link funktioniert ohne Anmeldung, also halte ihn privat.
+home = Start
+music-home = Musik
+back = ← Zurück
+archive-suffix = (Archiv)
+loading = Wird geladen …
+indexing = ⏳ Die Bibliothek wird noch indiziert. Suche, Zufall und Statistiken können vorerst unvollständig sein.
+indexing-timeline = ⏳ Die Bibliothek wird noch indiziert. Die Zeitleiste kann vorerst unvollständig sein.
+indexing-map = ⏳ Die Bibliothek wird noch indiziert. Die Karte kann vorerst unvollständig sein.
+
+## Directory listings
+
+share-qr = 📱 QR-Code
+share-qr-hint = Den Code scannen, um diesen Ordner auf einem Handy zu öffnen
+download-zip = ⬇️ Als ZIP herunterladen
+slideshow-link = ▶️ Diashow
+upload-prompt = 📤 Fotos und Videos hier ablegen oder tippen, um Dateien auszuwählen
+continue-watching = Weiterschauen
+sort = Sortierung
+sort-name = Name
+sort-size = Größe
+sort-mtime = Geändert
+sort-type = Typ
+all-subfolders = 🗂️ Alle Unterordner
+all-subfolders-off = Nur diese Ordnerebene zeigen
+all-subfolders-on = Die Dateien aller Unterordner auf einer Seite auflisten
+empty-gallery = An diesem Ort wurden keine Mediendateien oder Ordner gefunden.
+empty-music = An diesem Ort wurden keine Audiodateien oder Ordner gefunden.
+type-directory = Ordner
+type-image = Bild
+type-video = Video
+type-audio = Audio
+type-album-folder = Albumordner
+type-music-archive = Musikarchiv
+type-playlist = Playlist
+your-rating = Deine Bewertung
+favorite = Favorit
+video-unplayable = Browser können dieses Video möglicherweise nicht abspielen
+album-art = Albumcover
+
+## Lightbox
+
+lightbox-prev = ← Zurück
+lightbox-random = 🎲 Zufällig auf dieser Seite
+lightbox-next = Weiter →
+lightbox-auto-random = Automatisch zufällig:
+lightbox-seconds = s
+
+## Music player
+
+music-player = 🎵 Musikplayer
+playlists = 📜 Playlists
+playlist = Playlist
+playlist-play = ▶️ Abspielen
+playlist-new = ＋ Neu
+playlist-delete = Playlist löschen
+playlist-add = Zur Playlist hinzufügen
+queue-resume = ⏯️ Fortsetzen
+audio-file-count = 📊 { $count ->
+    [one] { $count } Audiodatei
+   *[other] { $count } Audiodateien
+}
+folder-count = 📁 { $count ->
+    [one] { $count } Ordner
+   *[other] { $count } Ordner
+}
+play-all-random = 🎲 Alle zufällig abspielen
+play-all = ▶️ Alle abspielen
+
+## Music library
+
+music-library = 🎵 Musikbibliothek
+library-artists = 👤 Interpreten
+library-albums = 💿 Alben
+library-genres = 🏷️ Genres
+library-empty = Keine getaggten Audiodateien gefunden. Titel erscheinen hier, sobald sie Interpret-, Album- oder Genre-Tags haben.
+heading-artists = Interpreten
+heading-albums = Alben
+heading-albums-by = Alben von { $artist }
+heading-genres = Genres
+album-count = { $count ->
+    [one] { $count } Album
+   *[other] { $count } Alben
+}
+track-count = { $count ->
+    [one] { $count } Titel
+   *[other] { $count } Titel
+}
+
+## Timeline
+
+timeline = Zeitleiste
+timeline-empty = Noch keine indizierten Fotos oder Videos.
+timeline-years = Jahre
+timeline-months = Monate
+item-count = { $count ->
+    [one] { $count } Element
+   *[other] { $count } Elemente
+}
+timeline-day = { $weekday }, { $day }. { $month } { $year }
+month-1 = Januar
+month-2 = Februar
+month-3 = März
+month-4 = April
+month-5 = Mai
+month-6 = Juni
+month-7 = Juli
+month-8 = August
+month-9 = September
+month-10 = Oktober
+month-11 = November
+month-12 = Dezember
+weekday-1 = Montag
+weekday-2 = Dienstag
+weekday-3 = Mittwoch
+weekday-4 = Donnerstag
+weekday-5 = Freitag
+weekday-6 = Samstag
+weekday-7 = Sonntag
+
+## Map
+
+map = Karte
+map-zoom-in = Vergrößern
+map-zoom-out = Verkleinern
+map-world = 🌍 Welt
+map-label = Karte der Fotos mit Standort
+map-hint = Ziehen zum Verschieben, scrollen zum Zoomen. Auf eine Gruppe klicken, um hineinzuzoomen, oder auf ein einzelnes Foto, um es zu öffnen.
+
+## Slideshow
+
+slideshow = Diashow
+slideshow-exit = Beenden (Esc)
+slideshow-prev = Zurück (←)
+slideshow-play = Abspielen/Pause (Leertaste)
+slideshow-next = Weiter (→)
+slideshow-in-order = Der Reihe nach
+slideshow-shuffle = Zufällige Reihenfolge
+slideshow-fullscreen = Vollbild (F)
+
+## Errors
+
+error-not-found = Nicht gefunden
+error-forbidden = Zugriff verweigert
+error-invalid-path = Ungültiger Pfad
+error-request-body-too-large = Anfrage zu groß
+error-internal-server-error = Interner Serverfehler
+error-destination-exists = Das Ziel existiert bereits
+error-directory-not-empty = Der Ordner ist nicht leer
+error-file-exists = Die Datei existiert bereits
+error-path-already-exists = Der Pfad existiert bereits
+error-path-exists-and-is-not-a-file = Der Pfad existiert und ist keine Datei
+error-something-else-is-at-the-original-path = Am ursprünglichen Pfad befindet sich inzwischen etwas anderes
+error-at-least-one-track-is-required = Mindestens ein Titel ist erforderlich
+error-audio-could-not-be-decoded = Die Audiodatei konnte nicht dekodiert werden
+error-between-1-and-1000-operations-are-required = Es sind 1 bis 1000 Operationen erforderlich
+error-between-1-and-50-tags-are-required = Es sind 1 bis 50 Tags erforderlich
+error-cannot-delete-the-media-directory = Das Medienverzeichnis kann nicht gelöscht werden
+error-cannot-move-a-directory-into-itself = Ein Ordner kann nicht in sich selbst verschoben werden
+error-cannot-move-the-media-directory = Das Medienverzeichnis kann nicht verschoben werden
+error-destination-must-keep-the-file-s-media-type = Das Ziel muss den Medientyp der Datei beibehalten
+error-expected-a-multipart-form-data-body = multipart/form-data-Inhalt erwartet
+error-invalid-bbox = Ungültiger Kartenausschnitt (bbox)
+error-invalid-directory-name = Ungültiger Ordnername
+error-invalid-file-name = Ungültiger Dateiname
+error-invalid-multipart-body = Ungültiger Multipart-Inhalt
+error-missing-host-header = Host-Header fehlt
+error-no-file-in-multipart-body = Keine Datei im Multipart-Inhalt
+error-not-a-media-file-name = Kein Mediendateiname
+error-playlist-names-must-be-1-to-100-characters = Playlist-Namen müssen 1 bis 100 Zeichen lang sein
+error-playlist-tracks-must-be-audio-files = Playlist-Titel müssen Audiodateien sein
+error-playlists-can-hold-at-most-10000-tracks = Playlists können höchstens 10000 Titel enthalten
+error-queued-tracks-must-be-audio-files = Titel in der Warteschlange müssen Audiodateien sein
+error-queues-can-hold-at-most-10000-tracks = Warteschlangen können höchstens 10000 Titel enthalten
+error-rating-must-be-between-1-and-5 = Die Bewertung muss zwischen 1 und 5 liegen
+error-subtitle-file-is-too-large = Die Untertiteldatei ist zu groß
+error-tags-need-an-index-database = Tags benötigen eine Indexdatenbank
+error-track-index-or-position-out-of-range = Titelnummer oder Position außerhalb des gültigen Bereichs
+error-upload-interrupted = Upload abgebrochen
+error-write-operations-are-disabled = Schreibvorgänge sind deaktiviert
+error-type-must-be-image-video-or-audio = type muss image, video oder audio sein
+error-sign-in-was-cancelled-or-failed = Die Anmeldung wurde abgebrochen oder ist fehlgeschlagen
+error-missing-code-or-state = code oder state fehlt
+error-unknown-or-expired-login-attempt = Unbekannter oder abgelaufener Anmeldeversuch
+error-target-is-too-long-for-a-qr-code = Das Ziel ist zu lang für einen QR-Code
+error-only-the-json-format-is-supported = Nur das Format json wird unterstützt
+error-archive-entry-is-too-large = Der Archiveintrag ist zu groß
+error-archive-expands-beyond-the-decompression-limits = Das Archiv entpackt sich über die Dekomprimierungsgrenzen hinaus
+error-sign-in-failed = Anmeldung fehlgeschlagen
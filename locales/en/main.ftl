@@ -0,0 +1,155 @@
+## Navigation
+
+nav-music = 🎵 Music
+nav-gallery = 🖼️ Gallery
+nav-folders = 📁 Folders
+nav-timeline = 📅 Timeline
+nav-map = 🗺️ Map
+nav-filter = 🔍 Filter
+nav-random = 🎲 Random
+nav-faq = FAQ
+nav-api = 📚 API
+nav-library = 💿 Library
+nav-feed = 📡 Feed
+nav-feed-hint = Subscribe in a podcast app. The link works without logging in, so keep it private.
+home = Home
+music-home = Music Home
+back = ← Back
+archive-suffix = (archive)
+loading = Loading...
+indexing = ⏳ Still indexing the library. Search, random and statistics may be incomplete for now.
+indexing-timeline = ⏳ Still indexing the library. The timeline may be incomplete for now.
+indexing-map = ⏳ Still indexing the library. The map may be incomplete for now.
+
+## Directory listings
+
+share-qr = 📱 QR code
+share-qr-hint = Open this folder on a phone by scanning the code
+download-zip = ⬇️ Download ZIP
+slideshow-link = ▶️ Slideshow
+upload-prompt = 📤 Drop photos and videos here, or tap to choose files
+continue-watching = Continue watching
+sort = Sort
+sort-name = Name
+sort-size = Size
+sort-mtime = Modified
+sort-type = Type
+all-subfolders = 🗂️ All subfolders
+all-subfolders-off = Show one directory level
+all-subfolders-on = List the files of every subfolder on one page
+empty-gallery = No media files or directories found in this location.
+empty-music = No audio files or directories found in this location.
+type-directory = Directory
+type-image = Image
+type-video = Video
+type-audio = Audio
+type-album-folder = Album Folder
+type-music-archive = Music Archive
+type-playlist = Playlist
+your-rating = Your rating
+favorite = Favorite
+video-unplayable = Browsers may not play this video
+album-art = Album Art
+
+## Lightbox
+
+lightbox-prev = ← Prev
+lightbox-random = 🎲 Random on Page
+lightbox-next = Next →
+lightbox-auto-random = Auto-Random:
+lightbox-seconds = sec
+
+## Music player
+
+music-player = 🎵 Music Player
+playlists = 📜 Playlists
+playlist = Playlist
+playlist-play = ▶️ Play
+playlist-new = ＋ New
+playlist-delete = Delete playlist
+playlist-add = Add to playlist
+queue-resume = ⏯️ Resume
+audio-file-count = 📊 { $count ->
+    [one] { $count } audio file
+   *[other] { $count } audio files
+}
+folder-count = 📁 { $count ->
+    [one] { $count } folder
+   *[other] { $count } folders
+}
+play-all-random = 🎲 Play All Random
+play-all = ▶️ Play All
+
+## Music library
+
+music-library = 🎵 Music Library
+library-artists = 👤 Artists
+library-albums = 💿 Albums
+library-genres = 🏷️ Genres
+library-empty = No tagged audio files found. Tracks appear here once they have artist, album or genre tags.
+heading-artists = Artists
+heading-albums = Albums
+heading-albums-by = Albums by { $artist }
+heading-genres = Genres
+album-count = { $count ->
+    [one] { $count } album
+   *[other] { $count } albums
+}
+track-count = { $count ->
+    [one] { $count } track
+   *[other] { $count } tracks
+}
+
+## Timeline
+
+timeline = Timeline
+timeline-empty = No indexed photos or videos yet.
+timeline-years = Years
+timeline-months = Months
+item-count = { $count ->
+    [one] { $count } item
+   *[other] { $count } items
+}
+timeline-day = { $weekday }, { $day } { $month } { $year }
+month-1 = January
+month-2 = February
+month-3 = March
+month-4 = April
+month-5 = May
+month-6 = June
+month-7 = July
+month-8 = August
+month-9 = September
+month-10 = October
+month-11 = November
+month-12 = December
+weekday-1 = Monday
+weekday-2 = Tuesday
+weekday-3 = Wednesday
+weekday-4 = Thursday
+weekday-5 = Friday
+weekday-6 = Saturday
+weekday-7 = Sunday
+
+## Map
+
+map = Map
+map-zoom-in = Zoom in
+map-zoom-out = Zoom out
+map-world = 🌍 World
+map-label = Map of geotagged photos
+map-hint = Drag to pan, scroll to zoom. Click a cluster to zoom in, or a single photo to open it.
+
+## Slideshow
+
+slideshow = Slideshow
+slideshow-exit = Exit (Esc)
+slideshow-prev = Previous (←)
+slideshow-play = Play/pause (Space)
+slideshow-next = Next (→)
+slideshow-in-order = In order
+slideshow-shuffle = Shuffle
+slideshow-fullscreen = Fullscreen (F)
+
+## Errors are written in English in the code; other languages translate them as
+## error-<message in lowercase, with dashes between words>
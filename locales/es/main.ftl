@@ -0,0 +1,202 @@
+## Navigation
+
+nav-music = 🎵 Música
+nav-gallery = 🖼️ Galería
+nav-folders = 📁 Carpetas
+nav-timeline = 📅 Cronología
+nav-map = 🗺️ Mapa
+nav-filter = 🔍 Filtrar
+nav-random = 🎲 Aleatorio
+nav-faq = Preguntas frecuentes
+nav-api = 📚 API
+nav-library = 💿 Biblioteca
+nav-feed = 📡 Feed
+nav-feed-hint = Suscríbete desde una app de pódcasts. El enlace funciona sin iniciar sesión, así que mantenlo en privado.
+home = Inicio
+music-home = Música
+back = ← Atrás
+archive-suffix = (archivo comprimido)
+loading = Cargando…
+indexing = ⏳ La biblioteca aún se está indexando. La búsqueda, el modo aleatorio y las estadísticas pueden estar incompletos por ahora.
+indexing-timeline = ⏳ La biblioteca aún se está indexando. La cronología puede estar incompleta por ahora.
+indexing-map = ⏳ La biblioteca aún se está indexando. El mapa puede estar incompleto por ahora.
+
+## Directory listings
+
+share-qr = 📱 Código QR
+share-qr-hint = Escanea el código para abrir esta carpeta en un móvil
+download-zip = ⬇️ Descargar ZIP
+slideshow-link = ▶️ Presentación
+upload-prompt = 📤 Suelta aquí fotos y vídeos, o toca para elegir archivos
+continue-watching = Seguir viendo
+sort = Ordenar
+sort-name = Nombre
+sort-size = Tamaño
+sort-mtime = Modificado
+sort-type = Tipo
+all-subfolders = 🗂️ Todas las subcarpetas
+all-subfolders-off = Mostrar un solo nivel de carpetas
+all-subfolders-on = Mostrar los archivos de todas las subcarpetas en una página
+empty-gallery = No se encontraron archivos multimedia ni carpetas en esta ubicación.
+empty-music = No se encontraron archivos de audio ni carpetas en esta ubicación.
+type-directory = Carpeta
+type-image = Imagen
+type-video = Vídeo
+type-audio = Audio
+type-album-folder = Carpeta de álbum
+type-music-archive = Archivo de música
+type-playlist = Lista de reproducción
+your-rating = Tu valoración
+favorite = Favorito
+video-unplayable = Puede que los navegadores no reproduzcan este vídeo
+album-art = Portada del álbum
+
+## Lightbox
+
+lightbox-prev = ← Anterior
+lightbox-random = 🎲 Aleatorio en la página
+lightbox-next = Siguiente →
+lightbox-auto-random = Aleatorio automático:
+lightbox-seconds = s
+
+## Music player
+
+music-player = 🎵 Reproductor de música
+playlists = 📜 Listas
+playlist = Lista de reproducción
+playlist-play = ▶️ Reproducir
+playlist-new = ＋ Nueva
+playlist-delete = Eliminar lista
+playlist-add = Añadir a la lista
+queue-resume = ⏯️ Reanudar
+audio-file-count = 📊 { $count ->
+    [one] { $count } archivo de audio
+   *[other] { $count } archivos de audio
+}
+folder-count = 📁 { $count ->
+    [one] { $count } carpeta
+   *[other] { $count } carpetas
+}
+play-all-random = 🎲 Reproducir todo al azar
+play-all = ▶️ Reproducir todo
+
+## Music library
+
+music-library = 🎵 Biblioteca musical
+library-artists = 👤 Artistas
+library-albums = 💿 Álbumes
+library-genres = 🏷️ Géneros
+library-empty = No se encontraron archivos de audio etiquetados. Las pistas aparecen aquí cuando tienen etiquetas de artista, álbum o género.
+heading-artists = Artistas
+heading-albums = Álbumes
+heading-albums-by = Álbumes de { $artist }
+heading-genres = Géneros
+album-count = { $count ->
+    [one] { $count } álbum
+   *[other] { $count } álbumes
+}
+track-count = { $count ->
+    [one] { $count } pista
+   *[other] { $count } pistas
+}
+
+## Timeline
+
+timeline = Cronología
+timeline-empty = Aún no hay fotos ni vídeos indexados.
+timeline-years = Años
+timeline-months = Meses
+item-count = { $count ->
+    [one] { $count } elemento
+   *[other] { $count } elementos
+}
+timeline-day = { $weekday }, { $day } de { $month } de { $year }
+month-1 = enero
+month-2 = febrero
+month-3 = marzo
+month-4 = abril
+month-5 = mayo
+month-6 = junio
+month-7 = julio
+month-8 = agosto
+month-9 = septiembre
+month-10 = octubre
+month-11 = noviembre
+month-12 = diciembre
+weekday-1 = lunes
+weekday-2 = martes
+weekday-3 = miércoles
+weekday-4 = jueves
+weekday-5 = viernes
+weekday-6 = sábado
+weekday-7 = domingo
+
+## Map
+
+map = Mapa
+map-zoom-in = Acercar
+map-zoom-out = Alejar
+map-world = 🌍 Mundo
+map-label = Mapa de fotos geoetiquetadas
+map-hint = Arrastra para desplazarte y usa la rueda para hacer zoom. Haz clic en un grupo para acercarte o en una foto para abrirla.
+
+## Slideshow
+
+slideshow = Presentación
+slideshow-exit = Salir (Esc)
+slideshow-prev = Anterior (←)
+slideshow-play = Reproducir/pausar (Espacio)
+slideshow-next = Siguiente (→)
+slideshow-in-order = En orden
+slideshow-shuffle = Aleatorio
+slideshow-fullscreen = Pantalla completa (F)
+
+## Errors
+
+error-not-found = No encontrado
+error-forbidden = Acceso denegado
+error-invalid-path = Ruta no válida
+error-request-body-too-large = Solicitud demasiado grande
+error-internal-server-error = Error interno del servidor
+error-destination-exists = El destino ya existe
+error-directory-not-empty = La carpeta no está vacía
+error-file-exists = El archivo ya existe
+error-path-already-exists = La ruta ya existe
+error-path-exists-and-is-not-a-file = La ruta existe y no es un archivo
+error-something-else-is-at-the-original-path = Ahora hay otra cosa en la ruta original
+error-at-least-one-track-is-required = Se necesita al menos una pista
+error-audio-could-not-be-decoded = No se pudo decodificar el audio
+error-between-1-and-1000-operations-are-required = Se necesitan entre 1 y 1000 operaciones
+error-between-1-and-50-tags-are-required = Se necesitan entre 1 y 50 etiquetas
+error-cannot-delete-the-media-directory = No se puede eliminar el directorio multimedia
+error-cannot-move-a-directory-into-itself = No se puede mover una carpeta dentro de sí misma
+error-cannot-move-the-media-directory = No se puede mover el directorio multimedia
+error-destination-must-keep-the-file-s-media-type = El destino debe conservar el tipo multimedia del archivo
+error-expected-a-multipart-form-data-body = Se esperaba un cuerpo multipart/form-data
+error-invalid-bbox = Área del mapa (bbox) no válida
+error-invalid-directory-name = Nombre de carpeta no válido
+error-invalid-file-name = Nombre de archivo no válido
+error-invalid-multipart-body = Cuerpo multipart no válido
+error-missing-host-header = Falta la cabecera Host
+error-no-file-in-multipart-body = No hay ningún archivo en el cuerpo multipart
+error-not-a-media-file-name = No es un nombre de archivo multimedia
+error-playlist-names-must-be-1-to-100-characters = Los nombres de lista deben tener entre 1 y 100 caracteres
+error-playlist-tracks-must-be-audio-files = Las pistas de una lista deben ser archivos de audio
+error-playlists-can-hold-at-most-10000-tracks = Las listas pueden contener como máximo 10000 pistas
+error-queued-tracks-must-be-audio-files = Las pistas de la cola deben ser archivos de audio
+error-queues-can-hold-at-most-10000-tracks = Las colas pueden contener como máximo 10000 pistas
+error-rating-must-be-between-1-and-5 = La valoración debe estar entre 1 y 5
+error-subtitle-file-is-too-large = El archivo de subtítulos es demasiado grande
+error-tags-need-an-index-database = Las etiquetas necesitan una base de datos de índice
+error-track-index-or-position-out-of-range = Índice de pista o posición fuera de rango
+error-upload-interrupted = Subida interrumpida
+error-write-operations-are-disabled = Las operaciones de escritura están desactivadas
+error-type-must-be-image-video-or-audio = type debe ser image, video o audio
+error-sign-in-was-cancelled-or-failed = El inicio de sesión se canceló o falló
+error-missing-code-or-state = Falta code o state
+error-unknown-or-expired-login-attempt = Intento de inicio de sesión desconocido o caducado
+error-target-is-too-long-for-a-qr-code = El destino es demasiado largo para un código QR
+error-only-the-json-format-is-supported = Solo se admite el formato json
+error-archive-entry-is-too-large = La entrada del archivo comprimido es demasiado grande
+error-archive-expands-beyond-the-decompression-limits = El archivo comprimido se expande más allá de los límites de descompresión
+error-sign-in-failed = Error al iniciar sesión
@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+
+use crate::auth::Principal;
+
+/// Lines buffered between request handlers and the writer task
+/// When the disk can't keep up, further lines are dropped rather than slowing requests.
+const CHANNEL_CAPACITY: usize = 10_000;
+
+/// Access log line format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AccessLogFormat {
+    /// Common Log Format
+    Common,
+    /// Combined Log Format (Common plus Referer and User-Agent)
+    #[default]
+    Combined,
+}
+
+/// Handle for submitting lines to the access log writer task
+#[derive(Clone)]
+pub struct AccessLog {
+    tx: mpsc::Sender<String>,
+    format: AccessLogFormat,
+}
+
+impl AccessLog {
+    /// Open the access log and start the background writer
+    /// The file is rotated to `<path>.1`, `<path>.2`, ... once it exceeds `max_bytes`.
+    pub async fn start(path: PathBuf, format: AccessLogFormat, max_bytes: u64, keep: usize) -> Result<Self> {
+        let file = open_append(&path).await?;
+        let size = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+
+        tokio::spawn(write_loop(rx, path, file, size, max_bytes, keep));
+
+        Ok(Self { tx, format })
+    }
+
+    fn submit(&self, line: String) {
+        if self.tx.try_send(line).is_err() {
+            tracing::debug!("Access log queue full - dropping line");
+        }
+    }
+}
+
+async fn open_append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .with_context(|| format!("Failed to open access log: {:?}", path))
+}
+
+/// Background task owning the log file: writes queued lines, flushes after each batch, rotates by size
+async fn write_loop(
+    mut rx: mpsc::Receiver<String>,
+    path: PathBuf,
+    file: File,
+    mut size: u64,
+    max_bytes: u64,
+    keep: usize,
+) {
+    let mut writer = BufWriter::new(file);
+
+    while let Some(line) = rx.recv().await {
+        let mut batch = vec![line];
+        while let Ok(line) = rx.try_recv() {
+            batch.push(line);
+        }
+
+        for line in batch {
+            if let Err(e) = writer.write_all(line.as_bytes()).await {
+                tracing::error!("Failed to write access log: {}", e);
+            }
+            size += line.len() as u64;
+        }
+        if let Err(e) = writer.flush().await {
+            tracing::error!("Failed to flush access log: {}", e);
+        }
+
+        if size >= max_bytes {
+            match rotate(&path, keep).await {
+                Ok(file) => {
+                    writer = BufWriter::new(file);
+                    size = 0;
+                }
+                Err(e) => tracing::error!("Failed to rotate access log: {:?}", e),
+            }
+        }
+    }
+}
+
+/// Shift `<path>.N` files up by one, move the current log to `<path>.1` and reopen it
+async fn rotate(path: &Path, keep: usize) -> Result<File> {
+    let numbered = |n: usize| {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+
+    if keep == 0 {
+        tokio::fs::remove_file(path).await.ok();
+    } else {
+        tokio::fs::remove_file(numbered(keep)).await.ok();
+        for n in (1..keep).rev() {
+            tokio::fs::rename(numbered(n), numbered(n + 1)).await.ok();
+        }
+        tokio::fs::rename(path, numbered(1))
+            .await
+            .with_context(|| format!("Failed to rotate {:?}", path))?;
+    }
+
+    open_append(path).await
+}
+
+/// Middleware writing one Common/Combined Log Format line per request (if enabled)
+pub async fn access_log_middleware(
+    State(log): State<Option<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(log) = log else {
+        return next.run(request).await;
+    };
+
+    let host = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical().to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let request_line = format!(
+        "{} {} {:?}",
+        request.method(),
+        redact_query(request.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/")),
+        request.version()
+    );
+    let referer = redact_query(&header_or_dash(request.headers(), header::REFERER));
+    let user_agent = header_or_dash(request.headers(), header::USER_AGENT);
+    let time = chrono::Local::now().format("%d/%b/%Y:%H:%M:%S %z");
+
+    let response = next.run(request).await;
+
+    let user = response
+        .extensions()
+        .get::<Principal>()
+        .map(|p| escape(&p.username).replace(' ', "%20"))
+        .unwrap_or_else(|| "-".to_string());
+    let bytes = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-");
+
+    let mut line = format!(
+        "{} - {} [{}] \"{}\" {} {}",
+        host,
+        user,
+        time,
+        escape(&request_line),
+        response.status().as_u16(),
+        bytes
+    );
+    if log.format == AccessLogFormat::Combined {
+        line.push_str(&format!(" \"{}\" \"{}\"", referer, user_agent));
+    }
+    line.push('\n');
+    log.submit(line);
+
+    response
+}
+
+/// Query parameters that grant access on their own (signed URLs and share tokens)
+const SECRET_PARAMS: [&str; 3] = ["sig", "token", "expires"];
+
+/// Replace the values of [`SECRET_PARAMS`] in a URL, so the log can't be used to replay them
+fn redact_query(url: &str) -> String {
+    let Some((path, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if SECRET_PARAMS.contains(&name) => format!("{}=REDACTED", name),
+            _ => pair.to_string(),
+        })
+        .collect();
+    format!("{}?{}", path, query.join("&"))
+}
+
+fn header_or_dash(headers: &HeaderMap, name: header::HeaderName) -> String {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(escape)
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Escape quotes, backslashes and control characters so a field can't break the line format
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
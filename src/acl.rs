@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::auth::{Principal, Role};
+use crate::handlers::AppError;
+
+/// Routes whose remaining path is a location inside the media directory
+const MEDIA_PATH_ROUTES: &[&str] = &[
+    "/browse/",
+    "/music/",
+    "/music-archive/",
+    "/media/",
+    "/thumbnail/",
+    "/media-archive/",
+    "/download/",
+    "/album-art/",
+    "/subtitles/",
+    "/share-card/",
+];
+
+/// A rule restricting a subtree of the media directory
+///
+/// ```toml
+/// [[rules]]
+/// path = "private"
+/// users = ["alice"]
+/// roles = ["admin"]  # optional: everyone with one of these roles
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct AclRule {
+    /// Directory relative to the media directory
+    pub path: String,
+    /// Usernames allowed to see the subtree
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// Roles allowed to see the subtree
+    #[serde(default)]
+    pub roles: Vec<Role>,
+}
+
+/// Per-directory access control lists
+/// Paths without a matching rule are visible to every authenticated user.
+/// When rules are nested, the most specific one decides.
+#[derive(Debug, Default, Deserialize)]
+pub struct AccessControl {
+    #[serde(default)]
+    rules: Vec<AclRule>,
+    /// Subtrees the server keeps for itself, such as the trash, hidden from everyone
+    #[serde(skip)]
+    hidden: Vec<String>,
+}
+
+impl AccessControl {
+    /// Load and parse an ACL file from disk
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ACL file: {:?}", path))?;
+
+        let mut acl: AccessControl = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse ACL file: {:?}", path))?;
+
+        for rule in &mut acl.rules {
+            rule.path = normalize(&rule.path)
+                .with_context(|| format!("Invalid ACL path: {:?}", rule.path))?;
+            if rule.path.is_empty() {
+                anyhow::bail!("ACL rules cannot target the media root; use --users-file instead");
+            }
+        }
+
+        // Most specific rule first
+        acl.rules.sort_by_key(|rule| std::cmp::Reverse(rule.path.len()));
+
+        Ok(acl)
+    }
+
+    /// Hide a subtree from every user, whatever the rules say
+    pub fn hide(&mut self, path: &str) {
+        if let Some(path) = normalize(path).filter(|path| !path.is_empty()) {
+            self.hidden.push(path);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Check whether a principal may see a path relative to the media directory
+    /// Paths that try to escape with `..` are denied.
+    pub fn allows(&self, principal: Option<&Principal>, path: &str) -> bool {
+        if self.rules.is_empty() && self.hidden.is_empty() {
+            return true;
+        }
+
+        let Some(path) = normalize(path) else {
+            return false;
+        };
+        if self.hidden.iter().any(|hidden| is_within(&path, hidden)) {
+            return false;
+        }
+
+        let rule = self.rules.iter().find(|rule| is_within(&path, &rule.path));
+
+        match (rule, principal) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(rule), Some(principal)) => {
+                rule.users.iter().any(|u| u == &principal.username)
+                    || rule.roles.contains(&principal.role)
+            }
+        }
+    }
+}
+
+/// Lexically normalize a relative media path: drop empty and `.` segments and
+/// resolve `..`, so `public/../private` is checked as `private`
+/// Returns `None` if the path climbs out of the media directory.
+pub fn normalize(path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+/// Check whether a normalized media path is `dir` itself or inside it
+pub fn is_within(path: &str, dir: &str) -> bool {
+    path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The (still percent-encoded) media path addressed by a request path,
+/// or `None` for routes that don't address a media location
+pub fn media_route_path(request_path: &str) -> Option<&str> {
+    MEDIA_PATH_ROUTES
+        .iter()
+        .find_map(|prefix| request_path.strip_prefix(prefix))
+}
+
+/// Decode a media path taken from a request URI and normalize it
+/// Decoded exactly once, as axum's `Path` extractor decodes it for the handler, so a rule is
+/// checked against the file that is then served. `None` if it isn't UTF-8 or climbs out of
+/// the media directory.
+pub fn decode_media_path(encoded: &str) -> Option<String> {
+    normalize(&percent_decode_str(encoded).decode_utf8().ok()?)
+}
+
+/// Middleware enforcing ACLs on every route addressing a media path
+/// (browse/music listings, media, thumbnails, archives, album art, subtitles and share cards).
+/// Must run inside the auth middleware so the principal is known.
+pub async fn enforce_acl(
+    State(acl): State<Arc<AccessControl>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if acl.is_empty() && acl.hidden.is_empty() {
+        return next.run(request).await;
+    }
+
+    if let Some(media_path) = media_route_path(request.uri().path()) {
+        let Some(media_path) = decode_media_path(media_path) else {
+            return AppError::InvalidPath.into_response();
+        };
+
+        let principal = request.extensions().get::<Principal>();
+        if !acl.allows(principal, &media_path) {
+            tracing::warn!(
+                username = principal.map(|p| p.username.as_str()).unwrap_or("-"),
+                path = %media_path,
+                "Access denied by ACL"
+            );
+            return AppError::Forbidden.into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::Router;
+
+    use crate::handlers::tests::{media_router, status};
+
+    /// The media router behind the ACL middleware, with `private` readable only by alice,
+    /// signed in as `username`
+    fn setup(username: &'static str) -> (tempfile::TempDir, Router) {
+        let (dir, router) = media_router();
+        let acl_file = dir.path().join("acl.toml");
+        std::fs::write(&acl_file, "[[rules]]\npath = \"private\"\nusers = [\"alice\"]\n").unwrap();
+        let acl = Arc::new(AccessControl::load(&acl_file).unwrap());
+
+        let router = router
+            .layer(axum::middleware::from_fn_with_state(acl, enforce_acl))
+            .layer(axum::middleware::from_fn(move |mut request: Request, next: Next| async move {
+                request.extensions_mut().insert(Principal { username: username.to_string(), role: Role::Viewer });
+                next.run(request).await
+            }));
+        (dir, router)
+    }
+
+    #[test]
+    fn normalize_resolves_dot_segments() {
+        assert_eq!(normalize("public/../private/a.png").as_deref(), Some("private/a.png"));
+        assert_eq!(normalize("/a//./b/").as_deref(), Some("a/b"));
+        assert_eq!(normalize("public/../../etc/passwd"), None);
+    }
+
+    #[tokio::test]
+    async fn acl_applies_to_the_file_that_is_served() {
+        let (_dir, router) = setup("bob");
+        assert_eq!(status(&router, "/media/private/secret.png").await, StatusCode::FORBIDDEN);
+        assert_eq!(status(&router, "/media/public/%2e%2e/private/secret.png").await, StatusCode::FORBIDDEN);
+        // Decoded once, `%2e%2e` is a name, not a parent directory
+        assert_eq!(status(&router, "/media/public/%252e%252e/private/secret.png").await, StatusCode::NOT_FOUND);
+
+        let (_dir, router) = setup("alice");
+        assert_eq!(status(&router, "/media/private/secret.png").await, StatusCode::OK);
+        assert_eq!(status(&router, "/media/public/%2e%2e/private/secret.png").await, StatusCode::OK);
+    }
+}
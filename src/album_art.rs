@@ -0,0 +1,327 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::archives::{self, ArchiveLimits};
+use crate::constants;
+use crate::decoding;
+use crate::handlers::{AppError, AppState};
+use crate::stats::CacheCounters;
+use crate::workers::Workers;
+
+/// Widths and heights served for `?size=`; other sizes are rounded up to the next one
+/// A fixed set keeps the number of cached variants per track small.
+const ART_SIZES: [u32; 5] = [64, 128, 256, 512, 1024];
+
+/// Query parameters of the album art endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct AlbumArtQuery {
+    /// Largest width or height wanted, in pixels
+    pub size: Option<u32>,
+}
+
+/// An embedded picture and its MIME type
+pub struct Art {
+    pub data: Vec<u8>,
+    pub mime: String,
+}
+
+impl Art {
+    /// Wrap image file contents, or `None` if they aren't an image
+    fn from_image(data: Vec<u8>) -> Option<Self> {
+        let mime = infer::get(&data)
+            .map(|kind| kind.mime_type())
+            .filter(|mime| mime.starts_with("image/"))?
+            .to_string();
+        Some(Self { data, mime })
+    }
+}
+
+/// On-disk cache of extracted album art and its resized variants, also used for thumbnails
+/// Each track gets a directory named after the hash of its path. Entries are named after
+/// the track's modification time, so an edited track misses the cache and its stale
+/// entries are replaced. Tracks without art get an empty marker, so they aren't reread.
+pub struct ArtCache {
+    dir: PathBuf,
+    pub counters: CacheCounters,
+}
+
+impl ArtCache {
+    /// Open (or create) the cache directory; `name` labels its hit and miss counters
+    pub fn open(dir: &Path, name: &'static str) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create album art cache directory: {:?}", dir))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            counters: CacheCounters::new(name),
+        })
+    }
+
+    fn entry_dir(&self, key: &str) -> PathBuf {
+        let hash: String = Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.dir.join(&hash[..2]).join(hash)
+    }
+
+    /// Cached art of a track, `Some(None)` if the track is known to have none,
+    /// or `None` on a cache miss
+    pub fn get(&self, key: &str, mtime: u64, size: Option<u32>) -> Option<Option<Art>> {
+        let entry = self.lookup(key, mtime, size);
+        match entry {
+            Some(_) => self.counters.hit(1),
+            None => self.counters.miss(1),
+        }
+        entry
+    }
+
+    fn lookup(&self, key: &str, mtime: u64, size: Option<u32>) -> Option<Option<Art>> {
+        let dir = self.entry_dir(key);
+        if dir.join(format!("{}-none", mtime)).exists() {
+            return Some(None);
+        }
+        let data = std::fs::read(dir.join(entry_name(mtime, size))).ok()?;
+        Some(Some(Art::from_image(data)?))
+    }
+
+    /// Whether `get` would hit, without reading the entry
+    pub fn contains(&self, key: &str, mtime: u64, size: Option<u32>) -> bool {
+        let dir = self.entry_dir(key);
+        dir.join(format!("{}-none", mtime)).exists() || dir.join(entry_name(mtime, size)).exists()
+    }
+
+    /// Store the art of a track, or record that it has none
+    /// Entries of older versions of the track are removed.
+    pub fn put(&self, key: &str, mtime: u64, size: Option<u32>, art: Option<&Art>) -> Result<()> {
+        let dir = self.entry_dir(key);
+        std::fs::create_dir_all(&dir)?;
+
+        let current = format!("{}-", mtime);
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            if !entry.file_name().to_string_lossy().starts_with(&current)
+                && std::fs::remove_file(entry.path()).is_ok()
+            {
+                self.counters.evict(1);
+            }
+        }
+
+        let (name, data) = match art {
+            Some(art) => (entry_name(mtime, size), art.data.as_slice()),
+            None => (format!("{}-none", mtime), &[][..]),
+        };
+        // Write under a temporary name first so readers never see a partial file
+        let temp = dir.join(format!("{}.{:x}.tmp", name, rand::random::<u64>()));
+        std::fs::write(&temp, data)?;
+        std::fs::rename(&temp, dir.join(name))?;
+        Ok(())
+    }
+}
+
+fn entry_name(mtime: u64, size: Option<u32>) -> String {
+    match size {
+        Some(size) => format!("{}-{}", mtime, size),
+        None => format!("{}-full", mtime),
+    }
+}
+
+/// Preference of a file name as folder art, lower is better, or `None` if it isn't folder art
+fn folder_art_rank(name: &str) -> Option<usize> {
+    constants::FOLDER_ART_NAMES
+        .iter()
+        .position(|art| art.eq_ignore_ascii_case(name))
+}
+
+/// Cover image stored next to the tracks in `dir`, such as `cover.jpg` or `folder.jpg`
+pub async fn folder_art(dir: PathBuf) -> Option<Art> {
+    tokio::task::spawn_blocking(move || {
+        let (_, path) = std::fs::read_dir(&dir)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| {
+                let rank = folder_art_rank(entry.file_name().to_str()?)?;
+                entry.file_type().ok()?.is_file().then(|| (rank, entry.path()))
+            })
+            .min_by_key(|(rank, _)| *rank)?;
+        Art::from_image(decoding::read_image(&path).ok()?)
+    })
+    .await
+    .ok()?
+}
+
+/// Cover image stored next to `track` inside an archive
+/// Covers too big to extract into memory are skipped.
+pub async fn archive_folder_art(
+    archive: &Path,
+    track: &str,
+    limits: ArchiveLimits,
+    workers: &Workers,
+) -> Option<Art> {
+    let track_dir = track.rsplit_once('/').map_or("", |(dir, _)| dir).to_string();
+    let data = archives::extract_best_file_from_archive(archive, limits, workers, move |path| {
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if dir == track_dir {
+            folder_art_rank(name)
+        } else {
+            None
+        }
+    })
+    .await
+    .ok()??
+    .into_memory()?;
+    Art::from_image(data)
+}
+
+/// Round a requested size up to the nearest served size
+pub fn snap_size(size: u32) -> u32 {
+    ART_SIZES
+        .iter()
+        .copied()
+        .find(|&served| served >= size)
+        .unwrap_or(ART_SIZES[ART_SIZES.len() - 1])
+}
+
+/// Shrink art to fit in a `size`×`size` square, re-encoded as JPEG
+/// Art that already fits, or can't be decoded, is returned unchanged.
+async fn resize(state: &AppState, art: Art, size: u32) -> Art {
+    match decoding::dimensions(&art.data) {
+        Some((width, height)) if width > size || height > size => {}
+        _ => return art,
+    }
+
+    match decoding::thumbnail(state, art.data.clone(), size).await {
+        Ok(data) => Art {
+            data,
+            mime: "image/jpeg".to_string(),
+        },
+        Err(e) => {
+            tracing::debug!("Failed to resize album art: {}", e);
+            art
+        }
+    }
+}
+
+/// Serve the album art of a track, resized if `size` is given
+/// `key` identifies the track and `mtime` its version; together they form the cache key and
+/// the ETag. `extract` reads the embedded picture and only runs on a cache miss.
+pub async fn serve<F>(
+    state: &AppState,
+    headers: &HeaderMap,
+    key: String,
+    mtime: u64,
+    size: Option<u32>,
+    extract: F,
+) -> Result<Response, AppError>
+where
+    F: Future<Output = Option<Art>>,
+{
+    let size = size.map(snap_size);
+    let etag = format!("\"{}\"", entry_name(mtime, size));
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    if not_modified {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, &etag)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let art = match &state.art_cache {
+        Some(cache) => cached_art(state, cache.clone(), key, mtime, size, extract).await?,
+        None => match (extract.await, size) {
+            (Some(art), Some(size)) => Some(resize(state, art, size).await),
+            (art, _) => art,
+        },
+    };
+    let art = art.ok_or(AppError::NotFound)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, art.mime)
+        .header(header::CONTENT_LENGTH, art.data.len())
+        .header(header::CACHE_CONTROL, state.cache.album_art.to_string())
+        .header(header::ETAG, etag)
+        .body(Body::from(art.data))
+        .unwrap())
+}
+
+/// Find art in the cache, extracting, resizing and storing it on a miss
+async fn cached_art<F>(
+    state: &AppState,
+    cache: Arc<ArtCache>,
+    key: String,
+    mtime: u64,
+    size: Option<u32>,
+    extract: F,
+) -> Result<Option<Art>, AppError>
+where
+    F: Future<Output = Option<Art>>,
+{
+    let (hit, full) = {
+        let (cache, key) = (cache.clone(), key.clone());
+        blocking(move || {
+            let hit = cache.get(&key, mtime, size);
+            // A resized miss can still reuse the cached full-size art
+            let full = match (&hit, size) {
+                (None, Some(_)) => cache.get(&key, mtime, None),
+                _ => None,
+            };
+            (hit, full)
+        })
+        .await?
+    };
+    if let Some(art) = hit {
+        return Ok(art);
+    }
+
+    let full = match full {
+        Some(art) => art,
+        None => {
+            let art = extract.await;
+            let (cache, key) = (cache.clone(), key.clone());
+            blocking(move || {
+                if let Err(e) = cache.put(&key, mtime, None, art.as_ref()) {
+                    tracing::warn!("Failed to cache album art: {:?}", e);
+                }
+                art
+            })
+            .await?
+        }
+    };
+    let Some(size) = size else {
+        return Ok(full);
+    };
+    let Some(full) = full else {
+        return Ok(None);
+    };
+
+    let resized = resize(state, full, size).await;
+    blocking(move || {
+        if let Err(e) = cache.put(&key, mtime, Some(size), Some(&resized)) {
+            tracing::warn!("Failed to cache album art: {:?}", e);
+        }
+        Some(resized)
+    })
+    .await
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, AppError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|_| AppError::InternalError)
+}
@@ -1,16 +1,20 @@
-use axum::{response::Json, extract::State};
-use serde::Serialize;
-use utoipa::ToSchema;
+use axum::{response::Json, extract::{Query, State}, Extension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 
+use crate::auth::{Principal, Role};
+use crate::cache;
 use crate::constants;
-use crate::handlers::AppState;
+use crate::handlers::{self, AppError, AppState};
+use crate::stats::CacheCounts;
 
 /// Configuration information about supported file types
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ConfigInfo {
     /// Emoji prefix displayed in the app
     pub emoji_prefix: String,
-    /// Application name
+    /// Site title shown in the app (--site-title)
     pub app_name: String,
     /// TLS version enforced
     pub tls_version: String,
@@ -33,10 +37,10 @@ pub struct ConfigInfo {
     ),
     tag = "info"
 )]
-pub async fn config_handler(State(_state): State<AppState>) -> Json<ConfigInfo> {
+pub async fn config_handler(State(state): State<AppState>) -> Json<ConfigInfo> {
     Json(ConfigInfo {
         emoji_prefix: constants::EMOJI_PREFIX.to_string(),
-        app_name: constants::APP_NAME.to_string(),
+        app_name: state.branding.title.clone(),
         tls_version: constants::TLS_VERSION.to_string(),
         http_version: constants::HTTP_VERSION.to_string(),
         image_extensions: constants::IMAGE_EXTENSIONS.iter().map(|s| s.to_string()).collect(),
@@ -44,3 +48,558 @@ pub async fn config_handler(State(_state): State<AppState>) -> Json<ConfigInfo>
         audio_extensions: constants::AUDIO_EXTENSIONS.iter().map(|s| s.to_string()).collect(),
     })
 }
+
+/// Result of a manual media cache refresh
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RefreshResponse {
+    /// Number of media files in the rebuilt cache
+    pub items: usize,
+}
+
+/// Rebuild the media cache immediately instead of waiting for the next scheduled refresh
+#[utoipa::path(
+    post,
+    path = "/api/admin/refresh",
+    responses(
+        (status = 200, description = "Media cache rebuilt", body = RefreshResponse),
+        (status = 403, description = "Admin role required")
+    ),
+    tag = "admin"
+)]
+pub async fn refresh_handler(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<RefreshResponse>, AppError> {
+    tracing::info!(username = %principal.username, "Manual media cache refresh requested");
+
+    let items = cache::refresh_media_cache(
+        &state.media_cache,
+        &state.scan,
+        &state.media_dir,
+        cache::Source::new(state.index.as_ref(), state.store.as_deref(), state.scan_concurrency),
+        state.search.as_ref(),
+        &state.events,
+    )
+    .await?;
+
+    Ok(Json(RefreshResponse { items }))
+}
+
+/// Server status for the admin dashboard
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AdminStats {
+    /// Seconds since the server started
+    pub uptime_seconds: u64,
+    /// Server start time (RFC 3339)
+    pub started_at: String,
+    /// Requests handled since startup
+    pub total_requests: u64,
+    /// Response body bytes served (responses with a known length)
+    pub bytes_served: u64,
+    /// Media downloads currently in progress
+    pub active_streams: u64,
+    /// Media cache state
+    pub cache: CacheStats,
+    /// Hit, miss and eviction counts of the media cache, the index (with `--index-db`) and
+    /// the album art and thumbnail caches (when configured)
+    pub caches: Vec<CacheCounts>,
+    /// Rate limiter state
+    pub rate_limits: RateLimitStats,
+}
+
+/// Media cache entry counts and refresh timing
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CacheStats {
+    /// Total cached media files
+    pub items: usize,
+    /// Cached images
+    pub images: usize,
+    /// Cached videos
+    pub videos: usize,
+    /// Cached audio files
+    pub audio: usize,
+    /// Time of the last refresh (RFC 3339)
+    pub last_refresh: String,
+    /// Duration of the last refresh in milliseconds
+    pub last_refresh_duration_ms: u64,
+}
+
+/// State of the failed-login and per-request rate limiters
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RateLimitStats {
+    /// Failed authentication limiter
+    pub auth: AuthLimiterStats,
+    /// Per-client request limiter (absent unless `--rate-limit-rps` is set)
+    pub request: Option<RequestLimiterStats>,
+}
+
+/// Failed authentication limiter state
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuthLimiterStats {
+    /// Failed attempts allowed per window
+    pub max_attempts: usize,
+    /// Window length in seconds
+    pub window_seconds: u64,
+    /// Client IPs with recent failed attempts
+    pub tracked_ips: usize,
+    /// Client IPs currently locked out
+    pub blocked_ips: usize,
+}
+
+/// Per-client request limiter state
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RequestLimiterStats {
+    /// Sustained requests per second allowed per client
+    pub rate: f64,
+    /// Burst size per client
+    pub burst: u32,
+    /// Clients that have used part of their burst
+    pub tracked_clients: usize,
+    /// Clients currently being rejected
+    pub throttled_clients: usize,
+}
+
+/// Get server statistics: uptime, traffic, cache and rate limiter state
+#[utoipa::path(
+    get,
+    path = "/api/admin/stats",
+    responses(
+        (status = 200, description = "Server statistics", body = AdminStats),
+        (status = 403, description = "Admin role required")
+    ),
+    tag = "admin"
+)]
+pub async fn stats_handler(State(state): State<AppState>) -> Json<AdminStats> {
+    let cache = {
+        let cache = state.media_cache.read().await;
+        CacheStats {
+            items: cache.len(),
+            images: cache.count_of("image"),
+            videos: cache.count_of("video"),
+            audio: cache.count_of("audio"),
+            last_refresh: cache.refreshed_at.to_rfc3339(),
+            last_refresh_duration_ms: cache.refresh_duration.as_millis() as u64,
+        }
+    };
+
+    let mut caches = vec![match &state.index {
+        Some(_) => state.scan.index_counters.snapshot(),
+        None => state.scan.media_counters.snapshot(),
+    }];
+    caches.extend(
+        [&state.art_cache, &state.thumbnail_cache]
+            .into_iter()
+            .flatten()
+            .map(|cache| cache.counters.snapshot()),
+    );
+
+    let (tracked_ips, blocked_ips) = state.auth_limiter.snapshot().await;
+    let request = match &state.request_limiter {
+        Some(limiter) => {
+            let (tracked_clients, throttled_clients) = limiter.snapshot().await;
+            Some(RequestLimiterStats {
+                rate: limiter.rate(),
+                burst: limiter.burst(),
+                tracked_clients,
+                throttled_clients,
+            })
+        }
+        None => None,
+    };
+
+    let stats = &state.stats;
+    Json(AdminStats {
+        uptime_seconds: stats.uptime().as_secs(),
+        started_at: stats.started_at().to_rfc3339(),
+        total_requests: stats.total_requests(),
+        bytes_served: stats.bytes_served(),
+        active_streams: stats.active_streams(),
+        cache,
+        caches,
+        rate_limits: RateLimitStats {
+            auth: AuthLimiterStats {
+                max_attempts: state.auth_limiter.max_attempts(),
+                window_seconds: state.auth_limiter.window().as_secs(),
+                tracked_ips,
+                blocked_ips,
+            },
+            request,
+        },
+    })
+}
+
+/// Disk usage of the media volume and the media library
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StorageStats {
+    /// Space on the volume holding the media directory (absent if it could not be read)
+    pub volume: Option<cache::VolumeUsage>,
+    /// Number of media files in the library
+    pub media_files: usize,
+    /// Combined size of all media files in bytes
+    pub media_bytes: u64,
+    /// Media stored under each top-level directory, largest first
+    pub directories: Vec<cache::DirectoryUsage>,
+    /// Time the figures were computed, at the last cache refresh (RFC 3339)
+    pub computed_at: String,
+}
+
+/// Get disk usage of the media volume and per-directory library sizes
+/// Figures are computed during the media cache refresh, so this returns immediately.
+#[utoipa::path(
+    get,
+    path = "/api/stats/storage",
+    responses(
+        (status = 200, description = "Storage statistics", body = StorageStats)
+    ),
+    tag = "stats"
+)]
+pub async fn storage_stats_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Json<StorageStats> {
+    let cache = state.media_cache.read().await;
+
+    // Totals include every file, but directories hidden by an ACL are not listed
+    let directories = cache
+        .directories
+        .iter()
+        .filter(|dir| state.acl.allows(principal.as_deref(), &dir.path))
+        .cloned()
+        .collect();
+
+    Json(StorageStats {
+        volume: cache.volume.clone(),
+        media_files: cache.len(),
+        media_bytes: cache.total_bytes(),
+        directories,
+        computed_at: cache.refreshed_at.to_rfc3339(),
+    })
+}
+
+/// Default and maximum number of entries in the largest files and deepest directories lists
+const DEFAULT_TOP_N: usize = 10;
+const MAX_TOP_N: usize = 100;
+
+/// Query parameters for library statistics
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct LibraryStatsQuery {
+    /// Number of largest files and deepest directories to return (default 10, max 100)
+    limit: Option<usize>,
+}
+
+/// Composition of the media library
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LibraryStats {
+    /// Number of media files
+    pub total_files: usize,
+    /// Combined size of all media files in bytes
+    pub total_bytes: u64,
+    /// Counts and sizes per media type (image, video, audio), most files first
+    pub by_type: Vec<CategoryStats>,
+    /// Counts and sizes per lowercase file extension, most files first
+    pub by_extension: Vec<CategoryStats>,
+    /// Largest media files, largest first
+    pub largest_files: Vec<handlers::FilterResult>,
+    /// Most deeply nested directories containing media, deepest first
+    pub deepest_directories: Vec<DirectoryDepth>,
+}
+
+/// File count and combined size for one media type or extension
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CategoryStats {
+    /// Media type or extension
+    pub name: String,
+    /// Number of files
+    pub files: usize,
+    /// Combined size in bytes
+    pub bytes: u64,
+}
+
+/// A directory and how deeply it is nested below the media directory
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DirectoryDepth {
+    /// Directory path relative to the media directory
+    pub path: String,
+    /// Number of path segments (a top-level directory has depth 1)
+    pub depth: usize,
+    /// Media files directly inside the directory
+    pub files: usize,
+}
+
+/// Get library composition: counts and sizes by type and extension, largest files and deepest directories
+/// Computed from the media cache; files hidden by an ACL are not counted.
+#[utoipa::path(
+    get,
+    path = "/api/stats/library",
+    params(LibraryStatsQuery),
+    responses(
+        (status = 200, description = "Library statistics", body = LibraryStats)
+    ),
+    tag = "stats"
+)]
+pub async fn library_stats_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<LibraryStatsQuery>,
+) -> Json<LibraryStats> {
+    let limit = query.limit.unwrap_or(DEFAULT_TOP_N).clamp(1, MAX_TOP_N);
+
+    let cache = state.media_cache.read().await;
+    let items: Vec<&handlers::FilterResult> = cache
+        .items()
+        .filter(|item| state.acl.allows(principal.as_deref(), &item.path))
+        .collect();
+
+    let mut by_type: HashMap<String, CategoryStats> = HashMap::new();
+    let mut by_extension: HashMap<String, CategoryStats> = HashMap::new();
+    let mut directories: HashMap<&str, usize> = HashMap::new();
+    for item in &items {
+        let extension = std::path::Path::new(&item.name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        add_to_category(&mut by_type, &item.file_type, item.size);
+        add_to_category(&mut by_extension, &extension, item.size);
+
+        if let Some((dir, _)) = item.path.rsplit_once('/') {
+            *directories.entry(dir).or_default() += 1;
+        }
+    }
+
+    let mut largest_files = items.clone();
+    largest_files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    largest_files.truncate(limit);
+
+    let mut deepest_directories: Vec<DirectoryDepth> = directories
+        .into_iter()
+        .map(|(path, files)| DirectoryDepth {
+            path: path.to_string(),
+            depth: path.split('/').count(),
+            files,
+        })
+        .collect();
+    deepest_directories.sort_by(|a, b| b.depth.cmp(&a.depth).then_with(|| a.path.cmp(&b.path)));
+    deepest_directories.truncate(limit);
+
+    Json(LibraryStats {
+        total_files: items.len(),
+        total_bytes: items.iter().map(|item| item.size).sum(),
+        by_type: sorted_categories(by_type),
+        by_extension: sorted_categories(by_extension),
+        largest_files: largest_files.into_iter().cloned().collect(),
+        deepest_directories,
+    })
+}
+
+fn add_to_category(categories: &mut HashMap<String, CategoryStats>, name: &str, bytes: u64) {
+    let category = categories
+        .entry(name.to_string())
+        .or_insert_with(|| CategoryStats {
+            name: name.to_string(),
+            files: 0,
+            bytes: 0,
+        });
+    category.files += 1;
+    category.bytes += bytes;
+}
+
+fn sorted_categories(categories: HashMap<String, CategoryStats>) -> Vec<CategoryStats> {
+    let mut categories: Vec<CategoryStats> = categories.into_values().collect();
+    categories.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.name.cmp(&b.name)));
+    categories
+}
+
+/// Get progress of the media scan: files scanned, estimated time remaining and errors
+/// Error messages are only included for admins, as they may name restricted directories.
+#[utoipa::path(
+    get,
+    path = "/api/index/status",
+    responses(
+        (status = 200, description = "Media scan status", body = cache::IndexStatus)
+    ),
+    tag = "stats"
+)]
+pub async fn index_status_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Json<cache::IndexStatus> {
+    let is_admin = principal.is_some_and(|p| p.role == Role::Admin);
+    Json(state.scan.status(is_admin))
+}
+
+/// Search hits considered before access control and paging
+const MAX_SEARCH_HITS: usize = 1000;
+
+/// Query parameters for full-text search
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SearchQuery {
+    /// Words to search for in file names, paths and audio tags
+    q: String,
+    /// Only return files of this type (image, video, or audio)
+    #[serde(rename = "type")]
+    file_type: Option<String>,
+    /// Page number (starting at 1)
+    page: Option<usize>,
+    /// Results per page (default 50, max 500)
+    per_page: Option<usize>,
+}
+
+/// Full-text search results, best matches first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResponse {
+    /// Matching files
+    pub results: Vec<SearchResult>,
+    /// Total number of results (at most 1000)
+    pub total: usize,
+    /// Current page number
+    pub page: usize,
+    /// Number of items per page
+    pub per_page: usize,
+    /// Total number of pages
+    pub total_pages: usize,
+    /// Whether a later page exists
+    pub has_next: bool,
+}
+
+/// A file matching a search, with its relevance score
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub file: handlers::FilterResult,
+    /// Relevance score; higher is a better match
+    pub score: f32,
+}
+
+/// Search file names, paths and audio tags, ranked by relevance
+/// Every word must match, exactly, as a prefix or with a typo. Requires `--search-index`.
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching files, best first", body = SearchResponse),
+        (status = 404, description = "Search index not enabled")
+    ),
+    tag = "media"
+)]
+pub async fn search_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, AppError> {
+    let search = state.search.clone().ok_or(AppError::NotFound)?;
+
+    let text = query.q.clone();
+    let file_type = query.file_type.clone();
+    let mut hits = tokio::task::spawn_blocking(move || {
+        search.search(&text, file_type.as_deref(), MAX_SEARCH_HITS)
+    })
+    .await
+    .map_err(|_| AppError::InternalError)?
+    .map_err(|e| {
+        tracing::error!("Search failed: {:?}", e);
+        AppError::InternalError
+    })?;
+    hits.retain(|hit| state.acl.allows(principal.as_deref(), &hit.file.path));
+
+    let total = hits.len();
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
+    let page = query.page.unwrap_or(1).max(1);
+    let total_pages = total.div_ceil(per_page);
+
+    let results = hits
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .map(|hit| SearchResult {
+            file: hit.file,
+            score: hit.score,
+        })
+        .collect();
+
+    Ok(Json(SearchResponse {
+        results,
+        total,
+        page,
+        per_page,
+        total_pages,
+        has_next: page < total_pages,
+    }))
+}
+
+/// Default and largest number of differing hash bits for images to count as similar
+const DEFAULT_MAX_DISTANCE: u32 = 10;
+const HASH_BITS: u32 = 64;
+
+/// Query parameters for similar image search
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SimilarQuery {
+    /// Image to compare against, relative to the media directory
+    path: String,
+    /// Largest Hamming distance between perceptual hashes to include (default 10, max 64)
+    max_distance: Option<u32>,
+    /// Maximum number of results (default 50, max 500)
+    limit: Option<usize>,
+}
+
+/// Images visually similar to a given one
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SimilarResponse {
+    /// The image compared against
+    pub path: String,
+    /// Similar images, closest first
+    pub results: Vec<SimilarImage>,
+}
+
+/// An image and how different its perceptual hash is
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SimilarImage {
+    #[serde(flatten)]
+    pub file: handlers::FilterResult,
+    /// Differing bits between perceptual hashes (0 means near-identical, 64 is the maximum)
+    pub distance: u32,
+}
+
+/// Find visually similar images, e.g. near-duplicate burst shots
+/// Compares perceptual hashes computed while indexing. Requires `--index-db`.
+#[utoipa::path(
+    get,
+    path = "/api/similar",
+    params(SimilarQuery),
+    responses(
+        (status = 200, description = "Similar images, closest first", body = SimilarResponse),
+        (status = 404, description = "Image not indexed, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn similar_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<SimilarQuery>,
+) -> Result<Json<SimilarResponse>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let path = query.path.trim_start_matches('/').to_string();
+    if !state.acl.allows(principal.as_deref(), &path) {
+        return Err(AppError::NotFound);
+    }
+
+    let max_distance = query.max_distance.unwrap_or(DEFAULT_MAX_DISTANCE).min(HASH_BITS);
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let similar = index
+        .similar_images(path.clone(), max_distance)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to query media index: {:?}", e);
+            AppError::InternalError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    let results = similar
+        .into_iter()
+        .filter(|(file, _)| state.acl.allows(principal.as_deref(), &file.path))
+        .take(limit)
+        .map(|(file, distance)| SimilarImage { file, distance })
+        .collect();
+
+    Ok(Json(SimilarResponse { path, results }))
+}
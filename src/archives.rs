@@ -1,9 +1,255 @@
+use crate::config::Config;
 use crate::constants;
 use crate::models::{DirectoryEntry, EntryType};
+use crate::natural::NameOrder;
+use crate::workers::Workers;
 use anyhow::Result;
-use std::io::{Cursor, Read};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 
+/// Decompressed output an archive may produce regardless of its compression ratio,
+/// so small archives of very compressible files still open
+const RATIO_SLACK: u64 = 1024 * 1024;
+
+/// How much an archive operation may decompress and extract
+/// These keep decompression bombs, such as a tiny gzip expanding to terabytes of zeros, from
+/// exhausting memory, disk or CPU when an archive is listed or a file is extracted from it.
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveLimits {
+    /// Entries up to this many bytes are extracted into memory, larger ones into a temporary file
+    pub memory: u64,
+    /// Entries larger than this many bytes are refused
+    pub max_entry: u64,
+    /// Largest ratio of decompressed to compressed bytes
+    pub max_ratio: u64,
+    /// Most bytes one operation may decompress, counting the entries it skips
+    pub max_output: u64,
+}
+
+impl ArchiveLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            memory: config.archive_memory_limit.saturating_mul(1024 * 1024),
+            max_entry: config.max_archive_entry_size.saturating_mul(1024 * 1024),
+            max_ratio: config.max_archive_ratio,
+            max_output: config.max_archive_output.saturating_mul(1024 * 1024),
+        }
+    }
+}
+
+/// An archive operation stopped by one of its `ArchiveLimits`
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum LimitExceeded {
+    #[error("Archive entry is larger than {0} bytes")]
+    EntrySize(u64),
+    #[error("Archive data expands more than {0} times")]
+    Ratio(u64),
+    #[error("Archive expands to more than {0} bytes")]
+    Output(u64),
+}
+
+/// Decompression accounting of one archive operation
+/// Readers report the compressed bytes they consume and the bytes they produce; the first
+/// read that breaks a limit fails, and the limit is kept so the operation can report it.
+struct Meter {
+    limits: ArchiveLimits,
+    compressed: Cell<u64>,
+    output: Cell<u64>,
+    exceeded: Cell<Option<LimitExceeded>>,
+}
+
+impl Meter {
+    fn new(limits: ArchiveLimits) -> Self {
+        Self {
+            limits,
+            compressed: Cell::new(0),
+            output: Cell::new(0),
+            exceeded: Cell::new(None),
+        }
+    }
+
+    fn fail(&self, exceeded: LimitExceeded) -> std::io::Error {
+        self.exceeded.set(Some(exceeded));
+        std::io::Error::other(exceeded)
+    }
+
+    /// Count `len` more decompressed bytes
+    fn produce(&self, len: u64) -> std::io::Result<()> {
+        let output = self.output.get() + len;
+        self.output.set(output);
+        if output > self.limits.max_output {
+            return Err(self.fail(LimitExceeded::Output(self.limits.max_output)));
+        }
+        if output > RATIO_SLACK && output > self.compressed.get().saturating_mul(self.limits.max_ratio) {
+            return Err(self.fail(LimitExceeded::Ratio(self.limits.max_ratio)));
+        }
+        Ok(())
+    }
+
+    /// Check a ZIP entry by the sizes it declares, and count its compressed bytes
+    /// Reading it still goes through `bound`, in case the declared size is a lie.
+    fn zip_entry(&self, size: u64, compressed_size: u64) -> Result<()> {
+        if size > RATIO_SLACK && size > compressed_size.saturating_mul(self.limits.max_ratio) {
+            return Err(self.fail(LimitExceeded::Ratio(self.limits.max_ratio)).into());
+        }
+        self.compressed.set(self.compressed.get() + compressed_size);
+        Ok(())
+    }
+
+    /// Count what `reader` produces against the limits
+    fn bound<R: Read>(&self, reader: R) -> Bounded<'_, R> {
+        Bounded { reader, meter: self }
+    }
+
+    /// The limit that stopped the operation, in place of the error it caused, if there is one
+    fn finish<T>(&self, result: Result<T>) -> Result<T> {
+        match self.exceeded.get() {
+            Some(exceeded) => Err(exceeded.into()),
+            None => result,
+        }
+    }
+}
+
+/// Decompressed data, counted against a `Meter`
+struct Bounded<'a, R> {
+    reader: R,
+    meter: &'a Meter,
+}
+
+impl<R: Read> Read for Bounded<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.meter.produce(len as u64)?;
+        Ok(len)
+    }
+}
+
+/// Compressed data read from an archive file, counted against a `Meter`
+struct Compressed<'a, R> {
+    reader: R,
+    meter: &'a Meter,
+}
+
+impl<R: Read> Read for Compressed<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.meter.compressed.set(self.meter.compressed.get() + len as u64);
+        Ok(len)
+    }
+}
+
+/// A file extracted from an archive
+pub enum Extracted {
+    /// Small enough to keep in memory
+    Memory(Vec<u8>),
+    /// Spooled to an anonymous temporary file, which disappears when closed
+    File { file: File, size: u64 },
+}
+
+impl Extracted {
+    pub fn size(&self) -> u64 {
+        match self {
+            Extracted::Memory(data) => data.len() as u64,
+            Extracted::File { size, .. } => *size,
+        }
+    }
+
+    /// The first bytes of the contents, enough to detect their type
+    pub fn head(&mut self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Extracted::Memory(data) => Ok(data[..data.len().min(constants::SNIFF_SIZE)].to_vec()),
+            Extracted::File { file, .. } => {
+                let mut head = Vec::new();
+                Read::by_ref(file).take(constants::SNIFF_SIZE as u64).read_to_end(&mut head)?;
+                file.rewind()?;
+                Ok(head)
+            }
+        }
+    }
+
+    /// The contents, if they were small enough to keep in memory
+    pub fn into_memory(self) -> Option<Vec<u8>> {
+        match self {
+            Extracted::Memory(data) => Some(data),
+            Extracted::File { .. } => None,
+        }
+    }
+}
+
+/// Read an archive entry of `declared_size` bytes within the limits of `meter`
+/// The declared size only picks the first attempt: an entry that turns out bigger moves from
+/// memory to a temporary file, and one past the maximum fails with `LimitExceeded::EntrySize`.
+fn read_entry(mut entry: impl Read, declared_size: u64, meter: &Meter) -> Result<Extracted> {
+    let limits = meter.limits;
+    if declared_size > limits.max_entry {
+        anyhow::bail!(LimitExceeded::EntrySize(limits.max_entry));
+    }
+    let in_memory = limits.memory.min(limits.max_entry);
+    let mut data = Vec::with_capacity(declared_size.min(in_memory) as usize);
+    entry.by_ref().take(in_memory.saturating_add(1)).read_to_end(&mut data)?;
+    if data.len() as u64 <= in_memory {
+        return Ok(Extracted::Memory(data));
+    }
+    if data.len() as u64 > limits.max_entry {
+        anyhow::bail!(LimitExceeded::EntrySize(limits.max_entry));
+    }
+
+    let mut file = tempfile::tempfile()?;
+    file.write_all(&data)?;
+    let rest = limits.max_entry.saturating_add(1) - data.len() as u64;
+    let size = data.len() as u64 + std::io::copy(&mut entry.take(rest), &mut file)?;
+    if size > limits.max_entry {
+        anyhow::bail!(LimitExceeded::EntrySize(limits.max_entry));
+    }
+    file.rewind()?;
+    Ok(Extracted::File { file, size })
+}
+
+/// Open an archive for reading in place, rather than loading it into memory
+fn open(archive_path: &Path) -> Result<BufReader<File>> {
+    Ok(BufReader::new(File::open(archive_path)?))
+}
+
+/// The decompressed stream of a TAR archive, counted against `meter`
+fn tar_reader<'a>(reader: BufReader<File>, filename: &str, meter: &'a Meter) -> Bounded<'a, Box<dyn Read + 'a>> {
+    let reader = Compressed { reader, meter };
+    // Note: for bz2 we'd need the bzip2 crate, so those share the gzip decoder for now
+    let reader: Box<dyn Read> = if filename.ends_with(".tar.gz") || filename.ends_with(".tgz")
+        || filename.ends_with(".tar.bz2") || filename.ends_with(".tbz2")
+    {
+        Box::new(flate2::read::GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    meter.bound(reader)
+}
+
+/// Run an archive operation on the worker pool, within `limits`
+/// `operation` gets the archive's path, its file name and the meter its readers report to.
+async fn metered<T, F>(archive_path: &Path, limits: ArchiveLimits, workers: &Workers, operation: F) -> Result<T>
+where
+    F: FnOnce(&Path, &str, &Meter) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let archive_path = archive_path.to_path_buf();
+    workers.run(move || {
+        let filename = archive_path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        let meter = Meter::new(limits);
+        let result = meter.finish(operation(&archive_path, filename, &meter));
+        if let Err(e) = &result {
+            if let Some(exceeded) = e.downcast_ref::<LimitExceeded>() {
+                tracing::warn!(archive = ?archive_path, "Stopped reading archive: {}", exceeded);
+            }
+        }
+        result
+    })
+    .await?
+}
+
 /// Check if a filename is an archive
 pub fn is_archive(filename: &str) -> bool {
     let lower = filename.to_lowercase();
@@ -17,27 +263,28 @@ fn is_audio_file(filename: &str) -> bool {
 }
 
 /// Check if an archive contains audio files
-pub async fn archive_contains_audio(archive_path: &Path) -> Result<bool> {
-    let data = tokio::fs::read(archive_path).await?;
-    let filename = archive_path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-
-    if filename.ends_with(".zip") {
-        check_zip_for_audio(&data)
-    } else if filename.ends_with(".tar") || filename.ends_with(".tar.gz") ||
-              filename.ends_with(".tgz") || filename.ends_with(".tar.bz2") ||
-              filename.ends_with(".tbz2") {
-        check_tar_for_audio(&data, filename)
-    } else {
-        Ok(false)
-    }
+pub async fn archive_contains_audio(
+    archive_path: &Path,
+    limits: ArchiveLimits,
+    workers: &Workers,
+) -> Result<bool> {
+    metered(archive_path, limits, workers, |archive_path, filename, meter| {
+        if filename.ends_with(".zip") {
+            check_zip_for_audio(open(archive_path)?)
+        } else if filename.ends_with(".tar") || filename.ends_with(".tar.gz") ||
+                  filename.ends_with(".tgz") || filename.ends_with(".tar.bz2") ||
+                  filename.ends_with(".tbz2") {
+            check_tar_for_audio(tar_reader(open(archive_path)?, filename, meter))
+        } else {
+            Ok(false)
+        }
+    })
+    .await
 }
 
 /// Check if a ZIP archive contains audio files
-fn check_zip_for_audio(data: &[u8]) -> Result<bool> {
-    let cursor = Cursor::new(data);
-    let mut archive = zip::ZipArchive::new(cursor)?;
+fn check_zip_for_audio(reader: BufReader<File>) -> Result<bool> {
+    let mut archive = zip::ZipArchive::new(reader)?;
 
     for i in 0..archive.len() {
         let file = archive.by_index(i)?;
@@ -50,16 +297,7 @@ fn check_zip_for_audio(data: &[u8]) -> Result<bool> {
 }
 
 /// Check if a TAR archive contains audio files
-fn check_tar_for_audio(data: &[u8], filename: &str) -> Result<bool> {
-    let cursor = Cursor::new(data);
-    let reader: Box<dyn Read> = if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
-        Box::new(flate2::read::GzDecoder::new(cursor))
-    } else if filename.ends_with(".tar.bz2") || filename.ends_with(".tbz2") {
-        Box::new(flate2::read::GzDecoder::new(cursor)) // Note: for bz2 we'd need bzip2 crate
-    } else {
-        Box::new(cursor)
-    };
-
+fn check_tar_for_audio(reader: impl Read) -> Result<bool> {
     let mut archive = tar::Archive::new(reader);
 
     for entry in archive.entries()? {
@@ -78,28 +316,32 @@ fn check_tar_for_audio(data: &[u8], filename: &str) -> Result<bool> {
     Ok(false)
 }
 
-/// List contents of an archive
-pub async fn list_archive_contents(archive_path: &Path) -> Result<Vec<DirectoryEntry>> {
-    let data = tokio::fs::read(archive_path).await?;
-    let filename = archive_path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-
-    if filename.ends_with(".zip") {
-        list_zip_contents(&data)
-    } else if filename.ends_with(".tar") || filename.ends_with(".tar.gz") ||
-              filename.ends_with(".tgz") || filename.ends_with(".tar.bz2") ||
-              filename.ends_with(".tbz2") {
-        list_tar_contents(&data, filename)
-    } else {
-        Ok(Vec::new())
-    }
+/// List contents of an archive, sorted by name
+pub async fn list_archive_contents(
+    archive_path: &Path,
+    names: NameOrder,
+    limits: ArchiveLimits,
+    workers: &Workers,
+) -> Result<Vec<DirectoryEntry>> {
+    metered(archive_path, limits, workers, move |archive_path, filename, meter| {
+        let mut entries = if filename.ends_with(".zip") {
+            list_zip_contents(open(archive_path)?)?
+        } else if filename.ends_with(".tar") || filename.ends_with(".tar.gz") ||
+                  filename.ends_with(".tgz") || filename.ends_with(".tar.bz2") ||
+                  filename.ends_with(".tbz2") {
+            list_tar_contents(tar_reader(open(archive_path)?, filename, meter))?
+        } else {
+            Vec::new()
+        };
+        entries.sort_by(|a, b| names.cmp(&a.name, &b.name));
+        Ok(entries)
+    })
+    .await
 }
 
 /// List contents of a ZIP archive
-fn list_zip_contents(data: &[u8]) -> Result<Vec<DirectoryEntry>> {
-    let cursor = Cursor::new(data);
-    let mut archive = zip::ZipArchive::new(cursor)?;
+fn list_zip_contents(reader: BufReader<File>) -> Result<Vec<DirectoryEntry>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
     let mut entries = Vec::new();
 
     for i in 0..archive.len() {
@@ -128,26 +370,22 @@ fn list_zip_contents(data: &[u8]) -> Result<Vec<DirectoryEntry>> {
             path: name.to_string(),
             entry_type: EntryType::Audio,
             size: file.size(),
+            modified: None,
+            tags: Vec::new(),
+            favorite: false,
+            rating: None,
+            audio: None,
+            video: None,
+            title: None,
+            cover: None,
         });
     }
 
-    // Sort by name
-    entries.sort_by(|a, b| a.name.cmp(&b.name));
-
     Ok(entries)
 }
 
 /// List contents of a TAR archive
-fn list_tar_contents(data: &[u8], filename: &str) -> Result<Vec<DirectoryEntry>> {
-    let cursor = Cursor::new(data);
-    let reader: Box<dyn Read> = if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
-        Box::new(flate2::read::GzDecoder::new(cursor))
-    } else if filename.ends_with(".tar.bz2") || filename.ends_with(".tbz2") {
-        Box::new(flate2::read::GzDecoder::new(cursor))
-    } else {
-        Box::new(cursor)
-    };
-
+fn list_tar_contents(reader: impl Read) -> Result<Vec<DirectoryEntry>> {
     let mut archive = tar::Archive::new(reader);
     let mut entries = Vec::new();
 
@@ -181,13 +419,18 @@ fn list_tar_contents(data: &[u8], filename: &str) -> Result<Vec<DirectoryEntry>>
                 path: path_str.to_string(),
                 entry_type: EntryType::Audio,
                 size: entry.header().size()?,
+                modified: None,
+                tags: Vec::new(),
+                favorite: false,
+                rating: None,
+                audio: None,
+                video: None,
+                title: None,
+                cover: None,
             });
         }
     }
 
-    // Sort by name
-    entries.sort_by(|a, b| a.name.cmp(&b.name));
-
     Ok(entries)
 }
 
@@ -195,34 +438,112 @@ fn list_tar_contents(data: &[u8], filename: &str) -> Result<Vec<DirectoryEntry>>
 pub async fn extract_file_from_archive(
     archive_path: &Path,
     file_path: &str,
-) -> Result<Vec<u8>> {
-    let data = tokio::fs::read(archive_path).await?;
-    let filename = archive_path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-
-    if filename.ends_with(".zip") {
-        extract_from_zip(&data, file_path)
-    } else if filename.ends_with(".tar") || filename.ends_with(".tar.gz") ||
-              filename.ends_with(".tgz") || filename.ends_with(".tar.bz2") ||
-              filename.ends_with(".tbz2") {
-        extract_from_tar(&data, filename, file_path)
-    } else {
-        anyhow::bail!("Unsupported archive format")
+    limits: ArchiveLimits,
+    workers: &Workers,
+) -> Result<Extracted> {
+    let file_path = file_path.to_string();
+    metered(archive_path, limits, workers, move |archive_path, filename, meter| {
+        if filename.ends_with(".zip") {
+            extract_from_zip(open(archive_path)?, &file_path, meter)
+        } else if filename.ends_with(".tar") || filename.ends_with(".tar.gz") ||
+                  filename.ends_with(".tgz") || filename.ends_with(".tar.bz2") ||
+                  filename.ends_with(".tbz2") {
+            extract_from_tar(tar_reader(open(archive_path)?, filename, meter), &file_path, meter)
+        } else {
+            anyhow::bail!("Unsupported archive format")
+        }
+    })
+    .await
+}
+
+/// Extract the best-ranked file of an archive
+/// `rank` scores each file path, lower is better; files it returns `None` for are skipped.
+pub async fn extract_best_file_from_archive(
+    archive_path: &Path,
+    limits: ArchiveLimits,
+    workers: &Workers,
+    rank: impl Fn(&str) -> Option<usize> + Send + 'static,
+) -> Result<Option<Extracted>> {
+    metered(archive_path, limits, workers, move |archive_path, filename, meter| {
+        if filename.ends_with(".zip") {
+            extract_best_from_zip(open(archive_path)?, meter, rank)
+        } else if filename.ends_with(".tar") || filename.ends_with(".tar.gz") ||
+                  filename.ends_with(".tgz") || filename.ends_with(".tar.bz2") ||
+                  filename.ends_with(".tbz2") {
+            extract_best_from_tar(tar_reader(open(archive_path)?, filename, meter), meter, rank)
+        } else {
+            anyhow::bail!("Unsupported archive format")
+        }
+    })
+    .await
+}
+
+/// Extract the best-ranked file of a ZIP archive
+fn extract_best_from_zip(
+    reader: BufReader<File>,
+    meter: &Meter,
+    rank: impl Fn(&str) -> Option<usize>,
+) -> Result<Option<Extracted>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+
+    let mut best: Option<(usize, usize)> = None;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        if let Some(score) = rank(file.name()) {
+            if best.is_none_or(|(best_score, _)| score < best_score) {
+                best = Some((score, i));
+            }
+        }
+    }
+
+    let Some((_, index)) = best else {
+        return Ok(None);
+    };
+    let file = archive.by_index(index)?;
+    let size = file.size();
+    meter.zip_entry(size, file.compressed_size())?;
+    Ok(Some(read_entry(meter.bound(file), size, meter)?))
+}
+
+/// Extract the best-ranked file of a TAR archive
+/// Entries can only be read in order, so each better match is read as it is found.
+fn extract_best_from_tar(
+    reader: impl Read,
+    meter: &Meter,
+    rank: impl Fn(&str) -> Option<usize>,
+) -> Result<Option<Extracted>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut best: Option<(usize, Extracted)> = None;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_dir() {
+            continue;
+        }
+        let Some(score) = entry.path().ok().and_then(|path| path.to_str().and_then(&rank)) else {
+            continue;
+        };
+        if best.as_ref().is_none_or(|(best_score, _)| score < *best_score) {
+            let size = entry.header().size()?;
+            best = Some((score, read_entry(entry, size, meter)?));
+        }
     }
+
+    Ok(best.map(|(_, contents)| contents))
 }
 
 /// Extract a file from a ZIP archive
-fn extract_from_zip(data: &[u8], file_path: &str) -> Result<Vec<u8>> {
-    let cursor = Cursor::new(data);
-    let mut archive = zip::ZipArchive::new(cursor)?;
+fn extract_from_zip(reader: BufReader<File>, file_path: &str, meter: &Meter) -> Result<Extracted> {
+    let mut archive = zip::ZipArchive::new(reader)?;
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
+        let file = archive.by_index(i)?;
         if file.name() == file_path {
-            let mut contents = Vec::new();
-            file.read_to_end(&mut contents)?;
-            return Ok(contents);
+            let size = file.size();
+            meter.zip_entry(size, file.compressed_size())?;
+            return read_entry(meter.bound(file), size, meter);
         }
     }
 
@@ -230,28 +551,84 @@ fn extract_from_zip(data: &[u8], file_path: &str) -> Result<Vec<u8>> {
 }
 
 /// Extract a file from a TAR archive
-fn extract_from_tar(data: &[u8], filename: &str, file_path: &str) -> Result<Vec<u8>> {
-    let cursor = Cursor::new(data);
-    let reader: Box<dyn Read> = if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
-        Box::new(flate2::read::GzDecoder::new(cursor))
-    } else if filename.ends_with(".tar.bz2") || filename.ends_with(".tbz2") {
-        Box::new(flate2::read::GzDecoder::new(cursor))
-    } else {
-        Box::new(cursor)
-    };
-
+fn extract_from_tar(reader: impl Read, file_path: &str, meter: &Meter) -> Result<Extracted> {
     let mut archive = tar::Archive::new(reader);
 
     for entry in archive.entries()? {
-        let mut entry = entry?;
-        if let Ok(path) = entry.path() {
-            if path.to_str() == Some(file_path) {
-                let mut contents = Vec::new();
-                entry.read_to_end(&mut contents)?;
-                return Ok(contents);
-            }
+        let entry = entry?;
+        if entry.path().ok().and_then(|path| path.to_str().map(|path| path == file_path)) == Some(true) {
+            let size = entry.header().size()?;
+            return read_entry(entry, size, meter);
         }
     }
 
     anyhow::bail!("File not found in archive")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn limits(max_ratio: u64, max_output: u64) -> ArchiveLimits {
+        ArchiveLimits { memory: MB, max_entry: 64 * MB, max_ratio, max_output }
+    }
+
+    fn exceeded<T>(result: Result<T>) -> Option<LimitExceeded> {
+        result.err()?.downcast::<LimitExceeded>().ok()
+    }
+
+    #[test]
+    fn output_within_the_slack_ignores_the_ratio() {
+        let meter = Meter::new(limits(2, 100 * MB));
+        assert!(meter.produce(RATIO_SLACK).is_ok());
+        assert!(meter.produce(1).is_err());
+        assert!(matches!(exceeded(meter.finish(Ok(()))), Some(LimitExceeded::Ratio(2))));
+    }
+
+    #[test]
+    fn output_may_grow_with_the_compressed_input() {
+        let meter = Meter::new(limits(10, 100 * MB));
+        meter.compressed.set(MB);
+        assert!(meter.produce(10 * MB).is_ok());
+        assert!(meter.produce(1).is_err());
+        assert!(matches!(meter.exceeded.get(), Some(LimitExceeded::Ratio(10))));
+    }
+
+    #[test]
+    fn total_output_is_capped() {
+        let meter = Meter::new(limits(1000, 3 * MB));
+        meter.compressed.set(MB);
+        assert!(meter.produce(2 * MB).is_ok());
+        assert!(meter.produce(MB).is_ok());
+        assert!(meter.produce(1).is_err());
+        assert!(matches!(meter.exceeded.get(), Some(LimitExceeded::Output(_))));
+    }
+
+    #[test]
+    fn zip_entries_are_checked_by_declared_size() {
+        let meter = Meter::new(limits(100, 100 * MB));
+        assert!(meter.zip_entry(50 * MB, MB).is_ok());
+        assert_eq!(meter.compressed.get(), MB);
+        assert!(meter.zip_entry(200 * MB, MB).is_err());
+        assert!(matches!(meter.exceeded.get(), Some(LimitExceeded::Ratio(100))));
+    }
+
+    #[test]
+    fn gzip_bomb_stops_at_the_ratio() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&vec![0; 8 * MB as usize]).unwrap();
+        let bomb = encoder.finish().unwrap();
+
+        let meter = Meter::new(limits(100, 100 * MB));
+        let reader = Compressed { reader: bomb.as_slice(), meter: &meter };
+        let result = read_entry(meter.bound(flate2::read::GzDecoder::new(reader)), 0, &meter);
+        assert!(matches!(exceeded(meter.finish(result)), Some(LimitExceeded::Ratio(100))));
+
+        let meter = Meter::new(limits(10_000, 100 * MB));
+        let reader = Compressed { reader: bomb.as_slice(), meter: &meter };
+        let result = read_entry(meter.bound(flate2::read::GzDecoder::new(reader)), 0, &meter);
+        assert_eq!(meter.finish(result).unwrap().size(), 8 * MB);
+    }
+}
@@ -1,23 +1,212 @@
 use axum::{
     body::Body,
-    extract::Request,
-    http::{header, StatusCode},
+    extract::{ConnectInfo, Request},
+    http::{header, Method, StatusCode},
     middleware::Next,
     response::Response,
 };
 use base64::Engine;
-use subtle::ConstantTimeEq;
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::Instrument;
 
+use crate::acl;
+use crate::cast::CastSessions;
+use crate::feeds::UrlSigner;
+use crate::icons;
+use crate::jwt::JwtVerifier;
+use crate::oidc::{self, OidcClient};
+use crate::preview;
 use crate::rate_limiter::AuthRateLimiter;
+use crate::tokens::{hash_token, TokenEntry};
+use crate::users::{Credential, UserAccount};
 
-#[derive(Clone, Zeroize, ZeroizeOnDrop)]
-pub struct AuthConfig {
-    #[zeroize(skip)]
+/// How long a successfully verified username/password pair is remembered
+/// Basic auth resends credentials on every request, and hashing each one
+/// (dozens per gallery page) would be far too slow.
+const VERIFIED_CREDENTIALS_TTL: Duration = Duration::from_secs(300);
+
+/// Routes holding a user's own data, which viewers may modify as well as read, along with
+/// the routes below them
+const PERSONAL_ROUTES: &[&str] = &[
+    "/api/cast/sessions",
+    "/api/favorites",
+    "/api/history",
+    "/api/playlists",
+    "/api/queue",
+    "/api/rating",
+];
+
+/// What an authenticated user or token is allowed to do
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read-only access to browse and media routes
+    #[default]
+    #[serde(alias = "read")]
+    #[value(alias = "read")]
+    Viewer,
+    /// Viewer access plus uploading files and creating directories (with `--enable-uploads`)
+    Uploader,
+    /// Full access, including refresh and management endpoints
+    Admin,
+}
+
+impl Role {
+    /// Check whether this role permits a request with the given method and path
+    pub fn allows(&self, method: &Method, path: &str) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Viewer => {
+                matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+                    || PERSONAL_ROUTES.iter().any(|route| acl::is_within(path, route))
+                    // GraphQL queries are sent with POST, but the schema is read-only
+                    || (*method == Method::POST && path == "/graphql")
+            }
+            Role::Uploader => {
+                Role::Viewer.allows(method, path)
+                    || (matches!(*method, Method::PUT | Method::POST)
+                        && (path == "/api/upload" || path.starts_with("/api/upload/")))
+                    || (*method == Method::POST && path == "/api/mkdir")
+            }
+        }
+    }
+}
+
+/// The authenticated user, available to handlers as `Extension<Principal>`
+#[derive(Debug, Clone)]
+pub struct Principal {
     pub username: String,
-    pub password: String,
-    #[zeroize(skip)]
+    pub role: Role,
+}
+
+#[derive(Clone)]
+pub struct AuthConfig {
+    /// Known users keyed by username (credentials zeroize on drop)
+    pub users: Arc<HashMap<String, UserAccount>>,
+    /// API tokens keyed by the SHA-256 hex of the token
+    pub api_tokens: Arc<HashMap<String, TokenEntry>>,
+    /// Verifier for JWTs from an external identity provider, if configured
+    pub jwt: Option<Arc<JwtVerifier>>,
+    /// OpenID Connect browser sessions, if configured
+    pub oidc: Option<Arc<OidcClient>>,
+    /// Verifier for signed feed and media URLs, if configured
+    pub url_signer: Option<Arc<UrlSigner>>,
+    /// Cast sessions whose tokenized media URLs work without credentials, if casting is enabled
+    pub cast_sessions: Option<Arc<CastSessions>>,
     pub rate_limiter: AuthRateLimiter,
+    /// Normalized media directories browsable without credentials
+    pub public_dirs: Arc<Vec<String>>,
+    /// Digests of recently verified credentials, salted per process
+    verified: Arc<RwLock<HashMap<[u8; 32], Instant>>>,
+    verified_salt: [u8; 32],
+}
+
+impl AuthConfig {
+    pub fn new(
+        users: HashMap<String, UserAccount>,
+        api_tokens: HashMap<String, TokenEntry>,
+        jwt: Option<Arc<JwtVerifier>>,
+        oidc: Option<Arc<OidcClient>>,
+        url_signer: Option<Arc<UrlSigner>>,
+        rate_limiter: AuthRateLimiter,
+        public_dirs: Vec<String>,
+    ) -> Self {
+        Self {
+            users: Arc::new(users),
+            api_tokens: Arc::new(api_tokens),
+            jwt,
+            oidc,
+            url_signer,
+            cast_sessions: None,
+            rate_limiter,
+            public_dirs: Arc::new(public_dirs),
+            verified: Arc::new(RwLock::new(HashMap::new())),
+            verified_salt: rand::random(),
+        }
+    }
+
+    /// Accept the tokens of these cast sessions on media URLs
+    pub fn with_cast_sessions(mut self, cast_sessions: Option<Arc<CastSessions>>) -> Self {
+        self.cast_sessions = cast_sessions;
+        self
+    }
+
+    /// Verify a username/password pair against the user table, returning the user's role
+    pub async fn verify(&self, username: &str, password: &str) -> Option<Role> {
+        let digest = self.credentials_digest(username, password);
+        let account = self.users.get(username);
+
+        if let Some(verified_at) = self.verified.read().await.get(&digest) {
+            if verified_at.elapsed() < VERIFIED_CREDENTIALS_TTL {
+                return account.map(|a| a.role);
+            }
+        }
+
+        // Unknown users are checked against a dummy hash so the response time
+        // doesn't reveal which usernames exist
+        let credential = account.map(|a| a.credential.clone());
+        let known_user = credential.is_some();
+        let password = password.to_string();
+        let valid = tokio::task::spawn_blocking(move || {
+            let credential = credential.as_ref().unwrap_or_else(|| Credential::dummy());
+            credential.verify(&password)
+        })
+        .await
+        .unwrap_or(false);
+
+        if known_user && valid {
+            let mut verified = self.verified.write().await;
+            verified.retain(|_, at| at.elapsed() < VERIFIED_CREDENTIALS_TTL);
+            verified.insert(digest, Instant::now());
+            return account.map(|a| a.role);
+        }
+
+        None
+    }
+
+    /// Whether a request may be served anonymously because it reads from a public directory
+    /// Static and branding assets are included so public pages render.
+    fn is_public(&self, request: &Request) -> bool {
+        if self.public_dirs.is_empty() || !matches!(*request.method(), Method::GET | Method::HEAD) {
+            return false;
+        }
+
+        let path = request.uri().path();
+        if path.starts_with("/static/") || path.starts_with("/branding/") {
+            return true;
+        }
+
+        // oEmbed lookups are as public as the page or file they describe
+        let target = match path {
+            "/oembed" => request.uri().query().and_then(preview::oembed_target),
+            _ => None,
+        };
+        let Some(media_path) = acl::media_route_path(target.as_deref().unwrap_or(path)) else {
+            return false;
+        };
+        let Some(media_path) = acl::decode_media_path(media_path) else {
+            return false;
+        };
+
+        self.public_dirs.iter().any(|dir| acl::is_within(&media_path, dir))
+    }
+
+    fn credentials_digest(&self, username: &str, password: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.verified_salt);
+        hasher.update(username.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(password.as_bytes());
+        hasher.finalize().into()
+    }
 }
 
 /// Middleware for HTTP Basic Authentication
@@ -32,10 +221,40 @@ pub async fn basic_auth_middleware(
         .get("x-forwarded-for")
         .or_else(|| request.headers().get("x-real-ip"))
         .and_then(|h| h.to_str().ok())
-        .unwrap_or("unknown");
+        .map(str::to_string)
+        .or_else(|| {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_canonical().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    let client_ip = client_ip.as_str();
+
+    // The OIDC login flow itself must be reachable without credentials
+    if auth_config.oidc.is_some() && request.uri().path().starts_with("/auth/") {
+        return next.run(request).await;
+    }
+
+    // Browsers fetch the favicon on their own, without the credentials of the page
+    if icons::ICON_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    // Public directories are served anonymously, unless the client presents
+    // credentials (so signed-in users keep their identity for ACLs)
+    let has_credentials = request.headers().contains_key(header::AUTHORIZATION)
+        || auth_config
+            .oidc
+            .as_ref()
+            .is_some_and(|_| oidc::cookie_value(request.headers(), oidc::SESSION_COOKIE).is_some());
+    if !has_credentials && auth_config.is_public(&request) {
+        return next.run(request).await;
+    }
 
     // Check rate limit for this IP
     if auth_config.rate_limiter.is_rate_limited(client_ip).await {
+        metrics::counter!("doggygallery_rate_limited_total", "limiter" => "auth").increment(1);
         tracing::warn!(
             client_ip = %client_ip,
             "Authentication rate limited - too many failed attempts"
@@ -52,6 +271,86 @@ pub async fn basic_auth_middleware(
             .unwrap();
     }
 
+    // Browser session established through an OIDC login
+    if let Some(oidc) = &auth_config.oidc {
+        if let Some(session) = oidc.session_from_headers(request.headers()).await {
+            let principal = Principal {
+                username: session.principal.username,
+                role: session.principal.role,
+            };
+            return run_as(principal, client_ip, request, next).await;
+        }
+    }
+
+    // JWTs from an SSO gateway, either in a dedicated header or as a bearer token
+    if let Some(jwt) = &auth_config.jwt {
+        let token = match &jwt.header {
+            Some(name) => request.headers().get(name.as_str()).and_then(|h| h.to_str().ok()),
+            None => request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                // API tokens are opaque; JWTs always have three dot-separated parts
+                .filter(|t| t.split('.').count() == 3),
+        };
+
+        if let Some(token) = token {
+            match jwt.verify(token.trim()).await {
+                Ok(principal) => {
+                    auth_config.rate_limiter.clear(client_ip).await;
+
+                    tracing::debug!(
+                        client_ip = %client_ip,
+                        username = %principal.username,
+                        roles = ?principal.roles,
+                        "JWT authentication successful"
+                    );
+
+                    let principal = Principal {
+                        username: principal.username,
+                        role: principal.role,
+                    };
+                    return run_as(principal, client_ip, request, next).await;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        client_ip = %client_ip,
+                        error = %e,
+                        "Authentication failed - invalid JWT"
+                    );
+                }
+            }
+        }
+    }
+
+    // Podcast apps can't log in, so feeds and the files they link to accept signed URLs
+    if let Some(username) = auth_config.url_signer.as_ref().and_then(|signer| signer.verify(&request)) {
+        // Users outside the users file (SSO, OIDC) act as viewers
+        let role = auth_config
+            .users
+            .get(&username)
+            .map_or(Role::Viewer, |account| account.role);
+        tracing::debug!(
+            client_ip = %client_ip,
+            username = %username,
+            "Signed URL authentication successful"
+        );
+        return run_as(Principal { username, role }, client_ip, request, next).await;
+    }
+
+    // Cast receivers fetch media themselves, so cast URLs carry a session token instead
+    if let Some(cast_sessions) = &auth_config.cast_sessions {
+        if let Some(principal) = cast_sessions.verify(request.method(), request.uri()).await {
+            tracing::debug!(
+                client_ip = %client_ip,
+                username = %principal.username,
+                "Cast session authentication successful"
+            );
+            return run_as(principal, client_ip, request, next).await;
+        }
+    }
+
     // Extract Authorization header
     let auth_header = request
         .headers()
@@ -59,17 +358,35 @@ pub async fn basic_auth_middleware(
         .and_then(|h| h.to_str().ok());
 
     if let Some(auth_value) = auth_header {
-        if let Some(credentials) = auth_value.strip_prefix("Basic ") {
+        if let Some(token) = auth_value.strip_prefix("Bearer ") {
+            if let Some(entry) = auth_config.api_tokens.get(&hash_token(token.trim())) {
+                auth_config.rate_limiter.clear(client_ip).await;
+
+                tracing::debug!(
+                    client_ip = %client_ip,
+                    token = %entry.name,
+                    "API token authentication successful"
+                );
+
+                let principal = Principal {
+                    username: format!("token:{}", entry.name),
+                    role: entry.role,
+                };
+                return run_as(principal, client_ip, request, next).await;
+            }
+
+            tracing::warn!(
+                client_ip = %client_ip,
+                "Authentication failed - unknown API token"
+            );
+        } else if let Some(credentials) = auth_value.strip_prefix("Basic ") {
             // Decode base64 credentials
             if let Ok(decoded) = base64::prelude::BASE64_STANDARD.decode(credentials) {
                 if let Ok(credentials_str) = String::from_utf8(decoded) {
                     // Parse username:password
                     if let Some((username, password)) = credentials_str.split_once(':') {
-                        // Use constant-time comparison to prevent timing attacks
-                        let username_match = username.as_bytes().ct_eq(auth_config.username.as_bytes());
-                        let password_match = password.as_bytes().ct_eq(auth_config.password.as_bytes());
-
-                        if bool::from(username_match & password_match) {
+                        // Hash verification and plaintext comparison are both constant-time
+                        if let Some(role) = auth_config.verify(username, password).await {
                             // Clear rate limit on successful authentication
                             auth_config.rate_limiter.clear(client_ip).await;
 
@@ -78,7 +395,12 @@ pub async fn basic_auth_middleware(
                                 username = %username,
                                 "Authentication successful"
                             );
-                            return next.run(request).await;
+
+                            let principal = Principal {
+                                username: username.to_string(),
+                                role,
+                            };
+                            return run_as(principal, client_ip, request, next).await;
                         } else {
                             // Record failed attempt
                             auth_config.rate_limiter.record_failure(client_ip).await;
@@ -97,12 +419,32 @@ pub async fn basic_auth_middleware(
 
     // Authentication failed - record and return 401 with WWW-Authenticate header
     auth_config.rate_limiter.record_failure(client_ip).await;
+    metrics::counter!("doggygallery_auth_failures_total").increment(1);
 
     tracing::warn!(
         client_ip = %client_ip,
         "Authentication failed - no valid credentials provided"
     );
 
+    // Send browsers to the OIDC provider; API routes fall back to Basic auth
+    let path = request.uri().path();
+    if auth_config.oidc.is_some() && !path.starts_with("/api") && request.method() == Method::GET {
+        let return_to = request
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+        let location = format!(
+            "/auth/login?return_to={}",
+            utf8_percent_encode(return_to, NON_ALPHANUMERIC)
+        );
+        return Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(header::LOCATION, location)
+            .body(Body::empty())
+            .unwrap();
+    }
+
     Response::builder()
         .status(StatusCode::UNAUTHORIZED)
         .header(
@@ -112,3 +454,84 @@ pub async fn basic_auth_middleware(
         .body(Body::from("Authentication required"))
         .unwrap()
 }
+
+/// Continue handling an authenticated request, enforcing the principal's role
+async fn run_as(principal: Principal, client_ip: &str, mut request: Request, next: Next) -> Response {
+    let mut response = if principal.role.allows(request.method(), request.uri().path()) {
+        // Attach the user to every log line produced while handling the request
+        let span = tracing::info_span!("auth", user = %principal.username);
+        request.extensions_mut().insert(principal.clone());
+        next.run(request).instrument(span).await
+    } else {
+        tracing::warn!(
+            client_ip = %client_ip,
+            username = %principal.username,
+            method = %request.method(),
+            "Role does not permit this request"
+        );
+        Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("Your role does not permit this request"))
+            .unwrap()
+    };
+
+    // Expose the user to outer layers such as the access log
+    response.extensions_mut().insert(principal);
+    response
+}
+
+/// Middleware for admin-only routes (apply with `route_layer` after authentication)
+pub async fn require_admin(request: Request, next: Next) -> Response {
+    match request.extensions().get::<Principal>() {
+        Some(principal) if principal.role == Role::Admin => next.run(request).await,
+        _ => Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("Admin role required"))
+            .unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+
+    use crate::handlers::tests::{media_router, status};
+
+    /// The media router behind basic authentication, with the `public` directory browsable
+    /// without credentials
+    fn setup() -> (tempfile::TempDir, Router) {
+        let (dir, router) = media_router();
+        let auth_config = AuthConfig::new(
+            HashMap::new(),
+            HashMap::new(),
+            None,
+            None,
+            None,
+            AuthRateLimiter::new(10, Duration::from_secs(60)),
+            vec!["public".to_string()],
+        );
+        let router = router.layer(axum::middleware::from_fn_with_state(auth_config, basic_auth_middleware));
+        (dir, router)
+    }
+
+    #[test]
+    fn viewers_write_only_to_personal_routes() {
+        assert!(Role::Viewer.allows(&Method::PUT, "/api/history/settings"));
+        assert!(Role::Viewer.allows(&Method::DELETE, "/api/history"));
+        assert!(Role::Viewer.allows(&Method::PUT, "/api/favorites/photos/a.jpg"));
+        assert!(!Role::Viewer.allows(&Method::DELETE, "/api/historyX"));
+        assert!(!Role::Viewer.allows(&Method::POST, "/api/queues"));
+        assert!(!Role::Viewer.allows(&Method::DELETE, "/api/files/a.jpg"));
+    }
+
+    #[tokio::test]
+    async fn encoded_parent_directory_does_not_escape_public_dir() {
+        let (_dir, router) = setup();
+        assert_eq!(status(&router, "/media/public/open.png").await, StatusCode::OK);
+        assert_eq!(status(&router, "/media/private/secret.png").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&router, "/media/public/%2e%2e/private/secret.png").await, StatusCode::UNAUTHORIZED);
+        // Decoded once, `%2e%2e` is a name inside the public directory, which doesn't exist
+        assert_eq!(status(&router, "/media/public/%252e%252e/private/secret.png").await, StatusCode::NOT_FOUND);
+    }
+}
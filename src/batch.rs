@@ -0,0 +1,245 @@
+use axum::{extract::State, response::Json, Extension};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::files;
+use crate::handlers::{self, AppError, AppState};
+use crate::tags;
+
+/// Most operations accepted in one batch
+const MAX_OPERATIONS: usize = 1000;
+
+/// One operation of a batch
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperation {
+    /// Move or rename a file or directory (needs `--enable-write-ops`)
+    Move {
+        from: String,
+        to: String,
+        #[serde(default)]
+        overwrite: bool,
+    },
+    /// Delete a file or directory (needs `--enable-write-ops`)
+    Delete {
+        path: String,
+        #[serde(default)]
+        recursive: bool,
+        /// Skip the trash
+        #[serde(default)]
+        permanent: bool,
+    },
+    /// Add and remove tags on a file or directory (needs `--index-db`)
+    Tag {
+        path: String,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+}
+
+/// Operations to run in order
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    /// 1 to 1000 operations
+    pub operations: Vec<BatchOperation>,
+}
+
+/// Outcome of one operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    /// The operation was applied
+    Ok,
+    /// The operation was refused or went wrong
+    Failed,
+    /// The operation was not attempted because another one failed
+    Skipped,
+}
+
+/// Result of one operation, in request order
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResult {
+    /// Position of the operation in the request
+    pub index: usize,
+    pub status: BatchStatus,
+    /// Why the operation failed
+    pub error: Option<String>,
+}
+
+/// Per-operation report of a batch
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResponse {
+    /// Number of operations applied
+    pub applied: usize,
+    pub results: Vec<BatchResult>,
+}
+
+/// An operation that passed the checks that don't depend on the disk
+enum Checked {
+    Move { from: String, to: String, overwrite: bool },
+    Delete { path: String, recursive: bool, permanent: bool },
+    Tag { path: String, add: Vec<String>, remove: Vec<String> },
+}
+
+fn check(state: &AppState, principal: &Principal, operation: BatchOperation) -> Result<Checked, AppError> {
+    match operation {
+        BatchOperation::Move { from, to, overwrite } => {
+            if !state.write_ops {
+                return Err(AppError::InvalidInput("Write operations are disabled"));
+            }
+            let (from, to) = files::check_move(state, principal, &from, &to)?;
+            Ok(Checked::Move { from, to, overwrite })
+        }
+        BatchOperation::Delete { path, recursive, permanent } => {
+            if !state.write_ops {
+                return Err(AppError::InvalidInput("Write operations are disabled"));
+            }
+            let path = files::check_delete(state, principal, &path)?;
+            Ok(Checked::Delete { path, recursive, permanent })
+        }
+        BatchOperation::Tag { path, add, remove } => {
+            if state.index.is_none() {
+                return Err(AppError::InvalidInput("Tags need an index database"));
+            }
+            let path = handlers::media_path(&path)?;
+            if path.is_empty() {
+                return Err(AppError::InvalidPath);
+            }
+            if !state.acl.allows(Some(principal), &path) {
+                return Err(AppError::Forbidden);
+            }
+            let (add, remove) = (tags::normalize_tags(&add)?, tags::normalize_tags(&remove)?);
+            if add.is_empty() && remove.is_empty() {
+                return Err(AppError::InvalidInput("Between 1 and 50 tags are required"));
+            }
+            Ok(Checked::Tag { path, add, remove })
+        }
+    }
+}
+
+fn failed(error: AppError) -> (BatchStatus, Option<String>) {
+    (BatchStatus::Failed, Some(error.parts().1.to_string()))
+}
+
+/// Run several move, delete and tag operations in one request
+/// Every operation is checked first; if any is invalid, none are run. Moves and deletes
+/// then run in order and stop at the first failure, since filesystem changes can't be
+/// rolled back. Tag changes are applied last, all together in one database transaction,
+/// so they can refer to paths as they are after the moves. Admins only.
+#[utoipa::path(
+    post,
+    path = "/api/batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Per-operation results", body = BatchResponse),
+        (status = 400, description = "No operations, or more than 1000"),
+        (status = 403, description = "Not an admin")
+    ),
+    tag = "media"
+)]
+pub async fn batch_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<BatchRequest>,
+) -> Result<Json<BatchResponse>, AppError> {
+    let Some(Extension(principal)) = principal else {
+        return Err(AppError::Forbidden);
+    };
+    if request.operations.is_empty() || request.operations.len() > MAX_OPERATIONS {
+        return Err(AppError::InvalidInput("Between 1 and 1000 operations are required"));
+    }
+
+    let count = request.operations.len();
+    let checked: Vec<Result<Checked, AppError>> = request
+        .operations
+        .into_iter()
+        .map(|operation| check(&state, &principal, operation))
+        .collect();
+    let mut outcomes = vec![(BatchStatus::Skipped, None); count];
+    if checked.iter().any(Result::is_err) {
+        for (outcome, result) in outcomes.iter_mut().zip(checked) {
+            if let Err(e) = result {
+                *outcome = failed(e);
+            }
+        }
+        return Ok(report(outcomes));
+    }
+    let checked: Vec<Checked> = checked.into_iter().flatten().collect();
+
+    // Filesystem changes, in order, until one fails
+    let mut aborted = false;
+    for (i, operation) in checked.iter().enumerate() {
+        let result = match operation {
+            Checked::Move { from, to, overwrite } => files::move_entry(&state, &principal, from, to, *overwrite).await,
+            Checked::Delete { path, recursive, permanent } => {
+                files::delete_entry(&state, &principal, path, *recursive, *permanent).await
+            }
+            Checked::Tag { .. } => continue,
+        };
+        match result {
+            Ok(()) => outcomes[i] = (BatchStatus::Ok, None),
+            Err(e) => {
+                outcomes[i] = failed(e);
+                aborted = true;
+                break;
+            }
+        }
+    }
+
+    // Tag changes, all or nothing
+    let tag_changes: Vec<(usize, &String, &Vec<String>, &Vec<String>)> = checked
+        .iter()
+        .enumerate()
+        .filter_map(|(i, operation)| match operation {
+            Checked::Tag { path, add, remove } => Some((i, path, add, remove)),
+            _ => None,
+        })
+        .collect();
+    if !aborted && !tag_changes.is_empty() {
+        let missing = tag_changes
+            .iter()
+            .find_map(|(i, path, _, _)| handlers::validate_media_path(&state.media_dir, path).err().map(|e| (*i, e)));
+        if let Some((i, e)) = missing {
+            outcomes[i] = failed(e);
+        } else if let Some(index) = &state.index {
+            let changes = tag_changes
+                .iter()
+                .map(|(_, path, add, remove)| (path.to_string(), add.to_vec(), remove.to_vec()))
+                .collect();
+            let result = index.change_tags(changes, principal.username.clone()).await;
+            for (i, ..) in &tag_changes {
+                outcomes[*i] = match &result {
+                    Ok(()) => (BatchStatus::Ok, None),
+                    Err(_) => failed(AppError::InternalError),
+                };
+            }
+            if let Err(e) = result {
+                tracing::error!("Failed to apply batch tag changes: {:?}", e);
+            }
+        }
+    }
+
+    let response = report(outcomes);
+    tracing::info!(
+        username = %principal.username,
+        operations = count,
+        applied = response.applied,
+        "Batch finished"
+    );
+    Ok(response)
+}
+
+fn report(outcomes: Vec<(BatchStatus, Option<String>)>) -> Json<BatchResponse> {
+    let results: Vec<BatchResult> = outcomes
+        .into_iter()
+        .enumerate()
+        .map(|(index, (status, error))| BatchResult { index, status, error })
+        .collect();
+    Json(BatchResponse {
+        applied: results.iter().filter(|result| result.status == BatchStatus::Ok).count(),
+        results,
+    })
+}
@@ -0,0 +1,117 @@
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, Response, StatusCode},
+};
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::handlers::{self, AppError, AppState};
+
+/// URL prefix of the files in --branding-dir
+const BRANDING_PREFIX: &str = "/branding/";
+
+/// How the web interface presents itself, from the branding options
+#[derive(Debug, Clone)]
+pub struct Branding {
+    /// Name shown in page headers and titles
+    pub title: String,
+    /// URL of the logo shown instead of the emoji prefix
+    pub logo: Option<String>,
+    /// The logo file, which the favicon and app icons are made from
+    pub logo_file: Option<PathBuf>,
+    /// CSS color replacing the default purple of links and buttons
+    pub accent_color: Option<String>,
+    /// URLs of extra stylesheets, loaded after the built-in ones
+    pub stylesheets: Vec<String>,
+    /// URLs of extra scripts, loaded at the end of every page
+    pub scripts: Vec<String>,
+    /// Canonical --branding-dir, served at /branding/
+    dir: Option<PathBuf>,
+}
+
+impl Branding {
+    /// Check the branding options and resolve their files in --branding-dir
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let title = config.site_title.trim();
+        if title.is_empty() {
+            anyhow::bail!("--site-title cannot be empty");
+        }
+        if let Some(color) = &config.accent_color {
+            if !is_hex_color(color) {
+                anyhow::bail!("Invalid --accent-color {:?}: expected a hex color such as #e91e63", color);
+            }
+        }
+
+        let dir = match &config.branding_dir {
+            Some(dir) => Some(
+                dunce::canonicalize(dir)
+                    .with_context(|| format!("Branding directory does not exist: {:?}", dir))?,
+            ),
+            None => None,
+        };
+        let file_path = |option: &str, file: &String| -> anyhow::Result<PathBuf> {
+            let Some(dir) = &dir else {
+                anyhow::bail!("{} requires --branding-dir", option);
+            };
+            dunce::canonicalize(dir.join(file))
+                .ok()
+                .filter(|path| path.starts_with(dir) && path.is_file())
+                .with_context(|| format!("{} {:?} is not a file in the branding directory", option, file))
+        };
+        let file_url = |option: &str, file: &String| -> anyhow::Result<String> {
+            let path = file_path(option, file)?;
+            let dir = dir.as_ref().expect("file_path checked the branding directory");
+            let relative = path.strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+            Ok(format!("{}{}", BRANDING_PREFIX, handlers::encode_url_path(&relative)))
+        };
+
+        Ok(Branding {
+            title: title.to_string(),
+            logo: config.logo.as_ref().map(|logo| file_url("--logo", logo)).transpose()?,
+            logo_file: config.logo.as_ref().map(|logo| file_path("--logo", logo)).transpose()?,
+            accent_color: config.accent_color.clone(),
+            stylesheets: config
+                .custom_css
+                .iter()
+                .map(|file| file_url("--custom-css", file))
+                .collect::<anyhow::Result<_>>()?,
+            scripts: config
+                .custom_js
+                .iter()
+                .map(|file| file_url("--custom-js", file))
+                .collect::<anyhow::Result<_>>()?,
+            dir,
+        })
+    }
+}
+
+/// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`, which is safe to put in a style element
+fn is_hex_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+/// Handler for /branding/*path - logos, stylesheets and scripts from --branding-dir
+pub async fn serve_branding(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+) -> Result<Response<Body>, AppError> {
+    let dir = state.branding.dir.as_ref().ok_or(AppError::NotFound)?;
+    let file = handlers::validate_media_path(dir, &path)?;
+    if !file.is_file() {
+        return Err(AppError::NotFound);
+    }
+    let contents = tokio::fs::read(&file).await.map_err(|_| AppError::NotFound)?;
+    let mime = mime_guess::from_path(&file).first_or_octet_stream();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, mime.as_ref())
+        // Unlike the embedded assets these can change without a new release
+        .header(header::CACHE_CONTROL, "public, max-age=3600")
+        .body(Body::from(contents))
+        .map_err(|_| AppError::InternalError)
+}
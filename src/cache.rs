@@ -0,0 +1,1025 @@
+use serde::{Deserialize, Serialize};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::fs;
+use tokio::sync::RwLock;
+
+use crate::events::{EventBus, EventData, RefreshEvent};
+use crate::handlers::{self, AppError, FilterQuery, FilterResult};
+use crate::index::MediaIndex;
+use crate::netfs;
+use crate::search::SearchIndex;
+use crate::sidecar::DirectoryMeta;
+use crate::stats::CacheCounters;
+use crate::store::MediaStore;
+
+/// Scan errors kept for the status API
+const MAX_RECENT_ERRORS: usize = 50;
+
+/// Format of the cache file; files written in another format are ignored
+const CACHE_FILE_VERSION: u32 = 1;
+
+/// Media files directly inside one directory, as of its last scan
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct CachedDir {
+    /// Directory mtime at scan time; `None` when loaded from the index, forcing a rescan
+    mtime: Option<SystemTime>,
+    files: Arc<Vec<FilterResult>>,
+    /// Relative paths of the subdirectories
+    subdirs: Vec<String>,
+}
+
+/// Cached media files for fast random selection, grouped by directory
+#[derive(Clone, Debug)]
+pub struct MediaCache {
+    /// Keyed by path relative to the media directory ("" is the root)
+    dirs: BTreeMap<String, CachedDir>,
+    len: usize,
+    pub last_updated: Instant,
+    /// Wall-clock time the cache was last refreshed
+    pub refreshed_at: chrono::DateTime<chrono::Utc>,
+    /// Time taken by the last refresh
+    pub refresh_duration: Duration,
+    /// Space on the volume holding the media directory, if it could be read
+    pub volume: Option<VolumeUsage>,
+    /// Media stored under each top-level directory, largest first
+    pub directories: Vec<DirectoryUsage>,
+}
+
+impl Default for MediaCache {
+    fn default() -> Self {
+        Self {
+            dirs: BTreeMap::new(),
+            len: 0,
+            last_updated: Instant::now(),
+            refreshed_at: chrono::Utc::now(),
+            refresh_duration: Duration::ZERO,
+            volume: None,
+            directories: Vec::new(),
+        }
+    }
+}
+
+impl MediaCache {
+    /// All cached media files
+    pub fn items(&self) -> impl Iterator<Item = &FilterResult> {
+        self.dirs.values().flat_map(|dir| dir.files.iter())
+    }
+
+    /// Cached files in a directory and, if `recursive`, all of its subdirectories
+    pub fn items_in<'a>(&'a self, dir: &'a str, recursive: bool) -> impl Iterator<Item = &'a FilterResult> {
+        self.dirs
+            .iter()
+            .filter(move |(path, _)| {
+                path.as_str() == dir
+                    || (recursive
+                        && (dir.is_empty()
+                            || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))))
+            })
+            .flat_map(|(_, cached)| cached.files.iter())
+    }
+
+    /// A preview image for a directory: a `cover.*` image directly inside it, else the first
+    /// image by name directly inside it, else the first image of its subdirectories.
+    /// `allowed` filters the candidates by path.
+    pub fn cover_image(&self, dir: &str, allowed: impl Fn(&str) -> bool) -> Option<&str> {
+        fn first_image<'a>(files: &'a [FilterResult], allowed: &dyn Fn(&str) -> bool) -> Option<&'a str> {
+            files
+                .iter()
+                .filter(|item| item.file_type == "image" && allowed(&item.path))
+                .min_by_key(|item| {
+                    let stem = item.name.rsplit_once('.').map_or(item.name.as_str(), |(stem, _)| stem);
+                    (!stem.eq_ignore_ascii_case("cover"), &item.name)
+                })
+                .map(|item| item.path.as_str())
+        }
+
+        if let Some(cover) = self.dirs.get(dir).and_then(|cached| first_image(&cached.files, &allowed)) {
+            return Some(cover);
+        }
+        let prefix = format!("{}/", dir);
+        self.dirs
+            .range(prefix.clone()..)
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .find_map(|(_, cached)| first_image(&cached.files, &allowed))
+    }
+
+    /// Every scanned directory with the media files directly inside it
+    pub fn dirs(&self) -> impl Iterator<Item = (&str, &[FilterResult])> {
+        self.dirs.iter().map(|(path, cached)| (path.as_str(), cached.files.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of cached files of a type (image, video or audio)
+    pub fn count_of(&self, file_type: &str) -> usize {
+        self.items().filter(|item| item.file_type == file_type).count()
+    }
+
+    /// Combined size of all cached files in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.items().map(|item| item.size).sum()
+    }
+
+    /// Add a file, or replace the entry of a file at the same path
+    /// Used for files the server wrote itself, so they show up before the next refresh.
+    pub fn insert(&mut self, item: FilterResult) {
+        let dir = item.path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("").to_string();
+        let files = Arc::make_mut(&mut self.dirs.entry(dir).or_default().files);
+        count_usage(&mut self.directories, &item, true);
+        match files.iter_mut().find(|file| file.path == item.path) {
+            Some(file) => {
+                let replaced = std::mem::replace(file, item);
+                count_usage(&mut self.directories, &replaced, false);
+            }
+            None => {
+                files.push(item);
+                self.len += 1;
+            }
+        }
+    }
+
+    /// Drop a file, or a directory and everything below it
+    pub fn remove(&mut self, path: &str) {
+        let is_below = |candidate: &str| candidate.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'));
+        let removed: Vec<String> = self.dirs.keys().filter(|dir| *dir == path || is_below(dir)).cloned().collect();
+        for dir in removed {
+            if let Some(cached) = self.dirs.remove(&dir) {
+                for file in cached.files.iter() {
+                    count_usage(&mut self.directories, file, false);
+                }
+                self.len -= cached.files.len();
+            }
+        }
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        if let Some(cached) = self.dirs.get_mut(parent) {
+            if let Some(index) = cached.files.iter().position(|file| file.path == path) {
+                let file = Arc::make_mut(&mut cached.files).remove(index);
+                count_usage(&mut self.directories, &file, false);
+                self.len -= 1;
+            }
+        }
+    }
+
+    /// Move the entries of a file, or a directory and everything below it, to a new path
+    /// Moved directories are rescanned at the next refresh.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let renamed = |path: &str| -> Option<String> {
+            let rest = path.strip_prefix(from)?;
+            (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", to, rest))
+        };
+        let moved_dirs: Vec<String> = self.dirs.keys().filter(|dir| renamed(dir).is_some()).cloned().collect();
+        for dir in moved_dirs {
+            if let (Some(mut cached), Some(new_dir)) = (self.dirs.remove(&dir), renamed(&dir)) {
+                cached.mtime = None;
+                for file in Arc::make_mut(&mut cached.files) {
+                    if let Some(path) = renamed(&file.path) {
+                        count_usage(&mut self.directories, file, false);
+                        file.path = path;
+                        count_usage(&mut self.directories, file, true);
+                    }
+                }
+                for subdir in &mut cached.subdirs {
+                    if let Some(path) = renamed(subdir) {
+                        *subdir = path;
+                    }
+                }
+                self.dirs.insert(new_dir, cached);
+            }
+        }
+        let moved_file = self
+            .dirs
+            .values()
+            .flat_map(|dir| dir.files.iter())
+            .find(|file| file.path == from)
+            .cloned();
+        if let Some(mut file) = moved_file {
+            self.remove(from);
+            file.name = to.rsplit('/').next().unwrap_or(to).to_string();
+            file.path = to.to_string();
+            self.insert(file);
+        }
+    }
+
+    /// Replace the contents with a flat list of files, grouped by their directory
+    fn set_items(&mut self, items: Vec<FilterResult>) {
+        let mut dirs: BTreeMap<String, CachedDir> = BTreeMap::new();
+        for item in items {
+            let dir = item.path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("").to_string();
+            let entry: &mut CachedDir = dirs.entry(dir).or_default();
+            Arc::make_mut(&mut entry.files).push(item);
+        }
+        // Register every ancestor so directories without files of their own are listed
+        let found: Vec<String> = dirs.keys().filter(|dir| !dir.is_empty()).cloned().collect();
+        for dir in found {
+            let mut child = dir;
+            while !child.is_empty() {
+                let parent = child.rsplit_once('/').map_or("", |(parent, _)| parent).to_string();
+                let entry = dirs.entry(parent.clone()).or_default();
+                if entry.subdirs.contains(&child) {
+                    break;
+                }
+                entry.subdirs.push(child);
+                child = parent;
+            }
+        }
+        for cached in dirs.values_mut() {
+            cached.subdirs.sort();
+        }
+        self.dirs = dirs;
+    }
+
+    /// Files directly inside a directory and the paths of its subdirectories, if it is known
+    pub fn listing(&self, dir: &str) -> Option<(&[FilterResult], &[String])> {
+        self.dirs.get(dir).map(|cached| (cached.files.as_slice(), cached.subdirs.as_slice()))
+    }
+
+    /// Recompute totals and per-directory usage after the contents changed
+    fn finish_refresh(&mut self, volume: Option<VolumeUsage>, started: Instant) {
+        self.len = self.dirs.values().map(|dir| dir.files.len()).sum();
+        self.directories = directory_usage(self.items());
+        self.volume = volume;
+        self.last_updated = Instant::now();
+        self.refreshed_at = chrono::Utc::now();
+        self.refresh_duration = started.elapsed();
+
+        metrics::histogram!("doggygallery_media_cache_refresh_duration_seconds")
+            .record(self.refresh_duration.as_secs_f64());
+        metrics::gauge!("doggygallery_media_cache_items").set(self.len as f64);
+    }
+}
+
+/// Media cache contents saved by `--cache-file`
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    /// Media directory the cache was built from
+    media_dir: PathBuf,
+    saved_at: SystemTime,
+    dirs: BTreeMap<String, CachedDir>,
+}
+
+/// Save the media cache so a restart can start from it instead of a full scan
+/// Written to a temporary file first, so a crash never leaves a truncated cache file.
+pub async fn save_media_cache(cache: &RwLock<MediaCache>, media_dir: &Path, path: &Path) -> std::io::Result<()> {
+    let contents = CacheFile {
+        version: CACHE_FILE_VERSION,
+        media_dir: media_dir.to_path_buf(),
+        saved_at: SystemTime::now(),
+        dirs: cache.read().await.dirs.clone(),
+    };
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        // Unique, as a second server may save the same file during an upgrade
+        let tmp_path = path.with_extension(format!("{:x}.tmp", rand::random::<u64>()));
+        let mut writer = BufWriter::new(std::fs::File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, &contents)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&tmp_path, &path)
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+/// Load a cache file written by `save_media_cache` for the same media directory
+/// Every directory keeps the mtime it was listed at, so the next refresh only rescans
+/// directories that changed while the server was down.
+async fn load_media_cache(media_dir: &Path, path: &Path) -> std::io::Result<Option<CacheFile>> {
+    let path = path.to_path_buf();
+    let contents: CacheFile = match tokio::task::spawn_blocking(move || {
+        let reader = BufReader::new(std::fs::File::open(path)?);
+        Ok::<_, std::io::Error>(serde_json::from_reader(reader)?)
+    })
+    .await
+    .map_err(std::io::Error::other)?
+    {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    if contents.version != CACHE_FILE_VERSION || contents.media_dir != media_dir {
+        tracing::info!("Ignoring cache file from another version or media directory");
+        return Ok(None);
+    }
+    Ok(Some(contents))
+}
+
+/// Progress of the running (or last) media scan, reported by `GET /api/index/status`
+pub struct ScanProgress {
+    /// Serializes refreshes, so a manual refresh waits for a scheduled one
+    scan_lock: tokio::sync::Mutex<()>,
+    running: AtomicBool,
+    /// Set once the cache holds the whole library (first scan done, or loaded from the index)
+    ready: AtomicBool,
+    files_scanned: AtomicU64,
+    dirs_scanned: AtomicU64,
+    state: Mutex<ScanState>,
+    /// Directories reused (hits) or listed again (misses) by refreshes without an index
+    pub media_counters: CacheCounters,
+    /// Files left alone (hits) or reread (misses) by index syncs
+    pub index_counters: CacheCounters,
+}
+
+#[derive(Default)]
+struct ScanState {
+    started: Option<Instant>,
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_duration: Option<Duration>,
+    /// File count of the previous scan, used to estimate the remaining time
+    expected_files: Option<u64>,
+    error_count: u64,
+    recent_errors: VecDeque<String>,
+}
+
+/// Snapshot of the media scan
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct IndexStatus {
+    /// Whether a scan is running right now
+    pub indexing: bool,
+    /// Whether the cache covers the whole library; false while the first scan after startup runs
+    pub ready: bool,
+    /// Media files seen by the current (or last) scan
+    pub files_scanned: u64,
+    /// Directories visited by the current (or last) scan
+    pub directories_scanned: u64,
+    /// Files found by the previous scan, if any
+    pub expected_files: Option<u64>,
+    /// Estimated seconds until the running scan finishes, if it can be estimated
+    pub eta_seconds: Option<u64>,
+    /// Start of the current (or last) scan (RFC 3339)
+    pub started_at: Option<String>,
+    /// End of the last completed scan (RFC 3339)
+    pub finished_at: Option<String>,
+    /// Duration of the last completed scan in milliseconds
+    pub last_duration_ms: Option<u64>,
+    /// Errors during the current (or last) scan
+    pub error_count: u64,
+    /// Most recent error messages (admins only)
+    pub errors: Vec<String>,
+}
+
+impl ScanProgress {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            scan_lock: tokio::sync::Mutex::new(()),
+            running: AtomicBool::new(false),
+            ready: AtomicBool::new(false),
+            files_scanned: AtomicU64::new(0),
+            dirs_scanned: AtomicU64::new(0),
+            state: Mutex::new(ScanState::default()),
+            media_counters: CacheCounters::new("media"),
+            index_counters: CacheCounters::new("index"),
+        })
+    }
+
+    /// Whether the cache covers the whole library
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    fn mark_ready(&self) {
+        self.ready.store(true, Ordering::Relaxed);
+    }
+
+    fn begin(&self, expected_files: usize) {
+        self.files_scanned.store(0, Ordering::Relaxed);
+        self.dirs_scanned.store(0, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.started = Some(Instant::now());
+        state.started_at = Some(chrono::Utc::now());
+        state.expected_files = (expected_files > 0).then_some(expected_files as u64);
+        state.error_count = 0;
+        state.recent_errors.clear();
+        self.running.store(true, Ordering::Relaxed);
+    }
+
+    fn finish(&self, completed: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if completed {
+            state.finished_at = Some(chrono::Utc::now());
+            state.last_duration = state.started.map(|started| started.elapsed());
+            self.mark_ready();
+        }
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn add_files(&self, count: usize) {
+        self.files_scanned.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_directory(&self) {
+        self.dirs_scanned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a non-fatal scan error, such as an unreadable directory
+    pub fn record_error(&self, message: String) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.error_count += 1;
+        if state.recent_errors.len() == MAX_RECENT_ERRORS {
+            state.recent_errors.pop_front();
+        }
+        state.recent_errors.push_back(message);
+    }
+
+    /// Current progress; error messages may contain paths, so they are only included on request
+    pub fn status(&self, include_errors: bool) -> IndexStatus {
+        let indexing = self.running.load(Ordering::Relaxed);
+        let files_scanned = self.files_scanned.load(Ordering::Relaxed);
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Extrapolate from the scan rate so far towards the previous scan's file count
+        let eta_seconds = match (indexing, state.started, state.expected_files) {
+            (true, Some(started), Some(expected)) if files_scanned > 0 => {
+                let rate = files_scanned as f64 / started.elapsed().as_secs_f64().max(0.001);
+                Some((expected.saturating_sub(files_scanned) as f64 / rate).ceil() as u64)
+            }
+            _ => None,
+        };
+
+        IndexStatus {
+            indexing,
+            ready: self.is_ready(),
+            files_scanned,
+            directories_scanned: self.dirs_scanned.load(Ordering::Relaxed),
+            expected_files: state.expected_files,
+            eta_seconds,
+            started_at: state.started_at.map(|t| t.to_rfc3339()),
+            finished_at: state.finished_at.map(|t| t.to_rfc3339()),
+            last_duration_ms: state.last_duration.map(|d| d.as_millis() as u64),
+            error_count: state.error_count,
+            errors: if include_errors {
+                state.recent_errors.iter().cloned().collect()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+/// Refresh the shared media cache in place, returning the number of cached files
+///
+/// Without an index, directories whose mtime hasn't changed since their last scan are
+/// reused without being listed again, and each rescanned directory is swapped in on its
+/// own, so readers are never blocked for a whole scan. Note that a file rewritten in place
+/// doesn't change its directory's mtime, so its cached size may lag until it is renamed.
+///
+/// With an index database, the index is synchronized and the cache is reloaded from it.
+/// With a media store, the cache is rebuilt from a listing of the store.
+pub async fn refresh_media_cache(
+    cache: &RwLock<MediaCache>,
+    progress: &Arc<ScanProgress>,
+    media_dir: &Path,
+    source: Source<'_>,
+    search: Option<&Arc<SearchIndex>>,
+    events: &EventBus,
+) -> Result<usize, AppError> {
+    let _scan = progress.scan_lock.lock().await;
+    let started = Instant::now();
+
+    // Remember what was there to announce new files, unless this is the first scan
+    let known: Option<HashSet<String>> = if progress.is_ready() {
+        Some(cache.read().await.items().map(|item| item.path.clone()).collect())
+    } else {
+        None
+    };
+
+    progress.begin(cache.read().await.len());
+    let result = scan(cache, progress, media_dir, &source).await;
+    progress.finish(matches!(result, Ok(true)));
+    if !result? {
+        return Ok(cache.read().await.len());
+    }
+    let scan_duration = started.elapsed();
+    metrics::histogram!(
+        "doggygallery_media_scan_duration_seconds",
+        "mode" => source.mode()
+    )
+    .record(scan_duration.as_secs_f64());
+
+    // A store's capacity isn't known; the local media directory is only a placeholder then
+    let volume = match volume_usage(media_dir) {
+        _ if matches!(source, Source::Store(_)) => None,
+        Ok(volume) => Some(volume),
+        Err(e) => {
+            tracing::warn!("Failed to read media volume usage: {}", e);
+            None
+        }
+    };
+
+    let (len, items, added) = {
+        let mut cache = cache.write().await;
+        cache.finish_refresh(volume, started);
+        let items: Vec<FilterResult> = match search {
+            Some(_) => cache.items().cloned().collect(),
+            None => Vec::new(),
+        };
+        let added: Vec<FilterResult> = match &known {
+            Some(known) => cache.items().filter(|item| !known.contains(&item.path)).cloned().collect(),
+            None => Vec::new(),
+        };
+        (cache.len(), items, added)
+    };
+    tracing::info!(
+        "Media cache refreshed: {} items indexed, scan took {:.1?} ({} directories)",
+        len,
+        scan_duration,
+        progress.status(false).directories_scanned
+    );
+    events.publish_media(added);
+    events.publish(EventData::Refresh(RefreshEvent {
+        items: len,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }));
+
+    if let Some(search) = search {
+        if let Err(e) = search.rebuild(items, media_dir, source.index()).await {
+            tracing::error!("Failed to update search index: {:?}", e);
+        }
+    }
+    Ok(len)
+}
+
+/// Where a refresh reads the library from
+#[derive(Clone, Copy)]
+pub enum Source<'a> {
+    /// Walk the media directory, listing up to this many directories at once
+    Walk(usize),
+    /// Synchronize the index database and reload it
+    Index(&'a MediaIndex),
+    /// List a remote media store
+    Store(&'a dyn MediaStore),
+}
+
+impl<'a> Source<'a> {
+    pub fn new(index: Option<&'a MediaIndex>, store: Option<&'a dyn MediaStore>, concurrency: usize) -> Self {
+        match (store, index) {
+            (Some(store), _) => Source::Store(store),
+            (None, Some(index)) => Source::Index(index),
+            (None, None) => Source::Walk(concurrency),
+        }
+    }
+
+    fn index(&self) -> Option<&'a MediaIndex> {
+        match self {
+            Source::Index(index) => Some(index),
+            _ => None,
+        }
+    }
+
+    /// Label of the scan duration metric
+    fn mode(&self) -> &'static str {
+        match self {
+            Source::Walk(_) => "walk",
+            Source::Index(_) => "index",
+            Source::Store(_) => "store",
+        }
+    }
+}
+
+/// Bring the cache up to date, returning whether it now covers the whole library
+async fn scan(
+    cache: &RwLock<MediaCache>,
+    progress: &Arc<ScanProgress>,
+    media_dir: &Path,
+    source: &Source<'_>,
+) -> Result<bool, AppError> {
+    match *source {
+        Source::Index(index) if !index.is_writer() => {
+            // Another server syncs the shared index; pick up what it wrote
+            let synced = index.last_synced().await.map_err(|e| {
+                tracing::error!("Failed to read media index: {:?}", e);
+                AppError::InternalError
+            })?;
+            if synced.is_none() {
+                tracing::info!("Waiting for another server to finish the first index sync");
+                return Ok(false);
+            }
+            let items = load_indexed_items(index).await?;
+            progress.add_files(items.len());
+            tracing::debug!("Media index synced by another server, reloaded {} items", items.len());
+            cache.write().await.set_items(items);
+        }
+        Source::Index(index) => {
+            let summary = index.sync(media_dir, progress.clone()).await.map_err(|e| {
+                tracing::error!("Failed to update media index: {:?}", e);
+                AppError::InternalError
+            })?;
+            progress.index_counters.hit(summary.unchanged as u64);
+            progress.index_counters.miss((summary.added + summary.updated) as u64);
+            progress.index_counters.evict(summary.removed as u64);
+            tracing::debug!(
+                added = summary.added,
+                updated = summary.updated,
+                removed = summary.removed,
+                unchanged = summary.unchanged,
+                "Media index updated"
+            );
+            let items = load_indexed_items(index).await?;
+            cache.write().await.set_items(items);
+        }
+        Source::Walk(concurrency) => {
+            let (rescanned, reused) = refresh_directories(cache, progress, media_dir, concurrency).await?;
+            tracing::debug!(rescanned, reused, "Media directories refreshed");
+        }
+        Source::Store(store) => {
+            let files = store.list().await.map_err(|e| {
+                tracing::error!("Failed to list media store: {}", e);
+                AppError::InternalError
+            })?;
+            let items: Vec<FilterResult> = files
+                .into_iter()
+                .filter_map(|file| {
+                    let name = file.path.rsplit('/').next().unwrap_or(&file.path).to_string();
+                    let file_type = handlers::media_type(&name)?;
+                    Some(FilterResult {
+                        path: file.path,
+                        name,
+                        size: file.size,
+                        file_type: file_type.to_string(),
+                        modified: file.modified,
+                    })
+                })
+                .collect();
+            progress.add_files(items.len());
+            cache.write().await.set_items(items);
+        }
+    }
+    Ok(true)
+}
+
+/// Build the initial media cache without scanning, so the server can start right away
+/// A populated index database or a cache file is loaded as-is; otherwise the cache starts
+/// empty and fills in directory by directory as the first background refresh runs.
+pub async fn initial_media_cache(
+    media_dir: &Path,
+    index: Option<&MediaIndex>,
+    cache_file: Option<&Path>,
+    progress: &ScanProgress,
+) -> MediaCache {
+    let mut cache = MediaCache::default();
+
+    if let Some(path) = cache_file {
+        let started = Instant::now();
+        match load_media_cache(media_dir, path).await {
+            Ok(Some(saved)) => {
+                cache.dirs = saved.dirs;
+                cache.finish_refresh(volume_usage(media_dir).ok(), started);
+                cache.refreshed_at = saved.saved_at.into();
+                progress.mark_ready();
+                tracing::info!("Loaded {} items from the cache file", cache.len());
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to read cache file {:?}: {}", path, e),
+        }
+    }
+
+    if let Some(index) = index {
+        if index.len().await.unwrap_or(0) > 0 {
+            let started = Instant::now();
+            match load_indexed_items(index).await {
+                Ok(items) => {
+                    cache.set_items(items);
+                    cache.finish_refresh(volume_usage(media_dir).ok(), started);
+                    progress.mark_ready();
+                    tracing::info!("Loaded {} items from the media index", cache.len());
+                }
+                Err(e) => tracing::warn!("Starting with an empty media cache: {:?}", e),
+            }
+        }
+    }
+
+    cache
+}
+
+async fn load_indexed_items(index: &MediaIndex) -> Result<Vec<FilterResult>, AppError> {
+    index.files(&FilterQuery::default()).await.map_err(|e| {
+        tracing::error!("Failed to read media index: {:?}", e);
+        AppError::InternalError
+    })
+}
+
+/// Walk the directory tree, rescanning only directories whose mtime changed
+/// Up to `concurrency` directories are read at once, which mostly pays off on SSDs and
+/// network storage. Returns the number of directories rescanned and reused.
+async fn refresh_directories(
+    cache: &RwLock<MediaCache>,
+    progress: &ScanProgress,
+    media_dir: &Path,
+    concurrency: usize,
+) -> Result<(usize, usize), AppError> {
+    let mut pending = vec![String::new()];
+    let mut running = FuturesUnordered::new();
+    let mut visited = HashSet::new();
+    let (mut rescanned, mut reused) = (0, 0);
+
+    loop {
+        while running.len() < concurrency {
+            let Some(relative) = pending.pop() else {
+                break;
+            };
+            running.push(refresh_directory(cache, progress, media_dir, relative));
+        }
+        let Some(visit) = running.next().await else {
+            break;
+        };
+        let Some(visit) = visit? else {
+            continue;
+        };
+        match visit.scan {
+            DirScan::Rescanned => rescanned += 1,
+            DirScan::Reused => reused += 1,
+            DirScan::Failed => {}
+        }
+        pending.extend(visit.subdirs);
+        visited.insert(visit.relative);
+    }
+
+    // Drop directories that no longer exist
+    let removed = {
+        let mut cache = cache.write().await;
+        let before = cache.dirs.len();
+        cache.dirs.retain(|dir, _| visited.contains(dir));
+        before - cache.dirs.len()
+    };
+    progress.media_counters.hit(reused as u64);
+    progress.media_counters.miss(rescanned as u64);
+    progress.media_counters.evict(removed as u64);
+
+    Ok((rescanned, reused))
+}
+
+/// One directory visited by a refresh, and the subdirectories to visit next
+struct DirVisit {
+    relative: String,
+    subdirs: Vec<String>,
+    scan: DirScan,
+}
+
+enum DirScan {
+    Rescanned,
+    /// Unchanged since the last scan
+    Reused,
+    /// Unreadable; the previous listing is kept
+    Failed,
+}
+
+/// Bring one directory of the cache up to date
+/// Returns `None` for directories that are skipped with everything below them.
+async fn refresh_directory(
+    cache: &RwLock<MediaCache>,
+    progress: &ScanProgress,
+    media_dir: &Path,
+    relative: String,
+) -> Result<Option<DirVisit>, AppError> {
+    let path = if relative.is_empty() {
+        media_dir.to_path_buf()
+    } else {
+        media_dir.join(&relative)
+    };
+
+    let mtime = match netfs::retry_async(|| fs::metadata(&path)).await {
+        Ok(metadata) => metadata.modified().ok(),
+        Err(e) if relative.is_empty() => {
+            tracing::error!("Failed to read media directory: {}", e);
+            return Err(AppError::InternalError);
+        }
+        Err(e) => {
+            tracing::warn!("Skipping unreadable directory {:?}: {}", path, e);
+            progress.record_error(format!("{}: {}", relative, e));
+            return Ok(None);
+        }
+    };
+    // Hidden directories drop out of the cache with everything below them
+    if !relative.is_empty() && DirectoryMeta::load(&path).await.is_hidden() {
+        return Ok(None);
+    }
+    progress.add_directory();
+
+    let previous = cache.read().await.dirs.get(&relative).cloned();
+    if let Some(previous) = &previous {
+        if previous.mtime.is_some() && previous.mtime == mtime {
+            progress.add_files(previous.files.len());
+            return Ok(Some(DirVisit {
+                relative,
+                subdirs: previous.subdirs.clone(),
+                scan: DirScan::Reused,
+            }));
+        }
+    }
+
+    match scan_directory(&path, &relative).await {
+        Ok((files, subdirs)) => {
+            progress.add_files(files.len());
+            let dir = CachedDir {
+                mtime,
+                files: Arc::new(files),
+                subdirs: subdirs.clone(),
+            };
+            cache.write().await.dirs.insert(relative.clone(), dir);
+            Ok(Some(DirVisit {
+                relative,
+                subdirs,
+                scan: DirScan::Rescanned,
+            }))
+        }
+        Err(e) if relative.is_empty() => {
+            tracing::error!("Failed to read media directory: {}", e);
+            Err(AppError::InternalError)
+        }
+        Err(e) => {
+            // Keep serving what we had; the mtime is unchanged so it is retried next time
+            tracing::warn!("Failed to rescan directory {:?}: {}", path, e);
+            progress.record_error(format!("{}: {}", relative, e));
+            Ok(Some(DirVisit {
+                relative,
+                subdirs: previous.map(|previous| previous.subdirs).unwrap_or_default(),
+                scan: DirScan::Failed,
+            }))
+        }
+    }
+}
+
+/// List the media files and subdirectories directly inside one directory
+/// The whole listing is one blocking task, so directories scanned concurrently are read
+/// on separate threads instead of taking turns entry by entry.
+async fn scan_directory(
+    path: &Path,
+    relative: &str,
+) -> std::io::Result<(Vec<FilterResult>, Vec<String>)> {
+    let (path, relative) = (path.to_path_buf(), relative.to_string());
+    tokio::task::spawn_blocking(move || list_directory(&path, &relative))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+fn list_directory(path: &Path, relative: &str) -> std::io::Result<(Vec<FilterResult>, Vec<String>)> {
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+
+    for (name, metadata) in netfs::list_dir(path, |name| handlers::media_type(name).is_some())? {
+        let entry_relative = if relative.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative, name)
+        };
+
+        if metadata.is_dir() {
+            subdirs.push(entry_relative);
+        } else if let Some(file_type) = handlers::media_type(&name) {
+            files.push(FilterResult {
+                path: entry_relative,
+                name,
+                size: metadata.len(),
+                file_type: file_type.to_string(),
+                modified: handlers::modified_secs(&metadata),
+            });
+        }
+    }
+
+    Ok((files, subdirs))
+}
+
+/// Space on the volume holding the media directory
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct VolumeUsage {
+    /// Total size of the volume in bytes
+    pub total_bytes: u64,
+    /// Bytes in use
+    pub used_bytes: u64,
+    /// Bytes available to this process
+    pub free_bytes: u64,
+}
+
+/// Media files stored under one top-level directory (recursively)
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct DirectoryUsage {
+    /// Directory name relative to the media directory
+    pub path: String,
+    /// Number of media files
+    pub files: usize,
+    /// Combined size of the media files in bytes
+    pub bytes: u64,
+}
+
+fn volume_usage(media_dir: &Path) -> std::io::Result<VolumeUsage> {
+    let total_bytes = fs2::total_space(media_dir)?;
+    let free_bytes = fs2::available_space(media_dir)?;
+    let used_bytes = total_bytes.saturating_sub(fs2::free_space(media_dir)?);
+    Ok(VolumeUsage {
+        total_bytes,
+        used_bytes,
+        free_bytes,
+    })
+}
+
+/// Sum cached media sizes per top-level directory; files in the media root itself are skipped
+fn directory_usage<'a>(items: impl Iterator<Item = &'a FilterResult>) -> Vec<DirectoryUsage> {
+    let mut usage: HashMap<&str, DirectoryUsage> = HashMap::new();
+    for item in items {
+        let Some((top, _)) = item.path.split_once('/') else {
+            continue;
+        };
+        let entry = usage.entry(top).or_insert_with(|| DirectoryUsage {
+            path: top.to_string(),
+            files: 0,
+            bytes: 0,
+        });
+        entry.files += 1;
+        entry.bytes += item.size;
+    }
+
+    let mut directories: Vec<DirectoryUsage> = usage.into_values().collect();
+    sort_usage(&mut directories);
+    directories
+}
+
+/// Count a file in or, if not `added`, out of the usage of its top-level directory, keeping
+/// the directories largest first
+fn count_usage(directories: &mut Vec<DirectoryUsage>, item: &FilterResult, added: bool) {
+    let Some((top, _)) = item.path.split_once('/') else {
+        return;
+    };
+    let index = match directories.iter().position(|dir| dir.path == top) {
+        Some(index) => index,
+        None if added => {
+            directories.push(DirectoryUsage {
+                path: top.to_string(),
+                files: 0,
+                bytes: 0,
+            });
+            directories.len() - 1
+        }
+        None => return,
+    };
+    let entry = &mut directories[index];
+    if added {
+        entry.files += 1;
+        entry.bytes += item.size;
+    } else {
+        entry.files = entry.files.saturating_sub(1);
+        entry.bytes = entry.bytes.saturating_sub(item.size);
+    }
+    if entry.files == 0 {
+        directories.remove(index);
+    }
+    sort_usage(directories);
+}
+
+/// Largest directories first, then by name
+fn sort_usage(directories: &mut [DirectoryUsage]) {
+    directories.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> FilterResult {
+        FilterResult {
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            size,
+            file_type: "image".to_string(),
+            modified: 0,
+        }
+    }
+
+    fn usage(cache: &MediaCache) -> Vec<(String, usize, u64)> {
+        cache.directories.iter().map(|dir| (dir.path.clone(), dir.files, dir.bytes)).collect()
+    }
+
+    #[test]
+    fn directory_usage_follows_inserts_removes_and_renames() {
+        let mut cache = MediaCache::default();
+        cache.insert(file("a/1.jpg", 10));
+        cache.insert(file("a/b/2.jpg", 20));
+        cache.insert(file("c/3.jpg", 5));
+        cache.insert(file("root.jpg", 100));
+        assert_eq!(usage(&cache), [("a".to_string(), 2, 30), ("c".to_string(), 1, 5)]);
+
+        cache.insert(file("c/3.jpg", 50));
+        assert_eq!(usage(&cache), [("c".to_string(), 1, 50), ("a".to_string(), 2, 30)]);
+
+        cache.rename("a/b", "c/b");
+        assert_eq!(usage(&cache), [("c".to_string(), 2, 70), ("a".to_string(), 1, 10)]);
+
+        cache.remove("a/1.jpg");
+        cache.remove("c/b");
+        assert_eq!(usage(&cache), [("c".to_string(), 1, 50)]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(usage(&cache), {
+            let mut fresh = cache.clone();
+            fresh.directories = directory_usage(cache.items());
+            usage(&fresh)
+        });
+    }
+}
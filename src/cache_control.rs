@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::config::Config;
+use crate::handlers;
+
+/// How long browsers and proxies may keep one kind of response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Never stored
+    NoStore,
+    /// Reused for `seconds` without asking the server again
+    MaxAge {
+        seconds: u64,
+        /// Not even revalidated on reload, for files that never change
+        immutable: bool,
+        /// Kept by browsers only, not by shared caches such as CDNs
+        private: bool,
+    },
+}
+
+impl fmt::Display for CachePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CachePolicy::NoStore => write!(f, "no-store"),
+            CachePolicy::MaxAge { seconds, immutable, private } => {
+                let scope = if *private { "private" } else { "public" };
+                write!(f, "{}, max-age={}", scope, seconds)?;
+                if *immutable {
+                    write!(f, ", immutable")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parse a cache policy such as `3600`, `31536000,immutable`, `86400,private` or `no-store`
+pub fn parse_policy(value: &str) -> Result<CachePolicy, String> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("no-store") {
+        return Ok(CachePolicy::NoStore);
+    }
+
+    let mut parts = value.split(',').map(str::trim);
+    let seconds = parts
+        .next()
+        .and_then(|seconds| seconds.parse::<u64>().ok())
+        .ok_or_else(|| format!("'{}' doesn't start with a max-age in seconds", value))?;
+    let (mut immutable, mut private) = (false, false);
+    for option in parts {
+        match option.to_ascii_lowercase().as_str() {
+            "immutable" => immutable = true,
+            "private" => private = true,
+            "public" => private = false,
+            _ => return Err(format!("unknown cache option '{}', expected immutable, private or public", option)),
+        }
+    }
+    Ok(CachePolicy::MaxAge { seconds, immutable, private })
+}
+
+/// Cache policies of each kind of response, from the --cache-* options
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicies {
+    pub images: CachePolicy,
+    pub videos: CachePolicy,
+    pub audio: CachePolicy,
+    pub thumbnails: CachePolicy,
+    pub album_art: CachePolicy,
+    pub static_assets: CachePolicy,
+}
+
+impl CachePolicies {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            images: config.cache_images,
+            videos: config.cache_videos,
+            audio: config.cache_audio,
+            thumbnails: config.cache_thumbnails,
+            album_art: config.cache_album_art,
+            static_assets: config.cache_static,
+        }
+    }
+
+    /// Policy of a media file, by its name
+    pub fn media(&self, file_name: &str) -> CachePolicy {
+        match handlers::media_type(file_name) {
+            Some("image") => self.images,
+            Some("video") => self.videos,
+            Some("audio") => self.audio,
+            _ => CachePolicy::NoStore,
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::Json,
+    Extension,
+};
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+
+/// How long a cast URL keeps working; long enough for a film with pauses
+const CAST_SESSION_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Most cast sessions a user can hold; creating another ends the oldest
+const MAX_SESSIONS_PER_USER: usize = 20;
+
+/// mDNS service types of cast receivers
+const CAST_SERVICES: &[(&str, &str)] = &[
+    ("_googlecast._tcp.local.", "chromecast"),
+    ("_airplay._tcp.local.", "airplay"),
+];
+
+/// Default and longest time spent listening for devices
+const DEFAULT_DISCOVERY_SECONDS: u64 = 2;
+const MAX_DISCOVERY_SECONDS: u64 = 10;
+
+/// Access to one media file granted to a cast receiver
+/// Receivers such as a Chromecast fetch media themselves and can't send credentials, so
+/// the URL carries a random token instead. It only opens the one file, for a limited time.
+struct CastSession {
+    id: String,
+    principal: Principal,
+    path: String,
+    created: SystemTime,
+    expires: Instant,
+}
+
+/// Active cast sessions, keyed by token
+#[derive(Default)]
+pub struct CastSessions {
+    sessions: RwLock<HashMap<String, CastSession>>,
+}
+
+impl CastSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a session for `path`, returning its token
+    async fn create(&self, principal: Principal, path: String) -> (String, CastSessionInfo) {
+        let token = base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        let session = CastSession {
+            id: format!("{:016x}", rand::random::<u64>()),
+            principal,
+            path,
+            created: SystemTime::now(),
+            expires: Instant::now() + CAST_SESSION_TTL,
+        };
+        let info = session.info(None);
+
+        let mut sessions = self.sessions.write().await;
+        sessions.retain(|_, session| session.expires > Instant::now());
+        let mut own: Vec<(&String, SystemTime)> = sessions
+            .iter()
+            .filter(|(_, s)| s.principal.username == session.principal.username)
+            .map(|(token, s)| (token, s.created))
+            .collect();
+        if own.len() >= MAX_SESSIONS_PER_USER {
+            own.sort_by_key(|(_, created)| *created);
+            let oldest = own[0].0.clone();
+            sessions.remove(&oldest);
+        }
+        sessions.insert(token.clone(), session);
+        (token, info)
+    }
+
+    /// The user a request acts for, if it carries the token of a live session for its path
+    /// Only reads of `/media/` URLs are accepted.
+    pub async fn verify(&self, method: &Method, uri: &Uri) -> Option<Principal> {
+        if !matches!(*method, Method::GET | Method::HEAD) {
+            return None;
+        }
+        let path = uri.path().strip_prefix("/media/")?;
+        let path = percent_decode_str(path).decode_utf8().ok()?;
+        let token = uri
+            .query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("cast="))?;
+
+        let sessions = self.sessions.read().await;
+        let session = sessions.get(token)?;
+        (session.path == path && session.expires > Instant::now()).then(|| session.principal.clone())
+    }
+
+    /// Drop expired sessions
+    pub async fn cleanup(&self) {
+        self.sessions
+            .write()
+            .await
+            .retain(|_, session| session.expires > Instant::now());
+    }
+}
+
+impl CastSession {
+    fn info(&self, url: Option<String>) -> CastSessionInfo {
+        let expires_in = self.expires.saturating_duration_since(Instant::now());
+        let expires_at = (SystemTime::now() + expires_in)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        CastSessionInfo {
+            id: self.id.clone(),
+            path: self.path.clone(),
+            url: url.unwrap_or_default(),
+            expires_at,
+        }
+    }
+}
+
+/// A file to cast
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CastRequest {
+    /// Media file path relative to the media directory
+    pub path: String,
+}
+
+/// A cast session and the URL to hand to the receiver
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CastSessionInfo {
+    pub id: String,
+    /// Media file path relative to the media directory
+    pub path: String,
+    /// Absolute media URL that works without credentials until the session ends
+    pub url: String,
+    /// When the URL stops working (Unix seconds)
+    pub expires_at: u64,
+}
+
+/// A cast receiver found on the local network
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CastDevice {
+    /// Friendly name, such as "Living Room TV"
+    pub name: String,
+    /// `chromecast` or `airplay`
+    pub kind: String,
+    /// Model reported by the device, if any
+    pub model: Option<String>,
+    /// IP addresses the device announced
+    pub addresses: Vec<String>,
+    pub port: u16,
+}
+
+/// Query parameters of device discovery
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DiscoveryQuery {
+    /// Seconds to listen for devices (1 to 10, default 2)
+    pub timeout: Option<u64>,
+}
+
+/// URL of a cast session on the host the request was sent to
+fn session_url(uri: &Uri, headers: &HeaderMap, path: &str, token: &str) -> Result<String, AppError> {
+    Ok(format!(
+        "https://{}{}?cast={}",
+        handlers::request_host(uri, headers)?,
+        handlers::encode_url_path(&format!("/media/{}", path)),
+        token
+    ))
+}
+
+/// Start casting a file
+/// Returns a URL to give to the receiver. It opens only this file, without credentials,
+/// for six hours or until the session is ended.
+#[utoipa::path(
+    post,
+    path = "/api/cast/sessions",
+    request_body = CastRequest,
+    responses(
+        (status = 201, description = "Session started", body = CastSessionInfo),
+        (status = 403, description = "Not signed in, or file not accessible"),
+        (status = 404, description = "File not found, or casting disabled")
+    ),
+    tag = "media"
+)]
+pub async fn create_session_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    uri: Uri,
+    headers: HeaderMap,
+    Json(request): Json<CastRequest>,
+) -> Result<(StatusCode, Json<CastSessionInfo>), AppError> {
+    let sessions = state.cast.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+    let path = handlers::media_path(&request.path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !canonical.is_file() || handlers::media_type(&path).is_none() {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(Some(&principal), &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    let username = principal.username.clone();
+    let (token, mut info) = sessions.create(principal, path).await;
+    info.url = session_url(&uri, &headers, &info.path, &token)?;
+    tracing::info!(username = %username, path = %info.path, "Cast session started");
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+/// List your active cast sessions
+#[utoipa::path(
+    get,
+    path = "/api/cast/sessions",
+    responses(
+        (status = 200, description = "Active sessions, oldest first", body = Vec<CastSessionInfo>),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "Casting disabled")
+    ),
+    tag = "media"
+)]
+pub async fn list_sessions_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<Vec<CastSessionInfo>>, AppError> {
+    let sessions = state.cast.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let sessions = sessions.sessions.read().await;
+    let mut own: Vec<(&String, &CastSession)> = sessions
+        .iter()
+        .filter(|(_, session)| {
+            session.principal.username == principal.username && session.expires > Instant::now()
+        })
+        .collect();
+    own.sort_by_key(|(_, session)| session.created);
+    own.into_iter()
+        .map(|(token, session)| {
+            Ok(session.info(Some(session_url(&uri, &headers, &session.path, token)?)))
+        })
+        .collect::<Result<_, _>>()
+        .map(Json)
+}
+
+/// End a cast session, so its URL stops working
+#[utoipa::path(
+    delete,
+    path = "/api/cast/sessions/{id}",
+    params(("id" = String, Path, description = "Session ID")),
+    responses(
+        (status = 204, description = "Session ended"),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No such session, or casting disabled")
+    ),
+    tag = "media"
+)]
+pub async fn delete_session_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let sessions = state.cast.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let mut sessions = sessions.sessions.write().await;
+    let before = sessions.len();
+    sessions.retain(|_, session| !(session.id == id && session.principal.username == principal.username));
+    if sessions.len() == before {
+        return Err(AppError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Find Chromecast and AirPlay receivers on the server's local network
+/// Listens for mDNS announcements for `timeout` seconds. Only devices on the same network
+/// as the server are found.
+#[utoipa::path(
+    get,
+    path = "/api/cast/devices",
+    params(DiscoveryQuery),
+    responses(
+        (status = 200, description = "Devices found, sorted by name", body = Vec<CastDevice>),
+        (status = 404, description = "Casting disabled")
+    ),
+    tag = "media"
+)]
+pub async fn devices_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DiscoveryQuery>,
+) -> Result<Json<Vec<CastDevice>>, AppError> {
+    state.cast.as_ref().ok_or(AppError::NotFound)?;
+    let timeout = Duration::from_secs(
+        query
+            .timeout
+            .unwrap_or(DEFAULT_DISCOVERY_SECONDS)
+            .clamp(1, MAX_DISCOVERY_SECONDS),
+    );
+    let devices = tokio::task::spawn_blocking(move || discover(timeout))
+        .await
+        .map_err(|_| AppError::InternalError)?
+        .map_err(|e| {
+            tracing::error!("Cast device discovery failed: {:?}", e);
+            AppError::InternalError
+        })?;
+    Ok(Json(devices))
+}
+
+/// Browse for cast receivers until `timeout` passes
+fn discover(timeout: Duration) -> mdns_sd::Result<Vec<CastDevice>> {
+    let daemon = mdns_sd::ServiceDaemon::new()?;
+    let receivers = CAST_SERVICES
+        .iter()
+        .map(|(service, kind)| Ok((daemon.browse(service)?, *kind)))
+        .collect::<mdns_sd::Result<Vec<_>>>()?;
+
+    let mut devices: HashMap<String, CastDevice> = HashMap::new();
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        for (receiver, kind) in &receivers {
+            while let Ok(event) = receiver.try_recv() {
+                let mdns_sd::ServiceEvent::ServiceResolved(info) = event else {
+                    continue;
+                };
+                // Chromecasts put their friendly name in a TXT record; AirPlay uses the instance name
+                let instance = info.get_fullname().split("._").next().unwrap_or_default();
+                let name = info.get_property_val_str("fn").unwrap_or(instance).to_string();
+                let model = info
+                    .get_property_val_str("md")
+                    .or_else(|| info.get_property_val_str("model"))
+                    .map(str::to_string);
+                let mut addresses: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+                addresses.sort();
+                let addresses = addresses.iter().map(IpAddr::to_string).collect();
+                devices.insert(
+                    info.get_fullname().to_string(),
+                    CastDevice {
+                        name,
+                        kind: kind.to_string(),
+                        model,
+                        addresses,
+                        port: info.get_port(),
+                    },
+                );
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let _ = daemon.shutdown();
+
+    let mut devices: Vec<CastDevice> = devices.into_values().collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.kind.cmp(&b.kind)));
+    Ok(devices)
+}
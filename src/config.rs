@@ -1,7 +1,15 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
-#[derive(Parser, Debug, Clone)]
+use crate::access_log::AccessLogFormat;
+use crate::auth::Role;
+use crate::cache_control::CachePolicy;
+use crate::tokens::{TokenEntry, TokensFile};
+use crate::users::{Credential, UserAccount, UsersFile};
+
+#[derive(Parser, Debug)]
 #[command(name = "doggygallery")]
 #[command(version)]
 #[command(about = "🐕 A secure media gallery server with TLS 1.3 and HTTP Basic Auth")]
@@ -10,33 +18,281 @@ DoggyGallery - A Beautiful, Secure Media Gallery Server
 
 Serve your images, videos, and audio files over HTTPS with authentication.
 Features automatic light/dark mode, advanced filtering, and OpenAPI docs.
+Running without a subcommand is the same as `doggygallery serve`.
 
 EXAMPLES:
     # Development with self-signed certificates
     doggygallery --self-signed-certs-on-the-fly \\
         --media-dir ./media \\
         --username admin \\
-        --password secret
+        --password secret \\
+        --insecure-plaintext-password
 
     # Production with Let's Encrypt certificates
     doggygallery --cert /etc/letsencrypt/live/example.com/fullchain.pem \\
         --key /etc/letsencrypt/live/example.com/privkey.pem \\
         --media-dir /var/media \\
         --username admin \\
-        --password secure_password \\
+        --password-hash '$argon2id$v=19$m=19456,t=2,p=1$...' \\
         --host 0.0.0.0 \\
         --port 7833
 
+    # Multiple users from a users file
+    doggygallery --self-signed-certs-on-the-fly \\
+        --media-dir ./media \\
+        --users-file ./users.toml
+
     # Using environment variables
     export DOGGYGALLERY_MEDIA_DIR=/path/to/media
     export DOGGYGALLERY_USERNAME=admin
-    export DOGGYGALLERY_PASSWORD=secret
+    export DOGGYGALLERY_PASSWORD_HASH='$argon2id$v=19$m=19456,t=2,p=1$...'
     export DOGGYGALLERY_SELF_SIGNED=true
     doggygallery
 
+    # Checking a configuration in a deploy script, without starting the server
+    doggygallery check --cert ./cert.pem --key ./key.pem \\
+        --media-dir /var/media \\
+        --users-file ./users.toml
+
+    # Starting from a config file
+    doggygallery gen-config --interactive --output ./doggygallery.toml
+    doggygallery --config ./doggygallery.toml
+
+    # Hashing a password for --password-hash
+    doggygallery hash-password
+
+    # Managing accounts in a users file
+    doggygallery user add alice --users-file ./users.toml
+    doggygallery user list --users-file ./users.toml
+
+    # Creating a read-only API token for scripts
+    doggygallery token create cron-job --role viewer --tokens-file ./tokens.toml
+
 For more information: https://github.com/meltingscales/DoggyGallery
 ")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Server arguments given without a subcommand, as for `serve`
+    #[command(flatten)]
+    pub serve: Option<Config>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the gallery server; the default when no subcommand is given
+    Serve {
+        #[command(flatten)]
+        config: Box<Config>,
+    },
+    /// Manage accounts in the users file
+    User {
+        #[command(subcommand)]
+        action: UserCommand,
+    },
+    /// Manage API tokens for programmatic access
+    Token {
+        #[command(subcommand)]
+        action: TokenCommand,
+    },
+    /// Hash a password (prompts for it) and print the hash for --password-hash or a users file
+    HashPassword {
+        /// Read the password from stdin instead of prompting
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Write an example config file with every server option described and commented out
+    GenConfig {
+        /// File to write (default: print to stdout)
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+        /// Ask for the media directory, TLS mode and an admin account, and set them in the file
+        #[arg(long, short)]
+        interactive: bool,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write a self-signed certificate and key to reuse with --cert and --key, so browsers only
+    /// need to accept it once instead of after every restart
+    GenCerts {
+        /// Certificate file to write (PEM)
+        #[arg(long, value_name = "CERT_PATH")]
+        cert: PathBuf,
+        /// Private key file to write (PEM), readable only by its owner
+        #[arg(long, value_name = "KEY_PATH")]
+        key: PathBuf,
+        /// Host names and IP addresses the certificate is valid for; the first is its common name
+        #[arg(long = "san", value_delimiter = ',', default_value = "localhost,127.0.0.1,::1", value_name = "NAME")]
+        sans: Vec<String>,
+        /// Days the certificate is valid for, starting today
+        #[arg(long, default_value = "365", value_name = "DAYS", value_parser = clap::value_parser!(u32).range(1..=3650))]
+        days: u32,
+        /// Overwrite the files if they exist
+        #[arg(long)]
+        force: bool,
+    },
+    /// Build the thumbnail cache for every image ahead of time, so browsing never waits for one
+    Thumbnails {
+        /// Directory containing the media files
+        #[arg(long, env = "DOGGYGALLERY_MEDIA_DIR", value_name = "DIR")]
+        media_dir: PathBuf,
+        /// Thumbnail cache directory, as passed to the server
+        #[arg(long, env = "DOGGYGALLERY_THUMBNAIL_CACHE_DIR", value_name = "DIR")]
+        thumbnail_cache_dir: PathBuf,
+        /// Thumbnail sizes to build, in pixels; rounded up to 50, 128, 256, 512 or 1024
+        #[arg(long, value_delimiter = ',', default_value = "50", value_name = "PIXELS")]
+        sizes: Vec<u32>,
+        /// Images processed at once (default: one per CPU core)
+        #[arg(long, value_name = "THREADS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: Option<usize>,
+        /// Most memory decoding one image may take, in megabytes
+        #[arg(long, default_value = "512", env = "DOGGYGALLERY_MAX_DECODE_MEMORY", value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+        max_decode_memory: u64,
+    },
+    /// Check the configuration, credentials, certificate and media directory without starting
+    /// the server
+    ///
+    /// Takes the same arguments as `serve`, and exits with an error if a check fails.
+    Check {
+        #[command(flatten)]
+        config: Box<Config>,
+    },
+    /// Sync the index database with the media directory without serving, then optionally
+    /// build thumbnails, e.g. as a scheduled job next to servers started with --no-index-sync
+    Index {
+        /// Directory containing the media files
+        #[arg(long, env = "DOGGYGALLERY_MEDIA_DIR", value_name = "DIR")]
+        media_dir: PathBuf,
+        /// Index database, as passed to the server
+        #[arg(long, env = "DOGGYGALLERY_INDEX_DB", value_name = "INDEX_DB")]
+        index_db: PathBuf,
+        /// ffprobe executable for reading video streams, as passed to the server
+        #[arg(long, env = "DOGGYGALLERY_FFPROBE", value_name = "FFPROBE")]
+        ffprobe: Option<PathBuf>,
+        /// Also build thumbnails in this cache directory, as passed to the server
+        #[arg(long, env = "DOGGYGALLERY_THUMBNAIL_CACHE_DIR", value_name = "DIR")]
+        thumbnail_cache_dir: Option<PathBuf>,
+        /// Thumbnail sizes to build, in pixels; rounded up to 50, 128, 256, 512 or 1024
+        #[arg(long, value_delimiter = ',', default_value = "50", value_name = "PIXELS", requires = "thumbnail_cache_dir")]
+        sizes: Vec<u32>,
+        /// Images thumbnailed at once (default: one per CPU core)
+        #[arg(long, value_name = "THREADS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: Option<usize>,
+        /// Most memory decoding one image may take, in megabytes
+        #[arg(long, default_value = "512", env = "DOGGYGALLERY_MAX_DECODE_MEMORY", value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+        max_decode_memory: u64,
+    },
+    /// Helper process for --isolate-decoding: reads an image on stdin, writes a JPEG thumbnail
+    #[command(hide = true)]
+    DecodeImage {
+        #[arg(long)]
+        size: u32,
+        /// Bytes the decoder may allocate
+        #[arg(long)]
+        max_memory: u64,
+        #[arg(long)]
+        cpu_seconds: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UserCommand {
+    /// Add a new user (prompts for the password)
+    Add {
+        /// Username of the new account
+        username: String,
+        #[command(flatten)]
+        file: UsersFileArg,
+        /// What the user may do
+        #[arg(long, value_enum, default_value = "viewer")]
+        role: Role,
+        /// Read the password from stdin instead of prompting
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Remove an existing user
+    Remove {
+        /// Username of the account to remove
+        username: String,
+        #[command(flatten)]
+        file: UsersFileArg,
+    },
+    /// List all users
+    List {
+        #[command(flatten)]
+        file: UsersFileArg,
+    },
+    /// Change a user's password (prompts for the new password)
+    Passwd {
+        /// Username of the account to update
+        username: String,
+        #[command(flatten)]
+        file: UsersFileArg,
+        /// Read the password from stdin instead of prompting
+        #[arg(long)]
+        password_stdin: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TokenCommand {
+    /// Create a new token and print it once
+    Create {
+        /// Name identifying the token (e.g. what script uses it)
+        name: String,
+        /// What the token may do
+        #[arg(long, alias = "scope", value_enum, default_value = "viewer")]
+        role: Role,
+        /// Path to the TOML tokens file to manage
+        #[arg(long, env = "DOGGYGALLERY_TOKENS_FILE", value_name = "TOKENS_FILE")]
+        tokens_file: PathBuf,
+    },
+    /// Revoke an existing token
+    Revoke {
+        /// Name of the token to revoke
+        name: String,
+        /// Path to the TOML tokens file to manage
+        #[arg(long, env = "DOGGYGALLERY_TOKENS_FILE", value_name = "TOKENS_FILE")]
+        tokens_file: PathBuf,
+    },
+    /// List all tokens (names and roles only)
+    List {
+        /// Path to the TOML tokens file to manage
+        #[arg(long, env = "DOGGYGALLERY_TOKENS_FILE", value_name = "TOKENS_FILE")]
+        tokens_file: PathBuf,
+    },
+}
+
+/// Format of log lines written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct UsersFileArg {
+    /// Path to the TOML users file to manage
+    #[arg(long, env = "DOGGYGALLERY_USERS_FILE", value_name = "USERS_FILE")]
+    pub users_file: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
 pub struct Config {
+    /// TOML file with values for these options, keyed by their long names
+    ///
+    /// For example `media-dir = "/var/media"` or `public-dir = ["shared", "kids"]`. Options
+    /// given on the command line or in environment variables take precedence over the file.
+    /// `doggygallery gen-config` writes a commented example.
+    #[arg(long, env = "DOGGYGALLERY_CONFIG", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
     /// Path to TLS certificate file (PEM format)
     ///
     /// Required for production. For development, use --self-signed-certs-on-the-fly instead.
@@ -62,21 +318,497 @@ pub struct Config {
     ///
     /// The server will recursively serve all supported media files from this directory.
     /// Supported formats: JPG, PNG, GIF, WebP, MP4, WebM, MKV, MP3, FLAC, WAV, and more.
-    #[arg(long, env = "DOGGYGALLERY_MEDIA_DIR", value_name = "DIR")]
-    pub media_dir: PathBuf,
+    #[arg(long, env = "DOGGYGALLERY_MEDIA_DIR", value_name = "DIR", required_unless_present = "backend", conflicts_with = "backend")]
+    pub media_dir: Option<PathBuf>,
+
+    /// Serve media from an S3-compatible bucket instead of --media-dir
+    ///
+    /// Format: s3://bucket or s3://bucket/prefix. The bucket is listed at every refresh and
+    /// files are streamed from it, so no local copy is needed. Uploads, file management,
+    /// the index database, archives and music features need a local media directory.
+    #[arg(long, env = "DOGGYGALLERY_BACKEND", value_name = "URL", conflicts_with_all = ["index_db", "cache_file", "enable_uploads", "enable_write_ops"])]
+    pub backend: Option<String>,
+
+    /// Endpoint of the S3-compatible service (default: AWS S3 in --s3-region)
+    ///
+    /// Example: https://minio.example.com:9000 or https://<account>.r2.cloudflarestorage.com
+    #[arg(long, env = "DOGGYGALLERY_S3_ENDPOINT", value_name = "URL", requires = "backend")]
+    pub s3_endpoint: Option<String>,
+
+    /// Region of the bucket, part of every request signature
+    #[arg(long, default_value = "us-east-1", env = "AWS_REGION", value_name = "REGION")]
+    pub s3_region: String,
+
+    /// Access key ID for the bucket
+    #[arg(long, env = "AWS_ACCESS_KEY_ID", value_name = "KEY_ID")]
+    pub s3_access_key_id: Option<String>,
+
+    /// Secret access key for the bucket
+    #[arg(long, env = "AWS_SECRET_ACCESS_KEY", value_name = "SECRET", hide_env_values = true)]
+    pub s3_secret_access_key: Option<String>,
+
+    /// Session token of temporary credentials
+    #[arg(long, env = "AWS_SESSION_TOKEN", value_name = "TOKEN", hide_env_values = true)]
+    pub s3_session_token: Option<String>,
+
+    /// Redirect media downloads to presigned bucket URLs instead of proxying them
+    ///
+    /// Saves the server's bandwidth, but clients must be able to reach the bucket endpoint.
+    /// Thumbnails are still generated and served by the server.
+    #[arg(long, env = "DOGGYGALLERY_S3_PRESIGN", requires = "backend")]
+    pub s3_presign: bool,
 
     /// Username for HTTP Basic Authentication
     ///
-    /// All requests must provide this username. Choose a strong username.
+    /// Defines a single user. Combine with --users-file to add further accounts.
     #[arg(long, env = "DOGGYGALLERY_USERNAME", value_name = "USERNAME")]
-    pub username: String,
+    pub username: Option<String>,
+
+    /// Argon2 or bcrypt hash of the password for --username
+    ///
+    /// Generate one with any argon2/bcrypt tool (e.g. the `argon2` CLI).
+    /// Example: $argon2id$v=19$m=19456,t=2,p=1$...
+    #[arg(long, env = "DOGGYGALLERY_PASSWORD_HASH", value_name = "HASH", conflicts_with = "password")]
+    pub password_hash: Option<String>,
 
-    /// Password for HTTP Basic Authentication
+    /// Plaintext password for --username (requires --insecure-plaintext-password)
     ///
-    /// All requests must provide this password. Use a strong, randomly generated password.
-    /// Consider using a password manager to generate secure passwords.
+    /// Plaintext passwords end up in shell history and process listings.
+    /// Prefer --password-hash.
     #[arg(long, env = "DOGGYGALLERY_PASSWORD", value_name = "PASSWORD")]
-    pub password: String,
+    pub password: Option<String>,
+
+    /// Path to a TOML file of hashed API tokens
+    ///
+    /// Tokens are sent as `Authorization: Bearer <token>`.
+    /// Create them with `doggygallery token create`.
+    #[arg(long, env = "DOGGYGALLERY_TOKENS_FILE", value_name = "TOKENS_FILE")]
+    pub tokens_file: Option<PathBuf>,
+
+    /// Secret for signing podcast feed URLs and the episode links inside them
+    ///
+    /// Enables /feed/<dir>.rss. Signed URLs work without logging in, so podcast apps can
+    /// subscribe; changing the secret revokes every signed URL handed out so far.
+    #[arg(long, env = "DOGGYGALLERY_URL_SIGNING_SECRET", value_name = "SECRET")]
+    pub url_signing_secret: Option<String>,
+
+    /// Shared secret for verifying HMAC-signed JWTs (HS256/HS384/HS512)
+    ///
+    /// Enables JWT mode for SSO gateways that sign tokens with a shared secret.
+    #[arg(long, env = "DOGGYGALLERY_JWT_SECRET", value_name = "SECRET", conflicts_with = "jwt_jwks_url")]
+    pub jwt_secret: Option<String>,
+
+    /// JWKS URL for verifying JWTs signed by an identity provider
+    ///
+    /// Example: https://auth.example.com/jwks.json (Authelia)
+    /// Example: https://kc.example.com/realms/home/protocol/openid-connect/certs (Keycloak)
+    #[arg(long, env = "DOGGYGALLERY_JWT_JWKS_URL", value_name = "URL")]
+    pub jwt_jwks_url: Option<String>,
+
+    /// Required JWT issuer (`iss` claim)
+    #[arg(long, env = "DOGGYGALLERY_JWT_ISSUER", value_name = "ISSUER")]
+    pub jwt_issuer: Option<String>,
+
+    /// Required JWT audience (`aud` claim)
+    #[arg(long, env = "DOGGYGALLERY_JWT_AUDIENCE", value_name = "AUDIENCE")]
+    pub jwt_audience: Option<String>,
+
+    /// JWT claim holding the username (falls back to `sub`)
+    ///
+    /// Use dots for nested claims.
+    #[arg(long, default_value = "preferred_username", env = "DOGGYGALLERY_JWT_USERNAME_CLAIM", value_name = "CLAIM")]
+    pub jwt_username_claim: String,
+
+    /// JWT claim holding the user's roles or groups
+    ///
+    /// Use dots for nested claims, e.g. `realm_access.roles` for Keycloak.
+    #[arg(long, default_value = "groups", env = "DOGGYGALLERY_JWT_ROLES_CLAIM", value_name = "CLAIM")]
+    pub jwt_roles_claim: String,
+
+    /// Role (from the roles claim) that grants admin access; others are read-only
+    #[arg(long, default_value = "admin", env = "DOGGYGALLERY_JWT_ADMIN_ROLE", value_name = "ROLE")]
+    pub jwt_admin_role: String,
+
+    /// Header carrying the raw JWT instead of `Authorization: Bearer`
+    ///
+    /// Example: Cf-Access-Jwt-Assertion
+    #[arg(long, env = "DOGGYGALLERY_JWT_HEADER", value_name = "HEADER")]
+    pub jwt_header: Option<String>,
+
+    /// OpenID Connect issuer URL for browser sign-in
+    ///
+    /// Browsers are redirected to the provider instead of getting a Basic auth prompt.
+    /// API routes keep accepting Basic auth and tokens.
+    /// Example: https://accounts.google.com
+    #[arg(long, env = "DOGGYGALLERY_OIDC_ISSUER", value_name = "URL", requires_all = ["oidc_client_id", "oidc_redirect_url"])]
+    pub oidc_issuer: Option<String>,
+
+    /// OpenID Connect client ID registered with the provider
+    #[arg(long, env = "DOGGYGALLERY_OIDC_CLIENT_ID", value_name = "CLIENT_ID")]
+    pub oidc_client_id: Option<String>,
+
+    /// OpenID Connect client secret (omit for public clients)
+    #[arg(long, env = "DOGGYGALLERY_OIDC_CLIENT_SECRET", value_name = "SECRET")]
+    pub oidc_client_secret: Option<String>,
+
+    /// Callback URL registered with the provider
+    ///
+    /// Must point at this server's /auth/callback route.
+    /// Example: https://gallery.example.com/auth/callback
+    #[arg(long, env = "DOGGYGALLERY_OIDC_REDIRECT_URL", value_name = "URL")]
+    pub oidc_redirect_url: Option<String>,
+
+    /// How long an OIDC browser session lasts, in hours
+    #[arg(long, default_value = "24", env = "DOGGYGALLERY_OIDC_SESSION_HOURS", value_name = "HOURS", value_parser = clap::value_parser!(u64).range(1..=8760))]
+    pub oidc_session_hours: u64,
+
+    /// Allow plaintext passwords from --password and the users file
+    ///
+    /// WARNING: Only for development and migration. Store hashes instead.
+    #[arg(long, env = "DOGGYGALLERY_INSECURE_PLAINTEXT_PASSWORD")]
+    pub insecure_plaintext_password: bool,
+
+    /// Path to a TOML file defining multiple users
+    ///
+    /// Each user is a [[users]] table with `username`, `password_hash` and an optional `role`
+    /// (viewer, uploader or admin) key. Plaintext `password` keys are rejected unless
+    /// --insecure-plaintext-password is set.
+    /// Example: /etc/doggygallery/users.toml
+    #[arg(long, env = "DOGGYGALLERY_USERS_FILE", value_name = "USERS_FILE")]
+    pub users_file: Option<PathBuf>,
+
+    /// Path to a TOML file restricting directories to specific users or roles
+    ///
+    /// Each rule is a [[rules]] table with `path`, `users` and `roles` keys.
+    /// Example: /etc/doggygallery/acl.toml
+    #[arg(long, env = "DOGGYGALLERY_ACL_FILE", value_name = "ACL_FILE")]
+    pub acl_file: Option<PathBuf>,
+
+    /// Path to a SQLite database indexing media files and their metadata
+    ///
+    /// Stores sizes, modification times, hashes and EXIF/ID3 tags so refreshes only reread
+    /// changed files and the index survives restarts. Created if missing.
+    /// Example: /var/lib/doggygallery/index.db
+    #[arg(long, env = "DOGGYGALLERY_INDEX_DB", value_name = "INDEX_DB")]
+    pub index_db: Option<PathBuf>,
+
+    /// Only read the index database, leaving syncing it to `doggygallery index`
+    ///
+    /// For running heavy indexing as a scheduled job apart from the server. The server
+    /// reloads the index at every refresh and picks up what the job wrote.
+    #[arg(long, env = "DOGGYGALLERY_NO_INDEX_SYNC", requires = "index_db")]
+    pub no_index_sync: bool,
+
+    /// File to keep the media cache in between restarts
+    ///
+    /// Saved after every refresh and on shutdown. At startup the saved cache is served right
+    /// away, and the first refresh only rescans directories modified since. Not needed with
+    /// --index-db, which survives restarts by itself.
+    /// Example: /var/cache/doggygallery/media-cache.json
+    #[arg(long, env = "DOGGYGALLERY_CACHE_FILE", value_name = "FILE", conflicts_with = "index_db")]
+    pub cache_file: Option<PathBuf>,
+
+    /// Directories read at once while refreshing the media cache
+    ///
+    /// Higher values speed up full scans of large libraries on SSDs and network storage;
+    /// on a single spinning disk, 1 to 4 avoids seek thrashing.
+    #[arg(long, default_value = "8", env = "DOGGYGALLERY_SCAN_CONCURRENCY", value_name = "DIRS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=256))]
+    pub scan_concurrency: usize,
+
+    /// Tune file access for a media directory on NFS, SMB or another network filesystem
+    ///
+    /// Request paths are checked lexically instead of resolving every component, directory
+    /// entries are statted several at a time, and timed-out or stale file operations are
+    /// retried. Symlinks inside the media directory are trusted rather than resolved, so
+    /// only use this if nobody can create symlinks leading out of it.
+    #[arg(long, env = "DOGGYGALLERY_NETWORK_FS")]
+    pub network_fs: bool,
+
+    /// Directory holding a full-text search index for /api/search
+    ///
+    /// Indexes file names, paths and audio tags with relevance ranking, prefix matching
+    /// and typo tolerance. Rebuilt whenever the media cache changes. Created if missing.
+    /// Example: /var/lib/doggygallery/search
+    #[arg(long, env = "DOGGYGALLERY_SEARCH_INDEX", value_name = "SEARCH_DIR")]
+    pub search_index: Option<PathBuf>,
+
+    /// Directory caching album art extracted from audio files, and its resized variants
+    ///
+    /// Art is reread from a track only when the track changes. Without it, art is
+    /// extracted on every request. Created if missing.
+    /// Example: /var/cache/doggygallery/art
+    #[arg(long, env = "DOGGYGALLERY_ART_CACHE_DIR", value_name = "ART_CACHE_DIR")]
+    pub art_cache_dir: Option<PathBuf>,
+
+    /// Directory caching image thumbnails
+    ///
+    /// Thumbnails are made once per image and size, and again only when the image changes.
+    /// Without it, they are made on every request. Fill it ahead of time with
+    /// `doggygallery thumbnails`. Created if missing.
+    /// Example: /var/cache/doggygallery/thumbnails
+    #[arg(long, env = "DOGGYGALLERY_THUMBNAIL_CACHE_DIR", value_name = "DIR")]
+    pub thumbnail_cache_dir: Option<PathBuf>,
+
+    /// ffprobe executable for reading video duration, resolution, codecs and bitrate
+    ///
+    /// Videos are probed while indexing, so this needs --index-db. Gallery listings
+    /// then show duration, resolution and codec, and flag codecs browsers can't play.
+    /// Example: /usr/bin/ffprobe
+    #[arg(long, env = "DOGGYGALLERY_FFPROBE", value_name = "FFPROBE", requires = "index_db")]
+    pub ffprobe: Option<PathBuf>,
+
+    /// Enable casting to Chromecast and AirPlay devices
+    ///
+    /// Adds a Cast button to the video player, /api/cast/devices for finding receivers on
+    /// the local network, and /api/cast/sessions for media URLs that receivers can open
+    /// without credentials.
+    #[arg(long, env = "DOGGYGALLERY_CASTING")]
+    pub casting: bool,
+
+    /// Sort file names by their bytes instead of naturally (where "track2" comes before "track10")
+    #[arg(long, env = "DOGGYGALLERY_BYTEWISE_SORT")]
+    pub bytewise_sort: bool,
+
+    /// Language of the web interface when the browser's Accept-Language names none that is
+    /// supported (en, de or es)
+    #[arg(long, default_value = "en", env = "DOGGYGALLERY_LANGUAGE", value_name = "LANG")]
+    pub language: String,
+
+    /// Name shown in page headers and titles instead of DoggyGallery
+    #[arg(long, default_value = "DoggyGallery", env = "DOGGYGALLERY_SITE_TITLE", value_name = "TITLE")]
+    pub site_title: String,
+
+    /// Hex color for links and buttons, such as #e91e63
+    #[arg(long, env = "DOGGYGALLERY_ACCENT_COLOR", value_name = "COLOR")]
+    pub accent_color: Option<String>,
+
+    /// Directory of the logo and custom CSS and JS files, served at /branding/
+    #[arg(long, env = "DOGGYGALLERY_BRANDING_DIR", value_name = "DIR")]
+    pub branding_dir: Option<PathBuf>,
+
+    /// Image in --branding-dir shown in page headers instead of the emoji prefix
+    #[arg(long, env = "DOGGYGALLERY_LOGO", value_name = "FILE", requires = "branding_dir")]
+    pub logo: Option<String>,
+
+    /// Stylesheet in --branding-dir added to every page, after the built-in styles (repeatable)
+    #[arg(long = "custom-css", env = "DOGGYGALLERY_CUSTOM_CSS", value_name = "FILE", value_delimiter = ',', requires = "branding_dir")]
+    pub custom_css: Vec<String>,
+
+    /// Script in --branding-dir added to the end of every page (repeatable)
+    #[arg(long = "custom-js", env = "DOGGYGALLERY_CUSTOM_JS", value_name = "FILE", value_delimiter = ',', requires = "branding_dir")]
+    pub custom_js: Vec<String>,
+
+    /// Serve a read-only GraphQL API of the library at /graphql
+    #[arg(long, env = "DOGGYGALLERY_GRAPHQL")]
+    pub graphql: bool,
+
+    /// Accept file uploads at PUT /api/upload/<path> from admins and uploaders
+    #[arg(long, env = "DOGGYGALLERY_ENABLE_UPLOADS")]
+    pub enable_uploads: bool,
+
+    /// Allow admins to delete, rename and move files through /api/files
+    #[arg(long, env = "DOGGYGALLERY_ENABLE_WRITE_OPS")]
+    pub enable_write_ops: bool,
+
+    /// Largest file accepted by the upload API, in megabytes
+    #[arg(long, env = "DOGGYGALLERY_MAX_UPLOAD_SIZE", value_name = "MB", default_value = "1024", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_upload_size: u64,
+
+    /// Where deleted files go until they are purged
+    ///
+    /// Must be on the same filesystem as the media directory. Only used with
+    /// --enable-write-ops. Defaults to .trash in the media directory, which is then
+    /// hidden from browsing.
+    /// Example: /srv/media/.trash
+    #[arg(long, env = "DOGGYGALLERY_TRASH_DIR", value_name = "TRASH_DIR")]
+    pub trash_dir: Option<PathBuf>,
+
+    /// Days deleted files stay in the trash before being purged (0 keeps them until purged by hand, at most 36500)
+    #[arg(long, env = "DOGGYGALLERY_TRASH_RETENTION_DAYS", value_name = "DAYS", default_value = "30", value_parser = clap::value_parser!(u64).range(0..=36500))]
+    pub trash_retention_days: u64,
+
+    /// Delete files for good instead of moving them to the trash
+    #[arg(long, env = "DOGGYGALLERY_NO_TRASH", conflicts_with = "trash_dir")]
+    pub no_trash: bool,
+
+    /// Directory (relative to the media directory) browsable without credentials
+    ///
+    /// Repeat the option or separate entries with commas. Read-only.
+    /// Example: --public-dir public --public-dir shared/holiday-2024
+    #[arg(long = "public-dir", env = "DOGGYGALLERY_PUBLIC_DIRS", value_delimiter = ',', value_name = "PATH")]
+    pub public_dirs: Vec<String>,
+
+    /// Only accept connections from this CIDR range or address
+    ///
+    /// Repeat the option or separate entries with commas. Checked against the TCP peer address.
+    /// Example: --allow-cidr 192.168.1.0/24 --allow-cidr 10.8.0.0/24
+    #[arg(long = "allow-cidr", env = "DOGGYGALLERY_ALLOW_CIDRS", value_delimiter = ',', value_name = "CIDR", value_parser = crate::ip_filter::parse_cidr)]
+    pub allow_cidrs: Vec<ipnet::IpNet>,
+
+    /// Reject connections from this CIDR range or address (takes precedence over --allow-cidr)
+    ///
+    /// Repeat the option or separate entries with commas.
+    /// Example: --deny-cidr 203.0.113.0/24
+    #[arg(long = "deny-cidr", env = "DOGGYGALLERY_DENY_CIDRS", value_delimiter = ',', value_name = "CIDR", value_parser = crate::ip_filter::parse_cidr)]
+    pub deny_cidrs: Vec<ipnet::IpNet>,
+
+    /// Maximum sustained requests per second per client IP (disabled if unset)
+    ///
+    /// Clients exceeding the limit get 429 Too Many Requests.
+    /// Example: 20
+    #[arg(long, env = "DOGGYGALLERY_RATE_LIMIT_RPS", value_name = "RPS")]
+    pub rate_limit_rps: Option<f64>,
+
+    /// Number of requests a client may make in a burst before --rate-limit-rps applies
+    ///
+    /// Gallery pages load many thumbnails at once, so keep this above the page size.
+    #[arg(long, default_value = "200", env = "DOGGYGALLERY_RATE_LIMIT_BURST", value_name = "REQUESTS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub rate_limit_burst: u32,
+
+    /// Maximum number of requests handled at once (unlimited if unset)
+    ///
+    /// Further requests get 503 Service Unavailable with Retry-After.
+    /// Example: 64
+    #[arg(long, env = "DOGGYGALLERY_MAX_IN_FLIGHT", value_name = "REQUESTS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_in_flight: Option<u32>,
+
+    /// Maximum number of open client connections (unlimited if unset)
+    ///
+    /// New connections beyond the limit are closed immediately.
+    /// Example: 256
+    #[arg(long, env = "DOGGYGALLERY_MAX_CONNECTIONS", value_name = "CONNECTIONS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_connections: Option<u32>,
+
+    /// Timeout for pages and API requests, in seconds
+    ///
+    /// Requests still running after this get 504 Gateway Timeout.
+    #[arg(long, default_value = "30", env = "DOGGYGALLERY_REQUEST_TIMEOUT", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub request_timeout: u64,
+
+    /// Timeout for media file and archive downloads, in seconds (no timeout if unset)
+    ///
+    /// Example: 600
+    #[arg(long, env = "DOGGYGALLERY_MEDIA_TIMEOUT", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub media_timeout: Option<u64>,
+
+    /// Most memory decoding one image may take, in megabytes
+    ///
+    /// Images needing more, such as decompression bombs declaring huge dimensions, are
+    /// treated as undecodable.
+    #[arg(long, default_value = "512", env = "DOGGYGALLERY_MAX_DECODE_MEMORY", value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_decode_memory: u64,
+
+    /// Decode thumbnails and album art in a separate, resource-limited helper process
+    ///
+    /// A malformed image then can't crash or take over the server. Costs a process start
+    /// per decode.
+    #[arg(long, env = "DOGGYGALLERY_ISOLATE_DECODING")]
+    pub isolate_decoding: bool,
+
+    /// Time a decode helper process gets before it is killed, in seconds
+    #[arg(long, default_value = "10", env = "DOGGYGALLERY_DECODE_TIMEOUT", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub decode_timeout: u64,
+
+    /// Number of threads for CPU-heavy work: thumbnails, album art, archives and hashing
+    ///
+    /// Further jobs wait for a free worker. Defaults to one per CPU core.
+    #[arg(long, env = "DOGGYGALLERY_WORKERS", value_name = "THREADS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub workers: Option<usize>,
+
+    /// Size of the reads media files are streamed with, in kilobytes
+    ///
+    /// Rounded up to a multiple of 16 KB, the HTTP/2 frame size. Larger reads cost less CPU
+    /// per stream and more memory per connection.
+    #[arg(long, default_value = "64", env = "DOGGYGALLERY_STREAM_BUFFER_SIZE", value_name = "KB", value_parser = clap::value_parser!(u64).range(1..=4096))]
+    pub stream_buffer_size: u64,
+
+    /// Largest archive entry extracted into memory, in megabytes
+    ///
+    /// Larger entries are extracted to a temporary file in $TMPDIR and streamed from there.
+    #[arg(long, default_value = "64", env = "DOGGYGALLERY_ARCHIVE_MEMORY_LIMIT", value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub archive_memory_limit: u64,
+
+    /// Largest archive entry served at all, in megabytes
+    ///
+    /// Requests for larger entries get 413 Content Too Large.
+    #[arg(long, default_value = "4096", env = "DOGGYGALLERY_MAX_ARCHIVE_ENTRY_SIZE", value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_archive_entry_size: u64,
+
+    /// Largest ratio of decompressed to compressed size an archive may have
+    ///
+    /// Listing or extracting from an archive that expands further, such as a decompression
+    /// bomb, stops with 413 Content Too Large. The first megabyte is exempt.
+    #[arg(long, default_value = "100", env = "DOGGYGALLERY_MAX_ARCHIVE_RATIO", value_name = "RATIO", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_archive_ratio: u64,
+
+    /// Most data one archive listing or extraction may decompress, in megabytes
+    ///
+    /// Counts the entries skipped on the way to the requested one, as TAR archives are read
+    /// from the start.
+    #[arg(long, default_value = "8192", env = "DOGGYGALLERY_MAX_ARCHIVE_OUTPUT", value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_archive_output: u64,
+
+    /// How long browsers and proxies may cache images: seconds, then optionally
+    /// `,immutable` and `,private`; or `no-store`
+    ///
+    /// Example: 31536000,immutable
+    #[arg(long, default_value = "3600", env = "DOGGYGALLERY_CACHE_IMAGES", value_name = "POLICY", value_parser = crate::cache_control::parse_policy)]
+    pub cache_images: CachePolicy,
+
+    /// How long videos may be cached, like --cache-images
+    #[arg(long, default_value = "3600", env = "DOGGYGALLERY_CACHE_VIDEOS", value_name = "POLICY", value_parser = crate::cache_control::parse_policy)]
+    pub cache_videos: CachePolicy,
+
+    /// How long audio files may be cached, like --cache-images
+    #[arg(long, default_value = "3600", env = "DOGGYGALLERY_CACHE_AUDIO", value_name = "POLICY", value_parser = crate::cache_control::parse_policy)]
+    pub cache_audio: CachePolicy,
+
+    /// How long thumbnails may be cached, like --cache-images
+    #[arg(long, default_value = "86400", env = "DOGGYGALLERY_CACHE_THUMBNAILS", value_name = "POLICY", value_parser = crate::cache_control::parse_policy)]
+    pub cache_thumbnails: CachePolicy,
+
+    /// How long album art may be cached, like --cache-images
+    #[arg(long, default_value = "604800,private", env = "DOGGYGALLERY_CACHE_ALBUM_ART", value_name = "POLICY", value_parser = crate::cache_control::parse_policy)]
+    pub cache_album_art: CachePolicy,
+
+    /// How long the built-in CSS, scripts and fonts may be cached, like --cache-images
+    #[arg(long, default_value = "31536000,immutable", env = "DOGGYGALLERY_CACHE_STATIC", value_name = "POLICY", value_parser = crate::cache_control::parse_policy)]
+    pub cache_static: CachePolicy,
+
+    /// Expose Prometheus metrics at /metrics (admin role required)
+    #[arg(long, env = "DOGGYGALLERY_METRICS")]
+    pub metrics: bool,
+
+    /// Also serve /metrics over plain HTTP without authentication on this address
+    ///
+    /// Bind to localhost or a private network only. Implies --metrics.
+    /// Example: 127.0.0.1:9090
+    #[arg(long, env = "DOGGYGALLERY_METRICS_LISTEN", value_name = "ADDR")]
+    pub metrics_listen: Option<SocketAddr>,
+
+    /// Log output format
+    ///
+    /// `json` writes one JSON object per line for Loki/ELK ingestion.
+    #[arg(long, value_enum, default_value = "text", env = "DOGGYGALLERY_LOG_FORMAT", value_name = "FORMAT")]
+    pub log_format: LogFormat,
+
+    /// Write an HTTP access log in Common/Combined Log Format to this file
+    ///
+    /// Independent of the regular log output. Example: /var/log/doggygallery/access.log
+    #[arg(long, env = "DOGGYGALLERY_ACCESS_LOG", value_name = "PATH")]
+    pub access_log: Option<PathBuf>,
+
+    /// Access log line format
+    #[arg(long, value_enum, default_value = "combined", env = "DOGGYGALLERY_ACCESS_LOG_FORMAT", value_name = "FORMAT")]
+    pub access_log_format: AccessLogFormat,
+
+    /// Rotate the access log once it reaches this size, in megabytes
+    #[arg(long, default_value = "100", env = "DOGGYGALLERY_ACCESS_LOG_MAX_SIZE", value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub access_log_max_size: u64,
+
+    /// Number of rotated access log files to keep
+    #[arg(long, default_value = "5", env = "DOGGYGALLERY_ACCESS_LOG_KEEP", value_name = "FILES")]
+    pub access_log_keep: usize,
 
     /// Host/IP address to bind to
     ///
@@ -91,6 +823,42 @@ pub struct Config {
     /// Port 7833 is memorable (spells RUFF) and doesn't require root.
     #[arg(long, default_value = "7833", env = "DOGGYGALLERY_PORT", value_name = "PORT")]
     pub port: u16,
+
+    /// Bind the port with SO_REUSEPORT, for upgrades without downtime
+    ///
+    /// A new server started with this flag can listen on the same port while the old one,
+    /// also started with it, is still running. Stop the old one with SIGTERM once the new
+    /// one is ready; it stops accepting connections and finishes the open ones.
+    /// Unix only.
+    #[arg(long, env = "DOGGYGALLERY_REUSE_PORT")]
+    pub reuse_port: bool,
+
+    /// Seconds open connections get to finish after Ctrl-C or SIGTERM (0 waits for all)
+    ///
+    /// Connections still open after that are closed, cutting off long downloads and streams.
+    #[arg(long, default_value = "30", env = "DOGGYGALLERY_SHUTDOWN_TIMEOUT", value_name = "SECONDS")]
+    pub shutdown_timeout: u64,
+
+    /// Run in the background, detached from the terminal, for hosts without systemd
+    ///
+    /// The command returns once the server accepts connections, or fails if it doesn't start.
+    /// Combine with --log-file and --pid-file. Unix only.
+    #[arg(long, env = "DOGGYGALLERY_DAEMON")]
+    pub daemon: bool,
+
+    /// Write the process ID to this file while the server runs
+    ///
+    /// The file is locked, so a second server with the same file refuses to start, and removed
+    /// on shutdown. Example: /run/doggygallery.pid
+    #[arg(long, env = "DOGGYGALLERY_PID_FILE", value_name = "PATH")]
+    pub pid_file: Option<PathBuf>,
+
+    /// Append log output to this file instead of writing it to the terminal
+    ///
+    /// Rotate it with logrotate's `copytruncate`. Unix only.
+    /// Example: /var/log/doggygallery/doggygallery.log
+    #[arg(long, env = "DOGGYGALLERY_LOG_FILE", value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
 }
 
 impl Config {
@@ -119,22 +887,139 @@ impl Config {
             );
         }
 
-        if !self.media_dir.exists() {
-            anyhow::bail!("Media directory does not exist: {:?}", self.media_dir);
+        if let Some(media_dir) = &self.media_dir {
+            if !media_dir.exists() {
+                anyhow::bail!("Media directory does not exist: {:?}", media_dir);
+            }
+
+            if !media_dir.is_dir() {
+                anyhow::bail!("Media path is not a directory: {:?}", media_dir);
+            }
+        }
+
+        match (&self.username, &self.password_hash, &self.password) {
+            (Some(username), hash, password) => {
+                if username.is_empty() {
+                    anyhow::bail!("Username cannot be empty");
+                }
+                if hash.is_none() && password.is_none() {
+                    anyhow::bail!("--username requires --password-hash");
+                }
+                if let Some(password) = password {
+                    if !self.insecure_plaintext_password {
+                        anyhow::bail!(
+                            "Plaintext --password is disabled; use --password-hash or pass --insecure-plaintext-password"
+                        );
+                    }
+                    if password.is_empty() {
+                        anyhow::bail!("Password cannot be empty");
+                    }
+                }
+            }
+            (None, hash, password) => {
+                if hash.is_some() || password.is_some() {
+                    anyhow::bail!("--password-hash and --password require --username");
+                }
+                if self.users_file.is_none() && !self.jwt_enabled() {
+                    anyhow::bail!(
+                        "Either provide --username and --password-hash, use --users-file, or configure JWT/OIDC"
+                    );
+                }
+            }
+        }
+
+        if let Some(users_file) = &self.users_file {
+            if !users_file.exists() {
+                anyhow::bail!("Users file does not exist: {:?}", users_file);
+            }
+        }
+
+        if let Some(tokens_file) = &self.tokens_file {
+            if !tokens_file.exists() {
+                anyhow::bail!("Tokens file does not exist: {:?}", tokens_file);
+            }
         }
 
-        if !self.media_dir.is_dir() {
-            anyhow::bail!("Media path is not a directory: {:?}", self.media_dir);
+        if let Some(rps) = self.rate_limit_rps {
+            if !(rps > 0.0 && rps.is_finite()) {
+                anyhow::bail!("--rate-limit-rps must be a positive number");
+            }
         }
 
-        if self.username.is_empty() {
-            anyhow::bail!("Username cannot be empty");
+        if let Some(acl_file) = &self.acl_file {
+            if !acl_file.exists() {
+                anyhow::bail!("ACL file does not exist: {:?}", acl_file);
+            }
         }
 
-        if self.password.is_empty() {
-            anyhow::bail!("Password cannot be empty");
+        if crate::i18n::Language::parse(&self.language).is_none() {
+            anyhow::bail!(
+                "Unsupported --language {:?}; supported languages are {}",
+                self.language,
+                crate::i18n::Language::supported()
+            );
         }
 
         Ok(())
     }
+
+    /// Whether identities from an external provider (JWT or OIDC) are accepted
+    pub fn jwt_enabled(&self) -> bool {
+        self.jwt_secret.is_some() || self.jwt_jwks_url.is_some() || self.oidc_issuer.is_some()
+    }
+
+    /// Build the token table from the tokens file (empty if not configured)
+    /// Normalize the --public-dir entries for the auth middleware
+    pub fn load_public_dirs(&self) -> anyhow::Result<Vec<String>> {
+        let mut dirs = Vec::new();
+        for dir in &self.public_dirs {
+            match crate::acl::normalize(dir) {
+                Some(normalized) if !normalized.is_empty() => dirs.push(normalized),
+                _ => anyhow::bail!(
+                    "Invalid --public-dir {:?}: must be a subdirectory of the media directory",
+                    dir
+                ),
+            }
+        }
+        Ok(dirs)
+    }
+
+    pub fn load_tokens(&self) -> anyhow::Result<HashMap<String, TokenEntry>> {
+        match &self.tokens_file {
+            Some(path) => TokensFile::load(path)?.into_table(),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Build the user table from --username/--password-hash and the users file
+    pub fn load_users(&self) -> anyhow::Result<HashMap<String, UserAccount>> {
+        let mut users = match &self.users_file {
+            Some(path) => UsersFile::load(path)?.into_table(self.insecure_plaintext_password)?,
+            None => HashMap::new(),
+        };
+
+        if let Some(username) = &self.username {
+            let credential = match (&self.password_hash, &self.password) {
+                (Some(hash), _) => Credential::from_hash(hash)?,
+                (None, Some(password)) => Credential::Plaintext(password.clone()),
+                (None, None) => anyhow::bail!("--username requires --password-hash"),
+            };
+
+            if users.contains_key(username) {
+                anyhow::bail!("User '{}' is defined both on the command line and in the users file", username);
+            }
+            // The command-line account is the server owner's, so it gets full access
+            users.insert(username.clone(), UserAccount { credential, role: Role::Admin });
+        }
+
+        if self.insecure_plaintext_password {
+            tracing::warn!("Plaintext passwords are enabled - this is NOT suitable for production!");
+        }
+
+        if users.is_empty() && !self.jwt_enabled() {
+            anyhow::bail!("No users configured");
+        }
+
+        Ok(users)
+    }
 }
@@ -0,0 +1,262 @@
+use anyhow::{Context, Result};
+use clap::{Arg, ArgAction, CommandFactory};
+use std::ffi::OsString;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::Cli;
+use crate::users;
+
+/// Subcommands taking the server options, which a config file fills in
+const SERVER_COMMANDS: [&str; 2] = ["serve", "check"];
+
+/// Width option descriptions are wrapped at in generated config files
+const COMMENT_WIDTH: usize = 90;
+
+/// Add the options of the config file named by `--config` (or `DOGGYGALLERY_CONFIG`) to the
+/// command line
+/// Keys are long option names, such as `media-dir = "/srv/media"`, and are passed to clap as
+/// if they had been typed, so they are validated like any other option. Options given on the
+/// command line or in environment variables win over the file. Other subcommands are left
+/// alone.
+pub fn apply(mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let root = Cli::command();
+    let (command, insert_at) = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(name) if SERVER_COMMANDS.contains(&name) => match root.find_subcommand(name) {
+            Some(command) => (command.clone(), 2),
+            None => return Ok(args),
+        },
+        Some(name) if root.find_subcommand(name).is_some() || name == "help" => return Ok(args),
+        _ => (root, 1),
+    };
+
+    let given = &args[insert_at.min(args.len())..];
+    let path = given
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|i| given.get(i + 1).cloned())
+        .or_else(|| {
+            given
+                .iter()
+                .find_map(|arg| arg.to_str()?.strip_prefix("--config=").map(OsString::from))
+        })
+        .or_else(|| std::env::var_os("DOGGYGALLERY_CONFIG"));
+    let Some(path) = path.map(PathBuf::from) else {
+        return Ok(args);
+    };
+
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    let table: toml::Table = toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+
+    let mut options = Vec::new();
+    for (key, value) in &table {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()) && key != "config" && !arg.is_hide_set())
+            .with_context(|| format!("Unknown option {:?} in config file {:?}", key, path))?;
+        let on_command_line = given.iter().any(|given| {
+            given.to_str().is_some_and(|given| {
+                given.strip_prefix("--").and_then(|given| given.strip_prefix(key.as_str()))
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+            })
+        });
+        let in_environment = arg.get_env().is_some_and(|env| std::env::var_os(env).is_some());
+        if on_command_line || in_environment {
+            continue;
+        }
+        push_option(&mut options, arg, key, value)
+            .with_context(|| format!("Invalid value for {:?} in config file {:?}", key, path))?;
+    }
+
+    let insert_at = insert_at.min(args.len());
+    args.splice(insert_at..insert_at, options);
+    Ok(args)
+}
+
+/// Append the command line form of one config file option
+fn push_option(options: &mut Vec<OsString>, arg: &Arg, key: &str, value: &toml::Value) -> Result<()> {
+    match value {
+        toml::Value::Boolean(enabled) if matches!(arg.get_action(), ArgAction::SetTrue) => {
+            if *enabled {
+                options.push(format!("--{}", key).into());
+            }
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                options.push(format!("--{}={}", key, scalar(value)?).into());
+            }
+        }
+        value => options.push(format!("--{}={}", key, scalar(value)?).into()),
+    }
+    Ok(())
+}
+
+/// A config file value as it would be typed on the command line
+fn scalar(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(text) => text.clone(),
+        toml::Value::Integer(number) => number.to_string(),
+        toml::Value::Float(number) => number.to_string(),
+        toml::Value::Boolean(enabled) => enabled.to_string(),
+        toml::Value::Datetime(_) | toml::Value::Array(_) | toml::Value::Table(_) => {
+            anyhow::bail!("expected a string, number, boolean or array of those")
+        }
+    })
+}
+
+/// `doggygallery gen-config`: write an example config file listing every server option
+/// With `interactive`, asks for the media directory, TLS mode and an admin account first and
+/// sets them; every other option is commented out with its description and default.
+pub fn run_gen_config_command(output: Option<PathBuf>, interactive: bool, force: bool) -> Result<()> {
+    if let Some(output) = &output {
+        if output.exists() && !force {
+            anyhow::bail!("{:?} already exists; pass --force to overwrite it", output);
+        }
+    }
+
+    let settings = if interactive { ask_settings()? } else { Vec::new() };
+    let mut contents = String::from(
+        "# DoggyGallery configuration\n\
+         #\n\
+         # Run the server with `doggygallery --config <this file>`. Keys are the long command line\n\
+         # options; options given on the command line or in environment variables take precedence.\n\n",
+    );
+    for (key, value) in &settings {
+        contents.push_str(&format!("{} = {}\n", key, value));
+    }
+    if !settings.is_empty() {
+        contents.push('\n');
+    }
+
+    let root = Cli::command();
+    let serve = root.find_subcommand("serve").context("The serve subcommand is missing")?;
+    for arg in serve.get_arguments() {
+        let Some(key) = arg.get_long() else {
+            continue;
+        };
+        if arg.is_hide_set() || key == "config" || settings.iter().any(|(set, _)| set == key) {
+            continue;
+        }
+        contents.push_str(&describe(arg, key));
+    }
+
+    match output {
+        Some(output) => {
+            // The file may end up holding a password hash or secrets
+            users::write_private_file(&output, &contents)?;
+            println!("Wrote {:?}", output);
+        }
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
+
+/// Commented-out entry for one option: its help, accepted values, environment variable and default
+fn describe(arg: &Arg, key: &str) -> String {
+    let mut entry = String::new();
+    let help = arg.get_long_help().or(arg.get_help()).map(|help| help.to_string()).unwrap_or_default();
+    for paragraph in help.lines() {
+        let mut line = String::from("#");
+        for word in paragraph.split_whitespace() {
+            if line.len() + word.len() >= COMMENT_WIDTH && line != "#" {
+                entry.push_str(&line);
+                entry.push('\n');
+                line = String::from("#");
+            }
+            line.push(' ');
+            line.push_str(word);
+        }
+        entry.push_str(&line);
+        entry.push('\n');
+    }
+    let possible: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if !possible.is_empty() && !matches!(arg.get_action(), ArgAction::SetTrue) {
+        entry.push_str(&format!("# One of: {}\n", possible.join(", ")));
+    }
+    if let Some(env) = arg.get_env() {
+        entry.push_str(&format!("# Environment: {}\n", env.to_string_lossy()));
+    }
+
+    let multiple = matches!(arg.get_action(), ArgAction::Append);
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|value| toml_value(&value.to_string_lossy()))
+        .collect();
+    let value_name = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| key.to_uppercase());
+    let example = match (arg.get_action(), defaults.as_slice()) {
+        (ArgAction::SetTrue, _) => "false".to_string(),
+        (_, []) if multiple => format!("[\"<{}>\"]", value_name),
+        (_, []) => format!("\"<{}>\"", value_name),
+        (_, _) if multiple => format!("[{}]", defaults.join(", ")),
+        (_, defaults) => defaults.join(", "),
+    };
+    entry.push_str(&format!("# {} = {}\n\n", key, example));
+    entry
+}
+
+/// A default value written as TOML: numbers and booleans as they are, anything else quoted
+fn toml_value(text: &str) -> String {
+    if text.parse::<f64>().is_ok() || text == "true" || text == "false" {
+        text.to_string()
+    } else {
+        toml::Value::String(text.to_string()).to_string()
+    }
+}
+
+/// Ask for the settings a new install needs, as config file keys and TOML values
+fn ask_settings() -> Result<Vec<(String, String)>> {
+    let mut settings = Vec::new();
+    let quoted = |text: &str| toml::Value::String(text.to_string()).to_string();
+
+    let media_dir = ask("Media directory", None)?;
+    if !Path::new(&media_dir).is_dir() {
+        eprintln!("Note: {:?} is not a directory yet", media_dir);
+    }
+    settings.push(("media-dir".to_string(), quoted(&media_dir)));
+
+    let tls = ask("TLS: [1] certificate and key files, [2] self-signed on the fly (development only)", Some("1"))?;
+    if tls == "2" {
+        settings.push(("self-signed-certs-on-the-fly".to_string(), "true".to_string()));
+    } else {
+        settings.push(("cert".to_string(), quoted(&ask("Certificate file (PEM)", None)?)));
+        settings.push(("key".to_string(), quoted(&ask("Private key file (PEM)", None)?)));
+    }
+
+    let username = ask("Admin username", Some("admin"))?;
+    let password_hash = users::hash_password(&users::read_new_password(false)?)?;
+    settings.push(("username".to_string(), quoted(&username)));
+    settings.push(("password-hash".to_string(), quoted(&password_hash)));
+    Ok(settings)
+}
+
+/// Prompt on stderr and read a line from stdin, falling back to `default` for an empty answer
+fn ask(question: &str, default: Option<&str>) -> Result<String> {
+    loop {
+        match default {
+            Some(default) => eprint!("{} [{}]: ", question, default),
+            None => eprint!("{}: ", question),
+        }
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            anyhow::bail!("No answer for {:?}", question);
+        }
+        match (line.trim(), default) {
+            ("", Some(default)) => return Ok(default.to_string()),
+            ("", None) => continue,
+            (answer, _) => return Ok(answer.to_string()),
+        }
+    }
+}
@@ -26,6 +26,22 @@ pub const AUDIO_EXTENSIONS: &[&str] = &[
     ".mp3", ".flac", ".wav", ".ogg", ".m4a", ".aac", ".opus", ".weba"
 ];
 
+/// Playlist file extensions shown in the music player
+pub const PLAYLIST_EXTENSIONS: &[&str] = &[
+    ".m3u", ".m3u8", ".pls"
+];
+
+/// Image files used as album art for tracks in the same directory, in order of preference
+pub const FOLDER_ART_NAMES: &[&str] = &[
+    "cover.jpg", "cover.jpeg", "cover.png", "folder.jpg", "folder.jpeg", "folder.png",
+    "front.jpg", "front.jpeg", "front.png", "album.jpg", "album.png"
+];
+
+/// Bytes at the start of a file read for MIME sniffing
+/// Enough for every signature `infer` checks in media files; nothing past them is read
+/// before a file's content is known to match its extension.
+pub const SNIFF_SIZE: usize = 16 * 1024;
+
 /// Supported archive file extensions
 pub const ARCHIVE_EXTENSIONS: &[&str] = &[
     ".zip", ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2"
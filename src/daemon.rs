@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::Config;
+
+/// Write end of the pipe the process that started the daemon waits on, until the server is ready
+static READY: Mutex<Option<File>> = Mutex::new(None);
+
+/// PID file of the running server, locked while it runs and removed when dropped
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    /// Create and lock the PID file, failing if another running server holds it
+    /// A file left behind by a server that was killed isn't locked, and is taken over.
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open PID file {:?}", path))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let pid = fs::read_to_string(path).unwrap_or_default();
+                anyhow::bail!("Another server (PID {}) is running with the PID file {:?}", pid.trim(), path);
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock PID file {:?}", path));
+            }
+        }
+        Ok(Self { path: path.to_path_buf(), file })
+    }
+
+    /// Record the current process, once it is the one that keeps running
+    fn write_pid(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        writeln!(self.file, "{}", std::process::id())?;
+        Ok(())
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            tracing::warn!("Failed to remove PID file {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Apply `--daemon`, `--log-file` and `--pid-file` before the server starts
+/// Must run before the async runtime starts threads, since only the forking thread survives a
+/// fork. The returned PID file is removed when dropped, after the server has shut down.
+pub fn start(config: &Config) -> Result<Option<PidFile>> {
+    let mut pid_file = config.pid_file.as_deref().map(PidFile::create).transpose()?;
+    let log_file = config.log_file.as_deref().map(open_log_file).transpose()?;
+    if config.daemon {
+        detach(log_file, config.log_file.as_deref())?;
+    } else if let Some(log_file) = log_file {
+        redirect_output(&log_file)?;
+    }
+    if let Some(pid_file) = &mut pid_file {
+        pid_file.write_pid().context("Failed to write PID file")?;
+    }
+    Ok(pid_file)
+}
+
+/// Tell the process that started the daemon that the server is accepting connections, so it
+/// can exit successfully
+pub fn notify_ready() {
+    let ready = READY.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(mut ready) = ready {
+        // Closing the pipe after the PID lets the waiting process exit
+        let _ = writeln!(ready, "{}", std::process::id());
+    }
+}
+
+fn open_log_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file {:?}", path))
+}
+
+/// Send stdout and stderr, where the logs and panics go, to the log file
+#[cfg(unix)]
+fn redirect_output(log_file: &File) -> Result<()> {
+    use std::os::fd::AsRawFd;
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(log_file.as_raw_fd(), fd) } == -1 {
+            return Err(std::io::Error::last_os_error()).context("Failed to redirect output to the log file");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn redirect_output(_log_file: &File) -> Result<()> {
+    anyhow::bail!("--log-file is only supported on Unix; redirect the output instead")
+}
+
+/// Detach from the terminal and session with a double fork
+/// The original process waits until the daemon is accepting connections and exits with its
+/// status, so start scripts notice a server that fails to start.
+#[cfg(unix)]
+fn detach(log_file: Option<File>, log_path: Option<&Path>) -> Result<()> {
+    use std::io::Read;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create a pipe");
+    }
+    let (mut ready_read, ready_write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("Failed to fork"),
+        0 => {}
+        _ => {
+            drop(ready_write);
+            let mut pid = String::new();
+            let _ = ready_read.read_to_string(&mut pid);
+            if pid.trim().is_empty() {
+                match log_path {
+                    Some(path) => eprintln!("DoggyGallery failed to start; see {:?}", path),
+                    None => eprintln!("DoggyGallery failed to start; pass --log-file to see why"),
+                }
+                std::process::exit(1);
+            }
+            println!("DoggyGallery is running in the background (PID {})", pid.trim());
+            std::process::exit(0);
+        }
+    }
+    drop(ready_read);
+
+    // A new session has no controlling terminal, and forking again means the daemon isn't
+    // its leader, so it can never acquire one
+    if unsafe { libc::setsid() } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to start a new session");
+    }
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("Failed to fork"),
+        0 => {}
+        _ => unsafe { libc::_exit(0) },
+    }
+
+    let null = File::options().read(true).write(true).open("/dev/null").context("Failed to open /dev/null")?;
+    if unsafe { libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to detach stdin");
+    }
+    redirect_output(log_file.as_ref().unwrap_or(&null))?;
+    *READY.lock().unwrap_or_else(|e| e.into_inner()) = Some(ready_write);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn detach(_log_file: Option<File>, _log_path: Option<&Path>) -> Result<()> {
+    anyhow::bail!("--daemon is only supported on Unix; run DoggyGallery as a service instead")
+}
@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageReader};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Config;
+use crate::constants;
+use crate::handlers::AppState;
+use crate::thumbnails;
+
+/// Address space a decode helper needs besides the image itself: the executable, its
+/// libraries and the stack
+#[cfg(unix)]
+const HELPER_OVERHEAD: u64 = 256 * 1024 * 1024;
+
+/// Decode memory limit where no --max-decode-memory is given, 512 MB
+pub const DEFAULT_MAX_MEMORY: u64 = 512 * 1024 * 1024;
+
+/// How images from the media directory are decoded
+/// Image decoders are large parsers of untrusted input. Every decode is held to a memory
+/// limit; with isolation, thumbnails and album art are decoded in a short-lived helper
+/// process that is killed when it runs too long, so a malformed file can't crash, hang or
+/// exploit the server itself.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeLimits {
+    /// Most bytes a decoder may allocate for one image
+    pub max_memory: u64,
+    /// Decode thumbnails and album art in a helper process
+    pub isolate: bool,
+    /// Time a helper process gets before it is killed
+    pub timeout: Duration,
+}
+
+impl DecodeLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_memory: config.max_decode_memory.saturating_mul(1024 * 1024),
+            isolate: config.isolate_decoding,
+            timeout: Duration::from_secs(config.decode_timeout),
+        }
+    }
+}
+
+fn is_image(data: &[u8]) -> bool {
+    infer::get(data).is_some_and(|kind| kind.mime_type().starts_with("image/"))
+}
+
+/// Read an image file, checking its magic bytes before reading past them
+pub fn read_image(path: &Path) -> Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut data = Vec::new();
+    Read::by_ref(&mut file).take(constants::SNIFF_SIZE as u64).read_to_end(&mut data)?;
+    if !is_image(&data) {
+        anyhow::bail!("not an image");
+    }
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Decode image file contents, allocating at most `max_memory` bytes
+/// Anything that isn't an image by its magic bytes is refused before a decoder sees it.
+pub fn decode(data: &[u8], max_memory: u64) -> Result<DynamicImage> {
+    if !is_image(data) {
+        anyhow::bail!("not an image");
+    }
+
+    let mut limits = image::Limits::default();
+    limits.max_alloc = Some(max_memory);
+    let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+/// Decode an image file, allocating at most `max_memory` bytes
+pub fn open(path: &Path, max_memory: u64) -> Result<DynamicImage> {
+    decode(&read_image(path)?, max_memory)
+}
+
+/// Width and height of an image, read from its header without decoding it
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// JPEG thumbnail of image file contents, `size` pixels at most on each side
+/// Runs on the worker pool, in a helper process when decoding is isolated.
+pub async fn thumbnail(state: &AppState, data: Vec<u8>, size: u32) -> Result<Vec<u8>> {
+    let limits = state.decode_limits;
+    if limits.isolate {
+        state.workers.hold(isolated(limits, data, size)).await
+    } else {
+        state
+            .workers
+            .run(move || thumbnails::render(&data, size, limits.max_memory))
+            .await?
+    }
+}
+
+/// Render a thumbnail in a `decode-image` helper process
+async fn isolated(limits: DecodeLimits, data: Vec<u8>, size: u32) -> Result<Vec<u8>> {
+    let mut child = tokio::process::Command::new(std::env::current_exe()?)
+        .arg("decode-image")
+        .arg("--size")
+        .arg(size.to_string())
+        .arg("--max-memory")
+        .arg(limits.max_memory.to_string())
+        .arg("--cpu-seconds")
+        .arg(limits.timeout.as_secs().to_string())
+        .env("RUST_LOG", "off")
+        .env_remove("RUST_BACKTRACE")
+        .env_remove("RUST_LIB_BACKTRACE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start image decode helper")?;
+
+    let mut stdin = child.stdin.take().context("Decode helper has no stdin")?;
+    let output = tokio::time::timeout(limits.timeout, async move {
+        // A helper that dies early closes its stdin; its exit status tells why
+        let _ = stdin.write_all(&data).await;
+        drop(stdin);
+        child.wait_with_output().await
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("decoding took longer than {:?}", limits.timeout))??;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = stderr.lines().next().unwrap_or("").trim_start_matches("Error: ");
+        if message.is_empty() {
+            anyhow::bail!("decode helper failed: {}", output.status);
+        }
+        anyhow::bail!("{}", message);
+    }
+    Ok(output.stdout)
+}
+
+/// `doggygallery decode-image`: the helper process behind --isolate-decoding
+/// Reads an image on stdin and writes its thumbnail as JPEG to stdout.
+pub fn run_decode_command(size: u32, max_memory: u64, cpu_seconds: u64) -> Result<()> {
+    #[cfg(unix)]
+    confine(max_memory, cpu_seconds);
+    #[cfg(not(unix))]
+    let _ = cpu_seconds;
+
+    let mut data = Vec::new();
+    std::io::stdin().lock().read_to_end(&mut data)?;
+    let thumbnail = thumbnails::render(&data, size, max_memory)?;
+    std::io::stdout().lock().write_all(&thumbnail)?;
+    Ok(())
+}
+
+/// Restrict the helper to what decoding needs: bounded memory and CPU time, no new files
+/// Limits only fail to apply where the system forbids them, which leaves the decoder's own
+/// memory limit and the parent's timeout in place.
+#[cfg(unix)]
+fn confine(max_memory: u64, cpu_seconds: u64) {
+    let limits = [
+        (libc::RLIMIT_AS, max_memory.saturating_add(HELPER_OVERHEAD)),
+        (libc::RLIMIT_CPU, cpu_seconds),
+        (libc::RLIMIT_CORE, 0),
+        (libc::RLIMIT_NOFILE, 3),
+    ];
+    for (resource, value) in limits {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        // SAFETY: setrlimit only reads the struct passed to it
+        unsafe {
+            libc::setrlimit(resource, &limit);
+        }
+    }
+}
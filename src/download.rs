@@ -0,0 +1,133 @@
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipDateTime, ZipEntryBuilder};
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::Response,
+    Extension,
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::path::PathBuf;
+use tokio::io::{BufReader, DuplexStream};
+use tokio_util::compat::FuturesAsyncWriteCompatExt;
+use tokio_util::io::ReaderStream;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+use crate::models::DirectoryEntry;
+use crate::netfs;
+use crate::sidecar;
+
+/// Bytes of ZIP data buffered between the writer task and the response body
+/// The writer waits while the buffer is full, so a slow client slows down the reads too.
+const PIPE_CAPACITY: usize = 256 * 1024;
+
+/// Handler for /download - the whole media directory as a ZIP
+pub async fn download_root_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Response, AppError> {
+    download_handler(State(state), principal, Path(String::new())).await
+}
+
+/// Handler for /download/*path - a directory and its subdirectories as a ZIP
+/// The archive is written while it is sent: files are stored as they are, without
+/// compression, so the download starts at once and nothing is buffered on the server.
+/// Directories with more files or levels than a recursive listing returns are refused.
+pub async fn download_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Response, AppError> {
+    let path = handlers::media_path(&path)?;
+    let canonical = if path.is_empty() {
+        state.media_dir.clone()
+    } else {
+        handlers::validate_media_path(&state.media_dir, &path)?
+    };
+    if !canonical.is_dir() || sidecar::in_nomedia(&state.media_dir, &canonical).await {
+        return Err(AppError::NotFound);
+    }
+
+    let (mut files, complete) = handlers::flatten_directory(
+        &state,
+        principal.as_deref(),
+        &canonical,
+        &path,
+        handlers::MAX_FLATTEN_DEPTH,
+        false,
+    )
+    .await?;
+    // Refuse rather than send an archive silently missing files
+    if !complete {
+        return Err(AppError::DownloadTooLarge);
+    }
+    files.sort_by(|a, b| state.name_order.cmp(&a.name, &b.name));
+
+    let folder = match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => state.branding.title.clone(),
+    };
+    let (writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+    tokio::spawn(write_zip(state.media_dir.clone(), folder.clone(), files, writer, state.stream_buffer));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.zip\"; filename*=UTF-8''{}.zip",
+                folder.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_"),
+                utf8_percent_encode(&folder, NON_ALPHANUMERIC)
+            ),
+        )
+        .header(header::CACHE_CONTROL, "private, no-store")
+        .body(Body::from_stream(ReaderStream::with_capacity(reader, state.stream_buffer)))
+        .map_err(|_| AppError::InternalError)
+}
+
+/// Write `files` into a ZIP under `folder`, streaming it into `writer`, reading `buffer` bytes at a time
+/// Files that vanish or can't be opened while the archive is written are left out. An
+/// error stops the archive short, which clients report as a failed download.
+async fn write_zip(
+    media_dir: PathBuf,
+    folder: String,
+    files: Vec<DirectoryEntry>,
+    writer: DuplexStream,
+    buffer: usize,
+) {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    for file in files {
+        let source = match netfs::canonicalize(&media_dir.join(&file.path)) {
+            Ok(source) if source.starts_with(&media_dir) => source,
+            _ => continue,
+        };
+        let mut source = match tokio::fs::File::open(&source).await {
+            Ok(source) => BufReader::with_capacity(buffer, source),
+            Err(e) => {
+                tracing::warn!("Leaving {:?} out of a download: {}", file.path, e);
+                continue;
+            }
+        };
+
+        let mut entry = ZipEntryBuilder::new(format!("{}/{}", folder, file.name).into(), Compression::Stored);
+        if let Some(modified) = file.modified.and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0)) {
+            entry = entry.last_modification_date(ZipDateTime::from_chrono(&modified));
+        }
+        let written = async {
+            let mut entry = zip.write_entry_stream(entry).await?.compat_write();
+            tokio::io::copy_buf(&mut source, &mut entry).await?;
+            entry.into_inner().close().await
+        }
+        .await;
+        if let Err(e) = written {
+            // Usually the client went away
+            tracing::debug!("Download of {:?} stopped: {}", folder, e);
+            return;
+        }
+    }
+    if let Err(e) = zip.close().await {
+        tracing::debug!("Download of {:?} stopped: {}", folder, e);
+    }
+}
@@ -1,24 +1,57 @@
 use axum::{
     body::Body,
-    http::{header, StatusCode, Response, Uri},
+    extract::State,
+    http::{header, HeaderMap, StatusCode, Response, Uri},
     response::IntoResponse,
 };
 use rust_embed::RustEmbed;
 
+use crate::handlers::AppState;
+
 /// Embed static files into the binary at compile time
 #[derive(RustEmbed)]
 #[folder = "static/"]
 pub struct StaticAssets;
 
+/// Brotli (`<file>.br`) and gzip (`<file>.gz`) variants of the compressible static files,
+/// made by build.rs
+#[derive(RustEmbed)]
+#[folder = "$OUT_DIR/static-compressed/"]
+struct CompressedAssets;
+
+/// Content codings of the precompressed variants, in order of preference, with their file extensions
+const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// Whether the request's `Accept-Encoding` lists `encoding` without `q=0`
+fn accepts(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let refused = params.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            name.eq_ignore_ascii_case(encoding) && !refused
+        })
+}
+
 /// Handler for serving embedded static files
-pub async fn serve_static(uri: Uri) -> impl IntoResponse {
+/// Text assets are sent precompressed when the client accepts Brotli or gzip.
+pub async fn serve_static(State(state): State<AppState>, uri: Uri, headers: HeaderMap) -> impl IntoResponse {
     let path = uri.path().trim_start_matches("/static/");
 
     match StaticAssets::get(path) {
         Some(content) => {
             let mime = mime_guess::from_path(path).first_or_octet_stream();
-
-            Response::builder()
+            let mut response = Response::builder()
                 .status(StatusCode::OK)
                 .header(
                     header::CONTENT_TYPE,
@@ -26,9 +59,30 @@ pub async fn serve_static(uri: Uri) -> impl IntoResponse {
                 )
                 .header(
                     header::CACHE_CONTROL,
-                    "public, max-age=31536000, immutable",
-                )
-                .body(Body::from(content.data.into_owned()))
+                    state.cache.static_assets.to_string(),
+                );
+
+            let variant = ENCODINGS
+                .iter()
+                .filter(|(encoding, _)| accepts(&headers, encoding))
+                .find_map(|&(encoding, extension)| {
+                    CompressedAssets::get(&format!("{}.{}", path, extension)).map(|variant| (encoding, variant))
+                });
+            let body = match variant {
+                // The compression layer passes encoded responses through untouched, so they
+                // need their own Vary; it adds one to the identity responses itself
+                Some((encoding, variant)) => {
+                    response = response
+                        .header(header::CONTENT_ENCODING, encoding)
+                        .header(header::VARY, "accept-encoding");
+                    variant.data
+                }
+                None => content.data,
+            };
+
+            response
+                .header(header::CONTENT_LENGTH, body.len())
+                .body(Body::from(body.into_owned()))
                 .unwrap()
         }
         None => Response::builder()
@@ -0,0 +1,262 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+    Extension,
+};
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::cache::{IndexStatus, ScanProgress};
+use crate::handlers::{AppState, FilterResult};
+
+/// Events kept for clients reconnecting with `Last-Event-ID`
+const REPLAY_EVENTS: usize = 256;
+
+/// New files listed in one `media` event; the rest are only counted
+pub const MAX_MEDIA_ITEMS: usize = 100;
+
+/// How often indexing progress is reported while a scan runs
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A media cache refresh finished
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RefreshEvent {
+    /// Media files in the cache
+    pub items: usize,
+    pub duration_ms: u64,
+}
+
+/// Media files appeared, from an upload or a refresh
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MediaEvent {
+    /// The new files, up to 100
+    pub items: Vec<FilterResult>,
+    /// Whether more files appeared than are listed
+    pub truncated: bool,
+}
+
+/// What happened, sent as the SSE event name and JSON data
+#[derive(Debug, Clone)]
+pub enum EventData {
+    Refresh(RefreshEvent),
+    Indexing(IndexStatus),
+    Media(MediaEvent),
+}
+
+impl EventData {
+    fn name(&self) -> &'static str {
+        match self {
+            EventData::Refresh(_) => "refresh",
+            EventData::Indexing(_) => "indexing",
+            EventData::Media(_) => "media",
+        }
+    }
+
+    /// Progress reports are superseded by the next one, so they aren't replayed
+    fn replayable(&self) -> bool {
+        !matches!(self, EventData::Indexing(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub id: u64,
+    pub data: EventData,
+}
+
+struct Recent {
+    next_id: u64,
+    /// Replayable events, oldest first
+    events: VecDeque<Event>,
+    /// ID of the newest event dropped from `events`
+    evicted: u64,
+}
+
+/// Fans server events out to `GET /api/events` subscribers
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    recent: Mutex<Recent>,
+}
+
+impl EventBus {
+    pub fn new() -> Arc<Self> {
+        let (sender, _) = broadcast::channel(REPLAY_EVENTS);
+        Arc::new(Self {
+            sender,
+            recent: Mutex::new(Recent {
+                next_id: 1,
+                events: VecDeque::new(),
+                evicted: 0,
+            }),
+        })
+    }
+
+    pub fn publish(&self, data: EventData) {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let event = Event {
+            id: recent.next_id,
+            data,
+        };
+        recent.next_id += 1;
+        if event.data.replayable() {
+            if recent.events.len() == REPLAY_EVENTS {
+                if let Some(dropped) = recent.events.pop_front() {
+                    recent.evicted = dropped.id;
+                }
+            }
+            recent.events.push_back(event.clone());
+        }
+        // Sent under the lock, so a subscriber never sees an event both replayed and live
+        let _ = self.sender.send(event);
+    }
+
+    /// Announce new media files
+    pub fn publish_media(&self, mut items: Vec<FilterResult>) {
+        if items.is_empty() {
+            return;
+        }
+        let truncated = items.len() > MAX_MEDIA_ITEMS;
+        items.truncate(MAX_MEDIA_ITEMS);
+        self.publish(EventData::Media(MediaEvent { items, truncated }));
+    }
+
+    /// Whether anyone is listening
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    /// Subscribe to new events, along with the kept events after `last_id`
+    /// The flag is set if events after `last_id` were dropped from the replay buffer
+    /// (or `last_id` is from before a restart), so the client should reload its state.
+    fn subscribe(&self, last_id: Option<u64>) -> (Vec<Event>, bool, broadcast::Receiver<Event>) {
+        let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let receiver = self.sender.subscribe();
+        let Some(last_id) = last_id else {
+            return (Vec::new(), false, receiver);
+        };
+        let missed = last_id >= recent.next_id || last_id < recent.evicted;
+        let backlog = recent.events.iter().filter(|event| event.id > last_id).cloned().collect();
+        (backlog, missed, receiver)
+    }
+}
+
+/// Report indexing progress while scans run
+/// A last report with `indexing: false` follows the end of each scan.
+pub fn watch_scan(progress: Arc<ScanProgress>, events: Arc<EventBus>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+        let mut was_indexing = false;
+        loop {
+            interval.tick().await;
+            let status = progress.status(false);
+            let report = status.indexing || was_indexing;
+            was_indexing = status.indexing;
+            if report && events.has_subscribers() {
+                events.publish(EventData::Indexing(status));
+            }
+        }
+    });
+}
+
+/// Query parameters of the event stream
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct EventsQuery {
+    /// Resume after this event ID, for clients that can't send the `Last-Event-ID` header
+    pub last_event_id: Option<u64>,
+}
+
+/// Convert an event for one subscriber, dropping media files it may not see
+fn to_sse(state: &AppState, principal: Option<&Principal>, event: Event) -> Option<SseEvent> {
+    let name = event.data.name();
+    let data = match event.data {
+        EventData::Refresh(refresh) => serde_json::to_string(&refresh),
+        EventData::Indexing(status) => serde_json::to_string(&status),
+        EventData::Media(media) => {
+            let items: Vec<FilterResult> = media
+                .items
+                .into_iter()
+                .filter(|item| state.acl.allows(principal, &item.path))
+                .collect();
+            if items.is_empty() && !media.truncated {
+                return None;
+            }
+            serde_json::to_string(&MediaEvent {
+                items,
+                truncated: media.truncated,
+            })
+        }
+    };
+    Some(SseEvent::default().id(event.id.to_string()).event(name).data(data.ok()?))
+}
+
+/// Stream of server events (Server-Sent Events)
+/// Event names:
+/// - `refresh`: a media cache refresh finished (`RefreshEvent`)
+/// - `indexing`: progress of a running scan, every few seconds (`IndexStatus`)
+/// - `media`: new files were uploaded or found by a refresh (`MediaEvent`)
+/// - `resync`: events were missed since `Last-Event-ID`; reload any derived state
+///
+/// Reconnecting clients send `Last-Event-ID` to receive the events they missed.
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    params(EventsQuery),
+    responses(
+        (status = 200, description = "Event stream", content_type = "text/event-stream", body = String)
+    ),
+    tag = "media"
+)]
+pub async fn events_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let last_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+        .or(query.last_event_id);
+    let (backlog, missed, receiver) = state.events.subscribe(last_id);
+    let principal = principal.map(|Extension(principal)| principal);
+
+    let mut initial: VecDeque<SseEvent> = VecDeque::new();
+    if missed {
+        initial.push_back(SseEvent::default().event("resync").data("{}"));
+    }
+    initial.extend(
+        backlog
+            .into_iter()
+            .filter_map(|event| to_sse(&state, principal.as_ref(), event)),
+    );
+
+    let stream = stream::unfold(
+        (initial, receiver, state, principal),
+        |(mut initial, mut receiver, state, principal)| async move {
+            if let Some(event) = initial.pop_front() {
+                return Some((Ok(event), (initial, receiver, state, principal)));
+            }
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        if let Some(event) = to_sse(&state, principal.as_ref(), event) {
+                            return Some((Ok(event), (initial, receiver, state, principal)));
+                        }
+                    }
+                    // A slow client ends the stream; it reconnects with Last-Event-ID and
+                    // catches up from the replay buffer
+                    Err(broadcast::error::RecvError::Lagged(_)) => return None,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,269 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    Extension,
+};
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState, FilterQuery, FilterResult};
+use crate::index::MediaIndex;
+use crate::models::DirectoryEntry;
+
+impl MediaIndex {
+    /// Mark a file as one of a user's favorites
+    pub async fn add_favorite(&self, username: String, path: String) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO favorites (username, path, added_at) VALUES (?1, ?2, ?3)",
+                params![username, path, now],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Remove a file from a user's favorites
+    pub async fn remove_favorite(&self, username: String, path: String) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM favorites WHERE username = ?1 AND path = ?2",
+                params![username, path],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Paths of a user's favorites
+    pub async fn favorite_paths(&self, username: String) -> anyhow::Result<HashSet<String>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT path FROM favorites WHERE username = ?1")?;
+            let rows = stmt.query_map([username], |row| row.get(0))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// A user's favorites with their file details, most recently added first
+    /// Favorites not (yet) in the index are returned with a zero size and mtime.
+    pub async fn favorites(&self, username: String) -> anyhow::Result<Vec<(FilterResult, i64)>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT f.path, files.name, files.size, files.file_type, files.mtime, f.added_at
+                 FROM favorites f LEFT JOIN files ON files.path = f.path
+                 WHERE f.username = ?1
+                 ORDER BY f.added_at DESC, f.path",
+            )?;
+            let rows = stmt.query_map([username], |row| {
+                let path: String = row.get(0)?;
+                let name = row
+                    .get::<_, Option<String>>(1)?
+                    .unwrap_or_else(|| path.rsplit('/').next().unwrap_or_default().to_string());
+                let file_type = row
+                    .get::<_, Option<String>>(3)?
+                    .or_else(|| handlers::media_type(&name).map(str::to_string))
+                    .unwrap_or_default();
+                Ok((
+                    FilterResult {
+                        size: row.get::<_, Option<u64>>(2)?.unwrap_or(0),
+                        modified: row.get::<_, Option<i64>>(4)?.unwrap_or(0).max(0) as u64,
+                        path,
+                        name,
+                        file_type,
+                    },
+                    row.get(5)?,
+                ))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+}
+
+/// Keep only the signed-in user's favorites when the query asks for `favorites=true`
+/// Anonymous users and servers without an index database have no favorites.
+pub async fn filter_favorites(
+    state: &AppState,
+    query: &FilterQuery,
+    principal: Option<&Principal>,
+    mut items: Vec<FilterResult>,
+) -> Result<Vec<FilterResult>, AppError> {
+    if query.favorites != Some(true) {
+        return Ok(items);
+    }
+    let (Some(index), Some(principal)) = (&state.index, principal) else {
+        return Ok(Vec::new());
+    };
+
+    let favorites = index
+        .favorite_paths(principal.username.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to read favorites: {:?}", e);
+            AppError::InternalError
+        })?;
+    items.retain(|item| favorites.contains(&item.path));
+    Ok(items)
+}
+
+/// Mark which directory listing entries are the user's favorites
+pub async fn attach_favorites(
+    state: &AppState,
+    principal: Option<&Principal>,
+    entries: &mut [DirectoryEntry],
+) {
+    let (Some(index), Some(principal)) = (&state.index, principal) else {
+        return;
+    };
+    match index.favorite_paths(principal.username.clone()).await {
+        Ok(favorites) => {
+            for entry in entries {
+                entry.favorite = favorites.contains(&entry.path);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to read favorites for listing: {:?}", e),
+    }
+}
+
+/// A favorite file and when it was added
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Favorite {
+    #[serde(flatten)]
+    pub file: FilterResult,
+    /// When the file was added to favorites (RFC 3339)
+    pub added_at: String,
+}
+
+/// Whether a file is now a favorite
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FavoriteState {
+    /// File path relative to the media directory
+    pub path: String,
+    /// Whether the file is in the user's favorites
+    pub favorite: bool,
+}
+
+/// List the signed-in user's favorites, most recently added first
+#[utoipa::path(
+    get,
+    path = "/api/favorites",
+    responses(
+        (status = 200, description = "Favorite files", body = Vec<Favorite>),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn list_favorites_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Vec<Favorite>>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let favorites = index
+        .favorites(principal.username.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to read favorites: {:?}", e);
+            AppError::InternalError
+        })?;
+
+    Ok(Json(
+        favorites
+            .into_iter()
+            .filter(|(file, _)| state.acl.allows(Some(&principal), &file.path))
+            .map(|(file, added_at)| Favorite {
+                file,
+                added_at: chrono::DateTime::from_timestamp(added_at, 0)
+                    .unwrap_or_default()
+                    .to_rfc3339(),
+            })
+            .collect(),
+    ))
+}
+
+/// Add a file to the signed-in user's favorites
+#[utoipa::path(
+    put,
+    path = "/api/favorites/{path}",
+    params(("path" = String, Path, description = "File path relative to the media directory")),
+    responses(
+        (status = 200, description = "File is a favorite", body = FavoriteState),
+        (status = 403, description = "Not signed in, or path not accessible"),
+        (status = 404, description = "File not found, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn add_favorite_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<FavoriteState>, AppError> {
+    let (index, principal, path) = validate_request(&state, principal, &path)?;
+    index
+        .add_favorite(principal.username.clone(), path.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to add favorite: {:?}", e);
+            AppError::InternalError
+        })?;
+    Ok(Json(FavoriteState { path, favorite: true }))
+}
+
+/// Remove a file from the signed-in user's favorites
+#[utoipa::path(
+    delete,
+    path = "/api/favorites/{path}",
+    params(("path" = String, Path, description = "File path relative to the media directory")),
+    responses(
+        (status = 200, description = "File is not a favorite", body = FavoriteState),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn remove_favorite_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<FavoriteState>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+    // Files that have since been deleted can still be removed from favorites
+    let path = handlers::media_path(&path)?;
+    index
+        .remove_favorite(principal.username.clone(), path.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to remove favorite: {:?}", e);
+            AppError::InternalError
+        })?;
+    Ok(Json(FavoriteState { path, favorite: false }))
+}
+
+/// Check a favorite request: signed in, and the path is an accessible media file
+fn validate_request<'a>(
+    state: &'a AppState,
+    principal: Option<Extension<Principal>>,
+    path: &str,
+) -> Result<(&'a MediaIndex, Principal, String), AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+
+    let path = handlers::media_path(path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !canonical.is_file() || handlers::media_type(&path).is_none() {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(Some(&principal), &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok((index, principal, path))
+}
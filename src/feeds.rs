@@ -0,0 +1,243 @@
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, Method, Uri},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use sha2::Sha256;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+use crate::metadata;
+
+/// Routes reachable through a signed URL: the feeds and what they link to
+const SIGNED_ROUTES: &[&str] = &["/feed/", "/media/", "/album-art/"];
+
+/// Signs URLs so podcast apps can fetch feeds and episodes without logging in
+/// A signature covers a username and one decoded request path, so it grants that user
+/// read access to exactly that URL. Signed URLs don't expire; changing the secret
+/// revokes all of them.
+pub struct UrlSigner {
+    secret: Vec<u8>,
+}
+
+impl UrlSigner {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn mac(&self, username: &str, path: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)
+            .expect("HMAC accepts keys of any length");
+        mac.update(username.as_bytes());
+        mac.update(b"\n");
+        mac.update(path.as_bytes());
+        mac
+    }
+
+    /// Signed URL (path and query) granting `username` access to `path`
+    /// `path` is the unencoded request path, such as `/media/books/01 Intro.mp3`.
+    pub fn sign(&self, username: &str, path: &str) -> String {
+        let token = base64::prelude::BASE64_URL_SAFE_NO_PAD
+            .encode(self.mac(username, path).finalize().into_bytes());
+        format!(
+            "{}?user={}&token={}",
+            handlers::encode_url_path(path),
+            utf8_percent_encode(username, NON_ALPHANUMERIC),
+            token
+        )
+    }
+
+    /// The user a signed request acts for, if it carries a valid signature
+    /// Only reads of feeds, media files and album art can be signed.
+    pub fn verify(&self, request: &Request) -> Option<String> {
+        if !matches!(*request.method(), Method::GET | Method::HEAD) {
+            return None;
+        }
+        let path = percent_decode_str(request.uri().path()).decode_utf8().ok()?;
+        if !SIGNED_ROUTES.iter().any(|prefix| path.starts_with(prefix)) {
+            return None;
+        }
+
+        let (mut username, mut token) = (None, None);
+        for pair in request.uri().query()?.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode_str(value).decode_utf8().ok()?;
+            match key {
+                "user" => username = Some(value.into_owned()),
+                "token" => token = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        let (username, token) = (username?, token?);
+        let token = base64::prelude::BASE64_URL_SAFE_NO_PAD.decode(token).ok()?;
+
+        // Constant-time comparison
+        self.mac(&username, &path)
+            .verify_slice(&token)
+            .ok()
+            .map(|_| username)
+    }
+}
+
+/// Escape text for XML element content and attribute values
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// One episode of a feed
+struct Episode {
+    path: String,
+    title: String,
+    size: u64,
+    modified: u64,
+    duration: Option<u64>,
+}
+
+/// Handler for /feed/<dir>.rss - a podcast feed of a directory's audio files
+/// Episodes are the audio files directly in the directory, numbered in name order, so
+/// audiobook chapters play in sequence. Enclosures are signed URLs for the requesting user.
+pub async fn feed_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let signer = state.url_signer.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+    let dir = handlers::media_path(path.strip_suffix(".rss").ok_or(AppError::NotFound)?)?;
+
+    let canonical = handlers::validate_media_path(&state.media_dir, &dir)?;
+    if !canonical.is_dir() {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(Some(&principal), &dir) {
+        return Err(AppError::Forbidden);
+    }
+    let host = handlers::request_host(&uri, &headers)?.to_string();
+
+    let mut episodes = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(&canonical)
+        .await
+        .map_err(|_| AppError::InternalError)?;
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || handlers::media_type(&name) != Some("audio") {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        let relative = if dir.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", dir, name)
+        };
+        if !metadata.is_file() || !state.acl.allows(Some(&principal), &relative) {
+            continue;
+        }
+        episodes.push(Episode {
+            path: relative,
+            title: name,
+            size: metadata.len(),
+            modified: handlers::modified_secs(&metadata),
+            duration: None,
+        });
+    }
+    episodes.sort_by(|a, b| a.title.cmp(&b.title));
+
+    // Titles and durations need the files themselves
+    let media_dir = state.media_dir.clone();
+    let episodes = tokio::task::spawn_blocking(move || {
+        for episode in &mut episodes {
+            let file = media_dir.join(&episode.path);
+            episode.duration = metadata::audio_duration(&file).map(|d| d.as_secs());
+            if let Some(title) = metadata::read_audio_tags(&file).and_then(|tags| tags.title) {
+                episode.title = title;
+            } else if let Some((stem, _)) = episode.title.rsplit_once('.') {
+                episode.title = stem.to_string();
+            }
+        }
+        episodes
+    })
+    .await
+    .map_err(|_| AppError::InternalError)?;
+
+    let title = match dir.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => state.branding.title.clone(),
+    };
+    let url = |path: &str| format!("https://{}{}", host, signer.sign(&principal.username, path));
+
+    let mut rss = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">\n",
+        "<channel>\n",
+    ));
+    rss.push_str(&format!("<title>{}</title>\n", xml_escape(&title)));
+    rss.push_str(&format!(
+        "<link>https://{}/music/{}</link>\n",
+        host,
+        xml_escape(&handlers::encode_url_path(&dir))
+    ));
+    rss.push_str(&format!(
+        "<description>{}</description>\n",
+        xml_escape(&format!("Audio files in {}", if dir.is_empty() { "/" } else { &dir }))
+    ));
+    rss.push_str("<itunes:type>serial</itunes:type>\n");
+    if let Some(first) = episodes.first() {
+        rss.push_str(&format!(
+            "<itunes:image href=\"{}\"/>\n",
+            xml_escape(&format!("{}&size=1024", url(&format!("/album-art/{}", first.path))))
+        ));
+    }
+
+    for (number, episode) in episodes.iter().enumerate() {
+        let mime = mime_guess::from_path(&episode.path).first_or_octet_stream();
+        let published = chrono::DateTime::from_timestamp(episode.modified as i64, 0)
+            .unwrap_or_default()
+            .to_rfc2822();
+        rss.push_str("<item>\n");
+        rss.push_str(&format!("<title>{}</title>\n", xml_escape(&episode.title)));
+        rss.push_str(&format!(
+            "<enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n",
+            xml_escape(&url(&format!("/media/{}", episode.path))),
+            episode.size,
+            mime
+        ));
+        rss.push_str(&format!(
+            "<guid isPermaLink=\"false\">{}</guid>\n",
+            xml_escape(&episode.path)
+        ));
+        rss.push_str(&format!("<pubDate>{}</pubDate>\n", published));
+        rss.push_str(&format!("<itunes:episode>{}</itunes:episode>\n", number + 1));
+        if let Some(duration) = episode.duration {
+            rss.push_str(&format!("<itunes:duration>{}</itunes:duration>\n", duration));
+        }
+        rss.push_str("</item>\n");
+    }
+    rss.push_str("</channel>\n</rss>\n");
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        rss,
+    )
+        .into_response())
+}
@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::models::{Chapter, MediaStreams, StreamTrack, VideoInfo};
+
+/// Longest a single ffprobe run may take before it is killed
+/// ffprobe reads little more than the container headers, so this only trips on damaged files.
+const FFPROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct Output {
+    #[serde(default)]
+    streams: Vec<Stream>,
+    #[serde(default)]
+    chapters: Vec<OutputChapter>,
+    format: Option<Format>,
+}
+
+#[derive(Deserialize)]
+struct Stream {
+    index: u32,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    channels: Option<u32>,
+    #[serde(default)]
+    tags: Tags,
+    #[serde(default)]
+    disposition: Disposition,
+}
+
+#[derive(Deserialize, Default)]
+struct Tags {
+    language: Option<String>,
+    title: Option<String>,
+}
+
+/// Stream flags, reported as 0 or 1
+#[derive(Deserialize, Default)]
+struct Disposition {
+    #[serde(default)]
+    default: u8,
+    #[serde(default)]
+    forced: u8,
+    #[serde(default)]
+    attached_pic: u8,
+}
+
+#[derive(Deserialize)]
+struct OutputChapter {
+    start_time: Option<String>,
+    end_time: Option<String>,
+    #[serde(default)]
+    tags: Tags,
+}
+
+/// ffprobe reports numbers in the format section as strings
+#[derive(Deserialize)]
+struct Format {
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+/// Make sure `ffprobe` can be run, so a bad path fails at startup instead of during indexing
+pub fn check(ffprobe: &Path) -> Result<()> {
+    let status = Command::new(ffprobe)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run ffprobe: {:?}", ffprobe))?;
+    anyhow::ensure!(status.success(), "ffprobe {:?} exited with {}", ffprobe, status);
+    Ok(())
+}
+
+/// Read the streams and chapters of a video with ffprobe, or `None` if it can't be probed
+/// Blocks until ffprobe exits; call it from a blocking task.
+pub fn probe(ffprobe: &Path, path: &Path) -> Option<(VideoInfo, MediaStreams)> {
+    let mut child = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+            "-show_chapters",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // Drain stdout on another thread so a large report can't fill the pipe and stall ffprobe
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + FFPROBE_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            _ => {
+                tracing::warn!("ffprobe timed out on {:?}", path);
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    let output = reader.join().ok()?.ok()?;
+    if !status.success() {
+        return None;
+    }
+    let output: Output = serde_json::from_slice(&output).ok()?;
+
+    // Cover art is stored as a video stream too
+    let streams = |kind: &'static str| {
+        output.streams.iter().filter(move |stream| {
+            stream.codec_type.as_deref() == Some(kind) && stream.disposition.attached_pic == 0
+        })
+    };
+    let stream = |kind: &'static str| streams(kind).next();
+    let track = |stream: &Stream| StreamTrack {
+        index: stream.index,
+        codec: stream.codec_name.clone(),
+        language: stream.tags.language.clone().filter(|language| language != "und"),
+        title: stream.tags.title.clone(),
+        channels: stream.channels,
+        default: stream.disposition.default != 0,
+        forced: stream.disposition.forced != 0,
+    };
+    let seconds = |time: &Option<String>| time.as_deref()?.parse::<f64>().ok().filter(|s| s.is_finite());
+    let media_streams = MediaStreams {
+        chapters: output
+            .chapters
+            .iter()
+            .filter_map(|chapter| {
+                Some(Chapter {
+                    start: seconds(&chapter.start_time)?,
+                    end: seconds(&chapter.end_time)?,
+                    title: chapter.tags.title.clone(),
+                })
+            })
+            .collect(),
+        audio_tracks: streams("audio").map(track).collect(),
+        subtitle_tracks: streams("subtitle").map(track).collect(),
+    };
+
+    let video = stream("video")?;
+    let format = output.format.as_ref();
+    let info = VideoInfo {
+        width: video.width,
+        height: video.height,
+        duration: format
+            .and_then(|f| f.duration.as_deref()?.parse::<f64>().ok())
+            .filter(|seconds| seconds.is_finite() && *seconds > 0.0),
+        bitrate: format
+            .and_then(|f| f.bit_rate.as_deref()?.parse::<u64>().ok())
+            .map(|bps| (bps / 1000) as u32)
+            .filter(|&kbps| kbps > 0),
+        video_codec: video.codec_name.clone(),
+        audio_codec: stream("audio").and_then(|audio| audio.codec_name.clone()),
+    };
+    Some((info, media_streams))
+}
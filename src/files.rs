@@ -0,0 +1,468 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    Extension,
+};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::{Principal, Role};
+use crate::handlers::{self, AppError, AppState};
+use crate::index::MediaIndex;
+
+/// Index tables keyed by media path, whose rows follow a moved file or directory
+const PATH_TABLES: &[&str] = &[
+    "files",
+    "waveforms",
+    "tags",
+    "favorites",
+    "ratings",
+    "history",
+    "playlist_tracks",
+    "queue_tracks",
+];
+
+/// Characters refused in new file and directory names; reserved on Windows or by URLs
+const RESERVED_CHARACTERS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Whether a new file or directory may be given this name
+/// Names can't be hidden (leading dot), contain path separators, reserved or control
+/// characters, or end in a space or dot, which some filesystems drop silently.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && !name.starts_with('.')
+        && !name.ends_with([' ', '.'])
+        && !name.chars().any(|c| c.is_control() || RESERVED_CHARACTERS.contains(&c))
+}
+
+/// A directory to create
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NewDirectory {
+    /// Directory path relative to the media directory; its parent must exist
+    pub path: String,
+}
+
+/// Query parameters of file deletion
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct DeleteQuery {
+    /// Delete a directory along with everything in it (default false: only empty directories)
+    #[serde(default)]
+    pub recursive: bool,
+    /// Skip the trash and delete for good (default false)
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+/// A file or directory to move or rename
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MoveRequest {
+    /// Current path relative to the media directory
+    pub from: String,
+    /// New path relative to the media directory; its parent directory must exist
+    pub to: String,
+    /// Replace a file already at `to` (default false)
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Where a file or directory was moved
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MoveResponse {
+    pub from: String,
+    pub to: String,
+}
+
+impl MediaIndex {
+    /// Point indexed metadata, tags, favorites, ratings, history, playlists and queues at
+    /// the new path of a moved file or directory
+    pub async fn move_path(&self, from: String, to: String) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            for table in PATH_TABLES {
+                // Rows already at the destination, e.g. from an overwritten file, are replaced
+                tx.execute(
+                    &format!(
+                        "UPDATE OR REPLACE {table} SET path = ?2 || substr(path, length(?1) + 1)
+                         WHERE path = ?1 OR substr(path, 1, length(?1) + 1) = ?1 || '/'"
+                    ),
+                    params![from, to],
+                )?;
+            }
+            tx.execute(
+                "UPDATE files SET name = ?2 WHERE path = ?1",
+                params![to, to.rsplit('/').next().unwrap_or(&to)],
+            )?;
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Forget the indexed metadata of a deleted file or directory
+    /// Tags, favorites and other user data are kept, as when files disappear from disk.
+    pub async fn remove_path(&self, path: String) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            for table in ["files", "waveforms"] {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {table} WHERE path = ?1 OR substr(path, 1, length(?1) + 1) = ?1 || '/'"
+                    ),
+                    [&path],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+    }
+}
+
+/// Location of an existing path in the media directory, without following a final symlink
+/// Deleting or moving a symlink must affect the link, not its target.
+async fn resolve(media_dir: &std::path::Path, path: &str) -> Result<(PathBuf, std::fs::Metadata), AppError> {
+    let path = handlers::media_path(path)?;
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    if name.is_empty() {
+        return Err(AppError::InvalidPath);
+    }
+    let location = handlers::validate_media_path(media_dir, dir)?.join(name);
+    let metadata = tokio::fs::symlink_metadata(&location)
+        .await
+        .map_err(|_| AppError::NotFound)?;
+    Ok((location, metadata))
+}
+
+/// Only admins may change files, on top of the role middleware refusing other roles
+pub fn require_write_access<'a>(state: &AppState, principal: Option<&'a Principal>) -> Result<&'a Principal, AppError> {
+    if !state.write_ops {
+        return Err(AppError::NotFound);
+    }
+    match principal {
+        Some(principal) if principal.role == Role::Admin => Ok(principal),
+        _ => Err(AppError::Forbidden),
+    }
+}
+
+/// Create a directory
+/// Only the last path component is created; its parent must exist. Needs `--enable-uploads`
+/// or `--enable-write-ops`, and the `admin` or `uploader` role.
+#[utoipa::path(
+    post,
+    path = "/api/mkdir",
+    request_body = NewDirectory,
+    responses(
+        (status = 201, description = "Directory created", body = NewDirectory),
+        (status = 400, description = "Invalid directory name"),
+        (status = 403, description = "Role not permitted, or path not accessible"),
+        (status = 404, description = "Parent directory not found, or uploads and write operations disabled"),
+        (status = 409, description = "Path already exists")
+    ),
+    tag = "media"
+)]
+pub async fn mkdir_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<NewDirectory>,
+) -> Result<(StatusCode, Json<NewDirectory>), AppError> {
+    if state.upload_limit.is_none() && !state.write_ops {
+        return Err(AppError::NotFound);
+    }
+    let Some(Extension(principal)) = principal else {
+        return Err(AppError::Forbidden);
+    };
+    if !matches!(principal.role, Role::Admin | Role::Uploader) {
+        return Err(AppError::Forbidden);
+    }
+    let path = handlers::media_path(&request.path)?;
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    if !is_valid_name(name) {
+        return Err(AppError::InvalidInput("Invalid directory name"));
+    }
+    if !state.acl.allows(Some(&principal), &path) {
+        return Err(AppError::Forbidden);
+    }
+    let parent = handlers::validate_media_path(&state.media_dir, dir)?;
+    if !parent.is_dir() {
+        return Err(AppError::NotFound);
+    }
+
+    match tokio::fs::create_dir(parent.join(name)).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(AppError::Conflict("Path already exists"));
+        }
+        Err(e) => {
+            tracing::error!("Failed to create directory {}: {}", path, e);
+            return Err(AppError::InternalError);
+        }
+    }
+    tracing::info!(username = %principal.username, path = %path, "Directory created");
+    Ok((StatusCode::CREATED, Json(NewDirectory { path })))
+}
+
+/// Check a deletion before touching the disk, returning the normalized path
+pub fn check_delete(state: &AppState, principal: &Principal, path: &str) -> Result<String, AppError> {
+    let path = delete_path(path)?;
+    if !state.acl.allows(Some(principal), &path) {
+        return Err(AppError::Forbidden);
+    }
+    Ok(path)
+}
+
+/// Normalized path of a deletion, which can't be the media directory itself
+fn delete_path(path: &str) -> Result<String, AppError> {
+    let path = handlers::media_path(path)?;
+    if path.is_empty() {
+        return Err(AppError::InvalidInput("Cannot delete the media directory"));
+    }
+    Ok(path)
+}
+
+/// Delete a checked path, or move it to the trash, and drop it from the cache and index
+pub async fn delete_entry(
+    state: &AppState,
+    principal: &Principal,
+    path: &str,
+    recursive: bool,
+    permanent: bool,
+) -> Result<(), AppError> {
+    let (location, metadata) = resolve(&state.media_dir, path).await?;
+    let trash = state.trash.as_deref().filter(|_| !permanent);
+    let result = if let Some(trash) = trash {
+        if metadata.is_dir() && !recursive && !is_empty_dir(&location).await {
+            return Err(AppError::Conflict("Directory not empty"));
+        }
+        trash.put(&location, path, &principal.username).await?;
+        Ok(())
+    } else if metadata.is_dir() {
+        if recursive {
+            tokio::fs::remove_dir_all(&location).await
+        } else {
+            tokio::fs::remove_dir(&location).await
+        }
+    } else {
+        tokio::fs::remove_file(&location).await
+    };
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => {
+            return Err(AppError::Conflict("Directory not empty"));
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete {:?}: {}", location, e);
+            return Err(AppError::InternalError);
+        }
+    }
+
+    if let Some(index) = &state.index {
+        if let Err(e) = index.remove_path(path.to_string()).await {
+            tracing::warn!("Failed to remove index entries of {}: {:?}", path, e);
+        }
+    }
+    state.media_cache.write().await.remove(path);
+    tracing::info!(username = %principal.username, path = %path, trashed = trash.is_some(), "Deleted");
+    Ok(())
+}
+
+async fn is_empty_dir(dir: &std::path::Path) -> bool {
+    match tokio::fs::read_dir(dir).await {
+        Ok(mut entries) => matches!(entries.next_entry().await, Ok(None)),
+        Err(_) => false,
+    }
+}
+
+/// Check a move before touching the disk, returning the normalized source and destination
+pub fn check_move(state: &AppState, principal: &Principal, from: &str, to: &str) -> Result<(String, String), AppError> {
+    let (from, to) = move_paths(from, to)?;
+    if !state.acl.allows(Some(principal), &from) || !state.acl.allows(Some(principal), &to) {
+        return Err(AppError::Forbidden);
+    }
+    Ok((from, to))
+}
+
+/// Normalized source and destination of a move, which can't move the media directory or a
+/// directory into itself
+fn move_paths(from: &str, to: &str) -> Result<(String, String), AppError> {
+    let from = handlers::media_path(from)?;
+    let to = handlers::media_path(to)?;
+    if from.is_empty() || to.is_empty() {
+        return Err(AppError::InvalidInput("Cannot move the media directory"));
+    }
+    if to == from || to.starts_with(&format!("{}/", from)) {
+        return Err(AppError::InvalidInput("Cannot move a directory into itself"));
+    }
+    let to_name = to.rsplit('/').next().unwrap_or(&to);
+    if !is_valid_name(to_name) {
+        return Err(AppError::InvalidInput("Invalid file name"));
+    }
+    Ok((from, to))
+}
+
+/// Whether a file keeps its media type under a new name
+/// Other files keep their extension, so a rename can't turn them into media either.
+fn keeps_type(from: &str, to_name: &str) -> bool {
+    match handlers::media_type(from) {
+        Some(file_type) => handlers::media_type(to_name) == Some(file_type),
+        None => {
+            let extension = |name: &str| name.rsplit_once('.').map(|(_, extension)| extension.to_lowercase());
+            let from_name = from.rsplit('/').next().unwrap_or(from);
+            handlers::media_type(to_name).is_none() && extension(from_name) == extension(to_name)
+        }
+    }
+}
+
+/// Move a checked path and carry its cache and index entries along
+/// Moves within one filesystem only; a destination on another one is an invalid input.
+pub async fn move_entry(
+    state: &AppState,
+    principal: &Principal,
+    from: &str,
+    to: &str,
+    overwrite: bool,
+) -> Result<(), AppError> {
+    let (source, metadata) = resolve(&state.media_dir, from).await?;
+    let (to_dir, to_name) = to.rsplit_once('/').unwrap_or(("", to));
+    let parent = handlers::validate_media_path(&state.media_dir, to_dir)?;
+    if !parent.is_dir() {
+        return Err(AppError::NotFound);
+    }
+    let destination = parent.join(to_name);
+    if !metadata.is_dir() && !keeps_type(from, to_name) {
+        return Err(AppError::InvalidInput("Destination must keep the file's media type"));
+    }
+    match tokio::fs::symlink_metadata(&destination).await {
+        Ok(existing) if existing.is_file() && metadata.is_file() && overwrite => {}
+        Ok(_) => return Err(AppError::Conflict("Destination exists")),
+        Err(_) => {}
+    }
+
+    tokio::fs::rename(&source, &destination).await.map_err(|e| match e.kind() {
+        std::io::ErrorKind::CrossesDevices => AppError::InvalidInput("Destination is on another filesystem"),
+        _ => {
+            tracing::error!("Failed to move {:?} to {:?}: {}", source, destination, e);
+            AppError::InternalError
+        }
+    })?;
+
+    if let Some(index) = &state.index {
+        if let Err(e) = index.move_path(from.to_string(), to.to_string()).await {
+            tracing::warn!("Failed to move index entries of {}: {:?}", from, e);
+        }
+    }
+    {
+        let mut cache = state.media_cache.write().await;
+        cache.remove(to);
+        cache.rename(from, to);
+    }
+    tracing::info!(username = %principal.username, from = %from, to = %to, "Moved");
+    Ok(())
+}
+
+/// Delete a file or directory
+/// Directories must be empty unless `recursive` is set. Deleted items go to the trash unless
+/// it is disabled or `permanent` is set. Needs `--enable-write-ops` and the `admin` role.
+#[utoipa::path(
+    delete,
+    path = "/api/files/{path}",
+    params(
+        ("path" = String, Path, description = "File or directory path relative to the media directory"),
+        DeleteQuery
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 400, description = "Path is the media directory itself"),
+        (status = 403, description = "Not an admin, or path not accessible"),
+        (status = 404, description = "Path not found, or write operations disabled"),
+        (status = 409, description = "Directory not empty and `recursive` not set")
+    ),
+    tag = "media"
+)]
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<StatusCode, AppError> {
+    let principal = require_write_access(&state, principal.as_deref())?;
+    let path = check_delete(&state, principal, &path)?;
+    delete_entry(&state, principal, &path, query.recursive, query.permanent).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Move or rename a file or directory
+/// The destination's parent directory must exist. Files keep their media type, so a move
+/// can't turn a photo into something the server refuses to serve. Tags, favorites,
+/// ratings, history, playlists and queues follow the move. Needs `--enable-write-ops` and
+/// the `admin` role.
+#[utoipa::path(
+    post,
+    path = "/api/files/move",
+    request_body = MoveRequest,
+    responses(
+        (status = 200, description = "Moved", body = MoveResponse),
+        (status = 400, description = "Invalid destination, or on another filesystem"),
+        (status = 403, description = "Not an admin, or a path not accessible"),
+        (status = 404, description = "Source or destination directory not found, or write operations disabled"),
+        (status = 409, description = "Destination exists")
+    ),
+    tag = "media"
+)]
+pub async fn move_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<MoveRequest>,
+) -> Result<Json<MoveResponse>, AppError> {
+    let principal = require_write_access(&state, principal.as_deref())?;
+    let (from, to) = check_move(&state, principal, &request.from, &request.to)?;
+    move_entry(&state, principal, &from, &to, request.overwrite).await?;
+    Ok(Json(MoveResponse { from, to }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delete_path_is_normalized() {
+        assert_eq!(delete_path("/a/../b/").ok().as_deref(), Some("b"));
+        assert!(matches!(delete_path("a/.."), Err(AppError::InvalidInput(_))));
+        assert!(matches!(delete_path("../b"), Err(AppError::InvalidPath)));
+    }
+
+    #[test]
+    fn move_paths_are_compared_normalized() {
+        let (from, to) = move_paths("a/../b", "c/./d").ok().unwrap();
+        assert_eq!((from.as_str(), to.as_str()), ("b", "c/d"));
+        assert!(matches!(move_paths("a", "b/../a/sub"), Err(AppError::InvalidInput(_))));
+        assert!(matches!(move_paths("a/x/..", "a"), Err(AppError::InvalidInput(_))));
+        assert!(matches!(move_paths("a", "../b"), Err(AppError::InvalidPath)));
+    }
+
+    #[test]
+    fn renamed_files_keep_their_type() {
+        assert!(keeps_type("a/cat.jpg", "dog.PNG"));
+        assert!(!keeps_type("a/cat.jpg", "cat.mp4"));
+        assert!(keeps_type("a/notes.txt", "todo.TXT"));
+        assert!(!keeps_type("a/notes.txt", "notes.jpg"));
+        assert!(!keeps_type("a/notes.txt", "notes.html"));
+        assert!(!keeps_type("a/README", "index.html"));
+    }
+
+    #[tokio::test]
+    async fn encoded_parent_directory_is_a_name() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("public")).unwrap();
+        std::fs::create_dir_all(dir.path().join("private")).unwrap();
+        let media_dir = dir.path().canonicalize().unwrap();
+
+        let (from, _) = move_paths("public/%2e%2e/private", "elsewhere").ok().unwrap();
+        assert_eq!(from, "public/%2e%2e/private");
+        assert!(matches!(resolve(&media_dir, &from).await, Err(AppError::NotFound)));
+
+        let (location, _) = resolve(&media_dir, "public/../private").await.ok().unwrap();
+        assert_eq!(location, media_dir.join("private"));
+    }
+}
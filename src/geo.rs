@@ -0,0 +1,288 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::{Html, Json},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::constants;
+use crate::handlers::{AppError, AppState, FilterResult};
+use crate::i18n::Language;
+use crate::index::MediaIndex;
+use crate::templates::MapTemplate;
+
+/// Cells across the bounding box that photos are clustered into
+const DEFAULT_GRID: u32 = 32;
+const MAX_GRID: u32 = 256;
+
+/// A geotagged photo
+#[derive(Debug, Clone)]
+pub struct GeoPhoto {
+    pub file: FilterResult,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Nearby photos drawn as one marker
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GeoCluster {
+    /// Mean position of the photos
+    pub latitude: f64,
+    pub longitude: f64,
+    pub count: usize,
+    /// The most recently modified photo, for a preview thumbnail
+    pub path: String,
+    /// Extent of the photos as `[west, south, east, north]`, to zoom in on
+    pub bbox: [f64; 4],
+}
+
+/// Clustered photo positions within a bounding box
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GeoResponse {
+    /// Geotagged photos in the bounding box
+    pub total: usize,
+    pub clusters: Vec<GeoCluster>,
+}
+
+/// Area to cluster photos in
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct GeoQuery {
+    /// `west,south,east,north` in decimal degrees; the whole world if omitted.
+    /// West may be greater than east for a box crossing the antimeridian.
+    pub bbox: Option<String>,
+    /// Cells across the box that photos are clustered into (default 32, max 256)
+    pub grid: Option<u32>,
+}
+
+/// A validated `west,south,east,north` box
+#[derive(Debug, Clone, Copy)]
+struct BoundingBox {
+    west: f64,
+    south: f64,
+    east: f64,
+    north: f64,
+}
+
+impl BoundingBox {
+    const WORLD: BoundingBox = BoundingBox {
+        west: -180.0,
+        south: -90.0,
+        east: 180.0,
+        north: 90.0,
+    };
+
+    fn parse(value: &str) -> Option<Self> {
+        let parts: Vec<f64> = value
+            .split(',')
+            .map(|part| part.trim().parse().ok().filter(|v: &f64| v.is_finite()))
+            .collect::<Option<_>>()?;
+        let [west, south, east, north] = parts[..] else {
+            return None;
+        };
+        let valid = (-180.0..=180.0).contains(&west)
+            && (-180.0..=180.0).contains(&east)
+            && (-90.0..=90.0).contains(&south)
+            && (-90.0..=90.0).contains(&north)
+            && south <= north;
+        valid.then_some(Self { west, south, east, north })
+    }
+
+    fn width(&self) -> f64 {
+        if self.west <= self.east {
+            self.east - self.west
+        } else {
+            self.east + 360.0 - self.west
+        }
+    }
+
+    /// Degrees east of the west edge, if the longitude is inside the box
+    fn offset(&self, longitude: f64) -> Option<f64> {
+        let offset = if longitude >= self.west {
+            longitude - self.west
+        } else {
+            longitude + 360.0 - self.west
+        };
+        (offset <= self.width()).then_some(offset)
+    }
+}
+
+impl MediaIndex {
+    /// Geotagged images between two latitudes
+    pub async fn geotagged_photos(&self, south: f64, north: f64) -> anyhow::Result<Vec<GeoPhoto>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT path, name, size, file_type, mtime, latitude, longitude
+                 FROM files WHERE latitude BETWEEN ?1 AND ?2 AND longitude IS NOT NULL",
+            )?;
+            let rows = stmt.query_map([south, north], |row| {
+                Ok(GeoPhoto {
+                    file: FilterResult {
+                        path: row.get(0)?,
+                        name: row.get(1)?,
+                        size: row.get(2)?,
+                        file_type: row.get(3)?,
+                        modified: row.get::<_, i64>(4)?.max(0) as u64,
+                    },
+                    latitude: row.get(5)?,
+                    longitude: row.get(6)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+}
+
+struct Cell {
+    count: usize,
+    latitude_sum: f64,
+    offset_sum: f64,
+    newest: GeoPhoto,
+    /// `[min offset, south, max offset, north]`, with longitudes as offsets from the west edge
+    extent: [f64; 4],
+}
+
+/// Group photos into a grid over the box; cells are square in degrees
+fn cluster(photos: Vec<GeoPhoto>, bbox: BoundingBox, grid: u32) -> Vec<GeoCluster> {
+    let cell_size = (bbox.width().max(bbox.north - bbox.south) / grid as f64).max(1e-6);
+    let mut cells: HashMap<(i64, i64), Cell> = HashMap::new();
+    for photo in photos {
+        let Some(offset) = bbox.offset(photo.longitude) else {
+            continue;
+        };
+        let key = (
+            (offset / cell_size) as i64,
+            ((photo.latitude - bbox.south) / cell_size) as i64,
+        );
+        match cells.get_mut(&key) {
+            Some(cell) => {
+                cell.count += 1;
+                cell.latitude_sum += photo.latitude;
+                cell.offset_sum += offset;
+                cell.extent = [
+                    cell.extent[0].min(offset),
+                    cell.extent[1].min(photo.latitude),
+                    cell.extent[2].max(offset),
+                    cell.extent[3].max(photo.latitude),
+                ];
+                if (photo.file.modified, &photo.file.path) > (cell.newest.file.modified, &cell.newest.file.path) {
+                    cell.newest = photo;
+                }
+            }
+            None => {
+                cells.insert(
+                    key,
+                    Cell {
+                        count: 1,
+                        latitude_sum: photo.latitude,
+                        offset_sum: offset,
+                        extent: [offset, photo.latitude, offset, photo.latitude],
+                        newest: photo,
+                    },
+                );
+            }
+        }
+    }
+
+    let longitude = |offset: f64| {
+        let longitude = bbox.west + offset;
+        if longitude > 180.0 {
+            longitude - 360.0
+        } else {
+            longitude
+        }
+    };
+    let mut clusters: Vec<GeoCluster> = cells
+        .into_values()
+        .map(|cell| match cell.count {
+            1 => GeoCluster {
+                latitude: cell.newest.latitude,
+                longitude: cell.newest.longitude,
+                count: 1,
+                bbox: [
+                    cell.newest.longitude,
+                    cell.newest.latitude,
+                    cell.newest.longitude,
+                    cell.newest.latitude,
+                ],
+                path: cell.newest.file.path,
+            },
+            _ => GeoCluster {
+                latitude: cell.latitude_sum / cell.count as f64,
+                longitude: longitude(cell.offset_sum / cell.count as f64),
+                count: cell.count,
+                path: cell.newest.file.path,
+                bbox: [
+                    longitude(cell.extent[0]),
+                    cell.extent[1],
+                    longitude(cell.extent[2]),
+                    cell.extent[3],
+                ],
+            },
+        })
+        .collect();
+    // Largest first, so clients draw small markers on top
+    clusters.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+    clusters
+}
+
+/// Geotagged photos in a bounding box, clustered into a grid
+/// Positions come from EXIF GPS tags read by the index. Zoom in by requesting a smaller
+/// `bbox`, such as a cluster's own `bbox`, until clusters split into single photos.
+#[utoipa::path(
+    get,
+    path = "/api/geo",
+    params(GeoQuery),
+    responses(
+        (status = 200, description = "Photo clusters, largest first", body = GeoResponse),
+        (status = 400, description = "Invalid bounding box"),
+        (status = 404, description = "No index database configured")
+    ),
+    tag = "media"
+)]
+pub async fn geo_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<GeoQuery>,
+) -> Result<Json<GeoResponse>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let bbox = match &query.bbox {
+        Some(value) => BoundingBox::parse(value).ok_or(AppError::InvalidInput("Invalid bbox"))?,
+        None => BoundingBox::WORLD,
+    };
+    let grid = query.grid.unwrap_or(DEFAULT_GRID).clamp(1, MAX_GRID);
+
+    let photos = index.geotagged_photos(bbox.south, bbox.north).await.map_err(|e| {
+        tracing::error!("{:?}", e);
+        AppError::InternalError
+    })?;
+    let photos: Vec<GeoPhoto> = photos
+        .into_iter()
+        .filter(|photo| bbox.offset(photo.longitude).is_some())
+        .filter(|photo| state.acl.allows(principal.as_deref(), &photo.file.path))
+        .collect();
+    let total = photos.len();
+    Ok(Json(GeoResponse {
+        total,
+        clusters: cluster(photos, bbox, grid),
+    }))
+}
+
+/// Handler for /map - geotagged photos on a world map
+pub async fn map_page_handler(State(state): State<AppState>) -> Result<Html<String>, AppError> {
+    if state.index.is_none() {
+        return Err(AppError::NotFound);
+    }
+    let template = MapTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
+        emoji_prefix: constants::EMOJI_PREFIX,
+        indexing: !state.scan.is_ready(),
+        casting: state.cast.is_some(),
+    };
+    Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
+}
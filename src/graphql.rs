@@ -0,0 +1,478 @@
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Enum, InputObject, Object, Schema, SimpleObject,
+};
+use axum::{
+    extract::{Query, State},
+    response::Json,
+    Extension,
+};
+
+use crate::auth::Principal;
+use crate::favorites;
+use crate::handlers::{self, AppError, AppState, FilterQuery, FilterResult, SortField, SortOrder};
+use crate::models::{DirectoryEntry, EntryType};
+use crate::playlists::{self, PlaylistSummary};
+use crate::ratings;
+use crate::sidecar::DirectoryMeta;
+use crate::tags::{self, TagCount};
+
+/// Deepest query accepted
+const MAX_DEPTH: usize = 10;
+
+/// Most expensive query accepted, counting one per field
+const MAX_COMPLEXITY: usize = 20_000;
+
+/// Most entries returned per page
+const MAX_PAGE_SIZE: usize = 500;
+
+pub type GraphQLSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Build the read-only library schema
+pub fn schema() -> GraphQLSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+fn error(e: AppError) -> async_graphql::Error {
+    async_graphql::Error::new(e.parts().1)
+}
+
+/// What a directory entry is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum EntryKind {
+    Directory,
+    Image,
+    Video,
+    Audio,
+    /// An archive of audio files
+    Archive,
+    /// An `.m3u`, `.m3u8` or `.pls` playlist file
+    Playlist,
+}
+
+impl From<&EntryType> for EntryKind {
+    fn from(entry_type: &EntryType) -> Self {
+        match entry_type {
+            EntryType::Directory => EntryKind::Directory,
+            EntryType::Image => EntryKind::Image,
+            EntryType::Video => EntryKind::Video,
+            EntryType::Audio => EntryKind::Audio,
+            EntryType::Archive => EntryKind::Archive,
+            EntryType::Playlist => EntryKind::Playlist,
+        }
+    }
+}
+
+/// A file or directory
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct Entry {
+    pub name: String,
+    /// Path relative to the media directory
+    pub path: String,
+    pub kind: EntryKind,
+    /// Size in bytes
+    pub size: u64,
+    /// Last modification time (Unix seconds), where known
+    pub modified: Option<u64>,
+    /// Tags set directly on this entry (with an index database)
+    pub tags: Vec<String>,
+    /// Whether the signed-in user marked this file as a favorite
+    pub favorite: bool,
+    /// The signed-in user's star rating
+    pub rating: Option<u8>,
+    /// How well the name matches the `name` filter, in `files` results
+    pub score: Option<i64>,
+}
+
+#[ComplexObject]
+impl Entry {
+    /// Everything the media index knows about the file, as in `GET /api/metadata/{path}` (with an index database)
+    async fn metadata(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<async_graphql::Json<serde_json::Value>>> {
+        let state = ctx.data::<AppState>()?;
+        let Some(index) = &state.index else {
+            return Ok(None);
+        };
+        if self.kind == EntryKind::Directory {
+            return Ok(None);
+        }
+        let info = index.media_info(self.path.clone()).await.map_err(|e| {
+            tracing::error!("Failed to read media info: {:?}", e);
+            error(AppError::InternalError)
+        })?;
+        Ok(info
+            .and_then(|info| serde_json::to_value(info).ok())
+            .map(async_graphql::Json))
+    }
+}
+
+impl Entry {
+    fn from_file(file: FilterResult, rating: Option<u8>, score: Option<i64>) -> Self {
+        let kind = match file.file_type.as_str() {
+            "image" => EntryKind::Image,
+            "video" => EntryKind::Video,
+            _ => EntryKind::Audio,
+        };
+        Entry {
+            name: file.name,
+            path: file.path,
+            kind,
+            size: file.size,
+            modified: Some(file.modified),
+            tags: Vec::new(),
+            favorite: false,
+            rating,
+            score,
+        }
+    }
+}
+
+/// Fill in tags, favorites and ratings, reusing the directory listing lookups
+async fn annotate(state: &AppState, principal: Option<&Principal>, entries: Vec<Entry>) -> Vec<Entry> {
+    let mut listing: Vec<DirectoryEntry> = entries
+        .iter()
+        .map(|entry| DirectoryEntry {
+            name: entry.name.clone(),
+            path: entry.path.clone(),
+            entry_type: EntryType::Directory,
+            size: entry.size,
+            modified: None,
+            tags: Vec::new(),
+            favorite: false,
+            rating: None,
+            audio: None,
+            video: None,
+            title: None,
+            cover: None,
+        })
+        .collect();
+    tags::attach_tags(state, &mut listing).await;
+    favorites::attach_favorites(state, principal, &mut listing).await;
+    ratings::attach_ratings(state, principal, &mut listing).await;
+    entries
+        .into_iter()
+        .zip(listing)
+        .map(|(entry, listed)| Entry {
+            tags: listed.tags,
+            favorite: listed.favorite,
+            rating: listed.rating,
+            ..entry
+        })
+        .collect()
+}
+
+/// A page of a directory listing
+#[derive(Debug, SimpleObject)]
+pub struct Directory {
+    /// Path relative to the media directory; empty for the media directory itself
+    pub path: String,
+    /// Parent directory, unless this is the media directory
+    pub parent: Option<String>,
+    /// Title from the directory's `.doggygallery.toml`
+    pub title: Option<String>,
+    /// Description from the directory's `.doggygallery.toml`
+    pub description: Option<String>,
+    /// Entries in the directory, before paging
+    pub total: usize,
+    /// Subdirectories first, then files, each by name
+    pub entries: Vec<Entry>,
+}
+
+/// A page of `files` results
+#[derive(Debug, SimpleObject)]
+pub struct FilePage {
+    /// Matching files, before paging
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub total_pages: usize,
+    pub has_next: bool,
+    pub items: Vec<Entry>,
+}
+
+/// A playlist with its tracks in order
+#[derive(Debug, SimpleObject)]
+pub struct Playlist {
+    pub id: i64,
+    pub name: String,
+    /// When the playlist was created (RFC 3339)
+    pub created_at: String,
+    /// When the playlist was last changed (RFC 3339)
+    pub updated_at: String,
+    pub tracks: Vec<Entry>,
+}
+
+/// Filters of the `files` query, as for `GET /api/filter`
+#[derive(Debug, Default, InputObject)]
+pub struct FileFilter {
+    /// image, video or audio
+    pub file_type: Option<String>,
+    /// File extension, e.g. `.jpg`
+    pub extension: Option<String>,
+    /// Fuzzy match on the file name
+    pub name: Option<String>,
+    /// Minimum size; KB, MB, GB and TB suffixes are accepted
+    pub min_size: Option<String>,
+    /// Maximum size; KB, MB, GB and TB suffixes are accepted
+    pub max_size: Option<String>,
+    /// Modified at or after this time (RFC 3339, YYYY-MM-DD or Unix seconds)
+    pub modified_after: Option<String>,
+    /// Modified before this time (RFC 3339, YYYY-MM-DD or Unix seconds)
+    pub modified_before: Option<String>,
+    /// Path prefix, e.g. `photos/2024`
+    pub path_prefix: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    /// Only files with all of these tags, on the file or a parent directory
+    pub tags: Option<Vec<String>>,
+    /// Only the signed-in user's favorites
+    pub favorites: Option<bool>,
+    pub min_rating: Option<u8>,
+    pub max_rating: Option<u8>,
+}
+
+impl FileFilter {
+    fn into_query(self, page: usize, per_page: usize, sort: Option<SortField>, order: Option<SortOrder>) -> async_graphql::Result<FilterQuery> {
+        fn parse(value: Option<String>, parse: fn(&str) -> Option<u64>, what: &str) -> async_graphql::Result<Option<u64>> {
+            value
+                .map(|value| parse(&value).ok_or_else(|| async_graphql::Error::new(format!("Invalid {}: {:?}", what, value))))
+                .transpose()
+        }
+        Ok(FilterQuery {
+            file_type: self.file_type,
+            extension: self.extension,
+            name: self.name,
+            page: Some(page),
+            per_page: Some(per_page),
+            min_size: parse(self.min_size, handlers::parse_size, "size")?,
+            max_size: parse(self.max_size, handlers::parse_size, "size")?,
+            modified_after: parse(self.modified_after, handlers::parse_time, "time")?,
+            modified_before: parse(self.modified_before, handlers::parse_time, "time")?,
+            path_prefix: self.path_prefix,
+            artist: self.artist,
+            album: self.album,
+            title: self.title,
+            genre: self.genre,
+            tag: self.tags.map(|tags| tags.join(",")),
+            favorites: self.favorites,
+            min_rating: self.min_rating,
+            max_rating: self.max_rating,
+            sort,
+            order,
+            fresh: false,
+        })
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// List a directory, a page at a time
+    async fn directory(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] path: String,
+        #[graphql(default = 1)] page: usize,
+        #[graphql(default = 100)] per_page: usize,
+    ) -> async_graphql::Result<Directory> {
+        let state = ctx.data::<AppState>()?;
+        let principal = ctx.data_opt::<Principal>();
+        let path = handlers::media_path(&path).map_err(error)?;
+        if !path.is_empty() && !state.acl.allows(principal, &path) {
+            return Err(error(AppError::Forbidden));
+        }
+        let dir = handlers::validate_media_path(&state.media_dir, &path).map_err(error)?;
+        if !dir.is_dir() {
+            return Err(error(AppError::NotFound));
+        }
+
+        let listed = handlers::read_directory(state, principal, &dir, &path).await.map_err(error)?;
+        let meta = DirectoryMeta::load(&dir).await;
+        let total = listed.len();
+        let per_page = per_page.clamp(1, MAX_PAGE_SIZE);
+        let entries = listed
+            .into_iter()
+            .skip(page.max(1).saturating_sub(1) * per_page)
+            .take(per_page)
+            .map(|entry| Entry {
+                kind: EntryKind::from(&entry.entry_type),
+                name: entry.name,
+                path: entry.path,
+                size: entry.size,
+                modified: None,
+                tags: Vec::new(),
+                favorite: false,
+                rating: None,
+                score: None,
+            })
+            .collect();
+
+        Ok(Directory {
+            parent: (!path.is_empty()).then(|| path.rsplit_once('/').map_or("", |(parent, _)| parent).to_string()),
+            path,
+            title: meta.title,
+            description: meta.description,
+            total,
+            entries: annotate(state, principal, entries).await,
+        })
+    }
+
+    /// Search and filter media files across the library, as `GET /api/filter` does
+    async fn files(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: FileFilter,
+        sort: Option<SortField>,
+        order: Option<SortOrder>,
+        #[graphql(default = 1)] page: usize,
+        #[graphql(default = 50)] per_page: usize,
+    ) -> async_graphql::Result<FilePage> {
+        let state = ctx.data::<AppState>()?;
+        let principal = ctx.data_opt::<Principal>();
+        let query = filter.into_query(page, per_page.clamp(1, MAX_PAGE_SIZE), sort, order)?;
+        let Json(response) = handlers::filter_handler(
+            State(state.clone()),
+            principal.cloned().map(Extension),
+            Query(query),
+        )
+        .await
+        .map_err(error)?;
+
+        let items = response
+            .results
+            .into_iter()
+            .map(|result| Entry::from_file(result.file, result.rating, result.score))
+            .collect();
+        Ok(FilePage {
+            total: response.total,
+            page: response.page,
+            per_page: response.per_page,
+            total_pages: response.total_pages,
+            has_next: response.has_next,
+            items: annotate(state, principal, items).await,
+        })
+    }
+
+    /// One file or directory
+    async fn entry(&self, ctx: &Context<'_>, path: String) -> async_graphql::Result<Entry> {
+        let state = ctx.data::<AppState>()?;
+        let principal = ctx.data_opt::<Principal>();
+        let path = handlers::media_path(&path).map_err(error)?;
+        if !state.acl.allows(principal, &path) {
+            return Err(error(AppError::Forbidden));
+        }
+        let location = handlers::validate_media_path(&state.media_dir, &path).map_err(error)?;
+        let metadata = tokio::fs::metadata(&location)
+            .await
+            .map_err(|_| error(AppError::NotFound))?;
+        let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+        let kind = if metadata.is_dir() {
+            EntryKind::Directory
+        } else {
+            match handlers::media_type(&name) {
+                Some("image") => EntryKind::Image,
+                Some("video") => EntryKind::Video,
+                Some(_) => EntryKind::Audio,
+                None => return Err(error(AppError::NotFound)),
+            }
+        };
+        let entry = Entry {
+            name,
+            path,
+            kind,
+            size: metadata.len(),
+            modified: Some(handlers::modified_secs(&metadata)),
+            tags: Vec::new(),
+            favorite: false,
+            rating: None,
+            score: None,
+        };
+        Ok(annotate(state, principal, vec![entry]).await.remove(0))
+    }
+
+    /// All tags with how many visible files and directories carry each (with an index database)
+    async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<TagCount>> {
+        let state = ctx.data::<AppState>()?;
+        let principal = ctx.data_opt::<Principal>();
+        let Json(tags) = tags::list_tags_handler(State(state.clone()), principal.cloned().map(Extension))
+            .await
+            .map_err(error)?;
+        Ok(tags)
+    }
+
+    /// The signed-in user's playlists, sorted by name (with an index database)
+    async fn playlists(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<PlaylistSummary>> {
+        let state = ctx.data::<AppState>()?;
+        let principal = ctx.data_opt::<Principal>();
+        let Json(playlists) = playlists::list_playlists_handler(State(state.clone()), principal.cloned().map(Extension))
+            .await
+            .map_err(error)?;
+        Ok(playlists)
+    }
+
+    /// One of the signed-in user's playlists with its tracks (with an index database)
+    async fn playlist(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Playlist> {
+        let state = ctx.data::<AppState>()?;
+        let principal = ctx.data_opt::<Principal>().ok_or_else(|| error(AppError::Forbidden))?;
+        let index = state.index.as_ref().ok_or_else(|| error(AppError::NotFound))?;
+        let playlist = playlists::load_playlist(state, index, principal, id).await.map_err(error)?;
+        let tracks = playlist
+            .tracks
+            .into_iter()
+            .map(|track| Entry::from_file(track.file, None, None))
+            .collect();
+        Ok(Playlist {
+            id: playlist.id,
+            name: playlist.name,
+            created_at: playlist.created_at,
+            updated_at: playlist.updated_at,
+            tracks: annotate(state, Some(principal), tracks).await,
+        })
+    }
+}
+
+/// Query the library with GraphQL
+/// Send `{"query": ..., "variables": ..., "operationName": ...}`. The schema is read-only
+/// and covers directories, files with their metadata and tags, tag counts and the
+/// signed-in user's playlists. Needs `--graphql`.
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    request_body(content = String, description = "GraphQL request", content_type = "application/json"),
+    responses(
+        (status = 200, description = "GraphQL response, with any errors in `errors`"),
+        (status = 404, description = "GraphQL disabled")
+    ),
+    tag = "media"
+)]
+pub async fn graphql_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Result<Json<async_graphql::Response>, AppError> {
+    let schema = state.graphql.clone().ok_or(AppError::NotFound)?;
+    let mut request = request.data(state);
+    if let Some(Extension(principal)) = principal {
+        request = request.data(principal);
+    }
+    Ok(Json(schema.execute(request).await))
+}
+
+/// The schema in GraphQL SDL, for code generators
+#[utoipa::path(
+    get,
+    path = "/graphql/schema",
+    responses(
+        (status = 200, description = "Schema definition", content_type = "text/plain", body = String),
+        (status = 404, description = "GraphQL disabled")
+    ),
+    tag = "media"
+)]
+pub async fn schema_handler(State(state): State<AppState>) -> Result<String, AppError> {
+    state.graphql.as_ref().map(|schema| schema.sdl()).ok_or(AppError::NotFound)
+}
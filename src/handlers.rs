@@ -2,76 +2,123 @@ use askama::Template;
 use axum::{
     body::Body,
     extract::{Path, Query, State},
-    http::{header, StatusCode},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
     response::{Html, IntoResponse, Response, Json, Redirect},
+    Extension,
 };
 use axum::http::header::CONTENT_SECURITY_POLICY;
-use percent_encoding::percent_decode_str;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::sync::RwLock;
-use image::imageops::FilterType;
+use tokio_util::io::ReaderStream;
 use rand::seq::SliceRandom;
-
-use crate::archives;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+
+use crate::acl::{self, AccessControl};
+use crate::album_art::{self, AlbumArtQuery, Art, ArtCache};
+use crate::archives::{self, ArchiveLimits, Extracted};
+use crate::auth::Principal;
+use crate::branding::Branding;
+use crate::cache_control::CachePolicies;
 use crate::constants;
-use crate::models::{DirectoryEntry, DirectoryListing, EntryType};
-use crate::templates::{GalleryTemplate, MusicPlayerTemplate};
-
-/// Cached media files for fast random selection
-#[derive(Clone, Debug)]
-pub struct MediaCache {
-    pub items: Vec<FilterResult>,
-    pub last_updated: std::time::Instant,
-}
-
-impl MediaCache {
-    pub fn new() -> Self {
-        Self {
-            items: Vec::new(),
-            last_updated: std::time::Instant::now(),
-        }
-    }
-}
-
-/// Refresh the media cache by scanning all media files
-pub async fn refresh_media_cache(media_dir: &PathBuf) -> Result<MediaCache, AppError> {
-    let mut items = Vec::new();
-
-    // Use empty query to get all media files
-    let query = FilterQuery {
-        file_type: None,
-        extension: None,
-        name: None,
-        page: None,
-        per_page: None,
-    };
-
-    // Recursively search all files
-    search_directory(media_dir, "", &query, &mut items).await?;
-
-    tracing::info!("Media cache refreshed: {} items indexed", items.len());
-
-    Ok(MediaCache {
-        items,
-        last_updated: std::time::Instant::now(),
-    })
-}
+use crate::cache::{MediaCache, ScanProgress};
+use crate::cast::CastSessions;
+use crate::events::EventBus;
+use crate::graphql::GraphQLSchema;
+use crate::index::MediaIndex;
+use crate::media_info;
+use crate::metadata::{read_audio_tags, AudioTags};
+use crate::oidc::OidcClient;
+use crate::rate_limiter::{AuthRateLimiter, RequestRateLimiter};
+use crate::search::SearchIndex;
+use crate::share_card::ShareCardCache;
+use crate::sidecar::{self, DirectoryMeta};
+use crate::stats::ServerStats;
+use crate::store::{self, MediaStore};
+use crate::tags;
+use crate::favorites;
+use crate::feeds::UrlSigner;
+use crate::playlist_files;
+use crate::preview::LinkPreview;
+use crate::history;
+use crate::i18n::Language;
+use crate::icons::Icons;
+use crate::ratings;
+use crate::tracks;
+use crate::trash::Trash;
+use crate::models::{Breadcrumb, DirectoryEntry, DirectoryListing, EntryType};
+use crate::natural::NameOrder;
+use crate::netfs;
+use crate::templates::{sort_controls, GalleryTemplate, MusicPlayerTemplate};
+use crate::decoding::{self, DecodeLimits};
+use crate::thumbnails;
+use crate::workers::Workers;
 
 #[derive(Clone)]
 pub struct AppState {
     pub media_dir: PathBuf,
     pub media_cache: Arc<RwLock<MediaCache>>,
+    pub scan: Arc<ScanProgress>,
+    pub oidc: Option<Arc<OidcClient>>,
+    pub acl: Arc<AccessControl>,
+    pub stats: Arc<ServerStats>,
+    pub auth_limiter: AuthRateLimiter,
+    pub request_limiter: Option<RequestRateLimiter>,
+    pub index: Option<MediaIndex>,
+    pub search: Option<Arc<SearchIndex>>,
+    pub art_cache: Option<Arc<ArtCache>>,
+    pub thumbnail_cache: Option<Arc<ArtCache>>,
+    /// Bucket the media is served from instead of the media directory, if any
+    pub store: Option<Arc<dyn MediaStore>>,
+    pub url_signer: Option<Arc<UrlSigner>>,
+    pub cast: Option<Arc<CastSessions>>,
+    /// Notifications for `GET /api/events` subscribers
+    pub events: Arc<EventBus>,
+    /// Library schema for `/graphql`, if enabled
+    pub graphql: Option<GraphQLSchema>,
+    /// Largest accepted upload in bytes, if uploads are enabled
+    pub upload_limit: Option<u64>,
+    /// How much of an archive entry a request may extract into memory or at all
+    pub archive_limits: ArchiveLimits,
+    /// Bytes read at a time when streaming a file into a response
+    pub stream_buffer: usize,
+    /// Pool that thumbnailing, album art resizing and archive extraction run on
+    pub workers: Workers,
+    /// Directories a cache refresh lists at once
+    pub scan_concurrency: usize,
+    /// Memory limit of image decoding, and whether it runs in a helper process
+    pub decode_limits: DecodeLimits,
+    /// Cache-Control of media files, thumbnails, album art and static assets
+    pub cache: CachePolicies,
+    /// Admins may delete, rename and move files
+    pub write_ops: bool,
+    /// Where deletions go, if write operations and the trash are enabled
+    pub trash: Option<Arc<Trash>>,
+    /// How listings and filter results compare file names
+    pub name_order: NameOrder,
+    /// Site title, logo, accent color and custom CSS/JS of the web interface
+    pub branding: Arc<Branding>,
+    /// Rendered link preview images of directories
+    pub share_cards: Arc<ShareCardCache>,
+    /// Favicon and app icons
+    pub icons: Arc<Icons>,
 }
 
 /// Handler for the root path - shows the media directory
 pub async fn index_handler(
     State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
     Query(pagination): Query<PaginationQuery>,
-) -> Result<Html<String>, AppError> {
-    list_directory_handler(State(state), Path("".to_string()), Query(pagination)).await
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    list_directory_handler(State(state), principal, Path("".to_string()), Query(pagination), uri, headers).await
 }
 
 /// Handler for /browse redirect - redirects to home page
@@ -82,9 +129,10 @@ pub async fn browse_redirect_handler() -> Redirect {
 /// Handler for /music root - shows music in the media directory
 pub async fn music_index_handler(
     State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
     Query(pagination): Query<PaginationQuery>,
 ) -> Result<Html<String>, AppError> {
-    music_list_handler(State(state), Path("".to_string()), Query(pagination)).await
+    music_list_handler(State(state), principal, Path("".to_string()), Query(pagination)).await
 }
 
 /// Handler for /music/ redirect - redirects to /music
@@ -95,122 +143,66 @@ pub async fn music_redirect_handler() -> Redirect {
 /// Handler for listing music directories and audio files
 pub async fn music_list_handler(
     State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
     Path(path): Path<String>,
     Query(pagination): Query<PaginationQuery>,
 ) -> Result<Html<String>, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(&path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
-
-    // Construct the full path
-    let full_path = state.media_dir.join(decoded_path.as_ref());
-
-    // Canonicalize to prevent path traversal attacks
-    let canonical_path = full_path
-        .canonicalize()
-        .map_err(|_| AppError::NotFound)?;
+    // Normalize the path, which axum has already decoded, as the access rules checked it
+    let path = media_path(&path)?;
 
-    // Ensure the path is within the media directory
-    if !canonical_path.starts_with(&state.media_dir) {
-        return Err(AppError::Forbidden);
-    }
+    // Resolve within the media directory to prevent path traversal attacks
+    let canonical_path = resolve_media_path(&state.media_dir, &path)?;
 
-    // Check if it's a directory
-    if !canonical_path.is_dir() {
+    // Check if it's a directory, outside any .nomedia tree
+    if !canonical_path.is_dir() || sidecar::in_nomedia(&state.media_dir, &canonical_path).await {
         return Err(AppError::NotFound);
     }
 
-    // Read directory contents
-    let mut entries = Vec::new();
-    let mut read_dir = fs::read_dir(&canonical_path)
-        .await
-        .map_err(|_| AppError::InternalError)?;
-
-    while let Some(entry) = read_dir
-        .next_entry()
-        .await
-        .map_err(|_| AppError::InternalError)?
-    {
-        let metadata = entry.metadata().await.map_err(|_| AppError::InternalError)?;
-        let file_name = entry.file_name().to_string_lossy().to_string();
-
-        // Skip hidden files (starting with .)
-        if file_name.starts_with('.') {
-            continue;
-        }
-
-        let entry_type = if metadata.is_dir() {
-            EntryType::Directory
-        } else if is_audio(&file_name) {
-            EntryType::Audio
-        } else if is_archive(&file_name) {
-            // Check if archive contains audio files
-            let file_path = entry.path();
-            if archives::archive_contains_audio(&file_path).await.unwrap_or(false) {
-                EntryType::Archive
-            } else {
-                continue; // Skip archives without audio
-            }
-        } else {
-            continue; // Skip non-audio files and show only directories, audio, and music archives
-        };
-
-        // Build relative path for URL
-        let relative_path = if path.is_empty() {
-            file_name.clone()
-        } else {
-            format!("{}/{}", path, file_name)
-        };
-
-        entries.push(DirectoryEntry {
-            name: file_name,
-            path: relative_path,
-            entry_type,
-            size: metadata.len(),
-        });
-    }
+    let mut entries = if pagination.recursive {
+        flatten_directory(&state, principal.as_deref(), &canonical_path, &path, pagination.depth(), true).await?.0
+    } else {
+        read_music_directory(&state, principal.as_deref(), &canonical_path, &path).await?
+    };
 
-    // Sort: directories first, then archives, then audio files, all by name
-    entries.sort_by(|a, b| {
-        match (&a.entry_type, &b.entry_type) {
-            (EntryType::Directory, EntryType::Directory) => a.name.cmp(&b.name),
-            (EntryType::Directory, _) => std::cmp::Ordering::Less,
-            (_, EntryType::Directory) => std::cmp::Ordering::Greater,
-            (EntryType::Archive, EntryType::Archive) => a.name.cmp(&b.name),
-            (EntryType::Archive, _) => std::cmp::Ordering::Less,
-            (_, EntryType::Archive) => std::cmp::Ordering::Greater,
-            _ => a.name.cmp(&b.name),
-        }
+    // Sort: directories first, then by the chosen field; by default archives, then
+    // playlists, then audio files, all by name
+    let meta = DirectoryMeta::load(&canonical_path).await;
+    let sort = pagination.sort.or(meta.sort).unwrap_or(ListingSort::Type);
+    let order = pagination.order.or(meta.order).unwrap_or_default();
+    sort_entries(&mut entries, sort, order, state.name_order, |entry_type| match entry_type {
+        EntryType::Archive => 0,
+        EntryType::Playlist => 1,
+        _ => 2,
     });
 
     // Pagination
     let total_items = entries.len();
-    let per_page = pagination.per_page.unwrap_or(100).max(1).min(500);
-    let total_pages = if total_items == 0 { 1 } else { (total_items + per_page - 1) / per_page };
-    let page = pagination.page.unwrap_or(1).max(1).min(total_pages);
+    let per_page = pagination.per_page.unwrap_or(100).clamp(1, 500);
+    let total_pages = if total_items == 0 { 1 } else { total_items.div_ceil(per_page) };
+    let page = pagination.page.unwrap_or(1).clamp(1, total_pages);
 
     // Calculate pagination slice
     let start = (page - 1) * per_page;
     let end = (start + per_page).min(total_items);
 
-    let paginated_entries = if start < total_items {
+    let mut paginated_entries = if start < total_items {
         entries[start..end].to_vec()
     } else {
         Vec::new()
     };
+    tags::attach_tags(&state, &mut paginated_entries).await;
+    ratings::attach_ratings(&state, principal.as_deref(), &mut paginated_entries).await;
+    tracks::attach_audio_info(&state, &mut paginated_entries).await;
 
     let listing = DirectoryListing {
         current_path: path.clone(),
+        breadcrumbs: Breadcrumb::trail(&path),
+        title: meta.title,
+        description: meta.description,
         parent_path: if path.is_empty() {
             None
         } else {
-            Some(
-                PathBuf::from(&path)
-                    .parent()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_default(),
-            )
+            Some(parent_path(&path))
         },
         entries: paginated_entries,
         page,
@@ -219,35 +211,111 @@ pub async fn music_list_handler(
         total_pages,
     };
 
+    let feed_url = match (&state.url_signer, &principal) {
+        (Some(signer), Some(principal)) if listing.entries.iter().any(|e| e.is_audio()) => {
+            Some(signer.sign(&principal.username, &format!("/feed/{}.rss", path)))
+        }
+        _ => None,
+    };
     let template = MusicPlayerTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
+        flattened: pagination.recursive,
+        sort_controls: sort_controls(sort, order, per_page, pagination.recursive),
         listing,
+        indexing: !state.scan.is_ready(),
+        playlists: state.index.is_some() && principal.is_some(),
+        library: state.index.is_some(),
+        feed_url,
     };
     Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
 }
 
+/// Subdirectories, audio files, music archives and playlists of a validated directory
+/// that the user may see, unsorted
+async fn read_music_directory(
+    state: &AppState,
+    principal: Option<&Principal>,
+    dir: &std::path::Path,
+    path: &str,
+) -> Result<Vec<DirectoryEntry>, AppError> {
+    let mut entries = Vec::new();
+    let listing = list_entries(dir, |name| {
+        is_audio(name) || is_archive(name) || playlist_files::is_playlist_file(name)
+    })
+    .await?;
+
+    for (file_name, metadata) in listing {
+        let entry_path = dir.join(&file_name);
+        let entry_type = if metadata.is_dir() {
+            EntryType::Directory
+        } else if is_audio(&file_name) {
+            EntryType::Audio
+        } else if is_archive(&file_name) {
+            // Check if archive contains audio files
+            if archives::archive_contains_audio(&entry_path, state.archive_limits, &state.workers).await.unwrap_or(false) {
+                EntryType::Archive
+            } else {
+                continue; // Skip archives without audio
+            }
+        } else if playlist_files::is_playlist_file(&file_name) {
+            EntryType::Playlist
+        } else {
+            continue; // Skip non-audio files and show only directories, audio, music archives and playlists
+        };
+
+        // Build relative path for URL
+        let relative_path = if path.is_empty() {
+            file_name.clone()
+        } else {
+            format!("{}/{}", path, file_name)
+        };
+
+        // Hide subtrees the user isn't allowed to see
+        if !state.acl.allows(principal, &relative_path) {
+            continue;
+        }
+
+        // Subdirectories may be hidden or titled by their sidecar
+        let mut title = None;
+        if metadata.is_dir() {
+            let meta = DirectoryMeta::load(&entry_path).await;
+            if meta.is_hidden() {
+                continue;
+            }
+            title = meta.title;
+        }
+
+        entries.push(DirectoryEntry {
+            name: file_name,
+            path: relative_path,
+            entry_type,
+            size: metadata.len(),
+            modified: Some(modified_secs(&metadata)),
+            tags: Vec::new(),
+            favorite: false,
+            rating: None,
+            audio: None,
+            video: None,
+            title,
+            cover: None,
+        });
+    }
+
+    Ok(entries)
+}
+
 /// Handler for browsing archive contents
 pub async fn music_archive_handler(
     State(state): State<AppState>,
     Path(path): Path<String>,
     Query(pagination): Query<PaginationQuery>,
 ) -> Result<Html<String>, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(&path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
-
-    // Construct the full path to the archive
-    let full_path = state.media_dir.join(decoded_path.as_ref());
-
-    // Canonicalize to prevent path traversal attacks
-    let canonical_path = full_path
-        .canonicalize()
-        .map_err(|_| AppError::NotFound)?;
+    // Normalize the path, which axum has already decoded, as the access rules checked it
+    let path = media_path(&path)?;
 
-    // Ensure the path is within the media directory
-    if !canonical_path.starts_with(&state.media_dir) {
-        return Err(AppError::Forbidden);
-    }
+    // Resolve within the media directory to prevent path traversal attacks
+    let canonical_path = resolve_media_path(&state.media_dir, &path)?;
 
     // Check if it's a file (archive)
     if !canonical_path.is_file() {
@@ -264,9 +332,12 @@ pub async fn music_archive_handler(
     }
 
     // List archive contents
-    let mut entries = archives::list_archive_contents(&canonical_path)
+    let mut entries = archives::list_archive_contents(&canonical_path, state.name_order, state.archive_limits, &state.workers)
         .await
-        .map_err(|_| AppError::InternalError)?;
+        .map_err(|e| match archive_error(e) {
+            AppError::NotFound => AppError::InternalError,
+            e => e,
+        })?;
 
     // Update paths to include archive prefix for serving
     for entry in &mut entries {
@@ -275,9 +346,9 @@ pub async fn music_archive_handler(
 
     // Pagination
     let total_items = entries.len();
-    let per_page = pagination.per_page.unwrap_or(100).max(1).min(500);
-    let total_pages = if total_items == 0 { 1 } else { (total_items + per_page - 1) / per_page };
-    let page = pagination.page.unwrap_or(1).max(1).min(total_pages);
+    let per_page = pagination.per_page.unwrap_or(100).clamp(1, 500);
+    let total_pages = if total_items == 0 { 1 } else { total_items.div_ceil(per_page) };
+    let page = pagination.page.unwrap_or(1).clamp(1, total_pages);
 
     // Calculate pagination slice
     let start = (page - 1) * per_page;
@@ -289,14 +360,17 @@ pub async fn music_archive_handler(
         Vec::new()
     };
 
+    let suffix = Language::current().t("archive-suffix");
+    let mut breadcrumbs = Breadcrumb::trail(&path);
+    if let Some(archive) = breadcrumbs.last_mut() {
+        archive.name = format!("{} {}", archive.name, suffix);
+    }
     let listing = DirectoryListing {
-        current_path: format!("{} (archive)", decoded_path),
-        parent_path: Some(
-            PathBuf::from(decoded_path.as_ref())
-                .parent()
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_default(),
-        ),
+        current_path: format!("{} {}", path, suffix),
+        breadcrumbs,
+        title: None,
+        description: None,
+        parent_path: Some(parent_path(&path)),
         entries: paginated_entries,
         page,
         per_page,
@@ -305,7 +379,15 @@ pub async fn music_archive_handler(
     };
 
     let template = MusicPlayerTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
         listing,
+        indexing: !state.scan.is_ready(),
+        playlists: false,
+        library: state.index.is_some(),
+        feed_url: None,
+        sort_controls: Vec::new(),
+        flattened: false,
     };
     Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
 }
@@ -316,14 +398,12 @@ pub async fn serve_archive_file_handler(
     Path(path): Path<String>,
     headers: axum::http::HeaderMap,
 ) -> Result<Response, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(&path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
+    // Normalize the path, which axum has already decoded, as the access rules checked it
+    let path = media_path(&path)?;
 
     // Split path into archive path and file path within archive
     // Format: "path/to/archive.zip!/path/in/archive.mp3"
-    let parts: Vec<&str> = decoded_path.split("!/").collect();
+    let parts: Vec<&str> = path.split("!/").collect();
     if parts.len() != 2 {
         return Err(AppError::InvalidPath);
     }
@@ -331,18 +411,8 @@ pub async fn serve_archive_file_handler(
     let archive_path_str = parts[0];
     let file_path_in_archive = parts[1];
 
-    // Construct the full path to the archive
-    let full_archive_path = state.media_dir.join(archive_path_str);
-
-    // Canonicalize to prevent path traversal attacks
-    let canonical_archive_path = full_archive_path
-        .canonicalize()
-        .map_err(|_| AppError::NotFound)?;
-
-    // Ensure the path is within the media directory
-    if !canonical_archive_path.starts_with(&state.media_dir) {
-        return Err(AppError::Forbidden);
-    }
+    // Resolve within the media directory to prevent path traversal attacks
+    let canonical_archive_path = resolve_media_path(&state.media_dir, archive_path_str)?;
 
     // Check if it's a file (archive)
     if !canonical_archive_path.is_file() {
@@ -358,20 +428,26 @@ pub async fn serve_archive_file_handler(
     }
 
     // Extract file from archive
-    let contents = archives::extract_file_from_archive(&canonical_archive_path, file_path_in_archive)
-        .await
-        .map_err(|_| AppError::NotFound)?;
+    let mut contents = archives::extract_file_from_archive(
+        &canonical_archive_path,
+        file_path_in_archive,
+        state.archive_limits,
+        &state.workers,
+    )
+    .await
+    .map_err(archive_error)?;
 
     // Validate MIME type from file contents
     let expected_mime_prefix = if is_audio_file { "audio/" } else { "image/" };
-    validate_mime_type(&contents, expected_mime_prefix)?;
+    let head = contents.head().map_err(|_| AppError::InternalError)?;
+    validate_mime_type(&head, expected_mime_prefix)?;
 
     // Determine MIME type for response
     let mime_type = mime_guess::from_path(file_path_in_archive)
         .first_or_octet_stream()
         .to_string();
 
-    let file_size = contents.len() as u64;
+    let file_size = contents.size();
 
     // Handle range requests for seeking/scrubbing support
     if let Some(range_header) = headers.get(header::RANGE) {
@@ -382,7 +458,7 @@ pub async fn serve_archive_file_handler(
                 let content_length = end - start + 1;
 
                 // Extract the requested byte range
-                let range_contents = contents[start as usize..=end as usize].to_vec();
+                let range_contents = extracted_body(&state, contents, start, content_length).await?;
 
                 let response = Response::builder()
                     .status(StatusCode::PARTIAL_CONTENT)
@@ -393,8 +469,8 @@ pub async fn serve_archive_file_handler(
                         format!("bytes {}-{}/{}", start, end, file_size),
                     )
                     .header(header::ACCEPT_RANGES, "bytes")
-                    .header(header::CACHE_CONTROL, "public, max-age=3600")
-                    .body(Body::from(range_contents))
+                    .header(header::CACHE_CONTROL, state.cache.media(file_path_in_archive).to_string())
+                    .body(range_contents)
                     .unwrap();
 
                 return Ok(response);
@@ -408,13 +484,72 @@ pub async fn serve_archive_file_handler(
         .header(header::CONTENT_TYPE, mime_type)
         .header(header::CONTENT_LENGTH, file_size)
         .header(header::ACCEPT_RANGES, "bytes")
-        .header(header::CACHE_CONTROL, "public, max-age=3600")
-        .body(Body::from(contents))
+        .header(header::CACHE_CONTROL, state.cache.media(file_path_in_archive).to_string())
+        .body(extracted_body(&state, contents, 0, file_size).await?)
         .unwrap();
 
     Ok(response)
 }
 
+/// 413 for archives past --max-archive-entry-size, --max-archive-ratio or
+/// --max-archive-output, 404 for any other failed extraction
+fn archive_error(e: anyhow::Error) -> AppError {
+    match e.downcast_ref::<archives::LimitExceeded>() {
+        Some(archives::LimitExceeded::EntrySize(_)) => AppError::EntryTooLarge,
+        Some(_) => AppError::ArchiveLimit,
+        None => AppError::NotFound,
+    }
+}
+
+/// Response body of `len` bytes of an extracted archive entry, starting at `start`
+/// Entries spooled to a temporary file are streamed from it.
+async fn extracted_body(state: &AppState, contents: Extracted, start: u64, len: u64) -> Result<Body, AppError> {
+    match contents {
+        Extracted::Memory(data) if start == 0 && len == data.len() as u64 => Ok(Body::from(data)),
+        Extracted::Memory(data) => Ok(Body::from(data[start as usize..(start + len) as usize].to_vec())),
+        Extracted::File { file, .. } => file_body(state, fs::File::from_std(file), start, len).await,
+    }
+}
+
+/// Response body streaming `len` bytes of `file`, starting at `start`
+/// Reads are --stream-buffer-size bytes, so each fills whole HTTP/2 frames. Every
+/// connection is TLS, encrypted in userspace, so sendfile(2) couldn't skip the copy.
+pub async fn file_body(state: &AppState, mut file: fs::File, start: u64, len: u64) -> Result<Body, AppError> {
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|_| AppError::InternalError)?;
+    Ok(Body::from_stream(ReaderStream::with_capacity(file.take(len), state.stream_buffer)))
+}
+
+/// Where the bytes of a media response come from
+enum MediaSource {
+    File(fs::File),
+    /// A file in the media store, by its clean path
+    Store(Arc<dyn MediaStore>, String),
+}
+
+impl MediaSource {
+    /// Stream `len` bytes from `start` as a response body
+    async fn body(self, state: &AppState, start: u64, len: u64) -> Result<Body, AppError> {
+        match self {
+            MediaSource::File(file) => file_body(state, file, start, len).await,
+            MediaSource::Store(store, path) => {
+                let chunks = store.read_range(&path, start, len).await.map_err(store_error)?;
+                Ok(Body::from_stream(chunks))
+            }
+        }
+    }
+}
+
+/// Map an error of the media store to a response, logging unexpected ones
+pub fn store_error(e: std::io::Error) -> AppError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        return AppError::NotFound;
+    }
+    tracing::error!("Media store request failed: {}", e);
+    AppError::InternalError
+}
+
 /// Parse HTTP Range header
 /// Returns (start, end) byte positions, or None if invalid
 fn parse_range_header(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
@@ -447,173 +582,315 @@ fn parse_range_header(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
 /// Handler for serving album art from MP3 files
 pub async fn serve_album_art_handler(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(path): Path<String>,
+    Query(query): Query<AlbumArtQuery>,
 ) -> Result<Response, AppError> {
+    // Normalize the path, which axum has already decoded, as the access rules checked it
+    let path = media_path(&path)?;
+
     // Check if this is an archive path
     if path.contains("!/") {
-        return serve_album_art_from_archive(state, path).await;
+        return serve_album_art_from_archive(state, headers, path, query).await;
     }
 
     // Validate and canonicalize the path
     let canonical_path = validate_media_path(&state.media_dir, &path)?;
 
     // Check if it's a file
-    if !canonical_path.is_file() {
+    let metadata = fs::metadata(&canonical_path)
+        .await
+        .map_err(|_| AppError::NotFound)?;
+    if !metadata.is_file() {
         return Err(AppError::NotFound);
     }
 
     // Only process audio files
     let file_name = canonical_path.file_name()
         .and_then(|n| n.to_str())
-        .ok_or(AppError::InvalidPath)?;
+        .ok_or(AppError::InvalidPath)?
+        .to_string();
 
-    if !is_audio(file_name) {
+    if !is_audio(&file_name) {
         return Err(AppError::Forbidden);
     }
 
-    // Try to extract album art from MP3 file
-    if let Ok(tag) = id3::Tag::read_from_path(&canonical_path) {
-        // Look for album art in ID3 tags
-        if let Some(picture) = tag.pictures().next() {
-            let mime_type = picture.mime_type.clone();
-            let data = picture.data.clone();
-
-            tracing::debug!(
-                file = %file_name,
-                mime_type = %mime_type,
-                size = data.len(),
-                "Found album art in MP3 file"
-            );
-
-            let response = Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, mime_type)
-                .header(header::CACHE_CONTROL, "public, max-age=86400") // Cache for 24 hours
-                .body(Body::from(data))
-                .unwrap();
-
-            return Ok(response);
-        }
-    }
+    // Adding, removing or renaming a cover image changes the directory's modification time,
+    // which invalidates cached art of the tracks next to it
+    let dir = canonical_path.parent().unwrap_or(&state.media_dir).to_path_buf();
+    let dir_mtime = fs::metadata(&dir).await.map(|m| modified_secs(&m)).unwrap_or(0);
+
+    let key = canonical_path.to_string_lossy().to_string();
+    album_art::serve(
+        &state,
+        &headers,
+        key,
+        modified_secs(&metadata).max(dir_mtime),
+        query.size,
+        async move {
+            // Try to extract album art from MP3 file
+            let tag = tokio::task::spawn_blocking(move || id3::Tag::read_from_path(&canonical_path))
+                .await
+                .ok()
+                .and_then(|tag| tag.ok());
+            if let Some(picture) = tag.as_ref().and_then(|tag| tag.pictures().next()) {
+                tracing::debug!(
+                    file = %file_name,
+                    mime_type = %picture.mime_type,
+                    size = picture.data.len(),
+                    "Found album art in MP3 file"
+                );
+                return Some(Art {
+                    data: picture.data.clone(),
+                    mime: picture.mime_type.clone(),
+                });
+            }
 
-    // No album art found, return 404
-    Err(AppError::NotFound)
+            // Fall back to a cover image in the same directory
+            album_art::folder_art(dir).await
+        },
+    )
+    .await
 }
 
 /// Handler for serving album art from MP3 files inside archives
 async fn serve_album_art_from_archive(
     state: AppState,
+    headers: HeaderMap,
     path: String,
+    query: AlbumArtQuery,
 ) -> Result<Response, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(&path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
-
     // Split path into archive path and file path within archive
-    let parts: Vec<&str> = decoded_path.split("!/").collect();
+    let parts: Vec<&str> = path.split("!/").collect();
     if parts.len() != 2 {
         return Err(AppError::InvalidPath);
     }
 
-    let archive_path_str = parts[0];
-    let file_path_in_archive = parts[1];
+    let archive_path_str = parts[0].to_string();
+    let file_path_in_archive = parts[1].to_string();
 
     // Validate and canonicalize the archive path
-    let canonical_archive_path = validate_media_path(&state.media_dir, archive_path_str)?;
+    let canonical_archive_path = validate_media_path(&state.media_dir, &archive_path_str)?;
 
     // Check if it's a file (archive)
-    if !canonical_archive_path.is_file() {
+    let metadata = fs::metadata(&canonical_archive_path)
+        .await
+        .map_err(|_| AppError::NotFound)?;
+    if !metadata.is_file() {
         return Err(AppError::NotFound);
     }
 
     // Only process audio files
-    if !is_audio(file_path_in_archive) {
+    if !is_audio(&file_path_in_archive) {
         return Err(AppError::Forbidden);
     }
 
-    // Extract file from archive
-    let contents = archives::extract_file_from_archive(&canonical_archive_path, file_path_in_archive)
-        .await
-        .map_err(|_| AppError::NotFound)?;
-
-    // Try to extract album art from MP3 data
-    if let Ok(tag) = id3::Tag::read_from2(std::io::Cursor::new(&contents)) {
-        if let Some(picture) = tag.pictures().next() {
-            let mime_type = picture.mime_type.clone();
-            let data = picture.data.clone();
-
-            tracing::debug!(
-                archive = %archive_path_str,
-                file = %file_path_in_archive,
-                mime_type = %mime_type,
-                size = data.len(),
-                "Found album art in archived MP3 file"
-            );
-
-            let response = Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, mime_type)
-                .header(header::CACHE_CONTROL, "public, max-age=86400")
-                .body(Body::from(data))
-                .unwrap();
-
-            return Ok(response);
-        }
-    }
+    // Tracks inside an archive share the archive's modification time
+    let key = format!("{}!/{}", canonical_archive_path.to_string_lossy(), file_path_in_archive);
+    let (limits, workers) = (state.archive_limits, state.workers.clone());
+    album_art::serve(
+        &state,
+        &headers,
+        key,
+        modified_secs(&metadata),
+        query.size,
+        async move {
+            // Extract file from archive
+            let contents = archives::extract_file_from_archive(&canonical_archive_path, &file_path_in_archive, limits, &workers)
+                .await
+                .ok()?;
+
+            // Try to extract album art from MP3 data
+            let tag = tokio::task::spawn_blocking(move || match contents {
+                Extracted::Memory(data) => id3::Tag::read_from2(std::io::Cursor::new(data)).ok(),
+                Extracted::File { file, .. } => id3::Tag::read_from2(file).ok(),
+            })
+            .await
+            .ok()
+            .flatten();
+            if let Some(picture) = tag.as_ref().and_then(|tag| tag.pictures().next()) {
+                tracing::debug!(
+                    archive = %archive_path_str,
+                    file = %file_path_in_archive,
+                    mime_type = %picture.mime_type,
+                    size = picture.data.len(),
+                    "Found album art in archived MP3 file"
+                );
+                return Some(Art {
+                    data: picture.data.clone(),
+                    mime: picture.mime_type.clone(),
+                });
+            }
 
-    // No album art found
-    Err(AppError::NotFound)
+            // Fall back to a cover image in the same directory of the archive
+            album_art::archive_folder_art(&canonical_archive_path, &file_path_in_archive, limits, &workers).await
+        },
+    )
+    .await
 }
 
 /// Handler for listing directories
 pub async fn list_directory_handler(
     State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
     Path(path): Path<String>,
     Query(pagination): Query<PaginationQuery>,
-) -> Result<Html<String>, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(&path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
-
-    // Construct the full path
-    let full_path = state.media_dir.join(decoded_path.as_ref());
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    // Normalize the path, which axum has already decoded, as the access rules checked it
+    let path = media_path(&path)?;
+
+    let (mut entries, meta) = if state.store.is_some() {
+        // A bucket is listed from the media cache; it has no sidecar files
+        let dir = store::clean_path(&path).ok_or(AppError::InvalidPath)?;
+        let depth = pagination.recursive.then(|| pagination.depth());
+        let entries = read_store_directory(&state, principal.as_deref(), &dir, depth).await?;
+        (entries, DirectoryMeta::default())
+    } else {
+        // Resolve within the media directory to prevent path traversal attacks
+        let canonical_path = resolve_media_path(&state.media_dir, &path)?;
 
-    // Canonicalize to prevent path traversal attacks
-    let canonical_path = full_path
-        .canonicalize()
-        .map_err(|_| AppError::NotFound)?;
+        // Check if it's a directory, outside any .nomedia tree
+        if !canonical_path.is_dir() || sidecar::in_nomedia(&state.media_dir, &canonical_path).await {
+            return Err(AppError::NotFound);
+        }
 
-    // Ensure the path is within the media directory
-    if !canonical_path.starts_with(&state.media_dir) {
-        return Err(AppError::Forbidden);
+        let entries = if pagination.recursive {
+            flatten_directory(&state, principal.as_deref(), &canonical_path, &path, pagination.depth(), false).await?.0
+        } else {
+            read_directory(&state, principal.as_deref(), &canonical_path, &path).await?
+        };
+        (entries, DirectoryMeta::load(&canonical_path).await)
+    };
+    let sort = pagination.sort.or(meta.sort).unwrap_or_default();
+    let order = pagination.order.or(meta.order).unwrap_or_default();
+    if pagination.recursive || (sort, order) != (ListingSort::Name, SortOrder::Asc) {
+        sort_entries(&mut entries, sort, order, state.name_order, |entry_type| match entry_type {
+            EntryType::Image => 0,
+            EntryType::Video => 1,
+            _ => 2,
+        });
     }
 
-    // Check if it's a directory
-    if !canonical_path.is_dir() {
-        return Err(AppError::NotFound);
-    }
+    // Pagination
+    let total_items = entries.len();
+    let per_page = pagination.per_page.unwrap_or(100).clamp(1, 500);
+    let total_pages = if total_items == 0 { 1 } else { total_items.div_ceil(per_page) };
+    let page = pagination.page.unwrap_or(1).clamp(1, total_pages);
 
-    // Read directory contents
-    let mut entries = Vec::new();
-    let mut read_dir = fs::read_dir(&canonical_path)
-        .await
-        .map_err(|_| AppError::InternalError)?;
+    // Calculate pagination slice
+    let start = (page - 1) * per_page;
+    let end = (start + per_page).min(total_items);
 
-    while let Some(entry) = read_dir
-        .next_entry()
-        .await
-        .map_err(|_| AppError::InternalError)?
-    {
-        let metadata = entry.metadata().await.map_err(|_| AppError::InternalError)?;
-        let file_name = entry.file_name().to_string_lossy().to_string();
+    let mut paginated_entries = if start < total_items {
+        entries[start..end].to_vec()
+    } else {
+        Vec::new()
+    };
+    tags::attach_tags(&state, &mut paginated_entries).await;
+    favorites::attach_favorites(&state, principal.as_deref(), &mut paginated_entries).await;
+    ratings::attach_ratings(&state, principal.as_deref(), &mut paginated_entries).await;
+    media_info::attach_video_info(&state, &mut paginated_entries).await;
+    attach_covers(&state, principal.as_deref(), &mut paginated_entries).await;
+    let continue_watching = if path.is_empty() && page == 1 {
+        history::continue_watching(&state, principal.as_deref()).await
+    } else {
+        Vec::new()
+    };
+    let preview = match request_host(&uri, &headers) {
+        Ok(host) => Some(LinkPreview::directory(&state, host, &path, &meta)),
+        Err(_) => None,
+    };
 
-        // Skip hidden files (starting with .)
-        if file_name.starts_with('.') {
-            continue;
+    let preloads = gallery_preloads(&state.branding, &paginated_entries);
+
+    let listing = DirectoryListing {
+        current_path: path.clone(),
+        breadcrumbs: Breadcrumb::trail(&path),
+        title: meta.title,
+        description: meta.description,
+        parent_path: if path.is_empty() {
+            None
+        } else {
+            Some(parent_path(&path))
+        },
+        entries: paginated_entries,
+        page,
+        per_page,
+        total_items,
+        total_pages,
+    };
+
+    let template = GalleryTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
+        flattened: pagination.recursive,
+        sort_controls: sort_controls(sort, order, per_page, pagination.recursive),
+        listing,
+        preview,
+        emoji_prefix: constants::EMOJI_PREFIX,
+        indexing: !state.scan.is_ready(),
+        favorites: state.index.is_some() && principal.is_some(),
+        index_views: state.index.is_some(),
+        continue_watching,
+        casting: state.cast.is_some(),
+        uploads: state.upload_limit.is_some()
+            && principal
+                .as_deref()
+                .is_some_and(|principal| principal.role.allows(&Method::POST, "/api/upload")),
+    };
+    let mut response = Html(template.render().map_err(|_| AppError::InternalError)?).into_response();
+    for link in preloads {
+        if let Ok(value) = header::HeaderValue::try_from(link) {
+            response.headers_mut().append(header::LINK, value);
         }
+    }
+    Ok(response)
+}
 
+/// Thumbnails preloaded with a gallery page, about what fits on the first screen
+const PRELOAD_THUMBNAILS: usize = 12;
+
+/// Characters browsers percent-encode in URL paths, so preloaded URLs match the page's exactly
+const BROWSER_PATH: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'<').add(b'>').add(b'?').add(b'`').add(b'{').add(b'}');
+
+/// `Link: rel=preload` values for the stylesheets, scripts and first thumbnails of a gallery page
+/// Browsers start fetching them while the HTML is still arriving, instead of after parsing it.
+fn gallery_preloads(branding: &Branding, entries: &[DirectoryEntry]) -> Vec<String> {
+    let styles = ["/static/css/main.css", "/static/css/lightbox.css"]
+        .into_iter()
+        .chain(branding.stylesheets.iter().map(String::as_str))
+        .map(|href| format!("<{}>; rel=preload; as=style", href));
+    let scripts = ["/static/js/utils.js", "/static/js/lightbox.js"]
+        .into_iter()
+        .map(|href| format!("<{}>; rel=preload; as=script", href));
+    let thumbnails = entries
+        .iter()
+        .filter_map(|entry| match entry.entry_type {
+            EntryType::Directory => entry.cover.as_deref(),
+            EntryType::Image => Some(entry.path.as_str()),
+            _ => None,
+        })
+        .take(PRELOAD_THUMBNAILS)
+        .map(|path| format!("</thumbnail/{}>; rel=preload; as=image", utf8_percent_encode(path, BROWSER_PATH)));
+    styles.chain(scripts).chain(thumbnails).collect()
+}
+
+/// Media files and subdirectories of a validated gallery directory that the user may
+/// see, directories first, then by name
+pub async fn read_directory(
+    state: &AppState,
+    principal: Option<&Principal>,
+    dir: &std::path::Path,
+    path: &str,
+) -> Result<Vec<DirectoryEntry>, AppError> {
+    let mut entries = Vec::new();
+    let listing = list_entries(dir, |name| media_type(name).is_some()).await?;
+
+    for (file_name, metadata) in listing {
+        let entry_path = dir.join(&file_name);
         let entry_type = if metadata.is_dir() {
             EntryType::Directory
         } else if is_image(&file_name) {
@@ -633,112 +910,291 @@ pub async fn list_directory_handler(
             format!("{}/{}", path, file_name)
         };
 
+        // Hide subtrees the user isn't allowed to see
+        if !state.acl.allows(principal, &relative_path) {
+            continue;
+        }
+
+        // Subdirectories may be hidden, titled or given a cover by their sidecar
+        let (mut title, mut cover) = (None, None);
+        if metadata.is_dir() {
+            let meta = DirectoryMeta::load(&entry_path).await;
+            if meta.is_hidden() {
+                continue;
+            }
+            title = meta.title;
+            cover = meta
+                .cover
+                .filter(|cover| is_image(cover) && entry_path.join(cover).is_file())
+                .map(|cover| format!("{}/{}", relative_path, cover));
+        }
+
         entries.push(DirectoryEntry {
             name: file_name,
             path: relative_path,
             entry_type,
             size: metadata.len(),
+            modified: Some(modified_secs(&metadata)),
+            tags: Vec::new(),
+            favorite: false,
+            rating: None,
+            audio: None,
+            video: None,
+            title,
+            cover,
         });
     }
 
     // Sort: directories first, then by name
     entries.sort_by(|a, b| {
         match (&a.entry_type, &b.entry_type) {
-            (EntryType::Directory, EntryType::Directory) => a.name.cmp(&b.name),
+            (EntryType::Directory, EntryType::Directory) => state.name_order.cmp(&a.name, &b.name),
             (EntryType::Directory, _) => std::cmp::Ordering::Less,
             (_, EntryType::Directory) => std::cmp::Ordering::Greater,
-            _ => a.name.cmp(&b.name),
+            _ => state.name_order.cmp(&a.name, &b.name),
         }
     });
+    Ok(entries)
+}
 
-    // Pagination
-    let total_items = entries.len();
-    let per_page = pagination.per_page.unwrap_or(100).max(1).min(500);
-    let total_pages = if total_items == 0 { 1 } else { (total_items + per_page - 1) / per_page };
-    let page = pagination.page.unwrap_or(1).max(1).min(total_pages);
-
-    // Calculate pagination slice
-    let start = (page - 1) * per_page;
-    let end = (start + per_page).min(total_items);
+/// Media files and subdirectories of a directory of the media store that the user may see,
+/// from the media cache, directories first, then by name
+/// With a `depth`, lists the files of the subdirectories too, like `flatten_directory`.
+async fn read_store_directory(
+    state: &AppState,
+    principal: Option<&Principal>,
+    dir: &str,
+    depth: Option<usize>,
+) -> Result<Vec<DirectoryEntry>, AppError> {
+    let cache = state.media_cache.read().await;
+    let Some((files, subdirs)) = cache.listing(dir) else {
+        // The root exists even while the store is empty or still being listed
+        return if dir.is_empty() { Ok(Vec::new()) } else { Err(AppError::NotFound) };
+    };
 
-    let paginated_entries = if start < total_items {
-        entries[start..end].to_vec()
-    } else {
-        Vec::new()
+    let entry = |path: &str, name: &str, entry_type: EntryType, size: u64, modified: Option<u64>| DirectoryEntry {
+        name: name.to_string(),
+        path: path.to_string(),
+        entry_type,
+        size,
+        modified,
+        tags: Vec::new(),
+        favorite: false,
+        rating: None,
+        audio: None,
+        video: None,
+        title: None,
+        cover: None,
+    };
+    let file_entry = |item: &FilterResult, name: &str| {
+        let entry_type = match item.file_type.as_str() {
+            "image" => EntryType::Image,
+            "video" => EntryType::Video,
+            _ => EntryType::Audio,
+        };
+        entry(&item.path, name, entry_type, item.size, Some(item.modified))
+    };
+    let mut entries: Vec<DirectoryEntry> = match depth {
+        Some(depth) => cache
+            .items_in(dir, true)
+            .filter_map(|item| {
+                let name = item.path.strip_prefix(dir)?.trim_start_matches('/');
+                (name.matches('/').count() <= depth).then(|| file_entry(item, name))
+            })
+            .filter(|entry| state.acl.allows(principal, &entry.path))
+            .take(MAX_FLATTENED_ENTRIES)
+            .collect(),
+        None => subdirs
+            .iter()
+            .map(|subdir| entry(subdir, subdir.rsplit('/').next().unwrap_or(subdir), EntryType::Directory, 0, None))
+            .chain(files.iter().map(|item| file_entry(item, &item.name)))
+            .filter(|entry| state.acl.allows(principal, &entry.path))
+            .collect(),
     };
 
-    let listing = DirectoryListing {
-        current_path: path.clone(),
-        parent_path: if path.is_empty() {
-            None
+    entries.sort_by(|a, b| {
+        b.is_directory()
+            .cmp(&a.is_directory())
+            .then_with(|| state.name_order.cmp(&a.name, &b.name))
+    });
+    Ok(entries)
+}
+
+/// Give directories without a sidecar cover a preview image from the media cache
+async fn attach_covers(state: &AppState, principal: Option<&Principal>, entries: &mut [DirectoryEntry]) {
+    let cache = state.media_cache.read().await;
+    for entry in entries.iter_mut().filter(|entry| entry.is_directory() && entry.cover.is_none()) {
+        entry.cover = cache
+            .cover_image(&entry.path, |path| state.acl.allows(principal, path))
+            .map(str::to_string);
+    }
+}
+
+/// Directory levels a recursive listing descends by default, and at most
+const DEFAULT_FLATTEN_DEPTH: usize = 8;
+pub const MAX_FLATTEN_DEPTH: usize = 32;
+
+/// Files listed by one recursive listing; the rest of the subtree is left out
+const MAX_FLATTENED_ENTRIES: usize = 10_000;
+
+/// Media files in a validated directory and its subdirectories, `depth` levels down, unsorted,
+/// and whether that is all of them
+/// Each entry is named by its path below `dir`. Subdirectories that resolve outside the
+/// media directory are skipped. The walk stops once `MAX_FLATTENED_ENTRIES` files are found,
+/// and leaves out subdirectories below `depth`; either makes the result incomplete.
+pub async fn flatten_directory(
+    state: &AppState,
+    principal: Option<&Principal>,
+    dir: &std::path::Path,
+    path: &str,
+    depth: usize,
+    music: bool,
+) -> Result<(Vec<DirectoryEntry>, bool), AppError> {
+    let root = path;
+    let mut files = Vec::new();
+    let mut complete = true;
+    let mut pending = vec![(dir.to_path_buf(), path.to_string(), 0)];
+    while let Some((dir, path, level)) = pending.pop() {
+        let entries = if music {
+            read_music_directory(state, principal, &dir, &path).await?
         } else {
-            Some(
-                PathBuf::from(&path)
-                    .parent()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_default(),
-            )
-        },
-        entries: paginated_entries,
-        page,
-        per_page,
-        total_items,
-        total_pages,
-    };
+            read_directory(state, principal, &dir, &path).await?
+        };
+        for mut entry in entries {
+            if entry.is_directory() {
+                let Ok(subdir) = netfs::canonicalize(&dir.join(&entry.name)) else {
+                    continue;
+                };
+                if !subdir.starts_with(&state.media_dir) {
+                    continue;
+                }
+                if level < depth {
+                    pending.push((subdir, entry.path, level + 1));
+                } else {
+                    complete = false;
+                }
+            } else if files.len() < MAX_FLATTENED_ENTRIES {
+                if let Some(name) = entry.path.strip_prefix(root).map(|rest| rest.trim_start_matches('/')) {
+                    entry.name = name.to_string();
+                }
+                files.push(entry);
+            } else {
+                return Ok((files, false));
+            }
+        }
+    }
+    Ok((files, complete))
+}
 
-    let template = GalleryTemplate {
-        listing,
-        emoji_prefix: constants::EMOJI_PREFIX,
-    };
-    Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
+/// Sort a directory listing, keeping directories first
+/// `rank` orders the other entry types for `ListingSort::Type`; ties go by extension, then name.
+fn sort_entries(
+    entries: &mut [DirectoryEntry],
+    sort: ListingSort,
+    order: SortOrder,
+    names: NameOrder,
+    rank: fn(&EntryType) -> u8,
+) {
+    fn extension(name: &str) -> String {
+        name.rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default()
+    }
+
+    entries.sort_by(|a, b| {
+        let a_dir = matches!(a.entry_type, EntryType::Directory);
+        let b_dir = matches!(b.entry_type, EntryType::Directory);
+        b_dir.cmp(&a_dir).then_with(|| {
+            let ordering = match sort {
+                ListingSort::Name => names.cmp(&a.name, &b.name),
+                ListingSort::Size => a.size.cmp(&b.size).then_with(|| names.cmp(&a.name, &b.name)),
+                ListingSort::Mtime => a.modified.cmp(&b.modified).then_with(|| names.cmp(&a.name, &b.name)),
+                ListingSort::Type => rank(&a.entry_type)
+                    .cmp(&rank(&b.entry_type))
+                    .then_with(|| extension(&a.name).cmp(&extension(&b.name)))
+                    .then_with(|| names.cmp(&a.name, &b.name)),
+            };
+            match order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        })
+    });
 }
 
 /// Handler for serving media files
 pub async fn serve_media_handler(
     State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
     Path(path): Path<String>,
     headers: axum::http::HeaderMap,
 ) -> Result<Response, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(&path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
-
-    // Construct the full path
-    let full_path = state.media_dir.join(decoded_path.as_ref());
+    // Normalize the path, which axum has already decoded, as the access rules checked it
+    let path = media_path(&path)?;
+
+    let (file_name, file_size, head, source) = match &state.store {
+        Some(store) => {
+            let path = store::clean_path(&path).ok_or(AppError::InvalidPath)?;
+            let file_name = path.rsplit('/').next().unwrap_or_default().to_string();
+            if media_type(&file_name).is_none() {
+                return Err(AppError::Forbidden);
+            }
+            let file_size = store.stat(&path).await.map_err(store_error)?.size;
 
-    // Canonicalize to prevent path traversal attacks
-    let canonical_path = full_path
-        .canonicalize()
-        .map_err(|_| AppError::NotFound)?;
+            // Let the client download straight from the bucket, if enabled
+            let mime_type = mime_guess::from_path(&file_name).first_or_octet_stream();
+            if let Some(url) = store.signed_url(&path, mime_type.as_ref(), store::SIGNED_URL_EXPIRY) {
+                if history::is_new_view(&headers) {
+                    history::record_view(&state, principal.as_deref(), &path);
+                }
+                let mut response = Redirect::temporary(&url).into_response();
+                response.headers_mut().insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+                return Ok(response);
+            }
 
-    // Ensure the path is within the media directory
-    if !canonical_path.starts_with(&state.media_dir) {
-        return Err(AppError::Forbidden);
-    }
+            let head = store::read(store.as_ref(), &path, 0, file_size.min(constants::SNIFF_SIZE as u64))
+                .await
+                .map_err(store_error)?;
+            (file_name, file_size, head, MediaSource::Store(store.clone(), path))
+        }
+        None => {
+            // Resolve within the media directory to prevent path traversal attacks
+            let canonical_path = resolve_media_path(&state.media_dir, &path)?;
 
-    // Check if it's a file
-    if !canonical_path.is_file() {
-        return Err(AppError::NotFound);
-    }
+            // Check if it's a file
+            if !canonical_path.is_file() {
+                return Err(AppError::NotFound);
+            }
 
-    // Only serve image, video, and audio files
-    let file_name = canonical_path.file_name()
-        .and_then(|n| n.to_str())
-        .ok_or(AppError::InvalidPath)?;
+            // Only serve image, video, and audio files
+            let file_name = canonical_path.file_name()
+                .and_then(|n| n.to_str())
+                .ok_or(AppError::InvalidPath)?
+                .to_string();
 
-    if !is_image(file_name) && !is_video(file_name) && !is_audio(file_name) {
-        return Err(AppError::Forbidden);
-    }
+            if !is_image(&file_name) && !is_video(&file_name) && !is_audio(&file_name) {
+                return Err(AppError::Forbidden);
+            }
 
-    // Read the file
-    let contents = fs::read(&canonical_path)
-        .await
-        .map_err(|_| AppError::InternalError)?;
+            // Open the file; it is streamed, not read into memory
+            let mut file = netfs::retry_async(|| fs::File::open(&canonical_path))
+                .await
+                .map_err(|_| AppError::InternalError)?;
+            let file_size = file
+                .metadata()
+                .await
+                .map_err(|_| AppError::InternalError)?
+                .len();
+
+            let head = read_head(&mut file).await.map_err(|_| AppError::InternalError)?;
+            (file_name, file_size, head, MediaSource::File(file))
+        }
+    };
+    let file_name = file_name.as_str();
 
-    // Validate MIME type from file contents (magic bytes)
+    // Validate MIME type from the start of the file (magic bytes)
     // This prevents serving malicious files with fake extensions
-    let detected_type = infer::get(&contents);
+    let detected_type = infer::get(&head);
 
     if let Some(file_type) = detected_type {
         let mime = file_type.mime_type();
@@ -777,12 +1233,15 @@ pub async fn serve_media_handler(
         return Err(AppError::Forbidden);
     }
 
+    if history::is_new_view(&headers) {
+        history::record_view(&state, principal.as_deref(), &path);
+    }
+
     // Determine MIME type for response
-    let mime_type = mime_guess::from_path(&canonical_path)
+    let mime_type = mime_guess::from_path(file_name)
         .first_or_octet_stream()
         .to_string();
 
-    let file_size = contents.len() as u64;
     let is_audio_file = is_audio(file_name);
     let is_svg = file_name.to_lowercase().ends_with(".svg");
 
@@ -794,8 +1253,8 @@ pub async fn serve_media_handler(
                     let (start, end) = range;
                     let content_length = end - start + 1;
 
-                    // Extract the requested byte range
-                    let range_contents = contents[start as usize..=end as usize].to_vec();
+                    // Stream the requested byte range
+                    let range_contents = source.body(&state, start, content_length).await?;
 
                     let response = Response::builder()
                         .status(StatusCode::PARTIAL_CONTENT)
@@ -806,8 +1265,8 @@ pub async fn serve_media_handler(
                             format!("bytes {}-{}/{}", start, end, file_size),
                         )
                         .header(header::ACCEPT_RANGES, "bytes")
-                        .header(header::CACHE_CONTROL, "public, max-age=3600")
-                        .body(Body::from(range_contents))
+                        .header(header::CACHE_CONTROL, state.cache.media(file_name).to_string())
+                        .body(range_contents)
                         .unwrap();
 
                     return Ok(response);
@@ -820,7 +1279,7 @@ pub async fn serve_media_handler(
     let mut response_builder = Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_LENGTH, file_size)
-        .header(header::CACHE_CONTROL, "public, max-age=3600");
+        .header(header::CACHE_CONTROL, state.cache.media(file_name).to_string());
 
     // Add Accept-Ranges header for audio/video files
     if is_audio_file || is_video(file_name) {
@@ -841,92 +1300,132 @@ pub async fn serve_media_handler(
     }
 
     // Return the file with appropriate headers
-    Ok(response_builder.body(Body::from(contents)).unwrap())
+    Ok(response_builder.body(source.body(&state, 0, file_size).await?).unwrap())
 }
 
 /// Handler for serving thumbnail versions of images
+/// `?size=` picks the largest width or height, rounded up to a served size (default 50).
+/// With --thumbnail-cache-dir, each thumbnail is made once until the image changes.
 pub async fn serve_thumbnail_handler(
     State(state): State<AppState>,
     Path(path): Path<String>,
+    Query(query): Query<thumbnails::ThumbnailQuery>,
 ) -> Result<Response, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(&path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
+    // Normalize the path, which axum has already decoded, as the access rules checked it
+    let path = media_path(&path)?;
+
+    // The local file, or the size of the file in the media store
+    let (key, mtime, canonical_path, file_size) = match &state.store {
+        Some(store) => {
+            let path = store::clean_path(&path).ok_or(AppError::InvalidPath)?;
+            if !is_image(path.rsplit('/').next().unwrap_or_default()) {
+                return Err(AppError::Forbidden);
+            }
+            let file = store.stat(&path).await.map_err(store_error)?;
+            (path, file.modified, None, file.size)
+        }
+        None => {
+            // Resolve within the media directory to prevent path traversal attacks
+            let canonical_path = resolve_media_path(&state.media_dir, &path)?;
+
+            // Check if it's a file
+            let metadata = fs::metadata(&canonical_path)
+                .await
+                .map_err(|_| AppError::NotFound)?;
+            if !metadata.is_file() {
+                return Err(AppError::NotFound);
+            }
 
-    // Construct the full path
-    let full_path = state.media_dir.join(decoded_path.as_ref());
+            // Only serve image files
+            let file_name = canonical_path.file_name()
+                .and_then(|n| n.to_str())
+                .ok_or(AppError::InvalidPath)?;
 
-    // Canonicalize to prevent path traversal attacks
-    let canonical_path = full_path
-        .canonicalize()
-        .map_err(|_| AppError::NotFound)?;
+            if !is_image(file_name) {
+                return Err(AppError::Forbidden);
+            }
 
-    // Ensure the path is within the media directory
-    if !canonical_path.starts_with(&state.media_dir) {
-        return Err(AppError::Forbidden);
-    }
+            let key = path.clone();
+            (key, modified_secs(&metadata), Some(canonical_path), metadata.len())
+        }
+    };
 
-    // Check if it's a file
-    if !canonical_path.is_file() {
-        return Err(AppError::NotFound);
+    let size = thumbnails::snap_size(query.size.unwrap_or(thumbnails::DEFAULT_SIZE));
+    if let Some(cache) = state.thumbnail_cache.clone() {
+        let key = key.clone();
+        let hit = tokio::task::spawn_blocking(move || cache.get(&key, mtime, Some(size)))
+            .await
+            .map_err(|_| AppError::InternalError)?;
+        match hit {
+            Some(Some(thumbnail)) => return Ok(thumbnail_response(&state, thumbnail.data)),
+            // Known not to decode
+            Some(None) => return Err(AppError::InternalError),
+            None => {}
+        }
     }
 
-    // Only serve image files
-    let file_name = canonical_path.file_name()
-        .and_then(|n| n.to_str())
-        .ok_or(AppError::InvalidPath)?;
+    // Validate MIME type from the start of the file, then read the rest
+    let contents = match (&state.store, canonical_path) {
+        (Some(store), _) => {
+            let head_size = file_size.min(constants::SNIFF_SIZE as u64);
+            let mut contents = store::read(store.as_ref(), &key, 0, head_size).await.map_err(store_error)?;
+            validate_mime_type(&contents, "image/")?;
+            let rest = store::read(store.as_ref(), &key, head_size, file_size - head_size)
+                .await
+                .map_err(store_error)?;
+            contents.extend_from_slice(&rest);
+            contents
+        }
+        (None, canonical_path) => {
+            let canonical_path = canonical_path.ok_or(AppError::InternalError)?;
+            let mut file = netfs::retry_async(|| fs::File::open(&canonical_path))
+                .await
+                .map_err(|_| AppError::InternalError)?;
+            let mut contents = read_head(&mut file).await.map_err(|_| AppError::InternalError)?;
+            validate_mime_type(&contents, "image/")?;
+            file.read_to_end(&mut contents)
+                .await
+                .map_err(|_| AppError::InternalError)?;
+            contents
+        }
+    };
 
-    if !is_image(file_name) {
-        return Err(AppError::Forbidden);
+    // Generate thumbnail
+    let thumbnail = decoding::thumbnail(&state, contents, size).await;
+    if let Err(e) = &thumbnail {
+        tracing::debug!(path = %key, "Failed to make thumbnail: {}", e);
     }
 
-    // Read the file
-    let contents = fs::read(&canonical_path)
-        .await
-        .map_err(|_| AppError::InternalError)?;
-
-    // Validate MIME type from file contents
-    let detected_type = infer::get(&contents);
-    if let Some(file_type) = detected_type {
-        let mime = file_type.mime_type();
-        if !mime.starts_with("image/") {
-            return Err(AppError::Forbidden);
+    if let Some(index) = &state.index {
+        if let Err(e) = index.set_thumbnail_state(key.clone(), thumbnail.is_ok()).await {
+            tracing::debug!("Failed to record thumbnail state: {:?}", e);
         }
-    } else {
-        return Err(AppError::Forbidden);
     }
+    if let Some(cache) = state.thumbnail_cache.clone() {
+        let art = thumbnail.as_ref().ok().map(|data| Art {
+            data: data.clone(),
+            mime: "image/jpeg".to_string(),
+        });
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = cache.put(&key, mtime, Some(size), art.as_ref()) {
+                tracing::warn!("Failed to cache thumbnail: {:?}", e);
+            }
+        });
+    }
+    let thumbnail = thumbnail.map_err(|_| AppError::InternalError)?;
 
-    // Generate thumbnail
-    let thumbnail = tokio::task::spawn_blocking(move || {
-        generate_thumbnail(&contents, 50, 50)
-    })
-    .await
-    .map_err(|_| AppError::InternalError)?
-    .map_err(|_| AppError::InternalError)?;
+    Ok(thumbnail_response(&state, thumbnail))
+}
 
-    // Return thumbnail with appropriate headers
-    let response = Response::builder()
+/// A JPEG thumbnail with the --cache-thumbnails policy
+fn thumbnail_response(state: &AppState, thumbnail: Vec<u8>) -> Response {
+    Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "image/jpeg")
         .header(header::CONTENT_LENGTH, thumbnail.len())
-        .header(header::CACHE_CONTROL, "public, max-age=86400") // Cache for 24 hours
+        .header(header::CACHE_CONTROL, state.cache.thumbnails.to_string())
         .body(Body::from(thumbnail))
-        .unwrap();
-
-    Ok(response)
-}
-
-/// Generate a thumbnail from image bytes
-fn generate_thumbnail(image_data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
-    let img = image::load_from_memory(image_data)?;
-    let thumbnail = img.resize(width, height, FilterType::Nearest);
-
-    let mut output = Vec::new();
-    let mut cursor = std::io::Cursor::new(&mut output);
-    thumbnail.write_to(&mut cursor, image::ImageFormat::Jpeg)?;
-
-    Ok(output)
+        .unwrap()
 }
 
 fn is_image(filename: &str) -> bool {
@@ -944,36 +1443,130 @@ fn is_audio(filename: &str) -> bool {
     constants::AUDIO_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
 }
 
+/// Media type of a file name ("image", "video" or "audio"), or `None` for other files
+pub fn media_type(filename: &str) -> Option<&'static str> {
+    if is_image(filename) {
+        Some("image")
+    } else if is_video(filename) {
+        Some("video")
+    } else if is_audio(filename) {
+        Some("audio")
+    } else {
+        None
+    }
+}
+
+/// Characters left unescaped in path segments of generated URLs
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// Percent-encode a path for use in a URL, keeping its slashes
+pub fn encode_url_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Host the client addressed, for building absolute URLs
+/// HTTP/2 clients send the host as the URI authority instead of a Host header.
+pub fn request_host<'a>(uri: &'a Uri, headers: &'a HeaderMap) -> Result<&'a str, AppError> {
+    uri.authority()
+        .map(|authority| authority.as_str())
+        .or_else(|| headers.get(header::HOST).and_then(|v| v.to_str().ok()))
+        .ok_or(AppError::InvalidInput("Missing Host header"))
+}
+
+/// Modification time as Unix seconds, or 0 if the platform doesn't report it
+pub fn modified_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 fn is_archive(filename: &str) -> bool {
     archives::is_archive(filename)
 }
 
+/// Normalize a decoded path below the media directory the way the access rules see it
+/// Empty and `.` segments are dropped and `..` is resolved, so `public/../private` becomes
+/// `private`. Paths climbing out of the media directory are invalid.
+pub fn media_path(path: &str) -> Result<String, AppError> {
+    acl::normalize(path).ok_or(AppError::InvalidPath)
+}
+
 /// Validate and canonicalize a path within the media directory
-/// Returns the canonical path if valid, or an error if invalid/outside media dir
-fn validate_media_path(media_dir: &PathBuf, path: &str) -> Result<PathBuf, AppError> {
-    // Decode the URL-encoded path
-    let decoded_path = percent_decode_str(path)
-        .decode_utf8()
-        .map_err(|_| AppError::InvalidPath)?;
-
-    // Construct the full path
-    let full_path = media_dir.join(decoded_path.as_ref());
-
-    // Canonicalize to prevent path traversal attacks
-    let canonical_path = full_path
-        .canonicalize()
-        .map_err(|_| AppError::NotFound)?;
+/// The path must already be decoded, as axum's `Path`, `Query` and `Json` extractors leave
+/// it; decoding it again would resolve another file than the access rules checked.
+pub fn validate_media_path(media_dir: &std::path::Path, path: &str) -> Result<PathBuf, AppError> {
+    resolve_media_path(media_dir, &media_path(path)?)
+}
 
-    // Ensure the path is within the media directory
-    if !canonical_path.starts_with(media_dir) {
-        return Err(AppError::Forbidden);
+/// Resolve a decoded path below the media directory, preventing path traversal attacks
+/// The path must exist; see `netfs::resolve` for how it is checked.
+/// Where the filesystem resolves the path, it must come back spelled as requested unless a
+/// symlink on the way leads elsewhere. A case-insensitive filesystem opens `Private/a.jpg`
+/// for `private/a.jpg`, and Windows also splits names at `\`, ignores trailing dots and
+/// accepts short names, but access rules, tags and the cache compare the `/`-separated
+/// request exactly, so only the spelling on disk is served.
+pub fn resolve_media_path(media_dir: &std::path::Path, path: &str) -> Result<PathBuf, AppError> {
+    let resolved = netfs::resolve(media_dir, std::path::Path::new(path)).map_err(|e| match e.kind() {
+        std::io::ErrorKind::PermissionDenied => AppError::Forbidden,
+        _ => AppError::NotFound,
+    })?;
+    if differs_from_request(&resolved, media_dir, path) && !leads_through_symlink(media_dir, path) {
+        return Err(AppError::NotFound);
     }
+    Ok(resolved)
+}
+
+/// Whether a resolved path names other entries below the media directory than the request
+fn differs_from_request(resolved: &std::path::Path, media_dir: &std::path::Path, path: &str) -> bool {
+    let Ok(relative) = resolved.strip_prefix(media_dir) else {
+        return true;
+    };
+    let requested = path.split('/').filter(|segment| !segment.is_empty() && *segment != ".");
+    !relative.iter().map(|name| name.to_string_lossy()).eq(requested)
+}
+
+/// Whether one of the entries on the way to a requested path is a symlink
+fn leads_through_symlink(media_dir: &std::path::Path, path: &str) -> bool {
+    let mut entry = media_dir.to_path_buf();
+    path.split('/').filter(|segment| !segment.is_empty() && *segment != ".").any(|segment| {
+        entry.push(segment);
+        std::fs::symlink_metadata(&entry).is_ok_and(|metadata| metadata.file_type().is_symlink())
+    })
+}
 
-    Ok(canonical_path)
+/// Parent of a `/`-separated path below the media directory, empty for a top-level entry
+fn parent_path(path: &str) -> String {
+    path.trim_end_matches('/').rsplit_once('/').map(|(parent, _)| parent.to_string()).unwrap_or_default()
+}
+
+/// Entries of a directory with their metadata, hidden ones and files `wanted` rejects left out
+async fn list_entries(
+    dir: &std::path::Path,
+    wanted: fn(&str) -> bool,
+) -> Result<Vec<(String, std::fs::Metadata)>, AppError> {
+    let dir = dir.to_path_buf();
+    tokio::task::spawn_blocking(move || netfs::list_dir(&dir, wanted))
+        .await
+        .map_err(|_| AppError::InternalError)?
+        .map_err(|_| AppError::InternalError)
+}
+
+/// Read the first `SNIFF_SIZE` bytes of a file for MIME sniffing
+/// The file is left just after them, so the rest can follow without reading them again.
+pub async fn read_head(file: &mut fs::File) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(constants::SNIFF_SIZE);
+    (&mut *file).take(constants::SNIFF_SIZE as u64).read_to_end(&mut head).await?;
+    Ok(head)
 }
 
 /// Validate MIME type of file contents
-fn validate_mime_type(contents: &[u8], expected_category: &str) -> Result<String, AppError> {
+pub fn validate_mime_type(contents: &[u8], expected_category: &str) -> Result<String, AppError> {
     let detected_type = infer::get(contents).ok_or_else(|| {
         tracing::warn!("Could not detect MIME type from file contents");
         AppError::Forbidden
@@ -998,21 +1591,348 @@ fn validate_mime_type(contents: &[u8], expected_category: &str) -> Result<String
     Ok(mime.to_string())
 }
 
+/// Field to sort filter results by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema, async_graphql::Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Name,
+    Size,
+    Mtime,
+    /// How well the file name matches the `name` filter
+    Score,
+    /// The signed-in user's star rating; unrated files sort lowest
+    Rating,
+    /// When the signed-in user last viewed the file; unviewed files sort lowest
+    #[serde(rename = "recently_viewed")]
+    RecentlyViewed,
+}
+
+/// Sort direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema, async_graphql::Enum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// Field to sort a browsed directory by; directories always come first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ListingSort {
+    #[default]
+    Name,
+    Size,
+    Mtime,
+    /// Kind of entry, then extension
+    Type,
+}
+
+impl ListingSort {
+    pub const ALL: [ListingSort; 4] = [ListingSort::Name, ListingSort::Size, ListingSort::Mtime, ListingSort::Type];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListingSort::Name => "name",
+            ListingSort::Size => "size",
+            ListingSort::Mtime => "mtime",
+            ListingSort::Type => "type",
+        }
+    }
+}
+
 /// Filter query parameters
-#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
 #[into_params(parameter_in = Query)]
 pub struct FilterQuery {
     /// Filter by file type (image, video, or audio)
     #[serde(rename = "type")]
-    file_type: Option<String>,
+    pub file_type: Option<String>,
     /// Filter by file extension (e.g., .jpg, .mp4)
-    extension: Option<String>,
-    /// Fuzzy match on file name
-    name: Option<String>,
+    pub extension: Option<String>,
+    /// Fuzzy match on file name; every word must match, e.g. "bday 2019" finds Birthday_Party_2019.jpg
+    pub name: Option<String>,
     /// Page number (1-indexed)
-    page: Option<usize>,
-    /// Number of items per page
-    per_page: Option<usize>,
+    pub page: Option<usize>,
+    /// Number of items per page (default 50, max 500)
+    pub per_page: Option<usize>,
+    /// Minimum file size in bytes; KB, MB, GB and TB suffixes are accepted (e.g. 1GB)
+    #[serde(default, deserialize_with = "deserialize_size")]
+    #[param(value_type = Option<String>)]
+    pub min_size: Option<u64>,
+    /// Maximum file size in bytes; KB, MB, GB and TB suffixes are accepted
+    #[serde(default, deserialize_with = "deserialize_size")]
+    #[param(value_type = Option<String>)]
+    pub max_size: Option<u64>,
+    /// Only files modified at or after this time (RFC 3339, YYYY-MM-DD or Unix seconds)
+    #[serde(default, deserialize_with = "deserialize_time")]
+    #[param(value_type = Option<String>)]
+    pub modified_after: Option<u64>,
+    /// Only files modified before this time (RFC 3339, YYYY-MM-DD or Unix seconds)
+    #[serde(default, deserialize_with = "deserialize_time")]
+    #[param(value_type = Option<String>)]
+    pub modified_before: Option<u64>,
+    /// Only files whose path starts with this prefix (e.g. photos/2024)
+    pub path_prefix: Option<String>,
+    /// Audio tag artist contains this text (case insensitive)
+    pub artist: Option<String>,
+    /// Audio tag album contains this text (case insensitive)
+    pub album: Option<String>,
+    /// Audio tag title contains this text (case insensitive)
+    pub title: Option<String>,
+    /// Audio tag genre contains this text (case insensitive)
+    pub genre: Option<String>,
+    /// Only files with all of these comma-separated tags, set on the file or a parent directory
+    pub tag: Option<String>,
+    /// Only the signed-in user's favorites (favorites=true)
+    pub favorites: Option<bool>,
+    /// Only files the signed-in user rated at least this many stars (1-5)
+    pub min_rating: Option<u8>,
+    /// Only files the signed-in user rated at most this many stars (1-5)
+    pub max_rating: Option<u8>,
+    /// Sort by name, size, mtime, score, rating or recently_viewed (default score with a name filter, otherwise name)
+    pub sort: Option<SortField>,
+    /// Sort order: asc or desc (default desc for score, rating and recently_viewed, otherwise asc)
+    pub order: Option<SortOrder>,
+    /// Read the media directory instead of the media cache, to include changes since the last
+    /// refresh (fresh=true); slow on big libraries, ignored with a media store
+    #[serde(default)]
+    pub fresh: bool,
+}
+
+thread_local! {
+    static NAME_MATCHER: SkimMatcherV2 = SkimMatcherV2::default().ignore_case();
+}
+
+impl FilterQuery {
+    /// Check whether a file satisfies every filter in the query
+    pub fn matches(&self, item: &FilterResult) -> bool {
+        if self.file_type.as_ref().is_some_and(|t| &item.file_type != t) {
+            return false;
+        }
+        if let Some(extension) = &self.extension {
+            if !item.name.to_lowercase().ends_with(&extension.to_lowercase()) {
+                return false;
+            }
+        }
+        if self.name_score(&item.name).is_none() {
+            return false;
+        }
+        if self.min_size.is_some_and(|min| item.size < min)
+            || self.max_size.is_some_and(|max| item.size > max)
+            || self.modified_after.is_some_and(|after| item.modified < after)
+            || self.modified_before.is_some_and(|before| item.modified >= before)
+        {
+            return false;
+        }
+        if let Some(prefix) = self.path_prefix() {
+            if !item.path.starts_with(prefix) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Score a file name against the name filter, or `None` if it doesn't match
+    /// Each word of the filter must appear in order, though not necessarily contiguously,
+    /// and the word scores are summed. Without a name filter every name scores 0.
+    pub fn name_score(&self, name: &str) -> Option<i64> {
+        let Some(filter) = &self.name else {
+            return Some(0);
+        };
+        NAME_MATCHER.with(|matcher| {
+            filter
+                .split_whitespace()
+                .map(|word| matcher.fuzzy_match(name, word))
+                .sum()
+        })
+    }
+
+    /// Sort field to apply: as requested, else by score when filtering by name
+    pub fn sort_field(&self) -> SortField {
+        self.sort.unwrap_or(if self.name.is_some() {
+            SortField::Score
+        } else {
+            SortField::Name
+        })
+    }
+
+    /// Sort order to apply: as requested, else best scores, best ratings and latest views first
+    pub fn sort_order(&self) -> SortOrder {
+        self.order.unwrap_or(match self.sort_field() {
+            SortField::Score | SortField::Rating | SortField::RecentlyViewed => SortOrder::Desc,
+            _ => SortOrder::Asc,
+        })
+    }
+
+    /// Check whether the user's rating of a file satisfies the rating filters
+    /// Unrated files only match when there are no rating filters.
+    pub fn matches_rating(&self, rating: Option<u8>) -> bool {
+        if self.min_rating.is_none() && self.max_rating.is_none() {
+            return true;
+        }
+        rating.is_some_and(|rating| {
+            self.min_rating.is_none_or(|min| rating >= min)
+                && self.max_rating.is_none_or(|max| rating <= max)
+        })
+    }
+
+    /// Whether the query has filters that need more than the media cache to check:
+    /// audio tags, user tags, favorites or ratings
+    pub fn needs_lookups(&self) -> bool {
+        self.has_audio_tag_filters()
+            || self.tag.is_some()
+            || self.favorites == Some(true)
+            || self.min_rating.is_some()
+            || self.max_rating.is_some()
+    }
+
+    /// Whether the query filters on audio tags, which aren't part of `FilterResult`
+    pub fn has_audio_tag_filters(&self) -> bool {
+        self.artist.is_some() || self.album.is_some() || self.title.is_some() || self.genre.is_some()
+    }
+
+    /// Check whether audio tags satisfy the tag filters; files without tags never match
+    pub fn matches_audio_tags(&self, tags: Option<&AudioTags>) -> bool {
+        let Some(tags) = tags else {
+            return !self.has_audio_tag_filters();
+        };
+        let contains = |value: &Option<String>, filter: &Option<String>| match filter {
+            None => true,
+            Some(filter) => value
+                .as_ref()
+                .is_some_and(|v| v.to_lowercase().contains(&filter.to_lowercase())),
+        };
+        contains(&tags.artist, &self.artist)
+            && contains(&tags.album, &self.album)
+            && contains(&tags.title, &self.title)
+            && contains(&tags.genre, &self.genre)
+    }
+
+    /// Normalized tags from the `tag` filter; invalid tags are ignored
+    pub fn tags(&self) -> Vec<String> {
+        self.tag
+            .as_deref()
+            .map(|tags| tags.split(',').filter_map(tags::normalize_tag).collect())
+            .unwrap_or_default()
+    }
+
+    /// The path prefix filter without leading slashes
+    pub fn path_prefix(&self) -> Option<&str> {
+        self.path_prefix
+            .as_deref()
+            .map(|prefix| prefix.trim_start_matches('/'))
+            .filter(|prefix| !prefix.is_empty())
+    }
+}
+
+/// Keep only the files whose audio tags match the query's tag filters
+/// Uses the index when available; otherwise each candidate's tags are read from disk.
+async fn filter_by_audio_tags(
+    state: &AppState,
+    query: &FilterQuery,
+    mut items: Vec<FilterResult>,
+) -> Result<Vec<FilterResult>, AppError> {
+    if !query.has_audio_tag_filters() {
+        return Ok(items);
+    }
+
+    if let Some(index) = &state.index {
+        let matching: std::collections::HashSet<String> = index
+            .files(query)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to query media index: {:?}", e);
+                AppError::InternalError
+            })?
+            .into_iter()
+            .map(|item| item.path)
+            .collect();
+        items.retain(|item| matching.contains(&item.path));
+        return Ok(items);
+    }
+
+    let media_dir = state.media_dir.clone();
+    let tag_query = FilterQuery {
+        artist: query.artist.clone(),
+        album: query.album.clone(),
+        title: query.title.clone(),
+        genre: query.genre.clone(),
+        ..Default::default()
+    };
+    tokio::task::spawn_blocking(move || {
+        items.retain(|item| {
+            item.file_type == "audio"
+                && tag_query.matches_audio_tags(read_audio_tags(&media_dir.join(&item.path)).as_ref())
+        });
+        items
+    })
+    .await
+    .map_err(|_| AppError::InternalError)
+}
+
+/// Parse a size such as `1500`, `500KB` or `1.5GB` (1024-based units)
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        "T" | "TB" => 1 << 40,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Parse a time as RFC 3339, a date (midnight UTC) or Unix seconds
+pub fn parse_time(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let timestamp = match chrono::DateTime::parse_from_rfc3339(value) {
+        Ok(time) => time.timestamp(),
+        Err(_) => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+            .and_utc()
+            .timestamp(),
+    };
+    Some(timestamp.max(0) as u64)
+}
+
+fn deserialize_size<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(value) => parse_size(&value)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid size: {:?}", value))),
+    }
+}
+
+fn deserialize_time<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(value) => parse_time(&value)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid time: {:?}", value))),
+    }
 }
 
 /// Pagination query parameters
@@ -1022,34 +1942,70 @@ pub struct PaginationQuery {
     pub page: Option<usize>,
     /// Number of items per page
     pub per_page: Option<usize>,
+    /// Sort directory entries by name, size, mtime or type
+    pub sort: Option<ListingSort>,
+    /// Sort direction (asc or desc)
+    pub order: Option<SortOrder>,
+    /// List the media files of all subdirectories on one page (default false)
+    #[serde(default)]
+    pub recursive: bool,
+    /// How many directory levels a recursive listing descends (default 8, max 32)
+    pub depth: Option<usize>,
+}
+
+impl PaginationQuery {
+    pub fn depth(&self) -> usize {
+        self.depth.unwrap_or(DEFAULT_FLATTEN_DEPTH).min(MAX_FLATTEN_DEPTH)
+    }
 }
 
 /// Filter response
 #[derive(Debug, Serialize, utoipa::ToSchema)]
 pub struct FilterResponse {
     /// List of matching files
-    results: Vec<FilterResult>,
+    pub results: Vec<FilterMatch>,
     /// Total number of results
-    total: usize,
+    pub total: usize,
     /// Current page number
-    page: usize,
+    pub page: usize,
     /// Number of items per page
-    per_page: usize,
+    pub per_page: usize,
     /// Total number of pages
-    total_pages: usize,
+    pub total_pages: usize,
+    /// Whether a later page exists
+    pub has_next: bool,
+    /// Sort field applied
+    pub sort: SortField,
+    /// Sort order applied
+    pub order: SortOrder,
 }
 
 /// Individual filter result
-#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct FilterResult {
     /// Relative path to the file
-    path: String,
+    pub path: String,
     /// File name
-    name: String,
+    pub name: String,
     /// File size in bytes
-    size: u64,
+    pub size: u64,
     /// File type (image, video, or audio)
-    file_type: String,
+    pub file_type: String,
+    /// Last modification time (Unix seconds, 0 if unknown)
+    pub modified: u64,
+}
+
+/// A file matching a filter query
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct FilterMatch {
+    #[serde(flatten)]
+    pub file: FilterResult,
+    /// How well the name matches the `name` filter; higher is better (absent without one)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<i64>,
+    /// The signed-in user's star rating of the file (absent if unrated)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
 }
 
 /// Search and filter media files
@@ -1064,32 +2020,85 @@ pub struct FilterResult {
 )]
 pub async fn filter_handler(
     State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
     Query(query): Query<FilterQuery>,
 ) -> Result<Json<FilterResponse>, AppError> {
-    let mut results = Vec::new();
-
-    // Recursively search all files
-    search_directory(&state.media_dir, "", &query, &mut results).await?;
+    let results = match &state.index {
+        Some(index) if !query.fresh => index.files(&query).await.map_err(|e| {
+            tracing::error!("Failed to query media index: {:?}", e);
+            AppError::InternalError
+        })?,
+        _ => {
+            // The cache is incomplete until the first scan has finished; a store can only
+            // be read through it
+            let results = if state.store.is_none() && (query.fresh || !state.scan.is_ready()) {
+                let mut results = Vec::new();
+                search_directory(&state.media_dir, "", &query, &mut results).await?;
+                results
+            } else {
+                let cache = state.media_cache.read().await;
+                cache.items().filter(|item| query.matches(item)).cloned().collect()
+            };
+            filter_by_audio_tags(&state, &query, results).await?
+        }
+    };
+    let results = tags::filter_by_tags(&state, &query, results).await?;
+    let results = favorites::filter_favorites(&state, &query, principal.as_deref(), results).await?;
+    let ratings = ratings::user_ratings(&state, principal.as_deref()).await?;
+    let mut results: Vec<(FilterResult, i64, Option<u8>)> = results
+        .into_iter()
+        .filter(|item| state.acl.allows(principal.as_deref(), &item.path))
+        .filter_map(|item| {
+            let score = query.name_score(&item.name)?;
+            let rating = ratings.get(&item.path).copied();
+            query.matches_rating(rating).then_some((item, score, rating))
+        })
+        .collect();
 
-    // Sort by name
-    results.sort_by(|a, b| a.name.cmp(&b.name));
+    // Ties are broken by path so page boundaries are stable between requests
+    let (sort, order) = (query.sort_field(), query.sort_order());
+    let last_viewed = if sort == SortField::RecentlyViewed {
+        history::user_last_viewed(&state, principal.as_deref()).await?
+    } else {
+        HashMap::new()
+    };
+    results.sort_by(|(a, a_score, a_rating), (b, b_score, b_rating)| {
+        let ordering = match sort {
+            SortField::Name => state.name_order.cmp(&a.name, &b.name),
+            SortField::Size => a.size.cmp(&b.size),
+            SortField::Mtime => a.modified.cmp(&b.modified),
+            SortField::Score => a_score.cmp(b_score),
+            SortField::Rating => a_rating.cmp(b_rating),
+            SortField::RecentlyViewed => last_viewed.get(&a.path).cmp(&last_viewed.get(&b.path)),
+        }
+        .then_with(|| a.path.cmp(&b.path));
+        match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    });
 
     let total = results.len();
 
     // Pagination
-    let per_page = query.per_page.unwrap_or(50).max(1).min(500);
+    let per_page = query.per_page.unwrap_or(50).clamp(1, 500);
     let page = query.page.unwrap_or(1).max(1);
-    let total_pages = (total + per_page - 1) / per_page;
+    let total_pages = total.div_ceil(per_page);
 
     // Calculate pagination slice
     let start = (page - 1) * per_page;
-    let end = (start + per_page).min(total);
 
-    let paginated_results = if start < total {
-        results[start..end].to_vec()
-    } else {
-        Vec::new()
-    };
+    let has_name = query.name.is_some();
+    let paginated_results = results
+        .into_iter()
+        .skip(start)
+        .take(per_page)
+        .map(|(file, score, rating)| FilterMatch {
+            file,
+            score: has_name.then_some(score),
+            rating,
+        })
+        .collect();
 
     Ok(Json(FilterResponse {
         results: paginated_results,
@@ -1097,72 +2106,181 @@ pub async fn filter_handler(
         page,
         per_page,
         total_pages,
+        has_next: page < total_pages,
+        sort,
+        order,
     }))
 }
 
+/// Most files one random pick returns
+const MAX_RANDOM_COUNT: usize = 100;
+
+/// Scope and size of a random pick, on top of the filter parameters
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RandomQuery {
+    /// Only pick from this directory and its subdirectories (e.g. vacations/2023)
+    pub path: Option<String>,
+    /// Number of different files to pick (default 1, max 100)
+    pub count: Option<usize>,
+}
+
 /// Random media response
 #[derive(Debug, Serialize, utoipa::ToSchema)]
 pub struct RandomMediaResponse {
-    /// Relative path to the random media file
+    /// Relative path to the random media file (the first of `items`)
     path: String,
     /// File type (image, video, or audio)
     file_type: String,
+    /// The `count` picked files, in random order
+    items: Vec<FilterResult>,
 }
 
-/// Handler for getting a random media item from the entire collection
+/// Handler for getting random media items from the collection or a directory
+/// Fewer than `count` files are returned if fewer match.
 #[utoipa::path(
     get,
     path = "/api/random",
-    params(FilterQuery),
+    params(FilterQuery, RandomQuery),
     responses(
-        (status = 200, description = "Random media item", body = RandomMediaResponse)
+        (status = 200, description = "Random media items", body = RandomMediaResponse),
+        (status = 403, description = "Directory not accessible"),
+        (status = 404, description = "No matching media")
     ),
     tag = "media"
 )]
 pub async fn random_media_handler(
     State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
     Query(query): Query<FilterQuery>,
+    Query(random): Query<RandomQuery>,
 ) -> Result<Json<RandomMediaResponse>, AppError> {
-    // Read from cache
+    let dir = random.path.as_deref().unwrap_or_default().trim_matches('/');
+    if !dir.is_empty() {
+        if dir.split('/').any(|part| matches!(part, "" | "." | "..")) {
+            return Err(AppError::InvalidPath);
+        }
+        if !state.acl.allows(principal.as_deref(), dir) {
+            return Err(AppError::Forbidden);
+        }
+    }
+    let count = random.count.unwrap_or(1).clamp(1, MAX_RANDOM_COUNT);
+
+    // Walk the directory for fresh results, otherwise read from the cache
+    let fresh = query.fresh && state.store.is_none();
+    let walked = if fresh {
+        if !state.media_dir.join(dir).is_dir() {
+            return Err(AppError::NotFound);
+        }
+        let mut walked = Vec::new();
+        search_directory(&state.media_dir, dir, &query, &mut walked).await?;
+        walked
+    } else {
+        Vec::new()
+    };
     let cache = state.media_cache.read().await;
-    let all_media = &cache.items;
+    let candidates: Box<dyn Iterator<Item = &FilterResult>> = if fresh {
+        Box::new(walked.iter())
+    } else {
+        Box::new(cache.items_in(dir, true))
+    };
 
-    // Filter cached items based on query
-    let filtered_media: Vec<&FilterResult> = all_media
-        .iter()
-        .filter(|item| {
-            // Filter by type
-            if let Some(ref file_type) = query.file_type {
-                if &item.file_type != file_type {
-                    return false;
-                }
-            }
+    // Filter items based on query
+    let filtered_media: Vec<&FilterResult> = candidates
+        .filter(|item| state.acl.allows(principal.as_deref(), &item.path) && query.matches(item))
+        .collect();
 
-            // Filter by extension
-            if let Some(ref extension) = query.extension {
-                if !item.name.to_lowercase().ends_with(&extension.to_lowercase()) {
-                    return false;
-                }
-            }
+    // Pick random items
+    let items: Vec<FilterResult> = if query.needs_lookups() {
+        // Only audio files have audio tags; check tags outside the cache lock
+        let candidates: Vec<FilterResult> = filtered_media
+            .into_iter()
+            .filter(|item| !query.has_audio_tag_filters() || item.file_type == "audio")
+            .cloned()
+            .collect();
+        drop(cache);
+        let matching = filter_by_audio_tags(&state, &query, candidates).await?;
+        let matching = tags::filter_by_tags(&state, &query, matching).await?;
+        let mut matching =
+            favorites::filter_favorites(&state, &query, principal.as_deref(), matching).await?;
+        let ratings = ratings::user_ratings(&state, principal.as_deref()).await?;
+        matching.retain(|item| query.matches_rating(ratings.get(&item.path).copied()));
+        matching.choose_multiple(&mut rand::thread_rng(), count).cloned().collect()
+    } else {
+        filtered_media
+            .choose_multiple(&mut rand::thread_rng(), count)
+            .map(|item| (*item).clone())
+            .collect()
+    };
+    let first = items.first().ok_or(AppError::NotFound)?;
 
-            // Filter by name (fuzzy match)
-            if let Some(ref name) = query.name {
-                if !item.name.to_lowercase().contains(&name.to_lowercase()) {
-                    return false;
-                }
-            }
+    Ok(Json(RandomMediaResponse {
+        path: first.path.clone(),
+        file_type: first.file_type.clone(),
+        items,
+    }))
+}
 
-            true
-        })
-        .collect();
+/// Query parameters of the random directory picker
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RandomDirectoryQuery {
+    /// Only pick directories holding files of this type (image, video, or audio)
+    #[serde(rename = "type")]
+    pub file_type: Option<String>,
+}
 
-    // Pick a random item
-    let mut rng = rand::thread_rng();
-    let random_item = filtered_media.choose(&mut rng).ok_or(AppError::NotFound)?;
+/// A random directory and its media
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct RandomDirectoryResponse {
+    /// Relative path to the directory ("" for the media directory itself)
+    pub path: String,
+    /// Directory name
+    pub name: String,
+    /// Files of the requested type directly inside the directory, in name order
+    pub files: Vec<FilterResult>,
+}
 
-    Ok(Json(RandomMediaResponse {
-        path: random_item.path.clone(),
-        file_type: random_item.file_type.clone(),
+/// Handler for picking a random directory, such as an album to play
+/// Only directories with matching files directly inside them are candidates, so a
+/// folder holding nothing but subfolders is never picked.
+#[utoipa::path(
+    get,
+    path = "/api/random-directory",
+    params(RandomDirectoryQuery),
+    responses(
+        (status = 200, description = "Random directory", body = RandomDirectoryResponse),
+        (status = 400, description = "Invalid type"),
+        (status = 404, description = "No directory has matching files")
+    ),
+    tag = "media"
+)]
+pub async fn random_directory_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<RandomDirectoryQuery>,
+) -> Result<Json<RandomDirectoryResponse>, AppError> {
+    let file_type = query.file_type.as_deref();
+    if file_type.is_some_and(|t| !matches!(t, "image" | "video" | "audio")) {
+        return Err(AppError::InvalidInput("type must be image, video or audio"));
+    }
+    let matching = |item: &FilterResult| {
+        file_type.is_none_or(|t| item.file_type == t) && state.acl.allows(principal.as_deref(), &item.path)
+    };
+
+    let cache = state.media_cache.read().await;
+    let candidates: Vec<(&str, &[FilterResult])> = cache
+        .dirs()
+        .filter(|(_, files)| files.iter().any(matching))
+        .collect();
+    let (path, files) = candidates.choose(&mut rand::thread_rng()).ok_or(AppError::NotFound)?;
+
+    let mut files: Vec<FilterResult> = files.iter().filter(|item| matching(item)).cloned().collect();
+    files.sort_by(|a, b| state.name_order.cmp(&a.name, &b.name));
+    Ok(Json(RandomDirectoryResponse {
+        path: path.to_string(),
+        name: path.rsplit('/').next().unwrap_or_default().to_string(),
+        files,
     }))
 }
 
@@ -1179,23 +2297,9 @@ async fn search_directory(
         base_path.join(relative_path)
     };
 
-    let mut read_dir = fs::read_dir(&current_path)
-        .await
-        .map_err(|_| AppError::InternalError)?;
-
-    while let Some(entry) = read_dir
-        .next_entry()
-        .await
-        .map_err(|_| AppError::InternalError)?
-    {
-        let metadata = entry.metadata().await.map_err(|_| AppError::InternalError)?;
-        let file_name = entry.file_name().to_string_lossy().to_string();
-
-        // Skip hidden files
-        if file_name.starts_with('.') {
-            continue;
-        }
+    let listing = list_entries(&current_path, |name| media_type(name).is_some()).await?;
 
+    for (file_name, metadata) in listing {
         let entry_relative_path = if relative_path.is_empty() {
             file_name.clone()
         } else {
@@ -1203,6 +2307,9 @@ async fn search_directory(
         };
 
         if metadata.is_dir() {
+            if DirectoryMeta::load(&current_path.join(&file_name)).await.is_hidden() {
+                continue;
+            }
             // Recurse into subdirectory
             Box::pin(search_directory(
                 base_path,
@@ -1213,43 +2320,20 @@ async fn search_directory(
             .await?;
         } else {
             // Check if file matches filters
-            let file_type = if is_image(&file_name) {
-                "image"
-            } else if is_video(&file_name) {
-                "video"
-            } else if is_audio(&file_name) {
-                "audio"
-            } else {
+            let Some(file_type) = media_type(&file_name) else {
                 continue; // Skip non-media files
             };
 
-            // Apply filters
-            if let Some(ref type_filter) = query.file_type {
-                if file_type != type_filter {
-                    continue;
-                }
-            }
-
-            if let Some(ref ext_filter) = query.extension {
-                let file_ext = file_name.to_lowercase();
-                if !file_ext.ends_with(&ext_filter.to_lowercase()) {
-                    continue;
-                }
-            }
-
-            if let Some(ref name_filter) = query.name {
-                // Fuzzy matching: check if filter is contained in filename (case insensitive)
-                if !file_name.to_lowercase().contains(&name_filter.to_lowercase()) {
-                    continue;
-                }
-            }
-
-            results.push(FilterResult {
+            let result = FilterResult {
                 path: entry_relative_path.clone(),
                 name: file_name.clone(),
                 size: metadata.len(),
                 file_type: file_type.to_string(),
-            });
+                modified: modified_secs(&metadata),
+            };
+            if query.matches(&result) {
+                results.push(result);
+            }
         }
     }
 
@@ -1262,18 +2346,132 @@ pub enum AppError {
     NotFound,
     Forbidden,
     InvalidPath,
+    /// A request field failed validation; the message is returned to the client
+    InvalidInput(&'static str),
+    /// The request clashes with existing state, such as a file that is already there
+    Conflict(&'static str),
+    /// The request body exceeds a configured size limit
+    TooLarge,
+    /// An archive entry exceeds --max-archive-entry-size
+    EntryTooLarge,
+    /// An archive expands past --max-archive-ratio or --max-archive-output
+    ArchiveLimit,
+    /// A directory download would leave out files past the limits of a recursive listing
+    DownloadTooLarge,
     InternalError,
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, message) = match self {
+impl AppError {
+    /// HTTP status and client-facing message of the error
+    pub fn parts(&self) -> (StatusCode, &'static str) {
+        match self {
             AppError::NotFound => (StatusCode::NOT_FOUND, "Not found"),
             AppError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
             AppError::InvalidPath => (StatusCode::BAD_REQUEST, "Invalid path"),
+            AppError::InvalidInput(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::Conflict(message) => (StatusCode::CONFLICT, message),
+            AppError::TooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large"),
+            AppError::EntryTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "Archive entry is too large"),
+            AppError::ArchiveLimit => (StatusCode::PAYLOAD_TOO_LARGE, "Archive expands beyond the decompression limits"),
+            AppError::DownloadTooLarge => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "Directory has too many files or levels to download at once")
+            }
             AppError::InternalError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
-        };
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, message) = self.parts();
+        (status, Language::current().error(message)).into_response()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::http::Request;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    /// A media directory with `public/open.png` and `private/secret.png`, and a router serving
+    /// `/media/*path` from it the way the media handlers resolve paths. The access layers
+    /// under test go on top of the router.
+    pub(crate) fn media_router() -> (tempfile::TempDir, Router) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("public")).unwrap();
+        std::fs::create_dir_all(dir.path().join("private")).unwrap();
+        std::fs::write(dir.path().join("public/open.png"), b"open").unwrap();
+        std::fs::write(dir.path().join("private/secret.png"), b"secret").unwrap();
+
+        let media_dir = dir.path().canonicalize().unwrap();
+        let router = Router::new().route(
+            "/media/*path",
+            get(move |Path(path): Path<String>| async move {
+                validate_media_path(&media_dir, &path).map(|_| "served")
+            }),
+        );
+        (dir, router)
+    }
+
+    pub(crate) async fn status(router: &Router, uri: &str) -> StatusCode {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[test]
+    fn validate_media_path_does_not_decode_again() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("public")).unwrap();
+        std::fs::create_dir_all(dir.path().join("private")).unwrap();
+        std::fs::write(dir.path().join("100%25.jpg"), b"literal").unwrap();
+        std::fs::write(dir.path().join("100%.jpg"), b"decoded").unwrap();
+        let media_dir = dir.path().canonicalize().unwrap();
+
+        let file = validate_media_path(&media_dir, "100%25.jpg").ok().unwrap();
+        assert_eq!(file, media_dir.join("100%25.jpg"));
+        assert!(matches!(validate_media_path(&media_dir, "public/%2e%2e/private"), Err(AppError::NotFound)));
+        let dir = validate_media_path(&media_dir, "public/../private").ok().unwrap();
+        assert_eq!(dir, media_dir.join("private"));
+        assert!(matches!(validate_media_path(&media_dir, "public/../../etc"), Err(AppError::InvalidPath)));
+    }
+
+    #[test]
+    fn differs_from_request_compares_names_exactly() {
+        let media_dir = std::path::Path::new("/media");
+        let resolved = media_dir.join("Private/a.jpg");
+        assert!(differs_from_request(&resolved, media_dir, "private/a.jpg"));
+        assert!(differs_from_request(&resolved, media_dir, "Private/A.jpg"));
+        assert!(differs_from_request(&resolved, media_dir, "Private\\a.jpg"));
+        assert!(differs_from_request(&resolved, media_dir, "Private/a.jpg."));
+        assert!(!differs_from_request(&resolved, media_dir, "Private/a.jpg"));
+        assert!(!differs_from_request(&resolved, media_dir, "./Private//a.jpg"));
+        assert!(differs_from_request(std::path::Path::new("/elsewhere/a.jpg"), media_dir, "a.jpg"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_media_path_serves_symlinks_and_exact_names() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("Photos")).unwrap();
+        std::fs::write(dir.path().join("Photos/a.jpg"), b"a").unwrap();
+        std::os::unix::fs::symlink("Photos", dir.path().join("latest")).unwrap();
+        let media_dir = dir.path().canonicalize().unwrap();
+
+        let file = resolve_media_path(&media_dir, "latest/a.jpg").ok().unwrap();
+        assert_eq!(file, media_dir.join("Photos/a.jpg"));
+        assert!(resolve_media_path(&media_dir, "Photos/a.jpg").is_ok());
+        // Found on a case-insensitive filesystem, but not spelled as on disk
+        assert!(matches!(resolve_media_path(&media_dir, "photos/a.jpg"), Err(AppError::NotFound)));
+    }
 
-        (status, message).into_response()
+    #[test]
+    fn parent_path_of_media_paths() {
+        assert_eq!(parent_path("a/b/c.jpg"), "a/b");
+        assert_eq!(parent_path("a/b/"), "a");
+        assert_eq!(parent_path("a"), "");
+        assert_eq!(parent_path(""), "");
     }
 }
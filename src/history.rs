@@ -0,0 +1,454 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::Json,
+    Extension,
+};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState, FilterResult};
+use crate::index::MediaIndex;
+use crate::models::ContinueWatching;
+
+/// Videos watched for less than this many seconds aren't worth continuing
+const MIN_CONTINUE_POSITION: f64 = 10.0;
+
+/// Videos watched past this fraction of their length count as finished
+const FINISHED_FRACTION: f64 = 0.95;
+
+/// Number of videos shown in the "Continue watching" strip
+const CONTINUE_WATCHING_LIMIT: usize = 12;
+
+/// One entry of a user's view history
+pub struct HistoryRow {
+    pub file: FilterResult,
+    pub views: u64,
+    pub last_viewed_at: i64,
+    pub position: Option<f64>,
+    pub duration: Option<f64>,
+}
+
+impl MediaIndex {
+    /// Record that a user viewed a file, unless they opted out of history
+    pub async fn record_view(&self, username: String, path: String) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO history (username, path, views, last_viewed_at)
+                 SELECT ?1, ?2, 1, ?3
+                 WHERE NOT EXISTS (SELECT 1 FROM history_opt_out WHERE username = ?1)
+                 ON CONFLICT (username, path)
+                 DO UPDATE SET views = views + 1, last_viewed_at = excluded.last_viewed_at",
+                params![username, path, now],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Remember how far into a video or track a user got, unless they opted out of history
+    pub async fn set_position(
+        &self,
+        username: String,
+        path: String,
+        position: f64,
+        duration: f64,
+    ) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO history (username, path, views, last_viewed_at, position, duration)
+                 SELECT ?1, ?2, 1, ?3, ?4, ?5
+                 WHERE NOT EXISTS (SELECT 1 FROM history_opt_out WHERE username = ?1)
+                 ON CONFLICT (username, path)
+                 DO UPDATE SET last_viewed_at = excluded.last_viewed_at,
+                               position = excluded.position,
+                               duration = excluded.duration",
+                params![username, path, now, position, duration],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// A user's view history, most recently viewed first
+    pub async fn history(&self, username: String, limit: usize) -> anyhow::Result<Vec<HistoryRow>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT h.path, files.name, files.size, files.file_type, files.mtime,
+                        h.views, h.last_viewed_at, h.position, h.duration
+                 FROM history h LEFT JOIN files ON files.path = h.path
+                 WHERE h.username = ?1
+                 ORDER BY h.last_viewed_at DESC, h.path
+                 LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![username, limit as i64], |row| {
+                let path: String = row.get(0)?;
+                let name = row
+                    .get::<_, Option<String>>(1)?
+                    .unwrap_or_else(|| path.rsplit('/').next().unwrap_or_default().to_string());
+                let file_type = row
+                    .get::<_, Option<String>>(3)?
+                    .or_else(|| handlers::media_type(&name).map(str::to_string))
+                    .unwrap_or_default();
+                Ok(HistoryRow {
+                    file: FilterResult {
+                        size: row.get::<_, Option<u64>>(2)?.unwrap_or(0),
+                        modified: row.get::<_, Option<i64>>(4)?.unwrap_or(0).max(0) as u64,
+                        path,
+                        name,
+                        file_type,
+                    },
+                    views: row.get(5)?,
+                    last_viewed_at: row.get(6)?,
+                    position: row.get(7)?,
+                    duration: row.get(8)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// When a user last viewed each file, keyed by path
+    pub async fn last_viewed(&self, username: String) -> anyhow::Result<HashMap<String, i64>> {
+        self.with_conn(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT path, last_viewed_at FROM history WHERE username = ?1")?;
+            let rows = stmt.query_map([username], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Delete a user's view history
+    pub async fn clear_history(&self, username: String) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM history WHERE username = ?1", [username])
+                .map(|_| ())
+        })
+        .await
+    }
+
+    /// Whether a user's views are recorded
+    pub async fn history_enabled(&self, username: String) -> anyhow::Result<bool> {
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT 1 FROM history_opt_out WHERE username = ?1",
+                [username],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|opted_out| opted_out.is_none())
+        })
+        .await
+    }
+
+    /// Opt a user in to or out of view history; opting out deletes their history
+    pub async fn set_history_enabled(&self, username: String, enabled: bool) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            if enabled {
+                tx.execute("DELETE FROM history_opt_out WHERE username = ?1", [&username])?;
+            } else {
+                tx.execute(
+                    "INSERT OR IGNORE INTO history_opt_out (username, opted_out_at) VALUES (?1, ?2)",
+                    params![username, now],
+                )?;
+                tx.execute("DELETE FROM history WHERE username = ?1", [&username])?;
+            }
+            tx.commit()
+        })
+        .await
+    }
+}
+
+/// Whether a media request starts a new view rather than continuing one
+/// Players fetch videos in many range requests; only one starting at byte 0 counts.
+pub fn is_new_view(headers: &HeaderMap) -> bool {
+    match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        None => true,
+        Some(range) => range.trim().starts_with("bytes=0-"),
+    }
+}
+
+/// Record a view in the background so serving the file isn't delayed
+pub fn record_view(state: &AppState, principal: Option<&Principal>, path: &str) {
+    let (Some(index), Some(principal)) = (state.index.clone(), principal) else {
+        return;
+    };
+    let username = principal.username.clone();
+    let path = path.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = index.record_view(username, path).await {
+            tracing::warn!("Failed to record view: {:?}", e);
+        }
+    });
+}
+
+/// When the signed-in user last viewed each file; empty for anonymous users or without an index
+pub async fn user_last_viewed(
+    state: &AppState,
+    principal: Option<&Principal>,
+) -> Result<HashMap<String, i64>, AppError> {
+    let (Some(index), Some(principal)) = (&state.index, principal) else {
+        return Ok(HashMap::new());
+    };
+    index.last_viewed(principal.username.clone()).await.map_err(|e| {
+        tracing::error!("Failed to read view history: {:?}", e);
+        AppError::InternalError
+    })
+}
+
+/// Partly watched videos for the "Continue watching" strip, most recent first
+pub async fn continue_watching(state: &AppState, principal: Option<&Principal>) -> Vec<ContinueWatching> {
+    let (Some(index), Some(principal)) = (&state.index, principal) else {
+        return Vec::new();
+    };
+    let history = match index.history(principal.username.clone(), 200).await {
+        Ok(history) => history,
+        Err(e) => {
+            tracing::warn!("Failed to read view history: {:?}", e);
+            return Vec::new();
+        }
+    };
+
+    history
+        .into_iter()
+        .filter(|row| row.file.file_type == "video")
+        .filter(|row| state.acl.allows(Some(principal), &row.file.path))
+        .filter_map(|row| {
+            let (position, duration) = (row.position?, row.duration?);
+            (duration > 0.0
+                && position >= MIN_CONTINUE_POSITION
+                && position < duration * FINISHED_FRACTION)
+                .then(|| ContinueWatching {
+                    path: row.file.path,
+                    name: row.file.name,
+                    position: position.floor() as u64,
+                    percent: (position / duration * 100.0).round() as u8,
+                })
+        })
+        .take(CONTINUE_WATCHING_LIMIT)
+        .collect()
+}
+
+/// Query parameters for the view history
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    /// Maximum number of entries (default 50, max 500)
+    limit: Option<usize>,
+}
+
+/// A user's view history
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryResponse {
+    /// Whether views are being recorded for this user
+    pub enabled: bool,
+    /// Viewed files, most recent first
+    pub items: Vec<HistoryEntry>,
+}
+
+/// A viewed file
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub file: FilterResult,
+    /// Number of times the file was opened
+    pub views: u64,
+    /// When the file was last opened (RFC 3339)
+    pub last_viewed_at: String,
+    /// Last playback position in seconds, for videos and audio
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<f64>,
+    /// Length in seconds, as reported by the player
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+}
+
+/// Whether view history is recorded
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistorySettings {
+    /// Record views; turning this off also deletes the existing history
+    pub enabled: bool,
+}
+
+/// A playback position to remember
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlaybackPosition {
+    /// Seconds from the start
+    pub position: f64,
+    /// Length of the media in seconds
+    pub duration: f64,
+}
+
+/// List the signed-in user's recently viewed files
+#[utoipa::path(
+    get,
+    path = "/api/history",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "View history, most recent first", body = HistoryResponse),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn history_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryResponse>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+    let (enabled, history) = tokio::try_join!(
+        index.history_enabled(principal.username.clone()),
+        index.history(principal.username.clone(), limit),
+    )
+    .map_err(|e| {
+        tracing::error!("Failed to read view history: {:?}", e);
+        AppError::InternalError
+    })?;
+
+    let items = history
+        .into_iter()
+        .filter(|row| state.acl.allows(Some(&principal), &row.file.path))
+        .map(|row| HistoryEntry {
+            file: row.file,
+            views: row.views,
+            last_viewed_at: chrono::DateTime::from_timestamp(row.last_viewed_at, 0)
+                .unwrap_or_default()
+                .to_rfc3339(),
+            position: row.position,
+            duration: row.duration,
+        })
+        .collect();
+
+    Ok(Json(HistoryResponse { enabled, items }))
+}
+
+/// Delete the signed-in user's view history
+#[utoipa::path(
+    delete,
+    path = "/api/history",
+    responses(
+        (status = 200, description = "History deleted", body = HistorySettings),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn clear_history_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<HistorySettings>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    index
+        .clear_history(principal.username.clone())
+        .await
+        .and(index.history_enabled(principal.username.clone()).await)
+        .map(|enabled| Json(HistorySettings { enabled }))
+        .map_err(|e| {
+            tracing::error!("Failed to delete view history: {:?}", e);
+            AppError::InternalError
+        })
+}
+
+/// Turn view history on or off for the signed-in user
+#[utoipa::path(
+    put,
+    path = "/api/history/settings",
+    request_body = HistorySettings,
+    responses(
+        (status = 200, description = "Setting saved", body = HistorySettings),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn history_settings_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(settings): Json<HistorySettings>,
+) -> Result<Json<HistorySettings>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    index
+        .set_history_enabled(principal.username.clone(), settings.enabled)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to save history setting: {:?}", e);
+            AppError::InternalError
+        })?;
+    tracing::info!(username = %principal.username, enabled = settings.enabled, "View history setting changed");
+    Ok(Json(settings))
+}
+
+/// Remember the playback position of a video or audio file
+/// Players call this periodically; the position shows up in the history and feeds the
+/// "Continue watching" strip.
+#[utoipa::path(
+    put,
+    path = "/api/history/position/{path}",
+    params(("path" = String, Path, description = "File path relative to the media directory")),
+    request_body = PlaybackPosition,
+    responses(
+        (status = 200, description = "Position saved", body = PlaybackPosition),
+        (status = 400, description = "Invalid position or duration"),
+        (status = 403, description = "Not signed in, or path not accessible"),
+        (status = 404, description = "File not found, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn position_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+    Json(playback): Json<PlaybackPosition>,
+) -> Result<Json<PlaybackPosition>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+
+    if !playback.position.is_finite()
+        || !playback.duration.is_finite()
+        || playback.position < 0.0
+        || playback.duration <= 0.0
+    {
+        return Err(AppError::InvalidInput(
+            "Position must be at least 0 and duration greater than 0",
+        ));
+    }
+
+    let path = handlers::media_path(&path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !canonical.is_file() || !matches!(handlers::media_type(&path), Some("video" | "audio")) {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(Some(&principal), &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    index
+        .set_position(
+            principal.username.clone(),
+            path,
+            playback.position.min(playback.duration),
+            playback.duration,
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to save playback position: {:?}", e);
+            AppError::InternalError
+        })?;
+    Ok(Json(playback))
+}
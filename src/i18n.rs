@@ -0,0 +1,147 @@
+use axum::{
+    extract::Request,
+    http::header::{HeaderValue, ACCEPT_LANGUAGE, VARY},
+    middleware::Next,
+    response::Response,
+};
+use fluent_templates::fluent_bundle::FluentValue;
+use fluent_templates::{LanguageIdentifier, Loader};
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// UI strings of every supported language, from locales/<language>/*.ftl
+fluent_templates::static_loader! {
+    static LOCALES = {
+        locales: "./locales",
+        fallback_language: "en",
+        // Unicode isolation marks around arguments would end up in titles and attributes
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+/// Language used when the browser asks for none we have, set by `--language`
+static DEFAULT: OnceLock<Language> = OnceLock::new();
+
+tokio::task_local! {
+    /// Language negotiated for the request being handled
+    static CURRENT: Language;
+}
+
+/// A supported UI language
+#[derive(Debug, Clone, Copy)]
+pub struct Language(&'static LanguageIdentifier);
+
+impl Language {
+    /// The supported language for a tag such as "de" or "de-AT", matching on the primary language
+    pub fn parse(tag: &str) -> Option<Language> {
+        let wanted: LanguageIdentifier = tag.trim().parse().ok()?;
+        LOCALES
+            .locales()
+            .find(|lang| lang.language == wanted.language)
+            .map(Language)
+    }
+
+    /// Codes of all supported languages, such as "en, de, es"
+    pub fn supported() -> String {
+        let mut codes: Vec<String> = LOCALES.locales().map(|lang| lang.to_string()).collect();
+        codes.sort();
+        codes.join(", ")
+    }
+
+    /// Make this the language of requests that don't ask for a supported one
+    pub fn set_default(self) {
+        let _ = DEFAULT.set(self);
+    }
+
+    fn default() -> Language {
+        *DEFAULT.get_or_init(|| Language(LOCALES.fallback()))
+    }
+
+    /// The language of the current request, or the default outside of one
+    pub fn current() -> Language {
+        CURRENT.try_with(|lang| *lang).unwrap_or_else(|_| Language::default())
+    }
+
+    /// The best supported language of an `Accept-Language` header, by quality
+    fn negotiate(accept: &str) -> Option<Language> {
+        let mut ranges: Vec<(f32, &str)> = accept
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                (!tag.is_empty() && quality > 0.0).then_some((quality, tag))
+            })
+            .collect();
+        // Stable, so equally preferred languages keep the browser's order
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges.into_iter().find_map(|(_, tag)| match tag {
+            "*" => Some(Language::default()),
+            tag => Language::parse(tag),
+        })
+    }
+
+    /// Language code for the `lang` attribute
+    pub fn code(&self) -> String {
+        self.0.to_string()
+    }
+
+    /// The translation of a message
+    pub fn t(&self, id: &str) -> String {
+        LOCALES.lookup(self.0, id)
+    }
+
+    /// The translation of a message with a `$count` argument, for plurals
+    /// Takes the count by reference too, as templates pass their loop variables.
+    pub fn t_count(&self, id: &str, count: impl Borrow<usize>) -> String {
+        let args = HashMap::from([(Cow::Borrowed("count"), FluentValue::from(*count.borrow()))]);
+        LOCALES.lookup_with_args(self.0, id, &args)
+    }
+
+    /// The translation of a message with one text argument
+    pub fn t_arg(&self, id: &str, name: &'static str, value: &str) -> String {
+        self.t_args(id, &[(name, value.to_string())])
+    }
+
+    /// The translation of a message with several text arguments
+    pub fn t_args(&self, id: &str, args: &[(&'static str, String)]) -> String {
+        let args: HashMap<Cow<'static, str>, FluentValue> = args
+            .iter()
+            .map(|(name, value)| (Cow::Borrowed(*name), FluentValue::from(value.clone())))
+            .collect();
+        LOCALES.lookup_with_args(self.0, id, &args)
+    }
+
+    /// The translation of an English error message, or the message itself if there is none
+    /// Messages are looked up as `error-` followed by the message in lowercase, with every run
+    /// of other characters than letters and digits replaced by a dash.
+    pub fn error(&self, message: &str) -> String {
+        let mut id = String::from("error");
+        for word in message.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()) {
+            id.push('-');
+            id.push_str(&word.to_ascii_lowercase());
+        }
+        LOCALES
+            .try_lookup(self.0, &id)
+            .unwrap_or_else(|| message.to_string())
+    }
+}
+
+/// Pick the language of each request from its `Accept-Language` header
+/// Responses vary on the header, so caches don't serve one language to everyone.
+pub async fn negotiate_language(request: Request, next: Next) -> Response {
+    let lang = request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Language::negotiate)
+        .unwrap_or_else(Language::default);
+    let mut response = CURRENT.scope(lang, next.run(request)).await;
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("accept-language"));
+    response
+}
@@ -0,0 +1,147 @@
+use ab_glyph::{point, Font, PxScale};
+use anyhow::Context;
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, Response, StatusCode, Uri},
+};
+use image::{
+    codecs::ico::{IcoEncoder, IcoFrame},
+    imageops::{self, FilterType},
+    DynamicImage, ExtendedColorType, ImageFormat, Rgb, Rgba, RgbaImage,
+};
+
+use crate::branding::Branding;
+use crate::handlers::{AppError, AppState};
+use crate::share_card;
+
+/// Sizes packed into /favicon.ico
+const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+
+/// Size of /apple-touch-icon.png, what iOS uses for home screen bookmarks
+const TOUCH_ICON_SIZE: u32 = 180;
+
+/// Size of /icon.png, for high-density screens and Android home screens
+const ICON_SIZE: u32 = 192;
+
+/// How long browsers may reuse an icon; they change only with a restart
+const ICON_MAX_AGE: u64 = 24 * 60 * 60;
+
+/// Paths browsers and home screens look for icons at, which are served without a login
+pub const ICON_PATHS: &[&str] = &[
+    "/favicon.ico",
+    "/icon.png",
+    "/apple-touch-icon.png",
+    "/apple-touch-icon-precomposed.png",
+];
+
+/// Favicon and app icons, rendered once at startup from --logo or the built-in default
+pub struct Icons {
+    favicon: Bytes,
+    icon: Bytes,
+    touch_icon: Bytes,
+}
+
+impl Icons {
+    /// Resize the logo into every icon size, or draw the default icon if there is none
+    /// A logo the image decoder can't read, such as an SVG, falls back to the default.
+    pub fn render(branding: &Branding) -> anyhow::Result<Self> {
+        let source = match &branding.logo_file {
+            Some(logo) => match image::open(logo) {
+                Ok(logo) => Some(logo),
+                Err(e) => {
+                    tracing::warn!("Logo {:?} can't be used as an icon ({}), using the default icon", logo, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let icon = |size: u32| match &source {
+            Some(logo) => fit(logo, size),
+            None => default_icon(branding, size),
+        };
+
+        let frames = FAVICON_SIZES
+            .iter()
+            .map(|&size| IcoFrame::as_png(icon(size).as_raw(), size, size, ExtendedColorType::Rgba8))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to encode favicon")?;
+        let mut favicon = Vec::new();
+        IcoEncoder::new(&mut favicon)
+            .encode_images(&frames)
+            .context("Failed to encode favicon")?;
+
+        // iOS puts transparent touch icons on black
+        let mut touch_icon = RgbaImage::from_pixel(TOUCH_ICON_SIZE, TOUCH_ICON_SIZE, Rgba([255, 255, 255, 255]));
+        imageops::overlay(&mut touch_icon, &icon(TOUCH_ICON_SIZE), 0, 0);
+
+        Ok(Self {
+            favicon: favicon.into(),
+            icon: png(icon(ICON_SIZE))?,
+            touch_icon: png(touch_icon)?,
+        })
+    }
+}
+
+fn png(image: RgbaImage) -> anyhow::Result<Bytes> {
+    let mut data = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut data), ImageFormat::Png)
+        .context("Failed to encode icon")?;
+    Ok(data.into())
+}
+
+/// Scale a logo to fit a `size`×`size` square, centered on a transparent background
+fn fit(logo: &DynamicImage, size: u32) -> RgbaImage {
+    let scaled = logo.resize(size, size, FilterType::Lanczos3).to_rgba8();
+    let mut icon = RgbaImage::new(size, size);
+    let x = (size - scaled.width()) / 2;
+    let y = (size - scaled.height()) / 2;
+    imageops::overlay(&mut icon, &scaled, x as i64, y as i64);
+    icon
+}
+
+/// The first letter of the site title in white on the accent gradient
+fn default_icon(branding: &Branding, size: u32) -> RgbaImage {
+    let mut icon = share_card::gradient(size, size, branding.accent_color.as_deref());
+    let letter = branding
+        .title
+        .chars()
+        .find(|c| c.is_alphanumeric())
+        .and_then(|c| c.to_uppercase().next());
+    if let (Some(font), Some(letter)) = (share_card::font(), letter) {
+        let glyph = font
+            .glyph_id(letter)
+            .with_scale_and_position(PxScale::from(size as f32 * 0.7), point(0.0, 0.0));
+        if let Some(outline) = font.outline_glyph(glyph) {
+            let bounds = outline.px_bounds();
+            let x = ((size as f32 - bounds.width()) / 2.0).round() as i64;
+            let y = ((size as f32 - bounds.height()) / 2.0).round() as i64;
+            outline.draw(|gx, gy, coverage| {
+                let (px, py) = (x + gx as i64, y + gy as i64);
+                if (0..size as i64).contains(&px) && (0..size as i64).contains(&py) {
+                    let pixel = icon.get_pixel_mut(px as u32, py as u32);
+                    *pixel = share_card::mix(*pixel, Rgb([255, 255, 255]), coverage.clamp(0.0, 1.0));
+                }
+            });
+        }
+    }
+    DynamicImage::ImageRgb8(icon).to_rgba8()
+}
+
+/// Handler for /favicon.ico, /icon.png and /apple-touch-icon.png
+pub async fn serve_icon(State(state): State<AppState>, uri: Uri) -> Result<Response<Body>, AppError> {
+    let (data, mime) = match uri.path() {
+        "/favicon.ico" => (&state.icons.favicon, "image/x-icon"),
+        "/icon.png" => (&state.icons.icon, "image/png"),
+        "/apple-touch-icon.png" | "/apple-touch-icon-precomposed.png" => (&state.icons.touch_icon, "image/png"),
+        _ => return Err(AppError::NotFound),
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::CACHE_CONTROL, format!("public, max-age={}", ICON_MAX_AGE))
+        .body(Body::from(data.clone()))
+        .map_err(|_| AppError::InternalError)
+}
@@ -0,0 +1,907 @@
+use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::sync::{Arc, Mutex};
+
+use crate::cache::ScanProgress;
+use crate::decoding;
+use crate::ffprobe;
+use crate::handlers::{self, FilterQuery, FilterResult};
+use crate::metadata;
+use crate::netfs;
+use crate::models::{MediaStreams, VideoInfo};
+use crate::sidecar::DirectoryMeta;
+use crate::thumbnails;
+use crate::workers::Workers;
+
+/// How long a write waits for another server sharing the database to finish its own
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Schema migrations, applied in order; `PRAGMA user_version` records how many have run
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE files (
+        path       TEXT PRIMARY KEY,
+        name       TEXT NOT NULL,
+        file_type  TEXT NOT NULL,
+        size       INTEGER NOT NULL,
+        mtime      INTEGER NOT NULL,
+        sha256     TEXT,
+        width      INTEGER,
+        height     INTEGER,
+        taken_at   TEXT,
+        camera     TEXT,
+        artist     TEXT,
+        album      TEXT,
+        title      TEXT,
+        genre      TEXT,
+        year       INTEGER,
+        thumbnail  TEXT,
+        indexed_at INTEGER NOT NULL
+    );
+    CREATE INDEX files_name ON files(name);
+    CREATE INDEX files_type ON files(file_type);
+", "
+    -- Audio tags are now read with lofty (Vorbis, MP4, ...); force audio files to be reread
+    UPDATE files SET mtime = -1 WHERE file_type = 'audio';
+", "
+    -- Perceptual hash of images; force images to be reread so it gets computed
+    ALTER TABLE files ADD COLUMN dhash INTEGER;
+    UPDATE files SET mtime = -1 WHERE file_type = 'image';
+", "
+    -- User tags on files and directories; kept when files disappear so a moved-back file keeps them
+    CREATE TABLE tags (
+        path     TEXT NOT NULL,
+        tag      TEXT NOT NULL,
+        added_by TEXT NOT NULL,
+        added_at INTEGER NOT NULL,
+        PRIMARY KEY (path, tag)
+    );
+    CREATE INDEX tags_tag ON tags(tag);
+", "
+    CREATE TABLE favorites (
+        username TEXT NOT NULL,
+        path     TEXT NOT NULL,
+        added_at INTEGER NOT NULL,
+        PRIMARY KEY (username, path)
+    );
+", "
+    CREATE TABLE ratings (
+        username TEXT NOT NULL,
+        path     TEXT NOT NULL,
+        rating   INTEGER NOT NULL CHECK (rating BETWEEN 1 AND 5),
+        rated_at INTEGER NOT NULL,
+        PRIMARY KEY (username, path)
+    );
+", "
+    CREATE TABLE history (
+        username       TEXT NOT NULL,
+        path           TEXT NOT NULL,
+        views          INTEGER NOT NULL,
+        last_viewed_at INTEGER NOT NULL,
+        position       REAL,
+        duration       REAL,
+        PRIMARY KEY (username, path)
+    );
+    CREATE INDEX history_recent ON history (username, last_viewed_at);
+    CREATE TABLE history_opt_out (
+        username     TEXT PRIMARY KEY,
+        opted_out_at INTEGER NOT NULL
+    );
+", "
+    CREATE TABLE playlists (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        username   TEXT NOT NULL,
+        name       TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX playlists_user ON playlists (username);
+    CREATE TABLE playlist_tracks (
+        playlist_id INTEGER NOT NULL,
+        position    INTEGER NOT NULL,
+        path        TEXT NOT NULL,
+        PRIMARY KEY (playlist_id, position)
+    );
+", "
+    CREATE TABLE queue_state (
+        username   TEXT PRIMARY KEY,
+        current    INTEGER NOT NULL,
+        position   REAL NOT NULL,
+        shuffled   INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE queue_tracks (
+        username TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        original INTEGER NOT NULL,
+        path     TEXT NOT NULL,
+        PRIMARY KEY (username, position)
+    );
+", "
+    -- Track numbers and stream properties of audio files; force audio files to be reread
+    ALTER TABLE files ADD COLUMN track INTEGER;
+    ALTER TABLE files ADD COLUMN duration REAL;
+    ALTER TABLE files ADD COLUMN bitrate INTEGER;
+    UPDATE files SET mtime = -1 WHERE file_type = 'audio';
+", "
+    -- Peak levels of audio files, keyed by the modification time they were computed for
+    CREATE TABLE waveforms (
+        path     TEXT PRIMARY KEY,
+        mtime    INTEGER NOT NULL,
+        duration REAL NOT NULL,
+        peaks    BLOB NOT NULL
+    );
+", "
+    -- Stream details of videos read with ffprobe; probed marks videos ffprobe has looked at
+    ALTER TABLE files ADD COLUMN video_codec TEXT;
+    ALTER TABLE files ADD COLUMN audio_codec TEXT;
+    ALTER TABLE files ADD COLUMN probed INTEGER NOT NULL DEFAULT 0;
+", "
+    -- Chapters and audio/subtitle streams of videos as JSON; probe videos again to fill them in
+    ALTER TABLE files ADD COLUMN streams TEXT;
+    UPDATE files SET probed = 0 WHERE file_type = 'video';
+", "
+    -- EXIF GPS position of images in decimal degrees; force images to be reread
+    ALTER TABLE files ADD COLUMN latitude REAL;
+    ALTER TABLE files ADD COLUMN longitude REAL;
+    CREATE INDEX files_location ON files(latitude, longitude);
+    UPDATE files SET mtime = -1 WHERE file_type = 'image';
+", "
+    -- Completion time of the last sync, for servers sharing the database that don't sync it
+    CREATE TABLE sync_state (
+        id        INTEGER PRIMARY KEY CHECK (id = 1),
+        synced_at INTEGER NOT NULL
+    );
+"];
+
+/// Persistent SQLite index of the media directory
+/// Stores sizes, modification times, content hashes and EXIF/audio tags so the media
+/// cache can be rebuilt without rereading unchanged files, including across restarts.
+#[derive(Clone)]
+pub struct MediaIndex {
+    conn: Arc<Mutex<Connection>>,
+    /// ffprobe executable used to read the streams of videos, if configured
+    ffprobe: Option<Arc<PathBuf>>,
+    /// Pool the hashing and metadata extraction of a sync run on
+    workers: Workers,
+    /// Most bytes decoding an image for its perceptual hash may allocate
+    max_decode_memory: u64,
+    /// Lock file electing the one server that syncs a shared database
+    lock_path: PathBuf,
+    /// The held lock, once this server is the one syncing
+    writer_lock: Arc<Mutex<Option<File>>>,
+    /// Whether this server may sync the index; if not, `doggygallery index` keeps it up to date
+    syncs: bool,
+}
+
+/// Outcome of synchronizing the index with the media directory
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+/// Metadata extracted from one media file
+#[derive(Default)]
+struct IndexedFile {
+    path: String,
+    name: String,
+    file_type: &'static str,
+    size: u64,
+    mtime: i64,
+    sha256: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    taken_at: Option<String>,
+    camera: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    dhash: Option<u64>,
+    artist: Option<String>,
+    album: Option<String>,
+    title: Option<String>,
+    genre: Option<String>,
+    year: Option<u32>,
+    track: Option<u32>,
+    duration: Option<f64>,
+    bitrate: Option<u32>,
+    video_codec: Option<String>,
+    audio_codec: Option<String>,
+    probed: bool,
+    /// JSON of the video's `MediaStreams`, if it has any
+    streams: Option<String>,
+}
+
+impl IndexedFile {
+    fn set_video_info(&mut self, info: VideoInfo, streams: MediaStreams) {
+        self.width = info.width;
+        self.height = info.height;
+        self.duration = info.duration;
+        self.bitrate = info.bitrate;
+        self.video_codec = info.video_codec;
+        self.audio_codec = info.audio_codec;
+        self.streams = streams_json(&streams);
+    }
+}
+
+fn streams_json(streams: &MediaStreams) -> Option<String> {
+    if streams.is_empty() {
+        None
+    } else {
+        serde_json::to_string(streams).ok()
+    }
+}
+
+impl MediaIndex {
+    /// Open (or create) the index database and bring its schema up to date
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create index directory: {:?}", parent))?;
+        }
+
+        let mut conn = Connection::open(path)
+            .with_context(|| format!("Failed to open index database: {:?}", path))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+
+        // Each step rereads the version under a write lock, in case another server
+        // sharing the database migrated it first
+        loop {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let version: usize = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+            if version > MIGRATIONS.len() {
+                anyhow::bail!(
+                    "Index database {:?} was created by a newer version of DoggyGallery",
+                    path
+                );
+            }
+            let Some(migration) = MIGRATIONS.get(version) else {
+                break;
+            };
+            tx.execute_batch(migration)
+                .with_context(|| format!("Failed to migrate index database to version {}", version + 1))?;
+            tx.pragma_update(None, "user_version", version + 1)?;
+            tx.commit()?;
+        }
+
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            ffprobe: None,
+            workers: Workers::default(),
+            max_decode_memory: decoding::DEFAULT_MAX_MEMORY,
+            lock_path: lock_path.into(),
+            writer_lock: Arc::new(Mutex::new(None)),
+            syncs: true,
+        })
+    }
+
+    /// Whether this server syncs the index with the media directory
+    /// Servers sharing one database elect a single writer: the first to take an advisory
+    /// lock on `<database>.lock` syncs it, and the others only read what it wrote. The lock
+    /// is held until the server exits; another server then takes over at its next refresh.
+    pub fn is_writer(&self) -> bool {
+        if !self.syncs {
+            return false;
+        }
+        let mut writer_lock = self.writer_lock.lock().unwrap_or_else(|e| e.into_inner());
+        if writer_lock.is_some() {
+            return true;
+        }
+
+        let file = match OpenOptions::new().create(true).truncate(false).write(true).open(&self.lock_path) {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::warn!("Failed to open index lock {:?}, syncing anyway: {}", self.lock_path, e);
+                return true;
+            }
+        };
+        match file.try_lock() {
+            Ok(()) => {
+                tracing::info!("Took the index lock {:?}; this server syncs the index", self.lock_path);
+                *writer_lock = Some(file);
+                true
+            }
+            Err(TryLockError::WouldBlock) => false,
+            Err(TryLockError::Error(e)) => {
+                tracing::warn!("Failed to lock {:?}, syncing anyway: {}", self.lock_path, e);
+                true
+            }
+        }
+    }
+
+    /// Leave syncing to another process, such as a scheduled `doggygallery index`, and only
+    /// read what it wrote
+    pub fn with_sync(mut self, syncs: bool) -> Self {
+        self.syncs = syncs;
+        self
+    }
+
+    /// Read the streams of videos with ffprobe while indexing
+    /// Videos indexed before ffprobe was configured are probed on the next sync.
+    pub fn with_ffprobe(mut self, ffprobe: Option<PathBuf>) -> Self {
+        self.ffprobe = ffprobe.map(Arc::new);
+        self
+    }
+
+    /// Hash and read new files on the server's shared worker pool
+    /// A sync takes one worker for as long as it walks the media directory.
+    pub fn with_workers(mut self, workers: Workers) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Memory limit for decoding images to compute their perceptual hashes
+    pub fn with_max_decode_memory(mut self, max_decode_memory: u64) -> Self {
+        self.max_decode_memory = max_decode_memory;
+        self
+    }
+
+    /// Run a closure against the connection on the blocking thread pool
+    pub(crate) async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut conn)
+        })
+        .await
+        .context("Index task panicked")?
+        .context("Index database query failed")
+    }
+
+    /// Number of files in the index
+    pub async fn len(&self) -> Result<usize> {
+        self.with_conn(|conn| conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0)))
+            .await
+    }
+
+    /// When the index was last synced (Unix seconds), or `None` if it never was
+    pub async fn last_synced(&self) -> Result<Option<i64>> {
+        self.with_conn(|conn| {
+            conn.query_row("SELECT synced_at FROM sync_state WHERE id = 1", [], |row| row.get(0))
+                .optional()
+        })
+        .await
+    }
+
+    /// Bring the index in line with the media directory
+    /// Only new files and files whose size or mtime changed are hashed and reread. With
+    /// ffprobe configured, unchanged videos that were never probed are probed without rehashing.
+    pub async fn sync(&self, media_dir: &Path, progress: Arc<ScanProgress>) -> Result<SyncSummary> {
+        let known = self
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT path, size, mtime, probed FROM files")?;
+                let rows = stmt.query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        (row.get::<_, u64>(1)?, row.get::<_, i64>(2)?, row.get::<_, bool>(3)?),
+                    ))
+                })?;
+                rows.collect::<rusqlite::Result<HashMap<_, _>>>()
+            })
+            .await?;
+
+        // Walk and extract metadata without holding the connection, so queries keep working
+        let media_dir = media_dir.to_path_buf();
+        let ffprobe = self.ffprobe.clone();
+        let max_decode_memory = self.max_decode_memory;
+        let (changed, probed, seen, unchanged) = self.workers.run(move || {
+            let mut changed = Vec::new();
+            let mut probed = Vec::new();
+            let mut seen = HashSet::new();
+            let mut unchanged = 0;
+            let ffprobe = ffprobe.as_deref().map(PathBuf::as_path);
+            walk(&media_dir, "", &progress, &mut |path, relative, name, file_type, metadata| {
+                progress.add_files(1);
+                let size = metadata.len();
+                let mtime = handlers::modified_secs(metadata) as i64;
+
+                match known.get(&relative) {
+                    Some(&(known_size, known_mtime, was_probed)) if (known_size, known_mtime) == (size, mtime) => {
+                        match ffprobe {
+                            Some(ffprobe) if file_type == "video" && !was_probed => {
+                                probed.push((relative.clone(), ffprobe::probe(ffprobe, path)));
+                            }
+                            _ => unchanged += 1,
+                        }
+                    }
+                    known_file => changed.push((
+                        known_file.is_some(),
+                        extract(path, relative.clone(), name, file_type, metadata, ffprobe, max_decode_memory),
+                    )),
+                }
+                seen.insert(relative);
+            });
+            (changed, probed, seen, unchanged)
+        })
+        .await
+        .context("Index walk panicked")?;
+
+        self.with_conn(move |conn| {
+            let now = chrono::Utc::now().timestamp();
+            // Take the write lock up front; other servers sharing the database wait for it
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut summary = SyncSummary {
+                unchanged,
+                ..Default::default()
+            };
+
+            {
+                let mut upsert = tx.prepare(
+                    "INSERT OR REPLACE INTO files
+                        (path, name, file_type, size, mtime, sha256, width, height, taken_at, camera,
+                         artist, album, title, genre, year, thumbnail, indexed_at, dhash,
+                         track, duration, bitrate, video_codec, audio_codec, probed, streams,
+                         latitude, longitude)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, NULL, ?16, ?17,
+                             ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26)",
+                )?;
+                for (existed, file) in &changed {
+                    upsert.execute(params![
+                        file.path,
+                        file.name,
+                        file.file_type,
+                        file.size,
+                        file.mtime,
+                        file.sha256,
+                        file.width,
+                        file.height,
+                        file.taken_at,
+                        file.camera,
+                        file.artist,
+                        file.album,
+                        file.title,
+                        file.genre,
+                        file.year,
+                        now,
+                        // SQLite integers are signed; the hash bits are stored as-is
+                        file.dhash.map(|hash| hash as i64),
+                        file.track,
+                        file.duration,
+                        file.bitrate,
+                        file.video_codec,
+                        file.audio_codec,
+                        file.probed,
+                        file.streams,
+                        file.latitude,
+                        file.longitude,
+                    ])?;
+                    if *existed {
+                        summary.updated += 1;
+                    } else {
+                        summary.added += 1;
+                    }
+                }
+
+                let mut update_probed = tx.prepare(
+                    "UPDATE files SET width = ?2, height = ?3, duration = ?4, bitrate = ?5,
+                                      video_codec = ?6, audio_codec = ?7, streams = ?8, probed = 1
+                     WHERE path = ?1",
+                )?;
+                for (path, probe) in probed {
+                    let (info, streams) = probe.unwrap_or_default();
+                    update_probed.execute(params![
+                        path,
+                        info.width,
+                        info.height,
+                        info.duration,
+                        info.bitrate,
+                        info.video_codec,
+                        info.audio_codec,
+                        streams_json(&streams),
+                    ])?;
+                    summary.updated += 1;
+                }
+
+                let mut paths = tx.prepare("SELECT path FROM files")?;
+                let stale: Vec<String> = paths
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .filter_map(|path| path.ok())
+                    .filter(|path| !seen.contains(path))
+                    .collect();
+                let mut delete = tx.prepare("DELETE FROM files WHERE path = ?1")?;
+                let mut delete_waveform = tx.prepare("DELETE FROM waveforms WHERE path = ?1")?;
+                for path in &stale {
+                    delete.execute([path])?;
+                    delete_waveform.execute([path])?;
+                }
+                summary.removed = stale.len();
+            }
+
+            tx.execute(
+                "INSERT INTO sync_state (id, synced_at) VALUES (1, ?1)
+                 ON CONFLICT(id) DO UPDATE SET synced_at = excluded.synced_at",
+                [now],
+            )?;
+            tx.commit()?;
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Files matching a filter query, sorted by name
+    /// The fuzzy `name` filter isn't applied here; callers score names themselves.
+    pub async fn files(&self, query: &FilterQuery) -> Result<Vec<FilterResult>> {
+        let file_type = query.file_type.clone();
+        let extension = query.extension.as_ref().map(|e| format!("%{}", e.to_lowercase()));
+        let min_size = query.min_size.map(|v| v as i64);
+        let max_size = query.max_size.map(|v| v as i64);
+        let modified_after = query.modified_after.map(|v| v as i64);
+        let modified_before = query.modified_before.map(|v| v as i64);
+        let path_prefix = query.path_prefix().map(str::to_string);
+        let contains = |value: &Option<String>| value.as_ref().map(|v| format!("%{}%", v.to_lowercase()));
+        let artist = contains(&query.artist);
+        let album = contains(&query.album);
+        let title = contains(&query.title);
+        let genre = contains(&query.genre);
+
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT path, name, size, file_type, mtime FROM files
+                 WHERE (?1 IS NULL OR file_type = ?1)
+                   AND (?2 IS NULL OR lower(name) LIKE ?2)
+                   AND (?3 IS NULL OR size >= ?3)
+                   AND (?4 IS NULL OR size <= ?4)
+                   AND (?5 IS NULL OR mtime >= ?5)
+                   AND (?6 IS NULL OR mtime < ?6)
+                   AND (?7 IS NULL OR substr(path, 1, length(?7)) = ?7)
+                   AND (?8 IS NULL OR lower(artist) LIKE ?8)
+                   AND (?9 IS NULL OR lower(album) LIKE ?9)
+                   AND (?10 IS NULL OR lower(title) LIKE ?10)
+                   AND (?11 IS NULL OR lower(genre) LIKE ?11)
+                 ORDER BY name",
+            )?;
+            let params = params![
+                file_type,
+                extension,
+                min_size,
+                max_size,
+                modified_after,
+                modified_before,
+                path_prefix,
+                artist,
+                album,
+                title,
+                genre
+            ];
+            let rows = stmt.query_map(params, |row| {
+                Ok(FilterResult {
+                    path: row.get(0)?,
+                    name: row.get(1)?,
+                    size: row.get(2)?,
+                    file_type: row.get(3)?,
+                    modified: row.get::<_, i64>(4)?.max(0) as u64,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Images that look like the given one, closest first
+    /// Returns `None` if the image isn't indexed or couldn't be hashed. Distance is the
+    /// number of differing bits between perceptual hashes (0 to 64).
+    pub async fn similar_images(
+        &self,
+        path: String,
+        max_distance: u32,
+    ) -> Result<Option<Vec<(FilterResult, u32)>>> {
+        self.with_conn(move |conn| {
+            let target: Option<i64> = match conn.query_row(
+                "SELECT dhash FROM files WHERE path = ?1 AND file_type = 'image'",
+                [&path],
+                |row| row.get(0),
+            ) {
+                Ok(hash) => hash,
+                Err(rusqlite::Error::QueryReturnedNoRows) => None,
+                Err(e) => return Err(e),
+            };
+            let Some(target) = target else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn.prepare(
+                "SELECT path, name, size, file_type, mtime, dhash FROM files
+                 WHERE file_type = 'image' AND dhash IS NOT NULL AND path != ?1",
+            )?;
+            let rows = stmt.query_map([&path], |row| {
+                let hash: i64 = row.get(5)?;
+                Ok((
+                    FilterResult {
+                        path: row.get(0)?,
+                        name: row.get(1)?,
+                        size: row.get(2)?,
+                        file_type: row.get(3)?,
+                        modified: row.get::<_, i64>(4)?.max(0) as u64,
+                    },
+                    (hash ^ target).count_ones(),
+                ))
+            })?;
+
+            let mut similar = Vec::new();
+            for row in rows {
+                let (file, distance) = row?;
+                if distance <= max_distance {
+                    similar.push((file, distance));
+                }
+            }
+            similar.sort_by(|(a, a_distance), (b, b_distance)| {
+                a_distance.cmp(b_distance).then_with(|| a.path.cmp(&b.path))
+            });
+            Ok(Some(similar))
+        })
+        .await
+    }
+
+    /// Audio tags of every tagged audio file, keyed by path
+    pub async fn audio_tags(&self) -> Result<HashMap<String, metadata::AudioTags>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT path, artist, album, title, genre, year, track FROM files
+                 WHERE file_type = 'audio'
+                   AND COALESCE(artist, album, title, genre, year) IS NOT NULL",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    metadata::AudioTags {
+                        artist: row.get(1)?,
+                        album: row.get(2)?,
+                        title: row.get(3)?,
+                        genre: row.get(4)?,
+                        year: row.get(5)?,
+                        track: row.get(6)?,
+                    },
+                ))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Record whether a thumbnail could be generated for a file
+    pub async fn set_thumbnail_state(&self, path: String, ok: bool) -> Result<()> {
+        let state = if ok { "ok" } else { "failed" };
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE files SET thumbnail = ?2 WHERE path = ?1 AND thumbnail IS NOT ?2",
+                params![path, state],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+}
+
+/// `doggygallery index`: sync the index database with the media directory, hashing and
+/// reading new and changed files, then optionally fill the thumbnail cache
+pub async fn run_index_command(
+    media_dir: PathBuf,
+    index_db: PathBuf,
+    ffprobe: Option<PathBuf>,
+    thumbnail_cache_dir: Option<PathBuf>,
+    sizes: Vec<u32>,
+    jobs: Option<usize>,
+    max_decode_memory: u64,
+) -> Result<()> {
+    let media_dir = dunce::canonicalize(&media_dir)
+        .with_context(|| format!("Media directory not found: {:?}", media_dir))?;
+    if let Some(ffprobe) = &ffprobe {
+        ffprobe::check(ffprobe)?;
+    }
+    let index = MediaIndex::open(&index_db)?
+        .with_ffprobe(ffprobe)
+        .with_max_decode_memory(max_decode_memory.saturating_mul(1024 * 1024));
+    if !index.is_writer() {
+        anyhow::bail!(
+            "Another process is syncing {:?}; start servers sharing it with --no-index-sync to leave syncing to this command",
+            index_db
+        );
+    }
+
+    let progress = ScanProgress::new();
+    let bar = ProgressBar::new_spinner().with_style(
+        ProgressStyle::with_template("{spinner} {msg} ({elapsed})").context("Invalid progress bar template")?,
+    );
+    bar.enable_steady_tick(Duration::from_millis(100));
+    let summary = {
+        let sync = index.sync(&media_dir, progress.clone());
+        tokio::pin!(sync);
+        loop {
+            tokio::select! {
+                summary = &mut sync => break summary?,
+                _ = tokio::time::sleep(Duration::from_millis(250)) => {
+                    let status = progress.status(false);
+                    bar.set_message(format!(
+                        "{} files in {} directories",
+                        status.files_scanned, status.directories_scanned
+                    ));
+                }
+            }
+        }
+    };
+    bar.finish_and_clear();
+
+    let status = progress.status(true);
+    for error in &status.errors {
+        println!("Skipped {}", error);
+    }
+    println!(
+        "Indexed {} files in {:.1?}: {} added, {} updated, {} removed, {} unchanged",
+        index.len().await?,
+        bar.elapsed(),
+        summary.added,
+        summary.updated,
+        summary.removed,
+        summary.unchanged
+    );
+
+    if let Some(thumbnail_cache_dir) = thumbnail_cache_dir {
+        tokio::task::spawn_blocking(move || {
+            thumbnails::run_thumbnails_command(media_dir, thumbnail_cache_dir, sizes, jobs, max_decode_memory)
+        })
+        .await??;
+    }
+    Ok(())
+}
+
+/// Recursively visit media files below `dir`, skipping hidden entries, directories hidden by
+/// their sidecar and unreadable directories
+pub fn walk(
+    dir: &Path,
+    relative: &str,
+    progress: &ScanProgress,
+    visit: &mut dyn FnMut(&Path, String, String, &'static str, &std::fs::Metadata),
+) {
+    if !relative.is_empty() && DirectoryMeta::load_blocking(dir).is_hidden() {
+        return;
+    }
+    let entries = match netfs::list_dir(dir, |name| handlers::media_type(name).is_some()) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Skipping unreadable directory {:?}: {}", dir, e);
+            progress.record_error(format!("{}: {}", relative, e));
+            return;
+        }
+    };
+    progress.add_directory();
+
+    for (name, metadata) in entries {
+        let entry_relative = if relative.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative, name)
+        };
+
+        if metadata.is_dir() {
+            walk(&dir.join(&name), &entry_relative, progress, visit);
+        } else if let Some(file_type) = handlers::media_type(&name) {
+            visit(&dir.join(&name), entry_relative, name, file_type, &metadata);
+        }
+    }
+}
+
+/// Hash a file and read its EXIF, audio tag or video stream metadata; unreadable metadata is
+/// left empty. Videos are only probed when `ffprobe` is given.
+fn extract(
+    path: &Path,
+    relative: String,
+    name: String,
+    file_type: &'static str,
+    metadata: &std::fs::Metadata,
+    ffprobe: Option<&Path>,
+    max_decode_memory: u64,
+) -> IndexedFile {
+    let mut file = IndexedFile {
+        path: relative,
+        name,
+        file_type,
+        size: metadata.len(),
+        mtime: handlers::modified_secs(metadata) as i64,
+        sha256: hash_file(path).ok(),
+        ..Default::default()
+    };
+
+    match file_type {
+        "image" => {
+            if let Ok((width, height)) = image::image_dimensions(path) {
+                file.width = Some(width);
+                file.height = Some(height);
+            }
+            file.dhash = metadata::dhash(path, max_decode_memory);
+            if let Ok(exif) = read_exif(path) {
+                let field = |tag| {
+                    exif.get_field(tag, exif::In::PRIMARY)
+                        .map(|f| f.display_value().to_string().trim_matches('"').trim().to_string())
+                        .filter(|v| !v.is_empty())
+                };
+                file.taken_at = field(exif::Tag::DateTimeOriginal).or_else(|| field(exif::Tag::DateTime));
+                file.camera = match (field(exif::Tag::Make), field(exif::Tag::Model)) {
+                    (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+                    (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+                    (make, model) => make.or(model),
+                };
+                if let Some((latitude, longitude)) = gps_position(&exif) {
+                    file.latitude = Some(latitude);
+                    file.longitude = Some(longitude);
+                }
+            }
+        }
+        "audio" => {
+            if let Some((tags, properties)) = metadata::read_audio_file(path) {
+                file.artist = tags.artist;
+                file.album = tags.album;
+                file.title = tags.title;
+                file.genre = tags.genre;
+                file.year = tags.year;
+                file.track = tags.track;
+                file.duration = properties.duration;
+                file.bitrate = properties.bitrate;
+            }
+        }
+        "video" => {
+            if let Some(ffprobe) = ffprobe {
+                file.probed = true;
+                if let Some((info, streams)) = ffprobe::probe(ffprobe, path) {
+                    file.set_video_info(info, streams);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    file
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// EXIF GPS position in decimal degrees, negative south and west
+/// A position of exactly 0, 0 is treated as missing; cameras without a fix often write it.
+fn gps_position(exif: &exif::Exif) -> Option<(f64, f64)> {
+    let coordinate = |tag, reference, negative: u8| -> Option<f64> {
+        let exif::Value::Rational(parts) = &exif.get_field(tag, exif::In::PRIMARY)?.value else {
+            return None;
+        };
+        let degrees: f64 = parts
+            .iter()
+            .zip([1.0, 60.0, 3600.0])
+            .map(|(part, scale)| part.to_f64() / scale)
+            .sum();
+        let negative = match exif.get_field(reference, exif::In::PRIMARY).map(|f| &f.value) {
+            Some(exif::Value::Ascii(values)) => values.first().and_then(|v| v.first()) == Some(&negative),
+            _ => false,
+        };
+        Some(if negative { -degrees } else { degrees })
+    };
+    let latitude = coordinate(exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, b'S')?;
+    let longitude = coordinate(exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, b'W')?;
+    let valid = latitude.is_finite()
+        && longitude.is_finite()
+        && latitude.abs() <= 90.0
+        && longitude.abs() <= 180.0
+        && (latitude, longitude) != (0.0, 0.0);
+    valid.then_some((latitude, longitude))
+}
+
+fn read_exif(path: &Path) -> Result<exif::Exif, exif::Error> {
+    let file = std::fs::File::open(path)?;
+    exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file))
+}
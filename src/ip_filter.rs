@@ -0,0 +1,132 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// IP allowlist/denylist checked against the TCP peer address
+/// Deny rules win over allow rules; an empty allowlist allows everyone not denied.
+#[derive(Clone, Default)]
+pub struct IpFilter {
+    allow: Arc<Vec<IpNet>>,
+    deny: Arc<Vec<IpNet>>,
+}
+
+impl IpFilter {
+    pub fn new(allow: Vec<IpNet>, deny: Vec<IpNet>) -> Self {
+        Self {
+            allow: Arc::new(allow),
+            deny: Arc::new(deny),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Check whether a client address may connect
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        // IPv4 clients on a dual-stack socket show up as ::ffff:a.b.c.d
+        let ip = ip.to_canonical();
+
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Parse a CIDR range such as `192.168.1.0/24`; a bare address means a single host
+pub fn parse_cidr(value: &str) -> Result<IpNet, String> {
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("'{}' is not a valid CIDR range or IP address", value))
+}
+
+/// Middleware rejecting clients outside the allowlist or inside the denylist
+/// Runs before authentication so blocked clients never reach the login flow.
+pub async fn ip_filter_middleware(
+    State(filter): State<IpFilter>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if filter.allows(peer.ip()) {
+        return next.run(request).await;
+    }
+
+    metrics::counter!("doggygallery_ip_denied_total").increment(1);
+    tracing::warn!(
+        client_ip = %peer.ip(),
+        path = %request.uri().path(),
+        "Request rejected by IP filter"
+    );
+
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(Body::from("Forbidden"))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(allow: &[&str], deny: &[&str]) -> IpFilter {
+        let parse = |values: &[&str]| values.iter().map(|value| parse_cidr(value).unwrap()).collect();
+        IpFilter::new(parse(allow), parse(deny))
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_cidr_accepts_ranges_and_bare_addresses() {
+        assert_eq!(parse_cidr("192.168.1.0/24").unwrap().to_string(), "192.168.1.0/24");
+        assert_eq!(parse_cidr("10.0.0.7").unwrap().to_string(), "10.0.0.7/32");
+        assert_eq!(parse_cidr("fd00::/8").unwrap().to_string(), "fd00::/8");
+        assert_eq!(parse_cidr("::1").unwrap().to_string(), "::1/128");
+        assert!(parse_cidr("192.168.1.0/33").is_err());
+        assert!(parse_cidr("example.com").is_err());
+    }
+
+    #[test]
+    fn empty_filter_allows_everyone() {
+        let filter = rules(&[], &[]);
+        assert!(filter.is_empty());
+        assert!(filter.allows(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn allowlist_admits_only_its_ranges() {
+        let filter = rules(&["192.168.1.0/24"], &[]);
+        assert!(filter.allows(ip("192.168.1.20")));
+        assert!(!filter.allows(ip("192.168.2.20")));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let filter = rules(&["192.168.0.0/16"], &["192.168.1.0/24"]);
+        assert!(filter.allows(ip("192.168.2.1")));
+        assert!(!filter.allows(ip("192.168.1.1")));
+
+        let filter = rules(&[], &["10.0.0.0/8"]);
+        assert!(!filter.allows(ip("10.1.2.3")));
+        assert!(filter.allows(ip("11.1.2.3")));
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_match_ipv4_ranges() {
+        let filter = rules(&["127.0.0.0/8"], &[]);
+        assert!(filter.allows(ip("::ffff:127.0.0.1")));
+        assert!(!filter.allows(ip("::ffff:10.0.0.1")));
+    }
+}
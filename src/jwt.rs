@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::auth::Role;
+
+/// Minimum time between JWKS refetches triggered by an unknown key ID
+/// Prevents clients with garbage `kid`s from hammering the identity provider
+const JWKS_REFETCH_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Verifies JWTs issued by an external identity provider (Authelia, Keycloak, ...)
+pub struct JwtVerifier {
+    source: KeySource,
+    issuer: Option<String>,
+    audience: Option<String>,
+    /// Claim holding the username (dot-separated path for nested claims)
+    username_claim: String,
+    /// Claim holding the user's roles/groups (dot-separated path for nested claims)
+    roles_claim: String,
+    /// Role granting the admin role; everyone else is a viewer
+    admin_role: String,
+    /// Header carrying the raw JWT; `None` means `Authorization: Bearer`
+    pub header: Option<String>,
+}
+
+enum KeySource {
+    /// HMAC shared secret (HS256/HS384/HS512)
+    Secret(DecodingKey),
+    /// Public keys fetched from a JWKS endpoint
+    Jwks {
+        url: String,
+        client: reqwest::Client,
+        keys: RwLock<JwkSet>,
+        last_fetch: RwLock<Instant>,
+    },
+}
+
+/// Identity extracted from a verified JWT
+#[derive(Debug, Clone)]
+pub struct JwtPrincipal {
+    pub username: String,
+    pub roles: Vec<String>,
+    pub role: Role,
+}
+
+impl JwtVerifier {
+    /// Build a verifier from the JWT options, or `None` if JWT mode is disabled
+    pub async fn from_config(config: &Config) -> Result<Option<Arc<Self>>> {
+        let source = match (&config.jwt_secret, &config.jwt_jwks_url) {
+            (Some(_), Some(_)) => anyhow::bail!("--jwt-secret and --jwt-jwks-url are mutually exclusive"),
+            (Some(secret), None) => KeySource::Secret(DecodingKey::from_secret(secret.as_bytes())),
+            (None, Some(url)) => KeySource::jwks(url).await?,
+            (None, None) => return Ok(None),
+        };
+
+        Ok(Some(Arc::new(Self {
+            source,
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            username_claim: config.jwt_username_claim.clone(),
+            roles_claim: config.jwt_roles_claim.clone(),
+            admin_role: config.jwt_admin_role.clone(),
+            header: config.jwt_header.clone(),
+        })))
+    }
+
+    /// Build a JWKS-backed verifier for OpenID Connect ID tokens
+    /// Claim mapping follows the --jwt-*-claim options so both modes agree on usernames and roles
+    pub async fn for_oidc(config: &Config, jwks_url: &str, issuer: &str, client_id: &str) -> Result<Self> {
+        Ok(Self {
+            source: KeySource::jwks(jwks_url).await?,
+            issuer: Some(issuer.to_string()),
+            audience: Some(client_id.to_string()),
+            username_claim: config.jwt_username_claim.clone(),
+            roles_claim: config.jwt_roles_claim.clone(),
+            admin_role: config.jwt_admin_role.clone(),
+            header: None,
+        })
+    }
+
+    /// Verify a JWT's signature and standard claims, then map it to a principal
+    pub async fn verify(&self, token: &str) -> Result<JwtPrincipal> {
+        let claims = self.verify_claims(token).await?;
+        self.principal_from_claims(&claims)
+    }
+
+    /// Verify a JWT's signature and standard claims, returning the raw claims
+    pub async fn verify_claims(&self, token: &str) -> Result<serde_json::Value> {
+        let header = jsonwebtoken::decode_header(token).context("Malformed JWT header")?;
+
+        let (key, algorithm) = match &self.source {
+            KeySource::Secret(key) => {
+                if !matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+                    anyhow::bail!("Unexpected JWT algorithm {:?} for shared-secret mode", header.alg);
+                }
+                (key.clone(), header.alg)
+            }
+            KeySource::Jwks { .. } => {
+                if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+                    anyhow::bail!("HMAC-signed JWTs are not accepted in JWKS mode");
+                }
+                let kid = header.kid.as_deref().context("JWT has no key ID (kid)")?;
+                (self.jwks_key(kid).await?, header.alg)
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        match &self.issuer {
+            Some(issuer) => validation.set_issuer(&[issuer]),
+            None => validation.iss = None,
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let data = jsonwebtoken::decode::<serde_json::Value>(token, &key, &validation)
+            .context("JWT validation failed")?;
+
+        Ok(data.claims)
+    }
+
+    /// Map verified claims to a username, roles and access role
+    pub fn principal_from_claims(&self, claims: &serde_json::Value) -> Result<JwtPrincipal> {
+        let username = lookup_claim(claims, &self.username_claim)
+            .and_then(|v| v.as_str())
+            .or_else(|| claims.get("sub").and_then(|v| v.as_str()))
+            .context("JWT has no username claim")?
+            .to_string();
+
+        let roles: Vec<String> = match lookup_claim(claims, &self.roles_claim) {
+            Some(serde_json::Value::Array(values)) => values
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect(),
+            // Some providers send roles as a space/comma separated string
+            Some(serde_json::Value::String(value)) => value
+                .split([' ', ','])
+                .filter(|r| !r.is_empty())
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let role = if roles.iter().any(|r| r == &self.admin_role) {
+            Role::Admin
+        } else {
+            Role::Viewer
+        };
+
+        Ok(JwtPrincipal { username, roles, role })
+    }
+
+    /// Look up a JWKS key by ID, refetching the key set once if the ID is unknown
+    /// (identity providers rotate keys without notice)
+    async fn jwks_key(&self, kid: &str) -> Result<DecodingKey> {
+        let KeySource::Jwks { url, client, keys, last_fetch } = &self.source else {
+            anyhow::bail!("Not in JWKS mode");
+        };
+
+        if let Some(jwk) = keys.read().await.find(kid) {
+            return DecodingKey::from_jwk(jwk).context("Unsupported JWK");
+        }
+
+        let mut last_fetch = last_fetch.write().await;
+        if last_fetch.elapsed() >= JWKS_REFETCH_COOLDOWN {
+            *last_fetch = Instant::now();
+            match fetch_jwks(client, url).await {
+                Ok(new_keys) => {
+                    tracing::info!("Refreshed JWKS: {} signing key(s)", new_keys.keys.len());
+                    *keys.write().await = new_keys;
+                }
+                Err(e) => tracing::warn!("Failed to refresh JWKS: {:?}", e),
+            }
+        }
+
+        let keys = keys.read().await;
+        let jwk = keys.find(kid).with_context(|| format!("Unknown JWT key ID: {}", kid))?;
+        DecodingKey::from_jwk(jwk).context("Unsupported JWK")
+    }
+}
+
+impl KeySource {
+    /// Fetch the initial key set from a JWKS endpoint
+    async fn jwks(url: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create HTTP client for JWKS")?;
+        let keys = fetch_jwks(&client, url).await?;
+        tracing::info!("Loaded {} signing key(s) from JWKS: {}", keys.keys.len(), url);
+
+        Ok(KeySource::Jwks {
+            url: url.to_string(),
+            client,
+            keys: RwLock::new(keys),
+            last_fetch: RwLock::new(Instant::now()),
+        })
+    }
+}
+
+/// Fetch a JSON Web Key Set
+async fn fetch_jwks(client: &reqwest::Client, url: &str) -> Result<JwkSet> {
+    client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch JWKS from {}", url))?
+        .error_for_status()
+        .with_context(|| format!("JWKS endpoint returned an error: {}", url))?
+        .json::<JwkSet>()
+        .await
+        .with_context(|| format!("Failed to parse JWKS from {}", url))
+}
+
+/// Resolve a dot-separated claim path such as `realm_access.roles`
+fn lookup_claim<'a>(claims: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.').try_fold(claims, |value, key| value.get(key))
+}
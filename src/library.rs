@@ -0,0 +1,459 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::{Html, Json},
+    Extension,
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::handlers::{AppError, AppState, FilterResult};
+use crate::i18n::Language;
+use crate::index::MediaIndex;
+use crate::models::{AudioInfo, DirectoryEntry, DirectoryListing, EntryType};
+use crate::ratings;
+use crate::tags;
+use crate::templates::{LibraryCard, MusicLibraryTemplate, MusicPlayerTemplate};
+
+/// An indexed audio file with its tags
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LibraryTrack {
+    #[serde(flatten)]
+    pub file: FilterResult,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    /// Position of the track on its album
+    pub track: Option<u32>,
+    /// Playing time in seconds
+    pub duration: Option<f64>,
+    /// Average bitrate in kbit/s
+    pub bitrate: Option<u32>,
+}
+
+/// An artist and how much of their music is in the library
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArtistSummary {
+    pub name: String,
+    pub albums: usize,
+    pub tracks: usize,
+}
+
+/// An album, identified by its title and artist
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlbumSummary {
+    pub name: String,
+    pub artist: String,
+    /// Earliest year tagged on the album's tracks
+    pub year: Option<u32>,
+    pub tracks: usize,
+    /// First track of the album, for `/album-art`
+    pub cover: String,
+}
+
+/// A genre and how many tracks have it
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GenreSummary {
+    pub name: String,
+    pub tracks: usize,
+}
+
+/// Tag values that library tracks must match exactly
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct LibraryQuery {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+}
+
+impl LibraryQuery {
+    fn matches(&self, track: &LibraryTrack) -> bool {
+        let matches = |wanted: &Option<String>, value: &Option<String>| {
+            wanted.is_none() || wanted == value
+        };
+        matches(&self.artist, &track.artist)
+            && matches(&self.album, &track.album)
+            && matches(&self.genre, &track.genre)
+    }
+
+    /// Heading describing the selection, such as "Daft Punk · Discovery"
+    fn title(&self) -> String {
+        [&self.artist, &self.album, &self.genre]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+}
+
+impl MediaIndex {
+    /// Every indexed audio file with its tags, ordered by path
+    pub async fn library_tracks(&self) -> anyhow::Result<Vec<LibraryTrack>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT path, name, size, mtime, artist, album, title, genre, year,
+                        track, duration, bitrate
+                 FROM files WHERE file_type = 'audio' ORDER BY path",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(LibraryTrack {
+                    file: FilterResult {
+                        path: row.get(0)?,
+                        name: row.get(1)?,
+                        size: row.get(2)?,
+                        file_type: "audio".to_string(),
+                        modified: row.get::<_, i64>(3)?.max(0) as u64,
+                    },
+                    artist: row.get(4)?,
+                    album: row.get(5)?,
+                    title: row.get(6)?,
+                    genre: row.get(7)?,
+                    year: row.get(8)?,
+                    track: row.get(9)?,
+                    duration: row.get(10)?,
+                    bitrate: row.get(11)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+}
+
+/// Indexed audio files the user may access
+async fn accessible_tracks(
+    state: &AppState,
+    principal: Option<&Principal>,
+) -> Result<Vec<LibraryTrack>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let tracks = index.library_tracks().await.map_err(|e| {
+        tracing::error!("{:?}", e);
+        AppError::InternalError
+    })?;
+    Ok(tracks
+        .into_iter()
+        .filter(|track| state.acl.allows(principal, &track.file.path))
+        .collect())
+}
+
+/// Sort names alphabetically, ignoring case
+fn by_name(a: &str, b: &str) -> std::cmp::Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b))
+}
+
+fn artists(tracks: &[LibraryTrack]) -> Vec<ArtistSummary> {
+    let mut artists: BTreeMap<&str, (BTreeSet<&str>, usize)> = BTreeMap::new();
+    for track in tracks {
+        let Some(artist) = &track.artist else { continue };
+        let (albums, count) = artists.entry(artist).or_default();
+        if let Some(album) = &track.album {
+            albums.insert(album);
+        }
+        *count += 1;
+    }
+    let mut artists: Vec<ArtistSummary> = artists
+        .into_iter()
+        .map(|(name, (albums, tracks))| ArtistSummary {
+            name: name.to_string(),
+            albums: albums.len(),
+            tracks,
+        })
+        .collect();
+    artists.sort_by(|a, b| by_name(&a.name, &b.name));
+    artists
+}
+
+fn albums(tracks: &[LibraryTrack], artist: Option<&str>) -> Vec<AlbumSummary> {
+    let mut albums: BTreeMap<(&str, &str), AlbumSummary> = BTreeMap::new();
+    for track in tracks {
+        let (Some(album), Some(track_artist)) = (&track.album, &track.artist) else {
+            continue;
+        };
+        if artist.is_some_and(|artist| artist != track_artist) {
+            continue;
+        }
+        let summary = albums
+            .entry((track_artist, album))
+            .or_insert_with(|| AlbumSummary {
+                name: album.clone(),
+                artist: track_artist.clone(),
+                year: None,
+                tracks: 0,
+                cover: track.file.path.clone(),
+            });
+        summary.tracks += 1;
+        summary.year = match (summary.year, track.year) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+    let mut albums: Vec<AlbumSummary> = albums.into_values().collect();
+    albums.sort_by(|a, b| by_name(&a.name, &b.name).then_with(|| by_name(&a.artist, &b.artist)));
+    albums
+}
+
+fn genres(tracks: &[LibraryTrack]) -> Vec<GenreSummary> {
+    let mut genres: BTreeMap<&str, usize> = BTreeMap::new();
+    for track in tracks {
+        if let Some(genre) = &track.genre {
+            *genres.entry(genre).or_default() += 1;
+        }
+    }
+    let mut genres: Vec<GenreSummary> = genres
+        .into_iter()
+        .map(|(name, tracks)| GenreSummary {
+            name: name.to_string(),
+            tracks,
+        })
+        .collect();
+    genres.sort_by(|a, b| by_name(&a.name, &b.name));
+    genres
+}
+
+/// List artists, from the artist tags of indexed audio files
+#[utoipa::path(
+    get,
+    path = "/api/library/artists",
+    responses(
+        (status = 200, description = "Artists, alphabetically", body = Vec<ArtistSummary>),
+        (status = 404, description = "No index database configured")
+    ),
+    tag = "media"
+)]
+pub async fn artists_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Vec<ArtistSummary>>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    Ok(Json(artists(&tracks)))
+}
+
+/// List albums, optionally of one artist
+#[utoipa::path(
+    get,
+    path = "/api/library/albums",
+    params(LibraryQuery),
+    responses(
+        (status = 200, description = "Albums, alphabetically", body = Vec<AlbumSummary>),
+        (status = 404, description = "No index database configured")
+    ),
+    tag = "media"
+)]
+pub async fn albums_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<LibraryQuery>,
+) -> Result<Json<Vec<AlbumSummary>>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    Ok(Json(albums(&tracks, query.artist.as_deref())))
+}
+
+/// List genres
+#[utoipa::path(
+    get,
+    path = "/api/library/genres",
+    responses(
+        (status = 200, description = "Genres, alphabetically", body = Vec<GenreSummary>),
+        (status = 404, description = "No index database configured")
+    ),
+    tag = "media"
+)]
+pub async fn genres_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Vec<GenreSummary>>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    Ok(Json(genres(&tracks)))
+}
+
+/// List tracks by artist, album and/or genre
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks",
+    params(LibraryQuery),
+    responses(
+        (status = 200, description = "Matching tracks, by path", body = Vec<LibraryTrack>),
+        (status = 404, description = "No index database configured")
+    ),
+    tag = "media"
+)]
+pub async fn tracks_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<LibraryQuery>,
+) -> Result<Json<Vec<LibraryTrack>>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    Ok(Json(selected_tracks(tracks, &query)))
+}
+
+/// Tracks matching a query, ordered by album and track number
+/// The sort is stable, so tracks without a track number stay in path order.
+fn selected_tracks(mut tracks: Vec<LibraryTrack>, query: &LibraryQuery) -> Vec<LibraryTrack> {
+    tracks.retain(|track| query.matches(track));
+    tracks.sort_by(|a, b| {
+        (&a.album, a.track.unwrap_or(u32::MAX)).cmp(&(&b.album, b.track.unwrap_or(u32::MAX)))
+    });
+    tracks
+}
+
+fn query_value(value: &str) -> String {
+    utf8_percent_encode(value, NON_ALPHANUMERIC).to_string()
+}
+
+fn render_library(
+    state: &AppState,
+    view: &'static str,
+    heading: String,
+    cards: Vec<LibraryCard>,
+) -> Result<Html<String>, AppError> {
+    let template = MusicLibraryTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
+        view,
+        heading,
+        cards,
+        indexing: !state.scan.is_ready(),
+    };
+    Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
+}
+
+/// Handler for /music/artists - artists from the audio tags in the index
+pub async fn artists_page_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Html<String>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    let lang = Language::current();
+    let cards = artists(&tracks)
+        .into_iter()
+        .map(|artist| LibraryCard {
+            href: format!("/music/albums?artist={}", query_value(&artist.name)),
+            detail: format!(
+                "{}, {}",
+                lang.t_count("album-count", artist.albums),
+                lang.t_count("track-count", artist.tracks)
+            ),
+            name: artist.name,
+            cover: None,
+        })
+        .collect();
+    render_library(&state, "artists", lang.t("heading-artists"), cards)
+}
+
+/// Handler for /music/albums - albums from the audio tags in the index, optionally of one artist
+pub async fn albums_page_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<LibraryQuery>,
+) -> Result<Html<String>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    let lang = Language::current();
+    let cards = albums(&tracks, query.artist.as_deref())
+        .into_iter()
+        .map(|album| LibraryCard {
+            href: format!(
+                "/music/tracks?artist={}&album={}",
+                query_value(&album.artist),
+                query_value(&album.name)
+            ),
+            detail: match album.year {
+                Some(year) => format!("{} · {} · {}", album.artist, year, lang.t_count("track-count", album.tracks)),
+                None => format!("{} · {}", album.artist, lang.t_count("track-count", album.tracks)),
+            },
+            name: album.name,
+            cover: Some(album.cover),
+        })
+        .collect();
+    let heading = match &query.artist {
+        Some(artist) => lang.t_arg("heading-albums-by", "artist", artist),
+        None => lang.t("heading-albums"),
+    };
+    render_library(&state, "albums", heading, cards)
+}
+
+/// Handler for /music/genres - genres from the audio tags in the index
+pub async fn genres_page_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Html<String>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    let lang = Language::current();
+    let cards = genres(&tracks)
+        .into_iter()
+        .map(|genre| LibraryCard {
+            href: format!("/music/tracks?genre={}", query_value(&genre.name)),
+            detail: lang.t_count("track-count", genre.tracks),
+            name: genre.name,
+            cover: None,
+        })
+        .collect();
+    render_library(&state, "genres", lang.t("heading-genres"), cards)
+}
+
+/// Handler for /music/tracks - the tracks of an artist, album or genre in the music player
+/// All matching tracks are shown on one page, so play-all covers the whole selection.
+pub async fn tracks_page_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<LibraryQuery>,
+) -> Result<Html<String>, AppError> {
+    let tracks = accessible_tracks(&state, principal.as_deref()).await?;
+    let mut entries: Vec<DirectoryEntry> = selected_tracks(tracks, &query)
+        .into_iter()
+        .map(|track| DirectoryEntry {
+            name: track.file.name,
+            path: track.file.path,
+            entry_type: EntryType::Audio,
+            size: track.file.size,
+            modified: None,
+            tags: Vec::new(),
+            favorite: false,
+            rating: None,
+            audio: Some(AudioInfo {
+                artist: track.artist,
+                album: track.album,
+                title: track.title,
+                track: track.track,
+                duration: track.duration,
+                bitrate: track.bitrate,
+            }),
+            video: None,
+            title: None,
+            cover: None,
+        })
+        .collect();
+    tags::attach_tags(&state, &mut entries).await;
+    ratings::attach_ratings(&state, principal.as_deref(), &mut entries).await;
+
+    let total_items = entries.len();
+    let listing = DirectoryListing {
+        current_path: query.title(),
+        parent_path: None,
+        breadcrumbs: Vec::new(),
+        title: None,
+        description: None,
+        entries,
+        page: 1,
+        per_page: total_items.max(1),
+        total_items,
+        total_pages: 1,
+    };
+    let template = MusicPlayerTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
+        listing,
+        indexing: !state.scan.is_ready(),
+        playlists: principal.is_some(),
+        library: true,
+        feed_url: None,
+        sort_controls: Vec::new(),
+        flattened: false,
+    };
+    Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
+}
@@ -0,0 +1,131 @@
+use axum::{
+    body::Body,
+    http::{header, StatusCode},
+    response::Response,
+    BoxError,
+};
+use axum_server::accept::Accept;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Acceptor wrapper capping the number of open connections
+/// Connections beyond the cap are closed immediately, before the TLS handshake.
+#[derive(Clone)]
+pub struct ConnectionLimitAcceptor<A> {
+    inner: A,
+    permits: Arc<Semaphore>,
+}
+
+impl<A> ConnectionLimitAcceptor<A> {
+    pub fn new(inner: A, max_connections: usize) -> Self {
+        Self {
+            inner,
+            permits: Arc::new(Semaphore::new(max_connections)),
+        }
+    }
+}
+
+impl<A, I, S> Accept<I, S> for ConnectionLimitAcceptor<A>
+where
+    A: Accept<I, S>,
+    A::Future: Send + 'static,
+    A::Stream: Send,
+    A::Service: Send,
+{
+    type Stream = LimitedStream<A::Stream>;
+    type Service = A::Service;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+            tracing::warn!("Connection limit reached - closing new connection");
+            return Box::pin(async {
+                Err(io::Error::other("connection limit reached"))
+            });
+        };
+
+        let accept = self.inner.accept(stream, service);
+        Box::pin(async move {
+            let (stream, service) = accept.await?;
+            Ok((LimitedStream { inner: stream, _permit: permit }, service))
+        })
+    }
+}
+
+/// A connection holding one slot of the connection limit until it is dropped
+pub struct LimitedStream<S> {
+    inner: S,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for LimitedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for LimitedStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+/// Turn errors from the load-shedding and timeout layers into responses:
+/// 503 when the in-flight request limit is reached, 504 when a request times out
+pub async fn handle_layer_error(error: BoxError) -> Response {
+    if error.is::<tower::load_shed::error::Overloaded>() {
+        tracing::warn!("In-flight request limit reached - shedding request");
+        return Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header(header::RETRY_AFTER, "1")
+            .body(Body::from("Server is busy. Try again shortly."))
+            .unwrap();
+    }
+
+    if error.is::<tower::timeout::error::Elapsed>() {
+        tracing::warn!("Request timed out");
+        return Response::builder()
+            .status(StatusCode::GATEWAY_TIMEOUT)
+            .body(Body::from("Request timed out"))
+            .unwrap();
+    }
+
+    tracing::error!("Unhandled middleware error: {}", error);
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::from("Internal server error"))
+        .unwrap()
+}
@@ -0,0 +1,38 @@
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::TcpSocket;
+
+/// Pending connections the kernel queues before they are accepted
+const BACKLOG: u32 = 1024;
+
+/// Open the server's listening socket
+/// With `reuse_port`, the socket is bound with SO_REUSEPORT, so an upgraded server can bind
+/// the same address while this one drains its connections. The kernel then spreads new
+/// connections over both until the old server stops listening.
+pub fn bind(addr: SocketAddr, reuse_port: bool) -> io::Result<std::net::TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    // Like std's TcpListener::bind, so a restart doesn't wait out closing connections
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
+    if reuse_port {
+        set_reuse_port(&socket)?;
+    }
+    socket.bind(addr)?;
+    socket.listen(BACKLOG)?.into_std()
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))))]
+fn set_reuse_port(socket: &TcpSocket) -> io::Result<()> {
+    socket.set_reuseport(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin")))))]
+fn set_reuse_port(_socket: &TcpSocket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--reuse-port is not supported on this platform",
+    ))
+}
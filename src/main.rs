@@ -1,37 +1,110 @@
 use axum::{
+    extract::DefaultBodyLimit,
     middleware,
-    routing::get,
+    routing::{delete, get, post, put},
     Router,
 };
+use anyhow::Context;
 use clap::Parser;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
+use axum::error_handling::HandleErrorLayer;
+use axum_server::tls_rustls::RustlsAcceptor;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::timeout::TimeoutLayer;
 use tower::ServiceBuilder;
-use tower_http::{compression::CompressionLayer, trace::TraceLayer};
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate},
+        CompressionLayer, DefaultPredicate,
+    },
+    trace::TraceLayer,
+};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use rate_limiter::AuthRateLimiter;
+use rate_limiter::{AuthRateLimiter, RequestRateLimiter};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+mod access_log;
+mod acl;
+mod album_art;
 mod api;
 mod archives;
 mod auth;
+mod batch;
+mod branding;
+mod cache;
+mod cache_control;
+mod cast;
 mod config;
+mod config_file;
 mod constants;
+mod daemon;
+mod decoding;
+mod download;
 mod embedded;
+mod events;
+mod favorites;
+mod files;
+mod feeds;
+mod ffprobe;
+mod geo;
+mod graphql;
 mod handlers;
+mod history;
+mod i18n;
+mod icons;
+mod index;
+mod ip_filter;
+mod jwt;
+mod library;
+mod limits;
+mod listener;
+mod media_info;
+mod metadata;
 mod models;
+mod natural;
+mod netfs;
+mod oidc;
+mod playlist_files;
+mod playlists;
+mod preview;
+mod qr;
+mod queue;
 mod rate_limiter;
+mod ratings;
+mod s3;
+mod search;
+mod selfcheck;
+mod slideshow;
 mod security_headers;
+mod share_card;
+mod sidecar;
+mod stats;
+mod store;
+mod subtitles;
+mod tags;
+mod timeline;
+mod telemetry;
 mod templates;
+mod thumbnails;
 mod tls;
+mod tokens;
+mod tracks;
+mod trash;
+mod uploads;
+mod users;
+mod waveform;
+mod workers;
 
-use auth::{basic_auth_middleware, AuthConfig};
-use config::Config;
+use auth::{basic_auth_middleware, require_admin, AuthConfig};
+use config::{Cli, Command, Config, LogFormat};
+use cache_control::CachePolicies;
 use handlers::AppState;
+use natural::NameOrder;
 
 /// OpenAPI documentation
 #[derive(OpenApi)]
@@ -39,19 +112,171 @@ use handlers::AppState;
     paths(
         handlers::filter_handler,
         handlers::random_media_handler,
+        handlers::random_directory_handler,
+        api::search_handler,
+        api::similar_handler,
+        tags::list_tags_handler,
+        tags::add_tags_handler,
+        tags::remove_tags_handler,
+        favorites::list_favorites_handler,
+        favorites::add_favorite_handler,
+        favorites::remove_favorite_handler,
+        history::history_handler,
+        history::clear_history_handler,
+        history::history_settings_handler,
+        history::position_handler,
+        playlist_files::playlist_file_handler,
+        playlists::list_playlists_handler,
+        playlists::create_playlist_handler,
+        playlists::get_playlist_handler,
+        playlists::update_playlist_handler,
+        playlists::delete_playlist_handler,
+        playlists::add_tracks_handler,
+        playlists::remove_track_handler,
+        queue::get_queue_handler,
+        queue::build_queue_handler,
+        queue::clear_queue_handler,
+        queue::shuffle_queue_handler,
+        queue::queue_position_handler,
+        queue::next_handler,
+        queue::previous_handler,
+        tracks::track_handler,
+        waveform::waveform_handler,
+        media_info::media_info_handler,
+        subtitles::subtitles_handler,
+        cast::devices_handler,
+        cast::create_session_handler,
+        cast::list_sessions_handler,
+        cast::delete_session_handler,
+        uploads::upload_handler,
+        uploads::upload_files_handler,
+        files::delete_handler,
+        files::move_handler,
+        files::mkdir_handler,
+        batch::batch_handler,
+        trash::list_handler,
+        trash::restore_handler,
+        trash::purge_handler,
+        trash::empty_handler,
+        library::artists_handler,
+        library::albums_handler,
+        library::genres_handler,
+        library::tracks_handler,
+        timeline::timeline_handler,
+        geo::geo_handler,
+        slideshow::slideshow_handler,
+        sidecar::directory_info_handler,
+        qr::qr_handler,
+        preview::oembed_handler,
+        ratings::set_rating_handler,
+        ratings::clear_rating_handler,
         api::config_handler,
+        api::refresh_handler,
+        api::stats_handler,
+        api::storage_stats_handler,
+        api::library_stats_handler,
+        api::index_status_handler,
+        events::events_handler,
+        graphql::graphql_handler,
+        graphql::schema_handler,
     ),
     components(
         schemas(
             handlers::FilterResponse,
             handlers::FilterResult,
+            handlers::FilterMatch,
             handlers::RandomMediaResponse,
+            handlers::RandomDirectoryResponse,
+            handlers::SortField,
+            handlers::SortOrder,
+            handlers::ListingSort,
+            api::SearchResponse,
+            api::SearchResult,
+            api::SimilarResponse,
+            api::SimilarImage,
+            tags::TagRequest,
+            tags::TagsResponse,
+            tags::TagCount,
+            favorites::Favorite,
+            favorites::FavoriteState,
+            history::HistoryResponse,
+            history::HistoryEntry,
+            history::HistorySettings,
+            history::PlaybackPosition,
+            playlist_files::PlaylistFile,
+            playlists::PlaylistSummary,
+            playlists::Playlist,
+            playlists::PlaylistTrack,
+            playlists::CreatePlaylistRequest,
+            playlists::UpdatePlaylistRequest,
+            playlists::AddTracksRequest,
+            queue::Queue,
+            queue::QueueRequest,
+            queue::ShuffleRequest,
+            queue::QueuePositionRequest,
+            tracks::TrackInfo,
+            waveform::WaveformResponse,
+            waveform::WaveformFormat,
+            media_info::MediaInfo,
+            subtitles::SubtitleTrack,
+            cast::CastDevice,
+            cast::CastRequest,
+            cast::CastSessionInfo,
+            files::MoveRequest,
+            files::MoveResponse,
+            files::NewDirectory,
+            uploads::UploadFailure,
+            uploads::UploadBatchResponse,
+            batch::BatchOperation,
+            batch::BatchRequest,
+            batch::BatchStatus,
+            batch::BatchResult,
+            batch::BatchResponse,
+            trash::TrashItem,
+            metadata::ReplayGain,
+            library::LibraryTrack,
+            library::ArtistSummary,
+            library::AlbumSummary,
+            library::GenreSummary,
+            timeline::TimelineItem,
+            timeline::TimelineDay,
+            timeline::TimelineMonth,
+            timeline::TimelineYear,
+            timeline::TimelineResponse,
+            geo::GeoCluster,
+            geo::GeoResponse,
+            slideshow::Slide,
+            slideshow::SlideshowResponse,
+            sidecar::DirectoryInfo,
+            sidecar::Visibility,
+            qr::QrFormat,
+            preview::OEmbedResponse,
+            ratings::RatingRequest,
+            ratings::RatingState,
             api::ConfigInfo,
+            api::RefreshResponse,
+            api::AdminStats,
+            api::CacheStats,
+            stats::CacheCounts,
+            api::RateLimitStats,
+            api::AuthLimiterStats,
+            api::RequestLimiterStats,
+            api::StorageStats,
+            cache::VolumeUsage,
+            cache::DirectoryUsage,
+            api::LibraryStats,
+            api::CategoryStats,
+            api::DirectoryDepth,
+            cache::IndexStatus,
+            events::RefreshEvent,
+            events::MediaEvent,
         )
     ),
     tags(
         (name = "media", description = "Media file operations"),
-        (name = "info", description = "Server configuration and information")
+        (name = "info", description = "Server configuration and information"),
+        (name = "stats", description = "Library and storage statistics"),
+        (name = "admin", description = "Management operations (admin role required)")
     ),
     info(
         title = "DoggyGallery API",
@@ -61,27 +286,104 @@ use handlers::AppState;
 )]
 struct ApiDoc;
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
+    // Parse configuration
+    let cli = Cli::parse_from(config_file::apply(std::env::args_os().collect())?);
+
+    // Detach before the runtime starts its threads; the PID file lives until the server stops
+    let _pid_file = match server_config(&cli) {
+        Some(config) => daemon::start(config)?,
+        None => None,
+    };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the async runtime")?
+        .block_on(run(cli))
+}
+
+/// Options of the server `cli` starts, if it starts one
+fn server_config(cli: &Cli) -> Option<&Config> {
+    match (&cli.command, &cli.serve) {
+        (Some(Command::Serve { config }), _) => Some(config),
+        (None, serve) => serve.as_ref(),
+        _ => None,
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
     // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "doggygallery=info,tower_http=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let log_format = server_config(&cli).map(|c| c.log_format).unwrap_or_default();
+    // No color codes in log files
+    let ansi = server_config(&cli).is_none_or(|c| c.log_file.is_none());
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "doggygallery=info,tower_http=info".into());
+    match log_format {
+        LogFormat::Text => tracing_subscriber::registry()
+            .with(env_filter)
+            .with(tracing_subscriber::fmt::layer().with_ansi(ansi))
+            .init(),
+        LogFormat::Json => tracing_subscriber::registry()
+            .with(env_filter)
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(true)
+                    .with_span_list(true),
+            )
+            .init(),
+    }
 
-    // Parse configuration
-    let config = Config::parse();
+    match cli.command {
+        Some(Command::Serve { config }) => serve(*config).await,
+        Some(Command::User { action }) => users::run_user_command(action),
+        Some(Command::Token { action }) => tokens::run_token_command(action),
+        Some(Command::HashPassword { password_stdin }) => users::run_hash_password_command(password_stdin),
+        Some(Command::GenConfig { output, interactive, force }) => {
+            config_file::run_gen_config_command(output, interactive, force)
+        }
+        Some(Command::GenCerts { cert, key, sans, days, force }) => {
+            tls::run_gen_certs_command(&cert, &key, sans, days, force)
+        }
+        Some(Command::Thumbnails { media_dir, thumbnail_cache_dir, sizes, jobs, max_decode_memory }) => {
+            thumbnails::run_thumbnails_command(media_dir, thumbnail_cache_dir, sizes, jobs, max_decode_memory)
+        }
+        Some(Command::Check { config }) => selfcheck::run_check_command(*config).await,
+        Some(Command::Index { media_dir, index_db, ffprobe, thumbnail_cache_dir, sizes, jobs, max_decode_memory }) => {
+            index::run_index_command(media_dir, index_db, ffprobe, thumbnail_cache_dir, sizes, jobs, max_decode_memory)
+                .await
+        }
+        Some(Command::DecodeImage { size, max_memory, cpu_seconds }) => {
+            decoding::run_decode_command(size, max_memory, cpu_seconds)
+        }
+        None => match cli.serve {
+            Some(config) => serve(config).await,
+            None => unreachable!("clap requires serve arguments when no subcommand is given"),
+        },
+    }
+}
+
+/// Run the gallery server
+async fn serve(config: Config) -> anyhow::Result<()> {
     config.validate()?;
+    if let Some(language) = i18n::Language::parse(&config.language) {
+        language.set_default();
+    }
+    let branding = Arc::new(branding::Branding::from_config(&config)?);
+    let icons = Arc::new(icons::Icons::render(&branding)?);
 
     tracing::info!(
         "{} Starting {}...",
         constants::EMOJI_PREFIX,
         constants::APP_NAME
     );
-    tracing::info!("Media directory: {:?}", config.media_dir);
+    match (&config.media_dir, &config.backend) {
+        (Some(media_dir), _) => tracing::info!("Media directory: {:?}", media_dir),
+        (None, Some(backend)) => tracing::info!("Media store: {}", backend),
+        (None, None) => {}
+    }
     tracing::info!(
         "Listening on: https://{}:{} ({} + {})",
         config.host,
@@ -90,34 +392,201 @@ async fn main() -> anyhow::Result<()> {
         constants::HTTP_VERSION
     );
 
-    // Initialize media cache
-    let media_dir_canonical = config.media_dir.clone().canonicalize()?;
-    tracing::info!("Building initial media cache...");
-    let initial_cache = handlers::refresh_media_cache(&media_dir_canonical)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to build initial media cache: {:?}", e))?;
+    // Install the metrics recorder before anything records metrics
+    let metrics_handle = if config.metrics || config.metrics_listen.is_some() {
+        Some(telemetry::install_recorder()?)
+    } else {
+        None
+    };
+    if let (Some(addr), Some(handle)) = (config.metrics_listen, metrics_handle.clone()) {
+        tokio::spawn(async move {
+            if let Err(e) = telemetry::serve_metrics(addr, handle).await {
+                tracing::error!("{:?}", e);
+            }
+        });
+    }
+
+    // Open the media index database, if configured
+    // Shared pool for thumbnailing, hashing and archive extraction
+    let workers = match config.workers {
+        Some(count) => workers::Workers::new(count),
+        None => workers::Workers::default(),
+    };
+
+    let media_index = match &config.index_db {
+        Some(path) => {
+            tracing::info!("Using media index database {:?}", path);
+            if let Some(ffprobe) = &config.ffprobe {
+                ffprobe::check(ffprobe)?;
+                tracing::info!("Probing videos with {:?}", ffprobe);
+            }
+            Some(
+                index::MediaIndex::open(path)?
+                    .with_ffprobe(config.ffprobe.clone())
+                    .with_workers(workers.clone())
+                    .with_sync(!config.no_index_sync)
+                    .with_max_decode_memory(config.max_decode_memory.saturating_mul(1024 * 1024)),
+            )
+        }
+        None => None,
+    };
+
+    // Open the full-text search index, if configured
+    let search_index = match &config.search_index {
+        Some(path) => {
+            let search = search::SearchIndex::open(path)?;
+            tracing::info!("Using search index {:?} ({} files)", path, search.len());
+            Some(search)
+        }
+        None => None,
+    };
+
+    // Open the album art cache, if configured
+    let art_cache = match &config.art_cache_dir {
+        Some(path) => {
+            tracing::info!("Caching album art in {:?}", path);
+            Some(Arc::new(album_art::ArtCache::open(path, "album_art")?))
+        }
+        None => None,
+    };
+
+    // Open the thumbnail cache, if configured
+    let thumbnail_cache = match &config.thumbnail_cache_dir {
+        Some(path) => {
+            tracing::info!("Caching thumbnails in {:?}", path);
+            Some(Arc::new(album_art::ArtCache::open(path, "thumbnails")?))
+        }
+        None => None,
+    };
+
+    // Signed URLs for podcast feeds, if configured
+    let url_signer = config
+        .url_signing_secret
+        .as_deref()
+        .map(|secret| Arc::new(feeds::UrlSigner::new(secret)));
+
+    // Cast sessions for Chromecast/AirPlay receivers, if enabled
+    let cast_sessions = config.casting.then(|| Arc::new(cast::CastSessions::new()));
+    if cast_sessions.is_some() {
+        tracing::info!("Casting enabled");
+    }
+    if config.graphql {
+        tracing::info!("GraphQL API enabled at /graphql");
+    }
+    if config.enable_uploads {
+        tracing::info!("Uploads enabled, up to {} MB per file", config.max_upload_size);
+    }
+    if config.enable_write_ops {
+        tracing::info!("File management enabled for admins");
+    }
+
+    if config.network_fs {
+        netfs::enable();
+        tracing::info!("Network filesystem mode: lexical path checks, batched stats and IO retries");
+    }
+    selfcheck::startup(&config)?;
+
+    // Open the media store, if configured; local file features then see an empty directory
+    let store = store::open(&config)?;
+    let placeholder_dir = match store {
+        Some(_) => Some(tempfile::tempdir().context("Failed to create placeholder media directory")?),
+        None => None,
+    };
+    if config.s3_presign {
+        tracing::info!("Redirecting media downloads to presigned bucket URLs");
+    }
+
+    // Initialize media cache; the first scan runs in the background
+    let media_dir_canonical = match (&placeholder_dir, &config.media_dir) {
+        (Some(dir), _) => dunce::canonicalize(dir.path())?,
+        (None, Some(media_dir)) => dunce::canonicalize(media_dir)?,
+        (None, None) => anyhow::bail!("Either --media-dir or --backend is required"),
+    };
+    let scan_progress = cache::ScanProgress::new();
+    let initial_cache = cache::initial_media_cache(
+        &media_dir_canonical,
+        media_index.as_ref(),
+        config.cache_file.as_deref(),
+        &scan_progress,
+    )
+    .await;
     let media_cache = Arc::new(RwLock::new(initial_cache));
+    let event_bus = events::EventBus::new();
+    events::watch_scan(scan_progress.clone(), event_bus.clone());
 
-    // Create application state
-    let app_state = AppState {
-        media_dir: media_dir_canonical.clone(),
-        media_cache: media_cache.clone(),
+    // Keep deleted files in a trash, unless disabled
+    let trash = match (config.enable_write_ops, config.no_trash) {
+        (true, false) => {
+            let dir = config
+                .trash_dir
+                .clone()
+                .unwrap_or_else(|| media_dir_canonical.join(".trash"));
+            let retention = (config.trash_retention_days > 0)
+                .then(|| Duration::from_secs(config.trash_retention_days.saturating_mul(24 * 60 * 60)));
+            let trash = trash::Trash::open(dir.clone(), retention)?;
+            tracing::info!("Deleted files go to the trash in {:?}", dir);
+            Some((Arc::new(trash), dunce::canonicalize(&dir)?))
+        }
+        _ => None,
+    };
+
+    // Set up OpenID Connect browser login, if configured
+    let oidc_client = oidc::OidcClient::from_config(&config).await?.map(Arc::new);
+
+    // Load per-directory access control lists
+    let mut acl = match &config.acl_file {
+        Some(path) => acl::AccessControl::load(path)?,
+        None => acl::AccessControl::default(),
     };
+    if !acl.is_empty() {
+        tracing::info!("Loaded {} directory ACL rule(s)", acl.len());
+    }
+    // A trash inside the media directory is not browsable
+    if let Some(relative) = trash
+        .as_ref()
+        .and_then(|(_, dir)| dir.strip_prefix(&media_dir_canonical).ok())
+    {
+        acl.hide(&relative.to_string_lossy().replace('\\', "/"));
+    }
+    let acl = Arc::new(acl);
+    let trash = trash.map(|(trash, _)| trash);
 
     // Start cache refresh task (refresh every 5 minutes)
     let cache_refresh_dir = media_dir_canonical.clone();
     let cache_refresh_cache = media_cache.clone();
+    let cache_refresh_index = media_index.clone();
+    let cache_refresh_progress = scan_progress.clone();
+    let cache_refresh_search = search_index.clone();
+    let cache_refresh_events = event_bus.clone();
+    let cache_refresh_file = config.cache_file.clone();
+    let cache_refresh_store = store.clone();
+    let scan_concurrency = config.scan_concurrency;
     tokio::spawn(async move {
+        // The first tick fires immediately, building the cache right after startup
         let mut interval = tokio::time::interval(Duration::from_secs(300)); // Refresh every 5 minutes
         loop {
             interval.tick().await;
-            match handlers::refresh_media_cache(&cache_refresh_dir).await {
-                Ok(new_cache) => {
-                    let mut cache = cache_refresh_cache.write().await;
-                    *cache = new_cache;
-                }
-                Err(e) => {
-                    tracing::error!("Failed to refresh media cache: {:?}", e);
+            let result = cache::refresh_media_cache(
+                &cache_refresh_cache,
+                &cache_refresh_progress,
+                &cache_refresh_dir,
+                cache::Source::new(cache_refresh_index.as_ref(), cache_refresh_store.as_deref(), scan_concurrency),
+                cache_refresh_search.as_ref(),
+                &cache_refresh_events,
+            )
+            .await;
+            if let Err(e) = &result {
+                tracing::error!("Failed to refresh media cache: {:?}", e);
+            }
+            // Retry soon until the cache covers the library, e.g. while a server sharing
+            // the index runs its first sync
+            if !cache_refresh_progress.is_ready() {
+                interval.reset_after(Duration::from_secs(10));
+                continue;
+            }
+            if let (Ok(_), Some(path)) = (&result, &cache_refresh_file) {
+                if let Err(e) = cache::save_media_cache(&cache_refresh_cache, &cache_refresh_dir, path).await {
+                    tracing::warn!("Failed to save cache file {:?}: {}", path, e);
                 }
             }
         }
@@ -127,51 +596,350 @@ async fn main() -> anyhow::Result<()> {
     // Allow 10 failed attempts within a 60-second window
     let rate_limiter = AuthRateLimiter::new(10, Duration::from_secs(60));
 
-    // Start cleanup task to remove old rate limit entries
+    // General per-IP request rate limit, if configured
+    let request_limiter = config
+        .rate_limit_rps
+        .map(|rps| RequestRateLimiter::new(rps, config.rate_limit_burst));
+    if let Some(rps) = config.rate_limit_rps {
+        tracing::info!(
+            "Request rate limit: {} req/s per client, burst {}",
+            rps,
+            config.rate_limit_burst
+        );
+    }
+
+    // Create application state
+    let server_stats = stats::ServerStats::new();
+    let app_state = AppState {
+        media_dir: media_dir_canonical.clone(),
+        media_cache: media_cache.clone(),
+        scan: scan_progress,
+        acl: acl.clone(),
+        oidc: oidc_client.clone(),
+        stats: server_stats.clone(),
+        auth_limiter: rate_limiter.clone(),
+        request_limiter: request_limiter.clone(),
+        index: media_index,
+        search: search_index,
+        art_cache,
+        thumbnail_cache,
+        store,
+        url_signer: url_signer.clone(),
+        cast: cast_sessions.clone(),
+        events: event_bus,
+        graphql: config.graphql.then(graphql::schema),
+        upload_limit: config
+            .enable_uploads
+            .then_some(config.max_upload_size.saturating_mul(1024 * 1024)),
+        archive_limits: archives::ArchiveLimits::from_config(&config),
+        stream_buffer: (config.stream_buffer_size.div_ceil(16) * 16 * 1024) as usize,
+        workers,
+        scan_concurrency: config.scan_concurrency,
+        decode_limits: decoding::DecodeLimits::from_config(&config),
+        cache: CachePolicies::from_config(&config),
+        write_ops: config.enable_write_ops,
+        trash: trash.clone(),
+        name_order: if config.bytewise_sort {
+            NameOrder::Bytewise
+        } else {
+            NameOrder::Natural
+        },
+        branding,
+        share_cards: Arc::new(share_card::ShareCardCache::new()),
+        icons,
+    };
+
+    // Start cleanup task to remove old rate limit entries, expired OIDC and cast sessions,
+    // and trash past its retention period
     let cleanup_limiter = rate_limiter.clone();
+    let cleanup_request_limiter = request_limiter.clone();
+    let cleanup_metrics = metrics_handle.clone();
+    let cleanup_oidc = oidc_client.clone();
+    let cleanup_cast = cast_sessions.clone();
+    let cleanup_trash = trash.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(300)); // Cleanup every 5 minutes
         loop {
             interval.tick().await;
             cleanup_limiter.cleanup().await;
+            if let Some(limiter) = &cleanup_request_limiter {
+                limiter.cleanup().await;
+            }
+            if let Some(oidc) = &cleanup_oidc {
+                oidc.cleanup().await;
+            }
+            if let Some(cast) = &cleanup_cast {
+                cast.cleanup().await;
+            }
+            if let Some(trash) = &cleanup_trash {
+                trash.purge_expired().await;
+            }
+            if let Some(handle) = &cleanup_metrics {
+                handle.run_upkeep();
+            }
         }
     });
 
     // Create authentication config
-    let auth_config = AuthConfig {
-        username: config.username.clone(),
-        password: config.password.clone(),
+    let users = config.load_users()?;
+    tracing::info!("Loaded {} user account(s)", users.len());
+    let api_tokens = config.load_tokens()?;
+    if !api_tokens.is_empty() {
+        tracing::info!("Loaded {} API token(s)", api_tokens.len());
+    }
+    let jwt_verifier = jwt::JwtVerifier::from_config(&config).await?;
+    if jwt_verifier.is_some() {
+        tracing::info!("JWT verification enabled");
+    }
+    let public_dirs = config.load_public_dirs()?;
+    for dir in &public_dirs {
+        tracing::warn!("Serving {:?} without authentication", dir);
+    }
+    let auth_config = AuthConfig::new(
+        users,
+        api_tokens,
+        jwt_verifier,
+        oidc_client,
+        url_signer,
         rate_limiter,
+        public_dirs,
+    )
+    .with_cast_sessions(cast_sessions);
+
+    // Access log in Common/Combined Log Format, if configured
+    let access_log = match &config.access_log {
+        Some(path) => {
+            tracing::info!("Writing access log to {:?}", path);
+            Some(
+                access_log::AccessLog::start(
+                    path.clone(),
+                    config.access_log_format,
+                    config.access_log_max_size.saturating_mul(1024 * 1024),
+                    config.access_log_keep,
+                )
+                .await?,
+            )
+        }
+        None => None,
     };
 
-    // Build the application router
-    let app = Router::new()
+    // IP allowlist/denylist, checked before authentication
+    let ip_filter = ip_filter::IpFilter::new(config.allow_cidrs.clone(), config.deny_cidrs.clone());
+    if !ip_filter.is_empty() {
+        tracing::info!(
+            "IP filter enabled: {} allowed range(s), {} denied range(s)",
+            config.allow_cidrs.len(),
+            config.deny_cidrs.len()
+        );
+    }
+
+    // Management endpoints, restricted to the admin role
+    let mut admin_routes = Router::new()
+        .route("/api/admin/refresh", post(api::refresh_handler))
+        .route("/api/admin/stats", get(api::stats_handler));
+    if let Some(handle) = metrics_handle {
+        admin_routes = admin_routes.merge(telemetry::metrics_router(handle));
+    }
+    let admin_routes = admin_routes.route_layer(middleware::from_fn(require_admin));
+
+    // Pages and API calls should finish quickly; recursive scans are cut off with a 504
+    let page_routes = Router::new()
         .route("/", get(handlers::index_handler))
         .route("/browse", get(handlers::browse_redirect_handler))
         .route("/browse/", get(handlers::browse_redirect_handler))
         .route("/browse/*path", get(handlers::list_directory_handler))
         .route("/music", get(handlers::music_index_handler))
         .route("/music/", get(handlers::music_redirect_handler))
+        .route("/music/artists", get(library::artists_page_handler))
+        .route("/music/albums", get(library::albums_page_handler))
+        .route("/music/genres", get(library::genres_page_handler))
+        .route("/music/tracks", get(library::tracks_page_handler))
+        .route("/timeline", get(timeline::timeline_page_handler))
+        .route("/map", get(geo::map_page_handler))
+        .route("/slideshow", get(slideshow::slideshow_page_handler))
         .route("/music/*path", get(handlers::music_list_handler))
         .route("/music-archive/*path", get(handlers::music_archive_handler))
-        .route("/media/*path", get(handlers::serve_media_handler))
+        .route("/feed/*path", get(feeds::feed_handler))
         .route("/thumbnail/*path", get(handlers::serve_thumbnail_handler))
-        .route("/media-archive/*path", get(handlers::serve_archive_file_handler))
         .route("/album-art/*path", get(handlers::serve_album_art_handler))
+        .route("/subtitles/*path", get(subtitles::serve_subtitle_handler))
         .route("/api/filter", get(handlers::filter_handler))
         .route("/api/random", get(handlers::random_media_handler))
+        .route("/api/random-directory", get(handlers::random_directory_handler))
+        .route("/api/search", get(api::search_handler))
+        .route("/api/similar", get(api::similar_handler))
+        .route(
+            "/api/tags",
+            get(tags::list_tags_handler)
+                .post(tags::add_tags_handler)
+                .delete(tags::remove_tags_handler),
+        )
+        .route("/api/favorites", get(favorites::list_favorites_handler))
+        .route(
+            "/api/favorites/*path",
+            put(favorites::add_favorite_handler).delete(favorites::remove_favorite_handler),
+        )
+        .route(
+            "/api/history",
+            get(history::history_handler).delete(history::clear_history_handler),
+        )
+        .route("/api/history/settings", put(history::history_settings_handler))
+        .route("/api/history/position/*path", put(history::position_handler))
+        .route("/api/playlist-file/*path", get(playlist_files::playlist_file_handler))
+        .route(
+            "/api/playlists",
+            get(playlists::list_playlists_handler).post(playlists::create_playlist_handler),
+        )
+        .route(
+            "/api/playlists/:id",
+            get(playlists::get_playlist_handler)
+                .put(playlists::update_playlist_handler)
+                .delete(playlists::delete_playlist_handler),
+        )
+        .route("/api/playlists/:id/tracks", post(playlists::add_tracks_handler))
+        .route(
+            "/api/playlists/:id/tracks/:position",
+            delete(playlists::remove_track_handler),
+        )
+        .route(
+            "/api/queue",
+            get(queue::get_queue_handler)
+                .post(queue::build_queue_handler)
+                .delete(queue::clear_queue_handler),
+        )
+        .route("/api/queue/shuffle", put(queue::shuffle_queue_handler))
+        .route("/api/queue/position", put(queue::queue_position_handler))
+        .route("/api/queue/next", post(queue::next_handler))
+        .route("/api/queue/previous", post(queue::previous_handler))
+        .route("/api/track/*path", get(tracks::track_handler))
+        .route("/api/waveform/*path", get(waveform::waveform_handler))
+        .route("/api/metadata/*path", get(media_info::media_info_handler))
+        .route("/api/subtitles/*path", get(subtitles::subtitles_handler))
+        .route("/api/cast/devices", get(cast::devices_handler))
+        .route(
+            "/api/cast/sessions",
+            get(cast::list_sessions_handler).post(cast::create_session_handler),
+        )
+        .route("/api/cast/sessions/:id", delete(cast::delete_session_handler))
+        .route("/api/files/move", post(files::move_handler))
+        .route("/api/mkdir", post(files::mkdir_handler))
+        .route("/api/batch", post(batch::batch_handler))
+        .route("/api/trash", get(trash::list_handler).delete(trash::empty_handler))
+        .route("/api/trash/:id/restore", post(trash::restore_handler))
+        .route("/api/trash/:id", delete(trash::purge_handler))
+        .route("/api/files/*path", delete(files::delete_handler))
+        .route("/api/library/artists", get(library::artists_handler))
+        .route("/api/library/albums", get(library::albums_handler))
+        .route("/api/library/genres", get(library::genres_handler))
+        .route("/api/library/tracks", get(library::tracks_handler))
+        .route("/api/timeline", get(timeline::timeline_handler))
+        .route("/api/geo", get(geo::geo_handler))
+        .route("/api/slideshow", get(slideshow::slideshow_handler))
+        .route("/api/directory/*path", get(sidecar::directory_info_handler))
+        .route("/api/qr", get(qr::qr_handler))
+        .route("/oembed", get(preview::oembed_handler))
+        .route("/share-card", get(share_card::share_card_root_handler))
+        .route("/share-card/*path", get(share_card::share_card_handler))
+        .route(
+            "/api/rating/*path",
+            put(ratings::set_rating_handler).delete(ratings::clear_rating_handler),
+        )
         .route("/api/config", get(api::config_handler))
+        .route("/api/stats/storage", get(api::storage_stats_handler))
+        .route("/api/stats/library", get(api::library_stats_handler))
+        .route("/api/index/status", get(api::index_status_handler))
+        .route("/api/events", get(events::events_handler))
+        .route("/graphql", post(graphql::graphql_handler))
+        .route("/graphql/schema", get(graphql::schema_handler))
+        .route_layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(limits::handle_layer_error))
+                .layer(TimeoutLayer::new(Duration::from_secs(config.request_timeout))),
+        );
+
+    // Media downloads and uploads can legitimately take a long time on slow links
+    let media_routes = Router::new()
+        .route("/media/*path", get(handlers::serve_media_handler))
+        .route("/media-archive/*path", get(handlers::serve_archive_file_handler))
+        .route("/download", get(download::download_root_handler))
+        .route("/download/", get(download::download_root_handler))
+        .route("/download/*path", get(download::download_handler))
+        // The upload handlers enforce --max-upload-size themselves while streaming
+        .route(
+            "/api/upload",
+            post(uploads::upload_root_files_handler).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/upload/*path",
+            put(uploads::upload_handler)
+                .post(uploads::upload_files_handler)
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .route_layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(limits::handle_layer_error))
+                .option_layer(config.media_timeout.map(|secs| TimeoutLayer::new(Duration::from_secs(secs)))),
+        );
+
+    // Build the application router
+    let app = Router::new()
+        .merge(page_routes)
+        .merge(media_routes)
+        .merge(admin_routes)
+        .route("/auth/login", get(oidc::login_handler))
+        .route("/auth/callback", get(oidc::callback_handler))
+        .route("/auth/logout", get(oidc::logout_handler))
         .merge(SwaggerUi::new("/docs").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .route("/static/*path", get(embedded::serve_static))
+        .route("/branding/*path", get(branding::serve_branding))
+        .route("/favicon.ico", get(icons::serve_icon))
+        .route("/icon.png", get(icons::serve_icon))
+        .route("/apple-touch-icon.png", get(icons::serve_icon))
+        .route("/apple-touch-icon-precomposed.png", get(icons::serve_icon))
         .layer(
             ServiceBuilder::new()
                 .layer(middleware::from_fn(security_headers::add_security_headers))
+                // Compress outside the request ID layer, which needs uncompressed error bodies
+                // ZIP downloads hold media that is already compressed
+                .layer(CompressionLayer::new().compress_when(
+                    DefaultPredicate::new().and(NotForContentType::const_new("application/zip")),
+                ))
+                .layer(middleware::from_fn(telemetry::request_id_middleware))
+                .layer(middleware::from_fn(i18n::negotiate_language))
+                // Inside the request ID layer, whose ID the span records, and outside every layer that
+                // logs, so each log line, including auth and IP filter rejections, is in the request span
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(telemetry::make_request_span)
+                        .on_response(telemetry::log_response),
+                )
+                .layer(middleware::from_fn_with_state(
+                    access_log,
+                    access_log::access_log_middleware,
+                ))
+                .layer(middleware::from_fn(telemetry::track_metrics))
+                .layer(middleware::from_fn_with_state(server_stats, stats::track_stats))
+                .layer(middleware::from_fn_with_state(
+                    ip_filter,
+                    ip_filter::ip_filter_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    request_limiter,
+                    rate_limiter::request_rate_limit_middleware,
+                ))
+                .layer(HandleErrorLayer::new(limits::handle_layer_error))
+                .option_layer(config.max_in_flight.map(|max| {
+                    // One semaphore shared by all routes, shedding load instead of queueing
+                    ServiceBuilder::new()
+                        .load_shed()
+                        .layer(GlobalConcurrencyLimitLayer::new(max as usize))
+                        .into_inner()
+                }))
                 .layer(middleware::from_fn_with_state(
                     auth_config,
                     basic_auth_middleware,
                 ))
-                .layer(CompressionLayer::new())
-                .layer(TraceLayer::new_for_http()),
+                .layer(middleware::from_fn_with_state(acl, acl::enforce_acl)),
         )
         .with_state(app_state);
 
@@ -185,17 +953,79 @@ async fn main() -> anyhow::Result<()> {
     };
 
     // Create the server address
-    let addr = format!("{}:{}", config.host, config.port)
+    let addr: SocketAddr = format!("{}:{}", config.host, config.port)
         .parse()
         .expect("Invalid address");
 
+    let listener = listener::bind(addr, config.reuse_port)
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    if config.reuse_port {
+        tracing::info!("Listening with SO_REUSEPORT; other servers may share the port");
+    }
+
     tracing::info!("Server ready! Accepting connections...");
+    daemon::notify_ready();
 
     // Start the HTTPS server with TLS 1.3
     // Use into_make_service_with_connect_info to provide SocketAddr for rate limiting
-    axum_server::bind_rustls(addr, tls_config)
-        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
-        .await?;
+    let make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let handle = axum_server::Handle::new();
+    let drain_timeout = (config.shutdown_timeout > 0).then(|| Duration::from_secs(config.shutdown_timeout));
+    tokio::spawn(shutdown_on_signal(handle.clone(), drain_timeout));
+    match config.max_connections {
+        Some(max) => {
+            tracing::info!("Limiting to {} concurrent connections", max);
+            let acceptor = limits::ConnectionLimitAcceptor::new(RustlsAcceptor::new(tls_config), max as usize);
+            axum_server::from_tcp(listener)
+                .acceptor(acceptor)
+                .handle(handle)
+                .serve(make_service)
+                .await?;
+        }
+        None => {
+            axum_server::from_tcp_rustls(listener, tls_config)
+                .handle(handle)
+                .serve(make_service)
+                .await?;
+        }
+    }
+
+    // Keep the cache for the next start
+    if let Some(path) = &config.cache_file {
+        match cache::save_media_cache(&media_cache, &media_dir_canonical, path).await {
+            Ok(()) => tracing::info!("Saved media cache to {:?}", path),
+            Err(e) => tracing::warn!("Failed to save cache file {:?}: {}", path, e),
+        }
+    }
 
     Ok(())
 }
+
+/// Stop accepting connections on Ctrl-C or SIGTERM, giving open ones `drain_timeout` to finish
+async fn shutdown_on_signal(handle: axum_server::Handle, drain_timeout: Option<Duration>) {
+    let ctrl_c = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!(
+        "Shutting down, waiting for {} open connection(s)",
+        handle.connection_count()
+    );
+    handle.graceful_shutdown(drain_timeout);
+}
@@ -0,0 +1,191 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    Extension,
+};
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState, FilterResult};
+use crate::index::MediaIndex;
+use crate::models::{DirectoryEntry, MediaStreams, VideoInfo};
+
+/// Everything the media index knows about a file
+/// Fields that don't apply to the file's type, or couldn't be read, are `null`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MediaInfo {
+    #[serde(flatten)]
+    pub file: FilterResult,
+    /// SHA-256 of the file contents
+    pub sha256: Option<String>,
+    /// Pixel dimensions of an image or video
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// EXIF capture time of an image
+    pub taken_at: Option<String>,
+    /// EXIF camera make and model of an image
+    pub camera: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    /// Position of an audio track on its album
+    pub track: Option<u32>,
+    /// Playing time of audio or video in seconds
+    pub duration: Option<f64>,
+    /// Average bitrate of audio or video in kbit/s
+    pub bitrate: Option<u32>,
+    /// ffprobe codec name of a video's first video stream
+    pub video_codec: Option<String>,
+    /// ffprobe codec name of a video's first audio stream
+    pub audio_codec: Option<String>,
+    /// Whether browsers can play a probed video's codecs; `null` for other files
+    pub browser_playable: Option<bool>,
+    /// Chapters and audio/subtitle streams of a probed video; empty for other files
+    #[serde(flatten)]
+    pub streams: MediaStreams,
+}
+
+impl MediaIndex {
+    /// Indexed metadata of one file
+    pub async fn media_info(&self, path: String) -> anyhow::Result<Option<MediaInfo>> {
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT path, name, size, file_type, mtime, sha256, width, height, taken_at, camera,
+                        artist, album, title, genre, year, track, duration, bitrate,
+                        video_codec, audio_codec, probed, streams
+                 FROM files WHERE path = ?1",
+                [&path],
+                |row| {
+                    let video = VideoInfo {
+                        width: row.get(6)?,
+                        height: row.get(7)?,
+                        duration: row.get(16)?,
+                        bitrate: row.get(17)?,
+                        video_codec: row.get(18)?,
+                        audio_codec: row.get(19)?,
+                    };
+                    let probed: bool = row.get(20)?;
+                    let streams = row
+                        .get::<_, Option<String>>(21)?
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default();
+                    Ok(MediaInfo {
+                        file: FilterResult {
+                            path: row.get(0)?,
+                            name: row.get(1)?,
+                            size: row.get(2)?,
+                            file_type: row.get(3)?,
+                            modified: row.get::<_, i64>(4)?.max(0) as u64,
+                        },
+                        sha256: row.get(5)?,
+                        width: video.width,
+                        height: video.height,
+                        taken_at: row.get(8)?,
+                        camera: row.get(9)?,
+                        artist: row.get(10)?,
+                        album: row.get(11)?,
+                        title: row.get(12)?,
+                        genre: row.get(13)?,
+                        year: row.get(14)?,
+                        track: row.get(15)?,
+                        duration: video.duration,
+                        bitrate: video.bitrate,
+                        browser_playable: (probed && video.video_codec.is_some())
+                            .then(|| video.browser_playable()),
+                        video_codec: video.video_codec,
+                        audio_codec: video.audio_codec,
+                        streams,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// Probed stream details of the given videos, keyed by path
+    pub async fn video_info(&self, paths: Vec<String>) -> anyhow::Result<HashMap<String, VideoInfo>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT width, height, duration, bitrate, video_codec, audio_codec FROM files
+                 WHERE path = ?1 AND file_type = 'video' AND video_codec IS NOT NULL",
+            )?;
+            let mut info = HashMap::new();
+            for path in paths {
+                let row = stmt
+                    .query_row([&path], |row| {
+                        Ok(VideoInfo {
+                            width: row.get(0)?,
+                            height: row.get(1)?,
+                            duration: row.get(2)?,
+                            bitrate: row.get(3)?,
+                            video_codec: row.get(4)?,
+                            audio_codec: row.get(5)?,
+                        })
+                    })
+                    .optional()?;
+                if let Some(row) = row {
+                    info.insert(path, row);
+                }
+            }
+            Ok(info)
+        })
+        .await
+    }
+}
+
+/// Fill in the stream details of the videos in a listing, if they have been probed
+pub async fn attach_video_info(state: &AppState, entries: &mut [DirectoryEntry]) {
+    let Some(index) = &state.index else {
+        return;
+    };
+    let paths = entries
+        .iter()
+        .filter(|entry| entry.is_video())
+        .map(|entry| entry.path.clone())
+        .collect();
+    match index.video_info(paths).await {
+        Ok(mut info) => {
+            for entry in entries {
+                entry.video = info.remove(&entry.path);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to read video info for listing: {:?}", e),
+    }
+}
+
+/// Get the indexed metadata of a file
+/// Needs `--index-db`. Video durations, resolutions, codecs, bitrates, chapters and
+/// stream lists also need `--ffprobe`.
+#[utoipa::path(
+    get,
+    path = "/api/metadata/{path}",
+    params(("path" = String, Path, description = "File path relative to the media directory")),
+    responses(
+        (status = 200, description = "Indexed metadata", body = MediaInfo),
+        (status = 403, description = "Path not accessible"),
+        (status = 404, description = "File not indexed, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn media_info_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<MediaInfo>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let path = handlers::media_path(&path)?;
+    if !state.acl.allows(principal.as_deref(), &path) {
+        return Err(AppError::Forbidden);
+    }
+    let info = index.media_info(path).await.map_err(|e| {
+        tracing::error!("{:?}", e);
+        AppError::InternalError
+    })?;
+    Ok(Json(info.ok_or(AppError::NotFound)?))
+}
@@ -0,0 +1,170 @@
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::{Accessor, ItemKey, Tag};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use crate::decoding;
+
+/// Width of the grid a perceptual hash compares; one column more than its height
+const DHASH_SIZE: u32 = 8;
+
+/// Tags read from an audio file (ID3, Vorbis comments, MP4 atoms, APE, ...)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    /// Position of the track on its album
+    pub track: Option<u32>,
+}
+
+/// Stream properties of an audio file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AudioProperties {
+    /// Playing time in seconds
+    pub duration: Option<f64>,
+    /// Average bitrate in kbit/s
+    pub bitrate: Option<u32>,
+}
+
+/// ReplayGain loudness normalization values of an audio file
+/// Gains are in dB relative to the ReplayGain reference level; peaks are linear sample peaks
+/// where 1.0 is full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, ToSchema)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+/// Opus R128 gains are EBU R128 (-23 LUFS) based, 5 dB below the ReplayGain reference
+const R128_TO_REPLAYGAIN_DB: f32 = 5.0;
+
+/// Read the primary tag of an audio file, or `None` if it has none or can't be parsed
+/// Audio properties are skipped, so only the tag blocks are read.
+pub fn read_audio_tags(path: &Path) -> Option<AudioTags> {
+    read_track_tags(path).map(|(tags, _)| tags)
+}
+
+/// Read the descriptive tags and ReplayGain values of an audio file in one pass
+pub fn read_track_tags(path: &Path) -> Option<(AudioTags, ReplayGain)> {
+    let tagged = Probe::open(path)
+        .ok()?
+        .options(ParseOptions::new().read_properties(false))
+        .guess_file_type()
+        .ok()?
+        .read()
+        .ok()?;
+    let tag = tagged.primary_tag().or_else(|| tagged.first_tag())?;
+    Some((audio_tags(tag), replay_gain(tag)))
+}
+
+fn audio_tags(tag: &Tag) -> AudioTags {
+    let text = |value: Option<std::borrow::Cow<'_, str>>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    AudioTags {
+        artist: text(tag.artist()),
+        album: text(tag.album()),
+        title: text(tag.title()),
+        genre: text(tag.genre()),
+        year: tag.year(),
+        track: tag.track(),
+    }
+}
+
+/// Read `REPLAYGAIN_*` tags, falling back to the Opus `R128_*_GAIN` tags for missing gains
+fn replay_gain(tag: &Tag) -> ReplayGain {
+    let number = |key: &ItemKey| tag.get_string(key).and_then(parse_gain_value);
+    // R128 gains are Q7.8 fixed-point integers in 1/256 dB
+    let r128 = |name: &str| {
+        tag.items()
+            .find(|item| matches!(item.key(), ItemKey::Unknown(key) if key.eq_ignore_ascii_case(name)))
+            .and_then(|item| item.value().text())
+            .and_then(|value| value.trim().parse::<i16>().ok())
+            .map(|q78| f32::from(q78) / 256.0 + R128_TO_REPLAYGAIN_DB)
+    };
+
+    ReplayGain {
+        track_gain: number(&ItemKey::ReplayGainTrackGain).or_else(|| r128("R128_TRACK_GAIN")),
+        track_peak: number(&ItemKey::ReplayGainTrackPeak),
+        album_gain: number(&ItemKey::ReplayGainAlbumGain).or_else(|| r128("R128_ALBUM_GAIN")),
+        album_peak: number(&ItemKey::ReplayGainAlbumPeak),
+    }
+}
+
+/// Parse a ReplayGain value such as `-6.48 dB` or `0.988312`
+fn parse_gain_value(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    number.trim().parse::<f32>().ok().filter(|n| n.is_finite())
+}
+
+/// Read the primary tag and the stream properties of an audio file in one pass
+/// Files without tags get empty tags; `None` if the file can't be parsed at all.
+pub fn read_audio_file(path: &Path) -> Option<(AudioTags, AudioProperties)> {
+    let tagged = Probe::open(path).ok()?.guess_file_type().ok()?.read().ok()?;
+    let tags = tagged
+        .primary_tag()
+        .or_else(|| tagged.first_tag())
+        .map(audio_tags)
+        .unwrap_or_default();
+
+    let properties = tagged.properties();
+    let duration = properties.duration();
+    let bitrate = properties.audio_bitrate().or_else(|| properties.overall_bitrate());
+    Some((
+        tags,
+        AudioProperties {
+            duration: (!duration.is_zero()).then_some(duration.as_secs_f64()),
+            bitrate: bitrate.filter(|&kbps| kbps > 0),
+        },
+    ))
+}
+
+/// Playing time of an audio file, from its stream properties
+/// Tags are skipped; `None` if the file can't be parsed or reports no duration.
+pub fn audio_duration(path: &Path) -> Option<Duration> {
+    let tagged = Probe::open(path)
+        .ok()?
+        .options(ParseOptions::new().read_tags(false))
+        .guess_file_type()
+        .ok()?
+        .read()
+        .ok()?;
+    let duration = tagged.properties().duration();
+    (!duration.is_zero()).then_some(duration)
+}
+
+/// Compute a 64-bit difference hash (dHash) of an image
+/// The image is shrunk to 9x8 grayscale and each bit records whether a pixel is brighter
+/// than its right neighbour, so resized or recompressed copies hash alike. Visually similar
+/// images differ in few bits.
+pub fn dhash(path: &Path, max_memory: u64) -> Option<u64> {
+    let image = decoding::open(path, max_memory).ok()?;
+    let small = image
+        .resize_exact(DHASH_SIZE + 1, DHASH_SIZE, image::imageops::FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..DHASH_SIZE {
+        for x in 0..DHASH_SIZE {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    Some(hash)
+}
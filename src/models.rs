@@ -1,9 +1,20 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::ratings::MAX_RATING;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirectoryListing {
     pub current_path: String,
     pub parent_path: Option<String>,
+    /// Directories from the top of the media directory down to this one (empty at the top)
+    #[serde(default)]
+    pub breadcrumbs: Vec<Breadcrumb>,
+    /// Title and description from the directory's `.doggygallery.toml`
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
     pub entries: Vec<DirectoryEntry>,
     pub page: usize,
     pub per_page: usize,
@@ -11,14 +22,246 @@ pub struct DirectoryListing {
     pub total_pages: usize,
 }
 
+/// One directory on the way to a listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Breadcrumb {
+    pub name: String,
+    /// Path relative to the media directory
+    pub path: String,
+}
+
+impl Breadcrumb {
+    /// The trail for a relative path, one entry per component, ending with the path itself
+    pub fn trail(path: &str) -> Vec<Breadcrumb> {
+        let mut trail: Vec<Breadcrumb> = Vec::new();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let path = match trail.last() {
+                Some(parent) => format!("{}/{}", parent.path, name),
+                None => name.to_string(),
+            };
+            trail.push(Breadcrumb {
+                name: name.to_string(),
+                path,
+            });
+        }
+        trail
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirectoryEntry {
     pub name: String,
     pub path: String,
     pub entry_type: EntryType,
     pub size: u64,
+    /// Modification time as Unix seconds, when known
+    #[serde(default)]
+    pub modified: Option<u64>,
+    /// Tags set directly on this file or directory
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether the signed-in user has marked this file as a favorite
+    #[serde(default)]
+    pub favorite: bool,
+    /// The signed-in user's star rating of this file
+    #[serde(default)]
+    pub rating: Option<u8>,
+    /// Tags and stream properties of an indexed audio file
+    #[serde(default)]
+    pub audio: Option<AudioInfo>,
+    /// Stream details of a video probed with ffprobe
+    #[serde(default)]
+    pub video: Option<VideoInfo>,
+    /// A directory's title from its `.doggygallery.toml`
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Image previewing a directory, relative to the media directory
+    #[serde(default)]
+    pub cover: Option<String>,
+}
+
+/// What the media index knows about an audio file, shown before it is played
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AudioInfo {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    /// Position of the track on its album
+    pub track: Option<u32>,
+    /// Playing time in seconds
+    pub duration: Option<f64>,
+    /// Average bitrate in kbit/s
+    pub bitrate: Option<u32>,
 }
 
+impl AudioInfo {
+    /// Title with its track number, e.g. "3. Digital Love"
+    pub fn heading(&self) -> Option<String> {
+        let title = self.title.as_ref()?;
+        Some(match self.track {
+            Some(track) => format!("{}. {}", track, title),
+            None => title.clone(),
+        })
+    }
+
+    /// Artist, album, duration and bitrate joined with dots; empty if none are known
+    pub fn details(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(self.artist.clone());
+        parts.extend(self.album.clone());
+        if self.title.is_none() {
+            parts.extend(self.track.map(|track| format!("Track {}", track)));
+        }
+        parts.extend(self.duration.map(|seconds| format_duration(seconds.round() as u64)));
+        parts.extend(self.bitrate.map(|kbps| format!("{} kbps", kbps)));
+        parts.join(" · ")
+    }
+}
+
+/// Stream details of a video, read with ffprobe while indexing
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct VideoInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Playing time in seconds
+    pub duration: Option<f64>,
+    /// Overall bitrate in kbit/s
+    pub bitrate: Option<u32>,
+    /// ffprobe codec name of the first video stream, such as `h264` or `hevc`
+    pub video_codec: Option<String>,
+    /// ffprobe codec name of the first audio stream, if any
+    pub audio_codec: Option<String>,
+}
+
+/// Chapters and selectable streams of a video container
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct MediaStreams {
+    /// Chapter markers in playback order
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+    #[serde(default)]
+    pub audio_tracks: Vec<StreamTrack>,
+    #[serde(default)]
+    pub subtitle_tracks: Vec<StreamTrack>,
+}
+
+/// A chapter marker of a video
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Chapter {
+    /// Start time in seconds
+    pub start: f64,
+    /// End time in seconds
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+/// An audio or subtitle stream of a video
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StreamTrack {
+    /// Stream index within the container
+    pub index: u32,
+    /// ffprobe codec name, such as `aac` or `subrip`
+    pub codec: Option<String>,
+    /// Language tag, usually an ISO 639-2 code such as `eng`
+    pub language: Option<String>,
+    pub title: Option<String>,
+    /// Number of audio channels; `null` for subtitles
+    pub channels: Option<u32>,
+    /// Whether the container marks this stream as the default
+    pub default: bool,
+    /// Whether the container marks this stream as forced
+    pub forced: bool,
+}
+
+impl MediaStreams {
+    pub fn is_empty(&self) -> bool {
+        self.chapters.is_empty() && self.audio_tracks.is_empty() && self.subtitle_tracks.is_empty()
+    }
+}
+
+/// Video codecs every current browser can play
+const BROWSER_VIDEO_CODECS: &[&str] = &["h264", "vp8", "vp9", "av1"];
+
+/// Audio codecs every current browser can play
+const BROWSER_AUDIO_CODECS: &[&str] = &["aac", "mp3", "opus", "vorbis", "flac"];
+
+impl VideoInfo {
+    /// Duration, resolution and codec joined with dots, e.g. "12:34 • 1080p • HEVC"
+    pub fn badges(&self) -> String {
+        let mut badges = Vec::new();
+        badges.extend(self.duration.map(|seconds| format_duration(seconds.round() as u64)));
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            // Portrait videos are named after their width, as players do
+            badges.push(format!("{}p", width.min(height)));
+        }
+        badges.extend(self.video_codec.as_deref().map(codec_label));
+        badges.join(" • ")
+    }
+
+    /// Whether browsers can be expected to play the video's streams
+    /// Unknown codecs count as playable, so only known problems are flagged.
+    pub fn browser_playable(&self) -> bool {
+        let supported = |codec: &Option<String>, supported: &[&str]| {
+            codec.as_deref().is_none_or(|codec| supported.contains(&codec))
+        };
+        supported(&self.video_codec, BROWSER_VIDEO_CODECS) && supported(&self.audio_codec, BROWSER_AUDIO_CODECS)
+    }
+
+    /// Why the video may not play in a browser, e.g. "HEVC video, AC-3 audio"
+    pub fn unplayable_codecs(&self) -> String {
+        let mut codecs = Vec::new();
+        if let Some(codec) = self.video_codec.as_deref().filter(|c| !BROWSER_VIDEO_CODECS.contains(c)) {
+            codecs.push(format!("{} video", codec_label(codec)));
+        }
+        if let Some(codec) = self.audio_codec.as_deref().filter(|c| !BROWSER_AUDIO_CODECS.contains(c)) {
+            codecs.push(format!("{} audio", codec_label(codec)));
+        }
+        codecs.join(", ")
+    }
+}
+
+/// Display name of an ffprobe codec name
+fn codec_label(codec: &str) -> String {
+    match codec {
+        "h264" => "H.264".to_string(),
+        "hevc" => "HEVC".to_string(),
+        "mpeg4" => "MPEG-4".to_string(),
+        "mpeg2video" => "MPEG-2".to_string(),
+        "ac3" => "AC-3".to_string(),
+        "eac3" => "E-AC-3".to_string(),
+        other => other.to_uppercase(),
+    }
+}
+
+/// A partly watched video shown in the "Continue watching" strip
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContinueWatching {
+    pub name: String,
+    pub path: String,
+    /// Seconds watched so far
+    pub position: u64,
+    /// Percentage of the video watched
+    pub percent: u8,
+}
+
+impl ContinueWatching {
+    /// Position as minutes and seconds, or hours, minutes and seconds for long videos
+    pub fn formatted_position(&self) -> String {
+        format_duration(self.position)
+    }
+}
+
+/// Seconds as minutes and seconds, or hours, minutes and seconds when over an hour
+fn format_duration(total: u64) -> String {
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum EntryType {
@@ -27,6 +270,8 @@ pub enum EntryType {
     Video,
     Audio,
     Archive,
+    /// An `.m3u`, `.m3u8` or `.pls` playlist file
+    Playlist,
 }
 
 impl DirectoryEntry {
@@ -34,6 +279,11 @@ impl DirectoryEntry {
         format_bytes(self.size)
     }
 
+    /// The title of a directory that has one, else the file or directory name
+    pub fn display_name(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.name)
+    }
+
     pub fn is_directory(&self) -> bool {
         matches!(self.entry_type, EntryType::Directory)
     }
@@ -53,6 +303,16 @@ impl DirectoryEntry {
     pub fn is_archive(&self) -> bool {
         matches!(self.entry_type, EntryType::Archive)
     }
+
+    pub fn is_playlist(&self) -> bool {
+        matches!(self.entry_type, EntryType::Playlist)
+    }
+
+    /// Star rating drawn as filled and empty stars, e.g. ★★★☆☆
+    pub fn stars(&self) -> String {
+        let filled = self.rating.unwrap_or(0).min(MAX_RATING) as usize;
+        format!("{}{}", "★".repeat(filled), "☆".repeat((MAX_RATING as usize) - filled))
+    }
 }
 
 fn format_bytes(bytes: u64) -> String {
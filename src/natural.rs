@@ -0,0 +1,97 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// How file names are compared when sorting listings and filter results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameOrder {
+    /// Runs of digits compare by value and letters ignore case, so `track2` comes before `track10`
+    #[default]
+    Natural,
+    /// Byte order of the UTF-8 names
+    Bytewise,
+}
+
+impl NameOrder {
+    pub fn cmp(self, a: &str, b: &str) -> Ordering {
+        match self {
+            NameOrder::Natural => compare(a, b),
+            NameOrder::Bytewise => a.cmp(b),
+        }
+    }
+}
+
+/// Compare two names naturally
+/// Names that only differ in case or leading zeros fall back to byte order, so the order is total.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        let ordering = match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                compare_numbers(&take_digits(&mut a_chars), &take_digits(&mut b_chars))
+            }
+            (Some(x), Some(y)) => {
+                a_chars.next();
+                b_chars.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
+
+/// Compare digit runs by value, without parsing, so long runs can't overflow
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(order: NameOrder, names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| order.cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn digit_runs_compare_by_value() {
+        assert_eq!(
+            sorted(NameOrder::Natural, &["track10.mp3", "track2.mp3", "track1.mp3"]),
+            ["track1.mp3", "track2.mp3", "track10.mp3"]
+        );
+        assert_eq!(compare("a99999999999999999999999", "a100000000000000000000000"), Ordering::Less);
+    }
+
+    #[test]
+    fn letters_ignore_case() {
+        assert_eq!(sorted(NameOrder::Natural, &["b.jpg", "A.jpg", "c.jpg"]), ["A.jpg", "b.jpg", "c.jpg"]);
+        assert_eq!(sorted(NameOrder::Bytewise, &["b.jpg", "A.jpg", "C.jpg"]), ["A.jpg", "C.jpg", "b.jpg"]);
+    }
+
+    #[test]
+    fn equal_names_fall_back_to_byte_order() {
+        assert_eq!(compare("IMG_1.jpg", "img_1.jpg"), Ordering::Less);
+        assert_eq!(compare("a01", "a1"), Ordering::Less);
+        assert_eq!(compare("a1", "a01"), Ordering::Greater);
+        assert_eq!(compare("a1", "a1"), Ordering::Equal);
+        assert_eq!(compare("a", "a1"), Ordering::Less);
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs::Metadata;
+use std::future::Future;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Whether the media directory is on a network filesystem, set by `--network-fs`
+static NETWORK_FS: OnceLock<bool> = OnceLock::new();
+
+/// Attempts at an operation that keeps failing with transient errors
+const ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubled before each further one
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Threads statting the entries of one directory at once on a network filesystem
+const STAT_THREADS: usize = 8;
+
+/// Switch to network filesystem mode, for the rest of the process
+pub fn enable() {
+    let _ = NETWORK_FS.set(true);
+}
+
+pub fn enabled() -> bool {
+    NETWORK_FS.get().copied().unwrap_or(false)
+}
+
+/// Whether an error may go away when the operation is repeated, e.g. a timed-out NFS call
+fn is_transient(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    if matches!(e.raw_os_error(), Some(libc::EIO | libc::EAGAIN | libc::ESTALE | libc::ETIMEDOUT)) {
+        return true;
+    }
+    matches!(
+        e.kind(),
+        io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::NetworkDown
+            | io::ErrorKind::HostUnreachable
+    )
+}
+
+/// Run a blocking filesystem operation, repeating it after transient errors in network
+/// filesystem mode
+pub fn retry<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = RETRY_DELAY;
+    for _ in 1..ATTEMPTS {
+        match op() {
+            Err(e) if enabled() && is_transient(&e) => {
+                tracing::debug!("Retrying after transient IO error: {}", e);
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    op()
+}
+
+/// Async version of `retry`
+pub async fn retry_async<T, F, Fut>(mut op: F) -> io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut delay = RETRY_DELAY;
+    for _ in 1..ATTEMPTS {
+        match op().await {
+            Err(e) if enabled() && is_transient(&e) => {
+                tracing::debug!("Retrying after transient IO error: {}", e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    op().await
+}
+
+/// Canonicalize a path that must exist
+/// On a network filesystem, where that takes a round trip per path component, `.` and `..`
+/// are resolved lexically instead and only the result is checked to exist, so symlinks are
+/// trusted rather than resolved. On Windows the result has no `\\?\` prefix when the path
+/// can be written without one, so it still compares equal to plain paths.
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    if !enabled() {
+        return dunce::canonicalize(path);
+    }
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+    retry(|| std::fs::metadata(&resolved))?;
+    Ok(resolved)
+}
+
+/// Resolve a path relative to `base` with `canonicalize`, failing with `PermissionDenied` if
+/// it leads outside `base`
+pub fn resolve(base: &Path, relative: &Path) -> io::Result<PathBuf> {
+    let resolved = canonicalize(&base.join(relative))?;
+    if !resolved.starts_with(base) {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "path leads outside its base"));
+    }
+    Ok(resolved)
+}
+
+/// Names and metadata of the entries of a directory, without following symlinks
+/// Hidden entries are skipped, and so are files whose names `wanted` rejects, without
+/// statting them. Entries that can't be statted are left out. On a network filesystem the
+/// stats run on several threads, since each waits for a round trip to the server, and the
+/// listing is retried after transient errors.
+pub fn list_dir(dir: &Path, wanted: impl Fn(&str) -> bool + Sync) -> io::Result<Vec<(String, Metadata)>> {
+    let entries = retry(|| {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            // The type usually comes with the listing itself
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if is_dir || wanted(&name) {
+                entries.push((name, entry));
+            }
+        }
+        Ok(entries)
+    })?;
+
+    let stat = |entries: &[(String, std::fs::DirEntry)]| -> Vec<(String, Metadata)> {
+        entries
+            .iter()
+            .filter_map(|(name, entry)| Some((name.clone(), retry(|| entry.metadata()).ok()?)))
+            .collect()
+    };
+    if !enabled() || entries.len() <= STAT_THREADS {
+        return Ok(stat(&entries));
+    }
+    let chunk = entries.len().div_ceil(STAT_THREADS);
+    Ok(std::thread::scope(|scope| {
+        let workers: Vec<_> = entries.chunks(chunk).map(|part| scope.spawn(move || stat(part))).collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap_or_default()).collect()
+    }))
+}
@@ -0,0 +1,371 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::handlers::{AppError, AppState};
+use crate::i18n::Language;
+use crate::jwt::{JwtPrincipal, JwtVerifier};
+
+/// Name of the cookie holding the session ID after an OIDC login
+pub const SESSION_COOKIE: &str = "dg_session";
+
+/// Name of the cookie tying a login in flight to the browser that started it
+const STATE_COOKIE: &str = "dg_oidc_state";
+
+/// How long a login may take between redirecting to the provider and the callback
+const PENDING_LOGIN_TTL: Duration = Duration::from_secs(600);
+
+/// Most logins in flight at once; /auth/login needs no credentials, so this bounds memory
+const MAX_PENDING_LOGINS: usize = 10_000;
+
+/// Provider metadata from `/.well-known/openid-configuration`
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+/// A login that was started but hasn't come back from the provider yet
+struct PendingLogin {
+    pkce_verifier: String,
+    nonce: String,
+    return_to: String,
+    created: Instant,
+}
+
+/// A signed-in browser session
+#[derive(Clone)]
+pub struct Session {
+    pub principal: JwtPrincipal,
+    expires: Instant,
+}
+
+/// OpenID Connect relying party (authorization code flow with PKCE)
+pub struct OidcClient {
+    metadata: ProviderMetadata,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_url: String,
+    session_ttl: Duration,
+    verifier: JwtVerifier,
+    http: reqwest::Client,
+    /// Logins in flight, keyed by the `state` parameter
+    pending: RwLock<HashMap<String, PendingLogin>>,
+    /// Active sessions, keyed by session ID
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl OidcClient {
+    /// Discover the provider and build a client, or `None` if OIDC is disabled
+    pub async fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(issuer) = &config.oidc_issuer else {
+            return Ok(None);
+        };
+        let client_id = config
+            .oidc_client_id
+            .clone()
+            .context("--oidc-issuer requires --oidc-client-id")?;
+        let redirect_url = config
+            .oidc_redirect_url
+            .clone()
+            .context("--oidc-issuer requires --oidc-redirect-url")?;
+
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create HTTP client for OIDC")?;
+
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = http
+            .get(&discovery_url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch OIDC discovery document: {}", discovery_url))?
+            .error_for_status()
+            .with_context(|| format!("OIDC discovery failed: {}", discovery_url))?
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse OIDC discovery document: {}", discovery_url))?;
+
+        let verifier =
+            JwtVerifier::for_oidc(config, &metadata.jwks_uri, &metadata.issuer, &client_id).await?;
+
+        tracing::info!("OIDC login enabled via {}", metadata.issuer);
+
+        Ok(Some(Self {
+            metadata,
+            client_id,
+            client_secret: config.oidc_client_secret.clone(),
+            redirect_url,
+            session_ttl: Duration::from_secs(config.oidc_session_hours.saturating_mul(3600)),
+            verifier,
+            http,
+            pending: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+        }))
+    }
+
+    /// Look up the session referenced by the request's session cookie
+    pub async fn session_from_headers(&self, headers: &HeaderMap) -> Option<Session> {
+        let session_id = cookie_value(headers, SESSION_COOKIE)?;
+        let sessions = self.sessions.read().await;
+        sessions
+            .get(session_id)
+            .filter(|s| s.expires > Instant::now())
+            .cloned()
+    }
+
+    /// Drop expired sessions and abandoned logins (call periodically)
+    pub async fn cleanup(&self) {
+        let now = Instant::now();
+        self.sessions.write().await.retain(|_, s| s.expires > now);
+        self.pending
+            .write()
+            .await
+            .retain(|_, p| p.created.elapsed() < PENDING_LOGIN_TTL);
+    }
+
+    /// Exchange an authorization code for tokens and verify the ID token
+    async fn complete_login(&self, code: &str, pending: &PendingLogin) -> Result<JwtPrincipal> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_url.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("code_verifier", pending.pkce_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            id_token: String,
+        }
+
+        let tokens: TokenResponse = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .context("Failed to reach OIDC token endpoint")?
+            .error_for_status()
+            .context("OIDC token endpoint rejected the authorization code")?
+            .json()
+            .await
+            .context("Failed to parse OIDC token response")?;
+
+        let claims = self.verifier.verify_claims(&tokens.id_token).await?;
+
+        if claims.get("nonce").and_then(|n| n.as_str()) != Some(pending.nonce.as_str()) {
+            anyhow::bail!("ID token nonce mismatch");
+        }
+
+        self.verifier.principal_from_claims(&claims)
+    }
+}
+
+/// Query parameters for /auth/login
+#[derive(Debug, Deserialize)]
+pub struct LoginQuery {
+    /// Local path to return to after signing in
+    return_to: Option<String>,
+}
+
+/// Start an OIDC login: redirect to the provider's authorization endpoint
+/// The `state` parameter also goes into a short-lived cookie, which the callback must carry,
+/// so a callback URL from someone else's login can't sign this browser in.
+pub async fn login_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LoginQuery>,
+) -> Result<Response, StatusCode> {
+    let oidc = state.oidc.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+
+    let state_param = random_token();
+    let nonce = random_token();
+    let pkce_verifier = random_token();
+    let pkce_challenge = base64::prelude::BASE64_URL_SAFE_NO_PAD
+        .encode(Sha256::digest(pkce_verifier.as_bytes()));
+
+    // Only allow local return paths to avoid an open redirect, and only ones that can go
+    // into the Location header
+    let return_to = query
+        .return_to
+        .filter(|p| p.starts_with('/') && !p.starts_with("//") && !p.starts_with("/\\"))
+        .filter(|p| HeaderValue::from_str(p).is_ok())
+        .unwrap_or_else(|| "/".to_string());
+
+    let url = reqwest::Url::parse_with_params(
+        &oidc.metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", oidc.client_id.as_str()),
+            ("redirect_uri", oidc.redirect_url.as_str()),
+            ("scope", "openid profile email"),
+            ("state", state_param.as_str()),
+            ("nonce", nonce.as_str()),
+            ("code_challenge", pkce_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    {
+        let mut pending = oidc.pending.write().await;
+        pending.retain(|_, p| p.created.elapsed() < PENDING_LOGIN_TTL);
+        if pending.len() >= MAX_PENDING_LOGINS {
+            tracing::warn!("Too many OIDC logins in flight, refusing another");
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        pending.insert(
+            state_param.clone(),
+            PendingLogin {
+                pkce_verifier,
+                nonce,
+                return_to,
+                created: Instant::now(),
+            },
+        );
+    }
+
+    let cookie = format!(
+        "{}={}; Path=/auth; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        STATE_COOKIE,
+        state_param,
+        PENDING_LOGIN_TTL.as_secs()
+    );
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(url.as_str())).into_response())
+}
+
+/// Query parameters the provider sends back to /auth/callback
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Finish an OIDC login: exchange the code, verify the ID token, start a session
+pub async fn callback_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Response, AppError> {
+    let Some(oidc) = state.oidc.as_ref() else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if let Some(error) = query.error {
+        tracing::warn!(error = %error, "OIDC provider returned an error");
+        return Ok((StatusCode::UNAUTHORIZED, Language::current().error("Sign-in was cancelled or failed")).into_response());
+    }
+
+    let (Some(code), Some(state_param)) = (query.code, query.state) else {
+        return Ok((StatusCode::BAD_REQUEST, Language::current().error("Missing code or state")).into_response());
+    };
+
+    // The login must have been started by this browser
+    if cookie_value(&headers, STATE_COOKIE) != Some(state_param.as_str()) {
+        tracing::warn!("OIDC callback without the matching state cookie");
+        return Ok((StatusCode::BAD_REQUEST, Language::current().error("Unknown or expired login attempt")).into_response());
+    }
+
+    let pending = oidc.pending.write().await.remove(&state_param);
+    let Some(pending) = pending.filter(|p| p.created.elapsed() < PENDING_LOGIN_TTL) else {
+        return Ok((StatusCode::BAD_REQUEST, Language::current().error("Unknown or expired login attempt")).into_response());
+    };
+
+    let principal = match oidc.complete_login(&code, &pending).await {
+        Ok(principal) => principal,
+        Err(e) => {
+            tracing::warn!(error = ?e, "OIDC login failed");
+            return Ok((StatusCode::UNAUTHORIZED, Language::current().error("Sign-in failed")).into_response());
+        }
+    };
+
+    tracing::info!(username = %principal.username, "OIDC login successful");
+
+    let session_id = random_token();
+    oidc.sessions.write().await.insert(
+        session_id.clone(),
+        Session {
+            principal,
+            expires: Instant::now().checked_add(oidc.session_ttl).ok_or(AppError::InternalError)?,
+        },
+    );
+
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(header::LOCATION, pending.return_to)
+        .header(
+            header::SET_COOKIE,
+            format!(
+                "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+                SESSION_COOKIE,
+                session_id,
+                oidc.session_ttl.as_secs()
+            ),
+        )
+        .header(
+            header::SET_COOKIE,
+            format!("{}=; Path=/auth; Max-Age=0; HttpOnly; Secure; SameSite=Lax", STATE_COOKIE),
+        )
+        .body(Body::empty())
+        .map_err(|_| AppError::InternalError)
+}
+
+/// End the current OIDC session
+pub async fn logout_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(oidc) = state.oidc.as_ref() {
+        if let Some(session_id) = cookie_value(&headers, SESSION_COOKIE) {
+            oidc.sessions.write().await.remove(session_id);
+        }
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            header::SET_COOKIE,
+            format!("{}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax", SESSION_COOKIE),
+        )
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(
+            "<p>You have been signed out. <a href=\"/auth/login\">Sign in again</a></p>",
+        ))
+        .unwrap()
+}
+
+/// Extract a cookie value from the request headers
+pub fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Generate a URL-safe random token (256 bits)
+fn random_token() -> String {
+    let random: [u8; 32] = rand::random();
+    base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(random)
+}
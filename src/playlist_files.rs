@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    Extension,
+};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::io::Read;
+use std::path::{Path as FsPath, PathBuf};
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::constants;
+use crate::handlers::{self, AppError, AppState, FilterResult};
+use crate::netfs;
+
+/// Largest playlist file read; anything beyond is ignored
+const MAX_PLAYLIST_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Check if a filename is an M3U or PLS playlist
+pub fn is_playlist_file(filename: &str) -> bool {
+    let lower = filename.to_lowercase();
+    constants::PLAYLIST_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
+/// Entries of a playlist file in order, as written
+/// M3U lists one entry per line with `#` comments; PLS has numbered `FileN=` keys.
+fn parse(contents: &str, pls: bool) -> Vec<String> {
+    let lines = contents
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+
+    if !pls {
+        return lines
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+    }
+
+    let mut entries: Vec<(u32, String)> = lines
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let number = key.trim().strip_prefix("File")?.parse().ok()?;
+            Some((number, value.trim().to_string()))
+        })
+        .collect();
+    entries.sort_by_key(|(number, _)| *number);
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Resolve a playlist entry to an audio file path relative to the media directory
+/// Relative entries are looked up next to the playlist first, then from the media root.
+/// Absolute paths and `file://` URLs must point inside the media directory. Other URLs,
+/// missing files and non-audio files resolve to `None`.
+fn resolve(media_dir: &FsPath, playlist_dir: &FsPath, entry: &str) -> Option<String> {
+    let entry = match entry.strip_prefix("file://") {
+        Some(url) => percent_decode_str(url).decode_utf8().ok()?.into_owned(),
+        None if entry.contains("://") => return None,
+        None => entry.to_string(),
+    };
+    // Playlists written on Windows use backslashes
+    let entry = entry.replace('\\', "/");
+    let entry_path = PathBuf::from(&entry);
+
+    let candidates = if entry_path.is_absolute() {
+        vec![entry_path]
+    } else {
+        vec![playlist_dir.join(&entry_path), media_dir.join(&entry_path)]
+    };
+    candidates.into_iter().find_map(|candidate| {
+        let canonical = netfs::canonicalize(&candidate).ok()?;
+        let relative = canonical.strip_prefix(media_dir).ok()?;
+        let relative = relative.to_str()?.replace('\\', "/");
+        (canonical.is_file() && handlers::media_type(&relative) == Some("audio")).then_some(relative)
+    })
+}
+
+/// Read a playlist file and resolve its entries
+/// Returns the resolved paths in order, and the number of entries that were skipped.
+fn read_playlist_file(media_dir: &FsPath, path: &FsPath) -> Result<(Vec<String>, usize)> {
+    let mut contents = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(MAX_PLAYLIST_FILE_SIZE).read_to_end(&mut contents))
+        .with_context(|| format!("Failed to read playlist {:?}", path))?;
+    // Older M3U files are often Latin-1; undecodable bytes simply won't resolve
+    let contents = String::from_utf8_lossy(&contents);
+    let pls = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+
+    let playlist_dir = path.parent().unwrap_or(media_dir);
+    let entries = parse(&contents, pls);
+    let total = entries.len();
+    let resolved: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| resolve(media_dir, playlist_dir, entry))
+        .collect();
+    let skipped = total - resolved.len();
+    Ok((resolved, skipped))
+}
+
+/// A playlist file found in the media directory, with its playable tracks
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlaylistFile {
+    /// Playlist file path relative to the media directory
+    pub path: String,
+    /// Playlist file name
+    pub name: String,
+    /// Audio files in playback order
+    pub tracks: Vec<FilterResult>,
+    /// Entries left out because they are missing, not audio, outside the media directory or not accessible
+    pub skipped: usize,
+}
+
+/// Read an `.m3u`, `.m3u8` or `.pls` playlist file from the media directory
+/// Relative entries resolve next to the playlist, then from the media root.
+#[utoipa::path(
+    get,
+    path = "/api/playlist-file/{path}",
+    params(("path" = String, Path, description = "Playlist file path relative to the media directory")),
+    responses(
+        (status = 200, description = "Playable tracks of the playlist", body = PlaylistFile),
+        (status = 403, description = "Path not accessible"),
+        (status = 404, description = "Playlist file not found")
+    ),
+    tag = "media"
+)]
+pub async fn playlist_file_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<PlaylistFile>, AppError> {
+    load_playlist_file(&state, principal.as_deref(), &path).await.map(Json)
+}
+
+/// Read a playlist file the user may access and look up its tracks
+pub async fn load_playlist_file(
+    state: &AppState,
+    principal: Option<&Principal>,
+    path: &str,
+) -> Result<PlaylistFile, AppError> {
+    let path = handlers::media_path(path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    let name = canonical
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_string();
+    if !canonical.is_file() || !is_playlist_file(&name) {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(principal, &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    let media_dir = state.media_dir.clone();
+    let (paths, mut skipped) =
+        tokio::task::spawn_blocking(move || read_playlist_file(&media_dir, &canonical))
+            .await
+            .map_err(|_| AppError::InternalError)?
+            .map_err(|e| {
+                tracing::error!("{:?}", e);
+                AppError::InternalError
+            })?;
+
+    let mut tracks = Vec::with_capacity(paths.len());
+    for track in paths {
+        if !state.acl.allows(principal, &track) {
+            skipped += 1;
+            continue;
+        }
+        let Ok(metadata) = tokio::fs::metadata(state.media_dir.join(&track)).await else {
+            skipped += 1;
+            continue;
+        };
+        tracks.push(FilterResult {
+            name: track.rsplit('/').next().unwrap_or_default().to_string(),
+            size: metadata.len(),
+            file_type: "audio".to_string(),
+            modified: handlers::modified_secs(&metadata),
+            path: track,
+        });
+    }
+
+    Ok(PlaylistFile {
+        path,
+        name,
+        tracks,
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m3u_skips_comments_and_blank_lines() {
+        let m3u = "\u{feff}#EXTM3U\n#EXTINF:123,Artist - Title\nmusic/a.mp3\n\n  b.flac  \r\n";
+        assert_eq!(parse(m3u, false), ["music/a.mp3", "b.flac"]);
+    }
+
+    #[test]
+    fn pls_orders_entries_by_number() {
+        let pls = "[playlist]\nFile2=second.mp3\nTitle2=Second\nFile1 = first.mp3\nNumberOfEntries=2\nVersion=2\n";
+        assert_eq!(parse(pls, true), ["first.mp3", "second.mp3"]);
+    }
+
+    #[test]
+    fn entries_resolve_inside_the_media_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("albums/one")).unwrap();
+        std::fs::write(dir.path().join("albums/one/a.mp3"), b"").unwrap();
+        std::fs::write(dir.path().join("root.mp3"), b"").unwrap();
+        std::fs::write(dir.path().join("albums/one/cover.jpg"), b"").unwrap();
+        let media_dir = dir.path().canonicalize().unwrap();
+        let playlist_dir = media_dir.join("albums/one");
+
+        let resolve = |entry: &str| resolve(&media_dir, &playlist_dir, entry);
+        assert_eq!(resolve("a.mp3").as_deref(), Some("albums/one/a.mp3"));
+        assert_eq!(resolve("root.mp3").as_deref(), Some("root.mp3"));
+        assert_eq!(resolve("..\\..\\root.mp3").as_deref(), Some("root.mp3"));
+        let url = format!("file://{}", media_dir.join("albums/one/a.mp3").display()).replace(' ', "%20");
+        assert_eq!(resolve(&url).as_deref(), Some("albums/one/a.mp3"));
+        assert_eq!(resolve("cover.jpg"), None);
+        assert_eq!(resolve("missing.mp3"), None);
+        assert_eq!(resolve("../../../etc/passwd"), None);
+        assert_eq!(resolve("http://example.com/stream.mp3"), None);
+    }
+}
@@ -0,0 +1,641 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use rusqlite::{params, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState, FilterResult};
+use crate::index::MediaIndex;
+
+/// Longest playlist name accepted, in characters
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Most tracks a playlist can hold
+const MAX_TRACKS: usize = 10_000;
+
+/// A playlist without its tracks
+#[derive(Debug, Serialize, ToSchema, async_graphql::SimpleObject)]
+pub struct PlaylistSummary {
+    /// Playlist ID
+    pub id: i64,
+    /// Playlist name
+    pub name: String,
+    /// Number of tracks
+    pub tracks: usize,
+    /// When the playlist was created (RFC 3339)
+    pub created_at: String,
+    /// When the playlist was last changed (RFC 3339)
+    pub updated_at: String,
+}
+
+/// A playlist with its tracks in order
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Playlist {
+    /// Playlist ID
+    pub id: i64,
+    /// Playlist name
+    pub name: String,
+    /// When the playlist was created (RFC 3339)
+    pub created_at: String,
+    /// When the playlist was last changed (RFC 3339)
+    pub updated_at: String,
+    /// Tracks in playback order
+    pub tracks: Vec<PlaylistTrack>,
+}
+
+/// A track in a playlist
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlaylistTrack {
+    /// Position in the playlist, starting at 0
+    pub position: usize,
+    #[serde(flatten)]
+    pub file: FilterResult,
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
+/// Replace the tracks of a playlist and bump its modification time
+fn write_tracks(tx: &Transaction, id: i64, paths: &[String], now: i64) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM playlist_tracks WHERE playlist_id = ?1", [id])?;
+    let mut insert =
+        tx.prepare("INSERT INTO playlist_tracks (playlist_id, position, path) VALUES (?1, ?2, ?3)")?;
+    for (position, path) in paths.iter().enumerate() {
+        insert.execute(params![id, position as i64, path])?;
+    }
+    tx.execute("UPDATE playlists SET updated_at = ?2 WHERE id = ?1", params![id, now])?;
+    Ok(())
+}
+
+/// Paths of a user's playlist in order, or `None` if the user has no such playlist
+fn track_paths(tx: &Transaction, username: &str, id: i64) -> rusqlite::Result<Option<Vec<String>>> {
+    let owned = tx
+        .query_row(
+            "SELECT 1 FROM playlists WHERE id = ?1 AND username = ?2",
+            params![id, username],
+            |_| Ok(()),
+        )
+        .optional()?;
+    if owned.is_none() {
+        return Ok(None);
+    }
+    let mut stmt =
+        tx.prepare("SELECT path FROM playlist_tracks WHERE playlist_id = ?1 ORDER BY position")?;
+    let paths = stmt.query_map([id], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+    Ok(Some(paths))
+}
+
+impl MediaIndex {
+    /// Create a playlist for a user and return its ID
+    pub async fn create_playlist(
+        &self,
+        username: String,
+        name: String,
+        tracks: Vec<String>,
+    ) -> anyhow::Result<i64> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO playlists (username, name, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+                params![username, name, now],
+            )?;
+            let id = tx.last_insert_rowid();
+            write_tracks(&tx, id, &tracks, now)?;
+            tx.commit()?;
+            Ok(id)
+        })
+        .await
+    }
+
+    /// A user's playlists, sorted by name
+    pub async fn playlists(&self, username: String) -> anyhow::Result<Vec<PlaylistSummary>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT p.id, p.name, p.created_at, p.updated_at,
+                        (SELECT COUNT(*) FROM playlist_tracks t WHERE t.playlist_id = p.id)
+                 FROM playlists p
+                 WHERE p.username = ?1
+                 ORDER BY p.name COLLATE NOCASE, p.id",
+            )?;
+            let rows = stmt.query_map([username], |row| {
+                Ok(PlaylistSummary {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    created_at: rfc3339(row.get(2)?),
+                    updated_at: rfc3339(row.get(3)?),
+                    tracks: row.get::<_, i64>(4)? as usize,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// A user's playlist with its tracks, or `None` if the user has no such playlist
+    /// Tracks not (yet) in the index are returned with a zero size and mtime.
+    pub async fn playlist(&self, username: String, id: i64) -> anyhow::Result<Option<Playlist>> {
+        self.with_conn(move |conn| {
+            let playlist = conn
+                .query_row(
+                    "SELECT name, created_at, updated_at FROM playlists WHERE id = ?1 AND username = ?2",
+                    params![id, username],
+                    |row| {
+                        Ok(Playlist {
+                            id,
+                            name: row.get(0)?,
+                            created_at: rfc3339(row.get(1)?),
+                            updated_at: rfc3339(row.get(2)?),
+                            tracks: Vec::new(),
+                        })
+                    },
+                )
+                .optional()?;
+            let Some(mut playlist) = playlist else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn.prepare(
+                "SELECT t.position, t.path, files.name, files.size, files.mtime
+                 FROM playlist_tracks t LEFT JOIN files ON files.path = t.path
+                 WHERE t.playlist_id = ?1
+                 ORDER BY t.position",
+            )?;
+            let rows = stmt.query_map([id], |row| {
+                let path: String = row.get(1)?;
+                let name = row
+                    .get::<_, Option<String>>(2)?
+                    .unwrap_or_else(|| path.rsplit('/').next().unwrap_or_default().to_string());
+                Ok(PlaylistTrack {
+                    position: row.get::<_, i64>(0)? as usize,
+                    file: FilterResult {
+                        size: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+                        modified: row.get::<_, Option<i64>>(4)?.unwrap_or(0).max(0) as u64,
+                        file_type: "audio".to_string(),
+                        path,
+                        name,
+                    },
+                })
+            })?;
+            playlist.tracks = rows.collect::<rusqlite::Result<_>>()?;
+            Ok(Some(playlist))
+        })
+        .await
+    }
+
+    /// Rename a user's playlist and/or replace its tracks; `false` if the user has no such playlist
+    pub async fn update_playlist(
+        &self,
+        username: String,
+        id: i64,
+        name: Option<String>,
+        tracks: Option<Vec<String>>,
+    ) -> anyhow::Result<bool> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let Some(paths) = track_paths(&tx, &username, id)? else {
+                return Ok(false);
+            };
+            if let Some(name) = name {
+                tx.execute("UPDATE playlists SET name = ?2 WHERE id = ?1", params![id, name])?;
+            }
+            write_tracks(&tx, id, tracks.as_deref().unwrap_or(&paths), now)?;
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Insert tracks into a user's playlist at a position, or append them
+    /// Returns `Ok(None)` if the user has no such playlist, and `Ok(Some(false))` if the
+    /// playlist would grow beyond its limit.
+    pub async fn add_playlist_tracks(
+        &self,
+        username: String,
+        id: i64,
+        tracks: Vec<String>,
+        position: Option<usize>,
+    ) -> anyhow::Result<Option<bool>> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let Some(mut paths) = track_paths(&tx, &username, id)? else {
+                return Ok(None);
+            };
+            if paths.len() + tracks.len() > MAX_TRACKS {
+                return Ok(Some(false));
+            }
+            let position = position.unwrap_or(paths.len()).min(paths.len());
+            paths.splice(position..position, tracks);
+            write_tracks(&tx, id, &paths, now)?;
+            tx.commit()?;
+            Ok(Some(true))
+        })
+        .await
+    }
+
+    /// Remove the track at a position from a user's playlist
+    /// Returns `Ok(None)` if the user has no such playlist, and `Ok(Some(false))` if there is
+    /// no track at that position.
+    pub async fn remove_playlist_track(
+        &self,
+        username: String,
+        id: i64,
+        position: usize,
+    ) -> anyhow::Result<Option<bool>> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let Some(mut paths) = track_paths(&tx, &username, id)? else {
+                return Ok(None);
+            };
+            if position >= paths.len() {
+                return Ok(Some(false));
+            }
+            paths.remove(position);
+            write_tracks(&tx, id, &paths, now)?;
+            tx.commit()?;
+            Ok(Some(true))
+        })
+        .await
+    }
+
+    /// Delete a user's playlist; `false` if the user has no such playlist
+    pub async fn delete_playlist(&self, username: String, id: i64) -> anyhow::Result<bool> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            if track_paths(&tx, &username, id)?.is_none() {
+                return Ok(false);
+            }
+            tx.execute("DELETE FROM playlist_tracks WHERE playlist_id = ?1", [id])?;
+            tx.execute("DELETE FROM playlists WHERE id = ?1", [id])?;
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+}
+
+/// A new playlist
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePlaylistRequest {
+    /// Playlist name (1 to 100 characters)
+    pub name: String,
+    /// Audio file paths in playback order
+    #[serde(default)]
+    pub tracks: Vec<String>,
+}
+
+/// Changes to a playlist; omitted fields are left alone
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePlaylistRequest {
+    /// New playlist name
+    pub name: Option<String>,
+    /// New track list, replacing the current one; use this to reorder tracks
+    pub tracks: Option<Vec<String>>,
+}
+
+/// Tracks to add to a playlist
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddTracksRequest {
+    /// Audio file paths to add, in order
+    pub tracks: Vec<String>,
+    /// Position to insert them at (default: the end)
+    pub position: Option<usize>,
+}
+
+/// Check a playlist name, trimming surrounding whitespace
+fn validate_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(AppError::InvalidInput("Playlist names must be 1 to 100 characters"));
+    }
+    Ok(name.to_string())
+}
+
+/// Check that every track is an accessible audio file, returning normalized paths
+fn validate_tracks(state: &AppState, principal: &Principal, tracks: &[String]) -> Result<Vec<String>, AppError> {
+    if tracks.len() > MAX_TRACKS {
+        return Err(AppError::InvalidInput("Playlists can hold at most 10000 tracks"));
+    }
+    tracks
+        .iter()
+        .map(|path| {
+            let path = handlers::media_path(path)?;
+            let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+            if !canonical.is_file() || handlers::media_type(&path) != Some("audio") {
+                return Err(AppError::InvalidInput("Playlist tracks must be audio files"));
+            }
+            if !state.acl.allows(Some(principal), &path) {
+                return Err(AppError::Forbidden);
+            }
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Fetch a playlist for a response, leaving out tracks the user can no longer access
+pub async fn load_playlist(state: &AppState, index: &MediaIndex, principal: &Principal, id: i64) -> Result<Playlist, AppError> {
+    let mut playlist = index
+        .playlist(principal.username.clone(), id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to read playlist: {:?}", e);
+            AppError::InternalError
+        })?
+        .ok_or(AppError::NotFound)?;
+    playlist
+        .tracks
+        .retain(|track| state.acl.allows(Some(principal), &track.file.path));
+    Ok(playlist)
+}
+
+fn internal_error(e: anyhow::Error) -> AppError {
+    tracing::error!("Failed to update playlist: {:?}", e);
+    AppError::InternalError
+}
+
+/// List the signed-in user's playlists, sorted by name
+#[utoipa::path(
+    get,
+    path = "/api/playlists",
+    responses(
+        (status = 200, description = "Playlists", body = Vec<PlaylistSummary>),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn list_playlists_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Vec<PlaylistSummary>>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    index
+        .playlists(principal.username.clone())
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to read playlists: {:?}", e);
+            AppError::InternalError
+        })
+}
+
+/// Create a playlist
+#[utoipa::path(
+    post,
+    path = "/api/playlists",
+    request_body = CreatePlaylistRequest,
+    responses(
+        (status = 200, description = "Playlist created", body = Playlist),
+        (status = 400, description = "Invalid name or track"),
+        (status = 403, description = "Not signed in, or a track is not accessible"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn create_playlist_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<CreatePlaylistRequest>,
+) -> Result<Json<Playlist>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+    let name = validate_name(&request.name)?;
+    let tracks = validate_tracks(&state, &principal, &request.tracks)?;
+
+    let id = index
+        .create_playlist(principal.username.clone(), name, tracks)
+        .await
+        .map_err(internal_error)?;
+    tracing::info!(username = %principal.username, id, "Playlist created");
+    load_playlist(&state, index, &principal, id).await.map(Json)
+}
+
+/// Get a playlist with its tracks, or export it as M3U8 when the ID ends in `.m3u8`
+/// The export lists absolute `/media/` URLs for external players, which must send the
+/// same credentials as the browser.
+#[utoipa::path(
+    get,
+    path = "/api/playlists/{id}",
+    params(("id" = String, Path, description = "Playlist ID, optionally followed by .m3u8")),
+    responses(
+        (status = 200, description = "Playlist, or an M3U8 file for `{id}.m3u8`", body = Playlist),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No such playlist, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn get_playlist_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+
+    let (id, export) = match id.strip_suffix(".m3u8") {
+        Some(id) => (id, true),
+        None => (id.as_str(), false),
+    };
+    let id = id.parse().map_err(|_| AppError::NotFound)?;
+    let playlist = load_playlist(&state, index, &principal, id).await?;
+    if !export {
+        return Ok(Json(playlist).into_response());
+    }
+
+    let host = handlers::request_host(&uri, &headers)?;
+    let tags = index.audio_tags().await.map_err(|e| {
+        tracing::error!("Failed to read audio tags: {:?}", e);
+        AppError::InternalError
+    })?;
+
+    let mut m3u = format!("#EXTM3U\n#PLAYLIST:{}\n", playlist.name);
+    for track in &playlist.tracks {
+        let title = tags
+            .get(&track.file.path)
+            .and_then(|tags| match (&tags.artist, &tags.title) {
+                (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+                (None, Some(title)) => Some(title.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| track.file.name.clone());
+        m3u.push_str(&format!(
+            "#EXTINF:-1,{}\nhttps://{}/media/{}\n",
+            title,
+            host,
+            handlers::encode_url_path(&track.file.path)
+        ));
+    }
+
+    let file_name: String = playlist
+        .name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "audio/x-mpegurl; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.m3u8\"", file_name),
+            ),
+        ],
+        m3u,
+    )
+        .into_response())
+}
+
+/// Rename a playlist and/or replace its tracks
+#[utoipa::path(
+    put,
+    path = "/api/playlists/{id}",
+    params(("id" = i64, Path, description = "Playlist ID")),
+    request_body = UpdatePlaylistRequest,
+    responses(
+        (status = 200, description = "Playlist updated", body = Playlist),
+        (status = 400, description = "Invalid name or track"),
+        (status = 403, description = "Not signed in, or a track is not accessible"),
+        (status = 404, description = "No such playlist, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn update_playlist_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<i64>,
+    Json(request): Json<UpdatePlaylistRequest>,
+) -> Result<Json<Playlist>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+    let name = request.name.as_deref().map(validate_name).transpose()?;
+    let tracks = request
+        .tracks
+        .as_deref()
+        .map(|tracks| validate_tracks(&state, &principal, tracks))
+        .transpose()?;
+
+    let found = index
+        .update_playlist(principal.username.clone(), id, name, tracks)
+        .await
+        .map_err(internal_error)?;
+    if !found {
+        return Err(AppError::NotFound);
+    }
+    load_playlist(&state, index, &principal, id).await.map(Json)
+}
+
+/// Delete a playlist
+#[utoipa::path(
+    delete,
+    path = "/api/playlists/{id}",
+    params(("id" = i64, Path, description = "Playlist ID")),
+    responses(
+        (status = 204, description = "Playlist deleted"),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No such playlist, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn delete_playlist_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let found = index
+        .delete_playlist(principal.username.clone(), id)
+        .await
+        .map_err(internal_error)?;
+    if !found {
+        return Err(AppError::NotFound);
+    }
+    tracing::info!(username = %principal.username, id, "Playlist deleted");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Add tracks to a playlist, at the end or at a given position
+#[utoipa::path(
+    post,
+    path = "/api/playlists/{id}/tracks",
+    params(("id" = i64, Path, description = "Playlist ID")),
+    request_body = AddTracksRequest,
+    responses(
+        (status = 200, description = "Tracks added", body = Playlist),
+        (status = 400, description = "Invalid track, or too many tracks"),
+        (status = 403, description = "Not signed in, or a track is not accessible"),
+        (status = 404, description = "No such playlist, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn add_tracks_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<i64>,
+    Json(request): Json<AddTracksRequest>,
+) -> Result<Json<Playlist>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+    if request.tracks.is_empty() {
+        return Err(AppError::InvalidInput("At least one track is required"));
+    }
+    let tracks = validate_tracks(&state, &principal, &request.tracks)?;
+
+    match index
+        .add_playlist_tracks(principal.username.clone(), id, tracks, request.position)
+        .await
+        .map_err(internal_error)?
+    {
+        None => Err(AppError::NotFound),
+        Some(false) => Err(AppError::InvalidInput("Playlists can hold at most 10000 tracks")),
+        Some(true) => load_playlist(&state, index, &principal, id).await.map(Json),
+    }
+}
+
+/// Remove the track at a position from a playlist; later tracks move up
+#[utoipa::path(
+    delete,
+    path = "/api/playlists/{id}/tracks/{position}",
+    params(
+        ("id" = i64, Path, description = "Playlist ID"),
+        ("position" = usize, Path, description = "Position of the track, starting at 0")
+    ),
+    responses(
+        (status = 200, description = "Track removed", body = Playlist),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No such playlist or position, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn remove_track_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path((id, position)): Path<(i64, usize)>,
+) -> Result<Json<Playlist>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+
+    match index
+        .remove_playlist_track(principal.username.clone(), id, position)
+        .await
+        .map_err(internal_error)?
+    {
+        Some(true) => load_playlist(&state, index, &principal, id).await.map(Json),
+        _ => Err(AppError::NotFound),
+    }
+}
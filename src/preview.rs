@@ -0,0 +1,273 @@
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+use crate::i18n::Language;
+use crate::share_card::{self, CARD_HEIGHT, CARD_WIDTH};
+use crate::sidecar::DirectoryMeta;
+
+/// Open Graph and Twitter card tags of a gallery page, for link previews in chat apps
+pub struct LinkPreview {
+    pub title: String,
+    pub description: Option<String>,
+    /// Absolute URL of the page
+    pub url: String,
+    /// Absolute URL of the directory's share card
+    pub image: String,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub site_name: String,
+    /// oEmbed discovery URL of the page
+    pub oembed: String,
+}
+
+impl LinkPreview {
+    /// Preview of the gallery page of `path`, a validated directory
+    pub fn directory(state: &AppState, host: &str, path: &str, meta: &DirectoryMeta) -> Self {
+        let url = page_url(host, path);
+        LinkPreview {
+            title: directory_title(state, path, meta),
+            description: meta.description.clone(),
+            image: share_card::card_url(host, path),
+            image_width: CARD_WIDTH,
+            image_height: CARD_HEIGHT,
+            site_name: state.branding.title.clone(),
+            oembed: format!(
+                "https://{}/oembed?url={}&format=json",
+                host,
+                utf8_percent_encode(&url, NON_ALPHANUMERIC)
+            ),
+            url,
+        }
+    }
+}
+
+/// Absolute URL of the gallery page of a directory
+fn page_url(host: &str, path: &str) -> String {
+    if path.is_empty() {
+        format!("https://{}/", host)
+    } else {
+        format!("https://{}/browse/{}", host, handlers::encode_url_path(path))
+    }
+}
+
+fn media_url(host: &str, path: &str) -> String {
+    format!("https://{}/media/{}", host, handlers::encode_url_path(path))
+}
+
+/// Sidecar title, else the directory name, else the site title for the root
+pub fn directory_title(state: &AppState, path: &str, meta: &DirectoryMeta) -> String {
+    meta.title
+        .clone()
+        .or_else(|| path.rsplit('/').next().filter(|name| !name.is_empty()).map(str::to_string))
+        .unwrap_or_else(|| state.branding.title.clone())
+}
+
+/// The sidecar cover of a directory if it is a readable image, else the one picked from the cache
+pub async fn directory_cover(
+    state: &AppState,
+    principal: Option<&Principal>,
+    path: &str,
+    meta: &DirectoryMeta,
+) -> Option<String> {
+    let sidecar_cover = meta
+        .cover
+        .as_ref()
+        .map(|cover| if path.is_empty() { cover.clone() } else { format!("{}/{}", path, cover) })
+        .filter(|cover| handlers::media_type(cover) == Some("image") && state.media_dir.join(cover).is_file());
+    if sidecar_cover.is_some() {
+        return sidecar_cover;
+    }
+    let cache = state.media_cache.read().await;
+    cache
+        .cover_image(path, |path| state.acl.allows(principal, path))
+        .map(str::to_string)
+}
+
+/// Query parameters of the oEmbed endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OEmbedQuery {
+    /// Gallery page (`/` or `/browse/...`) or media file (`/media/...`) URL on this server
+    pub url: String,
+    /// Only `json` is supported
+    pub format: Option<String>,
+    /// Largest width the consumer can show, in pixels
+    pub maxwidth: Option<u32>,
+    /// Largest height the consumer can show, in pixels
+    pub maxheight: Option<u32>,
+}
+
+/// oEmbed 1.0 description of a gallery page or media file
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OEmbedResponse {
+    /// Always "1.0"
+    pub version: &'static str,
+    /// `photo` for images, `link` for directories, videos and audio
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub title: String,
+    pub provider_name: String,
+    pub provider_url: String,
+    /// Image URL, for photos
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_height: Option<u32>,
+}
+
+/// Request path and query of an absolute or root-relative URL
+/// `https://gallery.example.com/browse/a?page=2` gives `("/browse/a", Some("page=2"))`.
+pub fn url_path(url: &str) -> (&str, Option<&str>) {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .map(|rest| rest.find('/').map_or("/", |slash| &rest[slash..]))
+        .unwrap_or(url);
+    let rest = rest.split('#').next().unwrap_or(rest);
+    match rest.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (rest, None),
+    }
+}
+
+/// Media path an oEmbed URL points at, for the auth middleware's public directory check
+pub fn oembed_target(query: &str) -> Option<String> {
+    let url = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("url="))
+        .and_then(|value| percent_decode_str(value).decode_utf8().ok())?;
+    Some(url_path(&url).0.to_string())
+}
+
+/// Dimensions of an image file, scaled down to fit `maxwidth` and `maxheight`
+async fn image_size(path: std::path::PathBuf, query: &OEmbedQuery) -> Option<(u32, u32)> {
+    let (width, height) = tokio::task::spawn_blocking(move || image::image_dimensions(path))
+        .await
+        .ok()?
+        .ok()?;
+    Some(fit(width, height, query))
+}
+
+/// `width`×`height` scaled down to fit `maxwidth` and `maxheight`
+fn fit(width: u32, height: u32, query: &OEmbedQuery) -> (u32, u32) {
+    let scale = [
+        query.maxwidth.map(|max| max as f64 / width as f64),
+        query.maxheight.map(|max| max as f64 / height as f64),
+    ]
+    .into_iter()
+    .flatten()
+    .fold(1.0_f64, f64::min);
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+/// oEmbed description of a gallery page or media file on this server
+/// Chat apps find this through the `<link rel="alternate">` tag of gallery pages and show a
+/// preview card. Images are `photo` embeds; directories, videos and audio are `link` embeds,
+/// with the directory's share card as thumbnail. Only the JSON format is supported.
+#[utoipa::path(
+    get,
+    path = "/oembed",
+    params(OEmbedQuery),
+    responses(
+        (status = 200, description = "oEmbed description", body = OEmbedResponse),
+        (status = 403, description = "Target not accessible"),
+        (status = 404, description = "Not a gallery page or media file of this server"),
+        (status = 501, description = "Format other than json")
+    ),
+    tag = "media"
+)]
+pub async fn oembed_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<OEmbedQuery>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if query.format.as_deref().is_some_and(|format| format != "json") {
+        return Ok((StatusCode::NOT_IMPLEMENTED, Language::current().error("Only the json format is supported")).into_response());
+    }
+    let principal = principal.as_deref();
+    let host = handlers::request_host(&uri, &headers)?;
+    let (path, url_query) = url_path(&query.url);
+    let path = percent_decode_str(path)
+        .decode_utf8()
+        .map_err(|_| AppError::InvalidPath)?;
+    let (directory, target) = if path == "/" {
+        (true, "")
+    } else if let Some(dir) = path.strip_prefix("/browse/") {
+        (true, dir.trim_matches('/'))
+    } else if let Some(file) = path.strip_prefix("/media/") {
+        (false, file.trim_matches('/'))
+    } else {
+        return Err(AppError::NotFound);
+    };
+
+    if !target.is_empty() && !state.acl.allows(principal, target) {
+        return Err(AppError::Forbidden);
+    }
+    let canonical = handlers::validate_media_path(&state.media_dir, target)?;
+    let provider_url = format!("https://{}/", host);
+    let mut response = OEmbedResponse {
+        version: "1.0",
+        kind: "link",
+        title: String::new(),
+        provider_name: state.branding.title.clone(),
+        provider_url,
+        url: None,
+        width: None,
+        height: None,
+        thumbnail_url: None,
+        thumbnail_width: None,
+        thumbnail_height: None,
+    };
+
+    if directory {
+        if !canonical.is_dir() {
+            return Err(AppError::NotFound);
+        }
+        let meta = DirectoryMeta::load(&canonical).await;
+        let (width, height) = fit(CARD_WIDTH, CARD_HEIGHT, &query);
+        response.title = directory_title(&state, target, &meta);
+        response.thumbnail_url = Some(share_card::card_url(host, target));
+        response.thumbnail_width = Some(width);
+        response.thumbnail_height = Some(height);
+    } else {
+        let media_type = handlers::media_type(target);
+        if !canonical.is_file() || media_type.is_none() {
+            return Err(AppError::NotFound);
+        }
+        response.title = target.rsplit('/').next().unwrap_or(target).to_string();
+        if media_type == Some("image") {
+            let (width, height) = image_size(canonical, &query).await.ok_or(AppError::NotFound)?;
+            // Keep the signature of a signed link, so the consumer can fetch the image
+            let url = media_url(host, target);
+            response.kind = "photo";
+            response.url = Some(match url_query {
+                Some(url_query) => format!("{}?{}", url, url_query),
+                None => url,
+            });
+            response.width = Some(width);
+            response.height = Some(height);
+        }
+    }
+    Ok(Json(response).into_response())
+}
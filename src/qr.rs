@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, Uri},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use image::Luma;
+use qrcode::{render::svg, QrCode};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+
+/// Side of a QR code when a request doesn't ask for a size, in pixels
+const DEFAULT_SIZE: u32 = 256;
+
+/// Smallest and largest side a request can ask for, in pixels
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+
+/// Image format of a QR code
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+/// Query parameters of the QR code endpoint
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct QrQuery {
+    /// File or directory relative to the media directory; the server itself if omitted
+    pub target: Option<String>,
+    /// `png` (default) or `svg`
+    pub format: Option<QrFormat>,
+    /// Smallest side of the image in pixels (64 to 2048, default 256)
+    pub size: Option<u32>,
+}
+
+/// The URL a QR code for `target` opens
+/// Files get a signed media link when URL signing is enabled, so the phone scanning the code
+/// can open them without an account; directories and unsigned files still ask for a login.
+fn share_url(
+    state: &AppState,
+    principal: Option<&Principal>,
+    host: &str,
+    target: Option<&str>,
+) -> Result<String, AppError> {
+    let Some(target) = target.map(|t| t.trim_matches('/')).filter(|t| !t.is_empty()) else {
+        return Ok(format!("https://{}/", host));
+    };
+    if !state.acl.allows(principal, target) {
+        return Err(AppError::Forbidden);
+    }
+    let canonical = handlers::validate_media_path(&state.media_dir, target)?;
+    if canonical.is_dir() {
+        return Ok(format!("https://{}/browse/{}", host, handlers::encode_url_path(target)));
+    }
+    if !canonical.is_file() || handlers::media_type(target).is_none() {
+        return Err(AppError::NotFound);
+    }
+
+    let path = format!("/media/{}", target);
+    Ok(match (&state.url_signer, principal) {
+        (Some(signer), Some(principal)) => format!("https://{}{}", host, signer.sign(&principal.username, &path)),
+        _ => format!("https://{}{}", host, handlers::encode_url_path(&path)),
+    })
+}
+
+/// QR code of the server's address, or of a link to a file or directory
+/// Point a phone's camera at it to open the gallery there. Without a target the code holds
+/// the address of this server as the request reached it. A directory's code opens its
+/// gallery page. A file's code opens the file itself, through a signed link that works
+/// without logging in when URL signing is enabled; anyone holding that link can open the
+/// file until the signing secret changes.
+#[utoipa::path(
+    get,
+    path = "/api/qr",
+    params(QrQuery),
+    responses(
+        (status = 200, description = "QR code as PNG or SVG", content_type = "image/png"),
+        (status = 400, description = "Target too long for a QR code"),
+        (status = 403, description = "Target not accessible"),
+        (status = 404, description = "Target not found")
+    ),
+    tag = "media"
+)]
+pub async fn qr_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<QrQuery>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let host = handlers::request_host(&uri, &headers)?;
+    let url = share_url(&state, principal.as_deref(), host, query.target.as_deref())?;
+    let code = QrCode::new(url.as_bytes())
+        .map_err(|_| AppError::InvalidInput("Target is too long for a QR code"))?;
+    let size = query.size.unwrap_or(DEFAULT_SIZE).clamp(MIN_SIZE, MAX_SIZE);
+
+    // Signed links belong to the requesting user
+    let cache = (header::CACHE_CONTROL, "private, no-store");
+    Ok(match query.format.unwrap_or_default() {
+        QrFormat::Png => {
+            let image = code.render::<Luma<u8>>().min_dimensions(size, size).build();
+            let mut data = Vec::new();
+            image
+                .write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Png)
+                .map_err(|_| AppError::InternalError)?;
+            ([(header::CONTENT_TYPE, "image/png"), cache], data).into_response()
+        }
+        QrFormat::Svg => {
+            let image = code.render::<svg::Color>().min_dimensions(size, size).build();
+            ([(header::CONTENT_TYPE, "image/svg+xml"), cache], image).into_response()
+        }
+    })
+}
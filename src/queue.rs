@@ -0,0 +1,538 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::Json,
+    Extension,
+};
+use rand::seq::SliceRandom;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState, FilterResult};
+use crate::index::MediaIndex;
+use crate::{playlist_files, playlists};
+
+/// Most tracks a queue can hold
+const MAX_QUEUE_LENGTH: usize = 10_000;
+
+/// A track in a stored queue
+pub struct QueuedTrack {
+    pub file: FilterResult,
+    /// Position in the unshuffled order
+    pub original: usize,
+}
+
+/// A user's play queue as stored in the index database
+pub struct StoredQueue {
+    /// Tracks in playback order
+    pub tracks: Vec<QueuedTrack>,
+    /// Index of the current track
+    pub current: usize,
+    /// Seconds into the current track
+    pub position: f64,
+    pub shuffled: bool,
+    pub updated_at: i64,
+}
+
+impl MediaIndex {
+    /// A user's play queue, or `None` if they have none
+    /// Tracks not (yet) in the index are returned with a zero size and mtime.
+    pub async fn queue(&self, username: String) -> anyhow::Result<Option<StoredQueue>> {
+        self.with_conn(move |conn| {
+            let state = conn
+                .query_row(
+                    "SELECT current, position, shuffled, updated_at FROM queue_state WHERE username = ?1",
+                    [&username],
+                    |row| {
+                        Ok(StoredQueue {
+                            tracks: Vec::new(),
+                            current: row.get::<_, i64>(0)? as usize,
+                            position: row.get(1)?,
+                            shuffled: row.get(2)?,
+                            updated_at: row.get(3)?,
+                        })
+                    },
+                )
+                .optional()?;
+            let Some(mut queue) = state else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn.prepare(
+                "SELECT q.path, q.original, files.name, files.size, files.mtime
+                 FROM queue_tracks q LEFT JOIN files ON files.path = q.path
+                 WHERE q.username = ?1
+                 ORDER BY q.position",
+            )?;
+            let rows = stmt.query_map([&username], |row| {
+                let path: String = row.get(0)?;
+                let name = row
+                    .get::<_, Option<String>>(2)?
+                    .unwrap_or_else(|| path.rsplit('/').next().unwrap_or_default().to_string());
+                Ok(QueuedTrack {
+                    original: row.get::<_, i64>(1)? as usize,
+                    file: FilterResult {
+                        size: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+                        modified: row.get::<_, Option<i64>>(4)?.unwrap_or(0).max(0) as u64,
+                        file_type: "audio".to_string(),
+                        path,
+                        name,
+                    },
+                })
+            })?;
+            queue.tracks = rows.collect::<rusqlite::Result<_>>()?;
+            Ok(Some(queue))
+        })
+        .await
+    }
+
+    /// Replace a user's play queue
+    pub async fn save_queue(
+        &self,
+        username: String,
+        tracks: Vec<(String, usize)>,
+        current: usize,
+        position: f64,
+        shuffled: bool,
+    ) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM queue_tracks WHERE username = ?1", [&username])?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT INTO queue_tracks (username, position, original, path) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for (position, (path, original)) in tracks.iter().enumerate() {
+                    insert.execute(params![username, position as i64, *original as i64, path])?;
+                }
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO queue_state (username, current, position, shuffled, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![username, current as i64, position, shuffled, now],
+            )?;
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Move a user's queue to a track and a position within it
+    pub async fn set_queue_position(&self, username: String, current: usize, position: f64) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE queue_state SET current = ?2, position = ?3, updated_at = ?4 WHERE username = ?1",
+                params![username, current as i64, position, now],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Delete a user's play queue
+    pub async fn clear_queue(&self, username: String) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM queue_tracks WHERE username = ?1", [&username])?;
+            tx.execute("DELETE FROM queue_state WHERE username = ?1", [&username])?;
+            tx.commit()
+        })
+        .await
+    }
+}
+
+/// What to build a queue from; exactly one source must be given
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct QueueRequest {
+    /// Directory whose audio files to queue, by name
+    pub directory: Option<String>,
+    /// Include audio files in subdirectories of `directory`
+    #[serde(default)]
+    pub recursive: bool,
+    /// ID of one of your playlists
+    pub playlist: Option<i64>,
+    /// Path of an `.m3u`, `.m3u8` or `.pls` file
+    pub playlist_file: Option<String>,
+    /// Audio file paths in order
+    pub tracks: Option<Vec<String>>,
+    /// Shuffle the queue, keeping the starting track first
+    #[serde(default)]
+    pub shuffle: bool,
+    /// Index of the track to start with (default 0)
+    #[serde(default)]
+    pub start: usize,
+}
+
+/// Turn shuffle on or off
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ShuffleRequest {
+    pub enabled: bool,
+}
+
+/// Jump to a track and a position within it
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct QueuePositionRequest {
+    /// Index of the track in the queue
+    pub current: usize,
+    /// Seconds into the track
+    #[serde(default)]
+    pub position: f64,
+}
+
+/// A user's play queue
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Queue {
+    /// Tracks in playback order
+    pub tracks: Vec<FilterResult>,
+    /// Index of the current track, or null if the queue is empty
+    pub current: Option<usize>,
+    /// Seconds into the current track
+    pub position: f64,
+    /// Whether the queue is shuffled
+    pub shuffled: bool,
+    /// When the queue last changed (RFC 3339), or null if there is no queue
+    pub updated_at: Option<String>,
+}
+
+impl From<Option<StoredQueue>> for Queue {
+    fn from(queue: Option<StoredQueue>) -> Self {
+        match queue {
+            Some(queue) => Queue {
+                current: (!queue.tracks.is_empty()).then_some(queue.current),
+                tracks: queue.tracks.into_iter().map(|track| track.file).collect(),
+                position: queue.position,
+                shuffled: queue.shuffled,
+                updated_at: chrono::DateTime::from_timestamp(queue.updated_at, 0)
+                    .map(|t| t.to_rfc3339()),
+            },
+            None => Queue {
+                tracks: Vec::new(),
+                current: None,
+                position: 0.0,
+                shuffled: false,
+                updated_at: None,
+            },
+        }
+    }
+}
+
+/// Order tracks for playback: shuffled with the current track first, or in their original order
+/// Returns the tracks with their original positions and the index of the current track.
+fn arrange(mut tracks: Vec<(String, usize)>, current: usize, shuffle: bool) -> (Vec<(String, usize)>, usize) {
+    if tracks.is_empty() {
+        return (tracks, 0);
+    }
+    let current = current.min(tracks.len() - 1);
+    if shuffle {
+        let first = tracks.remove(current);
+        tracks.shuffle(&mut rand::thread_rng());
+        tracks.insert(0, first);
+        (tracks, 0)
+    } else {
+        let original = tracks[current].1;
+        tracks.sort_by_key(|(_, original)| *original);
+        (tracks, original)
+    }
+}
+
+fn read_error(e: anyhow::Error) -> AppError {
+    tracing::error!("Failed to read play queue: {:?}", e);
+    AppError::InternalError
+}
+
+fn write_error(e: anyhow::Error) -> AppError {
+    tracing::error!("Failed to save play queue: {:?}", e);
+    AppError::InternalError
+}
+
+/// Resolve a queue request to the audio file paths it names, in order
+async fn source_tracks(state: &AppState, principal: &Principal, request: &QueueRequest) -> Result<Vec<String>, AppError> {
+    let sources = [
+        request.directory.is_some(),
+        request.playlist.is_some(),
+        request.playlist_file.is_some(),
+        request.tracks.is_some(),
+    ];
+    if sources.iter().filter(|given| **given).count() != 1 {
+        return Err(AppError::InvalidInput(
+            "Exactly one of directory, playlist, playlist_file or tracks is required",
+        ));
+    }
+
+    if let Some(directory) = &request.directory {
+        let directory = &handlers::media_path(directory)?;
+        if !directory.is_empty() {
+            let canonical = handlers::validate_media_path(&state.media_dir, directory)?;
+            if !canonical.is_dir() {
+                return Err(AppError::NotFound);
+            }
+        }
+        if !state.acl.allows(Some(principal), directory) {
+            return Err(AppError::Forbidden);
+        }
+        let cache = state.media_cache.read().await;
+        let mut files: Vec<&FilterResult> = cache
+            .items_in(directory, request.recursive)
+            .filter(|item| item.file_type == "audio")
+            .filter(|item| state.acl.allows(Some(principal), &item.path))
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        return Ok(files.into_iter().map(|item| item.path.clone()).collect());
+    }
+
+    if let Some(id) = request.playlist {
+        let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+        let playlist = playlists::load_playlist(state, index, principal, id).await?;
+        return Ok(playlist.tracks.into_iter().map(|track| track.file.path).collect());
+    }
+
+    if let Some(path) = &request.playlist_file {
+        let playlist = playlist_files::load_playlist_file(state, Some(principal), path).await?;
+        return Ok(playlist.tracks.into_iter().map(|track| track.path).collect());
+    }
+
+    let tracks = request.tracks.as_deref().unwrap_or_default();
+    tracks
+        .iter()
+        .map(|path| {
+            let path = handlers::media_path(path)?;
+            let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+            if !canonical.is_file() || handlers::media_type(&path) != Some("audio") {
+                return Err(AppError::InvalidInput("Queued tracks must be audio files"));
+            }
+            if !state.acl.allows(Some(principal), &path) {
+                return Err(AppError::Forbidden);
+            }
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Get the signed-in user's play queue
+/// An empty queue is returned if none has been built yet.
+#[utoipa::path(
+    get,
+    path = "/api/queue",
+    responses(
+        (status = 200, description = "Play queue", body = Queue),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn get_queue_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Queue>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let queue = index.queue(principal.username.clone()).await.map_err(read_error)?;
+    Ok(Json(queue.into()))
+}
+
+/// Replace the play queue with a directory, playlist, playlist file or list of tracks
+#[utoipa::path(
+    post,
+    path = "/api/queue",
+    request_body = QueueRequest,
+    responses(
+        (status = 200, description = "New play queue", body = Queue),
+        (status = 400, description = "No single source, invalid track, or too many tracks"),
+        (status = 403, description = "Not signed in, or a source is not accessible"),
+        (status = 404, description = "Source not found, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn build_queue_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<QueueRequest>,
+) -> Result<Json<Queue>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+
+    let paths = source_tracks(&state, &principal, &request).await?;
+    if paths.len() > MAX_QUEUE_LENGTH {
+        return Err(AppError::InvalidInput("Queues can hold at most 10000 tracks"));
+    }
+    let tracks = paths.into_iter().enumerate().map(|(i, path)| (path, i)).collect();
+    let (tracks, current) = arrange(tracks, request.start, request.shuffle);
+
+    index
+        .save_queue(principal.username.clone(), tracks, current, 0.0, request.shuffle)
+        .await
+        .map_err(write_error)?;
+    let queue = index.queue(principal.username.clone()).await.map_err(read_error)?;
+    Ok(Json(queue.into()))
+}
+
+/// Delete the signed-in user's play queue
+#[utoipa::path(
+    delete,
+    path = "/api/queue",
+    responses(
+        (status = 204, description = "Queue deleted"),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn clear_queue_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<StatusCode, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    index
+        .clear_queue(principal.username.clone())
+        .await
+        .map_err(write_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Shuffle or unshuffle the play queue
+/// Shuffling keeps the current track and moves it to the front; unshuffling restores the
+/// original order and stays on the current track.
+#[utoipa::path(
+    put,
+    path = "/api/queue/shuffle",
+    request_body = ShuffleRequest,
+    responses(
+        (status = 200, description = "Reordered play queue", body = Queue),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No queue, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn shuffle_queue_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<ShuffleRequest>,
+) -> Result<Json<Queue>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let queue = index
+        .queue(principal.username.clone())
+        .await
+        .map_err(read_error)?
+        .ok_or(AppError::NotFound)?;
+    let tracks = queue
+        .tracks
+        .into_iter()
+        .map(|track| (track.file.path, track.original))
+        .collect();
+    let (tracks, current) = arrange(tracks, queue.current, request.enabled);
+
+    index
+        .save_queue(principal.username.clone(), tracks, current, queue.position, request.enabled)
+        .await
+        .map_err(write_error)?;
+    let queue = index.queue(principal.username.clone()).await.map_err(read_error)?;
+    Ok(Json(queue.into()))
+}
+
+/// Move to a track in the queue, and remember how far into it playback is
+/// Players call this periodically so playback can resume after a reload or on another device.
+#[utoipa::path(
+    put,
+    path = "/api/queue/position",
+    request_body = QueuePositionRequest,
+    responses(
+        (status = 200, description = "Updated play queue", body = Queue),
+        (status = 400, description = "Track index or position out of range"),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No queue, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn queue_position_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<QueuePositionRequest>,
+) -> Result<Json<Queue>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let mut queue = index
+        .queue(principal.username.clone())
+        .await
+        .map_err(read_error)?
+        .ok_or(AppError::NotFound)?;
+    if request.current >= queue.tracks.len() || !request.position.is_finite() || request.position < 0.0 {
+        return Err(AppError::InvalidInput("Track index or position out of range"));
+    }
+
+    index
+        .set_queue_position(principal.username.clone(), request.current, request.position)
+        .await
+        .map_err(write_error)?;
+    queue.current = request.current;
+    queue.position = request.position;
+    queue.updated_at = chrono::Utc::now().timestamp();
+    Ok(Json(Some(queue).into()))
+}
+
+/// Skip to the next track, wrapping around to the first
+#[utoipa::path(
+    post,
+    path = "/api/queue/next",
+    responses(
+        (status = 200, description = "Updated play queue", body = Queue),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No queue, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn next_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Queue>, AppError> {
+    step(state, principal, 1).await
+}
+
+/// Go back to the previous track, wrapping around to the last
+#[utoipa::path(
+    post,
+    path = "/api/queue/previous",
+    responses(
+        (status = 200, description = "Updated play queue", body = Queue),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No queue, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn previous_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Queue>, AppError> {
+    step(state, principal, -1).await
+}
+
+/// Move the current track by `offset`, starting it from the beginning
+async fn step(state: AppState, principal: Option<Extension<Principal>>, offset: isize) -> Result<Json<Queue>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let mut queue = index
+        .queue(principal.username.clone())
+        .await
+        .map_err(read_error)?
+        .filter(|queue| !queue.tracks.is_empty())
+        .ok_or(AppError::NotFound)?;
+    let len = queue.tracks.len() as isize;
+    queue.current = (queue.current as isize + offset).rem_euclid(len) as usize;
+    queue.position = 0.0;
+
+    index
+        .set_queue_position(principal.username.clone(), queue.current, 0.0)
+        .await
+        .map_err(write_error)?;
+    queue.updated_at = chrono::Utc::now().timestamp();
+    Ok(Json(Some(queue).into()))
+}
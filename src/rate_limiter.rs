@@ -1,4 +1,12 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
@@ -77,4 +85,157 @@ impl AuthRateLimiter {
             "Cleaned up rate limiter"
         );
     }
+
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Number of IPs with recent failed attempts, and how many of them are currently locked out
+    pub async fn snapshot(&self) -> (usize, usize) {
+        let attempts = self.attempts.read().await;
+        let cutoff = Instant::now() - self.window;
+
+        let recent = attempts
+            .values()
+            .map(|ip_attempts| ip_attempts.iter().filter(|&&t| t > cutoff).count())
+            .filter(|&count| count > 0);
+        let (mut tracked, mut blocked) = (0, 0);
+        for count in recent {
+            tracked += 1;
+            if count >= self.max_attempts {
+                blocked += 1;
+            }
+        }
+        (tracked, blocked)
+    }
+}
+
+/// Token bucket state for one client
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// General per-IP request rate limiter (token bucket)
+/// Each client may burst up to `burst` requests, refilled at `rate` requests per second.
+#[derive(Clone)]
+pub struct RequestRateLimiter {
+    buckets: Arc<RwLock<HashMap<IpAddr, Bucket>>>,
+    rate: f64,
+    burst: f64,
+}
+
+impl RequestRateLimiter {
+    /// Create a new rate limiter
+    /// rate: Sustained requests per second allowed per client
+    /// burst: Maximum number of requests allowed at once
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            buckets: Arc::new(RwLock::new(HashMap::new())),
+            rate,
+            burst: f64::from(burst.max(1)),
+        }
+    }
+
+    /// Take a token for a request from this IP
+    /// Returns `Err` with the time until the next token is available if the bucket is empty
+    pub async fn try_acquire(&self, ip: IpAddr) -> Result<(), Duration> {
+        let mut buckets = self.buckets.write().await;
+        let now = Instant::now();
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+
+        // Refill tokens for the time since the last request
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            // A tiny rate may put the next token further off than a Duration reaches
+            Err(Duration::try_from_secs_f64((1.0 - bucket.tokens) / self.rate).unwrap_or(Duration::MAX))
+        }
+    }
+
+    /// Cleanup buckets that have refilled completely (call periodically)
+    pub async fn cleanup(&self) {
+        let mut buckets = self.buckets.write().await;
+        let now = Instant::now();
+
+        buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + elapsed * self.rate < self.burst
+        });
+
+        tracing::debug!(
+            tracked_ips = buckets.len(),
+            "Cleaned up request rate limiter"
+        );
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst as u32
+    }
+
+    /// Number of clients with a partly drained bucket, and how many of them are currently throttled
+    pub async fn snapshot(&self) -> (usize, usize) {
+        let buckets = self.buckets.read().await;
+        let now = Instant::now();
+
+        let (mut tracked, mut throttled) = (0, 0);
+        for bucket in buckets.values() {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            let tokens = bucket.tokens + elapsed * self.rate;
+            if tokens < self.burst {
+                tracked += 1;
+            }
+            if tokens < 1.0 {
+                throttled += 1;
+            }
+        }
+        (tracked, throttled)
+    }
+}
+
+/// Middleware applying the per-IP request rate limit (if enabled), returning 429 when exceeded
+pub async fn request_rate_limit_middleware(
+    State(limiter): State<Option<RequestRateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = limiter else {
+        return next.run(request).await;
+    };
+    let ip = peer.ip().to_canonical();
+
+    match limiter.try_acquire(ip).await {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            metrics::counter!("doggygallery_rate_limited_total", "limiter" => "request").increment(1);
+            tracing::warn!(
+                client_ip = %ip,
+                path = %request.uri().path(),
+                "Request rate limited"
+            );
+
+            Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header(header::RETRY_AFTER, (retry_after.as_secs_f64().ceil() as u64).max(1).to_string())
+                .body(Body::from("Too many requests. Slow down and try again shortly."))
+                .unwrap()
+        }
+    }
 }
@@ -0,0 +1,178 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    Extension,
+};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+use crate::index::MediaIndex;
+use crate::models::DirectoryEntry;
+
+/// Highest star rating
+pub const MAX_RATING: u8 = 5;
+
+impl MediaIndex {
+    /// Set a user's rating of a file, replacing any previous rating
+    pub async fn set_rating(&self, username: String, path: String, rating: u8) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO ratings (username, path, rating, rated_at) VALUES (?1, ?2, ?3, ?4)",
+                params![username, path, rating, now],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Remove a user's rating of a file
+    pub async fn clear_rating(&self, username: String, path: String) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM ratings WHERE username = ?1 AND path = ?2",
+                params![username, path],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// All of a user's ratings, keyed by path
+    pub async fn ratings(&self, username: String) -> anyhow::Result<HashMap<String, u8>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT path, rating FROM ratings WHERE username = ?1")?;
+            let rows = stmt.query_map([username], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await
+    }
+}
+
+/// The signed-in user's ratings; empty for anonymous users or without an index database
+pub async fn user_ratings(
+    state: &AppState,
+    principal: Option<&Principal>,
+) -> Result<HashMap<String, u8>, AppError> {
+    let (Some(index), Some(principal)) = (&state.index, principal) else {
+        return Ok(HashMap::new());
+    };
+    index.ratings(principal.username.clone()).await.map_err(|e| {
+        tracing::error!("Failed to read ratings: {:?}", e);
+        AppError::InternalError
+    })
+}
+
+/// Fill in the user's ratings of directory listing entries
+pub async fn attach_ratings(
+    state: &AppState,
+    principal: Option<&Principal>,
+    entries: &mut [DirectoryEntry],
+) {
+    // A failure is already logged; the listing is shown without ratings
+    if let Ok(ratings) = user_ratings(state, principal).await {
+        for entry in entries {
+            entry.rating = ratings.get(&entry.path).copied();
+        }
+    }
+}
+
+/// A star rating to give a file
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RatingRequest {
+    /// Stars from 1 to 5
+    pub rating: u8,
+}
+
+/// A user's rating of a file
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RatingState {
+    /// File path relative to the media directory
+    pub path: String,
+    /// Stars from 1 to 5, or null if unrated
+    pub rating: Option<u8>,
+}
+
+/// Rate a file from 1 to 5 stars
+/// Ratings are per user; rating a file again replaces the earlier rating.
+#[utoipa::path(
+    put,
+    path = "/api/rating/{path}",
+    params(("path" = String, Path, description = "File path relative to the media directory")),
+    request_body = RatingRequest,
+    responses(
+        (status = 200, description = "Rating saved", body = RatingState),
+        (status = 400, description = "Rating not between 1 and 5"),
+        (status = 403, description = "Not signed in, or path not accessible"),
+        (status = 404, description = "File not found, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn set_rating_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+    Json(request): Json<RatingRequest>,
+) -> Result<Json<RatingState>, AppError> {
+    if !(1..=MAX_RATING).contains(&request.rating) {
+        return Err(AppError::InvalidInput("Rating must be between 1 and 5"));
+    }
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+
+    let path = handlers::media_path(&path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !canonical.is_file() || handlers::media_type(&path).is_none() {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(Some(&principal), &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    index
+        .set_rating(principal.username.clone(), path.clone(), request.rating)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to save rating: {:?}", e);
+            AppError::InternalError
+        })?;
+    Ok(Json(RatingState {
+        path,
+        rating: Some(request.rating),
+    }))
+}
+
+/// Remove the signed-in user's rating of a file
+#[utoipa::path(
+    delete,
+    path = "/api/rating/{path}",
+    params(("path" = String, Path, description = "File path relative to the media directory")),
+    responses(
+        (status = 200, description = "Rating removed", body = RatingState),
+        (status = 403, description = "Not signed in"),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn clear_rating_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<RatingState>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let principal = principal.ok_or(AppError::Forbidden)?;
+
+    let path = handlers::media_path(&path)?;
+    index
+        .clear_rating(principal.username.clone(), path.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to remove rating: {:?}", e);
+            AppError::InternalError
+        })?;
+    Ok(Json(RatingState { path, rating: None }))
+}
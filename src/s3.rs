@@ -0,0 +1,355 @@
+use anyhow::{Context, Result};
+use axum::http::{header, StatusCode};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, StreamExt};
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::store::{self, ByteStream, MediaStore, StoredFile};
+
+/// SHA-256 of an empty request body, which every request here has
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Longest validity S3 accepts for a presigned URL
+const MAX_PRESIGN_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Time a listing page or metadata request may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Characters SigV4 leaves unencoded
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+/// An S3 bucket, or a prefix in one, accessed with AWS Signature Version 4
+/// Requests use path-style URLs (`https://endpoint/bucket/key`), which AWS and the
+/// S3-compatible stores (MinIO, Ceph, Cloudflare R2, Backblaze B2, Wasabi) all accept.
+pub struct S3Store {
+    client: reqwest::Client,
+    /// Scheme, host and port, without a trailing slash
+    endpoint: String,
+    /// `Host` header value the signatures cover
+    host: String,
+    bucket: String,
+    /// Key prefix of the media, with a trailing slash, or empty for the whole bucket
+    prefix: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    /// Hand out presigned URLs for downloads (`--s3-presign`)
+    presign: bool,
+}
+
+impl S3Store {
+    pub fn new(config: &Config, bucket: &str, prefix: &str) -> Result<Self> {
+        let endpoint = match &config.s3_endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("https://s3.{}.amazonaws.com", config.s3_region),
+        };
+        let url = reqwest::Url::parse(&endpoint).with_context(|| format!("Invalid --s3-endpoint {:?}", endpoint))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => anyhow::bail!("--s3-endpoint {:?} has no host", endpoint),
+        };
+        let (Some(access_key_id), Some(secret_access_key)) = (&config.s3_access_key_id, &config.s3_secret_access_key)
+        else {
+            anyhow::bail!("--backend s3:// needs --s3-access-key-id and --s3-secret-access-key");
+        };
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create HTTP client for S3")?;
+        Ok(Self {
+            client,
+            endpoint,
+            host,
+            bucket: bucket.to_string(),
+            prefix: if prefix.is_empty() { String::new() } else { format!("{}/", prefix) },
+            region: config.s3_region.clone(),
+            access_key_id: access_key_id.clone(),
+            secret_access_key: secret_access_key.clone(),
+            session_token: config.s3_session_token.clone(),
+            presign: config.s3_presign,
+        })
+    }
+
+    /// Request path of an object, each segment encoded
+    fn object_uri(&self, path: &str) -> String {
+        let key = format!("{}{}", self.prefix, path);
+        let key: Vec<String> = key.split('/').map(uri_encode).collect();
+        format!("/{}/{}", uri_encode(&self.bucket), key.join("/"))
+    }
+
+    fn scope(&self, date: &str) -> String {
+        format!("{}/{}/s3/aws4_request", date, self.region)
+    }
+
+    /// SigV4 signature of a canonical request made at `amz_date`
+    fn signature(&self, amz_date: &str, canonical_request: &str) -> String {
+        let date = &amz_date[..8];
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            self.scope(date),
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = [date, self.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(format!("AWS4{}", self.secret_access_key).into_bytes(), |key, part| {
+                hmac(&key, part.as_bytes())
+            });
+        hex(&hmac(&key, string_to_sign.as_bytes()))
+    }
+
+    /// Headers authorizing a request without a body
+    fn authorization(&self, method: &str, uri: &str, query: &str, now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        // Signed headers must be sorted by name
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", EMPTY_SHA256.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+        let signed_headers = signed_headers.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, uri, query, canonical_headers, signed_headers, EMPTY_SHA256
+        );
+
+        let signature = self.signature(&amz_date, &canonical_request);
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id,
+                self.scope(&amz_date[..8]),
+                signed_headers,
+                signature
+            ),
+        ));
+        // The HTTP client sets Host itself
+        headers.retain(|(name, _)| *name != "host");
+        headers
+    }
+
+    /// Send a signed request, turning error responses into errors
+    async fn send(&self, method: reqwest::Method, uri: &str, query: &str, range: Option<(u64, u64)>) -> io::Result<reqwest::Response> {
+        let url = match query {
+            "" => format!("{}{}", self.endpoint, uri),
+            query => format!("{}{}?{}", self.endpoint, uri, query),
+        };
+        let mut request = self.client.request(method.clone(), &url);
+        for (name, value) in self.authorization(method.as_str(), uri, query, Utc::now()) {
+            request = request.header(name, value);
+        }
+        match range {
+            Some((start, len)) => {
+                request = request.header(header::RANGE, format!("bytes={}-{}", start, start + len - 1));
+            }
+            None => request = request.timeout(REQUEST_TIMEOUT),
+        }
+
+        let response = request.send().await.map_err(io::Error::other)?;
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            StatusCode::NOT_FOUND => Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found in bucket", uri))),
+            status => {
+                let body = response.text().await.unwrap_or_default();
+                let code = text(&body, "Code").unwrap_or_default();
+                Err(io::Error::other(format!("S3 answered {} {} for {}", status, code, uri)))
+            }
+        }
+    }
+
+    /// One page of a ListObjectsV2 listing, and the token of the next page
+    async fn list_page(&self, token: Option<&str>) -> io::Result<(Vec<StoredFile>, Option<String>)> {
+        let mut params = vec![("encoding-type", "url"), ("list-type", "2")];
+        if !self.prefix.is_empty() {
+            params.push(("prefix", self.prefix.as_str()));
+        }
+        if let Some(token) = token {
+            params.push(("continuation-token", token));
+        }
+        let uri = format!("/{}", uri_encode(&self.bucket));
+        let body = self
+            .send(reqwest::Method::GET, &uri, &canonical_query(&params), None)
+            .await?
+            .text()
+            .await
+            .map_err(io::Error::other)?;
+
+        let mut files = Vec::new();
+        for contents in elements(&body, "Contents") {
+            let key = text(contents, "Key").unwrap_or_default();
+            let key = percent_decode_str(&key).decode_utf8_lossy();
+            // Keys ending in a slash are folder markers, not files
+            let Some(path) = key.strip_prefix(&self.prefix).filter(|path| !path.ends_with('/')) else {
+                continue;
+            };
+            let Some(path) = store::clean_path(path).filter(|path| !path.is_empty()) else {
+                continue;
+            };
+            let size = text(contents, "Size").and_then(|size| size.parse().ok()).unwrap_or(0);
+            let modified = text(contents, "LastModified")
+                .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+                .map_or(0, |time| time.timestamp().max(0) as u64);
+            files.push(StoredFile { path, size, modified });
+        }
+
+        let truncated = text(&body, "IsTruncated").is_some_and(|value| value == "true");
+        let next = text(&body, "NextContinuationToken");
+        Ok((files, next.filter(|_| truncated)))
+    }
+}
+
+impl MediaStore for S3Store {
+    fn list(&self) -> BoxFuture<'_, io::Result<Vec<StoredFile>>> {
+        Box::pin(async move {
+            let (mut files, mut token) = self.list_page(None).await?;
+            while let Some(next) = token {
+                let (page, next) = self.list_page(Some(&next)).await?;
+                files.extend(page);
+                token = next;
+            }
+            Ok(files)
+        })
+    }
+
+    fn stat<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<StoredFile>> {
+        Box::pin(async move {
+            let response = self.send(reqwest::Method::HEAD, &self.object_uri(path), "", None).await?;
+            let headers = response.headers();
+            let size = headers
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0);
+            let modified = headers
+                .get(header::LAST_MODIFIED)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+                .map_or(0, |time| time.timestamp().max(0) as u64);
+            Ok(StoredFile {
+                path: path.to_string(),
+                size,
+                modified,
+            })
+        })
+    }
+
+    fn read_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> BoxFuture<'a, io::Result<ByteStream>> {
+        Box::pin(async move {
+            if len == 0 {
+                return Ok(stream::empty().boxed());
+            }
+            let response = self
+                .send(reqwest::Method::GET, &self.object_uri(path), "", Some((start, len)))
+                .await?;
+            // Stop after the first error, which ends the response body
+            let chunks = stream::unfold(Some(response), |response| async move {
+                let mut response = response?;
+                match response.chunk().await {
+                    Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(io::Error::other(e)), None)),
+                }
+            });
+            Ok(chunks.boxed())
+        })
+    }
+
+    fn signed_url(&self, path: &str, mime: &str, expires: Duration) -> Option<String> {
+        if !self.presign {
+            return None;
+        }
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let credential = format!("{}/{}", self.access_key_id, self.scope(&amz_date[..8]));
+        let expires = expires.min(MAX_PRESIGN_EXPIRY).as_secs().to_string();
+        let mut params = vec![
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256"),
+            ("X-Amz-Credential", credential.as_str()),
+            ("X-Amz-Date", amz_date.as_str()),
+            ("X-Amz-Expires", expires.as_str()),
+            ("X-Amz-SignedHeaders", "host"),
+            ("response-content-type", mime),
+        ];
+        if let Some(token) = &self.session_token {
+            params.push(("X-Amz-Security-Token", token));
+        }
+
+        let uri = self.object_uri(path);
+        let query = canonical_query(&params);
+        let canonical_request = format!("GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD", uri, query, self.host);
+        let signature = self.signature(&amz_date, &canonical_request);
+        Some(format!("{}{}?{}&X-Amz-Signature={}", self.endpoint, uri, query, signature))
+    }
+}
+
+fn uri_encode(value: &str) -> String {
+    utf8_percent_encode(value, UNRESERVED).to_string()
+}
+
+/// Query string with its parameters encoded and sorted, as SigV4 signs it
+fn canonical_query(params: &[(&str, &str)]) -> String {
+    let mut pairs: Vec<(String, String)> = params
+        .iter()
+        .map(|(name, value)| (uri_encode(name), uri_encode(value)))
+        .collect();
+    pairs.sort();
+    let pairs: Vec<String> = pairs.into_iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    pairs.join("&")
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Raw contents of every `<tag>` element in an S3 response, in order
+/// S3 responses are flat and predictable enough not to need a full XML parser.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(&close) else {
+            break;
+        };
+        found.push(&after[..end]);
+        rest = &after[end + close.len()..];
+    }
+    found
+}
+
+/// Unescaped text of the first `<tag>` element
+fn text(xml: &str, tag: &str) -> Option<String> {
+    elements(xml, tag).first().map(|raw| unescape(raw))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,317 @@
+use anyhow::{Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, Query, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, TantivyDocument, Value, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, Term};
+
+use crate::handlers::FilterResult;
+use crate::index::MediaIndex;
+use crate::metadata::{self, AudioTags};
+
+/// Memory budget of the index writer
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// Optional full-text index over file names, paths and audio tags
+/// Kept on disk and rebuilt from the media cache whenever its contents change.
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+    /// Hash of the file list last indexed, so unchanged refreshes skip the rebuild
+    fingerprint: Mutex<Option<u64>>,
+    /// Tags read from disk when there is no media index database, keyed by path and mtime
+    tags: Mutex<HashMap<String, (u64, Option<AudioTags>)>>,
+}
+
+struct Fields {
+    path: Field,
+    path_text: Field,
+    name: Field,
+    file_type: Field,
+    size: Field,
+    modified: Field,
+    artist: Field,
+    album: Field,
+    title: Field,
+    genre: Field,
+}
+
+impl Fields {
+    /// Text fields searched by a query, with the boost applied to exact matches in each
+    fn searched(&self) -> [(Field, f32); 6] {
+        [
+            (self.name, 3.0),
+            (self.title, 3.0),
+            (self.artist, 2.0),
+            (self.album, 2.0),
+            (self.genre, 1.0),
+            (self.path_text, 1.0),
+        ]
+    }
+}
+
+/// One search result with its relevance score
+pub struct SearchHit {
+    pub file: FilterResult,
+    pub score: f32,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        path: builder.add_text_field("path_id", STRING | STORED),
+        path_text: builder.add_text_field("path", TEXT),
+        name: builder.add_text_field("name", TEXT | STORED),
+        file_type: builder.add_text_field("file_type", STRING | STORED),
+        size: builder.add_u64_field("size", STORED),
+        modified: builder.add_u64_field("modified", STORED),
+        artist: builder.add_text_field("artist", TEXT),
+        album: builder.add_text_field("album", TEXT),
+        title: builder.add_text_field("title", TEXT),
+        genre: builder.add_text_field("genre", TEXT),
+    };
+    (builder.build(), fields)
+}
+
+impl SearchIndex {
+    /// Open (or create) the search index in a directory
+    /// Files indexed by a previous run are searchable right away.
+    pub fn open(dir: &Path) -> Result<Arc<Self>> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create search index directory: {:?}", dir))?;
+        let (schema, fields) = schema();
+        let directory = MmapDirectory::open(dir)
+            .with_context(|| format!("Failed to open search index directory: {:?}", dir))?;
+        let index = Index::open_or_create(directory, schema).with_context(|| {
+            format!(
+                "Failed to open search index {:?}; delete the directory to rebuild it",
+                dir
+            )
+        })?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .context("Failed to open search index reader")?;
+        let writer = index
+            .writer_with_num_threads(1, WRITER_MEMORY)
+            .context("Failed to open search index writer")?;
+
+        Ok(Arc::new(Self {
+            index,
+            reader,
+            writer: Mutex::new(writer),
+            fields,
+            fingerprint: Mutex::new(None),
+            tags: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Number of files in the index
+    pub fn len(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    /// Reindex all files if the list differs from the one last indexed
+    /// Audio tags come from the media index database when there is one, otherwise they are
+    /// read from disk and remembered until the file changes.
+    pub async fn rebuild(
+        self: &Arc<Self>,
+        items: Vec<FilterResult>,
+        media_dir: &Path,
+        media_index: Option<&MediaIndex>,
+    ) -> Result<()> {
+        let fingerprint = fingerprint(&items);
+        if *self.fingerprint.lock().unwrap_or_else(|e| e.into_inner()) == Some(fingerprint) {
+            return Ok(());
+        }
+
+        let indexed_tags = match media_index {
+            Some(index) => Some(index.audio_tags().await?),
+            None => None,
+        };
+
+        let this = self.clone();
+        let media_dir = media_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || this.write_all(&items, &media_dir, indexed_tags))
+            .await
+            .context("Search index task panicked")??;
+
+        *self.fingerprint.lock().unwrap_or_else(|e| e.into_inner()) = Some(fingerprint);
+        Ok(())
+    }
+
+    fn write_all(
+        &self,
+        items: &[FilterResult],
+        media_dir: &Path,
+        indexed_tags: Option<HashMap<String, AudioTags>>,
+    ) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut cached_tags = self.tags.lock().unwrap_or_else(|e| e.into_inner());
+        let mut seen_tags = HashMap::new();
+        let f = &self.fields;
+
+        writer.delete_all_documents()?;
+        for item in items {
+            let mut document = doc!(
+                f.path => item.path.as_str(),
+                f.path_text => item.path.as_str(),
+                f.name => item.name.as_str(),
+                f.file_type => item.file_type.as_str(),
+                f.size => item.size,
+                f.modified => item.modified,
+            );
+
+            let tags = match (&indexed_tags, item.file_type.as_str()) {
+                (Some(indexed), _) => indexed.get(&item.path).cloned(),
+                (None, "audio") => {
+                    let tags = match cached_tags.remove(&item.path) {
+                        Some((modified, tags)) if modified == item.modified => tags,
+                        _ => metadata::read_audio_tags(&media_dir.join(&item.path)),
+                    };
+                    seen_tags.insert(item.path.clone(), (item.modified, tags.clone()));
+                    tags
+                }
+                (None, _) => None,
+            };
+            if let Some(tags) = tags {
+                for (field, value) in [
+                    (f.artist, tags.artist),
+                    (f.album, tags.album),
+                    (f.title, tags.title),
+                    (f.genre, tags.genre),
+                ] {
+                    if let Some(value) = value {
+                        document.add_text(field, value);
+                    }
+                }
+            }
+            writer.add_document(document)?;
+        }
+        writer.commit().context("Failed to commit search index")?;
+        self.reader.reload().context("Failed to reload search index")?;
+
+        // Forget tags of files that no longer exist
+        *cached_tags = seen_tags;
+        tracing::info!("Search index rebuilt: {} files", items.len());
+        Ok(())
+    }
+
+    /// Search for files matching every word of the query, best matches first
+    /// Each word matches exactly, as a prefix, or with a typo or two (depending on its
+    /// length) in the name, path or audio tags. Exact matches rank highest.
+    pub fn search(&self, text: &str, file_type: Option<&str>, limit: usize) -> Result<Vec<SearchHit>> {
+        let words = self.tokenize(text)?;
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = words
+            .iter()
+            .map(|word| (Occur::Must, self.word_query(word)))
+            .collect();
+        if let Some(file_type) = file_type {
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(self.fields.file_type, file_type),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        }
+        let query = BooleanQuery::new(clauses);
+
+        let searcher = self.reader.searcher();
+        let top = searcher.search(&query, &TopDocs::with_limit(limit.max(1)))?;
+        let mut hits = Vec::with_capacity(top.len());
+        for (score, address) in top {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let text = |field| {
+                document
+                    .get_first(field)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let number = |field| document.get_first(field).and_then(|v| v.as_u64()).unwrap_or(0);
+            hits.push(SearchHit {
+                file: FilterResult {
+                    path: text(self.fields.path),
+                    name: text(self.fields.name),
+                    size: number(self.fields.size),
+                    file_type: text(self.fields.file_type),
+                    modified: number(self.fields.modified),
+                },
+                score,
+            });
+        }
+        Ok(hits)
+    }
+
+    /// Split a query into lowercase words the same way indexed text is split
+    fn tokenize(&self, text: &str) -> Result<Vec<String>> {
+        let mut analyzer = self.index.tokenizer_for_field(self.fields.name)?;
+        let mut stream = analyzer.token_stream(text);
+        let mut words = Vec::new();
+        while stream.advance() {
+            words.push(stream.token().text.clone());
+        }
+        Ok(words)
+    }
+
+    /// Query matching one word in any searched field
+    fn word_query(&self, word: &str) -> Box<dyn Query> {
+        // Longer words tolerate more typos
+        let distance = match word.chars().count() {
+            0..=3 => 0,
+            4..=7 => 1,
+            _ => 2,
+        };
+
+        let mut alternatives: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for (field, boost) in self.fields.searched() {
+            let term = Term::from_field_text(field, word);
+            alternatives.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(TermQuery::new(term.clone(), IndexRecordOption::WithFreqs)),
+                    boost,
+                )),
+            ));
+            alternatives.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(FuzzyTermQuery::new_prefix(term.clone(), 0, true)),
+                    boost / 2.0,
+                )),
+            ));
+            if distance > 0 {
+                alternatives.push((
+                    Occur::Should,
+                    Box::new(BoostQuery::new(
+                        Box::new(FuzzyTermQuery::new(term, distance, true)),
+                        boost / 4.0,
+                    )),
+                ));
+            }
+        }
+        Box::new(BooleanQuery::new(alternatives))
+    }
+}
+
+/// Hash of every file's path, size and mtime
+fn fingerprint(items: &[FilterResult]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for item in items {
+        (&item.path, item.size, item.modified).hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::io;
+use std::path::Path;
+
+use crate::acl;
+use crate::branding;
+use crate::cache::ScanProgress;
+use crate::config::Config;
+use crate::index;
+use crate::store;
+use crate::tls;
+
+/// Certificate lifetime left below which the checks warn
+const EXPIRY_WARNING_DAYS: i64 = 14;
+
+/// Unreadable files and directories named in a summary
+const MAX_EXAMPLES: usize = 5;
+
+/// Media files and directories found below the media directory, and those that can't be read
+#[derive(Default)]
+pub struct MediaScan {
+    pub files: u64,
+    pub directories: u64,
+    pub unreadable_files: u64,
+    pub unreadable_directories: u64,
+    /// A few of the unreadable paths, with their errors
+    pub examples: Vec<String>,
+}
+
+impl MediaScan {
+    /// One-line summary of the unreadable paths, if there are any
+    pub fn problems(&self) -> Option<String> {
+        if self.unreadable_files == 0 && self.unreadable_directories == 0 {
+            return None;
+        }
+        Some(format!(
+            "{} media file(s) and {} directory(ies) can't be read by this user, e.g. {}",
+            self.unreadable_files,
+            self.unreadable_directories,
+            self.examples.join("; ")
+        ))
+    }
+}
+
+/// Check that the media directory can be listed by the user running the server
+pub fn check_media_dir(dir: &Path) -> Result<()> {
+    match std::fs::read_dir(dir) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => anyhow::bail!(
+            "Media directory {:?} is not readable by this user ({}); grant read and execute permission, e.g. `chmod a+rx`",
+            dir,
+            e
+        ),
+        Err(e) => Err(e).with_context(|| format!("Media directory {:?} can't be listed", dir)),
+    }
+}
+
+/// Walk the media directory and try to open every media file
+/// Directories hidden by their settings file are skipped, as the server does.
+pub fn scan_media(dir: &Path) -> MediaScan {
+    let progress = ScanProgress::new();
+    let mut scan = MediaScan::default();
+    index::walk(dir, "", &progress, &mut |path, relative, _, _, _| {
+        scan.files += 1;
+        if let Err(e) = std::fs::File::open(path) {
+            scan.unreadable_files += 1;
+            if scan.examples.len() < MAX_EXAMPLES {
+                scan.examples.push(format!("{}: {}", relative, e));
+            }
+        }
+    });
+    let status = progress.status(true);
+    scan.directories = status.directories_scanned;
+    scan.unreadable_directories = status.error_count;
+    let room = MAX_EXAMPLES.saturating_sub(scan.examples.len());
+    scan.examples.extend(status.errors.into_iter().take(room));
+    scan
+}
+
+/// Describe a certificate's validity period, warning when it ends soon
+fn describe_validity(validity: &tls::Validity) -> (String, bool) {
+    let days_left = (validity.not_after - Utc::now()).num_days();
+    let description = format!(
+        "valid from {} until {} ({} day(s) left)",
+        validity.not_before.format("%Y-%m-%d"),
+        validity.not_after.format("%Y-%m-%d"),
+        days_left
+    );
+    (description, days_left < EXPIRY_WARNING_DAYS)
+}
+
+/// Checks run before the server starts, which stop it with an explanation instead of letting
+/// it fail on every request: a listable media directory, and a certificate that matches its
+/// key and is valid now. Unreadable media files are counted in the background afterwards.
+pub fn startup(config: &Config) -> Result<()> {
+    if let Some(media_dir) = &config.media_dir {
+        check_media_dir(media_dir)?;
+    }
+    if let (false, Some(cert), Some(key)) = (config.self_signed_certs_on_the_fly, &config.cert, &config.key) {
+        let (description, expiring) = describe_validity(&tls::check_certificate(cert, key)?);
+        if expiring {
+            tracing::warn!("Self-check: the certificate is {}; renew it soon", description);
+        } else {
+            tracing::info!("Self-check: the certificate matches its key and is {}", description);
+        }
+    }
+    if let Some(media_dir) = config.media_dir.clone() {
+        tokio::task::spawn_blocking(move || {
+            let scan = scan_media(&media_dir);
+            match scan.problems() {
+                Some(problems) => tracing::warn!("Self-check: {}", problems),
+                None => tracing::info!(
+                    "Self-check: all {} media files in {} directories are readable",
+                    scan.files,
+                    scan.directories
+                ),
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Result of one check of `doggygallery check`
+enum Outcome {
+    Ok(String),
+    Warning(String),
+    Failed(String),
+}
+
+impl From<Result<String>> for Outcome {
+    fn from(result: Result<String>) -> Self {
+        match result {
+            Ok(message) => Outcome::Ok(message),
+            Err(e) => Outcome::Failed(format!("{:#}", e)),
+        }
+    }
+}
+
+/// `doggygallery check`: run every check without starting the server and print a summary,
+/// failing if any check failed
+pub async fn run_check_command(config: Config) -> Result<()> {
+    let mut outcomes = Vec::new();
+    let configuration = config
+        .validate()
+        .and_then(|()| config.load_public_dirs())
+        .and_then(|_| branding::Branding::from_config(&config));
+    outcomes.push(("Configuration", Outcome::from(configuration.map(|_| "valid".to_string()))));
+
+    let credentials = config.load_users().and_then(|users| {
+        let tokens = config.load_tokens()?;
+        let mut summary = format!("{} user(s), {} API token(s)", users.len(), tokens.len());
+        if config.jwt_enabled() {
+            summary.push_str(", JWT/OIDC identities");
+        }
+        Ok(summary)
+    });
+    outcomes.push(("Credentials", Outcome::from(credentials)));
+    if let Some(acl_file) = &config.acl_file {
+        let rules = acl::AccessControl::load(acl_file).map(|acl| format!("{} rule(s) in {:?}", acl.len(), acl_file));
+        outcomes.push(("Access rules", Outcome::from(rules)));
+    }
+
+    if let Some(media_dir) = &config.media_dir {
+        match check_media_dir(media_dir) {
+            Ok(()) => {
+                outcomes.push(("Media directory", Outcome::Ok(format!("{:?} is readable", media_dir))));
+                let dir = media_dir.clone();
+                let scan = tokio::task::spawn_blocking(move || scan_media(&dir)).await?;
+                let found = format!("{} in {} directories", scan.files, scan.directories);
+                let outcome = match scan.problems() {
+                    Some(problems) => Outcome::Warning(format!("{}; {}", found, problems)),
+                    None => Outcome::Ok(format!("{}, all readable", found)),
+                };
+                outcomes.push(("Media files", outcome));
+            }
+            Err(e) => outcomes.push(("Media directory", Outcome::Failed(format!("{:#}", e)))),
+        }
+    }
+    if let Some(backend) = &config.backend {
+        let outcome = match store::open(&config) {
+            Ok(Some(store)) => match store.list().await {
+                Ok(files) => Outcome::Ok(format!("{} lists {} files", backend, files.len())),
+                Err(e) => Outcome::Failed(format!("{} can't be listed: {}", backend, e)),
+            },
+            Ok(None) => Outcome::Failed(format!("{} could not be opened", backend)),
+            Err(e) => Outcome::Failed(format!("{:#}", e)),
+        };
+        outcomes.push(("Media store", outcome));
+    }
+
+    let certificate = match (config.self_signed_certs_on_the_fly, &config.cert, &config.key) {
+        (true, _, _) => Some(Outcome::Warning("self-signed on the fly; browsers will warn".to_string())),
+        (false, Some(cert), Some(key)) => Some(match tls::check_certificate(cert, key) {
+            Ok(validity) => match describe_validity(&validity) {
+                (description, true) => Outcome::Warning(format!("{}; renew it soon", description)),
+                (description, false) => Outcome::Ok(format!("matches its key, {}", description)),
+            },
+            Err(e) => Outcome::Failed(format!("{:#}", e)),
+        }),
+        _ => None,
+    };
+    if let Some(outcome) = certificate {
+        outcomes.push(("Certificate", outcome));
+    }
+    outcomes.push(("Clock", Outcome::Ok(Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string())));
+
+    let mut failures = 0;
+    for (check, outcome) in &outcomes {
+        let (status, message) = match outcome {
+            Outcome::Ok(message) => ("ok", message),
+            Outcome::Warning(message) => ("warn", message),
+            Outcome::Failed(message) => {
+                failures += 1;
+                ("FAIL", message)
+            }
+        };
+        println!("{:<5} {}: {}", status, check, message);
+    }
+    if failures > 0 {
+        anyhow::bail!("{} check(s) failed", failures);
+    }
+    Ok(())
+}
@@ -0,0 +1,359 @@
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+    Extension,
+};
+use image::{imageops::FilterType, Rgb, RgbImage};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use crate::auth::Principal;
+use crate::decoding;
+use crate::embedded::StaticAssets;
+use crate::handlers::{self, AppError, AppState};
+use crate::preview;
+use crate::sidecar::DirectoryMeta;
+
+/// Size of a share card, the 1.91:1 ratio chat apps and social networks show previews in
+pub const CARD_WIDTH: u32 = 1200;
+pub const CARD_HEIGHT: u32 = 630;
+
+/// Cards kept in memory; the least recently used one makes room for a new one
+const MAX_CACHED_CARDS: usize = 256;
+
+/// How long browsers and crawlers may reuse a card before revalidating it
+const CARD_MAX_AGE: u64 = 60 * 60;
+
+/// Embedded font of the card text, DejaVu Sans Bold (Bitstream Vera license)
+const FONT_FILE: &str = "fonts/DejaVuSans-Bold.ttf";
+
+/// Distance of the text from the edges of the card, in pixels
+const MARGIN: f32 = 64.0;
+const TITLE_SIZE: f32 = 76.0;
+const TITLE_LINES: usize = 2;
+const SITE_SIZE: f32 = 38.0;
+
+/// Gradient of the web interface's header
+const GRADIENT: (Rgb<u8>, Rgb<u8>) = (Rgb([0x66, 0x7e, 0xea]), Rgb([0x76, 0x4b, 0xa2]));
+
+/// Rendered share cards, keyed by the fingerprint of everything drawn on them
+/// A directory whose title or cover changes gets a new fingerprint, so its stale card
+/// simply ages out of the cache.
+pub struct ShareCardCache {
+    cards: Mutex<HashMap<u64, CachedCard>>,
+}
+
+struct CachedCard {
+    png: Bytes,
+    used: Instant,
+}
+
+impl ShareCardCache {
+    pub fn new() -> Self {
+        Self {
+            cards: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, fingerprint: u64) -> Option<Bytes> {
+        let mut cards = self.cards.lock().unwrap();
+        let card = cards.get_mut(&fingerprint)?;
+        card.used = Instant::now();
+        Some(card.png.clone())
+    }
+
+    fn put(&self, fingerprint: u64, png: Bytes) {
+        let mut cards = self.cards.lock().unwrap();
+        if cards.len() >= MAX_CACHED_CARDS && !cards.contains_key(&fingerprint) {
+            if let Some(oldest) = cards.iter().min_by_key(|(_, card)| card.used).map(|(key, _)| *key) {
+                cards.remove(&oldest);
+            }
+        }
+        cards.insert(
+            fingerprint,
+            CachedCard {
+                png,
+                used: Instant::now(),
+            },
+        );
+    }
+}
+
+/// What a share card shows
+#[derive(Hash)]
+struct Card {
+    title: String,
+    /// Shown under the title, unless the title already is the site title
+    site_name: Option<String>,
+    accent_color: Option<String>,
+    /// Cover image relative to the media directory, and its modification time
+    cover: Option<(String, u64)>,
+}
+
+/// The embedded font, loaded on first use
+pub fn font() -> Option<&'static FontVec> {
+    static FONT: OnceLock<Option<FontVec>> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let data = StaticAssets::get(FONT_FILE)?.data.into_owned();
+        FontVec::try_from_vec(data).ok()
+    })
+    .as_ref()
+}
+
+/// `#rgb` or `#rrggbb` (alpha ignored) as a color
+fn parse_color(color: &str) -> Option<Rgb<u8>> {
+    let hex = color.strip_prefix('#')?;
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        3 | 4 => {
+            let mut rgb = [0; 3];
+            for (value, digit) in rgb.iter_mut().zip(hex.chars()) {
+                *value = channel(&digit.to_string())? * 17;
+            }
+            Some(Rgb(rgb))
+        }
+        6 | 8 => Some(Rgb([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?])),
+        _ => None,
+    }
+}
+
+pub fn mix(from: Rgb<u8>, to: Rgb<u8>, amount: f32) -> Rgb<u8> {
+    Rgb(std::array::from_fn(|i| {
+        (from[i] as f32 + (to[i] as f32 - from[i] as f32) * amount).round() as u8
+    }))
+}
+
+/// Diagonal gradient of the accent color, else of the web interface's header colors
+pub fn gradient(width: u32, height: u32, accent_color: Option<&str>) -> RgbImage {
+    let (start, end) = match accent_color.and_then(parse_color) {
+        Some(accent) => (accent, mix(accent, Rgb([0, 0, 0]), 0.4)),
+        None => GRADIENT,
+    };
+    let span = (width + height) as f32;
+    RgbImage::from_fn(width, height, |x, y| mix(start, end, (x + y) as f32 / span))
+}
+
+/// Darken the lower part of the card so white text stays readable on any photo
+fn darken_bottom(card: &mut RgbImage) {
+    let top = CARD_HEIGHT as f32 * 0.35;
+    for (_, y, pixel) in card.enumerate_pixels_mut() {
+        let amount = ((y as f32 - top) / (CARD_HEIGHT as f32 - top)).clamp(0.0, 1.0) * 0.8;
+        *pixel = mix(*pixel, Rgb([0, 0, 0]), amount);
+    }
+}
+
+fn text_width(font: &FontVec, size: f32, text: &str) -> f32 {
+    let font = font.as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let glyph = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, glyph);
+        }
+        width += font.h_advance(glyph);
+        previous = Some(glyph);
+    }
+    width
+}
+
+/// Shorten `line` until it fits in `max_width` with a trailing ellipsis
+fn ellipsize(font: &FontVec, size: f32, line: &mut String, max_width: f32) {
+    while !line.is_empty() && text_width(font, size, &format!("{}…", line)) > max_width {
+        line.pop();
+    }
+    line.truncate(line.trim_end().len());
+    line.push('…');
+}
+
+/// Break `text` into at most `max_lines` lines no wider than `max_width`
+fn wrap(font: &FontVec, size: f32, text: &str, max_width: f32, max_lines: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for word in text.split_whitespace() {
+        let line = lines.last_mut().unwrap();
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+        if line.is_empty() || text_width(font, size, &candidate) <= max_width {
+            *line = candidate;
+        } else {
+            lines.push(word.to_string());
+        }
+    }
+
+    let overflow = lines.len() > max_lines;
+    lines.truncate(max_lines);
+    let last = lines.len() - 1;
+    for (i, line) in lines.iter_mut().enumerate() {
+        if (overflow && i == last) || text_width(font, size, line) > max_width {
+            ellipsize(font, size, line, max_width);
+        }
+    }
+    lines
+}
+
+/// Draw one line of text with its baseline at `baseline`
+fn draw_text(card: &mut RgbImage, font: &FontVec, size: f32, baseline: f32, text: &str, color: Rgb<u8>, opacity: f32) {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut caret = MARGIN;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(size, point(caret, baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|x, y, coverage| {
+            let (x, y) = (bounds.min.x as i64 + x as i64, bounds.min.y as i64 + y as i64);
+            if (0..CARD_WIDTH as i64).contains(&x) && (0..CARD_HEIGHT as i64).contains(&y) {
+                let pixel = card.get_pixel_mut(x as u32, y as u32);
+                *pixel = mix(*pixel, color, coverage.clamp(0.0, 1.0) * opacity);
+            }
+        });
+    }
+}
+
+/// Render a card as PNG
+fn render(media_dir: &std::path::Path, card: &Card, max_decode_memory: u64) -> Option<Vec<u8>> {
+    let font = font()?;
+    let cover = card.cover.as_ref().and_then(|(cover, _)| match decoding::open(&media_dir.join(cover), max_decode_memory) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            tracing::debug!(cover = %cover, "Failed to decode share card cover: {}", e);
+            None
+        }
+    });
+    let mut image = match cover {
+        Some(cover) => cover.resize_to_fill(CARD_WIDTH, CARD_HEIGHT, FilterType::Triangle).to_rgb8(),
+        None => gradient(CARD_WIDTH, CARD_HEIGHT, card.accent_color.as_deref()),
+    };
+    darken_bottom(&mut image);
+
+    let white = Rgb([255, 255, 255]);
+    let max_width = CARD_WIDTH as f32 - 2.0 * MARGIN;
+    let mut baseline = CARD_HEIGHT as f32 - MARGIN;
+    if let Some(site_name) = &card.site_name {
+        let site_name = wrap(font, SITE_SIZE, site_name, max_width, 1).remove(0);
+        draw_text(&mut image, font, SITE_SIZE, baseline, &site_name, white, 0.8);
+        baseline -= SITE_SIZE * 1.6;
+    }
+    let lines = wrap(font, TITLE_SIZE, &card.title, max_width, TITLE_LINES);
+    for (i, line) in lines.iter().enumerate() {
+        let line_baseline = baseline - (lines.len() - 1 - i) as f32 * TITLE_SIZE * 1.2;
+        draw_text(&mut image, font, TITLE_SIZE, line_baseline, line, white, 1.0);
+    }
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+/// Absolute URL of the share card of a directory
+pub fn card_url(host: &str, path: &str) -> String {
+    if path.is_empty() {
+        format!("https://{}/share-card", host)
+    } else {
+        format!("https://{}/share-card/{}", host, handlers::encode_url_path(path))
+    }
+}
+
+/// Handler for /share-card - the share card of the media directory
+pub async fn share_card_root_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    share_card_handler(State(state), principal, Path(String::new()), headers).await
+}
+
+/// Handler for /share-card/*path - a 1200×630 PNG with a directory's cover, title and the site name
+/// Gallery pages point their `og:image` here, so shared album links get a proper preview.
+/// Cards are rendered once and kept in memory until the directory's title or cover changes.
+pub async fn share_card_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let path = handlers::media_path(&path)?;
+    let canonical = if path.is_empty() {
+        state.media_dir.clone()
+    } else {
+        handlers::validate_media_path(&state.media_dir, &path)?
+    };
+    if !canonical.is_dir() {
+        return Err(AppError::NotFound);
+    }
+
+    let meta = DirectoryMeta::load(&canonical).await;
+    let title = preview::directory_title(&state, &path, &meta);
+    let cover = match preview::directory_cover(&state, principal.as_deref(), &path, &meta).await {
+        Some(cover) => {
+            let mtime = tokio::fs::metadata(state.media_dir.join(&cover))
+                .await
+                .map(|metadata| handlers::modified_secs(&metadata))
+                .unwrap_or(0);
+            Some((cover, mtime))
+        }
+        None => None,
+    };
+    let card = Card {
+        site_name: (title != state.branding.title).then(|| state.branding.title.clone()),
+        title,
+        accent_color: state.branding.accent_color.clone(),
+        cover,
+    };
+
+    let mut hasher = DefaultHasher::new();
+    card.hash(&mut hasher);
+    let fingerprint = hasher.finish();
+    let etag = format!("\"{:016x}\"", fingerprint);
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    if not_modified {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, &etag)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let png = match state.share_cards.get(fingerprint) {
+        Some(png) => png,
+        None => {
+            let media_dir = state.media_dir.clone();
+            let max_decode_memory = state.decode_limits.max_memory;
+            let png = state.workers.run(move || render(&media_dir, &card, max_decode_memory))
+                .await
+                .map_err(|_| AppError::InternalError)?
+                .map(Bytes::from)
+                .ok_or(AppError::InternalError)?;
+            state.share_cards.put(fingerprint, png.clone());
+            png
+        }
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CONTENT_LENGTH, png.len())
+        .header(header::CACHE_CONTROL, format!("private, max-age={}", CARD_MAX_AGE))
+        .header(header::ETAG, etag)
+        .body(Body::from(png))
+        .unwrap())
+}
@@ -0,0 +1,176 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState, ListingSort, SortOrder};
+
+/// File in a directory describing how the directory is shown
+pub const SIDECAR_FILE: &str = ".doggygallery.toml";
+
+/// Android-style marker file; a directory containing one is left out entirely, with its subdirectories
+pub const NOMEDIA_FILE: &str = ".nomedia";
+
+/// Sidecars larger than this are ignored
+const MAX_SIDECAR_SIZE: u64 = 64 * 1024;
+
+/// Whether a directory shows up in listings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    #[default]
+    Visible,
+    /// Left out of its parent's listing, search, random picks and slideshows, but still
+    /// reachable by its URL
+    Hidden,
+}
+
+/// Settings read from a directory's `.doggygallery.toml`; every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectoryMeta {
+    /// Shown instead of the directory name
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Default sort of the listing; `sort` and `order` in the URL still win
+    pub sort: Option<ListingSort>,
+    pub order: Option<SortOrder>,
+    /// Image in the directory to preview it with, relative to the directory
+    pub cover: Option<String>,
+    pub visibility: Visibility,
+    /// The directory contains a `.nomedia` file
+    #[serde(skip)]
+    pub nomedia: bool,
+}
+
+impl DirectoryMeta {
+    /// Parse a sidecar, logging and ignoring one that is invalid
+    fn parse(dir: &std::path::Path, text: &str) -> Self {
+        match toml::from_str::<DirectoryMeta>(text) {
+            Ok(mut meta) => {
+                // Only files inside the directory itself can be covers
+                meta.cover = meta
+                    .cover
+                    .filter(|cover| !cover.is_empty() && !cover.contains('/') && !cover.contains('\\') && !cover.starts_with('.'));
+                meta
+            }
+            Err(e) => {
+                tracing::warn!("Ignoring invalid {} in {:?}: {}", SIDECAR_FILE, dir, e);
+                DirectoryMeta::default()
+            }
+        }
+    }
+
+    /// The sidecar of a directory, or defaults if it has none
+    pub async fn load(dir: &std::path::Path) -> Self {
+        let path = dir.join(SIDECAR_FILE);
+        let mut meta = match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() && metadata.len() <= MAX_SIDECAR_SIZE => {
+                match tokio::fs::read_to_string(&path).await {
+                    Ok(text) => Self::parse(dir, &text),
+                    Err(_) => DirectoryMeta::default(),
+                }
+            }
+            _ => DirectoryMeta::default(),
+        };
+        meta.nomedia = tokio::fs::try_exists(dir.join(NOMEDIA_FILE)).await.unwrap_or(false);
+        meta
+    }
+
+    /// `load` for the blocking index walk
+    pub fn load_blocking(dir: &std::path::Path) -> Self {
+        let path = dir.join(SIDECAR_FILE);
+        let mut meta = match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() && metadata.len() <= MAX_SIDECAR_SIZE => {
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => Self::parse(dir, &text),
+                    Err(_) => DirectoryMeta::default(),
+                }
+            }
+            _ => DirectoryMeta::default(),
+        };
+        meta.nomedia = dir.join(NOMEDIA_FILE).exists();
+        meta
+    }
+
+    /// Whether the directory is left out of listings, search and random picks, by its
+    /// sidecar or a `.nomedia` file
+    pub fn is_hidden(&self) -> bool {
+        self.visibility == Visibility::Hidden || self.nomedia
+    }
+}
+
+/// Whether a directory inside the media directory, or one of its parents, has a `.nomedia` file
+pub async fn in_nomedia(media_dir: &std::path::Path, dir: &std::path::Path) -> bool {
+    for ancestor in dir.ancestors() {
+        if !ancestor.starts_with(media_dir) {
+            break;
+        }
+        if tokio::fs::try_exists(ancestor.join(NOMEDIA_FILE)).await.unwrap_or(false) {
+            return true;
+        }
+    }
+    false
+}
+
+/// A directory's sidecar settings
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DirectoryInfo {
+    /// Path relative to the media directory
+    pub path: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub sort: Option<ListingSort>,
+    pub order: Option<SortOrder>,
+    /// Cover image relative to the media directory, if it exists
+    pub cover: Option<String>,
+    pub visibility: Visibility,
+}
+
+/// Title, description, default sort, cover and visibility of a directory
+/// These come from the directory's `.doggygallery.toml`; a directory without one has
+/// every field empty. Directories in a `.nomedia` tree are not found.
+#[utoipa::path(
+    get,
+    path = "/api/directory/{path}",
+    params(("path" = String, Path, description = "Directory relative to the media directory")),
+    responses(
+        (status = 200, description = "Directory settings", body = DirectoryInfo),
+        (status = 403, description = "Directory not accessible"),
+        (status = 404, description = "Directory not found")
+    ),
+    tag = "media"
+)]
+pub async fn directory_info_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<DirectoryInfo>, AppError> {
+    let path = handlers::media_path(&path)?;
+    if !state.acl.allows(principal.as_deref(), &path) {
+        return Err(AppError::Forbidden);
+    }
+    let dir = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !dir.is_dir() || in_nomedia(&state.media_dir, &dir).await {
+        return Err(AppError::NotFound);
+    }
+
+    let meta = DirectoryMeta::load(&dir).await;
+    let cover = meta
+        .cover
+        .filter(|cover| dir.join(cover).is_file())
+        .map(|cover| format!("{}/{}", path, cover));
+    Ok(Json(DirectoryInfo {
+        path,
+        title: meta.title,
+        description: meta.description,
+        sort: meta.sort,
+        order: meta.order,
+        cover,
+        visibility: meta.visibility,
+    }))
+}
@@ -0,0 +1,155 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::{Html, Json},
+    Extension,
+};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+use crate::i18n::Language;
+use crate::templates::SlideshowTemplate;
+
+/// Seconds each image is shown unless the client asks otherwise
+const DEFAULT_INTERVAL: u64 = 5;
+const MAX_INTERVAL: u64 = 3600;
+
+/// Images listed in one slideshow; larger subtrees are cut off
+const MAX_SLIDES: usize = 10_000;
+
+/// Which images to show, in what order
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct SlideshowQuery {
+    /// Directory relative to the media directory; its subdirectories are included (default: everything)
+    #[serde(default)]
+    pub path: String,
+    /// Shuffle the images instead of showing them in path order (default false)
+    #[serde(default)]
+    pub shuffle: bool,
+    /// Seconds per image (default 5, max 3600)
+    pub interval: Option<u64>,
+}
+
+/// One image of a slideshow
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Slide {
+    pub path: String,
+    pub name: String,
+    /// URL of the full image
+    pub url: String,
+}
+
+/// The images of a directory tree in showing order
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SlideshowResponse {
+    pub path: String,
+    pub shuffled: bool,
+    /// Seconds per image
+    pub interval: u64,
+    /// Images in the directory tree, including any cut off
+    pub total: usize,
+    /// The first 10000 images
+    pub slides: Vec<Slide>,
+}
+
+/// The directory a slideshow covers, without leading or trailing slashes
+fn slideshow_dir(state: &AppState, principal: Option<&Principal>, path: &str) -> Result<String, AppError> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Ok(String::new());
+    }
+    if path.split('/').any(|part| matches!(part, "" | "." | "..")) {
+        return Err(AppError::InvalidPath);
+    }
+    if !state.acl.allows(principal, path) {
+        return Err(AppError::Forbidden);
+    }
+    let dir = handlers::validate_media_path(&state.media_dir, path)?;
+    if !dir.is_dir() {
+        return Err(AppError::NotFound);
+    }
+    Ok(path.to_string())
+}
+
+async fn slideshow(
+    state: &AppState,
+    principal: Option<&Principal>,
+    query: &SlideshowQuery,
+) -> Result<SlideshowResponse, AppError> {
+    let path = slideshow_dir(state, principal, &query.path)?;
+
+    let mut images: Vec<(String, String)> = {
+        let cache = state.media_cache.read().await;
+        cache
+            .items_in(&path, true)
+            .filter(|item| item.file_type == "image" && state.acl.allows(principal, &item.path))
+            .map(|item| (item.path.clone(), item.name.clone()))
+            .collect()
+    };
+    if query.shuffle {
+        images.shuffle(&mut rand::thread_rng());
+    } else {
+        images.sort_by(|(a, _), (b, _)| state.name_order.cmp(a, b));
+    }
+
+    let total = images.len();
+    let slides = images
+        .into_iter()
+        .take(MAX_SLIDES)
+        .map(|(path, name)| Slide {
+            url: format!("/media/{}", handlers::encode_url_path(&path)),
+            path,
+            name,
+        })
+        .collect();
+    Ok(SlideshowResponse {
+        path,
+        shuffled: query.shuffle,
+        interval: query.interval.unwrap_or(DEFAULT_INTERVAL).clamp(1, MAX_INTERVAL),
+        total,
+        slides,
+    })
+}
+
+/// Images of a directory tree for a slideshow
+/// Images come from the media cache, in natural path order or shuffled. Each request
+/// shuffles anew.
+#[utoipa::path(
+    get,
+    path = "/api/slideshow",
+    params(SlideshowQuery),
+    responses(
+        (status = 200, description = "Slideshow", body = SlideshowResponse),
+        (status = 403, description = "Directory not accessible"),
+        (status = 404, description = "Directory not found")
+    ),
+    tag = "media"
+)]
+pub async fn slideshow_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<SlideshowQuery>,
+) -> Result<Json<SlideshowResponse>, AppError> {
+    Ok(Json(slideshow(&state, principal.as_deref(), &query).await?))
+}
+
+/// Handler for /slideshow - a fullscreen slideshow of a directory tree
+/// The page loads its images from /api/slideshow with the same query.
+pub async fn slideshow_page_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<SlideshowQuery>,
+) -> Result<Html<String>, AppError> {
+    let path = slideshow_dir(&state, principal.as_deref(), &query.path)?;
+    let template = SlideshowTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
+        path,
+        shuffle: query.shuffle,
+        interval: query.interval.unwrap_or(DEFAULT_INTERVAL).clamp(1, MAX_INTERVAL),
+    };
+    Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
+}
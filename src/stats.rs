@@ -0,0 +1,186 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use http_body::{Frame, SizeHint};
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Routes whose responses count as media streams while their body is being sent
+const STREAM_ROUTES: &[&str] = &["/media/", "/media-archive/"];
+
+/// Live server counters shown by the admin statistics API
+/// Kept independently of Prometheus so they work without `--metrics`.
+pub struct ServerStats {
+    started: Instant,
+    started_at: DateTime<Utc>,
+    requests: AtomicU64,
+    bytes_served: AtomicU64,
+    active_streams: AtomicU64,
+}
+
+impl ServerStats {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            started: Instant::now(),
+            started_at: Utc::now(),
+            requests: AtomicU64::new(0),
+            bytes_served: AtomicU64::new(0),
+            active_streams: AtomicU64::new(0),
+        })
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub fn total_requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Response body bytes served (responses with a known length)
+    pub fn bytes_served(&self) -> u64 {
+        self.bytes_served.load(Ordering::Relaxed)
+    }
+
+    /// Media responses whose body is still being sent
+    pub fn active_streams(&self) -> u64 {
+        self.active_streams.load(Ordering::Relaxed)
+    }
+}
+
+/// Hit, miss and eviction counts of one cache
+/// Shown by the admin statistics API and exported as Prometheus counters labelled with
+/// the cache name, for sizing caches and judging whether they pay off.
+#[derive(Debug)]
+pub struct CacheCounters {
+    name: &'static str,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl CacheCounters {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn hit(&self, count: u64) {
+        self.hits.fetch_add(count, Ordering::Relaxed);
+        metrics::counter!("doggygallery_cache_hits_total", "cache" => self.name).increment(count);
+    }
+
+    pub fn miss(&self, count: u64) {
+        self.misses.fetch_add(count, Ordering::Relaxed);
+        metrics::counter!("doggygallery_cache_misses_total", "cache" => self.name).increment(count);
+    }
+
+    /// Entries dropped because they went stale or their file disappeared
+    pub fn evict(&self, count: u64) {
+        self.evictions.fetch_add(count, Ordering::Relaxed);
+        metrics::counter!("doggygallery_cache_evictions_total", "cache" => self.name).increment(count);
+    }
+
+    pub fn snapshot(&self) -> CacheCounts {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        CacheCounts {
+            cache: self.name.to_string(),
+            hits,
+            misses,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            hit_ratio: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+        }
+    }
+}
+
+/// Hit, miss and eviction counts of one cache since startup
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct CacheCounts {
+    /// Cache name: media, index, album_art or thumbnails
+    pub cache: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Share of lookups that hit, absent before the first lookup
+    pub hit_ratio: Option<f64>,
+}
+
+/// Middleware counting requests and bytes served, and tracking open media streams
+pub async fn track_stats(
+    State(stats): State<Arc<ServerStats>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_stream = STREAM_ROUTES
+        .iter()
+        .any(|prefix| request.uri().path().starts_with(prefix));
+
+    let response = next.run(request).await;
+
+    stats.requests.fetch_add(1, Ordering::Relaxed);
+    if let Some(bytes) = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+    {
+        stats.bytes_served.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    if !is_stream || !response.status().is_success() {
+        return response;
+    }
+
+    stats.active_streams.fetch_add(1, Ordering::Relaxed);
+    response.map(|body| Body::new(StreamBody { inner: body, stats }))
+}
+
+/// Response body that counts as an active stream until it is finished or dropped
+struct StreamBody {
+    inner: Body,
+    stats: Arc<ServerStats>,
+}
+
+impl Drop for StreamBody {
+    fn drop(&mut self) {
+        self.stats.active_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl http_body::Body for StreamBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
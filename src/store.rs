@@ -0,0 +1,84 @@
+use anyhow::Result;
+use axum::body::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{BoxStream, StreamExt};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::s3::S3Store;
+
+/// How long a signed download URL handed to a client stays valid
+pub const SIGNED_URL_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// Chunks of a file read from a store
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// A file in a media store
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    /// Path relative to the root of the store, `/`-separated
+    pub path: String,
+    pub size: u64,
+    /// Last modification time (Unix seconds)
+    pub modified: u64,
+}
+
+/// A remote source of media files standing in for the media directory, such as a bucket
+/// Paths are relative to the root of the store and checked with `clean_path` first.
+pub trait MediaStore: Send + Sync {
+    /// Every file in the store
+    fn list(&self) -> BoxFuture<'_, io::Result<Vec<StoredFile>>>;
+
+    /// Size and modification time of a file; an error of kind `NotFound` if there is none
+    fn stat<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<StoredFile>>;
+
+    /// `len` bytes of a file, starting at byte `start`
+    fn read_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> BoxFuture<'a, io::Result<ByteStream>>;
+
+    /// A URL clients can download a file from directly, valid for `expires` and served as
+    /// `mime`, or `None` to stream the file through this server
+    fn signed_url(&self, path: &str, mime: &str, expires: Duration) -> Option<String>;
+}
+
+/// Open the store named by `--backend`, if any
+pub fn open(config: &Config) -> Result<Option<Arc<dyn MediaStore>>> {
+    let Some(backend) = &config.backend else {
+        return Ok(None);
+    };
+    let Some(location) = backend.strip_prefix("s3://") else {
+        anyhow::bail!("Unsupported --backend {:?}; expected s3://bucket/prefix", backend);
+    };
+    let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+    if bucket.is_empty() {
+        anyhow::bail!("--backend {:?} names no bucket", backend);
+    }
+    let prefix = prefix.trim_matches('/');
+    if !prefix.is_empty() && clean_path(prefix).is_none() {
+        anyhow::bail!("--backend {:?} has an invalid prefix", backend);
+    }
+    Ok(Some(Arc::new(S3Store::new(config, bucket, prefix)?)))
+}
+
+/// Normalize a path within a store, or `None` if it has empty, `.`, `..` or hidden segments
+/// Stores have no symlinks to resolve, so a path that passes can't leave the store.
+pub fn clean_path(path: &str) -> Option<String> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Some(String::new());
+    }
+    path.split('/')
+        .all(|segment| !segment.is_empty() && !segment.starts_with('.'))
+        .then(|| path.to_string())
+}
+
+/// Read `len` bytes of a file from `start` into memory
+pub async fn read(store: &dyn MediaStore, path: &str, start: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut stream = store.read_range(path, start, len).await?;
+    let mut data = Vec::with_capacity(len.min(16 * 1024 * 1024) as usize);
+    while let Some(chunk) = stream.next().await {
+        data.extend_from_slice(&chunk?);
+    }
+    Ok(data)
+}
@@ -0,0 +1,328 @@
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+
+/// Subtitle file extensions looked for next to videos
+const SUBTITLE_EXTENSIONS: &[&str] = &["vtt", "srt", "ass", "ssa"];
+
+/// Largest subtitle file served; real subtitles are far smaller
+const MAX_SUBTITLE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// A subtitle file stored next to a video
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubtitleTrack {
+    /// Path of the subtitle file relative to the media directory
+    pub path: String,
+    /// WebVTT URL of the subtitles, for a `<track>` element
+    pub url: String,
+    /// Name to show in a player's subtitle menu
+    pub label: String,
+    /// Language code from the file name, such as `en` in `movie.en.srt`
+    pub language: Option<String>,
+    /// Format of the file on disk: `vtt`, `srt`, `ass` or `ssa`
+    pub format: String,
+}
+
+fn subtitle_extension(name: &str) -> Option<String> {
+    let (_, extension) = name.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    SUBTITLE_EXTENSIONS
+        .contains(&extension.as_str())
+        .then_some(extension)
+}
+
+/// Describe a subtitle file if it belongs to the video with file stem `stem`
+/// `movie.srt`, `movie.en.srt` and `movie.en.forced.srt` all belong to `movie.mkv`.
+fn sidecar(stem: &str, dir: &str, name: &str) -> Option<SubtitleTrack> {
+    let format = subtitle_extension(name)?;
+    let qualifiers = name[..name.len() - format.len() - 1].strip_prefix(stem)?;
+    if !qualifiers.is_empty() && !qualifiers.starts_with('.') {
+        return None;
+    }
+    let qualifiers: Vec<&str> = qualifiers.split('.').filter(|q| !q.is_empty()).collect();
+    let language = qualifiers
+        .iter()
+        .find(|q| (2..=3).contains(&q.len()) && q.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|q| q.to_ascii_lowercase());
+    let label = match (&language, qualifiers.len()) {
+        (_, 0) => format.to_uppercase(),
+        (Some(language), 1) => language.to_uppercase(),
+        _ => qualifiers.join(" "),
+    };
+    let path = if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    };
+    Some(SubtitleTrack {
+        url: handlers::encode_url_path(&format!("/subtitles/{}", path)),
+        path,
+        label,
+        language,
+        format,
+    })
+}
+
+/// List the subtitle files next to a video
+/// Subtitles share the video's file name up to its extension, optionally followed by a
+/// language and other qualifiers, e.g. `movie.en.srt` or `movie.de.forced.ass` for `movie.mkv`.
+#[utoipa::path(
+    get,
+    path = "/api/subtitles/{path}",
+    params(("path" = String, Path, description = "Video path relative to the media directory")),
+    responses(
+        (status = 200, description = "Subtitle tracks, sorted by file name", body = Vec<SubtitleTrack>),
+        (status = 403, description = "Path not accessible"),
+        (status = 404, description = "Video not found")
+    ),
+    tag = "media"
+)]
+pub async fn subtitles_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<Vec<SubtitleTrack>>, AppError> {
+    let path = handlers::media_path(&path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !canonical.is_file() || handlers::media_type(&path) != Some("video") {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(principal.as_deref(), &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let parent = canonical.parent().ok_or(AppError::NotFound)?;
+    let mut tracks = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(parent)
+        .await
+        .map_err(|_| AppError::InternalError)?;
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(track) = sidecar(stem, dir, &file_name) else {
+            continue;
+        };
+        if state.acl.allows(principal.as_deref(), &track.path) {
+            tracks.push(track);
+        }
+    }
+    tracks.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Json(tracks))
+}
+
+/// Handler for /subtitles/<path> - a subtitle file as WebVTT
+/// SRT and ASS/SSA files are converted on the fly, so browsers can use them in `<track>`.
+pub async fn serve_subtitle_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Response, AppError> {
+    let path = handlers::media_path(&path)?;
+    let format = subtitle_extension(&path).ok_or(AppError::NotFound)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !state.acl.allows(principal.as_deref(), &path) {
+        return Err(AppError::Forbidden);
+    }
+    let metadata = tokio::fs::metadata(&canonical)
+        .await
+        .map_err(|_| AppError::NotFound)?;
+    if !metadata.is_file() {
+        return Err(AppError::NotFound);
+    }
+    if metadata.len() > MAX_SUBTITLE_BYTES {
+        return Err(AppError::InvalidInput("Subtitle file is too large"));
+    }
+
+    let data = tokio::fs::read(&canonical)
+        .await
+        .map_err(|_| AppError::InternalError)?;
+    let text = decode_text(&data);
+    let vtt = match format.as_str() {
+        "vtt" => text,
+        "srt" => srt_to_vtt(&text),
+        _ => ass_to_vtt(&text),
+    };
+    Ok(([(header::CONTENT_TYPE, "text/vtt; charset=utf-8")], vtt).into_response())
+}
+
+/// Subtitle text as UTF-8, without a byte order mark
+/// Files that aren't valid UTF-8 are read as Latin-1, the usual encoding of older subtitles.
+fn decode_text(data: &[u8]) -> String {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => data.iter().map(|&byte| byte as char).collect(),
+    };
+    text.trim_start_matches('\u{feff}')
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+}
+
+/// Convert SubRip to WebVTT
+/// The formats differ in the header and the decimal separator of timestamps; SRT cue
+/// numbers become cue identifiers.
+fn srt_to_vtt(srt: &str) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for line in srt.lines() {
+        if line.contains("-->") {
+            vtt.push_str(&line.replace(',', "."));
+        } else {
+            vtt.push_str(line);
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+/// Convert an ASS/SSA timestamp (`H:MM:SS.cc`) to a WebVTT one (`HH:MM:SS.mmm`)
+fn ass_timestamp(time: &str) -> Option<String> {
+    let (hms, centiseconds) = time.trim().split_once('.')?;
+    let mut parts = hms.split(':').map(|part| part.parse::<u32>().ok());
+    let (hours, minutes, seconds) = (parts.next()??, parts.next()??, parts.next()??);
+    let centiseconds: u32 = centiseconds.parse().ok()?;
+    Some(format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        hours,
+        minutes,
+        seconds,
+        centiseconds * 10
+    ))
+}
+
+/// Dialogue text of an ASS event as WebVTT cue text
+/// Override blocks such as `{\i1}` are dropped; `\N` line breaks are kept.
+fn ass_text(text: &str) -> String {
+    let mut plain = String::new();
+    let mut in_override = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if in_override => {}
+            '\\' if matches!(chars.peek(), Some('N' | 'n')) => {
+                chars.next();
+                plain.push('\n');
+            }
+            '\\' if chars.peek() == Some(&'h') => {
+                chars.next();
+                plain.push(' ');
+            }
+            '&' => plain.push_str("&amp;"),
+            '<' => plain.push_str("&lt;"),
+            '>' => plain.push_str("&gt;"),
+            c => plain.push(c),
+        }
+    }
+    plain.trim().to_string()
+}
+
+/// Convert the dialogue of an Advanced SubStation Alpha (ASS/SSA) file to WebVTT
+/// Styling and positioning are dropped. Cues are sorted by start time, as WebVTT expects.
+fn ass_to_vtt(ass: &str) -> String {
+    let mut in_events = false;
+    let mut format: Vec<String> = Vec::new();
+    let mut cues = Vec::new();
+    for line in ass.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(fields) = line.strip_prefix("Format:") {
+            format = fields.split(',').map(|f| f.trim().to_ascii_lowercase()).collect();
+        } else if let Some(fields) = line.strip_prefix("Dialogue:") {
+            // Text is the last field and may itself contain commas
+            let values: Vec<&str> = fields.splitn(format.len().max(1), ',').collect();
+            let field = |name: &str| {
+                format
+                    .iter()
+                    .position(|f| f == name)
+                    .and_then(|i| values.get(i).copied())
+            };
+            let (Some(start), Some(end), Some(text)) = (
+                field("start").and_then(ass_timestamp),
+                field("end").and_then(ass_timestamp),
+                field("text").map(ass_text),
+            ) else {
+                continue;
+            };
+            if !text.is_empty() {
+                cues.push((start, end, text));
+            }
+        }
+    }
+    // Timestamps are zero-padded, so they sort as strings
+    cues.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut vtt = String::from("WEBVTT\n");
+    for (start, end, text) in cues {
+        vtt.push_str(&format!("\n{} --> {}\n{}\n", start, end, text));
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_timestamps_use_a_decimal_point() {
+        let srt = "1\n00:00:01,500 --> 00:00:03,250\nHello, world\n\n2\n00:00:04,000 --> 00:00:05,000\nBye\n";
+        assert_eq!(
+            srt_to_vtt(srt),
+            "WEBVTT\n\n1\n00:00:01.500 --> 00:00:03.250\nHello, world\n\n2\n00:00:04.000 --> 00:00:05.000\nBye\n"
+        );
+    }
+
+    #[test]
+    fn ass_dialogue_becomes_sorted_cues() {
+        let ass = "[Script Info]\nTitle: Test\n\n[Events]\n\
+            Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+            Dialogue: 0,0:00:05.00,0:00:06.50,Default,,0,0,0,,Second, with a comma\n\
+            Dialogue: 0,0:00:01.20,0:00:02.00,Default,,0,0,0,,{\\i1}First{\\i0}\\Nline <two>\n\
+            Dialogue: 0,0:00:07.00,0:00:08.00,Default,,0,0,0,,{\\pos(10,10)}\n";
+        assert_eq!(
+            ass_to_vtt(ass),
+            "WEBVTT\n\n00:00:01.200 --> 00:00:02.000\nFirst\nline &lt;two&gt;\n\n\
+             00:00:05.000 --> 00:00:06.500\nSecond, with a comma\n"
+        );
+    }
+
+    #[test]
+    fn ass_timestamps_are_zero_padded_milliseconds() {
+        assert_eq!(ass_timestamp("1:02:03.45").as_deref(), Some("01:02:03.450"));
+        assert_eq!(ass_timestamp("0:00:00.00").as_deref(), Some("00:00:00.000"));
+        assert_eq!(ass_timestamp("garbage"), None);
+    }
+
+    #[test]
+    fn text_is_utf8_without_bom_or_carriage_returns() {
+        assert_eq!(decode_text("\u{feff}a\r\nb\rc".as_bytes()), "a\nb\nc");
+        assert_eq!(decode_text(b"caf\xe9"), "café");
+    }
+
+    #[test]
+    fn sidecars_belong_to_their_video() {
+        let track = sidecar("movie", "films", "movie.en.srt").unwrap();
+        assert_eq!(track.path, "films/movie.en.srt");
+        assert_eq!(track.language.as_deref(), Some("en"));
+        assert_eq!(track.label, "EN");
+        assert_eq!(track.format, "srt");
+        assert_eq!(sidecar("movie", "", "movie.vtt").unwrap().label, "VTT");
+        assert_eq!(sidecar("movie", "", "movie.de.forced.ass").unwrap().label, "de forced");
+        assert!(sidecar("movie", "", "movie2.srt").is_none());
+        assert!(sidecar("movie", "", "movie.txt").is_none());
+    }
+}
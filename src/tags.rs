@@ -0,0 +1,375 @@
+use axum::{
+    extract::State,
+    response::Json,
+    Extension,
+};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState, FilterQuery, FilterResult};
+use crate::index::MediaIndex;
+use crate::models::DirectoryEntry;
+
+/// Longest tag accepted, in characters
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Most tags that can be added or removed in one request
+const MAX_TAGS_PER_REQUEST: usize = 50;
+
+/// Normalize a tag to lowercase with single spaces, or `None` if it isn't a valid tag
+/// Commas are rejected because the `tag` filter uses them as a separator.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (!tag.is_empty() && tag.chars().count() <= MAX_TAG_LENGTH && !tag.contains(','))
+        .then_some(tag)
+}
+
+/// Whether a path, or one of the directories containing it, is in a set of tagged paths
+pub fn is_tagged(tagged: &HashSet<String>, path: &str) -> bool {
+    let mut current = path;
+    loop {
+        if tagged.contains(current) {
+            return true;
+        }
+        match current.rfind('/') {
+            Some(slash) => current = &current[..slash],
+            None => return false,
+        }
+    }
+}
+
+impl MediaIndex {
+    /// Tag a file or directory; tags it already has are left alone
+    pub async fn add_tags(&self, path: String, tags: Vec<String>, added_by: String) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR IGNORE INTO tags (path, tag, added_by, added_at) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for tag in &tags {
+                    insert.execute(params![path, tag, added_by, now])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Remove tags from a file or directory
+    pub async fn remove_tags(&self, path: String, tags: Vec<String>) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut delete = tx.prepare("DELETE FROM tags WHERE path = ?1 AND tag = ?2")?;
+                for tag in &tags {
+                    delete.execute(params![path, tag])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Add and remove tags on several paths at once, in a single transaction
+    /// Each change is a path with the tags to add and the tags to remove.
+    pub async fn change_tags(
+        &self,
+        changes: Vec<(String, Vec<String>, Vec<String>)>,
+        added_by: String,
+    ) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR IGNORE INTO tags (path, tag, added_by, added_at) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                let mut delete = tx.prepare("DELETE FROM tags WHERE path = ?1 AND tag = ?2")?;
+                for (path, add, remove) in &changes {
+                    for tag in add {
+                        insert.execute(params![path, tag, added_by, now])?;
+                    }
+                    for tag in remove {
+                        delete.execute(params![path, tag])?;
+                    }
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Tags set directly on each of the given paths, sorted; untagged paths are omitted
+    pub async fn tags_of(&self, paths: Vec<String>) -> anyhow::Result<HashMap<String, Vec<String>>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT tag FROM tags WHERE path = ?1 ORDER BY tag")?;
+            let mut tags = HashMap::new();
+            for path in paths {
+                let path_tags = stmt
+                    .query_map([&path], |row| row.get(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()?;
+                if !path_tags.is_empty() {
+                    tags.insert(path, path_tags);
+                }
+            }
+            Ok(tags)
+        })
+        .await
+    }
+
+    /// Every tagged path, grouped by tag
+    pub async fn tagged_paths(&self) -> anyhow::Result<HashMap<String, HashSet<String>>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT tag, path FROM tags")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            let mut tagged: HashMap<String, HashSet<String>> = HashMap::new();
+            for row in rows {
+                let (tag, path) = row?;
+                tagged.entry(tag).or_default().insert(path);
+            }
+            Ok(tagged)
+        })
+        .await
+    }
+}
+
+/// Keep only the files carrying every tag in the query's `tag` filter
+/// A tag on a directory applies to everything inside it. Without an index database
+/// nothing is tagged, so a tag filter matches no files.
+pub async fn filter_by_tags(
+    state: &AppState,
+    query: &FilterQuery,
+    mut items: Vec<FilterResult>,
+) -> Result<Vec<FilterResult>, AppError> {
+    let wanted = query.tags();
+    if wanted.is_empty() {
+        return Ok(items);
+    }
+    let Some(index) = &state.index else {
+        return Ok(Vec::new());
+    };
+
+    let mut tagged = index.tagged_paths().await.map_err(|e| {
+        tracing::error!("Failed to read tags: {:?}", e);
+        AppError::InternalError
+    })?;
+    let sets: Vec<HashSet<String>> = wanted
+        .iter()
+        .map(|tag| tagged.remove(tag).unwrap_or_default())
+        .collect();
+    items.retain(|item| sets.iter().all(|set| is_tagged(set, &item.path)));
+    Ok(items)
+}
+
+/// Fill in the tags of directory listing entries, if there is an index database
+pub async fn attach_tags(state: &AppState, entries: &mut [DirectoryEntry]) {
+    let Some(index) = &state.index else {
+        return;
+    };
+    let paths = entries.iter().map(|entry| entry.path.clone()).collect();
+    match index.tags_of(paths).await {
+        Ok(mut tags) => {
+            for entry in entries {
+                entry.tags = tags.remove(&entry.path).unwrap_or_default();
+            }
+        }
+        Err(e) => tracing::warn!("Failed to read tags for listing: {:?}", e),
+    }
+}
+
+/// Tags to add to or remove from a file or directory
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TagRequest {
+    /// File or directory path relative to the media directory
+    pub path: String,
+    /// Tags to add or remove (case insensitive, at most 64 characters, no commas)
+    pub tags: Vec<String>,
+}
+
+/// Tags of a file or directory after a change
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TagsResponse {
+    /// File or directory path relative to the media directory
+    pub path: String,
+    /// Tags set directly on the path, sorted
+    pub tags: Vec<String>,
+}
+
+/// A tag and how many files and directories carry it
+#[derive(Debug, Serialize, ToSchema, async_graphql::SimpleObject)]
+pub struct TagCount {
+    /// Tag name
+    pub tag: String,
+    /// Number of tagged files and directories
+    pub paths: usize,
+}
+
+/// List all tags with how many paths carry each
+/// Paths hidden by an ACL are not counted.
+#[utoipa::path(
+    get,
+    path = "/api/tags",
+    responses(
+        (status = 200, description = "Tags, sorted by name", body = Vec<TagCount>),
+        (status = 404, description = "No index database")
+    ),
+    tag = "media"
+)]
+pub async fn list_tags_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Vec<TagCount>>, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let tagged = index.tagged_paths().await.map_err(|e| {
+        tracing::error!("Failed to read tags: {:?}", e);
+        AppError::InternalError
+    })?;
+
+    let counts: BTreeMap<String, usize> = tagged
+        .into_iter()
+        .map(|(tag, paths)| {
+            let visible = paths
+                .iter()
+                .filter(|path| state.acl.allows(principal.as_deref(), path))
+                .count();
+            (tag, visible)
+        })
+        .filter(|(_, paths)| *paths > 0)
+        .collect();
+
+    Ok(Json(
+        counts
+            .into_iter()
+            .map(|(tag, paths)| TagCount { tag, paths })
+            .collect(),
+    ))
+}
+
+/// Tag a file or directory
+/// Tags are shared by all users. A tag on a directory applies to everything inside it.
+#[utoipa::path(
+    post,
+    path = "/api/tags",
+    request_body = TagRequest,
+    responses(
+        (status = 200, description = "Tags added", body = TagsResponse),
+        (status = 400, description = "Invalid path or tag"),
+        (status = 403, description = "Not signed in, or path not accessible"),
+        (status = 404, description = "Path not found, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn add_tags_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<TagRequest>,
+) -> Result<Json<TagsResponse>, AppError> {
+    let (index, path, tags) = validate_request(&state, principal.as_deref(), request)?;
+    let username = principal.map(|p| p.username.clone()).unwrap_or_default();
+
+    index
+        .add_tags(path.clone(), tags.clone(), username.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to add tags: {:?}", e);
+            AppError::InternalError
+        })?;
+    tracing::info!(username = %username, path = %path, ?tags, "Tags added");
+
+    tags_response(index, path).await
+}
+
+/// Remove tags from a file or directory
+#[utoipa::path(
+    delete,
+    path = "/api/tags",
+    request_body = TagRequest,
+    responses(
+        (status = 200, description = "Tags removed", body = TagsResponse),
+        (status = 400, description = "Invalid path or tag"),
+        (status = 403, description = "Not signed in, or path not accessible"),
+        (status = 404, description = "Path not found, or no index database")
+    ),
+    tag = "media"
+)]
+pub async fn remove_tags_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Json(request): Json<TagRequest>,
+) -> Result<Json<TagsResponse>, AppError> {
+    let (index, path, tags) = validate_request(&state, principal.as_deref(), request)?;
+
+    index
+        .remove_tags(path.clone(), tags.clone())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to remove tags: {:?}", e);
+            AppError::InternalError
+        })?;
+    if let Some(principal) = &principal {
+        tracing::info!(username = %principal.username, path = %path, ?tags, "Tags removed");
+    }
+
+    tags_response(index, path).await
+}
+
+/// Check a tag request: signed in, path exists and is accessible, tags valid
+fn validate_request<'a>(
+    state: &'a AppState,
+    principal: Option<&Principal>,
+    request: TagRequest,
+) -> Result<(&'a MediaIndex, String, Vec<String>), AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    if principal.is_none() {
+        return Err(AppError::Forbidden);
+    }
+
+    let path = handlers::media_path(&request.path)?;
+    if path.is_empty() {
+        return Err(AppError::InvalidPath);
+    }
+    handlers::validate_media_path(&state.media_dir, &path)?;
+    if !state.acl.allows(principal, &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    if request.tags.is_empty() {
+        return Err(AppError::InvalidInput("Between 1 and 50 tags are required"));
+    }
+    let tags = normalize_tags(&request.tags)?;
+
+    Ok((index, path, tags))
+}
+
+/// Normalize the tags of a request, sorted and without duplicates
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, AppError> {
+    if tags.len() > MAX_TAGS_PER_REQUEST {
+        return Err(AppError::InvalidInput("Between 1 and 50 tags are required"));
+    }
+    let mut tags = tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(AppError::InvalidInput(
+            "Tags must be 1 to 64 characters and may not contain commas",
+        ))?;
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
+
+async fn tags_response(index: &MediaIndex, path: String) -> Result<Json<TagsResponse>, AppError> {
+    let mut tags = index.tags_of(vec![path.clone()]).await.map_err(|e| {
+        tracing::error!("Failed to read tags: {:?}", e);
+        AppError::InternalError
+    })?;
+    Ok(Json(TagsResponse {
+        tags: tags.remove(&path).unwrap_or_default(),
+        path,
+    }))
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tracing::Span;
+
+/// Header carrying the request ID, both inbound and outbound
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Error bodies larger than this are passed through without the request ID appended
+const MAX_ERROR_BODY: usize = 4096;
+
+/// Histogram buckets (seconds) for request latency and cache refresh duration
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+/// Install the global Prometheus recorder
+/// Until this is called, all `metrics::` macros are no-ops.
+pub fn install_recorder() -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), DURATION_BUCKETS)
+        .context("Invalid histogram buckets")?
+        .install_recorder()
+        .context("Failed to install metrics recorder")?;
+
+    metrics::describe_counter!("doggygallery_http_requests_total", "HTTP requests by method, route and status");
+    metrics::describe_histogram!(
+        "doggygallery_http_request_duration_seconds",
+        metrics::Unit::Seconds,
+        "HTTP request latency by method and route"
+    );
+    metrics::describe_counter!(
+        "doggygallery_http_response_bytes_total",
+        metrics::Unit::Bytes,
+        "Response body bytes served (responses with a known length)"
+    );
+    metrics::describe_gauge!("doggygallery_media_cache_items", "Media files in the cache");
+    metrics::describe_histogram!(
+        "doggygallery_media_cache_refresh_duration_seconds",
+        metrics::Unit::Seconds,
+        "Time taken to rebuild the media cache"
+    );
+    metrics::describe_counter!("doggygallery_auth_failures_total", "Failed authentication attempts");
+    metrics::describe_counter!("doggygallery_rate_limited_total", "Requests rejected by a rate limiter");
+    metrics::describe_counter!("doggygallery_ip_denied_total", "Requests rejected by the IP filter");
+
+    Ok(handle)
+}
+
+/// Create the span wrapping all logs for one request
+/// Field names are stable so JSON logs can be queried by them.
+pub fn make_request_span<B>(request: &axum::http::Request<B>) -> Span {
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-");
+
+    tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        client_ip = %client_ip,
+        method = %request.method(),
+        path = %request.uri().path(),
+    )
+}
+
+/// Log the outcome of a request inside its span
+pub fn log_response<B>(response: &axum::http::Response<B>, latency: Duration, _span: &Span) {
+    tracing::info!(
+        status = response.status().as_u16(),
+        duration_ms = latency.as_millis() as u64,
+        "request completed"
+    );
+}
+
+/// Middleware assigning each request an ID
+/// A well-formed incoming `X-Request-Id` (e.g. from a reverse proxy) is kept, otherwise a UUID
+/// is generated. The ID is echoed in the response header and appended to plain-text error
+/// bodies so users can report it.
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .filter(|v| is_valid_request_id(v.as_bytes()))
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&uuid::Uuid::new_v4().to_string())
+                .expect("UUIDs are valid header values")
+        });
+    request.headers_mut().insert(REQUEST_ID_HEADER, request_id.clone());
+
+    let mut response = next.run(request).await;
+
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response = append_request_id(response, &request_id).await;
+    }
+    response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+    response
+}
+
+/// Accept only short IDs of safe characters, so clients can't inject junk into logs
+fn is_valid_request_id(value: &[u8]) -> bool {
+    !value.is_empty()
+        && value.len() <= 128
+        && value
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+/// Append "Request ID: ..." to a small plain-text error body
+async fn append_request_id(response: Response, request_id: &HeaderValue) -> Response {
+    let is_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|ct| ct.starts_with("text/plain"));
+    let is_small = axum::body::HttpBody::size_hint(response.body())
+        .exact()
+        .is_some_and(|len| (len as usize) <= MAX_ERROR_BODY);
+    if !is_text || !is_small {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_ERROR_BODY).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+
+    let mut text = String::from_utf8_lossy(&bytes).into_owned();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&format!("Request ID: {}\n", request_id.to_str().unwrap_or("-")));
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(text))
+}
+
+/// Middleware recording request count, latency and bytes served per route
+/// Uses the route pattern (e.g. `/media/*path`) so label cardinality stays bounded.
+pub async fn track_metrics(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    let bytes = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    metrics::counter!(
+        "doggygallery_http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status
+    )
+    .increment(1);
+    metrics::histogram!(
+        "doggygallery_http_request_duration_seconds",
+        "method" => method,
+        "route" => route.clone()
+    )
+    .record(start.elapsed().as_secs_f64());
+    if let Some(bytes) = bytes {
+        metrics::counter!("doggygallery_http_response_bytes_total", "route" => route).increment(bytes);
+    }
+
+    response
+}
+
+/// Render all metrics in the Prometheus text format
+pub async fn metrics_handler(State(handle): State<PrometheusHandle>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        handle.render(),
+    )
+}
+
+/// Router exposing `/metrics`
+pub fn metrics_router<S>(handle: PrometheusHandle) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(handle)
+}
+
+/// Serve `/metrics` over plain HTTP without authentication on a separate address
+/// Intended for a Prometheus scraper on localhost or a private network.
+pub async fn serve_metrics(addr: SocketAddr, handle: PrometheusHandle) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind metrics listener on {}", addr))?;
+    tracing::info!("Serving metrics on http://{}/metrics", addr);
+
+    axum::serve(listener, metrics_router::<()>(handle))
+        .await
+        .context("Metrics listener failed")
+}
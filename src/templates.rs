@@ -1,15 +1,168 @@
 use askama::Template;
-use crate::models::DirectoryListing;
+use std::sync::Arc;
+use crate::branding::Branding;
+use crate::handlers::{ListingSort, SortOrder};
+use crate::i18n::Language;
+use crate::models::{ContinueWatching, DirectoryListing};
+use crate::preview::LinkPreview;
+use crate::timeline::TimelineResponse;
 
 #[derive(Template)]
 #[template(path = "gallery.html")]
 pub struct GalleryTemplate {
+    /// Language of the page's text
+    pub lang: Language,
+    pub branding: Arc<Branding>,
     pub listing: DirectoryListing,
+    /// Open Graph tags for link previews, when the request names its host
+    pub preview: Option<LinkPreview>,
     pub emoji_prefix: &'static str,
+    /// The first media scan is still running, so search and random results may be incomplete
+    pub indexing: bool,
+    /// Show favorite toggles (signed in, with an index database)
+    pub favorites: bool,
+    /// Partly watched videos, shown on the first page of the root directory
+    pub continue_watching: Vec<ContinueWatching>,
+    /// Show the Cast button in the video player (with --casting)
+    pub casting: bool,
+    /// Show the upload zone (with --enable-uploads, for admins and uploaders)
+    pub uploads: bool,
+    /// Listing every file of the subtree (recursive=true)
+    pub flattened: bool,
+    /// Link to the timeline and map (with an index database)
+    pub index_views: bool,
+    pub sort_controls: Vec<SortControl>,
 }
 
 #[derive(Template)]
 #[template(path = "music_player.html")]
 pub struct MusicPlayerTemplate {
+    /// Language of the page's text
+    pub lang: Language,
+    pub branding: Arc<Branding>,
     pub listing: DirectoryListing,
+    /// The first media scan is still running, so search and random results may be incomplete
+    pub indexing: bool,
+    /// Show the playlist bar and add-to-playlist buttons (signed in, with an index database)
+    pub playlists: bool,
+    /// Link to the artist, album and genre views (with an index database)
+    pub library: bool,
+    /// Signed podcast feed URL of this directory (signed in, with a URL signing secret)
+    pub feed_url: Option<String>,
+    /// Empty for archive and library views, which have a fixed order
+    pub sort_controls: Vec<SortControl>,
+    /// Listing every file of the subtree (recursive=true)
+    pub flattened: bool,
+}
+
+#[derive(Template)]
+#[template(path = "timeline.html")]
+pub struct TimelineTemplate {
+    /// Language of the page's text
+    pub lang: Language,
+    pub branding: Arc<Branding>,
+    pub timeline: TimelineResponse,
+    pub emoji_prefix: &'static str,
+    /// The first media scan is still running, so the timeline may be incomplete
+    pub indexing: bool,
+    /// Show the Cast button in the video player (with --casting)
+    pub casting: bool,
+}
+
+#[derive(Template)]
+#[template(path = "map.html")]
+pub struct MapTemplate {
+    /// Language of the page's text
+    pub lang: Language,
+    pub branding: Arc<Branding>,
+    pub emoji_prefix: &'static str,
+    /// The first media scan is still running, so the map may be incomplete
+    pub indexing: bool,
+    /// Show the Cast button in the video player (with --casting)
+    pub casting: bool,
+}
+
+#[derive(Template)]
+#[template(path = "slideshow.html")]
+pub struct SlideshowTemplate {
+    /// Language of the page's text
+    pub lang: Language,
+    pub branding: Arc<Branding>,
+    /// Directory of the slideshow, relative to the media directory
+    pub path: String,
+    pub shuffle: bool,
+    /// Seconds per image
+    pub interval: u64,
+}
+
+/// A link that re-sorts a directory listing
+pub struct SortControl {
+    pub label: String,
+    /// Query string of the link, starting again from the first page
+    pub query: String,
+    /// The listing is currently sorted by this field
+    pub active: bool,
+    /// Direction of the active sort
+    pub arrow: &'static str,
+}
+
+/// Sort links for a listing sorted by `sort` in `order`
+/// Choosing the active field again flips the direction. Recursive listings stay recursive.
+pub fn sort_controls(sort: ListingSort, order: SortOrder, per_page: usize, recursive: bool) -> Vec<SortControl> {
+    let lang = Language::current();
+    ListingSort::ALL
+        .into_iter()
+        .map(|field| {
+            let active = field == sort;
+            let next = match (active, order) {
+                (true, SortOrder::Asc) => SortOrder::Desc,
+                _ => SortOrder::Asc,
+            };
+            SortControl {
+                label: lang.t(match field {
+                    ListingSort::Name => "sort-name",
+                    ListingSort::Size => "sort-size",
+                    ListingSort::Mtime => "sort-mtime",
+                    ListingSort::Type => "sort-type",
+                }),
+                query: format!(
+                    "sort={}&order={}&per_page={}{}",
+                    field.as_str(),
+                    next.as_str(),
+                    per_page,
+                    if recursive { "&recursive=true" } else { "" }
+                ),
+                active,
+                arrow: match (active, order) {
+                    (false, _) => "",
+                    (true, SortOrder::Asc) => "↑",
+                    (true, SortOrder::Desc) => "↓",
+                },
+            }
+        })
+        .collect()
+}
+
+/// One artist, album or genre in a music library view
+pub struct LibraryCard {
+    pub name: String,
+    /// Counts shown under the name
+    pub detail: String,
+    pub href: String,
+    /// Track whose album art is shown on the card
+    pub cover: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "music_library.html")]
+pub struct MusicLibraryTemplate {
+    /// Language of the page's text
+    pub lang: Language,
+    pub branding: Arc<Branding>,
+    /// Active view: "artists", "albums" or "genres"
+    pub view: &'static str,
+    pub heading: String,
+    pub cards: Vec<LibraryCard>,
+    /// The first media scan is still running, so search and random results may be incomplete
+    pub indexing: bool,
 }
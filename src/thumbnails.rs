@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::album_art::{Art, ArtCache};
+use crate::cache::ScanProgress;
+use crate::decoding;
+use crate::handlers;
+use crate::index;
+
+/// Widths and heights served for `?size=`; other sizes are rounded up to the next one
+pub const THUMBNAIL_SIZES: [u32; 5] = [50, 128, 256, 512, 1024];
+
+/// Size of thumbnails requested without `?size=`, the placeholders gallery pages show
+/// until the full image has loaded
+pub const DEFAULT_SIZE: u32 = 50;
+
+/// Query parameters of /thumbnail
+#[derive(Debug, Default, Deserialize)]
+pub struct ThumbnailQuery {
+    /// Largest width or height wanted, in pixels
+    pub size: Option<u32>,
+}
+
+/// Round a requested size up to the nearest served size
+pub fn snap_size(size: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .iter()
+        .copied()
+        .find(|&served| served >= size)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+/// A JPEG of `image` fitting in a `size`×`size` square; smaller images keep their size
+fn encode(image: &DynamicImage, size: u32) -> Result<Vec<u8>> {
+    let thumbnail = if image.width() <= size && image.height() <= size {
+        image.to_rgb8()
+    } else {
+        image.thumbnail(size, size).to_rgb8()
+    };
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(thumbnail).write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Jpeg)?;
+    Ok(data)
+}
+
+/// Thumbnail of image file contents, `size` pixels at most on each side
+/// The decoder may allocate at most `max_memory` bytes.
+pub fn render(data: &[u8], size: u32, max_memory: u64) -> Result<Vec<u8>> {
+    encode(&decoding::decode(data, max_memory)?, size)
+}
+
+/// Build the missing thumbnails of one image in `sizes`, returning how many were made
+/// An image that can't be decoded is recorded as such, so the server doesn't retry it.
+fn pregenerate(
+    cache: &ArtCache,
+    path: &Path,
+    key: &str,
+    sizes: &[u32],
+    max_memory: u64,
+) -> Result<usize> {
+    let mtime = handlers::modified_secs(&std::fs::metadata(path)?);
+    let missing: Vec<u32> = sizes
+        .iter()
+        .copied()
+        .filter(|&size| !cache.contains(key, mtime, Some(size)))
+        .collect();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let image = match decoding::open(path, max_memory) {
+        Ok(image) => image,
+        Err(e) => {
+            cache.put(key, mtime, None, None)?;
+            return Err(e);
+        }
+    };
+    for &size in &missing {
+        let thumbnail = Art {
+            data: encode(&image, size)?,
+            mime: "image/jpeg".to_string(),
+        };
+        cache.put(key, mtime, Some(size), Some(&thumbnail))?;
+    }
+    Ok(missing.len())
+}
+
+/// `doggygallery thumbnails`: fill the thumbnail cache for every image in the library
+pub fn run_thumbnails_command(
+    media_dir: PathBuf,
+    thumbnail_cache_dir: PathBuf,
+    sizes: Vec<u32>,
+    jobs: Option<usize>,
+    max_decode_memory: u64,
+) -> Result<()> {
+    let media_dir = dunce::canonicalize(&media_dir)
+        .with_context(|| format!("Media directory not found: {:?}", media_dir))?;
+    let cache = ArtCache::open(&thumbnail_cache_dir, "thumbnails")?;
+    let mut sizes: Vec<u32> = sizes.into_iter().map(snap_size).collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let mut images = Vec::new();
+    index::walk(&media_dir, "", &ScanProgress::new(), &mut |path, relative, _, file_type, _| {
+        if file_type == "image" {
+            images.push((path.to_path_buf(), relative));
+        }
+    });
+
+    let jobs = jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1);
+    let bar = ProgressBar::new(images.len() as u64).with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} images ({per_sec}, {eta} left)")
+            .context("Invalid progress bar template")?,
+    );
+    let next = AtomicUsize::new(0);
+    let generated = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                while let Some((path, key)) = images.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    match pregenerate(&cache, path, key, &sizes, max_decode_memory.saturating_mul(1024 * 1024)) {
+                        Ok(count) => {
+                            generated.fetch_add(count, Ordering::Relaxed);
+                        }
+                        Err(e) => {
+                            failed.fetch_add(1, Ordering::Relaxed);
+                            bar.println(format!("Skipping {}: {}", key, e));
+                        }
+                    }
+                    bar.inc(1);
+                }
+            });
+        }
+    });
+    bar.finish_and_clear();
+
+    println!(
+        "Generated {} thumbnails for {} images in {:.1?} ({} skipped)",
+        generated.into_inner(),
+        images.len(),
+        bar.elapsed(),
+        failed.into_inner()
+    );
+    Ok(())
+}
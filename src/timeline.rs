@@ -0,0 +1,272 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::{Html, Json},
+    Extension,
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::constants;
+use crate::handlers::{AppError, AppState, FilterResult};
+use crate::i18n::Language;
+use crate::index::MediaIndex;
+use crate::templates::TimelineTemplate;
+
+/// An image or video with the time it was taken
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TimelineItem {
+    #[serde(flatten)]
+    pub file: FilterResult,
+    /// Local time the photo or video was taken, as `YYYY-MM-DDTHH:MM:SS`
+    pub taken_at: String,
+    /// Whether `taken_at` comes from EXIF; otherwise it is the file's modification time
+    pub exif: bool,
+}
+
+/// Media taken on one day, newest first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimelineDay {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub items: Vec<TimelineItem>,
+}
+
+impl TimelineDay {
+    /// Heading such as "Saturday, 4 May 2019"
+    pub fn label(&self, lang: &Language) -> String {
+        NaiveDate::parse_from_str(&self.date, "%Y-%m-%d")
+            .map(|date| {
+                lang.t_args(
+                    "timeline-day",
+                    &[
+                        ("weekday", lang.t(&format!("weekday-{}", date.weekday().number_from_monday()))),
+                        ("day", date.day().to_string()),
+                        ("month", lang.t(&format!("month-{}", date.month()))),
+                        ("year", date.year().to_string()),
+                    ],
+                )
+            })
+            .unwrap_or_else(|_| self.date.clone())
+    }
+}
+
+/// How much media was taken in one month
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimelineMonth {
+    /// 1 to 12
+    pub month: u32,
+    pub count: usize,
+}
+
+impl TimelineMonth {
+    pub fn name(&self, lang: &Language) -> String {
+        lang.t(&format!("month-{}", self.month))
+    }
+}
+
+/// How much media was taken in one year, by month
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimelineYear {
+    pub year: i32,
+    pub count: usize,
+    /// Months with media, newest first
+    pub months: Vec<TimelineMonth>,
+}
+
+/// Media counts for every year and month, and the media of the selected days
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimelineResponse {
+    /// Images and videos the user may see
+    pub total: usize,
+    /// Years with media, newest first
+    pub years: Vec<TimelineYear>,
+    /// Selected year
+    pub year: Option<i32>,
+    /// Selected month
+    pub month: Option<u32>,
+    /// Days of the selection with media, newest first
+    pub days: Vec<TimelineDay>,
+}
+
+/// Which part of the timeline to list media of
+/// Without a year, the newest month with media is listed.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct TimelineQuery {
+    pub year: Option<i32>,
+    /// Month of `year` (1 to 12); all of `year` if omitted
+    pub month: Option<u32>,
+    /// Day of `month`; the whole month if omitted
+    pub day: Option<u32>,
+}
+
+impl MediaIndex {
+    /// Every indexed image and video with its EXIF date, if it has one
+    pub async fn timeline_files(&self) -> anyhow::Result<Vec<(FilterResult, Option<String>)>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT path, name, size, file_type, mtime, taken_at
+                 FROM files WHERE file_type IN ('image', 'video')",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    FilterResult {
+                        path: row.get(0)?,
+                        name: row.get(1)?,
+                        size: row.get(2)?,
+                        file_type: row.get(3)?,
+                        modified: row.get::<_, i64>(4)?.max(0) as u64,
+                    },
+                    row.get(5)?,
+                ))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+}
+
+/// When a file was taken: its EXIF date, or else its modification time in the server's time zone
+fn taken_at(exif: Option<&str>, modified: u64) -> (NaiveDateTime, bool) {
+    let exif = exif.and_then(|value| {
+        ["%Y-%m-%d %H:%M:%S", "%Y:%m:%d %H:%M:%S"]
+            .into_iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value.trim(), format).ok())
+    });
+    match exif {
+        Some(taken) => (taken, true),
+        None => {
+            let modified = chrono::Local
+                .timestamp_opt(modified as i64, 0)
+                .single()
+                .map(|time| time.naive_local())
+                .unwrap_or_default();
+            (modified, false)
+        }
+    }
+}
+
+/// Build the timeline of the media the user may see
+async fn timeline(
+    state: &AppState,
+    principal: Option<&Principal>,
+    query: &TimelineQuery,
+) -> Result<TimelineResponse, AppError> {
+    let index = state.index.as_ref().ok_or(AppError::NotFound)?;
+    let files = index.timeline_files().await.map_err(|e| {
+        tracing::error!("{:?}", e);
+        AppError::InternalError
+    })?;
+
+    let mut items: Vec<(NaiveDateTime, TimelineItem)> = files
+        .into_iter()
+        .filter(|(file, _)| state.acl.allows(principal, &file.path))
+        .map(|(file, exif)| {
+            let (taken, exif) = taken_at(exif.as_deref(), file.modified);
+            let item = TimelineItem {
+                taken_at: taken.format("%Y-%m-%dT%H:%M:%S").to_string(),
+                exif,
+                file,
+            };
+            (taken, item)
+        })
+        .collect();
+    items.sort_by(|(a, a_item), (b, b_item)| b.cmp(a).then_with(|| a_item.file.path.cmp(&b_item.file.path)));
+
+    let mut counts: BTreeMap<i32, BTreeMap<u32, usize>> = BTreeMap::new();
+    for (taken, _) in &items {
+        *counts.entry(taken.year()).or_default().entry(taken.month()).or_default() += 1;
+    }
+    let years: Vec<TimelineYear> = counts
+        .into_iter()
+        .rev()
+        .map(|(year, months)| TimelineYear {
+            year,
+            count: months.values().sum(),
+            months: months
+                .into_iter()
+                .rev()
+                .map(|(month, count)| TimelineMonth { month, count })
+                .collect(),
+        })
+        .collect();
+
+    // Default to the newest month
+    let (year, month, day) = match query.year {
+        Some(year) => (Some(year), query.month, query.month.and(query.day)),
+        None => match years.first() {
+            Some(newest) => (Some(newest.year), newest.months.first().map(|m| m.month), None),
+            None => (None, None, None),
+        },
+    };
+
+    let total = items.len();
+    let mut days: Vec<TimelineDay> = Vec::new();
+    for (taken, item) in items {
+        let selected = Some(taken.year()) == year
+            && month.is_none_or(|month| taken.month() == month)
+            && day.is_none_or(|day| taken.day() == day);
+        if !selected {
+            continue;
+        }
+        let date = taken.format("%Y-%m-%d").to_string();
+        match days.last_mut() {
+            Some(last) if last.date == date => last.items.push(item),
+            _ => days.push(TimelineDay {
+                date,
+                items: vec![item],
+            }),
+        }
+    }
+
+    Ok(TimelineResponse {
+        total,
+        years,
+        year,
+        month,
+        days,
+    })
+}
+
+/// Images and videos grouped by the date they were taken
+/// Dates come from EXIF in the index, or else the file's modification time. Every
+/// response counts media per year and month; `days` lists the media of the selected
+/// year, month or day, or of the newest month if none is given.
+#[utoipa::path(
+    get,
+    path = "/api/timeline",
+    params(TimelineQuery),
+    responses(
+        (status = 200, description = "Timeline", body = TimelineResponse),
+        (status = 404, description = "No index database configured")
+    ),
+    tag = "media"
+)]
+pub async fn timeline_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Json<TimelineResponse>, AppError> {
+    Ok(Json(timeline(&state, principal.as_deref(), &query).await?))
+}
+
+/// Handler for /timeline - the gallery as a photo timeline
+pub async fn timeline_page_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Html<String>, AppError> {
+    let timeline = timeline(&state, principal.as_deref(), &query).await?;
+    let template = TimelineTemplate {
+        lang: Language::current(),
+        branding: state.branding.clone(),
+        timeline,
+        emoji_prefix: constants::EMOJI_PREFIX,
+        indexing: !state.scan.is_ready(),
+        casting: state.cast.is_some(),
+    };
+    Ok(Html(template.render().map_err(|_| AppError::InternalError)?))
+}
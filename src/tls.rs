@@ -1,7 +1,11 @@
 use anyhow::{Context, Result};
 use axum_server::tls_rustls::RustlsConfig;
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use rustls::crypto::CryptoProvider;
+use rustls::sign::CertifiedKey;
+use rustls::InconsistentKeys;
+use sha2::{Digest, Sha256};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -12,13 +16,34 @@ pub async fn load_tls_config(cert_path: &Path, key_path: &Path) -> Result<Rustls
     tracing::info!("  Certificate: {:?}", cert_path);
     tracing::info!("  Private key: {:?}", key_path);
 
-    // Read certificate and key files
-    let cert_file = tokio::fs::read(cert_path)
-        .await
+    let (certs, key) = read_pem_files(cert_path, key_path)?;
+
+    // Create a custom crypto provider with post-quantum key exchange
+    let crypto_provider = create_quantum_resistant_crypto_provider();
+
+    // Build ServerConfig with TLS 1.3 ONLY, HTTP/2 ONLY, and quantum-resistant crypto
+    let mut server_config = rustls::ServerConfig::builder_with_provider(crypto_provider.into())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .context("Failed to create server config builder")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Failed to create TLS configuration")?;
+
+    // Configure ALPN to only support HTTP/2 (no HTTP/1.1 fallback)
+    server_config.alpn_protocols = vec![b"h2".to_vec()];
+
+    tracing::info!("TLS configuration loaded successfully (TLS 1.3 + HTTP/2 + AWS-LC-RS crypto)");
+
+    // Convert to RustlsConfig
+    Ok(RustlsConfig::from_config(Arc::new(server_config)))
+}
+
+/// Read and parse the certificate chain and private key files
+fn read_pem_files(cert_path: &Path, key_path: &Path) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let cert_file = std::fs::read(cert_path)
         .with_context(|| format!("Failed to read certificate file: {:?}", cert_path))?;
 
-    let key_file = tokio::fs::read(key_path)
-        .await
+    let key_file = std::fs::read(key_path)
         .with_context(|| format!("Failed to read private key file: {:?}", key_path))?;
 
     // Parse certificates
@@ -35,24 +60,106 @@ pub async fn load_tls_config(cert_path: &Path, key_path: &Path) -> Result<Rustls
         .context("Failed to parse private key file")?
         .context("No private key found in key file")?;
 
-    // Create a custom crypto provider with post-quantum key exchange
-    let crypto_provider = create_quantum_resistant_crypto_provider();
+    Ok((certs, key))
+}
 
-    // Build ServerConfig with TLS 1.3 ONLY, HTTP/2 ONLY, and quantum-resistant crypto
-    let mut server_config = rustls::ServerConfig::builder_with_provider(crypto_provider.into())
-        .with_protocol_versions(&[&rustls::version::TLS13])
-        .context("Failed to create server config builder")?
-        .with_no_client_auth()
-        .with_single_cert(certs, key)
-        .context("Failed to create TLS configuration")?;
+/// Validity period of a certificate
+pub struct Validity {
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
 
-    // Configure ALPN to only support HTTP/2 (no HTTP/1.1 fallback)
-    server_config.alpn_protocols = vec![b"h2".to_vec()];
+/// Check that a certificate belongs to its private key and is valid at the current time
+/// A certificate that isn't valid yet usually means the system clock is wrong, and the error
+/// says so, rather than letting every browser reject the connection with a vague message.
+pub fn check_certificate(cert_path: &Path, key_path: &Path) -> Result<Validity> {
+    let (certs, key) = read_pem_files(cert_path, key_path)?;
+    let signing_key = create_quantum_resistant_crypto_provider()
+        .key_provider
+        .load_private_key(key)
+        .with_context(|| format!("Unsupported private key in {:?}", key_path))?;
+    let validity = validity(&certs[0])
+        .with_context(|| format!("Failed to read the validity period of the certificate in {:?}", cert_path))?;
 
-    tracing::info!("TLS configuration loaded successfully (TLS 1.3 + HTTP/2 + AWS-LC-RS crypto)");
+    match CertifiedKey::new(certs, signing_key).keys_match() {
+        Ok(()) | Err(rustls::Error::InconsistentKeys(InconsistentKeys::Unknown)) => {}
+        Err(rustls::Error::InconsistentKeys(InconsistentKeys::KeyMismatch)) => anyhow::bail!(
+            "The private key {:?} does not belong to the certificate {:?}; pass the key the certificate was issued for (e.g. privkey.pem next to fullchain.pem)",
+            key_path,
+            cert_path
+        ),
+        Err(e) => anyhow::bail!("Failed to parse the certificate in {:?}: {}", cert_path, e),
+    }
 
-    // Convert to RustlsConfig
-    Ok(RustlsConfig::from_config(Arc::new(server_config)))
+    let now = Utc::now();
+    if now < validity.not_before {
+        anyhow::bail!(
+            "The certificate {:?} is not valid until {}, but the system clock says it is {}; if the certificate is new, check the clock (NTP)",
+            cert_path,
+            validity.not_before,
+            now.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+    }
+    if now > validity.not_after {
+        anyhow::bail!(
+            "The certificate {:?} expired on {}; renew it (e.g. `certbot renew`), or check the system clock, which says it is {}",
+            cert_path,
+            validity.not_after,
+            now.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+    }
+    Ok(validity)
+}
+
+/// Validity period of a DER-encoded X.509 certificate
+fn validity(cert: &[u8]) -> Option<Validity> {
+    let (_, certificate, _) = der_element(cert)?;
+    let (_, tbs, _) = der_element(certificate)?;
+    // Skip the optional version, then the serial number, signature algorithm and issuer
+    let (tag, _, mut rest) = der_element(tbs)?;
+    if tag == 0xa0 {
+        rest = der_element(rest)?.2;
+    }
+    let rest = der_element(der_element(rest)?.2)?.2;
+    let (_, validity, _) = der_element(rest)?;
+    let (tag, time, rest) = der_element(validity)?;
+    let not_before = der_time(tag, time)?;
+    let (tag, time, _) = der_element(rest)?;
+    Some(Validity { not_before, not_after: der_time(tag, time)? })
+}
+
+/// Split the DER element at the start of `input` into its tag, contents and what follows
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        // Long form: the low bits count the length bytes that follow
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at(count);
+        (bytes.iter().fold(0, |length, &byte| length << 8 | byte as usize), rest)
+    };
+    if rest.len() < length {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(length);
+    Some((tag, contents, rest))
+}
+
+/// Parse a DER UTCTime or GeneralizedTime
+fn der_time(tag: u8, contents: &[u8]) -> Option<DateTime<Utc>> {
+    let text = std::str::from_utf8(contents).ok()?;
+    let text = match tag {
+        // UTCTime has a two-digit year, where 50 to 99 mean 1950 to 1999
+        0x17 => format!("{}{}", if text.get(..2)? >= "50" { "19" } else { "20" }, text),
+        0x18 => text.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&text, "%Y%m%d%H%M%SZ").ok().map(|time| time.and_utc())
 }
 
 /// Generate self-signed certificate and private key on-the-fly
@@ -91,10 +198,57 @@ pub async fn generate_self_signed_config() -> Result<RustlsConfig> {
 
     tracing::info!("Self-signed certificate generated successfully (TLS 1.3 + HTTP/2 + AWS-LC-RS crypto)");
     tracing::warn!("Using self-signed certificate - this is NOT suitable for production!");
+    tracing::info!("A new certificate is made at every start; `doggygallery gen-certs` writes one to keep");
 
     Ok(RustlsConfig::from_config(Arc::new(server_config)))
 }
 
+/// `doggygallery gen-certs`: write a self-signed certificate and its private key
+/// The certificate is valid from the start of today (UTC) for `days` days, for every name in
+/// `sans`. Its SHA-256 fingerprint is printed so it can be compared with what browsers show.
+pub fn run_gen_certs_command(cert_path: &Path, key_path: &Path, sans: Vec<String>, days: u32, force: bool) -> Result<()> {
+    for path in [cert_path, key_path] {
+        if path.exists() && !force {
+            anyhow::bail!("{:?} already exists; pass --force to overwrite it", path);
+        }
+    }
+    let Some(common_name) = sans.first().cloned() else {
+        anyhow::bail!("At least one --san is required");
+    };
+    let is_host_name = |name: &str| {
+        !name.is_empty()
+            && name.split('.').all(|label| {
+                !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '*')
+            })
+    };
+    if let Some(invalid) = sans.iter().find(|san| san.parse::<std::net::IpAddr>().is_err() && !is_host_name(san)) {
+        anyhow::bail!("Invalid --san {:?}: expected a host name or IP address", invalid);
+    }
+
+    let mut params = rcgen::CertificateParams::new(sans.clone()).context("Invalid --san")?;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.distinguished_name.push(rcgen::DnType::CommonName, common_name);
+    params.distinguished_name.push(rcgen::DnType::OrganizationName, "DoggyGallery");
+    params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ServerAuth];
+    let start = Utc::now().date_naive();
+    let end = start + chrono::Days::new(days.into());
+    let date = |date: chrono::NaiveDate| rcgen::date_time_ymd(date.year(), date.month() as u8, date.day() as u8);
+    params.not_before = date(start);
+    params.not_after = date(end);
+
+    let key_pair = rcgen::KeyPair::generate().context("Failed to generate a private key")?;
+    let cert = params.self_signed(&key_pair).context("Failed to generate the certificate")?;
+
+    crate::users::write_private_file(key_path, &key_pair.serialize_pem())?;
+    std::fs::write(cert_path, cert.pem()).with_context(|| format!("Failed to write {:?}", cert_path))?;
+
+    let fingerprint: Vec<String> = Sha256::digest(cert.der()).iter().map(|byte| format!("{:02X}", byte)).collect();
+    println!("Wrote {:?} and {:?}", cert_path, key_path);
+    println!("Valid for {} from {} until {}", sans.join(", "), start, end);
+    println!("SHA-256 fingerprint: {}", fingerprint.join(":"));
+    Ok(())
+}
+
 /// Create a crypto provider with post-quantum key exchange
 ///
 /// This uses AWS-LC-RS which provides:
@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::auth::Role;
+use crate::config::TokenCommand;
+use crate::users::write_private_file;
+
+/// Prefix for generated API tokens, so they are easy to spot in logs and secret scanners
+const TOKEN_PREFIX: &str = "dg_";
+
+/// A single API token as stored in the tokens file
+/// Only the SHA-256 of the token is stored, never the token itself.
+///
+/// ```toml
+/// [[tokens]]
+/// name = "cron-random-wallpaper"
+/// role = "viewer"
+/// hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenEntry {
+    pub name: String,
+    /// Older tokens files call this `scope` (`read` or `admin`)
+    #[serde(alias = "scope")]
+    pub role: Role,
+    pub hash: String,
+}
+
+/// Contents of the tokens file (a list of `[[tokens]]` tables)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokensFile {
+    #[serde(default)]
+    pub tokens: Vec<TokenEntry>,
+}
+
+impl TokensFile {
+    /// Load and parse a tokens file from disk
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tokens file: {:?}", path))?;
+
+        let tokens_file: TokensFile = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse tokens file: {:?}", path))?;
+
+        Ok(tokens_file)
+    }
+
+    /// Load a tokens file, or start an empty one if it doesn't exist yet
+    pub fn load_or_default(path: &Path) -> Result<Self> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Write the tokens file back to disk
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string_pretty(self).context("Failed to serialize tokens file")?;
+        write_private_file(path, &contents)
+    }
+
+    /// Build the hash -> token table used by the auth middleware
+    pub fn into_table(self) -> Result<HashMap<String, TokenEntry>> {
+        let mut table = HashMap::new();
+
+        for token in self.tokens {
+            if token.hash.len() != 64 || !token.hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                anyhow::bail!("Token '{}' has an invalid hash (expected SHA-256 hex)", token.name);
+            }
+            table.insert(token.hash.to_lowercase(), token);
+        }
+
+        Ok(table)
+    }
+}
+
+/// Hash a presented token for lookup in the token table
+/// Tokens are 256-bit random values, so a plain SHA-256 is sufficient (no salt/stretching needed)
+pub fn hash_token(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Generate a new random API token
+fn generate_token() -> String {
+    let random: [u8; 32] = rand::random();
+    format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(random)
+    )
+}
+
+/// Run a `doggygallery token ...` subcommand
+pub fn run_token_command(action: TokenCommand) -> Result<()> {
+    match action {
+        TokenCommand::Create { name, role, tokens_file } => {
+            let mut file = TokensFile::load_or_default(&tokens_file)?;
+            if file.tokens.iter().any(|t| t.name == name) {
+                anyhow::bail!("Token '{}' already exists in {:?}", name, tokens_file);
+            }
+
+            let token = generate_token();
+            file.tokens.push(TokenEntry {
+                name: name.clone(),
+                role,
+                hash: hash_token(&token),
+            });
+            file.save(&tokens_file)?;
+
+            eprintln!("Created {:?} token '{}'. It will not be shown again:", role, name);
+            println!("{}", token);
+        }
+        TokenCommand::Revoke { name, tokens_file } => {
+            let mut file = TokensFile::load(&tokens_file)?;
+            let before = file.tokens.len();
+            file.tokens.retain(|t| t.name != name);
+
+            if file.tokens.len() == before {
+                anyhow::bail!("Token '{}' not found in {:?}", name, tokens_file);
+            }
+            file.save(&tokens_file)?;
+
+            println!("Revoked token '{}'", name);
+        }
+        TokenCommand::List { tokens_file } => {
+            let file = TokensFile::load(&tokens_file)?;
+            for token in &file.tokens {
+                println!("{}\t{:?}", token.name, token.role);
+            }
+        }
+    }
+
+    Ok(())
+}
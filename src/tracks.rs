@@ -0,0 +1,142 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    Extension,
+};
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+use crate::index::MediaIndex;
+use crate::metadata::{self, ReplayGain};
+use crate::models::{AudioInfo, DirectoryEntry};
+
+/// Tags and loudness metadata of one audio file
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrackInfo {
+    /// Path relative to the media directory
+    pub path: String,
+    /// File name
+    pub name: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    /// Position of the track on its album
+    pub track: Option<u32>,
+    /// Playing time in seconds
+    pub duration: Option<f64>,
+    /// Average bitrate in kbit/s
+    pub bitrate: Option<u32>,
+    /// ReplayGain values from `REPLAYGAIN_*` tags, or Opus `R128_*_GAIN` tags
+    pub replaygain: ReplayGain,
+}
+
+/// Get the tags and ReplayGain values of an audio file
+/// Gains are read from the file's tags; files without them report `null` gains.
+#[utoipa::path(
+    get,
+    path = "/api/track/{path}",
+    params(("path" = String, Path, description = "Audio file path relative to the media directory")),
+    responses(
+        (status = 200, description = "Track metadata", body = TrackInfo),
+        (status = 403, description = "Path not accessible"),
+        (status = 404, description = "Audio file not found")
+    ),
+    tag = "media"
+)]
+pub async fn track_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+) -> Result<Json<TrackInfo>, AppError> {
+    let path = handlers::media_path(&path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !canonical.is_file() || handlers::media_type(&path) != Some("audio") {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(principal.as_deref(), &path) {
+        return Err(AppError::Forbidden);
+    }
+
+    let ((tags, replaygain), properties) = tokio::task::spawn_blocking(move || {
+        let properties = metadata::read_audio_file(&canonical).map(|(_, properties)| properties);
+        (
+            metadata::read_track_tags(&canonical).unwrap_or_default(),
+            properties.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(|_| AppError::InternalError)?;
+
+    Ok(Json(TrackInfo {
+        name: path.rsplit('/').next().unwrap_or_default().to_string(),
+        path,
+        artist: tags.artist,
+        album: tags.album,
+        title: tags.title,
+        genre: tags.genre,
+        year: tags.year,
+        track: tags.track,
+        duration: properties.duration,
+        bitrate: properties.bitrate,
+        replaygain,
+    }))
+}
+
+impl MediaIndex {
+    /// Indexed tags and stream properties of the given audio files, keyed by path
+    pub async fn audio_info(&self, paths: Vec<String>) -> anyhow::Result<HashMap<String, AudioInfo>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT artist, album, title, track, duration, bitrate FROM files
+                 WHERE path = ?1 AND file_type = 'audio'",
+            )?;
+            let mut info = HashMap::new();
+            for path in paths {
+                let row = stmt
+                    .query_row([&path], |row| {
+                        Ok(AudioInfo {
+                            artist: row.get(0)?,
+                            album: row.get(1)?,
+                            title: row.get(2)?,
+                            track: row.get(3)?,
+                            duration: row.get(4)?,
+                            bitrate: row.get(5)?,
+                        })
+                    })
+                    .optional()?;
+                if let Some(row) = row {
+                    info.insert(path, row);
+                }
+            }
+            Ok(info)
+        })
+        .await
+    }
+}
+
+/// Fill in the track info of the audio files in a listing, if there is an index database
+/// Files the index hasn't reached yet are left without it.
+pub async fn attach_audio_info(state: &AppState, entries: &mut [DirectoryEntry]) {
+    let Some(index) = &state.index else {
+        return;
+    };
+    let paths = entries
+        .iter()
+        .filter(|entry| entry.is_audio())
+        .map(|entry| entry.path.clone())
+        .collect();
+    match index.audio_info(paths).await {
+        Ok(mut info) => {
+            for entry in entries {
+                entry.audio = info.remove(&entry.path);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to read track info for listing: {:?}", e),
+    }
+}
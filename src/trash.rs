@@ -0,0 +1,366 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path as FsPath, PathBuf};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::files;
+use crate::handlers::{self, AppError, AppState, FilterResult};
+use crate::netfs;
+
+/// Suffix of the record kept next to each trashed item
+const RECORD_SUFFIX: &str = ".json";
+
+/// A deleted file or directory waiting in the trash
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TrashItem {
+    /// ID for restoring or purging the item
+    pub id: String,
+    /// Where the item was, relative to the media directory
+    pub path: String,
+    pub is_dir: bool,
+    /// Size in bytes; for a directory, the total of the files inside it
+    pub size: u64,
+    /// Who deleted the item
+    pub deleted_by: String,
+    /// When the item was deleted (Unix seconds)
+    pub deleted_at: i64,
+    /// When the item will be purged (Unix seconds), if the trash has a retention period
+    pub expires_at: Option<i64>,
+}
+
+/// Deleted files and directories, kept for a while so they can be restored
+/// Each item is stored as `<id>` (the file or directory itself) next to `<id>.json`.
+pub struct Trash {
+    dir: PathBuf,
+    retention: Option<Duration>,
+}
+
+impl Trash {
+    /// Use `dir` for the trash, creating it if missing
+    /// Items older than `retention` are purged by [`Trash::purge_expired`].
+    pub fn open(dir: PathBuf, retention: Option<Duration>) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create trash directory {:?}", dir))?;
+        Ok(Self { dir, retention })
+    }
+
+    fn item_path(&self, id: &str) -> Result<PathBuf, AppError> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(AppError::NotFound);
+        }
+        Ok(self.dir.join(id))
+    }
+
+    fn record_path(&self, id: &str) -> Result<PathBuf, AppError> {
+        self.item_path(id)?;
+        Ok(self.dir.join(format!("{}{}", id, RECORD_SUFFIX)))
+    }
+
+    /// Move a file or directory into the trash
+    /// The trash must be on the same filesystem as the media directory.
+    pub async fn put(&self, location: &FsPath, path: &str, deleted_by: &str) -> Result<TrashItem, AppError> {
+        let metadata = tokio::fs::symlink_metadata(location)
+            .await
+            .map_err(|_| AppError::NotFound)?;
+        let is_dir = metadata.is_dir();
+        let size = if is_dir {
+            let location = location.to_path_buf();
+            tokio::task::spawn_blocking(move || directory_size(&location))
+                .await
+                .unwrap_or(0)
+        } else {
+            metadata.len()
+        };
+        let deleted_at = chrono::Utc::now().timestamp();
+        let item = TrashItem {
+            id: format!("{}-{:08x}", deleted_at, rand::random::<u32>()),
+            path: path.to_string(),
+            is_dir,
+            size,
+            deleted_by: deleted_by.to_string(),
+            deleted_at,
+            expires_at: self
+                .retention
+                .map(|retention| deleted_at.saturating_add(i64::try_from(retention.as_secs()).unwrap_or(i64::MAX))),
+        };
+
+        let record = serde_json::to_vec_pretty(&item).map_err(|_| AppError::InternalError)?;
+        let record_path = self.record_path(&item.id)?;
+        tokio::fs::write(&record_path, record).await.map_err(|e| {
+            tracing::error!("Failed to write trash record {:?}: {}", record_path, e);
+            AppError::InternalError
+        })?;
+        if let Err(e) = tokio::fs::rename(location, self.item_path(&item.id)?).await {
+            tracing::error!("Failed to move {:?} to the trash: {}", location, e);
+            let _ = tokio::fs::remove_file(&record_path).await;
+            return Err(match e.kind() {
+                std::io::ErrorKind::CrossesDevices => {
+                    AppError::Conflict("Trash is on another filesystem")
+                }
+                _ => AppError::InternalError,
+            });
+        }
+        Ok(item)
+    }
+
+    /// Items in the trash, most recently deleted first
+    pub async fn list(&self) -> anyhow::Result<Vec<TrashItem>> {
+        let mut items = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.ends_with(RECORD_SUFFIX) {
+                continue;
+            }
+            let record = tokio::fs::read(entry.path()).await?;
+            match serde_json::from_slice::<TrashItem>(&record) {
+                Ok(item) => items.push(item),
+                Err(e) => tracing::warn!("Skipping unreadable trash record {:?}: {}", entry.path(), e),
+            }
+        }
+        items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then_with(|| a.path.cmp(&b.path)));
+        Ok(items)
+    }
+
+    async fn get(&self, id: &str) -> Result<TrashItem, AppError> {
+        let record = tokio::fs::read(self.record_path(id)?)
+            .await
+            .map_err(|_| AppError::NotFound)?;
+        serde_json::from_slice(&record).map_err(|_| AppError::InternalError)
+    }
+
+    /// Move an item back to `location`, its original place
+    async fn restore(&self, id: &str, location: &FsPath) -> Result<(), AppError> {
+        tokio::fs::rename(self.item_path(id)?, location).await.map_err(|e| {
+            tracing::error!("Failed to restore {} to {:?}: {}", id, location, e);
+            AppError::InternalError
+        })?;
+        let _ = tokio::fs::remove_file(self.record_path(id)?).await;
+        Ok(())
+    }
+
+    /// Delete an item for good
+    pub async fn purge(&self, id: &str) -> Result<(), AppError> {
+        let item_path = self.item_path(id)?;
+        let record_path = self.record_path(id)?;
+        if tokio::fs::symlink_metadata(&record_path).await.is_err() {
+            return Err(AppError::NotFound);
+        }
+        let result = match tokio::fs::symlink_metadata(&item_path).await {
+            Ok(metadata) if metadata.is_dir() => tokio::fs::remove_dir_all(&item_path).await,
+            Ok(_) => tokio::fs::remove_file(&item_path).await,
+            Err(_) => Ok(()),
+        };
+        result.map_err(|e| {
+            tracing::error!("Failed to purge {:?} from the trash: {}", item_path, e);
+            AppError::InternalError
+        })?;
+        let _ = tokio::fs::remove_file(&record_path).await;
+        Ok(())
+    }
+
+    /// Delete items older than the retention period, returning how many were purged
+    pub async fn purge_expired(&self) -> usize {
+        let now = chrono::Utc::now().timestamp();
+        let items = match self.list().await {
+            Ok(items) => items,
+            Err(e) => {
+                tracing::warn!("Failed to read the trash: {:?}", e);
+                return 0;
+            }
+        };
+        let mut purged = 0;
+        for item in items.iter().filter(|item| item.expires_at.is_some_and(|expires| expires <= now)) {
+            if self.purge(&item.id).await.is_ok() {
+                purged += 1;
+            }
+        }
+        if purged > 0 {
+            tracing::info!("Purged {} expired item(s) from the trash", purged);
+        }
+        purged
+    }
+}
+
+/// Total size of the files below a directory
+fn directory_size(dir: &FsPath) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+            Ok(file_type) if file_type.is_file() => entry.metadata().map_or(0, |m| m.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// The trash, if deletions go to one and the principal may manage files
+fn require_trash<'a>(state: &'a AppState, principal: Option<&Principal>) -> Result<&'a Trash, AppError> {
+    files::require_write_access(state, principal)?;
+    state.trash.as_deref().ok_or(AppError::NotFound)
+}
+
+/// List the trash
+#[utoipa::path(
+    get,
+    path = "/api/trash",
+    responses(
+        (status = 200, description = "Trashed items, most recently deleted first", body = Vec<TrashItem>),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Write operations or the trash disabled")
+    ),
+    tag = "media"
+)]
+pub async fn list_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<Json<Vec<TrashItem>>, AppError> {
+    let trash = require_trash(&state, principal.as_deref())?;
+    let items = trash.list().await.map_err(|e| {
+        tracing::error!("Failed to read the trash: {:?}", e);
+        AppError::InternalError
+    })?;
+    Ok(Json(
+        items
+            .into_iter()
+            .filter(|item| state.acl.allows(principal.as_deref(), &item.path))
+            .collect(),
+    ))
+}
+
+/// Put a trashed item back where it was
+/// Missing parent directories are recreated.
+#[utoipa::path(
+    post,
+    path = "/api/trash/{id}/restore",
+    params(("id" = String, Path, description = "Trash item ID")),
+    responses(
+        (status = 200, description = "Restored", body = TrashItem),
+        (status = 403, description = "Not an admin, or original path not accessible"),
+        (status = 404, description = "No such item, or write operations or the trash disabled"),
+        (status = 409, description = "Something else is at the original path")
+    ),
+    tag = "media"
+)]
+pub async fn restore_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<String>,
+) -> Result<Json<TrashItem>, AppError> {
+    let trash = require_trash(&state, principal.as_deref())?;
+    let item = trash.get(&id).await?;
+    if !state.acl.allows(principal.as_deref(), &item.path) {
+        return Err(AppError::Forbidden);
+    }
+    let path = crate::acl::normalize(&item.path)
+        .filter(|path| !path.is_empty())
+        .ok_or(AppError::InvalidPath)?;
+    let location = state.media_dir.join(&path);
+    if tokio::fs::symlink_metadata(&location).await.is_ok() {
+        return Err(AppError::Conflict("Something else is at the original path"));
+    }
+    if let Some(parent) = location.parent() {
+        // A symlinked parent could lead outside the media directory, so the deepest one that
+        // exists is checked before the missing ones are created below it
+        let existing = parent
+            .ancestors()
+            .find(|dir| std::fs::symlink_metadata(dir).is_ok())
+            .unwrap_or(&state.media_dir);
+        let existing = netfs::canonicalize(existing).map_err(|_| AppError::Forbidden)?;
+        if !existing.starts_with(&state.media_dir) {
+            return Err(AppError::Forbidden);
+        }
+        tokio::fs::create_dir_all(parent).await.map_err(|e| {
+            tracing::error!("Failed to recreate {:?}: {}", parent, e);
+            AppError::InternalError
+        })?;
+    }
+    trash.restore(&id, &location).await?;
+
+    let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+    if let (false, Some(file_type)) = (item.is_dir, handlers::media_type(&name)) {
+        let modified = tokio::fs::metadata(&location)
+            .await
+            .map(|metadata| handlers::modified_secs(&metadata))
+            .unwrap_or(0);
+        let restored = FilterResult {
+            path: path.clone(),
+            name,
+            size: item.size,
+            file_type: file_type.to_string(),
+            modified,
+        };
+        state.media_cache.write().await.insert(restored.clone());
+        state.events.publish_media(vec![restored]);
+    }
+    if let Some(principal) = &principal {
+        tracing::info!(username = %principal.username, path = %path, "Restored from trash");
+    }
+    Ok(Json(item))
+}
+
+/// Delete a trashed item for good
+#[utoipa::path(
+    delete,
+    path = "/api/trash/{id}",
+    params(("id" = String, Path, description = "Trash item ID")),
+    responses(
+        (status = 204, description = "Purged"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "No such item, or write operations or the trash disabled")
+    ),
+    tag = "media"
+)]
+pub async fn purge_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let trash = require_trash(&state, principal.as_deref())?;
+    let item = trash.get(&id).await?;
+    if !state.acl.allows(principal.as_deref(), &item.path) {
+        return Err(AppError::Forbidden);
+    }
+    trash.purge(&id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Empty the trash
+/// Only items whose original path you can access are purged.
+#[utoipa::path(
+    delete,
+    path = "/api/trash",
+    responses(
+        (status = 204, description = "Trash emptied"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Write operations or the trash disabled")
+    ),
+    tag = "media"
+)]
+pub async fn empty_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> Result<StatusCode, AppError> {
+    let trash = require_trash(&state, principal.as_deref())?;
+    let items = trash.list().await.map_err(|e| {
+        tracing::error!("Failed to read the trash: {:?}", e);
+        AppError::InternalError
+    })?;
+    for item in items.iter().filter(|item| state.acl.allows(principal.as_deref(), &item.path)) {
+        trash.purge(&item.id).await?;
+    }
+    if let Some(principal) = &principal {
+        tracing::info!(username = %principal.username, items = items.len(), "Trash emptied");
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,388 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Multipart, Path, Query, Request, State},
+    http::{header, StatusCode},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path as FsPath, PathBuf};
+use tokio::io::AsyncWriteExt;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::constants;
+use crate::files;
+use crate::handlers::{self, AppError, AppState, FilterResult};
+
+/// Query parameters of the upload endpoint
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct UploadQuery {
+    /// Replace the file if it already exists (default false)
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// An upload being written to a temporary file next to its destination
+/// The file is removed on drop unless it was moved into place.
+struct PendingUpload {
+    temp_path: PathBuf,
+    file: Option<tokio::fs::File>,
+    written: u64,
+    limit: u64,
+    head: Vec<u8>,
+}
+
+impl PendingUpload {
+    async fn create(dir: &FsPath, name: &str, limit: u64) -> Result<Self, AppError> {
+        let temp_path = dir.join(format!(".{}.{:016x}.upload", name, rand::random::<u64>()));
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to create upload file {:?}: {}", temp_path, e);
+                AppError::InternalError
+            })?;
+        Ok(Self {
+            temp_path,
+            file: Some(file),
+            written: 0,
+            limit,
+            head: Vec::new(),
+        })
+    }
+
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), AppError> {
+        self.written += chunk.len() as u64;
+        if self.written > self.limit {
+            return Err(AppError::TooLarge);
+        }
+        if self.head.len() < constants::SNIFF_SIZE {
+            let take = chunk.len().min(constants::SNIFF_SIZE - self.head.len());
+            self.head.extend_from_slice(&chunk[..take]);
+        }
+        let file = self.file.as_mut().ok_or(AppError::InternalError)?;
+        file.write_all(chunk).await.map_err(|e| {
+            tracing::error!("Failed to write upload file {:?}: {}", self.temp_path, e);
+            AppError::InternalError
+        })
+    }
+
+    /// Flush the file to disk and move it to `destination`
+    async fn persist(mut self, destination: &FsPath) -> Result<u64, AppError> {
+        let file = self.file.take().ok_or(AppError::InternalError)?;
+        file.sync_all().await.map_err(|_| AppError::InternalError)?;
+        drop(file);
+        tokio::fs::rename(&self.temp_path, destination).await.map_err(|e| {
+            tracing::error!("Failed to move upload to {:?}: {}", destination, e);
+            AppError::InternalError
+        })?;
+        // Nothing left to clean up
+        self.temp_path = PathBuf::new();
+        Ok(self.written)
+    }
+}
+
+impl Drop for PendingUpload {
+    fn drop(&mut self) {
+        if !self.temp_path.as_os_str().is_empty() {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Read the next chunk of a raw request body
+async fn next_chunk(body: &mut Body) -> Result<Option<Bytes>, AppError> {
+    loop {
+        let frame = std::future::poll_fn(|cx| http_body::Body::poll_frame(std::pin::Pin::new(&mut *body), cx)).await;
+        match frame {
+            None => return Ok(None),
+            Some(Ok(frame)) => {
+                if let Ok(data) = frame.into_data() {
+                    return Ok(Some(data));
+                }
+            }
+            Some(Err(_)) => return Err(AppError::InvalidInput("Upload interrupted")),
+        }
+    }
+}
+
+/// Where an upload goes, after the name, access and conflict checks
+struct Target {
+    path: String,
+    name: String,
+    file_type: &'static str,
+    parent: PathBuf,
+    destination: PathBuf,
+}
+
+/// Check that `path` may be written by an upload
+async fn target(state: &AppState, principal: &Principal, path: &str, overwrite: bool) -> Result<Target, AppError> {
+    let path = handlers::media_path(path)?;
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    if !files::is_valid_name(name) {
+        return Err(AppError::InvalidInput("Invalid file name"));
+    }
+    let file_type = handlers::media_type(name).ok_or(AppError::InvalidInput("Not a media file name"))?;
+    if !state.acl.allows(Some(principal), &path) {
+        return Err(AppError::Forbidden);
+    }
+    let parent = handlers::validate_media_path(&state.media_dir, dir)?;
+    if !parent.is_dir() {
+        return Err(AppError::NotFound);
+    }
+    let destination = parent.join(name);
+    match tokio::fs::symlink_metadata(&destination).await {
+        Ok(metadata) if !metadata.is_file() => return Err(AppError::Conflict("Path exists and is not a file")),
+        Ok(_) if !overwrite => return Err(AppError::Conflict("File exists")),
+        _ => {}
+    }
+    Ok(Target {
+        name: name.to_string(),
+        path,
+        file_type,
+        parent,
+        destination,
+    })
+}
+
+/// Check a finished upload's contents, move it into place and add it to the cache
+async fn store(
+    state: &AppState,
+    principal: &Principal,
+    upload: PendingUpload,
+    target: Target,
+) -> Result<FilterResult, AppError> {
+    // Refuse contents the media route would refuse to serve
+    handlers::validate_mime_type(&upload.head, &format!("{}/", target.file_type))?;
+    let size = upload.persist(&target.destination).await?;
+
+    let modified = tokio::fs::metadata(&target.destination)
+        .await
+        .map(|metadata| handlers::modified_secs(&metadata))
+        .unwrap_or(0);
+    let item = FilterResult {
+        path: target.path,
+        name: target.name,
+        size,
+        file_type: target.file_type.to_string(),
+        modified,
+    };
+    state.media_cache.write().await.insert(item.clone());
+    state.events.publish_media(vec![item.clone()]);
+    tracing::info!(username = %principal.username, path = %item.path, size, "File uploaded");
+    Ok(item)
+}
+
+/// Upload a media file
+/// Send the file as the raw request body, or as the first file field of a
+/// `multipart/form-data` body. The file name must have a media extension and its contents
+/// must match it, as for serving. The parent directory must already exist. Needs
+/// `--enable-uploads` and the `admin` or `uploader` role.
+#[utoipa::path(
+    put,
+    path = "/api/upload/{path}",
+    params(
+        ("path" = String, Path, description = "Destination path relative to the media directory"),
+        UploadQuery
+    ),
+    request_body(content = Vec<u8>, description = "File contents, raw or as multipart/form-data", content_type = "application/octet-stream"),
+    responses(
+        (status = 201, description = "File stored", body = FilterResult),
+        (status = 400, description = "Invalid or non-media file name, or upload interrupted"),
+        (status = 403, description = "Path not accessible, or contents don't match the extension"),
+        (status = 404, description = "Parent directory not found, or uploads disabled"),
+        (status = 409, description = "File exists and `overwrite` is not set"),
+        (status = 413, description = "File larger than --max-upload-size")
+    ),
+    tag = "media"
+)]
+pub async fn upload_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+    Query(query): Query<UploadQuery>,
+    request: Request,
+) -> Result<(StatusCode, Json<FilterResult>), AppError> {
+    let limit = state.upload_limit.ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+    let target = target(&state, &principal, &path, query.overwrite).await?;
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit) && !is_multipart(&request) {
+        return Err(AppError::TooLarge);
+    }
+
+    let mut upload = PendingUpload::create(&target.parent, &target.name, limit).await?;
+    if is_multipart(&request) {
+        let mut multipart = Multipart::from_request(request, &state)
+            .await
+            .map_err(|_| AppError::InvalidInput("Invalid multipart body"))?;
+        let mut field = loop {
+            match multipart.next_field().await {
+                Ok(Some(field)) if field.file_name().is_some() => break field,
+                Ok(Some(_)) => continue,
+                Ok(None) => return Err(AppError::InvalidInput("No file in multipart body")),
+                Err(_) => return Err(AppError::InvalidInput("Invalid multipart body")),
+            }
+        };
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|_| AppError::InvalidInput("Upload interrupted"))?
+        {
+            upload.write(&chunk).await?;
+        }
+    } else {
+        let mut body = request.into_body();
+        while let Some(chunk) = next_chunk(&mut body).await? {
+            upload.write(&chunk).await?;
+        }
+    }
+
+    let item = store(&state, &principal, upload, target).await?;
+    Ok((StatusCode::CREATED, Json(item)))
+}
+
+/// A file of a multi-file upload that was not stored
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UploadFailure {
+    /// File name as sent by the client
+    pub name: String,
+    pub error: String,
+}
+
+/// Outcome of a multi-file upload
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UploadBatchResponse {
+    /// Files stored, in the order they were sent
+    pub uploaded: Vec<FilterResult>,
+    pub failed: Vec<UploadFailure>,
+}
+
+/// Upload several media files into a directory
+/// Send the files as the file fields of a `multipart/form-data` body; each is stored under
+/// its own file name and streamed to disk as it arrives. A rejected file doesn't stop the
+/// others. The same rules as for single uploads apply to each file. Needs
+/// `--enable-uploads` and the `admin` or `uploader` role.
+#[utoipa::path(
+    post,
+    path = "/api/upload/{dir}",
+    params(
+        ("dir" = String, Path, description = "Directory relative to the media directory"),
+        UploadQuery
+    ),
+    request_body(content = Vec<u8>, description = "Files as multipart/form-data", content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Files stored and files refused", body = UploadBatchResponse),
+        (status = 400, description = "Not a multipart body, or upload interrupted"),
+        (status = 403, description = "Directory not accessible"),
+        (status = 404, description = "Directory not found, or uploads disabled")
+    ),
+    tag = "media"
+)]
+pub async fn upload_files_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(dir): Path<String>,
+    Query(query): Query<UploadQuery>,
+    request: Request,
+) -> Result<Json<UploadBatchResponse>, AppError> {
+    upload_files(state, principal, &dir, query, request).await
+}
+
+/// Upload several media files into the media directory itself, as `POST /api/upload/{dir}`
+pub async fn upload_root_files_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Query(query): Query<UploadQuery>,
+    request: Request,
+) -> Result<Json<UploadBatchResponse>, AppError> {
+    upload_files(state, principal, "", query, request).await
+}
+
+async fn upload_files(
+    state: AppState,
+    principal: Option<Extension<Principal>>,
+    dir: &str,
+    query: UploadQuery,
+    request: Request,
+) -> Result<Json<UploadBatchResponse>, AppError> {
+    let limit = state.upload_limit.ok_or(AppError::NotFound)?;
+    let Extension(principal) = principal.ok_or(AppError::Forbidden)?;
+    let dir = handlers::media_path(dir)?;
+    if !dir.is_empty() && !state.acl.allows(Some(&principal), &dir) {
+        return Err(AppError::Forbidden);
+    }
+    if !handlers::validate_media_path(&state.media_dir, &dir)?.is_dir() {
+        return Err(AppError::NotFound);
+    }
+    if !is_multipart(&request) {
+        return Err(AppError::InvalidInput("Expected a multipart/form-data body"));
+    }
+    let mut multipart = Multipart::from_request(request, &state)
+        .await
+        .map_err(|_| AppError::InvalidInput("Invalid multipart body"))?;
+
+    let mut response = UploadBatchResponse {
+        uploaded: Vec::new(),
+        failed: Vec::new(),
+    };
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(_) => return Err(AppError::InvalidInput("Upload interrupted")),
+        };
+        let Some(name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        // A name, not a path, so it can't lead into another directory
+        if !files::is_valid_name(&name) {
+            response.failed.push(UploadFailure { name, error: "Invalid file name".to_string() });
+            continue;
+        }
+        let path = if dir.is_empty() { name.clone() } else { format!("{}/{}", dir, name) };
+        let target = match target(&state, &principal, &path, query.overwrite).await {
+            Ok(target) => target,
+            Err(e) => {
+                // The rest of the field is skipped by the next `next_field`
+                response.failed.push(UploadFailure { name, error: e.parts().1.to_string() });
+                continue;
+            }
+        };
+        let mut upload = PendingUpload::create(&target.parent, &target.name, limit).await?;
+        let mut result = Ok(());
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|_| AppError::InvalidInput("Upload interrupted"))?
+        {
+            result = upload.write(&chunk).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        let result = match result {
+            Ok(()) => store(&state, &principal, upload, target).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(item) => response.uploaded.push(item),
+            Err(e) => response.failed.push(UploadFailure { name, error: e.parts().1.to_string() }),
+        }
+    }
+    Ok(Json(response))
+}
+
+fn is_multipart(request: &Request) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_ascii_lowercase().starts_with("multipart/form-data"))
+}
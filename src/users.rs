@@ -0,0 +1,366 @@
+use anyhow::{Context, Result};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::auth::Role;
+use crate::config::UserCommand;
+
+/// A single user account as stored in the users file
+///
+/// ```toml
+/// [[users]]
+/// username = "alice"
+/// password_hash = "$argon2id$v=19$m=19456,t=2,p=1$..."
+/// role = "admin"  # "viewer" (default), "uploader" or "admin"
+/// ```
+///
+/// A plaintext `password` key is also accepted, but only when the server
+/// runs with --insecure-plaintext-password.
+#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct UserEntry {
+    #[zeroize(skip)]
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    #[serde(default)]
+    #[zeroize(skip)]
+    pub role: Role,
+}
+
+/// A verifiable password credential
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub enum Credential {
+    /// Argon2 (PHC string) or bcrypt (`$2b$...`) hash
+    Hash(String),
+    /// Plaintext password (only allowed with --insecure-plaintext-password)
+    Plaintext(String),
+}
+
+/// Stands in for passwords and hashes in debug output, so logging a user never leaks them
+const REDACTED: &str = "<redacted>";
+
+impl fmt::Debug for UserEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserEntry")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("password_hash", &self.password_hash.as_ref().map(|_| REDACTED))
+            .field("role", &self.role)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let variant = match self {
+            Credential::Hash(_) => "Hash",
+            Credential::Plaintext(_) => "Plaintext",
+        };
+        f.debug_tuple(variant).field(&REDACTED).finish()
+    }
+}
+
+impl Credential {
+    /// Build a credential from a stored hash, rejecting unknown formats up front
+    pub fn from_hash(hash: &str) -> Result<Self> {
+        if is_bcrypt_hash(hash) {
+            hash.parse::<bcrypt::HashParts>()
+                .map_err(|e| anyhow::anyhow!("Invalid bcrypt hash: {}", e))?;
+        } else {
+            PasswordHash::new(hash).map_err(|e| anyhow::anyhow!("Invalid argon2 hash: {}", e))?;
+        }
+        Ok(Credential::Hash(hash.to_string()))
+    }
+
+    /// Verify a password against this credential
+    /// Hash verification is CPU-heavy; call from a blocking context
+    pub fn verify(&self, password: &str) -> bool {
+        match self {
+            Credential::Hash(hash) if is_bcrypt_hash(hash) => {
+                bcrypt::verify(password, hash).unwrap_or(false)
+            }
+            Credential::Hash(hash) => match PasswordHash::new(hash) {
+                Ok(parsed) => Argon2::default()
+                    .verify_password(password.as_bytes(), &parsed)
+                    .is_ok(),
+                Err(_) => false,
+            },
+            Credential::Plaintext(expected) => {
+                bool::from(password.as_bytes().ct_eq(expected.as_bytes()))
+            }
+        }
+    }
+
+    /// A credential nobody can match, used for unknown usernames so that
+    /// failed lookups cost the same as a real hash verification
+    pub fn dummy() -> &'static Credential {
+        static DUMMY: OnceLock<Credential> = OnceLock::new();
+        DUMMY.get_or_init(|| {
+            let random: [u8; 32] = rand::random();
+            let hash = hash_password(&base64::Engine::encode(
+                &base64::prelude::BASE64_STANDARD,
+                random,
+            ))
+            .expect("Failed to hash dummy password");
+            Credential::Hash(hash)
+        })
+    }
+}
+
+/// A user as seen by the auth middleware
+#[derive(Debug, Clone)]
+pub struct UserAccount {
+    pub credential: Credential,
+    pub role: Role,
+}
+
+fn is_bcrypt_hash(hash: &str) -> bool {
+    hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$")
+}
+
+/// Hash a password with argon2id using a random salt
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// Contents of the users file (a list of `[[users]]` tables)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UsersFile {
+    #[serde(default)]
+    pub users: Vec<UserEntry>,
+}
+
+impl UsersFile {
+    /// Load and parse a users file from disk
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read users file: {:?}", path))?;
+
+        let users_file: UsersFile = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse users file: {:?}", path))?;
+
+        Ok(users_file)
+    }
+
+    /// Load a users file, or start an empty one if it doesn't exist yet
+    pub fn load_or_default(path: &Path) -> Result<Self> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Write the users file back to disk
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string_pretty(self).context("Failed to serialize users file")?;
+        write_private_file(path, &contents)
+    }
+
+    fn find_mut(&mut self, username: &str) -> Option<&mut UserEntry> {
+        self.users.iter_mut().find(|u| u.username == username)
+    }
+
+    /// Build the username -> account table used by the auth middleware
+    /// Rejects empty usernames, duplicate usernames and (unless allowed) plaintext passwords
+    pub fn into_table(self, allow_plaintext: bool) -> Result<HashMap<String, UserAccount>> {
+        let mut table = HashMap::new();
+
+        for user in self.users.iter() {
+            if user.username.is_empty() {
+                anyhow::bail!("Users file contains an entry with an empty username");
+            }
+
+            let credential = match (&user.password_hash, &user.password) {
+                (Some(hash), _) => Credential::from_hash(hash)
+                    .with_context(|| format!("User '{}' has an invalid password_hash", user.username))?,
+                (None, Some(password)) => {
+                    if !allow_plaintext {
+                        anyhow::bail!(
+                            "User '{}' has a plaintext password; use password_hash or pass --insecure-plaintext-password",
+                            user.username
+                        );
+                    }
+                    if password.is_empty() {
+                        anyhow::bail!("User '{}' has an empty password", user.username);
+                    }
+                    Credential::Plaintext(password.clone())
+                }
+                (None, None) => {
+                    anyhow::bail!("User '{}' has no password_hash", user.username);
+                }
+            };
+
+            let account = UserAccount { credential, role: user.role };
+            if table.insert(user.username.clone(), account).is_some() {
+                anyhow::bail!("Duplicate user '{}' in users file", user.username);
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+/// Write a file containing credentials
+/// Writes to a temporary file first so a crash never leaves a truncated file; the temporary
+/// file is created readable only by its owner, so the credentials are never exposed
+pub fn write_private_file(path: &Path, contents: &str) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("Failed to create a temporary file in {:?}", dir))?;
+    tmp.write_all(contents.as_bytes())
+        .with_context(|| format!("Failed to write {:?}", tmp.path()))?;
+
+    tmp.persist(path)
+        .with_context(|| format!("Failed to replace {:?}", path))?;
+
+    Ok(())
+}
+
+/// Run a `doggygallery user ...` subcommand
+pub fn run_user_command(action: UserCommand) -> Result<()> {
+    match action {
+        UserCommand::Add { username, file, role, password_stdin } => {
+            if username.is_empty() || username.contains(':') {
+                anyhow::bail!("Usernames must be non-empty and cannot contain ':'");
+            }
+
+            let mut users_file = UsersFile::load_or_default(&file.users_file)?;
+            if users_file.find_mut(&username).is_some() {
+                anyhow::bail!("User '{}' already exists in {:?}", username, file.users_file);
+            }
+
+            let password_hash = hash_password(&read_new_password(password_stdin)?)?;
+            users_file.users.push(UserEntry {
+                username: username.clone(),
+                password: None,
+                password_hash: Some(password_hash),
+                role,
+            });
+            users_file.save(&file.users_file)?;
+
+            println!("Added {:?} user '{}'", role, username);
+        }
+        UserCommand::Remove { username, file } => {
+            let mut users_file = UsersFile::load(&file.users_file)?;
+            let before = users_file.users.len();
+            users_file.users.retain(|u| u.username != username);
+
+            if users_file.users.len() == before {
+                anyhow::bail!("User '{}' not found in {:?}", username, file.users_file);
+            }
+            users_file.save(&file.users_file)?;
+
+            println!("Removed user '{}'", username);
+        }
+        UserCommand::List { file } => {
+            let users_file = UsersFile::load(&file.users_file)?;
+            for user in &users_file.users {
+                let storage = if user.password_hash.is_some() { "hashed" } else { "PLAINTEXT" };
+                println!("{}\t{:?}\t{}", user.username, user.role, storage);
+            }
+        }
+        UserCommand::Passwd { username, file, password_stdin } => {
+            let mut users_file = UsersFile::load(&file.users_file)?;
+            if users_file.find_mut(&username).is_none() {
+                anyhow::bail!("User '{}' not found in {:?}", username, file.users_file);
+            }
+
+            let password_hash = hash_password(&read_new_password(password_stdin)?)?;
+            if let Some(user) = users_file.find_mut(&username) {
+                user.password = None;
+                user.password_hash = Some(password_hash);
+            }
+            users_file.save(&file.users_file)?;
+
+            println!("Updated password for '{}'", username);
+        }
+    }
+
+    Ok(())
+}
+
+/// `doggygallery hash-password`: print the argon2 hash of a password
+/// Only the hash goes to stdout, so it can be captured by a script.
+pub fn run_hash_password_command(password_stdin: bool) -> Result<()> {
+    println!("{}", hash_password(&read_new_password(password_stdin)?)?);
+    Ok(())
+}
+
+/// Read a new password, either from stdin or an interactive no-echo prompt
+pub fn read_new_password(from_stdin: bool) -> Result<String> {
+    let password = if from_stdin {
+        let mut line = String::new();
+        std::io::stdin()
+            .read_line(&mut line)
+            .context("Failed to read password from stdin")?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    } else {
+        let password = rpassword::prompt_password("New password: ")
+            .context("Failed to read password")?;
+        let confirm = rpassword::prompt_password("Confirm password: ")
+            .context("Failed to read password")?;
+        if password != confirm {
+            anyhow::bail!("Passwords do not match");
+        }
+        password
+    };
+
+    if password.is_empty() {
+        anyhow::bail!("Password cannot be empty");
+    }
+
+    Ok(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_output_redacts_secrets() {
+        let entry = UserEntry {
+            username: "alice".to_string(),
+            password: Some("hunter2".to_string()),
+            password_hash: Some("$argon2id$v=19$secret".to_string()),
+            role: Role::Admin,
+        };
+        let printed = format!("{:?}", entry);
+        assert!(printed.contains("alice") && printed.contains("Admin"));
+        assert!(!printed.contains("hunter2") && !printed.contains("argon2id"));
+
+        let account = UserAccount { credential: Credential::Plaintext("hunter2".to_string()), role: Role::Viewer };
+        assert!(!format!("{:?}", account).contains("hunter2"));
+        assert!(!format!("{:?}", Credential::Hash("$2b$12$secret".to_string())).contains("secret"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.toml");
+        write_private_file(&path, "first").unwrap();
+        write_private_file(&path, "second").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,273 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Principal;
+use crate::handlers::{self, AppError, AppState};
+use crate::index::MediaIndex;
+
+/// Number of peaks computed and cached per track; requests can ask for fewer
+const WAVEFORM_RESOLUTION: usize = 2000;
+
+/// Peaks returned when a request doesn't ask for a count
+const DEFAULT_POINTS: usize = 1000;
+
+/// Fewest peaks a request can ask for
+const MIN_POINTS: usize = 16;
+
+/// Audio frames folded into one peak while decoding, before the final downsampling
+const CHUNK_FRAMES: usize = 256;
+
+/// Peak levels of a whole track
+#[derive(Debug, Clone)]
+pub struct Waveform {
+    /// Playing time in seconds
+    pub duration: f64,
+    /// Loudest sample of each slice of the track, scaled from 0 (silence) to 255 (full scale)
+    pub peaks: Vec<u8>,
+}
+
+/// Response encoding of a waveform
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WaveformFormat {
+    #[default]
+    Json,
+    /// The peaks as raw bytes, one per peak
+    Binary,
+}
+
+/// Query parameters of the waveform endpoint
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct WaveformQuery {
+    /// Number of peaks to return (16 to 2000, default 1000)
+    pub points: Option<usize>,
+    /// `json` (default) or `binary`
+    pub format: Option<WaveformFormat>,
+}
+
+/// Peak data of an audio file for drawing its waveform
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WaveformResponse {
+    /// Path relative to the media directory
+    pub path: String,
+    /// Playing time in seconds
+    pub duration: f64,
+    /// Loudest sample of each equal slice of the track, from 0 (silence) to 255 (full scale)
+    pub peaks: Vec<u8>,
+}
+
+impl MediaIndex {
+    /// Cached waveform of a file, if it was computed for this version of the file
+    pub async fn waveform(&self, path: String, mtime: u64) -> anyhow::Result<Option<Waveform>> {
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT duration, peaks FROM waveforms WHERE path = ?1 AND mtime = ?2",
+                params![path, mtime as i64],
+                |row| {
+                    Ok(Waveform {
+                        duration: row.get(0)?,
+                        peaks: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// Cache the waveform of a file, replacing the one of an older version
+    pub async fn store_waveform(&self, path: String, mtime: u64, waveform: Waveform) -> anyhow::Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO waveforms (path, mtime, duration, peaks) VALUES (?1, ?2, ?3, ?4)",
+                params![path, mtime as i64, waveform.duration, waveform.peaks],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+}
+
+/// Decode an audio file and measure its peaks, or `None` if it can't be decoded
+/// Undecodable packets are skipped, so a damaged file still gets a waveform.
+fn compute(path: &std::path::Path) -> Option<Waveform> {
+    let file = std::fs::File::open(path).ok()?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?
+        .format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+
+    let mut chunks = Vec::new();
+    let (mut chunk_peak, mut chunk_frames) = (0f32, 0usize);
+    let (mut frames, mut sample_rate) = (0u64, track.codec_params.sample_rate.unwrap_or(0));
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // End of stream, or a chained stream this doesn't follow
+            Err(DecodeError::IoError(_)) | Err(DecodeError::ResetRequired) => break,
+            Err(_) => return None,
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(DecodeError::DecodeError(_)) | Err(DecodeError::IoError(_)) => continue,
+            Err(_) => break,
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        sample_rate = spec.rate;
+        let buffer = match &mut samples {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * channels => buffer,
+            _ => samples.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks(channels) {
+            let peak = frame.iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+            chunk_peak = chunk_peak.max(peak);
+            chunk_frames += 1;
+            if chunk_frames == CHUNK_FRAMES {
+                chunks.push(chunk_peak);
+                (chunk_peak, chunk_frames) = (0.0, 0);
+            }
+        }
+        frames += (buffer.samples().len() / channels) as u64;
+    }
+    if chunk_frames > 0 {
+        chunks.push(chunk_peak);
+    }
+    if frames == 0 || sample_rate == 0 {
+        return None;
+    }
+
+    let peaks = downsample(&chunks, WAVEFORM_RESOLUTION)
+        .into_iter()
+        .map(|peak| (peak.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    Some(Waveform {
+        duration: frames as f64 / f64::from(sample_rate),
+        peaks,
+    })
+}
+
+/// Reduce peaks to at most `points` by keeping the loudest of each equal slice
+fn downsample<T: Copy + PartialOrd>(peaks: &[T], points: usize) -> Vec<T> {
+    if peaks.len() <= points {
+        return peaks.to_vec();
+    }
+    (0..points)
+        .map(|i| {
+            let slice = &peaks[i * peaks.len() / points..(i + 1) * peaks.len() / points];
+            slice
+                .iter()
+                .copied()
+                .fold(slice[0], |loudest, peak| if peak > loudest { peak } else { loudest })
+        })
+        .collect()
+}
+
+/// Get the waveform of an audio file
+/// The file is decoded on the first request. With `--index-db` the peaks are cached until
+/// the file changes; without it every request decodes the file again.
+#[utoipa::path(
+    get,
+    path = "/api/waveform/{path}",
+    params(
+        ("path" = String, Path, description = "Audio file path relative to the media directory"),
+        WaveformQuery
+    ),
+    responses(
+        (status = 200, description = "Peak levels, as JSON or raw bytes with `format=binary`", body = WaveformResponse),
+        (status = 400, description = "Audio could not be decoded"),
+        (status = 403, description = "Path not accessible"),
+        (status = 404, description = "Audio file not found")
+    ),
+    tag = "media"
+)]
+pub async fn waveform_handler(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(path): Path<String>,
+    Query(query): Query<WaveformQuery>,
+) -> Result<Response, AppError> {
+    let path = handlers::media_path(&path)?;
+    let canonical = handlers::validate_media_path(&state.media_dir, &path)?;
+    if !canonical.is_file() || handlers::media_type(&path) != Some("audio") {
+        return Err(AppError::NotFound);
+    }
+    if !state.acl.allows(principal.as_deref(), &path) {
+        return Err(AppError::Forbidden);
+    }
+    let mtime = tokio::fs::metadata(&canonical)
+        .await
+        .map(|metadata| handlers::modified_secs(&metadata))
+        .map_err(|_| AppError::NotFound)?;
+
+    let cached = match &state.index {
+        Some(index) => index.waveform(path.clone(), mtime).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to read cached waveform: {:?}", e);
+            None
+        }),
+        None => None,
+    };
+    let waveform = match cached {
+        Some(waveform) => waveform,
+        None => {
+            let waveform = state.workers.run(move || compute(&canonical))
+                .await
+                .map_err(|_| AppError::InternalError)?
+                .ok_or(AppError::InvalidInput("Audio could not be decoded"))?;
+            if let Some(index) = &state.index {
+                if let Err(e) = index.store_waveform(path.clone(), mtime, waveform.clone()).await {
+                    tracing::warn!("Failed to cache waveform: {:?}", e);
+                }
+            }
+            waveform
+        }
+    };
+
+    let points = query
+        .points
+        .unwrap_or(DEFAULT_POINTS)
+        .clamp(MIN_POINTS, WAVEFORM_RESOLUTION);
+    let peaks = downsample(&waveform.peaks, points);
+    Ok(match query.format.unwrap_or_default() {
+        WaveformFormat::Json => Json(WaveformResponse {
+            path,
+            duration: waveform.duration,
+            peaks,
+        })
+        .into_response(),
+        WaveformFormat::Binary => ([(header::CONTENT_TYPE, "application/octet-stream")], peaks).into_response(),
+    })
+}
@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinError;
+
+/// Shared pool for CPU-heavy work such as thumbnailing, hashing and archive extraction
+/// Jobs run on the blocking thread pool, at most `--workers` at a time; the rest wait
+/// their turn, so a burst of requests can't take every core or starve the async runtime.
+#[derive(Clone)]
+pub struct Workers {
+    permits: Arc<Semaphore>,
+}
+
+impl Workers {
+    pub fn new(count: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(count.max(1))),
+        }
+    }
+
+    /// Run `job` once a worker is free
+    /// The worker stays taken until the job is done, even if the caller stops waiting for it.
+    pub async fn run<T, F>(&self, job: F) -> Result<T, JoinError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("worker pool is never closed");
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job()
+        })
+        .await
+    }
+
+    /// Keep a worker taken while `job` runs, for work done by a helper process
+    pub async fn hold<F: Future>(&self, job: F) -> F::Output {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("worker pool is never closed");
+        job.await
+    }
+}
+
+impl Default for Workers {
+    /// One worker per CPU core
+    fn default() -> Self {
+        Self::new(std::thread::available_parallelism().map_or(1, usize::from))
+    }
+}
@@ -8,6 +8,8 @@
     let touchEndX = 0;
     let randomTimerInterval = null;
     let randomTimerSeconds = 5;
+    let savePositions = true;
+    const POSITION_SAVE_INTERVAL = 10;
 
     /**
      * Initialize the lightbox with media items
@@ -48,6 +50,277 @@
         }
     }
 
+    /**
+     * Open lightbox with a video or track and start playing from a position
+     * Media that isn't in the list (e.g. from the "Continue watching" strip) is added to it.
+     * @param {string} src - Media source URL
+     * @param {string} type - Media type (video or audio)
+     * @param {number} start - Position in seconds
+     */
+    function openLightboxAt(src, type, start) {
+        if (!mediaItems.some(item => item.src === src)) {
+            mediaItems.push({ src: src, type: type });
+        }
+        openLightbox(src, type);
+
+        const content = document.getElementById('lightbox-content');
+        const player = content && content.querySelector('video, audio');
+        if (player && start > 0) {
+            player.addEventListener('loadedmetadata', () => {
+                player.currentTime = start;
+            }, { once: true });
+        }
+    }
+
+    /**
+     * Save the playback position of a video or track to the view history
+     * Positions are sent every few seconds while playing and on pause; saving stops for the
+     * rest of the page if the server has no history (anonymous or no index database).
+     * @param {HTMLMediaElement} player - Video or audio element
+     * @param {string} src - Media source URL
+     */
+    function trackPlaybackPosition(player, src) {
+        if (!src.startsWith('/media/')) return;
+        const path = src.substring('/media/'.length);
+        let lastSaved = 0;
+
+        function save() {
+            if (!savePositions || !isFinite(player.duration) || player.duration <= 0) return;
+            lastSaved = player.currentTime;
+            fetch('/api/history/position/' + path, {
+                method: 'PUT',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ position: player.currentTime, duration: player.duration })
+            }).then(response => {
+                if (response.status === 403 || response.status === 404) {
+                    savePositions = false;
+                }
+            }).catch(() => {});
+        }
+
+        player.addEventListener('timeupdate', () => {
+            if (Math.abs(player.currentTime - lastSaved) >= POSITION_SAVE_INTERVAL) {
+                save();
+            }
+        });
+        player.addEventListener('pause', save);
+        player.addEventListener('ended', save);
+    }
+
+    /**
+     * Look up the ReplayGain volume factor of a track
+     * Album gain is preferred so tracks keep their relative loudness within an album. The
+     * factor is capped at 1, since media elements can't amplify, and kept low enough that
+     * the tagged peak doesn't clip.
+     * @param {string} src - Audio source URL
+     * @returns {Promise<number>} Volume multiplier, 1 if the track has no gain tags
+     */
+    async function fetchReplayGain(src) {
+        if (!src.startsWith('/media/')) return 1;
+        try {
+            const response = await fetch('/api/track/' + src.substring('/media/'.length));
+            if (!response.ok) return 1;
+            const gain = (await response.json()).replaygain;
+            const db = gain.album_gain ?? gain.track_gain;
+            if (db === null || db === undefined) return 1;
+            const peak = (gain.album_gain !== null ? gain.album_peak : gain.track_peak) || 0;
+            let factor = Math.pow(10, db / 20);
+            if (peak > 0) {
+                factor = Math.min(factor, 1 / peak);
+            }
+            return Math.min(factor, 1);
+        } catch (e) {
+            return 1;
+        }
+    }
+
+    /**
+     * Attach the subtitle files stored next to a video as <track> elements
+     * They appear in the browser's captions menu; none is turned on by default.
+     * @param {HTMLVideoElement} video - Video element
+     * @param {string} src - Video source URL
+     */
+    async function addSubtitleTracks(video, src) {
+        if (!src.startsWith('/media/')) return;
+        try {
+            const response = await fetch('/api/subtitles/' + src.substring('/media/'.length).split('?')[0]);
+            if (!response.ok) return;
+            for (const subtitle of await response.json()) {
+                const track = document.createElement('track');
+                track.kind = 'subtitles';
+                track.src = subtitle.url;
+                track.label = subtitle.label;
+                if (subtitle.language) {
+                    track.srclang = subtitle.language;
+                }
+                video.appendChild(track);
+            }
+        } catch (e) {
+            // Subtitles are optional
+        }
+    }
+
+    /**
+     * Add a chapter menu below a video, if the index knows its chapters
+     * Chapters come from /api/metadata, which has them once ffprobe has read the video.
+     * @param {HTMLVideoElement} video - Video element
+     * @param {string} src - Video source URL
+     * @param {HTMLElement} container - Container to add the menu to
+     */
+    async function addChapterMenu(video, src, container) {
+        if (!src.startsWith('/media/')) return;
+        let chapters;
+        try {
+            const response = await fetch('/api/metadata/' + src.substring('/media/'.length).split('?')[0]);
+            if (!response.ok) return;
+            chapters = (await response.json()).chapters || [];
+        } catch (e) {
+            return;
+        }
+        if (chapters.length < 2 || !video.isConnected) return;
+
+        const menu = document.createElement('select');
+        menu.className = 'chapter-select';
+        menu.title = 'Chapters';
+        chapters.forEach((chapter, i) => {
+            const option = document.createElement('option');
+            option.value = chapter.start;
+            option.textContent = chapter.title || `Chapter ${i + 1}`;
+            menu.appendChild(option);
+        });
+        menu.addEventListener('click', (e) => e.stopPropagation());
+        menu.addEventListener('change', () => {
+            video.currentTime = Number(menu.value);
+        });
+        // Follow playback so the menu shows the current chapter
+        video.addEventListener('timeupdate', () => {
+            const current = chapters.findLastIndex(chapter => chapter.start <= video.currentTime);
+            if (current >= 0 && menu.selectedIndex !== current) {
+                menu.selectedIndex = current;
+            }
+        });
+        container.appendChild(menu);
+    }
+
+    /**
+     * Add a Cast button below a video, for playing it on a Chromecast or AirPlay receiver
+     * Receivers fetch the video themselves and can't log in, so casting first switches the
+     * player to a cast session URL, which works without credentials, then opens the
+     * browser's device picker.
+     * @param {HTMLVideoElement} video - Video element
+     * @param {string} src - Video source URL
+     * @param {HTMLElement} container - Container to add the button to
+     */
+    function addCastButton(video, src, container) {
+        if (!src.startsWith('/media/')) return;
+        const airplay = typeof video.webkitShowPlaybackTargetPicker === 'function';
+        if (!video.remote && !airplay) return;
+
+        const button = document.createElement('button');
+        button.className = 'cast-button';
+        button.textContent = '📺 Cast';
+        button.title = 'Play on a Chromecast or AirPlay device';
+        let castUrl = null;
+        button.addEventListener('click', async (e) => {
+            e.stopPropagation();
+            if (!castUrl) {
+                try {
+                    const response = await fetch('/api/cast/sessions', {
+                        method: 'POST',
+                        headers: { 'Content-Type': 'application/json' },
+                        body: JSON.stringify({ path: decodeURIComponent(src.substring('/media/'.length).split('?')[0]) })
+                    });
+                    if (!response.ok) throw new Error('HTTP ' + response.status);
+                    castUrl = (await response.json()).url;
+                } catch (err) {
+                    button.textContent = '📺 Cast unavailable';
+                    button.disabled = true;
+                    return;
+                }
+                // Keep the position while the player switches to the cast URL
+                const position = video.currentTime;
+                const playing = !video.paused;
+                const source = video.querySelector('source');
+                if (source) {
+                    source.src = castUrl;
+                } else {
+                    video.src = castUrl;
+                }
+                video.load();
+                video.addEventListener('loadedmetadata', () => {
+                    video.currentTime = position;
+                    if (playing) video.play().catch(() => {});
+                }, { once: true });
+            }
+            try {
+                if (airplay) {
+                    video.webkitShowPlaybackTargetPicker();
+                } else {
+                    await video.remote.prompt();
+                }
+            } catch (err) {
+                // Picker dismissed, or no devices found
+            }
+        });
+        container.appendChild(button);
+    }
+
+    /**
+     * Draw a track's waveform into a canvas and make it seek on click
+     * The canvas stays hidden if the server can't provide a waveform, such as for tracks
+     * inside archives.
+     * @param {string} src - Audio source URL
+     * @param {HTMLCanvasElement} canvas - Canvas to draw into
+     * @param {HTMLAudioElement} audio - Player to follow and seek
+     */
+    async function loadWaveform(src, canvas, audio) {
+        if (!src.startsWith('/media/')) return;
+        const scale = window.devicePixelRatio || 1;
+        // The canvas is hidden until drawn, so size it after its container
+        const width = Math.round((canvas.parentElement ? canvas.parentElement.clientWidth : 0) * scale) || 440;
+        const barWidth = Math.max(2, Math.round(3 * scale));
+        let peaks;
+        try {
+            const path = src.substring('/media/'.length).split('?')[0];
+            const points = Math.max(16, Math.floor(width / barWidth));
+            const response = await fetch('/api/waveform/' + path + '?points=' + points);
+            if (!response.ok) return;
+            peaks = (await response.json()).peaks;
+        } catch (e) {
+            return;
+        }
+        if (!peaks.length) return;
+
+        canvas.width = width;
+        canvas.height = Math.round(64 * scale);
+        canvas.style.display = 'block';
+        const context = canvas.getContext('2d');
+        const loudest = Math.max(...peaks, 1);
+
+        const draw = () => {
+            const played = audio.duration ? audio.currentTime / audio.duration : 0;
+            const step = canvas.width / peaks.length;
+            context.clearRect(0, 0, canvas.width, canvas.height);
+            peaks.forEach((peak, i) => {
+                const height = Math.max(1, (peak / loudest) * canvas.height);
+                context.fillStyle = i / peaks.length < played
+                    ? 'rgba(255, 255, 255, 0.95)'
+                    : 'rgba(255, 255, 255, 0.35)';
+                context.fillRect(i * step, (canvas.height - height) / 2, Math.max(1, step - scale), height);
+            });
+        };
+        draw();
+        audio.addEventListener('timeupdate', draw);
+        audio.addEventListener('seeked', draw);
+
+        canvas.addEventListener('click', (e) => {
+            e.stopPropagation();
+            if (!audio.duration) return;
+            const rect = canvas.getBoundingClientRect();
+            audio.currentTime = ((e.clientX - rect.left) / rect.width) * audio.duration;
+        });
+    }
+
     /**
      * Display media at given index
      * @param {number} index - Index of media to display
@@ -78,6 +351,12 @@
             source.src = item.src;
             video.appendChild(source);
             content.appendChild(video);
+            trackPlaybackPosition(video, item.src);
+            addSubtitleTracks(video, item.src);
+            addChapterMenu(video, item.src, content);
+            if (document.getElementById('lightbox').hasAttribute('data-casting')) {
+                addCastButton(video, item.src, content);
+            }
         } else if (item.type === 'audio') {
             createEnhancedAudioPlayer(item.src, content);
         }
@@ -271,21 +550,23 @@
         // Get track name from URL
         const trackName = decodeURIComponent(src.split('/').pop().split('?')[0]);
 
-        // Get directory path for album art search
-        const pathParts = src.split('/');
-        const dirPath = pathParts.slice(0, -1).join('/');
-
         // Create album art container
         const albumArtContainer = document.createElement('div');
         albumArtContainer.className = 'album-art-container';
 
         const albumArt = document.createElement('img');
         albumArt.className = 'album-art';
-        albumArt.src = '/static/img/default-album-art.png';
         albumArt.alt = 'Album Art';
 
-        // Try to find album art in the same directory
-        tryLoadAlbumArt(dirPath, albumArt);
+        // Embedded art, or a cover image next to the track
+        albumArt.onerror = () => {
+            albumArt.onerror = null;
+            albumArt.src = '/static/img/default-album-art.png';
+        };
+        const mediaPrefix = /^\/media(-archive)?\//;
+        albumArt.src = mediaPrefix.test(src)
+            ? src.replace(mediaPrefix, '/album-art/') + '?size=512'
+            : '/static/img/default-album-art.png';
 
         albumArtContainer.appendChild(albumArt);
 
@@ -300,11 +581,24 @@
         audio.src = src;
         audio.autoplay = true;
         audio.preload = 'metadata'; // Enable seeking by preloading metadata
+        trackPlaybackPosition(audio, src);
+
+        // Volume is the slider setting scaled by the track's ReplayGain
+        let gainFactor = 1;
+        fetchReplayGain(src).then(factor => {
+            gainFactor = factor;
+            audio.volume = (volumeSlider.value / 100) * gainFactor;
+        });
 
         // Create controls container
         const controlsContainer = document.createElement('div');
         controlsContainer.className = 'audio-controls';
 
+        // Waveform, shown once its peaks have loaded; clicking it seeks
+        const waveform = document.createElement('canvas');
+        waveform.className = 'waveform';
+        waveform.style.display = 'none';
+
         // Time display
         const timeDisplay = document.createElement('div');
         timeDisplay.className = 'time-display';
@@ -374,7 +668,7 @@
         });
 
         volumeSlider.addEventListener('input', () => {
-            audio.volume = volumeSlider.value / 100;
+            audio.volume = (volumeSlider.value / 100) * gainFactor;
             updateVolumeIcon();
         });
 
@@ -392,7 +686,7 @@
         }
 
         function updateVolumeIcon() {
-            const volume = audio.volume;
+            const volume = volumeSlider.value / 100;
             if (volume === 0) {
                 volumeIcon.textContent = '🔇';
             } else if (volume < 0.5) {
@@ -411,6 +705,7 @@
         playbackControls.appendChild(playPauseBtn);
         playbackControls.appendChild(volumeContainer);
 
+        controlsContainer.appendChild(waveform);
         controlsContainer.appendChild(timeDisplay);
         controlsContainer.appendChild(seekSlider);
         controlsContainer.appendChild(playbackControls);
@@ -422,60 +717,18 @@
         playerContainer.appendChild(controlsContainer);
 
         container.appendChild(playerContainer);
+        loadWaveform(src, waveform, audio);
 
         // Store reference for ended event handling
         container.audioElement = audio;
     }
 
-    /**
-     * Try to load album art from the same directory
-     * @param {string} dirPath - Directory path
-     * @param {HTMLImageElement} imgElement - Image element to update
-     */
-    function tryLoadAlbumArt(dirPath, imgElement) {
-        const commonNames = ['cover.jpg', 'cover.png', 'folder.jpg', 'folder.png', 'album.jpg', 'album.png'];
-
-        let found = false;
-
-        // Check if this is an archive path
-        const isArchivePath = dirPath.includes('!/');
-
-        async function tryNext(index) {
-            if (index >= commonNames.length || found) return;
-
-            let artPath;
-            if (isArchivePath) {
-                // For archive paths, replace the filename after !/ with cover image name
-                const parts = dirPath.split('!/');
-                const archivePath = parts[0];
-                const internalDir = parts[1].substring(0, parts[1].lastIndexOf('/'));
-                artPath = `${archivePath}!/${internalDir}/${commonNames[index]}`;
-            } else {
-                artPath = `${dirPath}/${commonNames[index]}`;
-            }
-
-            // Try to load the image
-            const testImg = new Image();
-            testImg.onload = () => {
-                if (!found) {
-                    found = true;
-                    imgElement.src = artPath;
-                }
-            };
-            testImg.onerror = () => {
-                tryNext(index + 1);
-            };
-            testImg.src = artPath;
-        }
-
-        tryNext(0);
-    }
-
     // Export functions (only if not already defined)
     if (!window.DoggyLightbox) {
         window.DoggyLightbox = {
             init: initLightbox,
             open: openLightbox,
+            openAt: openLightboxAt,
             close: closeLightbox,
             next: nextMedia,
             prev: prevMedia,
@@ -0,0 +1,233 @@
+// Map of geotagged photos, drawn on a canvas in an equirectangular projection
+(function() {
+    'use strict';
+
+    const MIN_SCALE_FACTOR = 1;
+    const MAX_SCALE_FACTOR = 100000;
+    const GRID = 24;
+
+    const canvas = document.getElementById('map');
+    if (!canvas) return;
+    const context = canvas.getContext('2d');
+    const status = document.getElementById('map-status');
+
+    // Center of the view in degrees, and pixels per degree
+    let view = { longitude: 0, latitude: 20, scale: 1 };
+    let clusters = [];
+    let request = null;
+    let fetchTimer = null;
+
+    function minScale() {
+        return Math.max(canvas.width / 360, canvas.height / 180) * MIN_SCALE_FACTOR;
+    }
+
+    function clampView() {
+        view.scale = Math.min(Math.max(view.scale, minScale()), minScale() * MAX_SCALE_FACTOR);
+        const halfWidth = canvas.width / 2 / view.scale;
+        const halfHeight = canvas.height / 2 / view.scale;
+        view.longitude = Math.min(Math.max(view.longitude, -180 + halfWidth), 180 - halfWidth);
+        view.latitude = Math.min(Math.max(view.latitude, -90 + halfHeight), 90 - halfHeight);
+    }
+
+    function toPixel(longitude, latitude) {
+        return {
+            x: canvas.width / 2 + (longitude - view.longitude) * view.scale,
+            y: canvas.height / 2 - (latitude - view.latitude) * view.scale
+        };
+    }
+
+    function toDegrees(x, y) {
+        return {
+            longitude: view.longitude + (x - canvas.width / 2) / view.scale,
+            latitude: view.latitude - (y - canvas.height / 2) / view.scale
+        };
+    }
+
+    function bounds() {
+        const topLeft = toDegrees(0, 0);
+        const bottomRight = toDegrees(canvas.width, canvas.height);
+        const clamp = (value, limit) => Math.min(Math.max(value, -limit), limit);
+        return [
+            clamp(topLeft.longitude, 180),
+            clamp(bottomRight.latitude, 90),
+            clamp(bottomRight.longitude, 180),
+            clamp(topLeft.latitude, 90)
+        ];
+    }
+
+    function radius(cluster) {
+        return cluster.count === 1 ? 7 : Math.min(10 + Math.log2(cluster.count) * 4, 40);
+    }
+
+    function drawGraticule() {
+        const [west, south, east, north] = bounds();
+        const span = Math.max(east - west, north - south);
+        const step = [90, 45, 30, 10, 5, 2, 1, 0.5, 0.1, 0.05, 0.01].find(s => span / s <= 12) || 30;
+
+        context.strokeStyle = 'rgba(255, 255, 255, 0.5)';
+        context.fillStyle = 'rgba(0, 0, 0, 0.55)';
+        context.font = '11px sans-serif';
+        context.lineWidth = 1;
+        for (let longitude = Math.ceil(west / step) * step; longitude <= east; longitude += step) {
+            const { x } = toPixel(longitude, 0);
+            context.beginPath();
+            context.moveTo(x, 0);
+            context.lineTo(x, canvas.height);
+            context.stroke();
+            context.fillText(longitude.toFixed(step < 1 ? 2 : 0) + '°', x + 3, canvas.height - 4);
+        }
+        for (let latitude = Math.ceil(south / step) * step; latitude <= north; latitude += step) {
+            const { y } = toPixel(0, latitude);
+            context.beginPath();
+            context.moveTo(0, y);
+            context.lineTo(canvas.width, y);
+            context.stroke();
+            context.fillText(latitude.toFixed(step < 1 ? 2 : 0) + '°', 3, y - 3);
+        }
+    }
+
+    function draw() {
+        context.clearRect(0, 0, canvas.width, canvas.height);
+        drawGraticule();
+        clusters.forEach(cluster => {
+            const { x, y } = toPixel(cluster.longitude, cluster.latitude);
+            context.beginPath();
+            context.arc(x, y, radius(cluster), 0, 2 * Math.PI);
+            context.fillStyle = cluster.count === 1 ? '#f59e0b' : '#667eea';
+            context.fill();
+            context.strokeStyle = 'white';
+            context.lineWidth = 2;
+            context.stroke();
+            if (cluster.count > 1) {
+                context.fillStyle = 'white';
+                context.font = 'bold 12px sans-serif';
+                context.textAlign = 'center';
+                context.textBaseline = 'middle';
+                context.fillText(cluster.count, x, y);
+                context.textAlign = 'start';
+                context.textBaseline = 'alphabetic';
+            }
+        });
+    }
+
+    async function load() {
+        if (request) request.abort();
+        request = new AbortController();
+        const grid = Math.max(4, Math.round(GRID * canvas.width / 1000));
+        try {
+            const response = await fetch(`/api/geo?bbox=${bounds().join(',')}&grid=${grid}`, { signal: request.signal });
+            if (!response.ok) {
+                status.textContent = 'Could not load photo positions';
+                return;
+            }
+            const data = await response.json();
+            clusters = data.clusters;
+            status.textContent = `${data.total} geotagged photo${data.total === 1 ? '' : 's'} in view`;
+            draw();
+        } catch (e) {
+            if (e.name !== 'AbortError') status.textContent = 'Could not load photo positions';
+        }
+    }
+
+    function update() {
+        clampView();
+        draw();
+        clearTimeout(fetchTimer);
+        fetchTimer = setTimeout(load, 150);
+    }
+
+    function zoom(factor, x = canvas.width / 2, y = canvas.height / 2) {
+        const before = toDegrees(x, y);
+        view.scale *= factor;
+        clampView();
+        const after = toDegrees(x, y);
+        view.longitude += before.longitude - after.longitude;
+        view.latitude += before.latitude - after.latitude;
+        update();
+    }
+
+    function zoomTo([west, south, east, north]) {
+        view.longitude = (west + east) / 2;
+        view.latitude = (south + north) / 2;
+        const width = Math.max(east - west, 0.0005) * 1.5;
+        const height = Math.max(north - south, 0.0005) * 1.5;
+        view.scale = Math.min(canvas.width / width, canvas.height / height);
+        update();
+    }
+
+    function resize() {
+        const rect = canvas.getBoundingClientRect();
+        canvas.width = Math.round(rect.width);
+        canvas.height = Math.round(rect.height);
+        update();
+    }
+
+    function clusterAt(x, y) {
+        // Small markers are drawn last, so search from the end
+        for (let i = clusters.length - 1; i >= 0; i--) {
+            const point = toPixel(clusters[i].longitude, clusters[i].latitude);
+            if (Math.hypot(point.x - x, point.y - y) <= radius(clusters[i])) return clusters[i];
+        }
+        return null;
+    }
+
+    function openPhoto(path) {
+        const photos = clusters.filter(c => c.count === 1).map(c => ({ src: '/media/' + c.path, type: 'image' }));
+        if (!photos.some(photo => photo.src === '/media/' + path)) {
+            photos.push({ src: '/media/' + path, type: 'image' });
+        }
+        DoggyLightbox.init(photos);
+        DoggyLightbox.open('/media/' + path, 'image');
+    }
+
+    let drag = null;
+    canvas.addEventListener('pointerdown', e => {
+        drag = { x: e.offsetX, y: e.offsetY, moved: false };
+        canvas.setPointerCapture(e.pointerId);
+    });
+    canvas.addEventListener('pointermove', e => {
+        if (!drag) return;
+        const dx = e.offsetX - drag.x;
+        const dy = e.offsetY - drag.y;
+        if (Math.abs(dx) + Math.abs(dy) > 3) drag.moved = true;
+        if (drag.moved) {
+            view.longitude -= dx / view.scale;
+            view.latitude += dy / view.scale;
+            drag.x = e.offsetX;
+            drag.y = e.offsetY;
+            canvas.style.cursor = 'grabbing';
+            update();
+        }
+    });
+    canvas.addEventListener('pointerup', e => {
+        const wasDrag = drag && drag.moved;
+        drag = null;
+        canvas.style.cursor = '';
+        if (wasDrag) return;
+        const cluster = clusterAt(e.offsetX, e.offsetY);
+        if (!cluster) return;
+        const [west, south, east, north] = cluster.bbox;
+        // Photos taken at the same spot never split up, so open the newest one
+        if (cluster.count === 1 || (east - west < 1e-5 && north - south < 1e-5)) {
+            openPhoto(cluster.path);
+        } else {
+            zoomTo(cluster.bbox);
+        }
+    });
+    canvas.addEventListener('wheel', e => {
+        e.preventDefault();
+        zoom(e.deltaY < 0 ? 1.25 : 0.8, e.offsetX, e.offsetY);
+    }, { passive: false });
+
+    document.getElementById('map-zoom-in').addEventListener('click', () => zoom(2));
+    document.getElementById('map-zoom-out').addEventListener('click', () => zoom(0.5));
+    document.getElementById('map-reset').addEventListener('click', () => {
+        view = { longitude: 0, latitude: 20, scale: minScale() };
+        update();
+    });
+    window.addEventListener('resize', resize);
+
+    resize();
+    view.scale = minScale();
+    update();
+})();
@@ -1,5 +1,7 @@
 // Music Player module for DoggyGallery
 // Adds playlist features: auto-advance, shuffle mode, play all functionality
+// When signed in with an index database, Play All builds a server-side queue so playback
+// can resume after a reload or on another device.
 (function() {
     'use strict';
 
@@ -8,6 +10,10 @@
     let shuffleMode = false;
     let playAllMode = false;
     let originalPlaylist = [];
+    let serverQueue = false;
+    let queueActive = false;
+    let lastQueueSave = 0;
+    const QUEUE_SAVE_INTERVAL = 10;
 
     /**
      * Initialize the music player with audio items
@@ -19,7 +25,123 @@
         currentIndex = 0;
 
         // Add event listener for audio ended event to auto-advance
-        document.addEventListener('DOMContentLoaded', attachAudioEndedListener);
+        if (document.readyState === 'loading') {
+            document.addEventListener('DOMContentLoaded', attachAudioEndedListener);
+        } else {
+            attachAudioEndedListener();
+        }
+
+        checkServerQueue();
+    }
+
+    /**
+     * Check whether the server keeps a play queue, and offer to resume it
+     */
+    async function checkServerQueue() {
+        const response = await fetch('/api/queue');
+        if (!response.ok) return;
+        serverQueue = true;
+
+        const queue = await response.json();
+        const button = document.getElementById('queue-resume');
+        if (!button || queue.current === null) return;
+        const track = queue.tracks[queue.current];
+        button.textContent = `⏯️ Resume ${track.name}`;
+        button.hidden = false;
+        button.onclick = resumeQueue;
+    }
+
+    /**
+     * Take over the track order and current track of a server queue
+     * @param {Object} queue - Queue returned by the API
+     */
+    function applyQueue(queue) {
+        playlist = queue.tracks.map(track => ({ src: '/media/' + track.path, type: 'audio' }));
+        currentIndex = queue.current === null ? 0 : queue.current;
+        shuffleMode = queue.shuffled;
+        queueActive = true;
+        updateShuffleButton();
+    }
+
+    /**
+     * Replace the server queue with the tracks on this page
+     * Archive tracks can't be queued, so pages of archives keep a local queue.
+     * @param {boolean} shuffle - Shuffle the queue
+     * @param {number} start - Index of the first track
+     * @returns {Promise<boolean>} Whether the server queue is now in use
+     */
+    async function startServerQueue(shuffle, start) {
+        if (!serverQueue || !originalPlaylist.every(item => item.src.startsWith('/media/'))) {
+            return false;
+        }
+        const response = await fetch('/api/queue', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                tracks: originalPlaylist.map(item => item.src.substring('/media/'.length)),
+                shuffle: shuffle,
+                start: start
+            })
+        });
+        if (!response.ok) return false;
+        applyQueue(await response.json());
+        return true;
+    }
+
+    /**
+     * Save the current track and position of the server queue
+     * @param {number} position - Seconds into the current track
+     */
+    function saveQueuePosition(position) {
+        if (!queueActive) return;
+        lastQueueSave = position;
+        fetch('/api/queue/position', {
+            method: 'PUT',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ current: currentIndex, position: position })
+        }).catch(() => {});
+    }
+
+    /**
+     * Continue the server queue where it was left off, possibly on another device
+     */
+    async function resumeQueue() {
+        const response = await fetch('/api/queue');
+        if (!response.ok) return;
+        const queue = await response.json();
+        if (queue.current === null) return;
+
+        applyQueue(queue);
+        playAllMode = true;
+        if (window.DoggyLightbox) {
+            window.DoggyLightbox.openAt(playlist[currentIndex].src, 'audio', queue.position);
+        }
+    }
+
+    /**
+     * Open a track in the lightbox, adding it to the lightbox items if it isn't on this page
+     * @param {Object} track - {src: string, type: string}
+     */
+    function openTrack(track) {
+        if (track && window.DoggyLightbox) {
+            window.DoggyLightbox.openAt(track.src, track.type, 0);
+        }
+    }
+
+    /**
+     * Listen for a track ending, and save its position while it plays
+     * @param {HTMLAudioElement} audio - Audio element
+     */
+    function watchAudio(audio) {
+        audio.addEventListener('ended', handleAudioEnded);
+        audio.addEventListener('timeupdate', () => {
+            if (Math.abs(audio.currentTime - lastQueueSave) >= QUEUE_SAVE_INTERVAL) {
+                saveQueuePosition(audio.currentTime);
+            }
+        });
+        audio.addEventListener('pause', () => {
+            if (!audio.ended) saveQueuePosition(audio.currentTime);
+        });
     }
 
     /**
@@ -30,14 +152,12 @@
             mutations.forEach(function(mutation) {
                 mutation.addedNodes.forEach(function(node) {
                     if (node.tagName === 'AUDIO' || node.classList.contains('audio-element')) {
-                        node.addEventListener('ended', handleAudioEnded);
+                        watchAudio(node);
                     }
                     // Also check for audio elements within added nodes
                     if (node.querySelector) {
                         const audioElements = node.querySelectorAll('audio, .audio-element');
-                        audioElements.forEach(function(audio) {
-                            audio.addEventListener('ended', handleAudioEnded);
-                        });
+                        audioElements.forEach(watchAudio);
                     }
                 });
             });
@@ -51,7 +171,7 @@
         // Also attach to any existing audio element
         const audio = document.querySelector('#lightbox-content audio, #lightbox-content .audio-element');
         if (audio) {
-            audio.addEventListener('ended', handleAudioEnded);
+            watchAudio(audio);
         }
     }
 
@@ -61,7 +181,11 @@
     function handleAudioEnded() {
         if (!playAllMode) return;
 
-        if (shuffleMode) {
+        if (queueActive) {
+            // The server queue is already in playback order, shuffled or not
+            currentIndex = (currentIndex + 1) % playlist.length;
+            saveQueuePosition(0);
+        } else if (shuffleMode) {
             // In shuffle mode, pick random next track
             if (playlist.length <= 1) return;
 
@@ -80,54 +204,79 @@
         }
 
         // Play next track
-        const nextTrack = playlist[currentIndex];
-        if (nextTrack && window.DoggyLightbox) {
-            window.DoggyLightbox.open(nextTrack.src, nextTrack.type);
-        }
+        openTrack(playlist[currentIndex]);
     }
 
     /**
      * Play all tracks in random order
      */
-    function playAllRandom() {
-        if (playlist.length === 0) return;
+    async function playAllRandom() {
+        if (originalPlaylist.length === 0) return;
 
         playAllMode = true;
+        const start = Math.floor(Math.random() * originalPlaylist.length);
+        if (await startServerQueue(true, start)) {
+            openTrack(playlist[currentIndex]);
+            return;
+        }
+
+        playlist = [...originalPlaylist];
+        queueActive = false;
         shuffleMode = true;
         updateShuffleButton();
 
         // Start with a random track
-        currentIndex = Math.floor(Math.random() * playlist.length);
-        const track = playlist[currentIndex];
-
-        if (track && window.DoggyLightbox) {
-            window.DoggyLightbox.open(track.src, track.type);
-        }
+        currentIndex = start;
+        openTrack(playlist[currentIndex]);
     }
 
     /**
      * Play all tracks sequentially
      */
-    function playAllSequential() {
-        if (playlist.length === 0) return;
+    async function playAllSequential() {
+        if (originalPlaylist.length === 0) return;
 
         playAllMode = true;
+        if (await startServerQueue(false, 0)) {
+            openTrack(playlist[currentIndex]);
+            return;
+        }
+
+        playlist = [...originalPlaylist];
+        queueActive = false;
         shuffleMode = false;
         updateShuffleButton();
 
         // Start with first track
         currentIndex = 0;
-        const track = playlist[currentIndex];
+        openTrack(playlist[currentIndex]);
+    }
 
-        if (track && window.DoggyLightbox) {
-            window.DoggyLightbox.open(track.src, track.type);
-        }
+    /**
+     * Play a list of tracks in order, e.g. a saved playlist
+     * @param {Array} items - Array of {src: string, type: string} objects
+     */
+    function playTracks(items) {
+        playlist = items.filter(item => item.type === 'audio');
+        originalPlaylist = [...playlist];
+        playAllSequential();
     }
 
     /**
      * Toggle shuffle mode
      */
-    function toggleShuffle() {
+    async function toggleShuffle() {
+        if (queueActive) {
+            const response = await fetch('/api/queue/shuffle', {
+                method: 'PUT',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ enabled: !shuffleMode })
+            });
+            if (response.ok) {
+                applyQueue(await response.json());
+            }
+            return;
+        }
         shuffleMode = !shuffleMode;
         updateShuffleButton();
     }
@@ -147,6 +296,7 @@
      */
     function stopPlayAll() {
         playAllMode = false;
+        queueActive = false;
     }
 
     // Export functions
@@ -155,6 +305,7 @@
             init: initMusicPlayer,
             playAllRandom: playAllRandom,
             playAllSequential: playAllSequential,
+            playTracks: playTracks,
             toggleShuffle: toggleShuffle,
             stopPlayAll: stopPlayAll
         };
@@ -0,0 +1,146 @@
+// Playlist module for DoggyGallery
+// Manages the signed-in user's playlists from the music player
+(function() {
+    'use strict';
+
+    let select = null;
+
+    /**
+     * Initialize the playlist bar, if the page has one
+     */
+    async function initPlaylists() {
+        select = document.getElementById('playlist-select');
+        if (!select) return;
+
+        document.getElementById('playlist-play').onclick = playSelected;
+        document.getElementById('playlist-new').onclick = createPlaylist;
+        document.getElementById('playlist-delete').onclick = deleteSelected;
+        select.onchange = updateExportLink;
+
+        // Add-to-playlist buttons; handled before the click reaches the track
+        document.addEventListener('click', function(e) {
+            const button = e.target.closest('.playlist-add');
+            if (!button) return;
+            e.stopPropagation();
+            addTrack(button.getAttribute('data-path'), button);
+        }, true);
+
+        await loadPlaylists();
+    }
+
+    /**
+     * Fill the playlist selector with the user's playlists
+     * @param {number} [selectedId] - Playlist to select afterwards
+     */
+    async function loadPlaylists(selectedId) {
+        const response = await fetch('/api/playlists');
+        if (!response.ok) return;
+        const playlists = await response.json();
+
+        select.innerHTML = '';
+        playlists.forEach(playlist => {
+            const option = document.createElement('option');
+            option.value = playlist.id;
+            option.textContent = `${playlist.name} (${playlist.tracks})`;
+            select.appendChild(option);
+        });
+        if (selectedId !== undefined) {
+            select.value = selectedId;
+        }
+        updateExportLink();
+    }
+
+    /**
+     * Point the M3U link at the selected playlist
+     */
+    function updateExportLink() {
+        const link = document.getElementById('playlist-export');
+        if (select.value) {
+            link.href = `/api/playlists/${select.value}.m3u8`;
+            link.style.visibility = 'visible';
+        } else {
+            link.removeAttribute('href');
+            link.style.visibility = 'hidden';
+        }
+    }
+
+    /**
+     * Play the selected playlist from the start
+     */
+    async function playSelected() {
+        if (!select.value) return;
+        const response = await fetch(`/api/playlists/${select.value}`);
+        if (!response.ok) return;
+        const playlist = await response.json();
+
+        const tracks = playlist.tracks.map(track => ({
+            src: '/media/' + track.path,
+            type: 'audio'
+        }));
+        if (tracks.length > 0 && window.MusicPlayer) {
+            MusicPlayer.playTracks(tracks);
+        }
+    }
+
+    /**
+     * Ask for a name and create an empty playlist
+     */
+    async function createPlaylist() {
+        const name = prompt('Playlist name');
+        if (!name) return;
+
+        const response = await fetch('/api/playlists', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ name: name })
+        });
+        if (!response.ok) {
+            alert(await response.text());
+            return;
+        }
+        const playlist = await response.json();
+        await loadPlaylists(playlist.id);
+    }
+
+    /**
+     * Delete the selected playlist after confirming
+     */
+    async function deleteSelected() {
+        if (!select.value) return;
+        const name = select.options[select.selectedIndex].textContent;
+        if (!confirm(`Delete playlist ${name}?`)) return;
+
+        const response = await fetch(`/api/playlists/${select.value}`, { method: 'DELETE' });
+        if (response.ok) {
+            await loadPlaylists();
+        }
+    }
+
+    /**
+     * Append a track to the selected playlist, creating one if there is none
+     * @param {string} path - Track path relative to the media directory
+     * @param {HTMLElement} button - Button to flash on success
+     */
+    async function addTrack(path, button) {
+        if (!select.value) {
+            await createPlaylist();
+            if (!select.value) return;
+        }
+
+        const id = select.value;
+        const response = await fetch(`/api/playlists/${id}/tracks`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ tracks: [path] })
+        });
+        if (!response.ok) {
+            alert(await response.text());
+            return;
+        }
+        button.textContent = '✓';
+        setTimeout(() => { button.textContent = '＋'; }, 1500);
+        await loadPlaylists(id);
+    }
+
+    document.addEventListener('DOMContentLoaded', initPlaylists);
+})();
@@ -0,0 +1,127 @@
+// Fullscreen slideshow of a directory tree, with keyboard controls
+(function() {
+    'use strict';
+
+    const body = document.body;
+    const image = document.getElementById('slide');
+    const message = document.getElementById('slideshow-message');
+    const caption = document.getElementById('slideshow-caption');
+    const playButton = document.getElementById('slideshow-play');
+    const controls = document.getElementById('slideshow-controls');
+
+    const path = body.dataset.path;
+    const interval = parseInt(body.dataset.interval, 10) * 1000;
+    let slides = [];
+    let current = 0;
+    let playing = true;
+    let timer = null;
+    let hideTimer = null;
+    // Keeps the next image alive so the browser caches it
+    let preloaded = null;
+
+    function show(index) {
+        if (slides.length === 0) return;
+        current = (index + slides.length) % slides.length;
+        const slide = slides[current];
+        image.src = slide.url;
+        image.alt = slide.name;
+        caption.textContent = `${current + 1} / ${slides.length} · ${slide.name}`;
+
+        preloaded = new Image();
+        preloaded.src = slides[(current + 1) % slides.length].url;
+        schedule();
+    }
+
+    function schedule() {
+        clearTimeout(timer);
+        if (playing && slides.length > 1) {
+            timer = setTimeout(() => show(current + 1), interval);
+        }
+    }
+
+    function togglePlay() {
+        playing = !playing;
+        playButton.textContent = playing ? '⏸' : '▶';
+        schedule();
+    }
+
+    function toggleFullscreen() {
+        if (document.fullscreenElement) {
+            document.exitFullscreen();
+        } else {
+            document.documentElement.requestFullscreen().catch(() => {});
+        }
+    }
+
+    // Controls fade out while the pointer rests
+    function showControls() {
+        controls.classList.remove('hidden');
+        body.style.cursor = '';
+        clearTimeout(hideTimer);
+        hideTimer = setTimeout(() => {
+            controls.classList.add('hidden');
+            body.style.cursor = 'none';
+        }, 3000);
+    }
+
+    document.getElementById('slideshow-prev').addEventListener('click', () => show(current - 1));
+    document.getElementById('slideshow-next').addEventListener('click', () => show(current + 1));
+    document.getElementById('slideshow-fullscreen').addEventListener('click', toggleFullscreen);
+    playButton.addEventListener('click', togglePlay);
+    document.addEventListener('mousemove', showControls);
+    document.addEventListener('keydown', e => {
+        switch (e.key) {
+            case 'ArrowLeft':
+                show(current - 1);
+                break;
+            case 'ArrowRight':
+                show(current + 1);
+                break;
+            case ' ':
+                e.preventDefault();
+                togglePlay();
+                break;
+            case 'f':
+            case 'F':
+                toggleFullscreen();
+                break;
+            case 'Home':
+                show(0);
+                break;
+            case 'End':
+                show(slides.length - 1);
+                break;
+            case 'Escape':
+                if (!document.fullscreenElement) window.location.href = '/browse/' + path;
+                break;
+            default:
+                return;
+        }
+        showControls();
+    });
+
+    async function load() {
+        const params = new URLSearchParams({
+            path: path,
+            shuffle: body.dataset.shuffle,
+            interval: body.dataset.interval
+        });
+        try {
+            const response = await fetch('/api/slideshow?' + params);
+            if (!response.ok) throw new Error();
+            slides = (await response.json()).slides;
+        } catch (e) {
+            message.textContent = 'Could not load the slideshow';
+            return;
+        }
+        if (slides.length === 0) {
+            message.textContent = 'No images in this directory';
+            return;
+        }
+        message.hidden = true;
+        show(0);
+        showControls();
+    }
+
+    load();
+})();
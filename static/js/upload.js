@@ -0,0 +1,107 @@
+// Drag-and-drop and file-picker uploads into the current gallery directory
+(function() {
+    'use strict';
+
+    /**
+     * Upload files in one multipart request, reporting progress as it goes
+     * @param {string} dir - Directory relative to the media directory
+     * @param {File[]} files - Files to upload
+     * @param {function(number, number)} onProgress - Called with bytes sent and total
+     * @returns {Promise<Object>} Uploaded and failed files
+     */
+    function uploadFiles(dir, files, onProgress) {
+        return new Promise((resolve, reject) => {
+            const form = new FormData();
+            files.forEach(file => form.append('file', file, file.name));
+
+            const xhr = new XMLHttpRequest();
+            const path = dir ? '/' + dir.split('/').map(encodeURIComponent).join('/') : '';
+            xhr.open('POST', '/api/upload' + path);
+            xhr.upload.addEventListener('progress', e => {
+                if (e.lengthComputable) onProgress(e.loaded, e.total);
+            });
+            xhr.addEventListener('load', () => {
+                if (xhr.status === 200) {
+                    resolve(JSON.parse(xhr.responseText));
+                } else {
+                    reject(new Error(xhr.responseText.split('\n')[0] || 'Upload failed (' + xhr.status + ')'));
+                }
+            });
+            xhr.addEventListener('error', () => reject(new Error('Upload interrupted')));
+            xhr.send(form);
+        });
+    }
+
+    function init() {
+        const zone = document.getElementById('upload-zone');
+        if (!zone) return;
+        const input = zone.querySelector('input[type="file"]');
+        const status = zone.querySelector('.upload-status');
+        const bar = zone.querySelector('.upload-progress span');
+        const dir = zone.getAttribute('data-dir');
+        let busy = false;
+
+        async function start(fileList) {
+            const files = Array.from(fileList);
+            if (busy || files.length === 0) return;
+            busy = true;
+            zone.classList.add('uploading');
+            status.textContent = 'Uploading ' + files.length + ' file(s)...';
+            try {
+                const result = await uploadFiles(dir, files, (sent, total) => {
+                    const percent = Math.round(sent / total * 100);
+                    bar.style.width = percent + '%';
+                    status.textContent = 'Uploading ' + files.length + ' file(s)... ' +
+                        DoggyUtils.formatBytes(sent) + ' of ' + DoggyUtils.formatBytes(total);
+                });
+                const failures = result.failed.map(f => f.name + ': ' + f.error);
+                status.textContent = 'Uploaded ' + result.uploaded.length + ' file(s)' +
+                    (failures.length ? '. Not uploaded: ' + failures.join('; ') : '');
+                if (result.uploaded.length > 0 && failures.length === 0) {
+                    window.location.reload();
+                }
+            } catch (e) {
+                status.textContent = e.message;
+            } finally {
+                busy = false;
+                zone.classList.remove('uploading');
+                bar.style.width = '0';
+                input.value = '';
+            }
+        }
+
+        input.addEventListener('change', () => start(input.files));
+
+        // Files can be dropped anywhere on the page
+        let depth = 0;
+        document.addEventListener('dragenter', e => {
+            if (!e.dataTransfer.types.includes('Files')) return;
+            depth++;
+            zone.classList.add('dragover');
+        });
+        document.addEventListener('dragleave', () => {
+            depth = Math.max(0, depth - 1);
+            if (depth === 0) zone.classList.remove('dragover');
+        });
+        document.addEventListener('dragover', e => {
+            if (e.dataTransfer.types.includes('Files')) e.preventDefault();
+        });
+        document.addEventListener('drop', e => {
+            if (!e.dataTransfer.types.includes('Files')) return;
+            e.preventDefault();
+            depth = 0;
+            zone.classList.remove('dragover');
+            start(e.dataTransfer.files);
+        });
+    }
+
+    if (!window.DoggyUpload) {
+        window.DoggyUpload = { uploadFiles };
+    }
+
+    if (document.readyState === 'loading') {
+        document.addEventListener('DOMContentLoaded', init);
+    } else {
+        init();
+    }
+})();
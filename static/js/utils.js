@@ -96,6 +96,19 @@
         }
     }
 
+    /**
+     * Link to another page of the listing, keeping the other query parameters (such as the sort)
+     * @param {number} page - Page number
+     * @param {number} perPage - Items per page
+     * @returns {string} Relative URL
+     */
+    function pageHref(page, perPage) {
+        const params = new URLSearchParams(window.location.search);
+        params.set('page', page);
+        params.set('per_page', perPage);
+        return `?${params}`;
+    }
+
     /**
      * Render pagination controls
      * @param {number} currentPage - Current page number
@@ -110,7 +123,7 @@
         // Previous button
         if (currentPage > 1) {
             const prevBtn = document.createElement('a');
-            prevBtn.href = `?page=${currentPage - 1}&per_page=${perPage}`;
+            prevBtn.href = pageHref(currentPage - 1, perPage);
             prevBtn.className = 'pagination-btn';
             prevBtn.textContent = '← Previous';
             pagination.appendChild(prevBtn);
@@ -138,7 +151,7 @@
                 (currentPage < 3 && pageNum <= 5)
             ) {
                 const pageBtn = document.createElement('a');
-                pageBtn.href = `?page=${pageNum}&per_page=${perPage}`;
+                pageBtn.href = pageHref(pageNum, perPage);
                 pageBtn.className = 'pagination-btn';
                 pageBtn.textContent = pageNum;
                 pagesContainer.appendChild(pageBtn);
@@ -158,7 +171,7 @@
         // Next button
         if (currentPage < totalPages) {
             const nextBtn = document.createElement('a');
-            nextBtn.href = `?page=${currentPage + 1}&per_page=${perPage}`;
+            nextBtn.href = pageHref(currentPage + 1, perPage);
             nextBtn.className = 'pagination-btn';
             nextBtn.textContent = 'Next →';
             pagination.appendChild(nextBtn);
@@ -31,5 +31,6 @@ RUST_LOG=doggygallery=info cargo run -- \
     --media-dir ./media \
     --username admin \
     --password admin \
+    --insecure-plaintext-password \
     --host 127.0.0.1 \
     --port 8443